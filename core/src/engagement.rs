@@ -0,0 +1,69 @@
+use scraper::ElementRef;
+use serde::{Deserialize, Serialize};
+
+use crate::extract::Selectors;
+
+// Reader engagement shown next to an article: its comment counter and, where
+// there's a share widget with a counter, how often it was shared
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Engagement {
+    pub comments: Option<u64>,
+    pub shares: Option<u64>,
+}
+
+impl Engagement {
+    pub fn is_empty(&self) -> bool {
+        self.comments.is_none() && self.shares.is_none()
+    }
+}
+
+// Helper function to read a counter as shown on the page: "12", "1.234",
+// "1,2k", "3 mila", "1 mln", "12 commenti"
+pub fn parse_count(value: &str) -> Option<u64> {
+    let value = value.trim().to_lowercase();
+    let number: String = value
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == ',')
+        .collect();
+    if number.is_empty() {
+        return None;
+    }
+    let rest = value[number.len()..].trim_start();
+    let multiplier = if rest.starts_with("mila") || rest.starts_with('k') {
+        1_000.0
+    } else if rest.starts_with("mln") {
+        1_000_000.0
+    } else {
+        1.0
+    };
+    let number = if multiplier > 1.0 {
+        // Abbreviated counts use a decimal comma or point: "1,2k"
+        number.replace(',', ".").parse::<f64>().ok()?
+    } else {
+        // Full counts use them as thousands separators: "1.234"
+        number.replace(['.', ','], "").parse::<f64>().ok()?
+    };
+    Some((number * multiplier).round() as u64)
+}
+
+// Helper function to read the first counter under `element` matched by
+// `selector`, from a data-count style attribute or else from its text
+fn counter(element: ElementRef, selector: &scraper::Selector, attribute: &str) -> Option<u64> {
+    element.select(selector).find_map(|counter| {
+        let value = counter.value();
+        match value.attr(attribute).or(value.attr("data-count")) {
+            Some(count) => parse_count(count),
+            None => parse_count(&counter.text().collect::<String>()),
+        }
+    })
+}
+
+// Helper function to extract the engagement shown in an item or article
+// element. None when the markup has no counters
+pub fn engagement(element: ElementRef, selectors: &Selectors) -> Option<Engagement> {
+    let engagement = Engagement {
+        comments: counter(element, &selectors.comments, "data-comments"),
+        shares: counter(element, &selectors.shares, "data-shares"),
+    };
+    (!engagement.is_empty()).then_some(engagement)
+}
@@ -0,0 +1,285 @@
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::engagement;
+use crate::fragment;
+use crate::image;
+use crate::ranking::{self, Placement};
+use crate::NewsItem;
+
+pub const CORRIERE_BASE_URL: &str = "https://www.corriere.it";
+
+// Maximum number of items extracted from the homepage
+pub const HOMEPAGE_LIMIT: usize = 20;
+
+// How much of the page is parsed into a DOM. Fragment mode cuts the
+// container element out of the raw HTML first, which is much cheaper on large
+// pages; it falls back to the whole document when the container can't be found
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ParseMode {
+    Document,
+    Fragment,
+}
+
+impl std::str::FromStr for ParseMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "document" => Ok(ParseMode::Document),
+            "fragment" => Ok(ParseMode::Fragment),
+            _ => Err("expected document or fragment".to_string()),
+        }
+    }
+}
+
+// CSS selectors as strings, so they can be overridden per request.
+// Missing fields fall back to the homepage defaults
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SelectorConfig {
+    pub container: String,
+    pub article: String,
+    pub title: String,
+    pub link: String,
+    pub summary: String,
+    pub image: String,
+    pub caption: String,
+    pub credit: String,
+    // Comment and share counters, read from a data-comments, data-shares or
+    // data-count attribute or else from their text
+    pub comments: String,
+    pub shares: String,
+}
+
+impl Default for SelectorConfig {
+    fn default() -> Self {
+        SelectorConfig {
+            container: ".body-hp".to_string(),
+            article: ".bck-media-news".to_string(),
+            title: "h4.title-art-hp".to_string(),
+            link: "a".to_string(),
+            summary: "p[class^='subtitle']".to_string(),
+            image: "img.is_full_image".to_string(),
+            caption: "figcaption".to_string(),
+            credit: ".credit, .credits, .photo-credit".to_string(),
+            comments: ".comments-count, .is-comments, [data-comments]".to_string(),
+            shares: ".share-count, [data-shares]".to_string(),
+        }
+    }
+}
+
+// A named selector config, one entry in a source's fallback chain
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SelectorSet {
+    pub name: String,
+    #[serde(flatten)]
+    pub selectors: SelectorConfig,
+}
+
+pub struct Selectors {
+    pub container: Selector,
+    // Set when the container is a plain class selector usable in fragment mode
+    pub container_class: Option<String>,
+    pub article: Selector,
+    pub title: Selector,
+    pub link: Selector,
+    pub summary: Selector,
+    pub image: Selector,
+    pub caption: Selector,
+    pub credit: Selector,
+    pub comments: Selector,
+    pub shares: Selector,
+}
+
+impl Selectors {
+    // Helper function to create CSS selectors
+    pub fn parse(config: &SelectorConfig) -> Result<Selectors, String> {
+        let parse = |selector: &str, name: &str| {
+            Selector::parse(selector)
+                .map_err(|e| format!("Failed to parse {} selector: {}", name, e))
+        };
+
+        Ok(Selectors {
+            container: parse(&config.container, "body")?,
+            container_class: fragment::simple_class_selector(&config.container).map(str::to_string),
+            article: parse(&config.article, "article")?,
+            title: parse(&config.title, "title")?,
+            link: parse(&config.link, "link")?,
+            summary: parse(&config.summary, "summary")?,
+            image: parse(&config.image, "image")?,
+            caption: parse(&config.caption, "caption")?,
+            credit: parse(&config.credit, "credit")?,
+            comments: parse(&config.comments, "comments")?,
+            shares: parse(&config.shares, "shares")?,
+        })
+    }
+}
+
+// Helper function to resolve an href or image src found on a page against
+// the page's URL, the way a browser would: protocol-relative (//host/path),
+// root-relative, path-relative and query-only references all work, and
+// absolute URLs are kept whatever their host. References that can't be
+// resolved are returned trimmed but otherwise untouched, for validation to
+// reject
+pub fn resolve_url(base_url: Option<&Url>, href: &str) -> String {
+    let href = href.trim();
+    if href.is_empty() {
+        return String::new();
+    }
+    let resolved = match base_url {
+        Some(base_url) => base_url.join(href),
+        None => Url::parse(href),
+    };
+    resolved.map_or_else(|_| href.to_string(), String::from)
+}
+
+// Helper function to extract up to `limit` news items from a page.
+// Relative links and images are resolved against `base_url`, the URL of the
+// page (or just its origin)
+pub fn extract_news(
+    html: &str,
+    selectors: &Selectors,
+    base_url: &str,
+    limit: usize,
+    mode: ParseMode,
+) -> Vec<NewsItem> {
+    let mut news_list = Vec::new();
+
+    // Parse the HTML document, or only the container when that's enough
+    let container_html = match (mode, &selectors.container_class) {
+        (ParseMode::Fragment, Some(class)) => fragment::element_with_class(html, class),
+        _ => None,
+    };
+    let document = match container_html {
+        Some(container_html) => Html::parse_fragment(container_html),
+        None => Html::parse_document(html),
+    };
+
+    if let Some(section) = document.select(&selectors.container).next() {
+        for element in section.select(&selectors.article) {
+            if let Some(mut news_item) = extract_news_item(element, selectors, base_url) {
+                news_item.placement = ranking::placement(element, selectors, news_list.len());
+                news_list.push(news_item);
+
+                if news_list.len() >= limit {
+                    break;
+                }
+            }
+        }
+    }
+
+    news_list
+}
+
+// Helper function to extract news with each selector set in turn, stopping
+// at the first that finds at least `min_items`. When none does, the set that
+// found the most wins. Returns the items with the name of the set used
+pub fn extract_with_fallback(
+    html: &str,
+    sets: &[SelectorSet],
+    base_url: &str,
+    limit: usize,
+    mode: ParseMode,
+    min_items: usize,
+) -> Result<(Vec<NewsItem>, String), String> {
+    let mut best: Option<(Vec<NewsItem>, String)> = None;
+    for set in sets {
+        let selectors = Selectors::parse(&set.selectors)?;
+        let news = extract_news(html, &selectors, base_url, limit, mode);
+        if news.len() >= min_items {
+            return Ok((news, set.name.clone()));
+        }
+        if best
+            .as_ref()
+            .is_none_or(|(best_news, _)| news.len() > best_news.len())
+        {
+            best = Some((news, set.name.clone()));
+        }
+    }
+    best.ok_or("No selector sets configured".to_string())
+}
+
+// Helper function to extract news item from an element
+pub fn extract_news_item(
+    element: scraper::ElementRef,
+    selectors: &Selectors,
+    base_url: &str,
+) -> Option<NewsItem> {
+    let base_url = Url::parse(base_url).ok();
+    let normalize_url = |url: &str| resolve_url(base_url.as_ref(), url);
+    let image = image::image_info(element, selectors, base_url.as_ref());
+
+    // Extract Title and Link
+    let (title, link) = if let Some(title_element) = element.select(&selectors.title).next() {
+        let text = title_element
+            .text()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .trim()
+            .to_string();
+        let href = title_element
+            .select(&selectors.link)
+            .next()
+            .and_then(|a| a.value().attr("href"))
+            .unwrap_or("")
+            .to_string();
+        (text, normalize_url(&href))
+    } else {
+        return None;
+    };
+
+    // Extract Description and Image
+    let mut description = String::new();
+
+    if let Some(summary) = element.select(&selectors.summary).next() {
+        description = summary
+            .text()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .trim()
+            .to_string();
+    }
+
+    if let Some(img) = element.select(&selectors.image).next() {
+        // Fallback description from alt if empty
+        if description.is_empty() {
+            if let Some(alt) = img.value().attr("alt") {
+                description = alt.to_string();
+            }
+        }
+    }
+
+    Some(NewsItem {
+        title,
+        description,
+        link,
+        image_url: image.as_ref().map(|image| image.url.clone()),
+        image,
+        placement: Placement::default(),
+        issues: vec![],
+        engagement: engagement::engagement(element, selectors),
+    })
+}
+
+// Helper function to tell the section of an article from its URL: the first
+// path segment on www.corriere.it ("politica", "esteri") or the subdomain of
+// a local edition ("milano")
+pub fn section(link: &str) -> Option<String> {
+    let url = Url::parse(link).ok()?;
+    let host = url.host_str()?.to_ascii_lowercase();
+    let subdomain = match host.as_str() {
+        "corriere.it" => "",
+        host => host.strip_suffix(".corriere.it")?,
+    };
+
+    match subdomain {
+        "" | "www" => url
+            .path_segments()?
+            .next()
+            .filter(|segment| !segment.is_empty() && !segment.contains('.'))
+            .map(str::to_ascii_lowercase),
+        subdomain => Some(subdomain.to_string()),
+    }
+}
@@ -0,0 +1,171 @@
+// Lightweight scanner that cuts a single element out of raw HTML, so the
+// parser only has to build a DOM for the part of the page we extract from.
+// It understands just enough HTML for that: quoted attributes, comments and
+// raw text elements (script/style) whose content may contain stray tags.
+
+// Helper function to find the first element carrying `class` and return its
+// full markup, including the opening and closing tags. An element that is
+// never closed runs to the end of the document, as in a real parser
+pub fn element_with_class<'a>(html: &'a str, class: &str) -> Option<&'a str> {
+    let mut pos = 0;
+
+    while let Some(offset) = html[pos..].find('<') {
+        let start = pos + offset;
+        let rest = &html[start..];
+
+        if rest.starts_with("<!--") {
+            pos = skip_past(html, start, "-->");
+            continue;
+        }
+
+        let tag_end = tag_end(html, start)?;
+        let tag = &html[start..=tag_end];
+        let name = tag_name(tag);
+
+        if !name.is_empty() && has_class(tag, class) {
+            let end = closing_tag_end(html, tag_end + 1, name);
+            return Some(&html[start..end]);
+        }
+
+        pos = if is_raw_text(name) {
+            skip_raw_text(html, tag_end + 1, name)
+        } else {
+            tag_end + 1
+        };
+    }
+
+    None
+}
+
+// Class name when the selector is a plain class selector such as ".body-hp"
+// or "main.body-hp"; anything more complex can't be matched by the scanner
+pub fn simple_class_selector(selector: &str) -> Option<&str> {
+    let (tag, class) = selector.trim().split_once('.')?;
+    let is_name = |text: &str| {
+        text.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    };
+    (is_name(tag) && !class.is_empty() && is_name(class)).then_some(class)
+}
+
+// Index of the '>' closing the tag that starts at `start`, skipping quoted
+// attribute values
+fn tag_end(html: &str, start: usize) -> Option<usize> {
+    let mut quote = None;
+    for (offset, c) in html[start..].char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            (None, '>') => return Some(start + offset),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn tag_name(tag: &str) -> &str {
+    let name = tag.trim_start_matches('<');
+    let end = name
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+        .unwrap_or(name.len());
+    &name[..end]
+}
+
+fn has_class(tag: &str, class: &str) -> bool {
+    let lower = tag.to_ascii_lowercase();
+    let mut search = 0;
+
+    while let Some(offset) = lower[search..].find("class") {
+        let at = search + offset;
+        search = at + "class".len();
+
+        // Must be the attribute name, not part of another word
+        let before = lower[..at].chars().next_back().unwrap_or(' ');
+        if !before.is_ascii_whitespace() {
+            continue;
+        }
+        let value = tag[search..].trim_start();
+        let Some(value) = value.strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+
+        let classes = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => value[1..].split(quote).next().unwrap_or(""),
+            _ => value
+                .split(|c: char| c.is_ascii_whitespace() || c == '>')
+                .next()
+                .unwrap_or(""),
+        };
+        return classes.split_ascii_whitespace().any(|name| name == class);
+    }
+
+    false
+}
+
+// Index just past the tag that closes the `name` element whose content starts
+// at `from`, counting nested elements with the same name
+fn closing_tag_end(html: &str, from: usize, name: &str) -> usize {
+    let mut depth = 1;
+    let mut pos = from;
+
+    while let Some(offset) = html[pos..].find('<') {
+        let start = pos + offset;
+        let rest = &html[start..];
+
+        if rest.starts_with("<!--") {
+            pos = skip_past(html, start, "-->");
+            continue;
+        }
+        let Some(end) = tag_end(html, start) else {
+            break;
+        };
+
+        let tag = &html[start..=end];
+        if let Some(closing) = tag.strip_prefix("</") {
+            if tag_name(closing).eq_ignore_ascii_case(name) {
+                depth -= 1;
+                if depth == 0 {
+                    return end + 1;
+                }
+            }
+            pos = end + 1;
+        } else {
+            let tag_name = tag_name(tag);
+            if tag_name.eq_ignore_ascii_case(name) && !tag.ends_with("/>") {
+                depth += 1;
+            }
+            pos = if is_raw_text(tag_name) {
+                skip_raw_text(html, end + 1, tag_name)
+            } else {
+                end + 1
+            };
+        }
+    }
+
+    html.len()
+}
+
+fn is_raw_text(name: &str) -> bool {
+    name.eq_ignore_ascii_case("script") || name.eq_ignore_ascii_case("style")
+}
+
+// Content of script and style elements is not markup; jump to its end tag.
+// The end tag is searched for in place, without lowercasing the rest of the
+// page for every script, which made pages full of scripts quadratic
+fn skip_raw_text(html: &str, from: usize, name: &str) -> usize {
+    let closing = format!("</{}", name);
+    let found = html.as_bytes()[from..]
+        .windows(closing.len())
+        .position(|window| window.eq_ignore_ascii_case(closing.as_bytes()));
+    match found {
+        Some(offset) => tag_end(html, from + offset).map_or(html.len(), |end| end + 1),
+        None => html.len(),
+    }
+}
+
+fn skip_past(html: &str, from: usize, marker: &str) -> usize {
+    html[from..]
+        .find(marker)
+        .map_or(html.len(), |offset| from + offset + marker.len())
+}
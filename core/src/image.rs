@@ -0,0 +1,112 @@
+use scraper::ElementRef;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::extract::{self, Selectors};
+
+// One candidate from an image's srcset, described either by its width
+// (`480w`) or its pixel density (`2x`)
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ImageVariant {
+    pub url: String,
+    pub width: Option<u32>,
+    pub density: Option<f64>,
+}
+
+// An item's image with what a frontend needs to show it responsively and
+// credit it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ImageInfo {
+    pub url: String,
+    // Declared size, when the markup gives one
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub caption: Option<String>,
+    pub credit: Option<String>,
+    pub variants: Vec<ImageVariant>,
+}
+
+// Helper function to read a width or height attribute such as "640" or "640px"
+pub fn dimension(value: &str) -> Option<u32> {
+    value.trim().trim_end_matches("px").parse().ok()
+}
+
+// Helper function to parse a srcset ("a.jpg 480w, b.jpg 960w" or
+// "a.jpg, b.jpg 2x"), resolving each URL against the page
+pub fn parse_srcset(srcset: &str, base_url: Option<&Url>) -> Vec<ImageVariant> {
+    srcset
+        .split(',')
+        .filter_map(|candidate| {
+            let mut parts = candidate.split_whitespace();
+            let url = parts.next()?;
+            let descriptor = parts.next().unwrap_or("1x");
+            let (width, density) = match descriptor.chars().last() {
+                Some('w') => (Some(descriptor.trim_end_matches('w').parse().ok()?), None),
+                Some('x') => (None, Some(descriptor.trim_end_matches('x').parse().ok()?)),
+                _ => return None,
+            };
+            Some(ImageVariant {
+                url: extract::resolve_url(base_url, url),
+                width,
+                density,
+            })
+        })
+        .collect()
+}
+
+fn text(element: ElementRef) -> Option<String> {
+    let text = element.text().collect::<Vec<_>>().join(" ");
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+// Helper function to gather an article card's image: the first element
+// matching the image selector, its srcset (lazy-loaded data-srcset first),
+// and the caption and photo credit found in the card. A credit nested in
+// the caption is left out of the caption text
+pub fn image_info(
+    element: ElementRef,
+    selectors: &Selectors,
+    base_url: Option<&Url>,
+) -> Option<ImageInfo> {
+    let img = element.select(&selectors.image).next()?;
+    let attrs = img.value();
+    let src = attrs.attr("data-src").or_else(|| attrs.attr("src"))?;
+
+    let credit = element.select(&selectors.credit).next();
+    let caption = element
+        .select(&selectors.caption)
+        .next()
+        .and_then(|caption| {
+            let credit_ids: Vec<_> = caption.select(&selectors.credit).map(|c| c.id()).collect();
+            let text: Vec<&str> = caption
+                .descendants()
+                .filter(|node| {
+                    !node
+                        .ancestors()
+                        .take_while(|ancestor| ancestor.id() != caption.id())
+                        .any(|ancestor| credit_ids.contains(&ancestor.id()))
+                })
+                .filter_map(|node| node.value().as_text().map(|text| &**text))
+                .collect();
+            let text = text
+                .join(" ")
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            (!text.is_empty()).then_some(text)
+        });
+
+    Some(ImageInfo {
+        url: extract::resolve_url(base_url, src),
+        width: attrs.attr("width").and_then(dimension),
+        height: attrs.attr("height").and_then(dimension),
+        caption,
+        credit: credit.and_then(text),
+        variants: attrs
+            .attr("data-srcset")
+            .or_else(|| attrs.attr("srcset"))
+            .map(|srcset| parse_srcset(srcset, base_url))
+            .unwrap_or_default(),
+    })
+}
@@ -0,0 +1,91 @@
+// The extraction rules of corriere_scraper without the server: selectors,
+// item extraction, placement, images and text normalization. It needs
+// neither tokio nor reqwest, so it also builds for wasm32, where
+// `extract_from_html` is exported to JavaScript, e.g. for a browser
+// extension reading the page the user is on:
+//   wasm-pack build core --target web
+use serde::{Deserialize, Serialize};
+
+pub mod engagement;
+pub mod extract;
+pub mod fragment;
+pub mod image;
+pub mod ranking;
+pub mod typography;
+pub mod validate;
+#[cfg(target_arch = "wasm32")]
+mod wasm;
+
+use engagement::Engagement;
+use image::ImageInfo;
+use ranking::Placement;
+
+// Version of the extraction rules, recorded with each homepage scrape
+pub const EXTRACTOR_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct NewsItem {
+    pub title: String,
+    pub description: String,
+    pub link: String,
+    pub image_url: Option<String>,
+    // Sizes, srcset, caption and credit of the image; v2 only
+    #[serde(skip)]
+    pub image: Option<ImageInfo>,
+    // Not part of the v1 shape; served as prominence_score from v2 on
+    #[serde(skip)]
+    pub placement: Placement,
+    // Validation rules the item breaks, when VALIDATION_MODE=flag; v2 only
+    #[serde(skip)]
+    pub issues: Vec<String>,
+    // Comment and share counters shown with the teaser; v2 only
+    #[serde(skip)]
+    pub engagement: Option<Engagement>,
+}
+
+// Helper function to extract the homepage items from its HTML with the
+// default selectors, exactly as the server does for /api/news
+pub fn extract_from_html(html: &str) -> Vec<NewsItem> {
+    extract_from_page(html, extract::CORRIERE_BASE_URL)
+}
+
+// Like extract_from_html, for a copy of the homepage fetched from
+// `base_url`, against which relative links are resolved
+pub fn extract_from_page(html: &str, base_url: &str) -> Vec<NewsItem> {
+    let selectors = extract::Selectors::parse(&extract::SelectorConfig::default())
+        .expect("The default selectors are valid");
+    extract::extract_news(
+        html,
+        &selectors,
+        base_url,
+        extract::HOMEPAGE_LIMIT,
+        extract::ParseMode::Document,
+    )
+}
+
+// Helper function to extract the items of a page fetched from `page_url` the
+// way the server scrapes it: each selector set in turn until one finds
+// `min_items`, then the validation rules for corriere.it and the page's own
+// site. Returns the items with the name of the set used
+pub fn extract_checked(
+    html: &str,
+    page_url: &str,
+    sets: &[extract::SelectorSet],
+    min_items: usize,
+    mode: validate::ValidationMode,
+) -> Result<(Vec<NewsItem>, String), String> {
+    let (news, set) = extract::extract_with_fallback(
+        html,
+        sets,
+        page_url,
+        extract::HOMEPAGE_LIMIT,
+        extract::ParseMode::Document,
+        min_items,
+    )?;
+    let domains: Vec<String> = validate::DEFAULT_DOMAINS
+        .iter()
+        .map(|domain| domain.to_string())
+        .collect();
+    let rules = validate::Rules::for_page(&domains, page_url);
+    Ok((validate::filter(news, mode, &rules, |_| {}), set))
+}
@@ -0,0 +1,76 @@
+use scraper::ElementRef;
+use serde::{Deserialize, Serialize};
+
+use crate::extract::Selectors;
+use crate::image;
+
+// Where and how prominently an item sat on the page it was scraped from
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Placement {
+    // 0-based rank among the extracted items
+    pub position: usize,
+    // Inside the opening block at the top of the page
+    pub opening: bool,
+    // Headline set in one of the large type sizes
+    pub large_title: bool,
+    pub image: bool,
+    // Declared width of the image, when the markup gives one
+    pub image_width: Option<u32>,
+}
+
+// Classes marking the opening block and the large headline sizes
+const OPENING_CLASSES: &[&str] = &["opening", "apertura", "top-news"];
+const LARGE_TITLE_CLASSES: &[&str] = &["is-xxlarge", "is-xlarge", "is-large"];
+// Images at least this wide count as a full-width lead image
+const WIDE_IMAGE_PX: u32 = 600;
+
+// Helper function to read the placement of an article element
+pub fn placement(element: ElementRef, selectors: &Selectors, position: usize) -> Placement {
+    let has_class = |element: ElementRef, classes: &[&str]| {
+        element
+            .value()
+            .classes()
+            .any(|class| classes.contains(&class))
+    };
+
+    let opening = std::iter::once(element)
+        .chain(element.ancestors().filter_map(ElementRef::wrap))
+        .any(|element| has_class(element, OPENING_CLASSES));
+    let large_title = element
+        .select(&selectors.title)
+        .next()
+        .is_some_and(|title| has_class(title, LARGE_TITLE_CLASSES));
+    let image = element.select(&selectors.image).next();
+
+    Placement {
+        position,
+        opening,
+        large_title,
+        image: image.is_some(),
+        image_width: image
+            .and_then(|img| img.value().attr("width"))
+            .and_then(image::dimension),
+    }
+}
+
+impl Placement {
+    // Editorial prominence from 0 to 100. Rank on the page matters most and
+    // decays down the page; the opening block, a large headline and a (wide)
+    // image each add to it
+    pub fn score(&self) -> f64 {
+        let mut score = 60.0 * (-(self.position as f64) / 8.0).exp();
+        if self.opening {
+            score += 20.0;
+        }
+        if self.large_title {
+            score += 10.0;
+        }
+        if self.image {
+            score += 5.0;
+        }
+        if self.image_width.is_some_and(|width| width >= WIDE_IMAGE_PX) {
+            score += 5.0;
+        }
+        (score.min(100.0) * 10.0).round() / 10.0
+    }
+}
@@ -0,0 +1,235 @@
+use serde::Serialize;
+
+// Labels the homepage puts in front of headlines, as in "LIVE | ..." or
+// "VIDEO – ...". Compared without regard to case
+const EDITORIAL_PREFIXES: &[&str] = &[
+    "LIVE",
+    "DIRETTA",
+    "IN DIRETTA",
+    "VIDEO",
+    "FOTO",
+    "GALLERY",
+    "PODCAST",
+    "ESCLUSIVO",
+    "ESCLUSIVA",
+    "BREAKING",
+    "BREAKING NEWS",
+    "ULTIM'ORA",
+    "AGGIORNAMENTO",
+    "L'INTERVISTA",
+    "L'ANALISI",
+    "IL COMMENTO",
+    "OPINIONE",
+];
+
+// Separators between a label and the headline
+const PREFIX_SEPARATORS: &[char] = &['|', '–', '—', '-', ':', '·', '•'];
+
+// Windows-1252 characters in 0x80..0x9F, by byte. Mojibake is UTF-8 read as
+// Windows-1252, so turning these back into bytes recovers the original text
+const WINDOWS_1252: [(char, u8); 27] = [
+    ('€', 0x80),
+    ('‚', 0x82),
+    ('ƒ', 0x83),
+    ('„', 0x84),
+    ('…', 0x85),
+    ('†', 0x86),
+    ('‡', 0x87),
+    ('ˆ', 0x88),
+    ('‰', 0x89),
+    ('Š', 0x8a),
+    ('‹', 0x8b),
+    ('Œ', 0x8c),
+    ('Ž', 0x8e),
+    ('‘', 0x91),
+    ('’', 0x92),
+    ('“', 0x93),
+    ('”', 0x94),
+    ('•', 0x95),
+    ('–', 0x96),
+    ('—', 0x97),
+    ('˜', 0x98),
+    ('™', 0x99),
+    ('š', 0x9a),
+    ('›', 0x9b),
+    ('œ', 0x9c),
+    ('ž', 0x9e),
+    ('Ÿ', 0x9f),
+];
+
+// Named entities that survive in the homepage, usually double-escaped
+const ENTITIES: &[(&str, &str)] = &[
+    ("amp", "&"),
+    ("lt", "<"),
+    ("gt", ">"),
+    ("quot", "\""),
+    ("apos", "'"),
+    ("nbsp", "\u{a0}"),
+    ("agrave", "à"),
+    ("Agrave", "À"),
+    ("egrave", "è"),
+    ("Egrave", "È"),
+    ("eacute", "é"),
+    ("Eacute", "É"),
+    ("igrave", "ì"),
+    ("ograve", "ò"),
+    ("ugrave", "ù"),
+    ("laquo", "«"),
+    ("raquo", "»"),
+    ("lsquo", "‘"),
+    ("rsquo", "’"),
+    ("ldquo", "“"),
+    ("rdquo", "”"),
+    ("ndash", "–"),
+    ("mdash", "—"),
+    ("hellip", "…"),
+    ("euro", "€"),
+];
+
+// The title and description of an item after normalization
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct NormalizedText {
+    pub title: String,
+    pub description: String,
+}
+
+// Helper function to undo UTF-8 that was decoded as Windows-1252 somewhere
+// upstream ("perchÃ©" for "perché"). Text that doesn't round-trip is
+// returned unchanged
+pub fn fix_mojibake(text: &str) -> String {
+    if !text.contains(['Ã', 'Â', 'â']) {
+        return text.to_string();
+    }
+    let bytes: Option<Vec<u8>> = text
+        .chars()
+        .map(|c| match u32::from(c) {
+            code @ 0..=0xff => Some(code as u8),
+            _ => WINDOWS_1252
+                .iter()
+                .find(|(special, _)| *special == c)
+                .map(|(_, byte)| *byte),
+        })
+        .collect();
+    match bytes.map(String::from_utf8) {
+        Some(Ok(fixed)) => fixed,
+        _ => text.to_string(),
+    }
+}
+
+// Helper function to decode the HTML entities left in a text, named
+// (&egrave;) and numeric (&#8217; or &#x2019;). Unknown ones are kept
+pub fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let entity = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &rest[1..end + 1]);
+        let value = entity.and_then(|entity| {
+            let numeric = match entity.strip_prefix('#') {
+                Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16).ok(),
+                Some(decimal) => decimal.parse().ok(),
+                None => None,
+            };
+            match numeric {
+                Some(code) => char::from_u32(code).map(String::from),
+                None => ENTITIES
+                    .iter()
+                    .find(|(name, _)| *name == entity)
+                    .map(|(_, value)| value.to_string()),
+            }
+        });
+        match (entity, value) {
+            (Some(entity), Some(value)) => {
+                decoded.push_str(&value);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+// Helper function to settle on one form of quotes and dashes: straight
+// quotes and apostrophes, en dashes for asides. Guillemets are kept, as in
+// Italian they are the quotes of choice
+pub fn normalize_punctuation(text: &str) -> String {
+    let text: String = text
+        .chars()
+        .map(|c| match c {
+            '‘' | '’' | '‚' | '‛' | '′' | '`' | '´' => '\'',
+            '“' | '”' | '„' | '‟' | '″' => '"',
+            '—' | '―' | '‒' => '–',
+            c => c,
+        })
+        .collect();
+    text.replace(" - ", " – ")
+}
+
+// Helper function to collapse runs of whitespace, non-breaking spaces
+// included, into one space and drop zero-width characters
+pub fn collapse_whitespace(text: &str) -> String {
+    text.chars()
+        .filter(|c| !matches!(c, '\u{200b}' | '\u{200c}' | '\u{200d}' | '\u{feff}'))
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Helper function to remove the editorial labels in front of a headline,
+// one after the other ("LIVE | VIDEO – ..."), leaving at least some text
+pub fn strip_prefixes(title: &str) -> &str {
+    let mut title = title.trim_start();
+    'labels: loop {
+        for prefix in EDITORIAL_PREFIXES {
+            let Some(head) = title.get(..prefix.len()) else {
+                continue;
+            };
+            if !head.eq_ignore_ascii_case(prefix) {
+                continue;
+            }
+            let after = title[prefix.len()..].trim_start();
+            // "LIVE-streaming" is a word, not a label
+            let Some(rest) = after
+                .strip_prefix(PREFIX_SEPARATORS)
+                .filter(|rest| rest.starts_with(char::is_whitespace))
+            else {
+                continue;
+            };
+            let rest = rest.trim_start();
+            if rest.is_empty() {
+                break 'labels;
+            }
+            title = rest;
+            continue 'labels;
+        }
+        break;
+    }
+    title
+}
+
+// Helper function to run a text through the whole pipeline
+pub fn normalize(text: &str) -> String {
+    let text = decode_entities(&fix_mojibake(text));
+    collapse_whitespace(&normalize_punctuation(&text))
+}
+
+// Helper function to normalize a headline, editorial labels removed
+pub fn normalize_title(title: &str) -> String {
+    strip_prefixes(&normalize(title)).to_string()
+}
+
+pub fn normalize_item(title: &str, description: &str) -> NormalizedText {
+    NormalizedText {
+        title: normalize_title(title),
+        description: normalize(description),
+    }
+}
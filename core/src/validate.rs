@@ -0,0 +1,154 @@
+// Checks on freshly extracted items: a title, a link to the site and a
+// usable image. The server counts what they find; the bindings share them
+use url::Url;
+
+use crate::NewsItem;
+
+// What happens to items breaking the rules below
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValidationMode {
+    // Items with a bad title or link are dropped, bad images cleared
+    Drop,
+    // Items are kept and their problems listed under issues in v2
+    Flag,
+    Off,
+}
+
+impl std::str::FromStr for ValidationMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "drop" => Ok(ValidationMode::Drop),
+            "flag" => Ok(ValidationMode::Flag),
+            "off" => Ok(ValidationMode::Off),
+            _ => Err("expected drop, flag or off".to_string()),
+        }
+    }
+}
+
+// Rules an item can break. An image problem costs the item its image; the
+// others make the item unusable
+pub const EMPTY_TITLE: &str = "empty_title";
+pub const MISSING_LINK: &str = "missing_link";
+pub const INVALID_LINK: &str = "invalid_link";
+pub const INSECURE_LINK: &str = "insecure_link";
+pub const OFFSITE_LINK: &str = "offsite_link";
+pub const INVALID_IMAGE: &str = "invalid_image";
+
+// Extensions an image URL may end in; URLs without one are given the benefit
+// of the doubt
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp", "avif", "svg"];
+
+// Where the links of a page may point
+pub struct Rules {
+    domains: Vec<String>,
+    allow_http: bool,
+}
+
+impl Rules {
+    // The configured domains plus the site of the page itself. Plain http is
+    // only accepted from a page that was itself served over http
+    pub fn for_page(domains: &[String], page_url: &str) -> Rules {
+        let page = Url::parse(page_url).ok();
+        let mut domains = domains.to_vec();
+        if let Some(host) = page.as_ref().and_then(Url::host_str) {
+            domains.push(host.trim_start_matches("www.").to_ascii_lowercase());
+        }
+        Rules {
+            domains,
+            allow_http: page.is_some_and(|page| page.scheme() == "http"),
+        }
+    }
+
+    fn scheme_ok(&self, url: &Url) -> bool {
+        url.scheme() == "https" || (self.allow_http && url.scheme() == "http")
+    }
+
+    fn domain_ok(&self, url: &Url) -> bool {
+        let host = url.host_str().unwrap_or("").to_ascii_lowercase();
+        self.domains.iter().any(|domain| {
+            host == *domain
+                || host
+                    .strip_suffix(domain.as_str())
+                    .is_some_and(|prefix| prefix.ends_with('.'))
+        })
+    }
+}
+
+// Helper function to list the rules an item breaks
+pub fn check(item: &NewsItem, rules: &Rules) -> Vec<&'static str> {
+    let mut broken = Vec::new();
+    if item.title.trim().is_empty() {
+        broken.push(EMPTY_TITLE);
+    }
+
+    if item.link.trim().is_empty() {
+        broken.push(MISSING_LINK);
+    } else {
+        match Url::parse(&item.link) {
+            Ok(link) if link.host_str().is_some() => {
+                if !rules.scheme_ok(&link) {
+                    broken.push(INSECURE_LINK);
+                } else if !rules.domain_ok(&link) {
+                    broken.push(OFFSITE_LINK);
+                }
+            }
+            _ => broken.push(INVALID_LINK),
+        }
+    }
+
+    if let Some(image_url) = &item.image_url {
+        let image_ok = Url::parse(image_url).is_ok_and(|image| {
+            let extension = image
+                .path_segments()
+                .and_then(|mut segments| segments.next_back())
+                .and_then(|name| name.rsplit_once('.'))
+                .map(|(_, extension)| extension.to_ascii_lowercase());
+            rules.scheme_ok(&image)
+                && image.host_str().is_some()
+                && extension.is_none_or(|extension| IMAGE_EXTENSIONS.contains(&extension.as_str()))
+        });
+        if !image_ok {
+            broken.push(INVALID_IMAGE);
+        }
+    }
+    broken
+}
+
+// The domains the server accepts links to by default (VALIDATION_DOMAINS)
+pub const DEFAULT_DOMAINS: &[&str] = &["corriere.it"];
+
+// Helper function to drop or flag the items breaking the rules, as `mode`
+// says. `on_broken` is called with every rule an item breaks
+pub fn filter(
+    news: Vec<NewsItem>,
+    mode: ValidationMode,
+    rules: &Rules,
+    mut on_broken: impl FnMut(&'static str),
+) -> Vec<NewsItem> {
+    if mode == ValidationMode::Off {
+        return news;
+    }
+    news.into_iter()
+        .filter_map(|mut item| {
+            let broken = check(&item, rules);
+            for rule in &broken {
+                on_broken(rule);
+            }
+            match mode {
+                ValidationMode::Drop => {
+                    if broken.iter().any(|rule| *rule != INVALID_IMAGE) {
+                        return None;
+                    }
+                    if !broken.is_empty() {
+                        item.image_url = None;
+                        item.image = None;
+                    }
+                }
+                _ => item.issues = broken.iter().map(|rule| rule.to_string()).collect(),
+            }
+            Some(item)
+        })
+        .collect()
+}
@@ -0,0 +1,22 @@
+use wasm_bindgen::prelude::*;
+
+use crate::typography;
+
+// Items come back as plain objects in the /api/news shape: title,
+// description, link and image_url
+#[wasm_bindgen(js_name = extractFromHtml)]
+pub fn extract_from_html(html: &str) -> Result<JsValue, JsValue> {
+    let json = serde_json::to_string(&crate::extract_from_html(html))
+        .map_err(|e| JsValue::from_str(&e.to_string()))?;
+    js_sys::JSON::parse(&json)
+}
+
+#[wasm_bindgen(js_name = normalizeText)]
+pub fn normalize_text(text: &str) -> String {
+    typography::normalize(text)
+}
+
+#[wasm_bindgen(js_name = normalizeTitle)]
+pub fn normalize_title(title: &str) -> String {
+    typography::normalize_title(title)
+}
@@ -0,0 +1,62 @@
+use corriere_core::extract::{SelectorConfig, SelectorSet, CORRIERE_BASE_URL};
+use corriere_core::validate::ValidationMode;
+use corriere_core::{extract_checked, extract_from_html, extract_from_page, typography};
+
+const HOMEPAGE: &str = include_str!("../../tests/fixtures/homepage.html");
+
+#[test]
+fn homepage_items_are_extracted_without_the_server() {
+    let news = extract_from_html(HOMEPAGE);
+
+    assert_eq!(news.len(), 6);
+    assert!(news
+        .iter()
+        .all(|item| !item.title.is_empty() && item.link.starts_with("https://")));
+    assert_eq!(
+        typography::normalize_title("LIVE | Ucraina, attacco su Kiev"),
+        "Ucraina, attacco su Kiev"
+    );
+}
+
+#[test]
+fn links_resolve_against_the_page_they_came_from() {
+    let html = r#"<div class="body-hp"><div class="bck-media-news">
+        <h4 class="title-art-hp"><a href="/politica/a.html">Titolo</a></h4>
+        </div></div>"#;
+
+    let news = extract_from_page(html, "https://mirror.test");
+
+    assert_eq!(news.len(), 1);
+    assert_eq!(news[0].link, "https://mirror.test/politica/a.html");
+}
+
+#[test]
+fn checked_extraction_falls_back_and_drops_offsite_links() {
+    let html = r#"<div class="body-hp">
+        <div class="bck-media-news"><h4 class="title-art-hp"><a href="/politica/a.html">Titolo</a></h4></div>
+        <div class="bck-media-news"><h4 class="title-art-hp"><a href="https://altro.example/b.html">Altrove</a></h4></div>
+        </div>"#;
+    let sets = [
+        SelectorSet {
+            name: "redesign".to_string(),
+            selectors: SelectorConfig {
+                article: ".card".to_string(),
+                ..SelectorConfig::default()
+            },
+        },
+        SelectorSet {
+            name: "classic".to_string(),
+            selectors: SelectorConfig::default(),
+        },
+    ];
+
+    let (news, set) =
+        extract_checked(html, CORRIERE_BASE_URL, &sets, 1, ValidationMode::Drop).unwrap();
+    assert_eq!(set, "classic");
+    assert_eq!(news.len(), 1);
+    assert_eq!(news[0].link, "https://www.corriere.it/politica/a.html");
+
+    let (news, _) =
+        extract_checked(html, CORRIERE_BASE_URL, &sets, 1, ValidationMode::Flag).unwrap();
+    assert_eq!(news[1].issues, ["offsite_link"]);
+}
@@ -0,0 +1,13 @@
+// Generates the NewsService code with the protoc shipped by
+// protoc-bin-vendored, so building needs no protoc installed
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = prost_build::Config::new();
+    config.protoc_executable(protoc_bin_vendored::protoc_bin_path()?);
+    let well_known = protoc_bin_vendored::include_path()?;
+    tonic_build::configure().compile_protos_with_config(
+        config,
+        &["proto/news.proto"],
+        &[std::path::Path::new("proto"), well_known.as_path()],
+    )?;
+    Ok(())
+}
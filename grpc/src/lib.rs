@@ -0,0 +1,151 @@
+// NewsService (proto/news.proto), answered from the scraper's state: the
+// same cache and fetches as the REST API
+use chrono::{DateTime, Utc};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+use corriere_scraper::article::{self, ArticleDetail};
+use corriere_scraper::url_safety::{self, UrlPolicy};
+use corriere_scraper::{get_news, AppState};
+
+pub mod proto {
+    tonic::include_proto!("corriere.v1");
+}
+
+use proto::news_service_server::NewsService;
+pub use proto::news_service_server::NewsServiceServer;
+use proto::{
+    Article, GetArticleRequest, ListNewsRequest, ListNewsResponse, NewsUpdate, StreamUpdatesRequest,
+};
+
+// Updates a slow client may fall behind by before it's dropped
+const UPDATE_BUFFER: usize = 4;
+
+pub struct News {
+    state: AppState,
+    // How often StreamUpdates checks the homepage
+    update_every: Duration,
+}
+
+fn timestamp(at: DateTime<Utc>) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: at.timestamp(),
+        nanos: at.timestamp_subsec_nanos() as i32,
+    }
+}
+
+fn news_item(item: &corriere_scraper::NewsItem) -> proto::NewsItem {
+    proto::NewsItem {
+        title: item.title.clone(),
+        description: item.description.clone(),
+        link: item.link.clone(),
+        image_url: item.image_url.clone(),
+        prominence_score: item.placement.score(),
+    }
+}
+
+fn article(detail: ArticleDetail) -> Article {
+    Article {
+        url: detail.url,
+        title: detail.title,
+        subtitle: detail.subtitle,
+        author: detail.author,
+        published_at: detail.published_at.map(timestamp),
+        image_url: detail.image_url,
+        body: detail.body,
+        corrections: detail.corrections,
+    }
+}
+
+#[tonic::async_trait]
+impl NewsService for News {
+    async fn list_news(
+        &self,
+        request: Request<ListNewsRequest>,
+    ) -> Result<Response<ListNewsResponse>, Status> {
+        let cached = get_news(&self.state)
+            .await
+            .map_err(|response| Status::unavailable(response.error.unwrap_or_default()))?;
+        let mut news: Vec<_> = cached.response.news.iter().map(news_item).collect();
+        let limit = request.into_inner().limit as usize;
+        if limit > 0 {
+            news.truncate(limit);
+        }
+        Ok(Response::new(ListNewsResponse {
+            scraped_at: Some(timestamp(cached.response.scraped_at)),
+            news,
+            stale: cached.response.stale,
+        }))
+    }
+
+    async fn get_article(
+        &self,
+        request: Request<GetArticleRequest>,
+    ) -> Result<Response<Article>, Status> {
+        let url = request.into_inner().url;
+        // Rejected URLs are the caller's fault, failed fetches upstream's
+        url_safety::check(&UrlPolicy::from_config(&self.state.config), &url)
+            .map_err(Status::invalid_argument)?;
+        let detail = article::fetch_article(&self.state, &url)
+            .await
+            .map_err(Status::unavailable)?;
+        Ok(Response::new(article(detail)))
+    }
+
+    type StreamUpdatesStream = ReceiverStream<Result<NewsUpdate, Status>>;
+
+    async fn stream_updates(
+        &self,
+        _request: Request<StreamUpdatesRequest>,
+    ) -> Result<Response<Self::StreamUpdatesStream>, Status> {
+        let (sender, receiver) = mpsc::channel(UPDATE_BUFFER);
+        let state = self.state.clone();
+        let mut interval = tokio::time::interval(self.update_every);
+        tokio::spawn(async move {
+            let mut previous: Option<Vec<String>> = None;
+            loop {
+                interval.tick().await;
+                let response = match get_news(&state).await {
+                    // A stale copy has nothing new to announce
+                    Ok(cached) if !cached.response.stale => cached.response,
+                    _ => continue,
+                };
+                let links: Vec<String> =
+                    response.news.iter().map(|item| item.link.clone()).collect();
+                let added: Vec<String> = match &previous {
+                    Some(previous) => links
+                        .iter()
+                        .filter(|link| !previous.contains(link))
+                        .cloned()
+                        .collect(),
+                    None => links.clone(),
+                };
+                if previous.is_some() && added.is_empty() {
+                    continue;
+                }
+                let update = NewsUpdate {
+                    scraped_at: Some(timestamp(response.scraped_at)),
+                    news: response.news.iter().map(news_item).collect(),
+                    added,
+                };
+                // The client went away
+                if sender.send(Ok(update)).await.is_err() {
+                    break;
+                }
+                previous = Some(links);
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+// The service over `state`, with StreamUpdates checking the homepage every
+// `update_every`
+pub fn service(state: AppState, update_every: Duration) -> NewsServiceServer<News> {
+    NewsServiceServer::new(News {
+        state,
+        update_every,
+    })
+}
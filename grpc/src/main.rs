@@ -0,0 +1,60 @@
+// gRPC front end for the scraper, for consumers that don't speak REST. It
+// runs the whole server, REST API and background jobs included, and serves
+// NewsService (proto/news.proto) on GRPC_ADDR from the same cache:
+//   cargo run -p corriere_grpc
+use dotenvy::dotenv;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use corriere_scraper::config::Config;
+use corriere_scraper::{listener, router, server};
+
+const DEFAULT_ADDR: &str = "127.0.0.1:50051";
+const DEFAULT_UPDATE_SECS: u64 = 60;
+
+// Helper function to read a setting of the gRPC server, which isn't part of
+// the main Config
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .map_err(|e| format!("Invalid {} '{}': {}", name, value, e)),
+        _ => Ok(default),
+    }
+}
+
+async fn run() -> Result<(), String> {
+    let config = Config::from_env().map_err(|e| format!("Invalid configuration: {}", e))?;
+    let addr: SocketAddr = env_or("GRPC_ADDR", DEFAULT_ADDR.parse().unwrap())?;
+    let update_every = Duration::from_secs(env_or("GRPC_UPDATE_SECS", DEFAULT_UPDATE_SECS)?.max(1));
+
+    let (state, rest_listener) = server::start(config).await?;
+    let service = corriere_grpc::service(state.clone(), update_every);
+    println!("Server listening on {}", rest_listener.describe());
+    println!("gRPC listening on {}", addr);
+
+    let grpc = async {
+        tonic::transport::Server::builder()
+            .add_service(service)
+            .serve(addr)
+            .await
+            .map_err(|e| format!("gRPC server failed: {}", e))
+    };
+    tokio::try_join!(listener::serve(rest_listener, router(state)), grpc)?;
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    // Load environment variables from .env file if it exists
+    dotenv().ok();
+
+    if let Err(error_message) = run().await {
+        eprintln!("{}", error_message);
+        std::process::exit(1);
+    }
+}
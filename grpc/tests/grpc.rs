@@ -0,0 +1,78 @@
+// NewsService end to end: the server as the binary starts it, against a
+// mock upstream, called through the generated client
+use std::time::Duration;
+
+use corriere_grpc::proto::news_service_client::NewsServiceClient;
+use corriere_grpc::proto::{GetArticleRequest, ListNewsRequest};
+use corriere_scraper::config::Config;
+use corriere_scraper::server;
+use tokio_stream::wrappers::TcpListenerStream;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: &str = include_str!("../../tests/fixtures/homepage.html");
+
+// Helper function to start the server on a mock upstream and serve
+// NewsService on a random local port, returning its address
+async fn spawn_grpc(upstream: &MockServer) -> String {
+    let data_dir = std::env::temp_dir().join(format!("corriere-grpc-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&data_dir);
+    let config = Config {
+        homepage_url: format!("{}/", upstream.uri()),
+        scrape_allowed_hosts: vec!["127.0.0.1".to_string()],
+        bind_addr: "127.0.0.1:0".parse().unwrap(),
+        data_dir,
+        ..Config::default()
+    };
+    let (state, _rest_listener) = server::start(config).await.unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let service = corriere_grpc::service(state, Duration::from_secs(60));
+    tokio::spawn(async move {
+        tonic::transport::Server::builder()
+            .add_service(service)
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .unwrap();
+    });
+    format!("http://{}", addr)
+}
+
+#[tokio::test]
+async fn news_and_errors_round_trip() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE))
+        .mount(&upstream)
+        .await;
+    let mut client = NewsServiceClient::connect(spawn_grpc(&upstream).await)
+        .await
+        .unwrap();
+
+    let all = client
+        .list_news(ListNewsRequest { limit: 0 })
+        .await
+        .unwrap()
+        .into_inner();
+    assert!(all.news.len() > 2);
+    assert!(all.scraped_at.is_some());
+    assert!(!all.stale);
+    assert!(all.news.iter().all(|item| !item.title.is_empty()));
+
+    let limited = client
+        .list_news(ListNewsRequest { limit: 2 })
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(limited.news, all.news[..2]);
+
+    let refused = client
+        .get_article(GetArticleRequest {
+            url: "file:///etc/passwd".to_string(),
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(refused.code(), tonic::Code::InvalidArgument);
+}
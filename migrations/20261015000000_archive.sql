@@ -0,0 +1,23 @@
+-- Every article seen on the homepage, with when it was first and last listed
+CREATE TABLE articles (
+    link TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    image_url TEXT,
+    first_seen_at TIMESTAMPTZ NOT NULL,
+    last_seen_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX articles_last_seen_at ON articles (last_seen_at DESC);
+CREATE INDEX articles_search ON articles
+    USING GIN (to_tsvector('italian', title || ' ' || description));
+
+-- One row per article per scrape, with its position on the page
+CREATE TABLE appearances (
+    scraped_at TIMESTAMPTZ NOT NULL,
+    link TEXT NOT NULL REFERENCES articles (link),
+    position INTEGER NOT NULL,
+    PRIMARY KEY (link, scraped_at)
+);
+
+CREATE INDEX appearances_scraped_at ON appearances (scraped_at);
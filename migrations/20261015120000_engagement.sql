@@ -0,0 +1,3 @@
+-- Comment and share counters of an article at each scrape, when shown
+ALTER TABLE appearances ADD COLUMN comments BIGINT;
+ALTER TABLE appearances ADD COLUMN shares BIGINT;
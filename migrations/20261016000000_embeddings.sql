@@ -0,0 +1,10 @@
+-- Vectors of the semantic search index (semantic feature), one per article
+CREATE TABLE embeddings (
+    link TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    description TEXT NOT NULL,
+    image_url TEXT,
+    model TEXT NOT NULL,
+    embedded_at TIMESTAMPTZ NOT NULL,
+    vector REAL[] NOT NULL
+);
@@ -0,0 +1,215 @@
+// Python bindings for corriere_core, so notebooks get the same items as
+// /api/news without re-implementing the selectors:
+//   pip install maturin && maturin develop -m python/Cargo.toml
+//   >>> import corriere
+//   >>> corriere.scrape_homepage()[0].title
+// The extraction and validation themselves are corriere_core's, tested with
+// the workspace; the bindings are tested with
+//   pip install pytest && pytest python/tests
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use corriere_core::extract::{SelectorConfig, SelectorSet, CORRIERE_BASE_URL};
+use corriere_core::validate::ValidationMode;
+use corriere_core::{image, typography};
+
+const USER_AGENT: &str = concat!("corriere-python/", env!("CARGO_PKG_VERSION"));
+// Largest page read, as the server's default SCRAPE_MAX_BYTES
+const MAX_PAGE_BYTES: u64 = 5 * 1024 * 1024;
+
+#[pyclass(module = "corriere", frozen, get_all)]
+#[derive(Clone)]
+pub struct Image {
+    pub url: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub caption: Option<String>,
+    pub credit: Option<String>,
+}
+
+#[pymethods]
+impl Image {
+    fn __repr__(&self) -> String {
+        format!("Image(url={:?})", self.url)
+    }
+}
+
+impl From<image::ImageInfo> for Image {
+    fn from(info: image::ImageInfo) -> Self {
+        Image {
+            url: info.url,
+            width: info.width,
+            height: info.height,
+            caption: info.caption,
+            credit: info.credit,
+        }
+    }
+}
+
+#[pyclass(module = "corriere", frozen, get_all)]
+#[derive(Clone)]
+pub struct NewsItem {
+    pub title: String,
+    pub description: String,
+    pub link: String,
+    pub image_url: Option<String>,
+    pub image: Option<Image>,
+    // Editorial prominence from 0 to 100, as in /api/v2/news
+    pub prominence_score: f64,
+}
+
+#[pymethods]
+impl NewsItem {
+    fn __repr__(&self) -> String {
+        format!("NewsItem(title={:?}, link={:?})", self.title, self.link)
+    }
+
+    // The item in the /api/news shape, e.g. for pandas.DataFrame
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("title", &self.title)?;
+        dict.set_item("description", &self.description)?;
+        dict.set_item("link", &self.link)?;
+        dict.set_item("image_url", &self.image_url)?;
+        dict.set_item("prominence_score", self.prominence_score)?;
+        Ok(dict)
+    }
+}
+
+impl From<corriere_core::NewsItem> for NewsItem {
+    fn from(item: corriere_core::NewsItem) -> Self {
+        NewsItem {
+            prominence_score: item.placement.score(),
+            title: item.title,
+            description: item.description,
+            link: item.link,
+            image_url: item.image_url,
+            image: item.image.map(Image::from),
+        }
+    }
+}
+
+// Helper function to fetch a page, blocking. Pages over MAX_PAGE_BYTES are
+// an error rather than read to the end
+fn fetch_html(url: &str, timeout: Duration) -> Result<String, String> {
+    let client = reqwest::blocking::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(timeout)
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
+    let resp = client
+        .get(url)
+        .send()
+        .map_err(|e| format!("Failed to fetch URL: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("Upstream returned HTTP {}", resp.status()));
+    }
+    let too_large = || format!("Page is larger than {} bytes", MAX_PAGE_BYTES);
+    if resp
+        .content_length()
+        .is_some_and(|len| len > MAX_PAGE_BYTES)
+    {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    resp.take(MAX_PAGE_BYTES + 1)
+        .read_to_end(&mut body)
+        .map_err(|e| format!("Failed to read response text: {}", e))?;
+    if body.len() as u64 > MAX_PAGE_BYTES {
+        return Err(too_large());
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+// Helper function to read the homepage chain of a SELECTOR_SETS_PATH file,
+// or the default selectors alone
+fn homepage_sets(path: Option<PathBuf>) -> PyResult<Vec<SelectorSet>> {
+    let default = || SelectorSet {
+        name: "default".to_string(),
+        selectors: SelectorConfig::default(),
+    };
+    let Some(path) = path else {
+        return Ok(vec![default()]);
+    };
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| PyValueError::new_err(format!("Failed to read {}: {}", path.display(), e)))?;
+    let mut sets: HashMap<String, Vec<SelectorSet>> = serde_json::from_str(&content)
+        .map_err(|e| PyValueError::new_err(format!("Invalid {}: {}", path.display(), e)))?;
+    Ok(sets
+        .remove("homepage")
+        .filter(|chain| !chain.is_empty())
+        .unwrap_or_else(|| vec![default()]))
+}
+
+// Extracts the homepage items from its HTML, links resolved against
+// base_url (the Corriere homepage by default)
+#[pyfunction]
+#[pyo3(signature = (html, base_url = CORRIERE_BASE_URL))]
+fn extract_from_html(py: Python<'_>, html: &str, base_url: &str) -> Vec<NewsItem> {
+    py.allow_threads(|| corriere_core::extract_from_page(html, base_url))
+        .into_iter()
+        .map(NewsItem::from)
+        .collect()
+}
+
+// Fetches the homepage and extracts its items as the server does: the
+// "homepage" selector sets of a SELECTOR_SETS_PATH file in turn, then the
+// validation rules ("drop", "flag" or "off", as VALIDATION_MODE). Raises
+// ValueError for a bad timeout or setting, RuntimeError when the page can't
+// be fetched
+#[pyfunction]
+#[pyo3(signature = (url = CORRIERE_BASE_URL, timeout = 10.0, selector_sets = None, validation = "drop"))]
+fn scrape_homepage(
+    py: Python<'_>,
+    url: &str,
+    timeout: f64,
+    selector_sets: Option<PathBuf>,
+    validation: &str,
+) -> PyResult<Vec<NewsItem>> {
+    if !timeout.is_finite() || timeout <= 0.0 {
+        return Err(PyValueError::new_err(format!(
+            "timeout must be a positive number of seconds, not {}",
+            timeout
+        )));
+    }
+    let timeout = Duration::try_from_secs_f64(timeout)
+        .map_err(|e| PyValueError::new_err(format!("Invalid timeout: {}", e)))?;
+    let mode: ValidationMode = validation.parse().map_err(|e| {
+        PyValueError::new_err(format!("Invalid validation '{}': {}", validation, e))
+    })?;
+    let sets = homepage_sets(selector_sets)?;
+
+    let (news, _) = py
+        .allow_threads(|| {
+            let html = fetch_html(url, timeout)?;
+            corriere_core::extract_checked(&html, url, &sets, 1, mode)
+        })
+        .map_err(PyRuntimeError::new_err)?;
+    Ok(news.into_iter().map(NewsItem::from).collect())
+}
+
+#[pyfunction]
+fn normalize(text: &str) -> String {
+    typography::normalize(text)
+}
+
+#[pyfunction]
+fn normalize_title(title: &str) -> String {
+    typography::normalize_title(title)
+}
+
+#[pymodule]
+fn corriere(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<NewsItem>()?;
+    m.add_class::<Image>()?;
+    m.add_function(wrap_pyfunction!(extract_from_html, m)?)?;
+    m.add_function(wrap_pyfunction!(scrape_homepage, m)?)?;
+    m.add_function(wrap_pyfunction!(normalize, m)?)?;
+    m.add_function(wrap_pyfunction!(normalize_title, m)?)?;
+    Ok(())
+}
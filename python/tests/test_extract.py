@@ -0,0 +1,38 @@
+import pathlib
+
+import pytest
+
+import corriere
+
+HOMEPAGE = pathlib.Path(__file__).parents[2] / "tests" / "fixtures" / "homepage.html"
+
+
+def test_homepage_items_match_the_server():
+    news = corriere.extract_from_html(HOMEPAGE.read_text())
+
+    assert len(news) == 6
+    assert all(item.link.startswith("https://") for item in news)
+    assert news[0].to_dict()["title"] == news[0].title
+    assert news[0].prominence_score >= news[-1].prominence_score
+
+
+def test_relative_links_follow_the_base_url():
+    html = (
+        '<div class="body-hp"><div class="bck-media-news">'
+        '<h4 class="title-art-hp"><a href="/politica/a.html">Titolo</a></h4>'
+        "</div></div>"
+    )
+    news = corriere.extract_from_html(html, base_url="https://mirror.test")
+
+    assert [item.link for item in news] == ["https://mirror.test/politica/a.html"]
+
+
+@pytest.mark.parametrize("timeout", [-1.0, 0.0, float("nan"), float("inf")])
+def test_bad_timeouts_are_rejected_before_fetching(timeout):
+    with pytest.raises(ValueError):
+        corriere.scrape_homepage("http://127.0.0.1:9", timeout=timeout)
+
+
+def test_unknown_validation_mode_is_rejected():
+    with pytest.raises(ValueError):
+        corriere.scrape_homepage("http://127.0.0.1:9", validation="strict")
@@ -0,0 +1,178 @@
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+use subtle::ConstantTimeEq;
+use tokio::sync::oneshot;
+
+use crate::audit::{self, AuditEvent};
+use crate::{request_id, scheduler, AppState};
+
+// Helper function to check the request carries ADMIN_TOKEN, or one of
+// ADMIN_TOKENS, as a bearer token, returning the error response when it
+// doesn't. The admin API doesn't exist at all when no token is configured
+pub fn unauthorized(state: &AppState, headers: &HeaderMap) -> Option<Response> {
+    if state.config.admin_token.is_none() && state.config.admin_tokens.is_empty() {
+        return Some(admin_error(
+            StatusCode::NOT_FOUND,
+            "The admin API is not enabled",
+        ));
+    }
+    actor(state, headers)
+        .is_none()
+        .then(|| admin_error(StatusCode::UNAUTHORIZED, "Missing or invalid admin token"))
+}
+
+// Helper function to name who the request's bearer token belongs to: its
+// name in ADMIN_TOKENS, or "admin" for ADMIN_TOKEN. Tokens are compared in
+// constant time
+pub fn actor(state: &AppState, headers: &HeaderMap) -> Option<String> {
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))?;
+    let matches = |token: &str| bool::from(token.as_bytes().ct_eq(provided.as_bytes()));
+    if state.config.admin_token.as_deref().is_some_and(matches) {
+        return Some("admin".to_string());
+    }
+    state
+        .config
+        .admin_tokens
+        .iter()
+        .find(|(_, token)| matches(token))
+        .map(|(name, _)| name.clone())
+}
+
+pub fn admin_error(status: StatusCode, error_message: &str) -> Response {
+    (status, Json(json!({ "error": error_message }))).into_response()
+}
+
+// Status of the scheduled jobs: last run, outcome and next run
+pub async fn scheduler_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(response) = unauthorized(&state, &headers) {
+        return response;
+    }
+    Json(json!({ "jobs": state.scheduler.status() })).into_response()
+}
+
+// Replacement selectors suggested for pages whose latest scrape came back
+// empty, best first
+pub async fn selectors_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(response) = unauthorized(&state, &headers) {
+        return response;
+    }
+    Json(json!({ "reports": state.repairs.reports() })).into_response()
+}
+
+// Runs a scheduled job right away, unless it is already running
+pub async fn run_job_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Response {
+    if let Some(response) = unauthorized(&state, &headers) {
+        return response;
+    }
+    if !state.scheduler.jobs().iter().any(|job| job.name == name) {
+        return admin_error(StatusCode::NOT_FOUND, "No job with this name");
+    }
+
+    // The job runs on its own task, so a request that times out or is
+    // dropped by the client doesn't cut it short between start and finish
+    let (done, result) = oneshot::channel();
+    let actor = actor(&state, &headers);
+    let job = name.clone();
+    request_id::spawn(async move {
+        let result = audit::triggered("admin", scheduler::run_job(&state, &job)).await;
+        let event = AuditEvent::admin("run_job", actor, Some(&job), &result);
+        audit::record(&state, event).await;
+        let _ = done.send(result);
+    });
+    let result = result
+        .await
+        .unwrap_or_else(|_| Err(format!("Job '{}' was interrupted", name)));
+    match result {
+        Ok(items) => Json(json!({ "job": name, "items": items })).into_response(),
+        Err(error_message) => admin_error(StatusCode::CONFLICT, &error_message),
+    }
+}
+
+// Notifications waiting for another attempt, and the dead ones that ran out
+// of attempts
+pub async fn deliveries_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(response) = unauthorized(&state, &headers) {
+        return response;
+    }
+    match state.deliveries.list() {
+        Ok(deliveries) => {
+            let dead = deliveries.iter().filter(|delivery| delivery.dead).count();
+            Json(json!({
+                "pending": deliveries.len() - dead,
+                "dead": dead,
+                "deliveries": deliveries,
+            }))
+            .into_response()
+        }
+        Err(error_message) => admin_error(StatusCode::INTERNAL_SERVER_ERROR, &error_message),
+    }
+}
+
+// Makes a delivery due right away with a fresh set of attempts
+pub async fn requeue_delivery_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    if let Some(response) = unauthorized(&state, &headers) {
+        return response;
+    }
+    let result = state.deliveries.requeue(&id);
+    let event = AuditEvent::admin(
+        "requeue_delivery",
+        actor(&state, &headers),
+        Some(&id),
+        &result,
+    );
+    audit::record(&state, event).await;
+    match result {
+        Ok(Some(delivery)) => Json(json!({ "delivery": delivery })).into_response(),
+        Ok(None) => admin_error(StatusCode::NOT_FOUND, "No delivery with this id"),
+        Err(error_message) => admin_error(StatusCode::INTERNAL_SERVER_ERROR, &error_message),
+    }
+}
+
+// Drops a delivery for good
+pub async fn delete_delivery_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    if let Some(response) = unauthorized(&state, &headers) {
+        return response;
+    }
+    let result = state.deliveries.delete(&id);
+    let event = AuditEvent::admin(
+        "delete_delivery",
+        actor(&state, &headers),
+        Some(&id),
+        &result,
+    );
+    audit::record(&state, event).await;
+    match result {
+        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+        Ok(false) => admin_error(StatusCode::NOT_FOUND, "No delivery with this id"),
+        Err(error_message) => admin_error(StatusCode::INTERNAL_SERVER_ERROR, &error_message),
+    }
+}
+
+// Forgets the cached homepage, so the next request scrapes it again
+pub async fn flush_cache_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(response) = unauthorized(&state, &headers) {
+        return response;
+    }
+    state.news_cache.clear();
+    let event = AuditEvent::admin("flush_cache", actor(&state, &headers), None, &Ok(()));
+    audit::record(&state, event).await;
+    StatusCode::NO_CONTENT.into_response()
+}
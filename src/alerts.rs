@@ -0,0 +1,777 @@
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use lettre::Address;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::StreamExt;
+
+use crate::clusters::{self, SourcedItem, HOMEPAGE_SOURCE};
+use crate::config::Config;
+use crate::delivery::{self, Message, Origin};
+use crate::digest::Mailer;
+use crate::extract;
+use crate::formats::escape_html;
+use crate::json_file;
+use crate::lease;
+use crate::request_id;
+use crate::sent_log::{self, SentLog};
+use crate::subscriptions::{self, random_string, tokens_match, TokenParams};
+use crate::url_safety::{self, UrlPolicy};
+use crate::AppState;
+
+// Match events kept for SSE clients that fall behind
+const EVENT_BUFFER: usize = 64;
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+// Where an alert's matches are sent
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Channel {
+    // POSTed as JSON
+    Webhook { url: String },
+    // Mailed through the digest's SMTP settings
+    Email { address: String },
+    // Only streamed from /api/alerts/:id/events
+    Sse,
+}
+
+impl Channel {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Channel::Webhook { .. } => "webhook",
+            Channel::Email { .. } => "email",
+            Channel::Sse => "sse",
+        }
+    }
+}
+
+// A saved search owned by one of the ALERT_API_KEYS. New articles matching
+// every filter that is set go to the alert's channel
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Alert {
+    pub id: String,
+    pub api_key: String,
+    // Secret of the unsubscribe link in alert emails. Alerts saved before
+    // it existed have none and are deleted through the API only
+    #[serde(default)]
+    pub token: String,
+    // Looked for in the title and summary, ignoring case
+    pub keyword: Option<String>,
+    // Section of corriere.it, as in the article URL (e.g. "esteri")
+    pub category: Option<String>,
+    // "corriere" or one of the NEWS_SOURCES outlets
+    pub source: Option<String>,
+    pub channel: Channel,
+    pub created_at: DateTime<Utc>,
+}
+
+impl Alert {
+    pub fn matches(&self, sourced: &SourcedItem) -> bool {
+        let item = &sourced.item;
+        let keyword = self.keyword.as_ref().is_none_or(|keyword| {
+            let keyword = keyword.to_lowercase();
+            item.title.to_lowercase().contains(&keyword)
+                || item.description.to_lowercase().contains(&keyword)
+        });
+        let category = self
+            .category
+            .as_ref()
+            .is_none_or(|category| extract::section(&item.link).as_ref() == Some(category));
+        let source = self
+            .source
+            .as_ref()
+            .is_none_or(|source| *source == sourced.source);
+        keyword && category && source
+    }
+}
+
+#[derive(Serialize)]
+pub struct AlertView {
+    pub id: String,
+    pub keyword: Option<String>,
+    pub category: Option<String>,
+    pub source: Option<String>,
+    pub channel: Channel,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&Alert> for AlertView {
+    fn from(alert: &Alert) -> Self {
+        AlertView {
+            id: alert.id.clone(),
+            keyword: alert.keyword.clone(),
+            category: alert.category.clone(),
+            source: alert.source.clone(),
+            channel: alert.channel.clone(),
+            created_at: alert.created_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AlertResponse {
+    pub alert: Option<AlertView>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct AlertListResponse {
+    pub alerts: Vec<AlertView>,
+    pub error: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AlertRequest {
+    keyword: Option<String>,
+    category: Option<String>,
+    source: Option<String>,
+    channel: Channel,
+}
+
+// Sent to the alert's channel with the new articles it matched
+#[derive(Serialize, Clone)]
+pub struct AlertEvent {
+    pub alert_id: String,
+    pub detected_at: DateTime<Utc>,
+    pub items: Vec<SourcedItem>,
+}
+
+// Saved searches, persisted as a JSON file under DATA_DIR, along with the
+// channel their matches are published on for SSE clients
+pub struct AlertStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+    events: broadcast::Sender<AlertEvent>,
+}
+
+impl AlertStore {
+    pub fn new(path: PathBuf) -> AlertStore {
+        AlertStore {
+            path,
+            lock: Mutex::new(()),
+            events: broadcast::channel(EVENT_BUFFER).0,
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<Alert>, String> {
+        let _lock = self.lock.lock().unwrap();
+        self.load()
+    }
+
+    // The alerts of one API key
+    pub fn list_for(&self, api_key: &str) -> Result<Vec<Alert>, String> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|alert| alert.api_key == api_key)
+            .collect())
+    }
+
+    // Fails when the key already has `max` alerts
+    pub fn create(
+        &self,
+        api_key: &str,
+        request: AlertRequest,
+        max: usize,
+    ) -> Result<Alert, String> {
+        let _lock = self.lock.lock().unwrap();
+        let mut alerts = self.load()?;
+        if alerts
+            .iter()
+            .filter(|alert| alert.api_key == api_key)
+            .count()
+            >= max
+        {
+            return Err(format!("At most {} alerts per API key", max));
+        }
+
+        let alert = Alert {
+            id: random_string(12),
+            api_key: api_key.to_string(),
+            token: random_string(32),
+            keyword: request.keyword,
+            category: request.category,
+            source: request.source,
+            channel: request.channel,
+            created_at: Utc::now(),
+        };
+        alerts.push(alert.clone());
+        json_file::save(&self.path, &alerts)?;
+        Ok(alert)
+    }
+
+    pub fn get(&self, id: &str, api_key: &str) -> Result<Option<Alert>, String> {
+        let _lock = self.lock.lock().unwrap();
+        Ok(self
+            .load()?
+            .into_iter()
+            .find(|alert| alert.id == id && alert.api_key == api_key))
+    }
+
+    // Returns whether a matching alert was removed
+    pub fn delete(&self, id: &str, api_key: &str) -> Result<bool, String> {
+        let _lock = self.lock.lock().unwrap();
+        let mut alerts = self.load()?;
+
+        let count = alerts.len();
+        alerts.retain(|alert| !(alert.id == id && alert.api_key == api_key));
+        if alerts.len() == count {
+            return Ok(false);
+        }
+
+        json_file::save(&self.path, &alerts)?;
+        Ok(true)
+    }
+
+    // Deletes the alert whose unsubscribe link was followed; whether one
+    // was removed
+    pub fn unsubscribe(&self, id: &str, token: &str) -> Result<bool, String> {
+        let _lock = self.lock.lock().unwrap();
+        let mut alerts = self.load()?;
+
+        let count = alerts.len();
+        alerts.retain(|alert| {
+            !(alert.id == id && !alert.token.is_empty() && tokens_match(token, &alert.token))
+        });
+        if alerts.len() == count {
+            return Ok(false);
+        }
+
+        json_file::save(&self.path, &alerts)?;
+        Ok(true)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AlertEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn publish(&self, event: AlertEvent) {
+        // Sending only fails when nobody is listening, which is fine
+        let _ = self.events.send(event);
+    }
+
+    fn load(&self) -> Result<Vec<Alert>, String> {
+        Ok(json_file::load(&self.path)?.unwrap_or_default())
+    }
+}
+
+fn alert_response(
+    status: StatusCode,
+    alert: Option<&Alert>,
+    error: Option<String>,
+) -> (StatusCode, Json<AlertResponse>) {
+    (
+        status,
+        Json(AlertResponse {
+            alert: alert.map(AlertView::from),
+            error,
+        }),
+    )
+}
+
+fn error_response(status: StatusCode, error_message: String) -> (StatusCode, Json<AlertResponse>) {
+    alert_response(status, None, Some(error_message))
+}
+
+fn not_found() -> (StatusCode, Json<AlertResponse>) {
+    error_response(
+        StatusCode::NOT_FOUND,
+        "No alert with this id for this API key".to_string(),
+    )
+}
+
+// Helper function to find the caller's API key in the X-API-Key header. It
+// is never taken from the URL, which ends up in access logs. Alerts don't
+// exist when no ALERT_API_KEYS are configured
+fn api_key(state: &AppState, headers: &HeaderMap) -> Result<String, (StatusCode, String)> {
+    if state.config.alert_api_keys.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "Alerts are not enabled".to_string()));
+    }
+
+    headers
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        // Every key is compared, so the time taken doesn't tell which matched
+        .filter(|key| {
+            state
+                .config
+                .alert_api_keys
+                .iter()
+                .fold(false, |found, known| tokens_match(key, known) | found)
+        })
+        .map(str::to_string)
+        .ok_or_else(|| {
+            (
+                StatusCode::UNAUTHORIZED,
+                "Missing or invalid API key".to_string(),
+            )
+        })
+}
+
+// Helper function to check an alert filters on something and its channel
+// can be delivered to, normalizing the filters
+async fn check_request(
+    state: &AppState,
+    mut request: AlertRequest,
+) -> Result<AlertRequest, String> {
+    let normalize = |value: Option<String>| {
+        value
+            .map(|value| value.trim().to_lowercase())
+            .filter(|value| !value.is_empty())
+    };
+    request.keyword = normalize(request.keyword);
+    request.category = normalize(request.category);
+    request.source = normalize(request.source);
+    if request.keyword.is_none() && request.category.is_none() && request.source.is_none() {
+        return Err("An alert needs a keyword, category or source".to_string());
+    }
+
+    if let Some(source) = &request.source {
+        let known = source == HOMEPAGE_SOURCE
+            || state
+                .config
+                .news_sources
+                .iter()
+                .any(|(name, _)| name == source);
+        if !known {
+            return Err(format!("Unknown source '{}'", source));
+        }
+    }
+
+    match &mut request.channel {
+        Channel::Webhook { url } => {
+            url_safety::check_callback(&UrlPolicy::from_config(&state.config), url).await?;
+        }
+        Channel::Email { address } => {
+            if state.config.smtp_url.is_none() {
+                return Err("Email alerts need SMTP_URL".to_string());
+            }
+            *address = address.trim().to_string();
+            if address.parse::<Address>().is_err() {
+                return Err(format!("Invalid email address '{}'", address));
+            }
+        }
+        Channel::Sse => {}
+    }
+    Ok(request)
+}
+
+pub async fn create_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<AlertRequest>,
+) -> (StatusCode, Json<AlertResponse>) {
+    let api_key = match api_key(&state, &headers) {
+        Ok(api_key) => api_key,
+        Err((status, error_message)) => return error_response(status, error_message),
+    };
+    let request = match check_request(&state, request).await {
+        Ok(request) => request,
+        Err(error_message) => return error_response(StatusCode::BAD_REQUEST, error_message),
+    };
+
+    match state
+        .alerts
+        .create(&api_key, request, state.config.alert_max_per_key)
+    {
+        Ok(alert) => alert_response(StatusCode::CREATED, Some(&alert), None),
+        Err(error_message) => error_response(StatusCode::CONFLICT, error_message),
+    }
+}
+
+pub async fn list_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let api_key = match api_key(&state, &headers) {
+        Ok(api_key) => api_key,
+        Err((status, error_message)) => {
+            return error_response(status, error_message).into_response()
+        }
+    };
+
+    let (status, alerts, error) = match state.alerts.list_for(&api_key) {
+        Ok(alerts) => (StatusCode::OK, alerts, None),
+        Err(error_message) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            vec![],
+            Some(error_message),
+        ),
+    };
+    (
+        status,
+        Json(AlertListResponse {
+            alerts: alerts.iter().map(AlertView::from).collect(),
+            error,
+        }),
+    )
+        .into_response()
+}
+
+pub async fn get_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> (StatusCode, Json<AlertResponse>) {
+    let api_key = match api_key(&state, &headers) {
+        Ok(api_key) => api_key,
+        Err((status, error_message)) => return error_response(status, error_message),
+    };
+
+    match state.alerts.get(&id, &api_key) {
+        Ok(Some(alert)) => alert_response(StatusCode::OK, Some(&alert), None),
+        Ok(None) => not_found(),
+        Err(error_message) => error_response(StatusCode::INTERNAL_SERVER_ERROR, error_message),
+    }
+}
+
+pub async fn delete_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> (StatusCode, Json<AlertResponse>) {
+    let api_key = match api_key(&state, &headers) {
+        Ok(api_key) => api_key,
+        Err((status, error_message)) => return error_response(status, error_message),
+    };
+
+    match state.alerts.delete(&id, &api_key) {
+        Ok(true) => alert_response(StatusCode::OK, None, None),
+        Ok(false) => not_found(),
+        Err(error_message) => error_response(StatusCode::INTERNAL_SERVER_ERROR, error_message),
+    }
+}
+
+// Server-sent events with the matches of one alert, whatever its channel.
+// Events missed while disconnected are not replayed
+pub async fn events_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Response {
+    let api_key = match api_key(&state, &headers) {
+        Ok(api_key) => api_key,
+        Err((status, error_message)) => {
+            return error_response(status, error_message).into_response()
+        }
+    };
+    match state.alerts.get(&id, &api_key) {
+        Ok(Some(_)) => {}
+        Ok(None) => return not_found().into_response(),
+        Err(error_message) => {
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, error_message).into_response()
+        }
+    }
+
+    // A client that lags behind skips the events it missed
+    let stream = BroadcastStream::new(state.alerts.subscribe()).filter_map(move |event| {
+        let event = event.ok().filter(|event| event.alert_id == id)?;
+        Some(Event::default().event("alert").json_data(&event))
+    });
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+// The link in alert emails opens a page asking to confirm, and the button
+// there posts back to delete the alert
+pub async fn unsubscribe_page_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<TokenParams>,
+) -> Response {
+    let action = format!(
+        "{}/api/alerts/{}/unsubscribe?token={}",
+        state.config.public_url,
+        id,
+        params.token.as_deref().unwrap_or("")
+    );
+    subscriptions::unsubscribe_page(
+        StatusCode::OK,
+        "Vuoi smettere di ricevere questo avviso?",
+        Some(&action),
+    )
+}
+
+pub async fn unsubscribe_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<TokenParams>,
+) -> Response {
+    let (status, message) = match state
+        .alerts
+        .unsubscribe(&id, params.token.as_deref().unwrap_or(""))
+    {
+        Ok(true) => (
+            StatusCode::OK,
+            "Avviso annullato: non riceverai più questi articoli.".to_string(),
+        ),
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            "Link non valido o avviso già annullato.".to_string(),
+        ),
+        Err(error_message) => (StatusCode::INTERNAL_SERVER_ERROR, error_message),
+    };
+    subscriptions::unsubscribe_page(status, &message, None)
+}
+
+// Helper function to build the link in alert emails that deletes the alert
+pub fn unsubscribe_url(config: &Config, alert: &Alert) -> Option<String> {
+    (!alert.token.is_empty()).then(|| {
+        format!(
+            "{}/api/alerts/{}/unsubscribe?token={}",
+            config.public_url, alert.id, alert.token
+        )
+    })
+}
+
+// Helper function to render the email for an alert's matches, as plain text
+// and HTML
+fn email(event: &AlertEvent, unsubscribe_url: Option<&str>) -> (String, String) {
+    let mut text = String::from("Nuovi articoli per il tuo avviso:\n\n");
+    let mut html = String::from("<p>Nuovi articoli per il tuo avviso:</p>\n<ul>\n");
+    for sourced in &event.items {
+        text.push_str(&format!(
+            "- {}\n  {}\n",
+            sourced.item.title, sourced.item.link
+        ));
+        html.push_str(&format!(
+            "<li><a href=\"{}\">{}</a> ({})</li>\n",
+            escape_html(&sourced.item.link),
+            escape_html(&sourced.item.title),
+            escape_html(&sourced.source)
+        ));
+    }
+    html.push_str("</ul>\n");
+    if let Some(url) = unsubscribe_url {
+        text.push_str(&format!("\nPer non ricevere più questo avviso: {}\n", url));
+        html.push_str(&format!(
+            "<p><a href=\"{}\">Non ricevere più questo avviso</a></p>\n",
+            escape_html(url)
+        ));
+    }
+    (text, html)
+}
+
+// Looks for new articles on the homepage and the NEWS_SOURCES outlets and
+// sends them to the alerts they match
+pub struct AlertNotifier {
+    client: reqwest::Client,
+    mailer: Option<Mailer>,
+    sent: SentLog,
+}
+
+impl AlertNotifier {
+    pub fn new(config: &Config, mailer: Option<Mailer>) -> Result<AlertNotifier, String> {
+        Ok(AlertNotifier {
+            client: url_safety::build_callback_client(
+                &UrlPolicy::from_config(config),
+                NOTIFY_TIMEOUT,
+            ),
+            mailer,
+            sent: SentLog::load(config, "alerts")?,
+        })
+    }
+
+    // Helper function to send the items that weren't seen before to the
+    // alerts they match. The first run only records the current items.
+    // Returns the events sent
+    pub async fn notify(
+        &mut self,
+        state: &AppState,
+        items: &[SourcedItem],
+    ) -> Result<Vec<AlertEvent>, String> {
+        if !self.sent.is_stored() {
+            for sourced in items {
+                self.sent.push(sourced.item.link.clone());
+            }
+            self.sent.save()?;
+            return Ok(vec![]);
+        }
+
+        let mut fresh: Vec<&SourcedItem> = Vec::new();
+        for sourced in items {
+            if !self.sent.contains(&sourced.item.link)
+                && !fresh.iter().any(|seen| seen.item.link == sourced.item.link)
+            {
+                fresh.push(sourced);
+            }
+        }
+        if fresh.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let request_id = request_id::current().unwrap_or_else(request_id::generate);
+        let mut events = Vec::new();
+        for alert in state.alerts.list()? {
+            let matched: Vec<SourcedItem> = fresh
+                .iter()
+                .filter(|sourced| alert.matches(sourced))
+                .map(|sourced| (*sourced).clone())
+                .collect();
+            if matched.is_empty() {
+                continue;
+            }
+
+            let event = AlertEvent {
+                alert_id: alert.id.clone(),
+                detected_at: Utc::now(),
+                items: matched,
+            };
+            state.metrics.increment(
+                "corriere_alert_matches_total",
+                &[("channel", alert.channel.as_str())],
+            );
+            if let Err((message, error_message)) =
+                self.send(state, &alert, &event, &request_id).await
+            {
+                eprintln!(
+                    "[{}] Alert {} to {} failed: {}",
+                    request_id,
+                    alert.id,
+                    message.target(),
+                    error_message
+                );
+                let origin = Some(Origin::Alert(alert.id.clone()));
+                delivery::enqueue(state, message, origin, &error_message);
+            }
+            state.alerts.publish(event.clone());
+            events.push(event);
+        }
+
+        for sourced in fresh {
+            self.sent.push(sourced.item.link.clone());
+        }
+        self.sent.save()?;
+        Ok(events)
+    }
+
+    // Helper function to send an event to the alert's channel, returning the
+    // message to queue for a retry when that fails
+    async fn send(
+        &self,
+        state: &AppState,
+        alert: &Alert,
+        event: &AlertEvent,
+        request_id: &str,
+    ) -> Result<(), (Message, String)> {
+        match &alert.channel {
+            Channel::Webhook { url } => {
+                let payload = serde_json::json!({
+                    "event": "alert",
+                    "alert": AlertView::from(alert),
+                    "items": event.items,
+                });
+                self.client
+                    .post(url)
+                    .header(request_id::HEADER, request_id)
+                    .json(&payload)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map(|_| ())
+                    .map_err(|e| {
+                        let message = Message::Webhook {
+                            url: url.clone(),
+                            request_id: request_id.to_string(),
+                            payload,
+                        };
+                        (message, e.to_string())
+                    })
+            }
+            Channel::Email { address } => {
+                let unsubscribe_url = unsubscribe_url(&state.config, alert);
+                let (text, html) = email(event, unsubscribe_url.as_deref());
+                let subject = format!("Corriere: {} nuovi articoli", event.items.len());
+                let result = match &self.mailer {
+                    Some(mailer) => {
+                        mailer
+                            .send(
+                                address,
+                                &subject,
+                                text.clone(),
+                                html.clone(),
+                                unsubscribe_url.as_deref(),
+                            )
+                            .await
+                    }
+                    None => Err("SMTP_URL is not set".to_string()),
+                };
+                result.map_err(|error_message| {
+                    let message = Message::Email {
+                        to: address.clone(),
+                        subject,
+                        text,
+                        html,
+                        unsubscribe_url,
+                    };
+                    (message, error_message)
+                })
+            }
+            Channel::Sse => Ok(()),
+        }
+    }
+}
+
+// Helper function to gather the homepage and NEWS_SOURCES items, skipping
+// the sources that can't be scraped
+pub async fn gather(state: &AppState) -> Vec<SourcedItem> {
+    let mut items = Vec::new();
+    match crate::get_news(state).await {
+        // A stale copy has nothing new
+        Ok(cached) if !cached.response.stale => {
+            items.extend(cached.response.news.into_iter().map(|item| SourcedItem {
+                source: HOMEPAGE_SOURCE.to_string(),
+                item,
+            }))
+        }
+        Ok(_) => {}
+        Err(response) => eprintln!(
+            "Alerts: homepage unavailable: {}",
+            response.error.unwrap_or_default()
+        ),
+    }
+    for (source, url) in &state.config.news_sources {
+        match clusters::source_news(state, source, url).await {
+            Ok(news) => items.extend(news.into_iter().map(|item| SourcedItem {
+                source: source.clone(),
+                item,
+            })),
+            Err(error_message) => eprintln!("Alerts: {} unavailable: {}", source, error_message),
+        }
+    }
+    items
+}
+
+// Checks the sources every ALERT_POLL_SECS and notifies the alerts, for as
+// long as the server runs
+pub async fn run(state: AppState, mut notifier: AlertNotifier) {
+    let every = Duration::from_secs(state.config.alert_poll_secs.max(1));
+    let mut interval = tokio::time::interval(every);
+
+    loop {
+        interval.tick().await;
+        // Settings changed by a reload apply from this run on
+        let state = state.current();
+        let ttl = lease::lease_ttl(every);
+        if !sent_log::should_post(&state, "alerts", ttl, &mut [&mut notifier.sent]).await {
+            continue;
+        }
+
+        let request_id = request_id::generate();
+        let result = request_id::scope(request_id.clone(), async {
+            let items = gather(&state).await;
+            notifier.notify(&state, &items).await
+        })
+        .await;
+        if let Err(error_message) = result {
+            eprintln!("[{}] Alert check failed: {}", request_id, error_message);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{self, ArchivedScrape, Storage};
+use crate::AppState;
+
+// How long after its last appearance we look for the scrape it dropped off in
+const DROP_OFF_WINDOW_HOURS: i64 = 24;
+
+// Where an article was in one scrape; position is 1-based and null when the
+// article was off the homepage
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct PlacementPoint {
+    pub scraped_at: DateTime<Utc>,
+    pub position: Option<usize>,
+}
+
+// A change of position between consecutive scrapes
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct PlacementMove {
+    pub scraped_at: DateTime<Utc>,
+    pub from: Option<usize>,
+    pub to: Option<usize>,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct PlacementTimeline {
+    pub url: String,
+    pub first_seen_at: Option<DateTime<Utc>>,
+    pub last_seen_at: Option<DateTime<Utc>>,
+    // First scrape after the last appearance, when there is one yet
+    pub dropped_off_at: Option<DateTime<Utc>>,
+    pub best_position: Option<usize>,
+    pub appearances: usize,
+    // Every scrape from the first appearance to the drop-off
+    pub series: Vec<PlacementPoint>,
+    pub moves: Vec<PlacementMove>,
+    pub error: Option<String>,
+}
+
+// Helper function to build an article's timeline from the scrapes covering
+// its time on the homepage, oldest first
+pub fn timeline(url: &str, scrapes: &[ArchivedScrape]) -> PlacementTimeline {
+    let mut series: Vec<PlacementPoint> = scrapes
+        .iter()
+        .map(|scrape| PlacementPoint {
+            scraped_at: scrape.scraped_at,
+            position: scrape
+                .news
+                .iter()
+                .position(|item| item.link == url)
+                .map(|index| index + 1),
+        })
+        .skip_while(|point| point.position.is_none())
+        .collect();
+
+    let last_index = series.iter().rposition(|point| point.position.is_some());
+    let Some(last_index) = last_index else {
+        return PlacementTimeline {
+            url: url.to_string(),
+            ..PlacementTimeline::default()
+        };
+    };
+    // Keep the scrape it dropped off in, and nothing after
+    series.truncate(last_index + 2);
+
+    let moves = series
+        .windows(2)
+        .filter(|pair| pair[0].position != pair[1].position)
+        .map(|pair| PlacementMove {
+            scraped_at: pair[1].scraped_at,
+            from: pair[0].position,
+            to: pair[1].position,
+        })
+        .collect();
+    let present = || series.iter().filter(|point| point.position.is_some());
+
+    PlacementTimeline {
+        url: url.to_string(),
+        first_seen_at: series.first().map(|point| point.scraped_at),
+        last_seen_at: Some(series[last_index].scraped_at),
+        dropped_off_at: series.get(last_index + 1).map(|point| point.scraped_at),
+        best_position: present().filter_map(|point| point.position).min(),
+        appearances: present().count(),
+        moves,
+        series,
+        error: None,
+    }
+}
+
+// Helper function to load the scrapes from an article's first appearance
+// between `from` and `to` up to the one it dropped off in
+async fn load_timeline(
+    archive: &dyn Storage,
+    url: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<PlacementTimeline, String> {
+    let history = archive.history(url, Some(from), Some(to)).await?;
+    let (Some(first), Some(last)) = (history.first(), history.last()) else {
+        return Ok(timeline(url, &[]));
+    };
+
+    let after_last = last.scraped_at + Duration::microseconds(1);
+    let mut scrapes = archive.scrapes(first.scraped_at, after_last).await?;
+    let later = archive
+        .scrapes(
+            after_last,
+            last.scraped_at + Duration::hours(DROP_OFF_WINDOW_HOURS),
+        )
+        .await?;
+    scrapes.extend(later.into_iter().take(1));
+    Ok(timeline(url, &scrapes))
+}
+
+#[derive(Deserialize)]
+pub struct PlacementParams {
+    url: String,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+// Homepage positions of an article across the archived scrapes: when it
+// appeared, how it moved and when it dropped off
+pub async fn placement_handler(
+    State(state): State<AppState>,
+    Query(params): Query<PlacementParams>,
+) -> (StatusCode, Json<PlacementTimeline>) {
+    let result = match (
+        &state.archive,
+        archive::history_range(params.from, params.to),
+    ) {
+        (None, _) => Err((
+            StatusCode::NOT_FOUND,
+            "The archive is not enabled".to_string(),
+        )),
+        (Some(_), Err(error_message)) => Err((StatusCode::BAD_REQUEST, error_message)),
+        (Some(archive), Ok((from, to))) => load_timeline(archive.as_ref(), &params.url, from, to)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e)),
+    };
+
+    match result {
+        Ok(timeline) => (StatusCode::OK, Json(timeline)),
+        Err((status, error_message)) => (
+            status,
+            Json(PlacementTimeline {
+                url: params.url,
+                error: Some(error_message),
+                ..PlacementTimeline::default()
+            }),
+        ),
+    }
+}
@@ -0,0 +1,830 @@
+use async_trait::async_trait;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::config::Config;
+use crate::engagement::Engagement;
+use crate::export::{self, ExportFormat};
+use crate::problem::{Lang, Problem};
+use crate::takedown;
+use crate::{AppState, NewsItem, NewsResponse};
+
+// Upper bound on articles returned by one search
+const MAX_SEARCH_LIMIT: usize = 200;
+const DEFAULT_SEARCH_LIMIT: usize = 50;
+// How far either side of a requested instant to look for the nearest scrape
+const NEAREST_WITHIN: chrono::Duration = chrono::Duration::days(31);
+// How far back an article's history goes without ?from=, and the longest
+// span one request reads
+const HISTORY_DAYS: i64 = 31;
+const MAX_HISTORY_DAYS: i64 = 366;
+
+// One homepage scrape, as stored in the archive
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ArchivedScrape {
+    pub scraped_at: DateTime<Utc>,
+    #[serde(with = "stored_items")]
+    pub news: Vec<NewsItem>,
+}
+
+// The items of an archived scrape keep their engagement, which the v1 shape
+// of NewsItem leaves out
+mod stored_items {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::engagement::Engagement;
+    use crate::NewsItem;
+
+    #[derive(Serialize)]
+    struct StoredItemRef<'a> {
+        #[serde(flatten)]
+        item: &'a NewsItem,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        engagement: Option<Engagement>,
+    }
+
+    #[derive(Deserialize)]
+    struct StoredItem {
+        #[serde(flatten)]
+        item: NewsItem,
+        #[serde(default)]
+        engagement: Option<Engagement>,
+    }
+
+    pub fn serialize<S: Serializer>(news: &[NewsItem], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(news.iter().map(|item| StoredItemRef {
+            item,
+            engagement: item.engagement,
+        }))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<NewsItem>, D::Error> {
+        let stored = Vec::<StoredItem>::deserialize(deserializer)?;
+        Ok(stored
+            .into_iter()
+            .map(|stored| NewsItem {
+                engagement: stored.engagement,
+                ..stored.item
+            })
+            .collect())
+    }
+}
+
+// An article with the span of time it was listed on the homepage
+#[derive(Serialize, Clone, Debug)]
+pub struct ArchivedArticle {
+    pub link: String,
+    pub title: String,
+    pub description: String,
+    pub image_url: Option<String>,
+    pub first_seen_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+}
+
+// Where an article was on the homepage at a given scrape, 1 being the top
+#[derive(Serialize, Clone, Debug)]
+pub struct Appearance {
+    pub scraped_at: DateTime<Utc>,
+    pub position: usize,
+}
+
+// How many of the newest day files the file archive looks through for an
+// article's counters, which only move while it is on the homepage
+const ENGAGEMENT_DAYS: usize = 31;
+
+// The comment and share counters of an article at a given scrape
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct EngagementPoint {
+    pub scraped_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub engagement: Engagement,
+}
+
+#[derive(Default)]
+pub struct SearchQuery {
+    // Words that must all appear in the title or summary
+    pub text: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub limit: usize,
+}
+
+// Storage backend for the archive of homepage scrapes
+#[async_trait]
+pub trait Storage: Send + Sync {
+    // Creates or upgrades whatever the backend needs before first use
+    async fn prepare(&self) -> Result<(), String>;
+
+    async fn record(&self, scrape: &ArchivedScrape) -> Result<(), String>;
+
+    // Articles listed at some point between `from` and `to`, most recently
+    // seen first
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<ArchivedArticle>, String>;
+
+    // Every scrape between `from` and `to` the article appeared in, oldest
+    // first
+    async fn history(
+        &self,
+        link: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Appearance>, String>;
+
+    // The article's counters at every scrape that showed them, oldest first
+    async fn engagement(&self, link: &str) -> Result<Vec<EngagementPoint>, String>;
+
+    // Scrapes taken from `from` up to but excluding `to`, oldest first
+    async fn scrapes(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<ArchivedScrape>, String>;
+
+    // The scrape closest to `at`, before or after it, at most `within` away
+    async fn nearest(
+        &self,
+        at: DateTime<Utc>,
+        within: chrono::Duration,
+    ) -> Result<Option<ArchivedScrape>, String>;
+
+    // Replaces every appearance of the article with a tombstone (see
+    // takedown.rs), returning how many scrapes listed it. Tombstones are
+    // left out of everything read back, the positions closing up around them
+    async fn redact(&self, link: &str) -> Result<usize, String>;
+}
+
+// Helper function to open the backend ARCHIVE_URL points at, if any, and
+// prepare it for use
+pub async fn connect(config: &Config) -> Result<Option<Arc<dyn Storage>>, String> {
+    let storage = open(config).await?;
+    if let Some(storage) = &storage {
+        storage.prepare().await?;
+    }
+    Ok(storage)
+}
+
+// Like connect, without preparing the backend: nothing is created or
+// migrated. file:///path for JSON lines files or, with the postgres
+// feature, postgres://
+pub async fn open(config: &Config) -> Result<Option<Arc<dyn Storage>>, String> {
+    let Some(url) = &config.archive_url else {
+        return Ok(None);
+    };
+
+    let storage: Arc<dyn Storage> = if let Some(dir) = url.strip_prefix("file://") {
+        Arc::new(FileStorage::new(PathBuf::from(dir)))
+    } else if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        #[cfg(feature = "postgres")]
+        {
+            Arc::new(crate::postgres::PostgresStorage::connect(url).await?)
+        }
+        #[cfg(not(feature = "postgres"))]
+        return Err(
+            "ARCHIVE_URL points at PostgreSQL but the postgres feature is not enabled".to_string(),
+        );
+    } else {
+        return Err(format!(
+            "Invalid ARCHIVE_URL '{}': expected file:// or postgres://",
+            url
+        ));
+    };
+    Ok(Some(storage))
+}
+
+// The archive as one JSON lines file per UTC day, each line a scrape. Fine
+// for a single instance; searches read every file in the requested range
+pub struct FileStorage {
+    dir: PathBuf,
+    // Held while a day file is appended to or rewritten
+    lock: Arc<Mutex<()>>,
+}
+
+impl FileStorage {
+    pub fn new(dir: PathBuf) -> FileStorage {
+        FileStorage {
+            dir,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub fn day_path(&self, day: NaiveDate) -> PathBuf {
+        day_path(&self.dir, day)
+    }
+
+    // Helper function to run a read of the day files on the blocking pool,
+    // as it reads and parses whole files
+    async fn read<T, F>(&self, read: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Path) -> Result<T, String> + Send + 'static,
+    {
+        let dir = self.dir.clone();
+        tokio::task::spawn_blocking(move || read(&dir))
+            .await
+            .map_err(|e| format!("Archive task failed: {}", e))?
+    }
+}
+
+fn day_path(dir: &Path, day: NaiveDate) -> PathBuf {
+    dir.join(format!("{}.jsonl", day.format("%Y-%m-%d")))
+}
+
+// Helper function to list the days the archive has a file for, unordered
+fn days(dir: &Path) -> Result<Vec<NaiveDate>, String> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(format!("Failed to read {}: {}", dir.display(), e)),
+    };
+    Ok(entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let day = name.strip_suffix(".jsonl")?;
+            NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()
+        })
+        .collect())
+}
+
+// Helper function to read the scrapes of the days overlapping the range, in
+// chronological order
+fn read_scrapes(
+    dir: &Path,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<Vec<ArchivedScrape>, String> {
+    let mut days: Vec<NaiveDate> = days(dir)?
+        .into_iter()
+        .filter(|day| from.is_none_or(|from| *day >= from.date_naive()))
+        .filter(|day| to.is_none_or(|to| *day <= to.date_naive()))
+        .collect();
+    days.sort();
+
+    let mut scrapes = Vec::new();
+    for day in days {
+        scrapes.extend(read_day(&day_path(dir, day))?);
+    }
+    scrapes.retain(|scrape| {
+        from.is_none_or(|from| scrape.scraped_at >= from)
+            && to.is_none_or(|to| scrape.scraped_at <= to)
+    });
+    for scrape in &mut scrapes {
+        scrape
+            .news
+            .retain(|item| !takedown::is_tombstone(&item.link));
+    }
+    Ok(scrapes)
+}
+
+// Helper function to find the scrape closest to `at` within `within`,
+// reading the day files outwards from the day of `at` and stopping once the
+// next day can't hold anything closer
+fn read_nearest(
+    dir: &Path,
+    at: DateTime<Utc>,
+    within: chrono::Duration,
+) -> Result<Option<ArchivedScrape>, String> {
+    let (from, to) = (at - within, at + within);
+    // Each day with how close to `at` its scrapes can be at best
+    let mut days: Vec<(chrono::Duration, NaiveDate)> = days(dir)?
+        .into_iter()
+        .filter(|day| (from.date_naive()..=to.date_naive()).contains(day))
+        .map(|day| {
+            let start = day.and_time(chrono::NaiveTime::MIN).and_utc();
+            let end = start + chrono::Duration::days(1);
+            let gap = if at < start {
+                start - at
+            } else if at >= end {
+                at - end
+            } else {
+                chrono::Duration::zero()
+            };
+            (gap, day)
+        })
+        .collect();
+    days.sort();
+
+    let mut closest: Option<(chrono::Duration, ArchivedScrape)> = None;
+    for (gap, day) in days {
+        if closest
+            .as_ref()
+            .is_some_and(|(distance, _)| *distance <= gap)
+        {
+            break;
+        }
+        for mut scrape in read_day(&day_path(dir, day))? {
+            let distance = (scrape.scraped_at - at).abs();
+            if distance > within || closest.as_ref().is_some_and(|(best, _)| *best <= distance) {
+                continue;
+            }
+            scrape
+                .news
+                .retain(|item| !takedown::is_tombstone(&item.link));
+            closest = Some((distance, scrape));
+        }
+    }
+    Ok(closest.map(|(_, scrape)| scrape))
+}
+
+// Helper function to rewrite a day file with the article's appearances
+// replaced by tombstones. Returns how many scrapes listed it
+fn redact_day(path: &Path, link: &str) -> Result<usize, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let mut redacted = 0;
+    let mut lines = Vec::new();
+    for line in content.lines() {
+        let Ok(mut scrape) = serde_json::from_str::<ArchivedScrape>(line) else {
+            // Cut short by a crash: dropped, as reads skip it anyway
+            continue;
+        };
+        let mut listed = false;
+        for item in scrape.news.iter_mut().filter(|item| item.link == link) {
+            *item = takedown::tombstone(link);
+            listed = true;
+        }
+        if listed {
+            redacted += 1;
+            lines.push(
+                serde_json::to_string(&scrape)
+                    .map_err(|e| format!("Failed to serialize scrape: {}", e))?,
+            );
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+    if redacted == 0 {
+        return Ok(0);
+    }
+
+    // Written under a temporary name so a crash never leaves half a day
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, lines.join("\n") + "\n")
+        .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+    std::fs::rename(&partial, path)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(redacted)
+}
+
+// Helper function to read one day file. A line cut short by a crash is skipped
+pub fn read_day(path: &Path) -> Result<Vec<ArchivedScrape>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    Ok(content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+#[async_trait]
+impl Storage for FileStorage {
+    async fn prepare(&self) -> Result<(), String> {
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))
+    }
+
+    async fn record(&self, scrape: &ArchivedScrape) -> Result<(), String> {
+        let path = self.day_path(scrape.scraped_at.date_naive());
+        // The homepage can list the same article twice; keep its top spot,
+        // as the Postgres backend does
+        let mut seen = HashSet::new();
+        let scrape = ArchivedScrape {
+            scraped_at: scrape.scraped_at,
+            news: scrape
+                .news
+                .iter()
+                .filter(|item| seen.insert(item.link.as_str()))
+                .cloned()
+                .collect(),
+        };
+        let mut line = serde_json::to_string(&scrape)
+            .map_err(|e| format!("Failed to serialize scrape: {}", e))?;
+        line.push('\n');
+
+        let lock = self.lock.clone();
+        tokio::task::spawn_blocking(move || {
+            let _lock = lock.lock().unwrap();
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+            file.write_all(line.as_bytes())
+                .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+        })
+        .await
+        .map_err(|e| format!("Archive task failed: {}", e))?
+    }
+
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<ArchivedArticle>, String> {
+        let words: Vec<String> = query
+            .text
+            .as_deref()
+            .unwrap_or("")
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect();
+        let (from, to, limit) = (query.from, query.to, query.limit);
+
+        self.read(move |dir| {
+            let mut articles: HashMap<String, ArchivedArticle> = HashMap::new();
+            for scrape in read_scrapes(dir, from, to)? {
+                for item in scrape.news {
+                    let article =
+                        articles
+                            .entry(item.link.clone())
+                            .or_insert_with(|| ArchivedArticle {
+                                link: item.link.clone(),
+                                title: String::new(),
+                                description: String::new(),
+                                image_url: None,
+                                first_seen_at: scrape.scraped_at,
+                                last_seen_at: scrape.scraped_at,
+                            });
+                    // The latest title and summary win, as they can be edited
+                    article.title = item.title;
+                    article.description = item.description;
+                    article.image_url = item.image_url;
+                    article.last_seen_at = scrape.scraped_at;
+                }
+            }
+
+            let mut matches: Vec<ArchivedArticle> = articles
+                .into_values()
+                .filter(|article| {
+                    let text = format!("{} {}", article.title, article.description).to_lowercase();
+                    words.iter().all(|word| text.contains(word.as_str()))
+                })
+                .collect();
+            matches.sort_by_key(|article| std::cmp::Reverse(article.last_seen_at));
+            matches.truncate(limit);
+            Ok(matches)
+        })
+        .await
+    }
+
+    async fn history(
+        &self,
+        link: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Appearance>, String> {
+        let link = link.to_string();
+        self.read(move |dir| {
+            Ok(read_scrapes(dir, from, to)?
+                .into_iter()
+                .filter_map(|scrape| {
+                    let index = scrape.news.iter().position(|item| item.link == link)?;
+                    Some(Appearance {
+                        scraped_at: scrape.scraped_at,
+                        position: index + 1,
+                    })
+                })
+                .collect())
+        })
+        .await
+    }
+
+    // Only the newest ENGAGEMENT_DAYS day files are read, newest first, and
+    // the search stops at the first day before those that listed the article
+    async fn engagement(&self, link: &str) -> Result<Vec<EngagementPoint>, String> {
+        let link = link.to_string();
+        self.read(move |dir| {
+            let mut days = days(dir)?;
+            days.sort();
+            let mut series: Vec<Vec<EngagementPoint>> = Vec::new();
+            for day in days.into_iter().rev().take(ENGAGEMENT_DAYS) {
+                let mut listed = false;
+                let mut points = Vec::new();
+                for scrape in read_day(&day_path(dir, day))? {
+                    let Some(item) = scrape.news.iter().find(|item| item.link == link) else {
+                        continue;
+                    };
+                    listed = true;
+                    if let Some(engagement) = item.engagement {
+                        points.push(EngagementPoint {
+                            scraped_at: scrape.scraped_at,
+                            engagement,
+                        });
+                    }
+                }
+                if !listed && !series.is_empty() {
+                    break;
+                }
+                if listed {
+                    series.push(points);
+                }
+            }
+            Ok(series.into_iter().rev().flatten().collect())
+        })
+        .await
+    }
+
+    async fn scrapes(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<ArchivedScrape>, String> {
+        self.read(move |dir| {
+            let mut scrapes = read_scrapes(dir, Some(from), Some(to))?;
+            scrapes.retain(|scrape| scrape.scraped_at < to);
+            Ok(scrapes)
+        })
+        .await
+    }
+
+    async fn nearest(
+        &self,
+        at: DateTime<Utc>,
+        within: chrono::Duration,
+    ) -> Result<Option<ArchivedScrape>, String> {
+        self.read(move |dir| read_nearest(dir, at, within)).await
+    }
+
+    async fn redact(&self, link: &str) -> Result<usize, String> {
+        let lock = self.lock.clone();
+        let link = link.to_string();
+        self.read(move |dir| {
+            let _lock = lock.lock().unwrap();
+            days(dir)?
+                .into_iter()
+                .map(|day| redact_day(&day_path(dir, day), &link))
+                .sum()
+        })
+        .await
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SearchParams {
+    q: Option<String>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct SearchResponse {
+    pub articles: Vec<ArchivedArticle>,
+    pub error: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct HistoryParams {
+    link: String,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryResponse {
+    pub link: String,
+    pub appearances: Vec<Appearance>,
+    pub error: Option<String>,
+}
+
+fn search_error(status: StatusCode, error_message: String) -> (StatusCode, Json<SearchResponse>) {
+    (
+        status,
+        Json(SearchResponse {
+            articles: vec![],
+            error: Some(error_message),
+        }),
+    )
+}
+
+// Helper function to find the archived scrape closest to `at`, before or
+// after it, within a month either way
+pub async fn nearest(
+    archive: &dyn Storage,
+    at: DateTime<Utc>,
+) -> Result<Option<ArchivedScrape>, String> {
+    archive.nearest(at, NEAREST_WITHIN).await
+}
+
+// Helper function to resolve the ?from= and ?to= of an article's history:
+// up to now and HISTORY_DAYS back unless given, spanning MAX_HISTORY_DAYS
+// at most
+pub fn history_range(
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), String> {
+    let to = to.unwrap_or_else(Utc::now);
+    let from = from.unwrap_or(to - chrono::Duration::days(HISTORY_DAYS));
+    if from > to {
+        return Err("from must not be after to".to_string());
+    }
+    if to - from > chrono::Duration::days(MAX_HISTORY_DAYS) {
+        return Err(format!(
+            "The range from {} to {} is longer than {} days",
+            from.to_rfc3339(),
+            to.to_rfc3339(),
+            MAX_HISTORY_DAYS
+        ));
+    }
+    Ok((from, to))
+}
+
+// Helper function to parse the ?at= of /api/news, an RFC 3339 instant
+pub fn parse_instant(value: &str) -> Result<DateTime<Utc>, String> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .map(|at| at.with_timezone(&Utc))
+        .map_err(|e| {
+            format!(
+                "Invalid instant '{}', expected e.g. 2024-05-01T08:00:00Z: {}",
+                value, e
+            )
+        })
+}
+
+// Helper function to serve the homepage as archived nearest to `at`, in
+// place of the live scrape. `scraped_at` is when the snapshot was taken
+pub async fn snapshot_news(state: &AppState, at: DateTime<Utc>) -> Result<NewsResponse, Problem> {
+    let archive = state
+        .archive
+        .as_ref()
+        .ok_or_else(|| Problem::not_found(disabled_message()))?;
+    let scrape = nearest(archive.as_ref(), at)
+        .await
+        .map_err(Problem::upstream_unavailable)?
+        .ok_or_else(|| {
+            Problem::not_found(format!("No archived snapshot near {}", at.to_rfc3339()))
+        })?;
+    Ok(NewsResponse {
+        scraped_at: scrape.scraped_at,
+        news: scrape.news,
+        error: None,
+        stale: false,
+        provenance: None,
+        demo: false,
+        snapshot: true,
+    })
+}
+
+fn disabled_message() -> String {
+    "The archive is not enabled".to_string()
+}
+
+pub async fn search_handler(
+    State(state): State<AppState>,
+    Query(params): Query<SearchParams>,
+) -> (StatusCode, Json<SearchResponse>) {
+    let Some(archive) = &state.archive else {
+        return search_error(StatusCode::NOT_FOUND, disabled_message());
+    };
+
+    let query = SearchQuery {
+        text: params.q.filter(|text| !text.trim().is_empty()),
+        from: params.from,
+        to: params.to,
+        limit: params
+            .limit
+            .unwrap_or(DEFAULT_SEARCH_LIMIT)
+            .min(MAX_SEARCH_LIMIT),
+    };
+    match archive.search(&query).await {
+        Ok(articles) => (
+            StatusCode::OK,
+            Json(SearchResponse {
+                articles,
+                error: None,
+            }),
+        ),
+        Err(error_message) => search_error(StatusCode::INTERNAL_SERVER_ERROR, error_message),
+    }
+}
+
+pub async fn history_handler(
+    State(state): State<AppState>,
+    Query(params): Query<HistoryParams>,
+) -> (StatusCode, Json<HistoryResponse>) {
+    let result = match (&state.archive, history_range(params.from, params.to)) {
+        (None, _) => Err((StatusCode::NOT_FOUND, disabled_message())),
+        (Some(_), Err(error_message)) => Err((StatusCode::BAD_REQUEST, error_message)),
+        (Some(archive), Ok((from, to))) => archive
+            .history(&params.link, Some(from), Some(to))
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e)),
+    };
+
+    let (status, appearances, error) = match result {
+        Ok(appearances) => (StatusCode::OK, appearances, None),
+        Err((status, error_message)) => (status, vec![], Some(error_message)),
+    };
+    (
+        status,
+        Json(HistoryResponse {
+            link: params.link,
+            appearances,
+            error,
+        }),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct EngagementParams {
+    url: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct EngagementResponse {
+    pub url: String,
+    pub points: Vec<EngagementPoint>,
+}
+
+// How an article's comment and share counters moved, one point per archived
+// scrape that showed them
+pub async fn engagement_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<EngagementParams>,
+) -> Response {
+    let lang = Lang::from_headers(&headers);
+    let Some(archive) = &state.archive else {
+        return Problem::not_found(disabled_message()).respond(lang);
+    };
+    let Some(url) = params.url else {
+        return Problem::invalid_parameter("url", "missing article URL".to_string()).respond(lang);
+    };
+    match archive.engagement(&url).await {
+        Ok(points) => Json(EngagementResponse { url, points }).into_response(),
+        Err(error_message) => Problem::upstream_unavailable(error_message).respond(lang),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ExportParams {
+    date: NaiveDate,
+    format: Option<String>,
+}
+
+// One UTC day of the archive as a file, one row per item per scrape
+pub async fn export_handler(
+    State(state): State<AppState>,
+    Query(params): Query<ExportParams>,
+) -> Response {
+    let error = |status: StatusCode, error_message: String| {
+        (status, Json(serde_json::json!({ "error": error_message }))).into_response()
+    };
+
+    let Some(archive) = &state.archive else {
+        return error(StatusCode::NOT_FOUND, disabled_message());
+    };
+    let format: ExportFormat = match params.format.as_deref().unwrap_or("ndjson").parse() {
+        Ok(format) => format,
+        Err(error_message) => {
+            return error(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid format: {}", error_message),
+            )
+        }
+    };
+
+    let (from, to) = export::day_range(params.date);
+    let body = match archive.scrapes(from, to).await {
+        Ok(scrapes) => {
+            let rows = export::rows(&scrapes);
+            export::run_encode(format, rows).await
+        }
+        Err(error_message) => Err(error_message),
+    };
+    let body = match body {
+        Ok(body) => body,
+        Err(error_message) => return error(StatusCode::INTERNAL_SERVER_ERROR, error_message),
+    };
+
+    // Gzipped NDJSON is sent as such, so clients decode it transparently
+    let file_name = format!(
+        "corriere-{}.{}",
+        params.date,
+        format.extension().trim_end_matches(".gz")
+    );
+    let mut response = (
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name),
+            ),
+        ],
+        body,
+    )
+        .into_response();
+    if format.is_gzipped() {
+        response.headers_mut().insert(
+            header::CONTENT_ENCODING,
+            header::HeaderValue::from_static("gzip"),
+        );
+    }
+    response
+}
@@ -0,0 +1,342 @@
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::task::JoinSet;
+
+use crate::dates::{DateFormat, DateParams};
+use crate::engagement::{self, Engagement};
+use crate::entities::Entity;
+#[cfg(feature = "parquet")]
+use crate::export::{self, ExportFormat};
+use crate::extract::{SelectorConfig, Selectors};
+use crate::url_safety::{self, UrlPolicy};
+use crate::{extract, fetch_queue, AppState};
+
+#[derive(Serialize)]
+pub struct ArticleDetail {
+    pub url: String,
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub author: Option<String>,
+    pub published_at: Option<DateTime<Utc>>,
+    pub image_url: Option<String>,
+    pub body: String,
+    // Notes the newsroom appends when an article is corrected
+    pub corrections: Vec<String>,
+    // Comment and share counters, when the page shows them
+    pub engagement: Option<Engagement>,
+}
+
+#[derive(Serialize)]
+pub struct ArticleResult {
+    pub url: String,
+    pub article: Option<ArticleDetail>,
+    // People, places and organizations named in the article
+    pub entities: Vec<Entity>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BatchResponse {
+    pub fetched_at: DateTime<Utc>,
+    pub articles: Vec<ArticleResult>,
+    pub error: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct BatchRequest {
+    urls: Vec<String>,
+}
+
+// ?format= of a batch: json by default, or parquet with the articles that
+// could be fetched, in the schema of export::article_parquet_schema()
+#[derive(Deserialize)]
+pub struct BatchFormat {
+    format: Option<String>,
+}
+
+// Selectors for Corriere article pages, each tried in order. The Open Graph
+// and article meta tags are the fallback when the visible markup changes
+const TITLE_SELECTORS: &[&str] = &["h1.title-art", "h1", "meta[property='og:title']"];
+const SUBTITLE_SELECTORS: &[&str] = &[
+    "h2.subtitle-art",
+    "p.summary-art",
+    "meta[property='og:description']",
+];
+const AUTHOR_SELECTORS: &[&str] = &[".writer", "meta[name='author']"];
+const PUBLISHED_SELECTORS: &[&str] = &["meta[property='article:published_time']", "time[datetime]"];
+const IMAGE_SELECTORS: &[&str] = &["meta[property='og:image']"];
+const BODY_SELECTORS: &[&str] = &["p.chapter-paragraph", ".chapter p", "article p"];
+const CORRECTION_SELECTORS: &[&str] = &[".nota-redazione", ".rettifica", ".correction"];
+
+// Helper function to extract the details of a single article page
+pub fn extract_article(html: &str, url: &str) -> Result<ArticleDetail, String> {
+    let document = Html::parse_document(html);
+
+    let title =
+        first_text(&document, TITLE_SELECTORS).ok_or("No article title found on the page")?;
+    let published_at = first_text(&document, PUBLISHED_SELECTORS).and_then(|value| {
+        DateTime::parse_from_rfc3339(&value)
+            .ok()
+            .map(|date| date.with_timezone(&Utc))
+    });
+
+    let mut body = Vec::new();
+    for selector in BODY_SELECTORS {
+        let selector = parse_selector(selector)?;
+        body = document
+            .select(&selector)
+            .map(element_text)
+            .filter(|paragraph| !paragraph.is_empty())
+            .collect();
+        if !body.is_empty() {
+            break;
+        }
+    }
+
+    let mut corrections = Vec::new();
+    for selector in CORRECTION_SELECTORS {
+        let selector = parse_selector(selector)?;
+        corrections.extend(
+            document
+                .select(&selector)
+                .map(element_text)
+                .filter(|note| !note.is_empty()),
+        );
+    }
+
+    Ok(ArticleDetail {
+        url: url.to_string(),
+        title,
+        subtitle: first_text(&document, SUBTITLE_SELECTORS),
+        author: first_text(&document, AUTHOR_SELECTORS),
+        published_at,
+        image_url: first_text(&document, IMAGE_SELECTORS)
+            .map(|image_url| extract::resolve_url(Url::parse(url).ok().as_ref(), &image_url)),
+        body: body.join("\n\n"),
+        corrections,
+        engagement: Selectors::parse(&SelectorConfig::default())
+            .ok()
+            .and_then(|selectors| engagement::engagement(document.root_element(), &selectors)),
+    })
+}
+
+// Text of the first element matched by any of the selectors; meta tags
+// contribute their content attribute
+fn first_text(document: &Html, selectors: &[&str]) -> Option<String> {
+    selectors.iter().find_map(|selector| {
+        let selector = parse_selector(selector).ok()?;
+        document.select(&selector).find_map(|element| {
+            let text = match element.value().name() {
+                "meta" => element
+                    .value()
+                    .attr("content")
+                    .unwrap_or("")
+                    .trim()
+                    .to_string(),
+                "time" => element
+                    .value()
+                    .attr("datetime")
+                    .unwrap_or("")
+                    .trim()
+                    .to_string(),
+                _ => element_text(element),
+            };
+            (!text.is_empty()).then_some(text)
+        })
+    })
+}
+
+fn element_text(element: ElementRef) -> String {
+    element
+        .text()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn parse_selector(selector: &str) -> Result<Selector, String> {
+    Selector::parse(selector).map_err(|e| format!("Failed to parse selector {}: {}", selector, e))
+}
+
+// Helper function to fetch and extract one article, waiting for the host's
+// politeness limit first
+pub async fn fetch_article(state: &AppState, url: &str) -> Result<ArticleDetail, String> {
+    let parsed = url_safety::check(&UrlPolicy::from_config(&state.config), url)?;
+
+    let html = fetch_queue::fetch_html(state, &state.scrape_client, parsed.as_str()).await?;
+
+    extract::run_blocking(move || extract_article(&html, parsed.as_str())).await?
+}
+
+pub async fn batch_handler(
+    State(state): State<AppState>,
+    Query(params): Query<DateParams>,
+    Query(output): Query<BatchFormat>,
+    Json(request): Json<BatchRequest>,
+) -> Response {
+    let dates = match DateFormat::parse(params.tz.as_deref(), params.locale.as_deref()) {
+        Ok(dates) => dates,
+        Err(error) => return batch_error(error.message),
+    };
+    let parquet = match output
+        .format
+        .as_deref()
+        .map(str::to_ascii_lowercase)
+        .as_deref()
+    {
+        None | Some("json") => false,
+        Some("parquet") if cfg!(feature = "parquet") => true,
+        Some("parquet") => {
+            return batch_error("parquet support is not enabled in this build".to_string())
+        }
+        Some(other) => {
+            return batch_error(format!(
+                "Invalid format '{}', expected json or parquet",
+                other
+            ))
+        }
+    };
+
+    let max_urls = state.config.batch_max_urls;
+    if request.urls.len() > max_urls {
+        return batch_error(format!(
+            "At most {} URLs can be fetched per batch",
+            max_urls
+        ));
+    }
+
+    let mut tasks = JoinSet::new();
+    let priority = fetch_queue::current();
+    for (index, url) in request.urls.iter().cloned().enumerate() {
+        let state = state.clone();
+        tasks.spawn(fetch_queue::scope(priority, async move {
+            let result = fetch_article(&state, &url).await;
+            (index, url, result)
+        }));
+    }
+
+    // Results complete in any order; put them back in request order
+    let mut results: Vec<Option<ArticleResult>> = request.urls.iter().map(|_| None).collect();
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok((index, url, result)) = joined {
+            results[index] = Some(match result {
+                Ok(article) => ArticleResult {
+                    url,
+                    entities: state.config.gazetteer.extract(&format!(
+                        "{}\n{}\n{}",
+                        article.title,
+                        article.subtitle.as_deref().unwrap_or_default(),
+                        article.body
+                    )),
+                    article: Some(article),
+                    error: None,
+                },
+                Err(error_message) => ArticleResult {
+                    url,
+                    article: None,
+                    entities: vec![],
+                    error: Some(error_message),
+                },
+            });
+        }
+    }
+
+    let articles = results
+        .into_iter()
+        .zip(request.urls)
+        .map(|(result, url)| {
+            result.unwrap_or(ArticleResult {
+                url,
+                article: None,
+                entities: vec![],
+                error: Some("Article fetch task failed".to_string()),
+            })
+        })
+        .collect();
+
+    if parquet {
+        return batch_parquet(articles).await;
+    }
+
+    let response = BatchResponse {
+        fetched_at: Utc::now(),
+        articles,
+        error: None,
+    };
+    (StatusCode::OK, Json(localize_batch(&response, &dates))).into_response()
+}
+
+// Helper function to answer a batch with the fetched articles as Parquet,
+// encoded off the async runtime
+#[cfg(feature = "parquet")]
+async fn batch_parquet(results: Vec<ArticleResult>) -> Response {
+    use axum::http::header;
+
+    let articles: Vec<ArticleDetail> = results
+        .into_iter()
+        .filter_map(|result| result.article)
+        .collect();
+    match extract::run_blocking(move || export::articles_parquet(&articles)).await {
+        Ok(Ok(body)) => (
+            [
+                (
+                    header::CONTENT_TYPE,
+                    ExportFormat::Parquet.content_type().to_string(),
+                ),
+                (
+                    header::CONTENT_DISPOSITION,
+                    "attachment; filename=\"articles.parquet\"".to_string(),
+                ),
+            ],
+            body,
+        )
+            .into_response(),
+        Ok(Err(error_message)) | Err(error_message) => {
+            (StatusCode::INTERNAL_SERVER_ERROR, error_message).into_response()
+        }
+    }
+}
+
+#[cfg(not(feature = "parquet"))]
+async fn batch_parquet(_results: Vec<ArticleResult>) -> Response {
+    batch_error("parquet support is not enabled in this build".to_string())
+}
+
+fn batch_error(error_message: String) -> Response {
+    let response = BatchResponse {
+        fetched_at: Utc::now(),
+        articles: vec![],
+        error: Some(error_message),
+    };
+    (
+        StatusCode::BAD_REQUEST,
+        Json(localize_batch(&response, &DateFormat::default())),
+    )
+        .into_response()
+}
+
+// Helper function to serialize a batch response with its timestamps
+// presented in the requested time zone and locale
+fn localize_batch(response: &BatchResponse, dates: &DateFormat) -> Value {
+    let mut value = serde_json::to_value(response).unwrap_or(Value::Null);
+    if let Value::Object(object) = &mut value {
+        dates.apply(object, "fetched_at");
+        if let Some(Value::Array(articles)) = object.get_mut("articles") {
+            for article in articles {
+                if let Some(Value::Object(article)) = article.get_mut("article") {
+                    dates.apply(article, "published_at");
+                }
+            }
+        }
+    }
+    value
+}
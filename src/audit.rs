@@ -0,0 +1,300 @@
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::future::Future;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::admin::{admin_error, unauthorized};
+use crate::{request_id, AppState};
+
+// Events returned by /api/admin/audit without a limit, and at most
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+// Bytes of the log read at a time, from the end back
+const READ_BLOCK: u64 = 64 * 1024;
+
+tokio::task_local! {
+    static TRIGGER: &'static str;
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditKind {
+    Scrape,
+    Admin,
+}
+
+// One line of DATA_DIR/audit.jsonl: a scrape run, or something done through
+// the admin API or a signal
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AuditEvent {
+    pub at: DateTime<Utc>,
+    pub kind: AuditKind,
+    // What was scraped ("homepage" or a section job) or done ("reload",
+    // "run_job", "takedown", "flush_cache", ...)
+    pub action: String,
+    // Scrapes: what started it, "request", "scheduler" or "admin"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger: Option<String>,
+    // Admin actions: the name of the token used, from ADMIN_TOKENS, "admin"
+    // for ADMIN_TOKEN or "signal" for SIGHUP
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>,
+    // The URL scraped, or the job, link hash or delivery acted on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub items: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn scrape(
+        source: &str,
+        url: &str,
+        started: Instant,
+        result: &Result<usize, String>,
+    ) -> AuditEvent {
+        AuditEvent {
+            at: Utc::now(),
+            kind: AuditKind::Scrape,
+            action: source.to_string(),
+            trigger: Some(trigger().to_string()),
+            actor: None,
+            target: Some(url.to_string()),
+            ok: result.is_ok(),
+            error: result.as_ref().err().cloned(),
+            duration_ms: Some(started.elapsed().as_millis() as u64),
+            items: result.as_ref().ok().copied(),
+            request_id: request_id::current(),
+        }
+    }
+
+    pub fn admin<T>(
+        action: &str,
+        actor: Option<String>,
+        target: Option<&str>,
+        result: &Result<T, String>,
+    ) -> AuditEvent {
+        AuditEvent {
+            at: Utc::now(),
+            kind: AuditKind::Admin,
+            action: action.to_string(),
+            trigger: None,
+            actor,
+            target: target.map(str::to_string),
+            ok: result.is_ok(),
+            error: result.as_ref().err().cloned(),
+            duration_ms: None,
+            items: None,
+            request_id: request_id::current(),
+        }
+    }
+}
+
+// Append-only log of scrapes and admin actions, one JSON object per line.
+// Lines are only ever added; rotating the file is left to the operator
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    // None when AUDIT_LOG is off: nothing is recorded
+    pub fn new(path: Option<PathBuf>) -> AuditLog {
+        AuditLog {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn append(&self, event: &AuditEvent) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let line = serde_json::to_string(event)
+            .map_err(|e| format!("Failed to serialize audit event: {}", e))?;
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        writeln!(file, "{}", line).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    // Up to `limit` of the events `wanted` keeps, newest first. The file is
+    // read from the end, and only as far back as needed. Lines that don't
+    // parse, e.g. one cut short by a crash, are skipped
+    pub fn newest(
+        &self,
+        limit: usize,
+        wanted: impl Fn(&AuditEvent) -> bool,
+    ) -> Result<Vec<AuditEvent>, String> {
+        let Some(path) = &self.path else {
+            return Ok(vec![]);
+        };
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        let mut events = Vec::new();
+        if limit == 0 {
+            return Ok(events);
+        }
+        lines_reversed(&mut file, |line| {
+            if let Ok(event) = serde_json::from_slice::<AuditEvent>(line) {
+                if wanted(&event) {
+                    events.push(event);
+                }
+            }
+            events.len() < limit
+        })
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        Ok(events)
+    }
+}
+
+// Helper function to pass the lines of `file` to `visit` last first, until
+// it returns false
+fn lines_reversed(
+    file: &mut std::fs::File,
+    mut visit: impl FnMut(&[u8]) -> bool,
+) -> std::io::Result<()> {
+    let mut end = file.metadata()?.len();
+    // The start of the file's earliest line seen so far, whose beginning is
+    // in a block not read yet
+    let mut rest = Vec::new();
+    while end > 0 {
+        let start = end.saturating_sub(READ_BLOCK);
+        let mut block = vec![0; (end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut block)?;
+        block.extend_from_slice(&rest);
+        end = start;
+
+        // Before the first newline is the end of a line begun further back,
+        // unless this block starts the file
+        let whole = if end == 0 {
+            0
+        } else {
+            match block.iter().position(|&byte| byte == b'\n') {
+                Some(newline) => newline + 1,
+                None => {
+                    rest = block;
+                    continue;
+                }
+            }
+        };
+        for line in block[whole..].rsplit(|&byte| byte == b'\n') {
+            if !line.is_empty() && !visit(line) {
+                return Ok(());
+            }
+        }
+        rest = block[..whole.saturating_sub(1)].to_vec();
+    }
+    Ok(())
+}
+
+// Helper function to record an event, off the async runtime. A failure to
+// write it is logged, not passed on: the scrape or action it describes has
+// already happened
+pub async fn record(state: &AppState, event: AuditEvent) {
+    if state.audit.path.is_none() {
+        return;
+    }
+    let audit = state.audit.clone();
+    let written = tokio::task::spawn_blocking(move || audit.append(&event))
+        .await
+        .unwrap_or_else(|e| Err(format!("Audit writer failed: {}", e)));
+    if let Err(error_message) = written {
+        eprintln!(
+            "{}Failed to record audit event: {}",
+            request_id::prefix(),
+            error_message
+        );
+    }
+}
+
+// What started the scrape running now: "request" unless run inside
+// `triggered`
+pub fn trigger() -> &'static str {
+    TRIGGER.try_with(|trigger| *trigger).unwrap_or("request")
+}
+
+// Runs a future with its scrapes recorded as started by `trigger`
+pub async fn triggered<F: Future>(trigger: &'static str, future: F) -> F::Output {
+    TRIGGER.scope(trigger, future).await
+}
+
+#[derive(Deserialize)]
+pub struct AuditParams {
+    // scrape or admin
+    pub kind: Option<AuditKind>,
+    pub action: Option<String>,
+    pub actor: Option<String>,
+    pub trigger: Option<String>,
+    pub ok: Option<bool>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl AuditParams {
+    fn matches(&self, event: &AuditEvent) -> bool {
+        fn same(wanted: &Option<String>, value: &Option<String>) -> bool {
+            wanted.is_none() || wanted.as_deref() == value.as_deref()
+        }
+        self.kind.is_none_or(|kind| kind == event.kind)
+            && self
+                .action
+                .as_ref()
+                .is_none_or(|action| *action == event.action)
+            && same(&self.actor, &event.actor)
+            && same(&self.trigger, &event.trigger)
+            && self.ok.is_none_or(|ok| ok == event.ok)
+            && self.since.is_none_or(|since| event.at >= since)
+            && self.until.is_none_or(|until| event.at < until)
+    }
+}
+
+// The audit log, newest first, filtered by kind, action, actor, trigger,
+// outcome and a since/until window
+pub async fn list_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<AuditParams>,
+) -> Response {
+    if let Some(response) = unauthorized(&state, &headers) {
+        return response;
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let audit = state.audit.clone();
+    let events =
+        tokio::task::spawn_blocking(move || audit.newest(limit, |event| params.matches(event)))
+            .await
+            .unwrap_or_else(|e| Err(format!("Audit reader failed: {}", e)));
+    match events {
+        Ok(events) => Json(json!({ "events": events })).into_response(),
+        Err(error_message) => admin_error(StatusCode::INTERNAL_SERVER_ERROR, &error_message),
+    }
+}
@@ -0,0 +1,237 @@
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 8] = b"CSBLOOM1";
+const MAX_HASHES: u32 = 16;
+
+// Helper function for FNV-1a, which unlike std's hasher is guaranteed to
+// give the same values across Rust releases, as a filter stored on disk needs
+fn fnv1a(key: &str) -> u64 {
+    key.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+// Helper function to derive a second, independent hash (splitmix64)
+fn mix(mut value: u64) -> u64 {
+    value = value.wrapping_add(0x9e3779b97f4a7c15);
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d049bb133111eb);
+    value ^ (value >> 31)
+}
+
+// Helper function to reduce a link to 64 bits, stable across releases. Two
+// links only share one by chance, about once in 10^13 pairs
+pub fn fingerprint(key: &str) -> u64 {
+    mix(fnv1a(key))
+}
+
+// Set of strings answering "definitely not seen" or "probably seen", in a
+// fixed amount of memory. Sized for `capacity` entries at a false positive
+// rate of `fp_rate`; beyond that the rate climbs
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+    items: usize,
+}
+
+impl BloomFilter {
+    pub fn new(capacity: usize, fp_rate: f64) -> BloomFilter {
+        let capacity = capacity.max(1) as f64;
+        let fp_rate = fp_rate.clamp(1e-9, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let bit_count = (-capacity * fp_rate.ln() / (ln2 * ln2)).ceil().max(64.0);
+        let hashes = ((bit_count / capacity) * ln2).round() as u32;
+        BloomFilter {
+            bits: vec![0; (bit_count as usize).div_ceil(64)],
+            hashes: hashes.clamp(1, MAX_HASHES),
+            items: 0,
+        }
+    }
+
+    fn positions(&self, key: &str) -> impl Iterator<Item = usize> {
+        let bit_count = self.bits.len() as u64 * 64;
+        let first = fnv1a(key);
+        let step = mix(first) | 1;
+        (0..u64::from(self.hashes))
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(step)) % bit_count) as usize)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.positions(key)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    // Adds `key`, returning false when it (probably) was there already
+    pub fn insert(&mut self, key: &str) -> bool {
+        if self.contains(key) {
+            return false;
+        }
+        let positions: Vec<usize> = self.positions(key).collect();
+        for bit in positions {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+        self.items += 1;
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items == 0
+    }
+
+    // Memory taken by the bits
+    pub fn size_bytes(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend(self.hashes.to_le_bytes());
+        out.extend((self.items as u64).to_le_bytes());
+        out.extend((self.bits.len() as u64).to_le_bytes());
+        for word in &self.bits {
+            out.extend(word.to_le_bytes());
+        }
+    }
+
+    fn read_from(input: &mut &[u8]) -> Option<BloomFilter> {
+        fn take<const N: usize>(input: &mut &[u8]) -> Option<[u8; N]> {
+            let (head, rest) = input.split_first_chunk::<N>()?;
+            *input = rest;
+            Some(*head)
+        }
+
+        let hashes = u32::from_le_bytes(take(input)?);
+        let items = u64::from_le_bytes(take(input)?) as usize;
+        let words = u64::from_le_bytes(take(input)?) as usize;
+        if words == 0 || words > input.len() / 8 || !(1..=MAX_HASHES).contains(&hashes) {
+            return None;
+        }
+        let bits = (0..words)
+            .map(|_| take(input).map(u64::from_le_bytes))
+            .collect::<Option<Vec<u64>>>()?;
+        Some(BloomFilter {
+            bits,
+            hashes,
+            items,
+        })
+    }
+}
+
+// Every URL ever recorded by a detector, kept in two generations of bloom
+// filters: once the current one holds `capacity` URLs it becomes the previous
+// one and a fresh filter takes over, so the false positive rate stays bounded
+// and the oldest URLs are eventually forgotten. Written to disk at most once
+// per `checkpoint_every`
+pub struct SeenFilter {
+    path: PathBuf,
+    capacity: usize,
+    fp_rate: f64,
+    current: BloomFilter,
+    previous: Option<BloomFilter>,
+    dirty: bool,
+    checkpoint_every: Duration,
+    checkpointed_at: Option<Instant>,
+}
+
+impl SeenFilter {
+    // Helper function to load the filter stored at `path`, or start an empty
+    // one when there is none. A damaged file is reported and replaced
+    pub fn load(
+        path: PathBuf,
+        capacity: usize,
+        fp_rate: f64,
+        checkpoint_every: Duration,
+    ) -> Result<SeenFilter, String> {
+        let mut filter = SeenFilter {
+            current: BloomFilter::new(capacity, fp_rate),
+            previous: None,
+            path,
+            capacity: capacity.max(1),
+            fp_rate,
+            dirty: false,
+            checkpoint_every,
+            checkpointed_at: None,
+        };
+
+        let bytes = match std::fs::read(&filter.path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(filter),
+            Err(e) => return Err(format!("Failed to read {}: {}", filter.path.display(), e)),
+        };
+        let mut input = bytes.strip_prefix(MAGIC.as_slice()).unwrap_or_default();
+        match BloomFilter::read_from(&mut input) {
+            Some(current) => {
+                filter.current = current;
+                filter.previous = BloomFilter::read_from(&mut input);
+            }
+            None => eprintln!("Ignoring damaged seen-URL filter {}", filter.path.display()),
+        }
+        Ok(filter)
+    }
+
+    pub fn contains(&self, link: &str) -> bool {
+        self.current.contains(link)
+            || self
+                .previous
+                .as_ref()
+                .is_some_and(|previous| previous.contains(link))
+    }
+
+    // Adds `link` to the current generation. Links only found in the previous
+    // one are copied over, so what is still being seen isn't forgotten
+    pub fn insert(&mut self, link: &str) {
+        if self.current.contains(link) {
+            return;
+        }
+        if self.current.len() >= self.capacity {
+            let fresh = BloomFilter::new(self.capacity, self.fp_rate);
+            self.previous = Some(std::mem::replace(&mut self.current, fresh));
+        }
+        self.current.insert(link);
+        self.dirty = true;
+    }
+
+    // URLs held by the current and previous generations
+    pub fn len(&self) -> usize {
+        self.current.len() + self.previous.as_ref().map_or(0, BloomFilter::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Helper function to write the filter out if it changed and the last
+    // checkpoint is old enough, or right away with `force`. Returns whether
+    // it was written
+    pub fn checkpoint(&mut self, force: bool) -> Result<bool, String> {
+        let due = self
+            .checkpointed_at
+            .is_none_or(|at| at.elapsed() >= self.checkpoint_every);
+        if !self.dirty || !(force || due) {
+            return Ok(false);
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        self.current.write_to(&mut bytes);
+        if let Some(previous) = &self.previous {
+            previous.write_to(&mut bytes);
+        }
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let partial = self.path.with_extension("partial");
+        std::fs::write(&partial, bytes)
+            .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+        std::fs::rename(&partial, &self.path)
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))?;
+
+        self.dirty = false;
+        self.checkpointed_at = Some(Instant::now());
+        Ok(true)
+    }
+}
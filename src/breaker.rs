@@ -0,0 +1,164 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::metrics::Metrics;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl BreakerState {
+    // Value exported on the corriere_breaker_state gauge
+    fn gauge_value(self) -> f64 {
+        match self {
+            BreakerState::Closed => 0.0,
+            BreakerState::Open => 1.0,
+            BreakerState::HalfOpen => 2.0,
+        }
+    }
+}
+
+struct Inner {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+// Circuit breaker for upstream fetches. After `failure_threshold` failures in
+// a row it opens and rejects calls for `cooldown`; the first call after that
+// is let through as a probe, and closes the breaker again if it succeeds
+pub struct CircuitBreaker {
+    name: &'static str,
+    failure_threshold: u32,
+    cooldown: Duration,
+    inner: Mutex<Inner>,
+    metrics: Arc<Metrics>,
+}
+
+impl CircuitBreaker {
+    pub fn new(
+        name: &'static str,
+        failure_threshold: u32,
+        cooldown: Duration,
+        metrics: Arc<Metrics>,
+    ) -> CircuitBreaker {
+        metrics.set_gauge(
+            "corriere_breaker_state",
+            &[("breaker", name)],
+            BreakerState::Closed.gauge_value(),
+        );
+        CircuitBreaker {
+            name,
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            inner: Mutex::new(Inner {
+                state: BreakerState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+            }),
+            metrics,
+        }
+    }
+
+    pub fn state(&self) -> BreakerState {
+        self.inner.lock().unwrap().state
+    }
+
+    // Helper function to run `call` through the breaker
+    pub async fn call<T, F>(&self, call: F) -> Result<T, String>
+    where
+        F: Future<Output = Result<T, String>>,
+    {
+        self.before_call()?;
+        let result = call.await;
+        match &result {
+            Ok(_) => self.record_success(),
+            Err(_) => self.record_failure(),
+        }
+        result
+    }
+
+    fn before_call(&self) -> Result<(), String> {
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            BreakerState::Closed => Ok(()),
+            BreakerState::HalfOpen => {
+                // A probe that never reported back (e.g. its request was
+                // cancelled) must not keep the breaker half-open forever
+                if inner
+                    .opened_at
+                    .is_some_and(|at| at.elapsed() >= self.cooldown)
+                {
+                    inner.opened_at = Some(Instant::now());
+                    Ok(())
+                } else {
+                    Err(format!(
+                        "Circuit breaker '{}' is waiting for a recovery probe",
+                        self.name
+                    ))
+                }
+            }
+            BreakerState::Open => {
+                let elapsed = inner.opened_at.map(|at| at.elapsed()).unwrap_or_default();
+                if elapsed >= self.cooldown {
+                    // Let exactly one call through to probe the upstream
+                    inner.opened_at = Some(Instant::now());
+                    self.transition(&mut inner, BreakerState::HalfOpen);
+                    Ok(())
+                } else {
+                    self.metrics
+                        .increment("corriere_breaker_rejected_total", &[("breaker", self.name)]);
+                    Err(format!(
+                        "Circuit breaker '{}' is open, retrying in {}s",
+                        self.name,
+                        (self.cooldown - elapsed).as_secs()
+                    ))
+                }
+            }
+        }
+    }
+
+    fn record_success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures = 0;
+        if inner.state != BreakerState::Closed {
+            self.transition(&mut inner, BreakerState::Closed);
+        }
+    }
+
+    fn record_failure(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.consecutive_failures += 1;
+        self.metrics.increment(
+            "corriere_upstream_failures_total",
+            &[("breaker", self.name)],
+        );
+
+        let failed_probe = inner.state == BreakerState::HalfOpen;
+        if failed_probe || inner.consecutive_failures >= self.failure_threshold {
+            inner.opened_at = Some(Instant::now());
+            if inner.state != BreakerState::Open {
+                self.metrics
+                    .increment("corriere_breaker_trips_total", &[("breaker", self.name)]);
+                self.transition(&mut inner, BreakerState::Open);
+            }
+        }
+    }
+
+    fn transition(&self, inner: &mut Inner, state: BreakerState) {
+        eprintln!(
+            "Circuit breaker '{}': {:?} -> {:?}",
+            self.name, inner.state, state
+        );
+        inner.state = state;
+        self.metrics.set_gauge(
+            "corriere_breaker_state",
+            &[("breaker", self.name)],
+            state.gauge_value(),
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use async_trait::async_trait;
+use axum::extract::{Path as UrlPath, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde_json::json;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::AsyncWriteExt;
+
+use crate::config::Config;
+use crate::dates::{DateFormat, Locale};
+use crate::formats::escape_html;
+use crate::{AppState, NewsItem};
+
+const EPISODE_PREFIX: &str = "briefing-";
+const EPISODE_SUFFIX: &str = ".mp3";
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+const ORDINALS: &[&str] = &[
+    "Prima", "Seconda", "Terza", "Quarta", "Quinta", "Sesta", "Settima", "Ottava", "Nona", "Decima",
+];
+
+// Turns the text of a briefing into MP3 audio
+#[async_trait]
+pub trait Synthesizer: Send + Sync {
+    async fn synthesize(&self, text: &str) -> Result<Vec<u8>, String>;
+}
+
+// Runs TTS_COMMAND through the shell with the text on stdin, reading the MP3
+// from stdout. Meant for a local engine such as piper piped into ffmpeg
+pub struct CommandSynthesizer {
+    pub command: String,
+}
+
+#[async_trait]
+impl Synthesizer for CommandSynthesizer {
+    async fn synthesize(&self, text: &str) -> Result<Vec<u8>, String> {
+        let mut child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to run TTS_COMMAND: {}", e))?;
+
+        let mut stdin = child.stdin.take().ok_or("TTS_COMMAND has no stdin")?;
+        let input = text.as_bytes().to_vec();
+        let writer = tokio::spawn(async move {
+            // The command may exit without reading everything; its status says why
+            let _ = stdin.write_all(&input).await;
+        });
+        let output = child
+            .wait_with_output()
+            .await
+            .map_err(|e| format!("Failed to run TTS_COMMAND: {}", e))?;
+        let _ = writer.await;
+
+        if !output.status.success() {
+            return Err(format!(
+                "TTS_COMMAND failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        if output.stdout.is_empty() {
+            return Err("TTS_COMMAND produced no audio".to_string());
+        }
+        Ok(output.stdout)
+    }
+}
+
+// Posts the text to an OpenAI-style speech endpoint, which answers with MP3
+pub struct HttpSynthesizer {
+    pub client: reqwest::Client,
+    pub url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    pub voice: String,
+}
+
+#[async_trait]
+impl Synthesizer for HttpSynthesizer {
+    async fn synthesize(&self, text: &str) -> Result<Vec<u8>, String> {
+        let mut request = self.client.post(&self.url).json(&json!({
+            "model": self.model,
+            "voice": self.voice,
+            "input": text,
+            "response_format": "mp3",
+        }));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach TTS_API_URL: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("TTS API returned HTTP {}", response.status()));
+        }
+        let audio = response
+            .bytes()
+            .await
+            .map_err(|e| format!("Failed to read TTS audio: {}", e))?;
+        Ok(audio.to_vec())
+    }
+}
+
+// Helper function to pick the TTS backend, or None when neither TTS_COMMAND
+// nor TTS_API_URL is set
+pub fn synthesizer(config: &Config) -> Result<Option<Box<dyn Synthesizer>>, String> {
+    match (&config.tts_command, &config.tts_api_url) {
+        (Some(_), Some(_)) => Err("Set only one of TTS_COMMAND and TTS_API_URL".to_string()),
+        (Some(command), None) => Ok(Some(Box::new(CommandSynthesizer {
+            command: command.clone(),
+        }))),
+        (None, Some(url)) => Ok(Some(Box::new(HttpSynthesizer {
+            client: reqwest::Client::new(),
+            url: url.clone(),
+            api_key: config.tts_api_key.clone(),
+            model: config.tts_model.clone(),
+            voice: config.tts_voice.clone(),
+        }))),
+        (None, None) => Ok(None),
+    }
+}
+
+// Helper function to write the text read out in a briefing
+pub fn script(news: &[NewsItem], day: &str) -> String {
+    let mut script = format!("Rassegna stampa del Corriere della Sera, {}.", day);
+    for (ordinal, item) in ORDINALS.iter().zip(news) {
+        script.push_str(&format!(
+            "\n\n{} notizia. {}.",
+            ordinal,
+            item.title.trim_end_matches('.')
+        ));
+        let description = item.description.trim();
+        if !description.is_empty() {
+            script.push(' ');
+            script.push_str(description);
+            if !description.ends_with(['.', '!', '?', '»']) {
+                script.push('.');
+            }
+        }
+    }
+    script.push_str("\n\nÈ tutto. Buona giornata.");
+    script
+}
+
+// A generated briefing as listed in the podcast feed
+pub struct Episode {
+    pub file: String,
+    pub created_at: DateTime<Utc>,
+    pub bytes: u64,
+    pub script: String,
+}
+
+fn briefings_dir(config: &Config) -> PathBuf {
+    config.data_dir.join("briefings")
+}
+
+fn created_at(name: &str) -> Option<DateTime<Utc>> {
+    let timestamp = name
+        .strip_prefix(EPISODE_PREFIX)?
+        .strip_suffix(EPISODE_SUFFIX)?;
+    NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT)
+        .ok()
+        .map(|naive| naive.and_utc())
+}
+
+// Helper function to list the stored briefings, newest first
+pub fn episodes(dir: &Path) -> Vec<Episode> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut episodes: Vec<Episode> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let file = entry.file_name().to_str()?.to_string();
+            let created_at = created_at(&file)?;
+            let bytes = entry.metadata().ok()?.len();
+            let script =
+                std::fs::read_to_string(entry.path().with_extension("txt")).unwrap_or_default();
+            Some(Episode {
+                file,
+                created_at,
+                bytes,
+                script,
+            })
+        })
+        .collect();
+    episodes.sort_by_key(|episode| std::cmp::Reverse(episode.created_at));
+    episodes
+}
+
+// Helper function to store a briefing and its script, dropping the oldest
+// beyond BRIEFING_MAX_EPISODES
+fn save(config: &Config, audio: &[u8], script: &str, at: DateTime<Utc>) -> Result<(), String> {
+    let dir = briefings_dir(config);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+
+    let path = dir.join(format!(
+        "{}{}{}",
+        EPISODE_PREFIX,
+        at.format(TIMESTAMP_FORMAT),
+        EPISODE_SUFFIX
+    ));
+    std::fs::write(path.with_extension("txt"), script)
+        .map_err(|e| format!("Failed to write briefing script: {}", e))?;
+    // Write under a temporary name so the feed never lists a truncated file
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, audio)
+        .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+    std::fs::rename(&partial, &path)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    for episode in episodes(&dir)
+        .iter()
+        .skip(config.briefing_max_episodes.max(1))
+    {
+        let path = dir.join(&episode.file);
+        let _ = std::fs::remove_file(path.with_extension("txt"));
+        std::fs::remove_file(&path)
+            .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+// Helper function to read the top BRIEFING_TOP_N headlines into a new
+// briefing. Run by the "briefing" scheduler job; returns how many
+// headlines it covers
+pub async fn generate(state: &AppState) -> Result<usize, String> {
+    let synthesizer = synthesizer(&state.config)?
+        .ok_or("No TTS backend configured, set TTS_COMMAND or TTS_API_URL")?;
+    let news = crate::get_news(state)
+        .await
+        .map_err(|response| response.error.unwrap_or_default())?
+        .response
+        .news;
+    let news = &news[..news
+        .len()
+        .min(state.config.briefing_top_n)
+        .min(ORDINALS.len())];
+    if news.is_empty() {
+        return Err("No headlines to read".to_string());
+    }
+
+    let now = Utc::now();
+    let dates = DateFormat {
+        tz: Some(state.config.digest_time_zone),
+        locale: Some(Locale::Italian),
+    };
+    let script = script(news, &dates.display(now).unwrap_or_default());
+    let audio = synthesizer.synthesize(&script).await?;
+    save(&state.config, &audio, &script, now)?;
+    state.metrics.increment("corriere_briefings_total", &[]);
+    Ok(news.len())
+}
+
+// Helper function to list the stored briefings off the async runtime
+async fn listed_episodes(dir: PathBuf) -> Vec<Episode> {
+    tokio::task::spawn_blocking(move || episodes(&dir))
+        .await
+        .unwrap_or_default()
+}
+
+// Read without blocking the runtime, and whole, so ranges.rs can serve Range
+// requests from it
+async fn audio_response(path: &Path) -> Response {
+    match tokio::fs::read(path).await {
+        Ok(audio) => ([(header::CONTENT_TYPE, "audio/mpeg")], audio).into_response(),
+        Err(_) => (StatusCode::NOT_FOUND, "No such briefing").into_response(),
+    }
+}
+
+// The latest briefing
+pub async fn latest_handler(State(state): State<AppState>) -> Response {
+    let dir = briefings_dir(&state.config);
+    match listed_episodes(dir.clone()).await.first() {
+        Some(episode) => audio_response(&dir.join(&episode.file)).await,
+        None => (StatusCode::NOT_FOUND, "No briefing has been generated yet").into_response(),
+    }
+}
+
+// One briefing from the feed, by file name
+pub async fn episode_handler(
+    State(state): State<AppState>,
+    UrlPath(file): UrlPath<String>,
+) -> Response {
+    if created_at(&file).is_none() {
+        return (StatusCode::NOT_FOUND, "No such briefing").into_response();
+    }
+    audio_response(&briefings_dir(&state.config).join(file)).await
+}
+
+// The stored briefings as a podcast feed
+pub async fn feed_handler(State(state): State<AppState>) -> Response {
+    let public_url = &state.config.public_url;
+    let dates = DateFormat {
+        tz: Some(state.config.digest_time_zone),
+        locale: Some(Locale::Italian),
+    };
+
+    let mut rss = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<rss version=\"2.0\" xmlns:itunes=\"http://www.itunes.com/dtds/podcast-1.0.dtd\">\n",
+        "<channel>\n",
+        "  <title>Rassegna stampa - Corriere della Sera</title>\n",
+        "  <link>https://www.corriere.it</link>\n",
+        "  <language>it</language>\n",
+        "  <description>Le notizie in apertura sul Corriere della Sera, lette ad alta voce</description>\n",
+        "  <itunes:category text=\"News\"/>\n",
+        "  <itunes:explicit>false</itunes:explicit>\n",
+    ));
+    for episode in listed_episodes(briefings_dir(&state.config)).await {
+        let url = format!("{}/api/briefing/{}", public_url, episode.file);
+        rss.push_str("  <item>\n");
+        rss.push_str(&format!(
+            "    <title>Rassegna stampa, {}</title>\n",
+            escape_html(&dates.display(episode.created_at).unwrap_or_default())
+        ));
+        rss.push_str(&format!(
+            "    <description>{}</description>\n",
+            escape_html(&episode.script)
+        ));
+        rss.push_str(&format!(
+            "    <enclosure url=\"{}\" type=\"audio/mpeg\" length=\"{}\"/>\n",
+            escape_html(&url),
+            episode.bytes
+        ));
+        rss.push_str(&format!(
+            "    <guid isPermaLink=\"false\">{}</guid>\n",
+            escape_html(&episode.file)
+        ));
+        rss.push_str(&format!(
+            "    <pubDate>{}</pubDate>\n",
+            episode.created_at.to_rfc2822()
+        ));
+        rss.push_str("  </item>\n");
+    }
+    rss.push_str("</channel>\n</rss>\n");
+
+    (
+        [(header::CONTENT_TYPE, "application/rss+xml; charset=utf-8")],
+        rss,
+    )
+        .into_response()
+}
@@ -0,0 +1,174 @@
+use serde_json::{Map, Value};
+
+use crate::NewsItem;
+
+const ELLIPSIS: char = '…';
+
+// Limits on what a news response carries, for clients on slow or metered
+// connections. Unset limits don't apply
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Budget {
+    // In characters, the ellipsis included
+    pub max_description_chars: Option<usize>,
+    pub max_items: Option<usize>,
+    // Size of the serialized JSON body
+    pub max_response_bytes: Option<usize>,
+}
+
+// Helper function to shorten a text to at most `max_chars` characters,
+// ending on a whole word followed by an ellipsis. None when it already fits
+pub fn truncate_text(text: &str, max_chars: usize) -> Option<String> {
+    if text.chars().count() <= max_chars {
+        return None;
+    }
+    if max_chars == 0 {
+        return Some(String::new());
+    }
+
+    let cut = text
+        .char_indices()
+        .nth(max_chars - 1)
+        .map(|(index, _)| index)
+        .unwrap_or(text.len());
+    let kept = &text[..cut];
+    // Back up to the last word boundary when the cut falls inside a word,
+    // unless that throws away most of the text
+    let inside_word = text[cut..].starts_with(char::is_alphanumeric);
+    let kept = match kept.rfind(char::is_whitespace) {
+        Some(space) if inside_word && space >= cut / 2 => &kept[..space],
+        _ => kept,
+    };
+    let kept = kept.trim_end_matches(|c: char| c.is_whitespace() || ",;:.-–".contains(c));
+    Some(format!("{}{}", kept, ELLIPSIS))
+}
+
+impl Budget {
+    // Helper function to shorten descriptions and drop the items past
+    // the limit. Shortened items are marked `truncated: true`. Returns
+    // whether items were dropped
+    pub fn trim_items(&self, items: &mut Vec<Value>) -> bool {
+        if let Some(max_chars) = self.max_description_chars {
+            for item in items.iter_mut().filter_map(Value::as_object_mut) {
+                if shorten_description(item, max_chars) {
+                    item.insert("truncated".to_string(), Value::Bool(true));
+                }
+                // The normalized copy of v2 items, when requested
+                if let Some(normalized) = item.get_mut("normalized").and_then(Value::as_object_mut)
+                {
+                    shorten_description(normalized, max_chars);
+                }
+            }
+        }
+
+        match self.max_items {
+            Some(max_items) if items.len() > max_items => {
+                items.truncate(max_items);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Helper function to bring a JSON body with a `news` array within the
+    // budget. Items are dropped from the end, the least prominent first,
+    // until the body fits; the body is then marked `truncated: true` and its
+    // `count`, if any, updated
+    pub fn apply(&self, body: &mut Value) {
+        let Some(object) = body.as_object_mut() else {
+            return;
+        };
+        let Some(Value::Array(news)) = object.get_mut("news") else {
+            return;
+        };
+        if self.trim_items(news) {
+            mark_truncated(object);
+        }
+
+        let Some(max_bytes) = self.max_response_bytes else {
+            return;
+        };
+        if body_size(object) <= max_bytes {
+            return;
+        }
+        let Some(Value::Array(news)) = object.get("news") else {
+            return;
+        };
+        if news.is_empty() {
+            return;
+        }
+        // Items are measured once and their size taken off the body as they
+        // are dropped, rather than serializing the body again for each one.
+        // The body is measured already marked, so the count can only shrink
+        let item_sizes: Vec<usize> = news.iter().map(value_size).collect();
+        mark_truncated(object);
+        let mut size = body_size(object);
+        let mut kept = item_sizes.len();
+        while size > max_bytes && kept > 0 {
+            kept -= 1;
+            // The item and, unless it was the only one left, its comma
+            size -= item_sizes[kept] + usize::from(kept > 0);
+        }
+        if let Some(Value::Array(news)) = object.get_mut("news") {
+            news.truncate(kept);
+        }
+        mark_truncated(object);
+    }
+
+    // Helper function to shorten descriptions and drop the items past the
+    // limit, for the formats rendered from the scraped items themselves.
+    // Returns whether items were dropped
+    pub fn trim_news(&self, news: &mut Vec<NewsItem>) -> bool {
+        if let Some(max_chars) = self.max_description_chars {
+            for item in news.iter_mut() {
+                if let Some(shortened) = truncate_text(&item.description, max_chars) {
+                    item.description = shortened;
+                }
+            }
+        }
+
+        match self.max_items {
+            Some(max_items) if news.len() > max_items => {
+                news.truncate(max_items);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn shorten_description(object: &mut Map<String, Value>, max_chars: usize) -> bool {
+    let shortened = object
+        .get("description")
+        .and_then(Value::as_str)
+        .and_then(|description| truncate_text(description, max_chars));
+    match shortened {
+        Some(shortened) => {
+            object.insert("description".to_string(), Value::String(shortened));
+            true
+        }
+        None => false,
+    }
+}
+
+fn mark_truncated(object: &mut Map<String, Value>) {
+    object.insert("truncated".to_string(), Value::Bool(true));
+    let count = object
+        .get("news")
+        .and_then(Value::as_array)
+        .map(|news| news.len());
+    if let (Some(count), Some(Value::Number(_))) = (count, object.get("count")) {
+        object.insert("count".to_string(), Value::from(count));
+    }
+}
+
+fn body_size(object: &Map<String, Value>) -> usize {
+    serde_json::to_vec(object)
+        .map(|body| body.len())
+        .unwrap_or(0)
+}
+
+fn value_size(value: &Value) -> usize {
+    serde_json::to_vec(value)
+        .map(|body| body.len())
+        .unwrap_or(0)
+}
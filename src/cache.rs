@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+
+use crate::NewsResponse;
+
+#[derive(Clone)]
+pub struct CacheEntry {
+    pub response: NewsResponse,
+    pub stored_at: Instant,
+}
+
+impl CacheEntry {
+    pub fn age(&self) -> Duration {
+        self.stored_at.elapsed()
+    }
+}
+
+type Flight<T = NewsResponse> = Arc<OnceCell<Result<T, String>>>;
+
+// Latest homepage scrape, plus a flag so only one background refresh runs at
+// a time and the upstream fetch currently in flight, if any
+#[derive(Default)]
+pub struct NewsCache {
+    entry: RwLock<Option<CacheEntry>>,
+    refreshing: AtomicBool,
+    inflight: Mutex<Option<Flight>>,
+}
+
+impl NewsCache {
+    pub fn get(&self) -> Option<CacheEntry> {
+        self.entry.read().unwrap().clone()
+    }
+
+    pub fn store(&self, response: NewsResponse) {
+        *self.entry.write().unwrap() = Some(CacheEntry {
+            response,
+            stored_at: Instant::now(),
+        });
+    }
+
+    // Forgets the latest scrape, so the next request scrapes again
+    pub fn clear(&self) {
+        *self.entry.write().unwrap() = None;
+    }
+
+    // Helper function to coalesce concurrent refreshes: the first caller runs
+    // `refresh`, everyone arriving while it is in flight waits for and shares
+    // its result. The boolean tells whether this caller joined an existing fetch
+    pub async fn coalesce<F, Fut>(&self, refresh: F) -> (Result<NewsResponse, String>, bool)
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<NewsResponse, String>>,
+    {
+        let (flight, joined) = {
+            let mut inflight = self.inflight.lock().unwrap();
+            match inflight.as_ref() {
+                Some(flight) => (flight.clone(), true),
+                None => {
+                    let flight: Flight = Arc::new(OnceCell::new());
+                    *inflight = Some(flight.clone());
+                    (flight, false)
+                }
+            }
+        };
+
+        // If the caller running the fetch is cancelled, OnceCell hands the
+        // work to one of the waiters instead
+        let result = flight.get_or_init(refresh).await.clone();
+
+        // The flight is over; the next miss starts a new fetch
+        let mut inflight = self.inflight.lock().unwrap();
+        if inflight
+            .as_ref()
+            .is_some_and(|current| Arc::ptr_eq(current, &flight))
+        {
+            *inflight = None;
+        }
+
+        (result, joined)
+    }
+
+    // Returns a guard when no other background refresh is running; the flag
+    // is cleared when the guard is dropped, even if the refresh panics
+    pub fn try_begin_refresh(self: &Arc<Self>) -> Option<RefreshGuard> {
+        self.refreshing
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .ok()
+            .map(|_| RefreshGuard {
+                cache: self.clone(),
+            })
+    }
+}
+
+// Fetches in flight by key, for caches of several pages: like
+// NewsCache::coalesce, everyone asking for a key while its fetch runs waits
+// for and shares the result
+pub struct Flights<T> {
+    inflight: Mutex<HashMap<String, Flight<T>>>,
+}
+
+impl<T> Default for Flights<T> {
+    fn default() -> Self {
+        Flights {
+            inflight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Clone> Flights<T> {
+    pub async fn coalesce<F, Fut>(&self, key: &str, fetch: F) -> Result<T, String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, String>>,
+    {
+        let flight = self
+            .inflight
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+        let result = flight.get_or_init(fetch).await.clone();
+
+        let mut inflight = self.inflight.lock().unwrap();
+        if inflight
+            .get(key)
+            .is_some_and(|current| Arc::ptr_eq(current, &flight))
+        {
+            inflight.remove(key);
+        }
+        result
+    }
+}
+
+pub struct RefreshGuard {
+    cache: Arc<NewsCache>,
+}
+
+impl Drop for RefreshGuard {
+    fn drop(&mut self) {
+        self.cache.refreshing.store(false, Ordering::Release);
+    }
+}
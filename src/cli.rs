@@ -0,0 +1,145 @@
+use chrono::Utc;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::extract::{self, SelectorConfig, Selectors};
+use crate::{plugins, server, snapshot, AppState, NewsResponse};
+
+pub const USAGE: &str = "Usage:
+  corriere_scraper [serve]          Start the HTTP server
+  corriere_scraper --demo           Start it serving the bundled sample
+                                    homepage (DEMO_MODE=always)
+  corriere_scraper replay [FILE]    Re-run extraction against a stored snapshot
+                                    (defaults to the latest one in DATA_DIR)
+  corriere_scraper digest           Send the daily email digest now
+  corriere_scraper test-plugin NAME [FILE]
+                                    Dry-run a plugin from SOURCE_PLUGINS_PATH
+                                    against its page or a saved HTML file
+  corriere_scraper build [--out DIR]
+                                    Write the latest news as static files
+                                    (defaults to STATIC_OUT_DIR)
+  corriere_scraper export [DATE]    Upload a day of the archive to S3
+                                    (defaults to yesterday; s3 feature)
+  corriere_scraper --check-config   Validate the configuration and exit, e.g.
+                                    as a container health or pre-deploy check";
+
+// Re-runs homepage extraction against a stored snapshot and prints the result
+// as JSON. Exits with an error when nothing could be extracted, so it can be
+// used to check selector changes against captured markup
+pub fn replay(config: &Config, file: Option<&String>) -> Result<(), String> {
+    let path = match file {
+        Some(file) => PathBuf::from(file),
+        None => {
+            let dir = config.snapshot_dir();
+            snapshot::list(&dir)?
+                .pop()
+                .map(|(path, _)| path)
+                .ok_or_else(|| format!("No snapshots found in {}", dir.display()))?
+        }
+    };
+
+    let html = snapshot::load(&path)?;
+    let selectors = Selectors::parse(&SelectorConfig::default())?;
+    let news = extract::extract_news(
+        &html,
+        &selectors,
+        extract::CORRIERE_BASE_URL,
+        extract::HOMEPAGE_LIMIT,
+        config.parse_mode,
+    );
+    let item_count = news.len();
+
+    let response = NewsResponse {
+        scraped_at: snapshot::snapshot_time(&path).unwrap_or_else(Utc::now),
+        news,
+        error: None,
+        stale: false,
+        provenance: None,
+        demo: false,
+        snapshot: false,
+    };
+    let json = serde_json::to_string_pretty(&response)
+        .map_err(|e| format!("Failed to serialize result: {}", e))?;
+    println!("{}", json);
+
+    eprintln!("Extracted {} items from {}", item_count, path.display());
+    if item_count == 0 {
+        return Err("No news items extracted".to_string());
+    }
+    Ok(())
+}
+
+// Runs a plugin once and prints what it extracts as JSON, without storing
+// anything. It fetches and validates like the scheduler does, and reads a
+// saved page instead when given one, so a definition can be worked on
+// offline. Exits with an error when no items come out
+pub async fn test_plugin(config: Config, args: &[String]) -> Result<(), String> {
+    let (name, file) = match args {
+        [name] => (name, None),
+        [name, file] => (name, Some(file)),
+        _ => return Err(format!("Expected a plugin name\n{}", USAGE)),
+    };
+    let plugin = config.plugin(name).cloned().ok_or_else(|| {
+        let names: Vec<&str> = config.plugins.iter().map(|p| p.name.as_str()).collect();
+        format!(
+            "No plugin named '{}' in SOURCE_PLUGINS_PATH (found: {})",
+            name,
+            if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join(", ")
+            }
+        )
+    })?;
+
+    let state = AppState::new(config);
+    let news = match file {
+        Some(file) => {
+            let html = std::fs::read_to_string(file)
+                .map_err(|e| format!("Failed to read {}: {}", file, e))?;
+            plugins::extract_page(&state, &plugin, html).await?
+        }
+        None => plugins::scrape(&state, &plugin).await?,
+    };
+    let item_count = news.len();
+
+    let response = NewsResponse {
+        scraped_at: Utc::now(),
+        news,
+        error: None,
+        stale: false,
+        provenance: None,
+        demo: false,
+        snapshot: false,
+    };
+    let json = serde_json::to_string_pretty(&response)
+        .map_err(|e| format!("Failed to serialize result: {}", e))?;
+    println!("{}", json);
+
+    eprintln!("Plugin {} extracted {} items", plugin.name, item_count);
+    if item_count == 0 {
+        return Err("No news items extracted".to_string());
+    }
+    Ok(())
+}
+
+// Checks what `serve` would refuse to start with, with the same checks it
+// runs, without binding the listener or creating anything: the environment
+// was already parsed by then, so this covers DATA_DIR, the archive and the
+// settings checked when the jobs are built
+pub async fn check_config(config: &Config) -> Result<(), String> {
+    server::validate(config).await?;
+
+    println!("Configuration OK");
+    println!("  listening on  {}", describe_listener(config));
+    println!("  public URL    {}", config.public_url);
+    println!("  data dir      {}", config.data_dir.display());
+    Ok(())
+}
+
+fn describe_listener(config: &Config) -> String {
+    match &config.unix_socket_path {
+        Some(path) => format!("unix:{}", path.display()),
+        None => config.bind_addr.to_string(),
+    }
+}
@@ -0,0 +1,291 @@
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+use crate::extract::SelectorConfig;
+use crate::{fetch_queue, scheduler, v2, AppState, NewsItem, NewsResponse};
+
+// Name the homepage goes by among the sources
+pub const HOMEPAGE_SOURCE: &str = "corriere";
+
+// Words too common in Italian headlines to say anything about the story
+const STOPWORDS: &[&str] = &[
+    "alla", "alle", "agli", "anche", "come", "con", "dai", "dal", "dalla", "dei", "del", "della",
+    "delle", "dopo", "gli", "nel", "nella", "nelle", "non", "per", "più", "sono", "sul", "sulla",
+    "tra", "una", "uno", "the", "and", "for",
+];
+
+// Latest scrape of each of the other outlets in NEWS_SOURCES
+#[derive(Default)]
+pub struct SourceCache {
+    entries: RwLock<HashMap<String, NewsResponse>>,
+}
+
+impl SourceCache {
+    fn get(&self, source: &str) -> Option<NewsResponse> {
+        self.entries.read().unwrap().get(source).cloned()
+    }
+
+    fn store(&self, source: &str, news: Vec<NewsItem>) {
+        self.entries.write().unwrap().insert(
+            source.to_string(),
+            NewsResponse {
+                scraped_at: Utc::now(),
+                news,
+                error: None,
+                stale: false,
+                provenance: None,
+                demo: false,
+                snapshot: false,
+            },
+        );
+    }
+}
+
+// Helper function to reduce a headline to its distinctive words, lowercased
+pub fn tokens(title: &str) -> Vec<String> {
+    title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 2 && !STOPWORDS.contains(word))
+        .map(str::to_string)
+        .collect()
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// Helper function to score how alike two headlines are, from 0 to 1: the
+// better of the cosine of their word sets, which ignores word order, and the
+// normalized edit distance of their words, which forgives inflections
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (tokens(a), tokens(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let set_a: BTreeSet<&String> = a.iter().collect();
+    let set_b: BTreeSet<&String> = b.iter().collect();
+    let shared = set_a.intersection(&set_b).count() as f64;
+    let cosine = shared / ((set_a.len() * set_b.len()) as f64).sqrt();
+
+    let a: Vec<char> = a.join(" ").chars().collect();
+    let b: Vec<char> = b.join(" ").chars().collect();
+    let edit = 1.0 - levenshtein(&a, &b) as f64 / a.len().max(b.len()) as f64;
+
+    cosine.max(edit)
+}
+
+#[derive(Serialize, Clone)]
+pub struct SourcedItem {
+    pub source: String,
+    #[serde(flatten)]
+    pub item: NewsItem,
+}
+
+// One story: the item it is best known by and how the other outlets ran it
+#[derive(Serialize, Clone)]
+pub struct Cluster {
+    pub id: String,
+    pub sources: Vec<String>,
+    pub canonical: SourcedItem,
+    pub alternates: Vec<SourcedItem>,
+}
+
+// Helper function to group items telling the same story. Each item joins
+// the cluster holding its most similar headline, if that reaches
+// `threshold` and the cluster has nothing from its source yet. Items are
+// taken in order, so the first source listed supplies the canonical items
+pub fn cluster(items: Vec<SourcedItem>, threshold: f64) -> Vec<Cluster> {
+    let mut clusters: Vec<Vec<SourcedItem>> = Vec::new();
+    for item in items {
+        let best = clusters
+            .iter()
+            .enumerate()
+            .filter(|(_, members)| members.iter().all(|m| m.source != item.source))
+            .map(|(index, members)| {
+                let score = members
+                    .iter()
+                    .map(|m| similarity(&m.item.title, &item.item.title))
+                    .fold(0.0, f64::max);
+                (index, score)
+            })
+            .filter(|(_, score)| *score >= threshold)
+            // Ties go to the earlier cluster
+            .max_by(|a, b| a.1.total_cmp(&b.1).then(b.0.cmp(&a.0)));
+        match best {
+            Some((index, _)) => clusters[index].push(item),
+            None => clusters.push(vec![item]),
+        }
+    }
+
+    let mut clusters: Vec<Cluster> = clusters
+        .into_iter()
+        .map(|mut members| {
+            let canonical = members.remove(0);
+            Cluster {
+                id: v2::item_id(&canonical.item.link),
+                sources: std::iter::once(&canonical)
+                    .chain(&members)
+                    .map(|m| m.source.clone())
+                    .collect(),
+                canonical,
+                alternates: members,
+            }
+        })
+        .collect();
+    // Stable, so stories with the same reach keep the homepage order
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.sources.len()));
+    clusters
+}
+
+// Helper function to get an outlet's items, scraping it again once the
+// cached copy is older than CACHE_SOFT_TTL_SECS and falling back to that
+// copy when the outlet can't be reached
+pub async fn source_news(
+    state: &AppState,
+    source: &str,
+    url: &str,
+) -> Result<Vec<NewsItem>, String> {
+    let cached = state.sources.get(source);
+    let ttl = Duration::from_secs(state.config.cache_soft_ttl_secs);
+    if let Some(cached) = &cached {
+        let age = (Utc::now() - cached.scraped_at)
+            .to_std()
+            .unwrap_or_default();
+        if age < ttl {
+            return Ok(cached.news.clone());
+        }
+    }
+
+    match scheduler::scrape_page(state, source, url, SelectorConfig::default()).await {
+        Ok(news) => {
+            state.sources.store(source, news.clone());
+            Ok(news)
+        }
+        Err(error_message) => cached.map(|cached| cached.news).ok_or(error_message),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ClusterParams {
+    min_sources: Option<usize>,
+}
+
+#[derive(Serialize, Default)]
+pub struct ClustersResponse {
+    pub scraped_at: Option<DateTime<Utc>>,
+    pub count: usize,
+    pub clusters: Vec<Cluster>,
+    // Sources that couldn't be scraped, with the reason
+    pub errors: BTreeMap<String, String>,
+    pub error: Option<String>,
+}
+
+// The homepage and the NEWS_SOURCES outlets grouped by story. Only stories
+// carried by at least ?min_sources= (default 2) outlets are listed
+pub async fn clusters_handler(
+    State(state): State<AppState>,
+    Query(params): Query<ClusterParams>,
+) -> (StatusCode, Json<ClustersResponse>) {
+    let source_count = state.config.news_sources.len() + 1;
+    let min_sources = params.min_sources.unwrap_or(2);
+    if min_sources == 0 || min_sources > source_count {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ClustersResponse {
+                error: Some(format!(
+                    "min_sources must be between 1 and {}",
+                    source_count
+                )),
+                ..ClustersResponse::default()
+            }),
+        );
+    }
+
+    // The outlets are scraped side by side, while the homepage is fetched
+    let mut tasks = JoinSet::new();
+    let priority = fetch_queue::current();
+    for (index, (source, url)) in state.config.news_sources.iter().enumerate() {
+        let (state, source, url) = (state.clone(), source.clone(), url.clone());
+        tasks.spawn(fetch_queue::scope(priority, async move {
+            let result = source_news(&state, &source, &url).await;
+            (index, source, result)
+        }));
+    }
+
+    let mut items = Vec::new();
+    let mut errors = BTreeMap::new();
+    match crate::get_news(&state).await {
+        Ok(cached) => items.extend(cached.response.news.into_iter().map(|item| SourcedItem {
+            source: HOMEPAGE_SOURCE.to_string(),
+            item,
+        })),
+        Err(response) => {
+            errors.insert(
+                HOMEPAGE_SOURCE.to_string(),
+                response.error.unwrap_or_default(),
+            );
+        }
+    }
+    // Results complete in any order; the items go in NEWS_SOURCES order
+    let mut results = BTreeMap::new();
+    while let Some(joined) = tasks.join_next().await {
+        if let Ok((index, source, result)) = joined {
+            results.insert(index, (source, result));
+        }
+    }
+    for (source, result) in results.into_values() {
+        match result {
+            Ok(news) => items.extend(news.into_iter().map(|item| SourcedItem {
+                source: source.clone(),
+                item,
+            })),
+            Err(error_message) => {
+                errors.insert(source, error_message);
+            }
+        }
+    }
+
+    if errors.len() == source_count {
+        return (
+            StatusCode::BAD_GATEWAY,
+            Json(ClustersResponse {
+                errors,
+                error: Some("No source could be scraped".to_string()),
+                ..ClustersResponse::default()
+            }),
+        );
+    }
+
+    let clusters: Vec<Cluster> = cluster(items, state.config.cluster_similarity)
+        .into_iter()
+        .filter(|cluster| cluster.sources.len() >= min_sources)
+        .collect();
+    (
+        StatusCode::OK,
+        Json(ClustersResponse {
+            scraped_at: Some(Utc::now()),
+            count: clusters.len(),
+            clusters,
+            errors,
+            error: None,
+        }),
+    )
+}
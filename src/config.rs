@@ -0,0 +1,1099 @@
+use chrono_tz::Tz;
+use reqwest::Url;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::budget::Budget;
+use crate::demo::DemoMode;
+use crate::entities::Gazetteer;
+use crate::export::ExportFormat;
+use crate::extract::{ParseMode, SelectorSets};
+use crate::limits;
+use crate::plugins::{self, SourcePlugin};
+use crate::scheduler::{self, Job};
+use crate::scoring::ScoreWeights;
+use crate::validate::{self, ValidationMode};
+
+// How to fix a DATA_DIR the server can't write to
+const DATA_DIR_HINT: &str = "set DATA_DIR to a writable directory, e.g. a volume mounted \
+                             for the user the server runs as";
+
+// Runtime configuration, read from the environment (and .env via dotenv)
+pub struct Config {
+    pub bind_addr: SocketAddr,
+    pub unix_socket_path: Option<PathBuf>,
+    pub unix_socket_mode: Option<u32>,
+    pub homepage_url: String,
+    pub scrape_allowed_hosts: Vec<String>,
+    pub scrape_allow_private_addresses: bool,
+    pub scrape_max_redirects: usize,
+    pub scrape_max_bytes: usize,
+    pub batch_max_urls: usize,
+    pub per_host_concurrency: usize,
+    pub fetch_concurrency: usize,
+    pub fetch_queue_size: usize,
+    pub data_dir: PathBuf,
+    pub static_out_dir: PathBuf,
+    pub snapshot_html: bool,
+    pub snapshot_max_files: usize,
+    pub snapshot_max_age_days: u32,
+    pub breaker_failure_threshold: u32,
+    pub breaker_cooldown_secs: u64,
+    pub cache_soft_ttl_secs: u64,
+    pub cache_hard_ttl_secs: u64,
+    pub parse_mode: ParseMode,
+    pub validation_mode: ValidationMode,
+    pub validation_domains: Vec<String>,
+    pub demo_mode: DemoMode,
+    pub selector_sets: SelectorSets,
+    // Names of people, places and organizations to tag items with
+    pub gazetteer: Gazetteer,
+    pub budget: Budget,
+    // Limit on handling a request, for routes not in route_timeouts
+    pub request_timeout_secs: u64,
+    // Timeouts by path prefix, see limits::timeout_for
+    pub route_timeouts: Vec<(String, Duration)>,
+    // Limit on the body of POST, PUT and PATCH requests
+    pub max_request_body_bytes: usize,
+    // Origins allowed by CORS; empty allows any
+    pub cors_origins: Vec<String>,
+    // Weights of ?sort=score on the news endpoints
+    pub scoring: ScoreWeights,
+    pub selector_min_items: usize,
+    pub smtp_url: Option<String>,
+    pub digest_from: String,
+    pub digest_hour: u32,
+    pub digest_time_zone: Tz,
+    pub digest_top_n: usize,
+    pub tts_command: Option<String>,
+    pub tts_api_url: Option<String>,
+    pub tts_api_key: Option<String>,
+    pub tts_model: String,
+    pub tts_voice: String,
+    pub briefing_top_n: usize,
+    pub briefing_max_episodes: usize,
+    pub embedding_command: Option<String>,
+    pub embedding_api_url: Option<String>,
+    pub embedding_api_key: Option<String>,
+    pub embedding_model: String,
+    pub embedding_batch_size: usize,
+    pub embedding_poll_secs: u64,
+    pub embedding_backfill_limit: usize,
+    pub semantic_queries_per_minute: u32,
+    pub summary_api_url: Option<String>,
+    pub summary_api_key: Option<String>,
+    pub summary_model: String,
+    pub edition_top_n: usize,
+    pub edition_days: usize,
+    pub public_url: String,
+    pub telegram_bot_token: Option<String>,
+    pub telegram_chat_ids: Vec<String>,
+    pub telegram_top_n: usize,
+    pub telegram_poll_secs: u64,
+    pub telegram_api_url: String,
+    pub mqtt_url: Option<String>,
+    pub mqtt_client_id: String,
+    pub mqtt_topic: String,
+    pub mqtt_breaking_topic: String,
+    pub mqtt_qos: u8,
+    pub mqtt_retain: bool,
+    pub mqtt_poll_secs: u64,
+    pub social_template: String,
+    pub social_sections: Vec<String>,
+    pub social_top_n: usize,
+    pub social_poll_secs: u64,
+    pub mastodon_url: Option<String>,
+    pub mastodon_token: Option<String>,
+    pub mastodon_min_interval_secs: u64,
+    pub bluesky_handle: Option<String>,
+    pub bluesky_app_password: Option<String>,
+    pub bluesky_pds_url: String,
+    pub bluesky_min_interval_secs: u64,
+    pub wallabag_url: Option<String>,
+    pub wallabag_client_id: Option<String>,
+    pub wallabag_client_secret: Option<String>,
+    pub wallabag_username: Option<String>,
+    pub wallabag_password: Option<String>,
+    pub pocket_consumer_key: Option<String>,
+    pub pocket_access_token: Option<String>,
+    pub pocket_api_url: String,
+    pub read_later_sections: Vec<String>,
+    pub read_later_poll_secs: u64,
+    pub seen_filter_capacity: usize,
+    pub seen_filter_fp_rate: f64,
+    pub seen_filter_checkpoint_secs: u64,
+    pub delivery_max_attempts: u32,
+    pub delivery_backoff_secs: u64,
+    pub delivery_max_backoff_secs: u64,
+    pub delivery_retry_secs: u64,
+    pub webhooks_enabled: bool,
+    pub webhook_poll_secs: u64,
+    pub watch_enabled: bool,
+    pub watch_poll_secs: u64,
+    pub watch_max: usize,
+    pub alert_api_keys: Vec<String>,
+    pub alert_max_per_key: usize,
+    pub alert_poll_secs: u64,
+    pub stats_hour: u32,
+    pub stats_backfill_days: usize,
+    pub paywall_link_patterns: Vec<String>,
+    pub scheduler_jobs: Vec<Job>,
+    pub plugins: Vec<SourcePlugin>,
+    // (city, front page URL) pairs replacing or adding local editions
+    pub local_editions: Vec<(String, String)>,
+    pub news_sources: Vec<(String, String)>,
+    pub cluster_similarity: f64,
+    pub scheduler_jitter_pct: u32,
+    pub heartbeat_url: Option<String>,
+    pub heartbeat_fail_url: Option<String>,
+    // Jobs that ping; all of them when empty
+    pub heartbeat_jobs: Vec<String>,
+    pub admin_token: Option<String>,
+    // (name, token) pairs: more admin tokens, each recorded in the audit log
+    // under its name
+    pub admin_tokens: Vec<(String, String)>,
+    // Record scrapes and admin actions in DATA_DIR/audit.jsonl
+    pub audit_log: bool,
+    // Key signing the preference cookies of /api/news/for-me
+    pub preferences_secret: Option<String>,
+    // (name, URL) pairs of the daily digests served at /api/digest/today
+    pub newsletter_urls: Vec<(String, String)>,
+    pub newsletter_cache_secs: u64,
+    pub lock_url: Option<String>,
+    pub archive_url: Option<String>,
+    pub s3_bucket: Option<String>,
+    pub s3_endpoint: String,
+    pub s3_region: String,
+    pub s3_access_key_id: Option<String>,
+    pub s3_secret_access_key: Option<String>,
+    pub s3_path_style: bool,
+    pub s3_key_template: String,
+    pub s3_export_snapshots: bool,
+    pub s3_export_hour: u32,
+    pub s3_export_format: ExportFormat,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind_addr: default_bind_addr(false, None),
+            unix_socket_path: None,
+            unix_socket_mode: None,
+            homepage_url: "https://www.corriere.it".to_string(),
+            scrape_allowed_hosts: vec!["corriere.it".to_string()],
+            scrape_allow_private_addresses: false,
+            scrape_max_redirects: 5,
+            scrape_max_bytes: 5 * 1024 * 1024,
+            batch_max_urls: 20,
+            per_host_concurrency: 2,
+            fetch_concurrency: 16,
+            fetch_queue_size: 256,
+            data_dir: PathBuf::from("data"),
+            static_out_dir: PathBuf::from("dist"),
+            snapshot_html: false,
+            snapshot_max_files: 500,
+            snapshot_max_age_days: 30,
+            breaker_failure_threshold: 5,
+            breaker_cooldown_secs: 60,
+            cache_soft_ttl_secs: 60,
+            cache_hard_ttl_secs: 600,
+            parse_mode: ParseMode::Document,
+            validation_mode: ValidationMode::Drop,
+            demo_mode: DemoMode::Off,
+            validation_domains: validate::DEFAULT_DOMAINS
+                .iter()
+                .map(|domain| domain.to_string())
+                .collect(),
+            selector_sets: SelectorSets::default(),
+            gazetteer: Gazetteer::default(),
+            budget: Budget::default(),
+            request_timeout_secs: 30,
+            route_timeouts: limits::default_route_timeouts(),
+            max_request_body_bytes: 65536,
+            cors_origins: vec![],
+            scoring: ScoreWeights::default(),
+            selector_min_items: 1,
+            smtp_url: None,
+            digest_from: "Corriere Scraper <digest@localhost>".to_string(),
+            digest_hour: 7,
+            digest_time_zone: chrono_tz::Europe::Rome,
+            digest_top_n: 10,
+            tts_command: None,
+            tts_api_url: None,
+            tts_api_key: None,
+            tts_model: "tts-1".to_string(),
+            tts_voice: "alloy".to_string(),
+            briefing_top_n: 5,
+            briefing_max_episodes: 14,
+            embedding_command: None,
+            embedding_api_url: None,
+            embedding_api_key: None,
+            embedding_model: "text-embedding-3-small".to_string(),
+            embedding_batch_size: 32,
+            embedding_poll_secs: 300,
+            embedding_backfill_limit: 5000,
+            semantic_queries_per_minute: 60,
+            summary_api_url: None,
+            summary_api_key: None,
+            summary_model: "gpt-4o-mini".to_string(),
+            edition_top_n: 10,
+            edition_days: 30,
+            public_url: "http://127.0.0.1:3000".to_string(),
+            telegram_bot_token: None,
+            telegram_chat_ids: vec![],
+            telegram_top_n: 3,
+            telegram_poll_secs: 60,
+            telegram_api_url: "https://api.telegram.org".to_string(),
+            mqtt_url: None,
+            mqtt_client_id: "corriere-scraper".to_string(),
+            mqtt_topic: "corriere/news".to_string(),
+            mqtt_breaking_topic: "corriere/breaking".to_string(),
+            mqtt_qos: 0,
+            mqtt_retain: false,
+            mqtt_poll_secs: 60,
+            social_template: "{title}\n\n{link}".to_string(),
+            social_sections: vec![],
+            social_top_n: 5,
+            social_poll_secs: 300,
+            mastodon_url: None,
+            mastodon_token: None,
+            mastodon_min_interval_secs: 60,
+            bluesky_handle: None,
+            bluesky_app_password: None,
+            bluesky_pds_url: "https://bsky.social".to_string(),
+            bluesky_min_interval_secs: 60,
+            wallabag_url: None,
+            wallabag_client_id: None,
+            wallabag_client_secret: None,
+            wallabag_username: None,
+            wallabag_password: None,
+            pocket_consumer_key: None,
+            pocket_access_token: None,
+            pocket_api_url: "https://getpocket.com".to_string(),
+            read_later_sections: vec![],
+            read_later_poll_secs: 300,
+            seen_filter_capacity: 500_000,
+            seen_filter_fp_rate: 0.001,
+            seen_filter_checkpoint_secs: 300,
+            delivery_max_attempts: 8,
+            delivery_backoff_secs: 30,
+            delivery_max_backoff_secs: 6 * 3600,
+            delivery_retry_secs: 15,
+            webhooks_enabled: false,
+            webhook_poll_secs: 60,
+            watch_enabled: false,
+            watch_poll_secs: 900,
+            watch_max: 100,
+            alert_api_keys: vec![],
+            alert_max_per_key: 20,
+            alert_poll_secs: 120,
+            stats_hour: 1,
+            stats_backfill_days: 30,
+            paywall_link_patterns: vec!["/premium/".to_string()],
+            scheduler_jobs: vec![],
+            plugins: vec![],
+            local_editions: vec![],
+            news_sources: vec![],
+            cluster_similarity: 0.5,
+            scheduler_jitter_pct: 10,
+            heartbeat_url: None,
+            heartbeat_fail_url: None,
+            heartbeat_jobs: vec![],
+            admin_token: None,
+            admin_tokens: vec![],
+            audit_log: true,
+            preferences_secret: None,
+            newsletter_urls: crate::newsletter::DEFAULT_NEWSLETTERS
+                .iter()
+                .map(|(name, url)| (name.to_string(), url.to_string()))
+                .collect(),
+            newsletter_cache_secs: 900,
+            lock_url: None,
+            archive_url: None,
+            s3_bucket: None,
+            s3_endpoint: "https://s3.amazonaws.com".to_string(),
+            s3_region: "us-east-1".to_string(),
+            s3_access_key_id: None,
+            s3_secret_access_key: None,
+            s3_path_style: false,
+            s3_key_template: "corriere/{kind}/{year}/{month}/{day}/{name}".to_string(),
+            s3_export_snapshots: false,
+            s3_export_hour: 2,
+            s3_export_format: ExportFormat::Ndjson,
+        }
+    }
+}
+
+impl Config {
+    pub fn plugin(&self, name: &str) -> Option<&SourcePlugin> {
+        self.plugins.iter().find(|plugin| plugin.name == name)
+    }
+
+    // Helper function to check that DATA_DIR is a writable directory, or can
+    // be created as one, without creating it. A read-only mount then fails
+    // at startup rather than on the first write
+    pub fn check_data_dir(&self) -> Result<(), String> {
+        let dir = &self.data_dir;
+        // The directory itself or, when it doesn't exist yet, the closest
+        // parent it would be created in
+        let existing = dir
+            .ancestors()
+            .map(|path| {
+                if path.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    path
+                }
+            })
+            .find(|path| path.exists())
+            .unwrap_or(Path::new("."));
+        if !existing.is_dir() {
+            return Err(format!(
+                "DATA_DIR {} can't be created: {} is not a directory; {}",
+                dir.display(),
+                existing.display(),
+                DATA_DIR_HINT
+            ));
+        }
+        let probe = existing.join(format!(".write-check-{}", std::process::id()));
+        std::fs::write(&probe, b"ok").map_err(|e| {
+            format!(
+                "DATA_DIR {} is not writable: {}; {}",
+                dir.display(),
+                e,
+                DATA_DIR_HINT
+            )
+        })?;
+        let _ = std::fs::remove_file(&probe);
+        Ok(())
+    }
+
+    // Helper function to create DATA_DIR, once check_data_dir passed
+    pub fn create_data_dir(&self) -> Result<(), String> {
+        std::fs::create_dir_all(&self.data_dir).map_err(|e| {
+            format!(
+                "DATA_DIR {} can't be created: {}; {}",
+                self.data_dir.display(),
+                e,
+                DATA_DIR_HINT
+            )
+        })
+    }
+
+    pub fn snapshot_dir(&self) -> PathBuf {
+        self.data_dir.join("snapshots")
+    }
+
+    pub fn subscriptions_path(&self) -> PathBuf {
+        self.data_dir.join("subscriptions.json")
+    }
+
+    pub fn webhooks_path(&self) -> PathBuf {
+        self.data_dir.join("webhooks.json")
+    }
+
+    pub fn deliveries_path(&self) -> PathBuf {
+        self.data_dir.join("deliveries")
+    }
+
+    pub fn scrape_log_path(&self) -> PathBuf {
+        self.data_dir.join("scrape_log.json")
+    }
+
+    pub fn daily_stats_path(&self) -> PathBuf {
+        self.data_dir.join("daily_stats.json")
+    }
+
+    pub fn alerts_path(&self) -> PathBuf {
+        self.data_dir.join("alerts.json")
+    }
+
+    pub fn watches_path(&self) -> PathBuf {
+        self.data_dir.join("watches.json")
+    }
+
+    pub fn takedowns_path(&self) -> PathBuf {
+        self.data_dir.join("takedowns.json")
+    }
+
+    pub fn audit_log_path(&self) -> PathBuf {
+        self.data_dir.join("audit.jsonl")
+    }
+
+    // The email digest, and with it /api/subscriptions, is on when SMTP is configured
+    pub fn digest_enabled(&self) -> bool {
+        self.smtp_url.is_some()
+    }
+
+    // Helper function to build the configuration, starting from the defaults
+    // and overriding whatever is set in the environment
+    pub fn from_env() -> Result<Config, String> {
+        Config::from_lookup(&|name| std::env::var(name).ok())
+    }
+
+    // Like from_env, with the variables taken from `lookup`, such as a
+    // reload's .env read over the environment
+    pub fn from_lookup(lookup: &dyn Fn(&str) -> Option<String>) -> Result<Config, String> {
+        let defaults = Config::default();
+        let var = |name: &str| lookup(name).ok_or(std::env::VarError::NotPresent);
+
+        let unix_socket_path = var("UNIX_SOCKET_PATH")
+            .ok()
+            .filter(|value| !value.is_empty())
+            .map(PathBuf::from);
+
+        // Permissions are given in octal, like chmod (e.g. 660)
+        let unix_socket_mode = match var("UNIX_SOCKET_MODE") {
+            Ok(value) => Some(
+                u32::from_str_radix(&value, 8)
+                    .map_err(|e| format!("Invalid UNIX_SOCKET_MODE '{}': {}", value, e))?,
+            ),
+            Err(_) => defaults.unix_socket_mode,
+        };
+
+        // Hosts /api/scrape may fetch from; subdomains of each entry are allowed too
+        let scrape_allowed_hosts = match var("SCRAPE_ALLOWED_HOSTS") {
+            Ok(value) => parse_list(&value)
+                .into_iter()
+                .map(|host| host.trim_start_matches('.').to_ascii_lowercase())
+                .collect(),
+            Err(_) => defaults.scrape_allowed_hosts,
+        };
+
+        let digest_hour = parse_env(lookup, "DIGEST_HOUR", defaults.digest_hour)?;
+        if digest_hour > 23 {
+            return Err(format!(
+                "Invalid DIGEST_HOUR '{}': expected 0 to 23",
+                digest_hour
+            ));
+        }
+
+        let s3_export_hour = parse_env(lookup, "S3_EXPORT_HOUR", defaults.s3_export_hour)?;
+        if s3_export_hour > 23 {
+            return Err(format!(
+                "Invalid S3_EXPORT_HOUR '{}': expected 0 to 23",
+                s3_export_hour
+            ));
+        }
+
+        let stats_hour = parse_env(lookup, "STATS_HOUR", defaults.stats_hour)?;
+        if stats_hour > 23 {
+            return Err(format!(
+                "Invalid STATS_HOUR '{}': expected 0 to 23",
+                stats_hour
+            ));
+        }
+
+        let cluster_similarity =
+            parse_env(lookup, "CLUSTER_SIMILARITY", defaults.cluster_similarity)?;
+        if !(0.0..=1.0).contains(&cluster_similarity) {
+            return Err(format!(
+                "Invalid CLUSTER_SIMILARITY '{}': expected 0 to 1",
+                cluster_similarity
+            ));
+        }
+
+        // BIND_ADDR wins; otherwise PORT and IN_CONTAINER pick the address
+        let bind_addr = match parse_optional_env(lookup, "BIND_ADDR")? {
+            Some(bind_addr) => bind_addr,
+            None => default_bind_addr(
+                parse_bool_env(lookup, "IN_CONTAINER", false)?,
+                parse_optional_env(lookup, "PORT")?,
+            ),
+        };
+        // Links in emails must reach the server from outside, so by default
+        // they point at the bind address, or localhost when bound to all
+        // interfaces
+        let public_url = var("PUBLIC_URL")
+            .ok()
+            .filter(|value| !value.is_empty())
+            .unwrap_or_else(|| {
+                if bind_addr.ip().is_unspecified() {
+                    format!("http://localhost:{}", bind_addr.port())
+                } else {
+                    format!("http://{}", bind_addr)
+                }
+            });
+
+        let selector_sets = match optional_env(lookup, "SELECTOR_SETS_PATH") {
+            Some(path) => SelectorSets::load(Path::new(&path))
+                .map_err(|e| format!("Invalid SELECTOR_SETS_PATH '{}': {}", path, e))?,
+            None => defaults.selector_sets,
+        };
+
+        let gazetteer = match optional_env(lookup, "ENTITY_GAZETTEER_PATH") {
+            Some(path) => Gazetteer::load(Path::new(&path))
+                .map_err(|e| format!("Invalid ENTITY_GAZETTEER_PATH '{}': {}", path, e))?,
+            None => defaults.gazetteer,
+        };
+
+        // Entries override the default for the same route
+        let mut route_timeouts = defaults.route_timeouts;
+        if let Some(value) = optional_env(lookup, "ROUTE_TIMEOUTS") {
+            for entry in parse_list(&value) {
+                let (route, timeout) = entry
+                    .split_once('=')
+                    .ok_or(format!("Invalid ROUTE_TIMEOUTS entry '{}'", entry))?;
+                let timeout = scheduler::parse_interval(timeout)
+                    .map_err(|e| format!("Invalid ROUTE_TIMEOUTS entry '{}': {}", entry, e))?;
+                let route = route.trim().to_string();
+                route_timeouts.retain(|(existing, _)| *existing != route);
+                route_timeouts.push((route, timeout));
+            }
+        }
+
+        let mut scheduler_jobs = match var("SCHEDULER_JOBS") {
+            Ok(value) => parse_list(&value)
+                .iter()
+                .map(|job| {
+                    job.parse()
+                        .map_err(|e| format!("Invalid SCHEDULER_JOBS '{}': {}", value, e))
+                })
+                .collect::<Result<Vec<Job>, String>>()?,
+            Err(_) => defaults.scheduler_jobs,
+        };
+
+        // Sources defined in JSON, each scraped by a job of its own
+        let plugins = match optional_env(lookup, "SOURCE_PLUGINS_PATH") {
+            Some(path) => plugins::load(Path::new(&path))
+                .map_err(|e| format!("Invalid SOURCE_PLUGINS_PATH '{}': {}", path, e))?,
+            None => defaults.plugins,
+        };
+        for plugin in &plugins {
+            if scheduler_jobs.iter().any(|job| job.name == plugin.name) {
+                return Err(format!(
+                    "Plugin '{}' has the name of a job in SCHEDULER_JOBS",
+                    plugin.name
+                ));
+            }
+            scheduler_jobs.push(plugin.job()?);
+        }
+
+        // Monitor URLs, where {job} stands for the job's name. Failures go to
+        // the success URL with /fail appended, healthchecks.io style, unless
+        // HEARTBEAT_FAIL_URL says otherwise
+        let heartbeat_url = optional_env(lookup, "HEARTBEAT_URL");
+        let heartbeat_fail_url = optional_env(lookup, "HEARTBEAT_FAIL_URL").or(heartbeat_url
+            .as_ref()
+            .map(|url| format!("{}/fail", url.trim_end_matches('/'))));
+        for (name, url) in [
+            ("HEARTBEAT_URL", &heartbeat_url),
+            ("HEARTBEAT_FAIL_URL", &heartbeat_fail_url),
+        ] {
+            if let Some(url) = url {
+                Url::parse(&url.replace("{job}", "homepage"))
+                    .map_err(|e| format!("Invalid {} '{}': {}", name, url, e))?;
+            }
+        }
+
+        // Local edition URLs as city=url pairs, e.g. milano=https://milano.corriere.it/
+        let local_editions = match var("LOCAL_EDITIONS") {
+            Ok(value) => parse_url_pairs("LOCAL_EDITIONS", &value)?,
+            Err(_) => defaults.local_editions,
+        };
+
+        // Other outlets as name=url pairs, e.g. ansa=https://www.ansa.it/
+        let news_sources = match var("NEWS_SOURCES") {
+            Ok(value) => parse_url_pairs("NEWS_SOURCES", &value)?,
+            Err(_) => defaults.news_sources,
+        };
+
+        // Daily digest pages as name=url pairs, e.g.
+        // prima-ora=https://www.corriere.it/newsletter/prima-ora/
+        let newsletter_urls = match var("NEWSLETTER_URLS") {
+            Ok(value) => parse_url_pairs("NEWSLETTER_URLS", &value)?,
+            Err(_) => defaults.newsletter_urls,
+        };
+
+        // Origins as browsers send them, e.g. https://example.com
+        let cors_origins = match var("CORS_ORIGINS") {
+            Ok(value) => parse_list(&value)
+                .into_iter()
+                .map(|origin| {
+                    let url = Url::parse(&origin)
+                        .map_err(|e| format!("Invalid CORS_ORIGINS origin '{}': {}", origin, e))?;
+                    let serialized = url.origin().ascii_serialization();
+                    if serialized != origin.trim_end_matches('/') {
+                        return Err(format!(
+                            "Invalid CORS_ORIGINS origin '{}': expected scheme://host[:port]",
+                            origin
+                        ));
+                    }
+                    Ok(serialized)
+                })
+                .collect::<Result<Vec<String>, String>>()?,
+            Err(_) => defaults.cors_origins,
+        };
+
+        Ok(Config {
+            bind_addr,
+            unix_socket_path,
+            unix_socket_mode,
+            homepage_url: var("HOMEPAGE_URL").unwrap_or(defaults.homepage_url),
+            scrape_allowed_hosts,
+            scrape_allow_private_addresses: parse_bool_env(
+                lookup,
+                "SCRAPE_ALLOW_PRIVATE_ADDRESSES",
+                defaults.scrape_allow_private_addresses,
+            )?,
+            scrape_max_redirects: parse_env(
+                lookup,
+                "SCRAPE_MAX_REDIRECTS",
+                defaults.scrape_max_redirects,
+            )?,
+            scrape_max_bytes: parse_env(lookup, "SCRAPE_MAX_BYTES", defaults.scrape_max_bytes)?,
+            batch_max_urls: parse_env(lookup, "BATCH_MAX_URLS", defaults.batch_max_urls)?,
+            per_host_concurrency: parse_env(
+                lookup,
+                "PER_HOST_CONCURRENCY",
+                defaults.per_host_concurrency,
+            )?,
+            fetch_concurrency: parse_env(lookup, "FETCH_CONCURRENCY", defaults.fetch_concurrency)?,
+            fetch_queue_size: parse_env(lookup, "FETCH_QUEUE_SIZE", defaults.fetch_queue_size)?,
+            data_dir: parse_env(lookup, "DATA_DIR", defaults.data_dir)?,
+            static_out_dir: parse_env(lookup, "STATIC_OUT_DIR", defaults.static_out_dir)?,
+            snapshot_html: parse_bool_env(lookup, "SNAPSHOT_HTML", defaults.snapshot_html)?,
+            snapshot_max_files: parse_env(
+                lookup,
+                "SNAPSHOT_MAX_FILES",
+                defaults.snapshot_max_files,
+            )?,
+            snapshot_max_age_days: parse_env(
+                lookup,
+                "SNAPSHOT_MAX_AGE_DAYS",
+                defaults.snapshot_max_age_days,
+            )?,
+            breaker_failure_threshold: parse_env(
+                lookup,
+                "BREAKER_FAILURE_THRESHOLD",
+                defaults.breaker_failure_threshold,
+            )?,
+            breaker_cooldown_secs: parse_env(
+                lookup,
+                "BREAKER_COOLDOWN_SECS",
+                defaults.breaker_cooldown_secs,
+            )?,
+            cache_soft_ttl_secs: parse_env(
+                lookup,
+                "CACHE_SOFT_TTL_SECS",
+                defaults.cache_soft_ttl_secs,
+            )?,
+            cache_hard_ttl_secs: parse_env(
+                lookup,
+                "CACHE_HARD_TTL_SECS",
+                defaults.cache_hard_ttl_secs,
+            )?,
+            parse_mode: parse_env(lookup, "PARSE_MODE", defaults.parse_mode)?,
+            validation_mode: parse_env(lookup, "VALIDATION_MODE", defaults.validation_mode)?,
+            demo_mode: parse_env(lookup, "DEMO_MODE", defaults.demo_mode)?,
+            validation_domains: match var("VALIDATION_DOMAINS") {
+                Ok(value) => parse_list(&value.to_ascii_lowercase()),
+                Err(_) => defaults.validation_domains,
+            },
+            selector_sets,
+            gazetteer,
+            budget: Budget {
+                max_description_chars: parse_optional_env(lookup, "DESCRIPTION_MAX_CHARS")?,
+                max_items: parse_optional_env(lookup, "RESPONSE_MAX_ITEMS")?,
+                max_response_bytes: parse_optional_env(lookup, "RESPONSE_MAX_BYTES")?,
+            },
+            request_timeout_secs: parse_env(
+                lookup,
+                "REQUEST_TIMEOUT_SECS",
+                defaults.request_timeout_secs,
+            )?,
+            route_timeouts,
+            max_request_body_bytes: parse_env(
+                lookup,
+                "MAX_REQUEST_BODY_BYTES",
+                defaults.max_request_body_bytes,
+            )?,
+            cors_origins,
+            scoring: ScoreWeights {
+                prominence: parse_env(
+                    lookup,
+                    "SCORE_PROMINENCE_WEIGHT",
+                    defaults.scoring.prominence,
+                )?,
+                recency: parse_env(lookup, "SCORE_RECENCY_WEIGHT", defaults.scoring.recency)?,
+                categories: match optional_env(lookup, "SCORE_CATEGORY_WEIGHTS") {
+                    Some(value) => parse_weight_pairs("SCORE_CATEGORY_WEIGHTS", &value)?,
+                    None => defaults.scoring.categories,
+                },
+            },
+            selector_min_items: parse_env(
+                lookup,
+                "SELECTOR_MIN_ITEMS",
+                defaults.selector_min_items,
+            )?,
+            smtp_url: var("SMTP_URL").ok().filter(|value| !value.is_empty()),
+            digest_from: var("DIGEST_FROM").unwrap_or(defaults.digest_from),
+            digest_hour,
+            digest_time_zone: parse_env(lookup, "DIGEST_TIME_ZONE", defaults.digest_time_zone)?,
+            digest_top_n: parse_env(lookup, "DIGEST_TOP_N", defaults.digest_top_n)?,
+            tts_command: optional_env(lookup, "TTS_COMMAND"),
+            tts_api_url: optional_env(lookup, "TTS_API_URL"),
+            tts_api_key: optional_env(lookup, "TTS_API_KEY"),
+            tts_model: var("TTS_MODEL").unwrap_or(defaults.tts_model),
+            tts_voice: var("TTS_VOICE").unwrap_or(defaults.tts_voice),
+            briefing_top_n: parse_env(lookup, "BRIEFING_TOP_N", defaults.briefing_top_n)?,
+            briefing_max_episodes: parse_env(
+                lookup,
+                "BRIEFING_MAX_EPISODES",
+                defaults.briefing_max_episodes,
+            )?,
+            embedding_command: optional_env(lookup, "EMBEDDING_COMMAND"),
+            embedding_api_url: optional_env(lookup, "EMBEDDING_API_URL"),
+            embedding_api_key: optional_env(lookup, "EMBEDDING_API_KEY"),
+            embedding_model: var("EMBEDDING_MODEL").unwrap_or(defaults.embedding_model),
+            embedding_batch_size: parse_env(
+                lookup,
+                "EMBEDDING_BATCH_SIZE",
+                defaults.embedding_batch_size,
+            )?,
+            embedding_poll_secs: parse_env(
+                lookup,
+                "EMBEDDING_POLL_SECS",
+                defaults.embedding_poll_secs,
+            )?,
+            embedding_backfill_limit: parse_env(
+                lookup,
+                "EMBEDDING_BACKFILL_LIMIT",
+                defaults.embedding_backfill_limit,
+            )?,
+            semantic_queries_per_minute: parse_env(
+                lookup,
+                "SEMANTIC_QUERIES_PER_MINUTE",
+                defaults.semantic_queries_per_minute,
+            )?,
+            summary_api_url: optional_env(lookup, "SUMMARY_API_URL"),
+            summary_api_key: optional_env(lookup, "SUMMARY_API_KEY"),
+            summary_model: var("SUMMARY_MODEL").unwrap_or(defaults.summary_model),
+            edition_top_n: parse_env(lookup, "EDITION_TOP_N", defaults.edition_top_n)?,
+            edition_days: parse_env(lookup, "EDITION_DAYS", defaults.edition_days)?,
+            public_url: public_url.trim_end_matches('/').to_string(),
+            telegram_bot_token: var("TELEGRAM_BOT_TOKEN")
+                .ok()
+                .filter(|value| !value.is_empty()),
+            telegram_chat_ids: var("TELEGRAM_CHAT_IDS")
+                .map(|value| parse_list(&value))
+                .unwrap_or(defaults.telegram_chat_ids),
+            telegram_top_n: parse_env(lookup, "TELEGRAM_TOP_N", defaults.telegram_top_n)?,
+            telegram_poll_secs: parse_env(
+                lookup,
+                "TELEGRAM_POLL_SECS",
+                defaults.telegram_poll_secs,
+            )?,
+            telegram_api_url: var("TELEGRAM_API_URL")
+                .map(|value| value.trim_end_matches('/').to_string())
+                .unwrap_or(defaults.telegram_api_url),
+            mqtt_url: optional_env(lookup, "MQTT_URL"),
+            mqtt_client_id: var("MQTT_CLIENT_ID").unwrap_or(defaults.mqtt_client_id),
+            mqtt_topic: var("MQTT_TOPIC").unwrap_or(defaults.mqtt_topic),
+            mqtt_breaking_topic: var("MQTT_BREAKING_TOPIC").unwrap_or(defaults.mqtt_breaking_topic),
+            mqtt_qos: parse_env(lookup, "MQTT_QOS", defaults.mqtt_qos)?,
+            mqtt_retain: parse_bool_env(lookup, "MQTT_RETAIN", defaults.mqtt_retain)?,
+            mqtt_poll_secs: parse_env(lookup, "MQTT_POLL_SECS", defaults.mqtt_poll_secs)?,
+            // "\n" in the template stands for a line break, as .env values are single line
+            social_template: var("SOCIAL_TEMPLATE")
+                .map(|value| value.replace("\\n", "\n"))
+                .unwrap_or(defaults.social_template),
+            social_sections: var("SOCIAL_SECTIONS")
+                .map(|value| {
+                    parse_list(&value)
+                        .into_iter()
+                        .map(|section| section.to_ascii_lowercase())
+                        .collect()
+                })
+                .unwrap_or(defaults.social_sections),
+            social_top_n: parse_env(lookup, "SOCIAL_TOP_N", defaults.social_top_n)?,
+            social_poll_secs: parse_env(lookup, "SOCIAL_POLL_SECS", defaults.social_poll_secs)?,
+            mastodon_url: optional_env(lookup, "MASTODON_URL")
+                .map(|value| value.trim_end_matches('/').to_string()),
+            mastodon_token: optional_env(lookup, "MASTODON_TOKEN"),
+            mastodon_min_interval_secs: parse_env(
+                lookup,
+                "MASTODON_MIN_INTERVAL_SECS",
+                defaults.mastodon_min_interval_secs,
+            )?,
+            bluesky_handle: optional_env(lookup, "BLUESKY_HANDLE"),
+            bluesky_app_password: optional_env(lookup, "BLUESKY_APP_PASSWORD"),
+            bluesky_pds_url: var("BLUESKY_PDS_URL")
+                .map(|value| value.trim_end_matches('/').to_string())
+                .unwrap_or(defaults.bluesky_pds_url),
+            bluesky_min_interval_secs: parse_env(
+                lookup,
+                "BLUESKY_MIN_INTERVAL_SECS",
+                defaults.bluesky_min_interval_secs,
+            )?,
+            wallabag_url: optional_env(lookup, "WALLABAG_URL")
+                .map(|value| value.trim_end_matches('/').to_string()),
+            wallabag_client_id: optional_env(lookup, "WALLABAG_CLIENT_ID"),
+            wallabag_client_secret: optional_env(lookup, "WALLABAG_CLIENT_SECRET"),
+            wallabag_username: optional_env(lookup, "WALLABAG_USERNAME"),
+            wallabag_password: optional_env(lookup, "WALLABAG_PASSWORD"),
+            pocket_consumer_key: optional_env(lookup, "POCKET_CONSUMER_KEY"),
+            pocket_access_token: optional_env(lookup, "POCKET_ACCESS_TOKEN"),
+            pocket_api_url: var("POCKET_API_URL")
+                .map(|value| value.trim_end_matches('/').to_string())
+                .unwrap_or(defaults.pocket_api_url),
+            read_later_sections: var("READ_LATER_SECTIONS")
+                .map(|value| {
+                    parse_list(&value)
+                        .into_iter()
+                        .map(|section| section.to_ascii_lowercase())
+                        .collect()
+                })
+                .unwrap_or(defaults.read_later_sections),
+            read_later_poll_secs: parse_env(
+                lookup,
+                "READ_LATER_POLL_SECS",
+                defaults.read_later_poll_secs,
+            )?,
+            seen_filter_capacity: parse_env(
+                lookup,
+                "SEEN_FILTER_CAPACITY",
+                defaults.seen_filter_capacity,
+            )?,
+            seen_filter_fp_rate: parse_env(
+                lookup,
+                "SEEN_FILTER_FP_RATE",
+                defaults.seen_filter_fp_rate,
+            )?,
+            seen_filter_checkpoint_secs: parse_env(
+                lookup,
+                "SEEN_FILTER_CHECKPOINT_SECS",
+                defaults.seen_filter_checkpoint_secs,
+            )?,
+            delivery_max_attempts: parse_env(
+                lookup,
+                "DELIVERY_MAX_ATTEMPTS",
+                defaults.delivery_max_attempts,
+            )?,
+            delivery_backoff_secs: parse_env(
+                lookup,
+                "DELIVERY_BACKOFF_SECS",
+                defaults.delivery_backoff_secs,
+            )?,
+            delivery_max_backoff_secs: parse_env(
+                lookup,
+                "DELIVERY_MAX_BACKOFF_SECS",
+                defaults.delivery_max_backoff_secs,
+            )?,
+            delivery_retry_secs: parse_env(
+                lookup,
+                "DELIVERY_RETRY_SECS",
+                defaults.delivery_retry_secs,
+            )?,
+            webhooks_enabled: parse_bool_env(
+                lookup,
+                "WEBHOOKS_ENABLED",
+                defaults.webhooks_enabled,
+            )?,
+            webhook_poll_secs: parse_env(lookup, "WEBHOOK_POLL_SECS", defaults.webhook_poll_secs)?,
+            watch_enabled: parse_bool_env(lookup, "WATCH_ENABLED", defaults.watch_enabled)?,
+            watch_poll_secs: parse_env(lookup, "WATCH_POLL_SECS", defaults.watch_poll_secs)?,
+            watch_max: parse_env(lookup, "WATCH_MAX", defaults.watch_max)?,
+            alert_api_keys: var("ALERT_API_KEYS")
+                .map(|value| parse_list(&value))
+                .unwrap_or(defaults.alert_api_keys),
+            alert_max_per_key: parse_env(lookup, "ALERT_MAX_PER_KEY", defaults.alert_max_per_key)?,
+            alert_poll_secs: parse_env(lookup, "ALERT_POLL_SECS", defaults.alert_poll_secs)?,
+            stats_hour,
+            stats_backfill_days: parse_env(
+                lookup,
+                "STATS_BACKFILL_DAYS",
+                defaults.stats_backfill_days,
+            )?,
+            paywall_link_patterns: var("PAYWALL_LINK_PATTERNS")
+                .map(|value| parse_list(&value))
+                .unwrap_or(defaults.paywall_link_patterns),
+            scheduler_jobs,
+            plugins,
+            local_editions,
+            news_sources,
+            cluster_similarity,
+            scheduler_jitter_pct: parse_env(
+                lookup,
+                "SCHEDULER_JITTER_PCT",
+                defaults.scheduler_jitter_pct,
+            )?,
+            heartbeat_url,
+            heartbeat_fail_url,
+            heartbeat_jobs: var("HEARTBEAT_JOBS")
+                .map(|value| {
+                    parse_list(&value)
+                        .into_iter()
+                        .map(|job| job.to_ascii_lowercase())
+                        .collect()
+                })
+                .unwrap_or(defaults.heartbeat_jobs),
+            admin_token: optional_env(lookup, "ADMIN_TOKEN"),
+            admin_tokens: match var("ADMIN_TOKENS") {
+                Ok(value) => parse_token_pairs("ADMIN_TOKENS", &value)?,
+                Err(_) => defaults.admin_tokens,
+            },
+            audit_log: parse_bool_env(lookup, "AUDIT_LOG", defaults.audit_log)?,
+            preferences_secret: optional_env(lookup, "PREFERENCES_SECRET"),
+            newsletter_urls,
+            newsletter_cache_secs: parse_env(
+                lookup,
+                "NEWSLETTER_CACHE_SECS",
+                defaults.newsletter_cache_secs,
+            )?,
+            lock_url: optional_env(lookup, "LOCK_URL"),
+            archive_url: optional_env(lookup, "ARCHIVE_URL"),
+            s3_bucket: optional_env(lookup, "S3_BUCKET"),
+            s3_endpoint: var("S3_ENDPOINT").unwrap_or(defaults.s3_endpoint),
+            s3_region: var("S3_REGION").unwrap_or(defaults.s3_region),
+            s3_access_key_id: optional_env(lookup, "S3_ACCESS_KEY_ID"),
+            s3_secret_access_key: optional_env(lookup, "S3_SECRET_ACCESS_KEY"),
+            s3_path_style: parse_bool_env(lookup, "S3_PATH_STYLE", defaults.s3_path_style)?,
+            s3_key_template: var("S3_KEY_TEMPLATE").unwrap_or(defaults.s3_key_template),
+            s3_export_snapshots: parse_bool_env(
+                lookup,
+                "S3_EXPORT_SNAPSHOTS",
+                defaults.s3_export_snapshots,
+            )?,
+            s3_export_hour,
+            s3_export_format: parse_env(lookup, "S3_EXPORT_FORMAT", defaults.s3_export_format)?,
+        })
+    }
+}
+
+// Helper function to read and parse a variable, falling back to a default when unset
+fn parse_env<T>(
+    lookup: &dyn Fn(&str) -> Option<String>,
+    name: &str,
+    default: T,
+) -> Result<T, String>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match lookup(name) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .map_err(|e| format!("Invalid {} '{}': {}", name, value, e)),
+        None => Ok(default),
+    }
+}
+
+// Helper function to read a variable that is unset when missing or empty
+fn optional_env(lookup: &dyn Fn(&str) -> Option<String>, name: &str) -> Option<String> {
+    lookup(name).filter(|value| !value.is_empty())
+}
+
+// Helper function to read a variable without a default, such as a limit
+// that doesn't apply when unset
+fn parse_optional_env<T>(
+    lookup: &dyn Fn(&str) -> Option<String>,
+    name: &str,
+) -> Result<Option<T>, String>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    optional_env(lookup, name)
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .map_err(|e| format!("Invalid {} '{}': {}", name, value, e))
+        })
+        .transpose()
+}
+
+// Helper function to read a boolean variable such as SNAPSHOT_HTML=true
+fn parse_bool_env(
+    lookup: &dyn Fn(&str) -> Option<String>,
+    name: &str,
+    default: bool,
+) -> Result<bool, String> {
+    match lookup(name) {
+        Some(value) => match value.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Ok(true),
+            "0" | "false" | "no" | "off" | "" => Ok(false),
+            _ => Err(format!(
+                "Invalid {} '{}': expected true or false",
+                name, value
+            )),
+        },
+        None => Ok(default),
+    }
+}
+
+// Helper function to split a comma separated value, dropping empty entries
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// Helper function to parse comma separated name=url pairs, with names lowercased
+fn parse_url_pairs(name: &str, value: &str) -> Result<Vec<(String, String)>, String> {
+    parse_list(value)
+        .iter()
+        .map(|entry| {
+            let (key, url) = entry
+                .split_once('=')
+                .ok_or(format!("Invalid {} entry '{}'", name, entry))?;
+            reqwest::Url::parse(url.trim())
+                .map_err(|e| format!("Invalid {} URL '{}': {}", name, url, e))?;
+            Ok((key.trim().to_ascii_lowercase(), url.trim().to_string()))
+        })
+        .collect()
+}
+
+// Helper function to parse comma separated name=token pairs, with names
+// lowercased, e.g. alice=s3cret,ci=t0ken
+fn parse_token_pairs(name: &str, value: &str) -> Result<Vec<(String, String)>, String> {
+    parse_list(value)
+        .iter()
+        .map(|entry| {
+            let (key, token) = entry
+                .split_once('=')
+                .ok_or(format!("Invalid {} entry '{}'", name, entry))?;
+            let (key, token) = (key.trim().to_ascii_lowercase(), token.trim());
+            if key.is_empty() || token.is_empty() {
+                return Err(format!("Invalid {} entry '{}'", name, entry));
+            }
+            Ok((key, token.to_string()))
+        })
+        .collect()
+}
+
+// Helper function to parse comma separated name=weight pairs, with names
+// lowercased, e.g. politica=10,sport=-5
+fn parse_weight_pairs(name: &str, value: &str) -> Result<Vec<(String, f64)>, String> {
+    parse_list(value)
+        .iter()
+        .map(|entry| {
+            let (key, weight) = entry
+                .split_once('=')
+                .ok_or(format!("Invalid {} entry '{}'", name, entry))?;
+            let weight = weight
+                .trim()
+                .parse()
+                .map_err(|e| format!("Invalid {} weight '{}': {}", name, weight, e))?;
+            Ok((key.trim().to_ascii_lowercase(), weight))
+        })
+        .collect()
+}
+
+// Helper function to pick the bind address when BIND_ADDR is unset: port
+// PORT (3000 by default), on all interfaces in a container, where loopback
+// can't be reached from outside, and on 127.0.0.1 otherwise
+pub fn default_bind_addr(in_container: bool, port: Option<u16>) -> SocketAddr {
+    let ip = if in_container {
+        [0, 0, 0, 0]
+    } else {
+        [127, 0, 0, 1]
+    };
+    SocketAddr::from((ip, port.unwrap_or(3000)))
+}
@@ -0,0 +1,147 @@
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, SecondsFormat, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::problem::ParameterError;
+
+const ITALIAN_WEEKDAYS: [&str; 7] = [
+    "lunedì",
+    "martedì",
+    "mercoledì",
+    "giovedì",
+    "venerdì",
+    "sabato",
+    "domenica",
+];
+const ITALIAN_MONTHS: [&str; 12] = [
+    "gennaio",
+    "febbraio",
+    "marzo",
+    "aprile",
+    "maggio",
+    "giugno",
+    "luglio",
+    "agosto",
+    "settembre",
+    "ottobre",
+    "novembre",
+    "dicembre",
+];
+
+// Helper function to read the publication day from a corriere.it path
+// segment such as "24_maggio_01"
+pub fn parse_path_date(segment: &str) -> Option<NaiveDate> {
+    let mut parts = segment.split('_');
+    let year = parts.next().filter(|year| year.len() == 2)?;
+    let year: i32 = year.parse().ok()?;
+    let month = parts.next()?;
+    let day: u32 = parts.next()?.parse().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    let month = ITALIAN_MONTHS.iter().position(|name| *name == month)? as u32 + 1;
+    NaiveDate::from_ymd_opt(2000 + year, month, day)
+}
+
+// The ?tz= and ?locale= query parameters
+#[derive(Deserialize)]
+pub struct DateParams {
+    pub tz: Option<String>,
+    pub locale: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Locale {
+    Italian,
+}
+
+// How timestamps are presented in a response. Without a time zone they stay
+// in UTC; with a locale every timestamp gets a readable "<name>_display"
+// companion for clients that show dates as they are
+#[derive(Clone, Copy, Default)]
+pub struct DateFormat {
+    pub tz: Option<Tz>,
+    pub locale: Option<Locale>,
+}
+
+impl DateFormat {
+    // Helper function to resolve the tz and locale query parameters
+    pub fn parse(tz: Option<&str>, locale: Option<&str>) -> Result<DateFormat, ParameterError> {
+        let tz = match tz {
+            Some(tz) => Some(tz.parse::<Tz>().map_err(|_| {
+                ParameterError::new(
+                    "tz",
+                    format!("Unknown time zone '{}', expected e.g. Europe/Rome", tz),
+                )
+            })?),
+            None => None,
+        };
+        let locale = match locale.map(|locale| locale.to_ascii_lowercase().replace('_', "-")) {
+            Some(locale) if locale == "it" || locale == "it-it" => Some(Locale::Italian),
+            Some(_) => {
+                return Err(ParameterError::new(
+                    "locale",
+                    format!(
+                        "Unsupported locale '{}', expected: it",
+                        locale.unwrap_or_default()
+                    ),
+                ))
+            }
+            None => None,
+        };
+        Ok(DateFormat { tz, locale })
+    }
+
+    pub fn localize(&self, date: DateTime<Utc>) -> DateTime<FixedOffset> {
+        match self.tz {
+            Some(tz) => date.with_timezone(&tz).fixed_offset(),
+            None => date.fixed_offset(),
+        }
+    }
+
+    // Date and time spelled out for the locale, e.g. "giovedì 15 ottobre 2026, 09:30"
+    pub fn display(&self, date: DateTime<Utc>) -> Option<String> {
+        let day = self.display_day(date)?;
+        let date = self.localize(date);
+        Some(format!("{}, {:02}:{:02}", day, date.hour(), date.minute()))
+    }
+
+    // Just the day, e.g. "giovedì 15 ottobre 2026"
+    pub fn display_day(&self, date: DateTime<Utc>) -> Option<String> {
+        let date = self.localize(date);
+        match self.locale? {
+            Locale::Italian => Some(format!(
+                "{} {} {} {}",
+                ITALIAN_WEEKDAYS[date.weekday().num_days_from_monday() as usize],
+                date.day(),
+                ITALIAN_MONTHS[date.month0() as usize],
+                date.year()
+            )),
+        }
+    }
+
+    // Rewrites the serialized timestamp `name` of a JSON object for this
+    // format, adding its display string when a locale was requested.
+    // Missing and null timestamps are left alone
+    pub fn apply(&self, object: &mut Map<String, Value>, name: &str) {
+        let Some(date) = object
+            .get(name)
+            .and_then(Value::as_str)
+            .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+            .map(|date| date.with_timezone(&Utc))
+        else {
+            return;
+        };
+
+        if self.tz.is_some() {
+            let localized = self
+                .localize(date)
+                .to_rfc3339_opts(SecondsFormat::AutoSi, true);
+            object.insert(name.to_string(), Value::String(localized));
+        }
+        if let Some(display) = self.display(date) {
+            object.insert(format!("{}_display", name), Value::String(display));
+        }
+    }
+}
@@ -0,0 +1,537 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::digest::Mailer;
+use crate::json_file;
+use crate::lease;
+use crate::metrics::Metrics;
+use crate::request_id;
+use crate::subscriptions::random_string;
+use crate::url_safety::{self, UrlPolicy};
+use crate::AppState;
+
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+// Telegram allows about 20 messages a minute into the same group or channel
+pub const TELEGRAM_CHAT_INTERVAL: Duration = Duration::from_secs(3);
+
+// A notification with everything needed to send it again later
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Message {
+    Webhook {
+        url: String,
+        request_id: String,
+        payload: Value,
+    },
+    // A Bot API call; the bot token is taken from the config when sending,
+    // so it never ends up in DATA_DIR
+    Telegram {
+        chat_id: String,
+        method: String,
+        body: Value,
+    },
+    Email {
+        to: String,
+        subject: String,
+        text: String,
+        html: String,
+        // Sent as List-Unsubscribe; missing from messages queued before it
+        #[serde(default)]
+        unsubscribe_url: Option<String>,
+    },
+}
+
+impl Message {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Message::Webhook { .. } => "webhook",
+            Message::Telegram { .. } => "telegram",
+            Message::Email { .. } => "email",
+        }
+    }
+
+    // Where the message goes, for log lines
+    pub fn target(&self) -> &str {
+        match self {
+            Message::Webhook { url, .. } => url,
+            Message::Telegram { chat_id, .. } => chat_id,
+            Message::Email { to, .. } => to,
+        }
+    }
+}
+
+// The subscription a message went out for. It's checked again before every
+// retry, so nothing is sent after an unsubscribe or a deletion
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", content = "id", rename_all = "lowercase")]
+pub enum Origin {
+    Digest(String),
+    Webhook(String),
+    Alert(String),
+}
+
+// A message whose delivery failed, waiting for its next attempt. Once
+// DELIVERY_MAX_ATTEMPTS are used up it is dead: kept for inspection but not
+// retried until requeued
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Delivery {
+    pub id: String,
+    pub message: Message,
+    // None for Telegram, whose chats are checked against TELEGRAM_CHAT_IDS,
+    // and for messages queued before origins were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<Origin>,
+    pub attempts: u32,
+    pub created_at: DateTime<Utc>,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: String,
+    pub dead: bool,
+}
+
+// Failed notifications, persisted under DATA_DIR so retries survive a
+// restart: one JSON file per delivery, each replaced on its own, so an
+// attempt never rewrites the rest of the queue. Attempts are spaced by
+// DELIVERY_BACKOFF_SECS, doubling each time up to DELIVERY_MAX_BACKOFF_SECS
+pub struct DeliveryQueue {
+    dir: PathBuf,
+    max_attempts: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+    metrics: Arc<Metrics>,
+    lock: Mutex<()>,
+}
+
+impl DeliveryQueue {
+    pub fn new(
+        dir: PathBuf,
+        max_attempts: u32,
+        backoff: Duration,
+        max_backoff: Duration,
+        metrics: Arc<Metrics>,
+    ) -> DeliveryQueue {
+        DeliveryQueue {
+            dir,
+            max_attempts: max_attempts.max(1),
+            backoff,
+            max_backoff,
+            metrics,
+            lock: Mutex::new(()),
+        }
+    }
+
+    // Wait before the attempt following the `attempts`th
+    pub fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+
+    // Helper function to queue a message whose first attempt just failed
+    pub fn enqueue(
+        &self,
+        message: Message,
+        origin: Option<Origin>,
+        error_message: &str,
+    ) -> Result<Delivery, String> {
+        let _lock = self.lock.lock().unwrap();
+
+        let now = Utc::now();
+        let mut delivery = Delivery {
+            id: random_string(12),
+            message,
+            origin,
+            attempts: 0,
+            created_at: now,
+            next_attempt_at: now,
+            last_error: String::new(),
+            dead: false,
+        };
+        self.record_failure(&mut delivery, error_message);
+
+        self.save(&delivery)?;
+        self.update_gauges()?;
+        Ok(delivery)
+    }
+
+    // Pending and dead deliveries, oldest first
+    pub fn list(&self) -> Result<Vec<Delivery>, String> {
+        let _lock = self.lock.lock().unwrap();
+        self.load()
+    }
+
+    // Deliveries due for another attempt at `now`
+    pub fn due(&self, now: DateTime<Utc>) -> Result<Vec<Delivery>, String> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .filter(|delivery| !delivery.dead && delivery.next_attempt_at <= now)
+            .collect())
+    }
+
+    // Helper function to record the outcome of a retry: a delivered message
+    // leaves the queue, a failed one waits longer or dies. Returns the
+    // delivery as it is now, None when delivered or no longer queued
+    pub fn record(&self, id: &str, result: Result<(), String>) -> Result<Option<Delivery>, String> {
+        let _lock = self.lock.lock().unwrap();
+        let Some(mut delivery) = self.load_one(id)? else {
+            return Ok(None);
+        };
+        let kind = delivery.message.kind();
+        let updated = match result {
+            Ok(()) => {
+                self.remove(id)?;
+                self.metrics.increment(
+                    "corriere_delivery_retries_total",
+                    &[("kind", kind), ("result", "sent")],
+                );
+                None
+            }
+            Err(error_message) => {
+                self.metrics.increment(
+                    "corriere_delivery_retries_total",
+                    &[("kind", kind), ("result", "failed")],
+                );
+                self.record_failure(&mut delivery, &error_message);
+                self.save(&delivery)?;
+                Some(delivery)
+            }
+        };
+
+        self.update_gauges()?;
+        Ok(updated)
+    }
+
+    // Helper function to make a delivery due right away with a fresh set of
+    // attempts, dead or not
+    pub fn requeue(&self, id: &str) -> Result<Option<Delivery>, String> {
+        let _lock = self.lock.lock().unwrap();
+        let Some(mut delivery) = self.load_one(id)? else {
+            return Ok(None);
+        };
+        delivery.attempts = 0;
+        delivery.dead = false;
+        delivery.next_attempt_at = Utc::now();
+
+        self.save(&delivery)?;
+        self.update_gauges()?;
+        Ok(Some(delivery))
+    }
+
+    // Returns whether a delivery was removed
+    pub fn delete(&self, id: &str) -> Result<bool, String> {
+        let _lock = self.lock.lock().unwrap();
+        if self.load_one(id)?.is_none() {
+            return Ok(false);
+        }
+        self.remove(id)?;
+        self.update_gauges()?;
+        Ok(true)
+    }
+
+    fn record_failure(&self, delivery: &mut Delivery, error_message: &str) {
+        delivery.attempts += 1;
+        delivery.last_error = error_message.to_string();
+        if delivery.attempts >= self.max_attempts {
+            delivery.dead = true;
+            eprintln!(
+                "Giving up on {} delivery {} to {} after {} attempts: {}",
+                delivery.message.kind(),
+                delivery.id,
+                delivery.message.target(),
+                delivery.attempts,
+                error_message
+            );
+            self.metrics.increment(
+                "corriere_delivery_dead_letters_total",
+                &[("kind", delivery.message.kind())],
+            );
+            return;
+        }
+        let wait = chrono::Duration::from_std(self.backoff(delivery.attempts))
+            .unwrap_or(chrono::Duration::MAX);
+        delivery.next_attempt_at = Utc::now()
+            .checked_add_signed(wait)
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+    }
+
+    // File of the delivery `id`; None for ids that can't be ours, which keeps
+    // ids from the admin API from naming other paths
+    fn path(&self, id: &str) -> Option<PathBuf> {
+        let valid = !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric());
+        valid.then(|| self.dir.join(format!("{}.json", id)))
+    }
+
+    fn load_one(&self, id: &str) -> Result<Option<Delivery>, String> {
+        self.migrate()?;
+        match self.path(id) {
+            Some(path) => json_file::load(&path),
+            None => Ok(None),
+        }
+    }
+
+    fn load(&self) -> Result<Vec<Delivery>, String> {
+        self.migrate()?;
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(format!("Failed to read {}: {}", self.dir.display(), e)),
+        };
+        let mut deliveries = Vec::new();
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                deliveries.extend(json_file::load::<Delivery>(&path)?);
+            }
+        }
+        deliveries.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
+        Ok(deliveries)
+    }
+
+    fn save(&self, delivery: &Delivery) -> Result<(), String> {
+        let path = self
+            .path(&delivery.id)
+            .ok_or_else(|| format!("Invalid delivery id '{}'", delivery.id))?;
+        json_file::save(&path, delivery)
+    }
+
+    fn remove(&self, id: &str) -> Result<(), String> {
+        let Some(path) = self.path(id) else {
+            return Ok(());
+        };
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!("Failed to remove {}: {}", path.display(), e)),
+        }
+    }
+
+    // Helper function to move the deliveries of a queue kept in a single
+    // file, as it used to be, to their own files
+    fn migrate(&self) -> Result<(), String> {
+        let legacy = self.dir.with_extension("json");
+        let Some(deliveries) = json_file::load::<Vec<Delivery>>(&legacy)? else {
+            return Ok(());
+        };
+        for delivery in &deliveries {
+            self.save(delivery)?;
+        }
+        std::fs::remove_file(&legacy)
+            .map_err(|e| format!("Failed to remove {}: {}", legacy.display(), e))
+    }
+
+    fn update_gauges(&self) -> Result<(), String> {
+        let deliveries = self.load()?;
+        let dead = deliveries.iter().filter(|delivery| delivery.dead).count();
+        for (state, count) in [("pending", deliveries.len() - dead), ("dead", dead)] {
+            self.metrics.set_gauge(
+                "corriere_delivery_queue_depth",
+                &[("state", state)],
+                count as f64,
+            );
+        }
+        Ok(())
+    }
+}
+
+// Spaces out the messages to each Telegram chat, shared by the notifier and
+// the retries of queued messages so together they stay within the limit
+pub struct ChatSpacing {
+    interval: Duration,
+    next: Mutex<HashMap<String, Instant>>,
+}
+
+impl ChatSpacing {
+    pub fn new(interval: Duration) -> ChatSpacing {
+        ChatSpacing {
+            interval,
+            next: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Helper function to wait for the chat's next free slot, taking it
+    pub async fn wait(&self, chat_id: &str) {
+        let slot = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let slot = next
+                .get(chat_id)
+                .copied()
+                .filter(|slot| *slot > now)
+                .unwrap_or(now);
+            next.insert(chat_id.to_string(), slot + self.interval);
+            slot
+        };
+        tokio::time::sleep_until(slot.into()).await;
+    }
+}
+
+// Sends queued messages again: webhooks and Telegram over HTTP, emails
+// through the digest's SMTP connection
+pub struct Sender {
+    client: reqwest::Client,
+    telegram_url: Option<String>,
+    mailer: Option<Mailer>,
+}
+
+impl Sender {
+    pub fn new(config: &Config, mailer: Option<Mailer>) -> Result<Sender, String> {
+        Ok(Sender {
+            client: url_safety::build_callback_client(
+                &UrlPolicy::from_config(config),
+                SEND_TIMEOUT,
+            ),
+            telegram_url: config
+                .telegram_bot_token
+                .as_ref()
+                .map(|token| format!("{}/bot{}", config.telegram_api_url, token)),
+            mailer,
+        })
+    }
+
+    pub async fn send(&self, message: &Message) -> Result<(), String> {
+        match message {
+            Message::Webhook {
+                url,
+                request_id,
+                payload,
+            } => self
+                .client
+                .post(url)
+                .header(request_id::HEADER, request_id)
+                .json(payload)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map(|_| ())
+                .map_err(|e| format!("Webhook delivery failed: {}", e)),
+            Message::Telegram { method, body, .. } => {
+                let bot_url = self
+                    .telegram_url
+                    .as_ref()
+                    .ok_or("TELEGRAM_BOT_TOKEN is not set")?;
+                let response: Value = self
+                    .client
+                    .post(format!("{}/{}", bot_url, method))
+                    .json(body)
+                    .send()
+                    .await
+                    .map_err(|e| format!("Failed to reach Telegram: {}", e))?
+                    .json()
+                    .await
+                    .map_err(|e| format!("Invalid Telegram response: {}", e))?;
+                if response["ok"] == true {
+                    return Ok(());
+                }
+                Err(response["description"]
+                    .as_str()
+                    .unwrap_or("Telegram refused the message")
+                    .to_string())
+            }
+            Message::Email {
+                to,
+                subject,
+                text,
+                html,
+                unsubscribe_url,
+            } => {
+                let mailer = self.mailer.as_ref().ok_or("SMTP_URL is not set")?;
+                mailer
+                    .send(
+                        to,
+                        subject,
+                        text.clone(),
+                        html.clone(),
+                        unsubscribe_url.as_deref(),
+                    )
+                    .await
+            }
+        }
+    }
+}
+
+// Helper function to queue a failed notification, logging rather than
+// failing the notifier when the queue can't be written
+pub fn enqueue(state: &AppState, message: Message, origin: Option<Origin>, error_message: &str) {
+    let kind = message.kind();
+    if let Err(queue_error) = state.deliveries.enqueue(message, origin, error_message) {
+        eprintln!("Failed to queue {} delivery: {}", kind, queue_error);
+    }
+}
+
+// Helper function to attempt every delivery that is due. Returns how many
+// went through
+pub async fn retry_due(state: &AppState, sender: &Sender) -> Result<usize, String> {
+    let mut delivered = 0;
+    for delivery in state.deliveries.due(Utc::now())? {
+        if !still_wanted(state, &delivery)? {
+            state.deliveries.delete(&delivery.id)?;
+            state.metrics.increment(
+                "corriere_delivery_retries_total",
+                &[("kind", delivery.message.kind()), ("result", "dropped")],
+            );
+            continue;
+        }
+        if let Message::Telegram { chat_id, .. } = &delivery.message {
+            state.telegram_spacing.wait(chat_id).await;
+        }
+        let result = sender.send(&delivery.message).await;
+        if result.is_ok() {
+            delivered += 1;
+        }
+        state.deliveries.record(&delivery.id, result)?;
+    }
+    Ok(delivered)
+}
+
+// Helper function to tell whether the subscription a delivery went out for
+// is still there, and to the same address
+fn still_wanted(state: &AppState, delivery: &Delivery) -> Result<bool, String> {
+    let target = delivery.message.target();
+    Ok(match &delivery.origin {
+        Some(Origin::Digest(id)) => state
+            .subscriptions
+            .list()?
+            .iter()
+            .any(|subscription| subscription.id == *id && subscription.email == target),
+        Some(Origin::Webhook(id)) => state
+            .webhooks
+            .list()?
+            .iter()
+            .any(|webhook| webhook.id == *id && webhook.url == target),
+        Some(Origin::Alert(id)) => state.alerts.list()?.iter().any(|alert| alert.id == *id),
+        None => match &delivery.message {
+            Message::Telegram { chat_id, .. } => state.config.telegram_chat_ids.contains(chat_id),
+            _ => true,
+        },
+    })
+}
+
+// Retries due deliveries every DELIVERY_RETRY_SECS, for as long as the
+// server runs
+pub async fn run(state: AppState, sender: Sender) {
+    let every = Duration::from_secs(state.config.delivery_retry_secs.max(1));
+    let mut interval = tokio::time::interval(every);
+
+    loop {
+        interval.tick().await;
+        // Settings changed by a reload apply from this run on
+        let state = state.current();
+        if !lease::should_run(&state, "deliveries", lease::lease_ttl(every)).await {
+            continue;
+        }
+
+        match retry_due(&state, &sender).await {
+            Ok(0) => {}
+            Ok(delivered) => println!("Delivered {} queued notifications", delivered),
+            Err(error_message) => eprintln!("Delivery retries failed: {}", error_message),
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use axum::http::{header, HeaderName, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+
+use crate::newsletter::{self, Digest};
+use crate::{NewsItem, NewsResponse};
+
+// The homepage the golden tests run against (tests/golden/homepage.json is
+// its extraction), bundled so the server can run without reaching upstream
+const SAMPLE_HOMEPAGE: &str = include_str!("../tests/fixtures/homepage.html");
+// A Prima Ora issue, standing in for every digest
+const SAMPLE_DIGEST: &str = include_str!("../tests/fixtures/prima_ora.html");
+
+// Header set on v1 responses made of sample data, whose shape can't gain a
+// demo field; v2 responses have one
+pub const DEMO_HEADER: &str = "x-demo";
+
+// When the bundled sample homepage is served instead of a scrape
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DemoMode {
+    Off,
+    // Only while upstream fails and there is no earlier scrape to fall back to
+    Fallback,
+    // Always, without contacting upstream; for frontend development
+    Always,
+}
+
+impl std::str::FromStr for DemoMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "off" => Ok(DemoMode::Off),
+            "fallback" => Ok(DemoMode::Fallback),
+            "always" => Ok(DemoMode::Always),
+            _ => Err("expected off, fallback or always".to_string()),
+        }
+    }
+}
+
+impl DemoMode {
+    // Helper function for endpoints serving data of their own: whether the
+    // sample stands in, given whether real data is at hand
+    pub fn stands_in(self, available: bool) -> bool {
+        match self {
+            DemoMode::Off => false,
+            DemoMode::Fallback => !available,
+            DemoMode::Always => true,
+        }
+    }
+}
+
+// Helper function to flag a response made of sample data, so it's neither
+// mistaken for news nor cached as such
+pub fn flagged(response: impl IntoResponse) -> Response {
+    let mut response = response.into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        HeaderName::from_static(DEMO_HEADER),
+        HeaderValue::from_static("true"),
+    );
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    response
+}
+
+// Helper function to extract the items of the sample homepage, the same way
+// a live scrape would
+pub fn sample_news() -> Vec<NewsItem> {
+    corriere_core::extract_from_html(SAMPLE_HOMEPAGE)
+}
+
+// Helper function to build a homepage response from the sample data, dated
+// now and flagged as a demo
+pub fn sample_response() -> NewsResponse {
+    NewsResponse {
+        scraped_at: Utc::now(),
+        news: sample_news(),
+        error: None,
+        stale: false,
+        provenance: None,
+        demo: true,
+        snapshot: false,
+    }
+}
+
+// Helper function to build a digest from the sample issue, under the name
+// and URL asked for
+pub fn sample_digest(name: &str, url: &str) -> Result<Digest, String> {
+    let mut digest = newsletter::extract_digest(SAMPLE_DIGEST, name, url)?;
+    digest.demo = true;
+    Ok(digest)
+}
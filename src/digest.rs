@@ -0,0 +1,244 @@
+use askama::Template;
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+use crate::config::Config;
+use crate::dates::{DateFormat, Locale};
+use crate::delivery;
+use crate::lease;
+use crate::subscriptions::Subscription;
+use crate::{AppState, NewsItem};
+
+#[derive(Template)]
+#[template(path = "digest.html")]
+struct DigestTemplate<'a> {
+    date: &'a str,
+    news: &'a [NewsItem],
+    unsubscribe_url: &'a str,
+}
+
+// SMTP connection and sender for the daily digest
+#[derive(Clone)]
+pub struct Mailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl Mailer {
+    // Helper function to build the mailer, or None when no SMTP_URL is set
+    pub fn from_config(config: &Config) -> Result<Option<Mailer>, String> {
+        let Some(smtp_url) = &config.smtp_url else {
+            return Ok(None);
+        };
+
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(smtp_url)
+            .map_err(|e| format!("Invalid SMTP_URL: {}", e))?
+            .build();
+        let from = config
+            .digest_from
+            .parse()
+            .map_err(|e| format!("Invalid DIGEST_FROM '{}': {}", config.digest_from, e))?;
+
+        Ok(Some(Mailer { transport, from }))
+    }
+
+    // Helper function to build an email. With `unsubscribe_url`, mail
+    // clients get List-Unsubscribe headers and may post to it in one click
+    pub fn message(
+        &self,
+        to: &str,
+        subject: &str,
+        text: String,
+        html: String,
+        unsubscribe_url: Option<&str>,
+    ) -> Result<Message, String> {
+        let to: Mailbox = to
+            .parse()
+            .map_err(|e| format!("Invalid recipient '{}': {}", to, e))?;
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(subject);
+        if let Some(url) = unsubscribe_url {
+            builder = builder
+                .raw_header(HeaderValue::new(
+                    HeaderName::new_from_ascii_str("List-Unsubscribe"),
+                    format!("<{}>", url),
+                ))
+                .raw_header(HeaderValue::new(
+                    HeaderName::new_from_ascii_str("List-Unsubscribe-Post"),
+                    "List-Unsubscribe=One-Click".to_string(),
+                ));
+        }
+        builder
+            .multipart(MultiPart::alternative_plain_html(text, html))
+            .map_err(|e| format!("Failed to build email: {}", e))
+    }
+
+    pub async fn send(
+        &self,
+        to: &str,
+        subject: &str,
+        text: String,
+        html: String,
+        unsubscribe_url: Option<&str>,
+    ) -> Result<(), String> {
+        let message = self.message(to, subject, text, html, unsubscribe_url)?;
+        self.transport
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|e| format!("Failed to send email: {}", e))
+    }
+}
+
+// Helper function to render the digest for one subscriber, as plain text
+// and HTML
+pub fn render(
+    date: &str,
+    news: &[NewsItem],
+    unsubscribe_url: &str,
+) -> Result<(String, String), String> {
+    let html = DigestTemplate {
+        date,
+        news,
+        unsubscribe_url,
+    }
+    .render()
+    .map_err(|e| format!("Failed to render digest: {}", e))?;
+
+    let mut text = format!("Le notizie del {}\n\n", date);
+    for item in news {
+        text.push_str(&format!("- {}\n  {}\n", item.title, item.link));
+    }
+    text.push_str(&format!(
+        "\nPer annullare l'iscrizione: {}\n",
+        unsubscribe_url
+    ));
+
+    Ok((text, html))
+}
+
+pub fn unsubscribe_url(config: &Config, subscription: &Subscription) -> String {
+    format!(
+        "{}/api/subscriptions/{}/unsubscribe?token={}",
+        config.public_url, subscription.id, subscription.token
+    )
+}
+
+// Helper function to send the digest with the current top headlines to every
+// subscriber. Returns how many emails were sent; failures for single
+// subscribers are logged and don't stop the others
+pub async fn send_digest(state: &AppState, mailer: &Mailer) -> Result<usize, String> {
+    let subscriptions = state.subscriptions.list()?;
+    if subscriptions.is_empty() {
+        return Ok(0);
+    }
+
+    let news = crate::get_news(state)
+        .await
+        .map_err(|response| response.error.unwrap_or_default())?
+        .response
+        .news;
+    if news.is_empty() {
+        return Err("No headlines to send".to_string());
+    }
+    let news = &news[..news.len().min(state.config.digest_top_n)];
+
+    let dates = DateFormat {
+        tz: Some(state.config.digest_time_zone),
+        locale: Some(Locale::Italian),
+    };
+    let date = dates.display_day(Utc::now()).unwrap_or_default();
+    let subject = format!("Corriere della Sera: le notizie del {}", date);
+
+    let mut sent = 0;
+    for subscription in &subscriptions {
+        let unsubscribe_url = unsubscribe_url(&state.config, subscription);
+        let (text, html) = render(&date, news, &unsubscribe_url)?;
+        match mailer
+            .send(
+                &subscription.email,
+                &subject,
+                text.clone(),
+                html.clone(),
+                Some(&unsubscribe_url),
+            )
+            .await
+        {
+            Ok(()) => {
+                sent += 1;
+                state
+                    .metrics
+                    .increment("corriere_digest_emails_total", &[("result", "sent")]);
+            }
+            Err(error_message) => {
+                eprintln!(
+                    "Digest for {} failed: {}",
+                    subscription.email, error_message
+                );
+                state
+                    .metrics
+                    .increment("corriere_digest_emails_total", &[("result", "failed")]);
+                let message = delivery::Message::Email {
+                    to: subscription.email.clone(),
+                    subject: subject.clone(),
+                    text,
+                    html,
+                    unsubscribe_url: Some(unsubscribe_url),
+                };
+                let origin = Some(delivery::Origin::Digest(subscription.id.clone()));
+                delivery::enqueue(state, message, origin, &error_message);
+            }
+        }
+    }
+    Ok(sent)
+}
+
+// Next time the clock reads `hour`:00 in `tz`, strictly after `now`. On days
+// where that hour is skipped by a DST change, the digest goes out an hour later
+pub fn next_run(now: DateTime<Utc>, hour: u32, tz: Tz) -> DateTime<Utc> {
+    let today = now.with_timezone(&tz).date_naive();
+    let at = NaiveTime::from_hms_opt(hour, 0, 0).unwrap_or(NaiveTime::MIN);
+
+    for days in 0..3 {
+        let day = today + Duration::days(days);
+        let local = tz
+            .from_local_datetime(&day.and_time(at))
+            .earliest()
+            .or_else(|| {
+                tz.from_local_datetime(&(day.and_time(at) + Duration::hours(1)))
+                    .earliest()
+            });
+        if let Some(run) = local.map(|local| local.with_timezone(&Utc)) {
+            if run > now {
+                return run;
+            }
+        }
+    }
+    now + Duration::days(1)
+}
+
+// Sends the digest every day at DIGEST_HOUR, for as long as the server runs
+pub async fn run_scheduler(state: AppState, mailer: Mailer) {
+    loop {
+        // Settings changed by a reload apply from this run on
+        let state = state.current();
+        let now = Utc::now();
+        let run_at = next_run(now, state.config.digest_hour, state.config.digest_time_zone);
+        let wait = (run_at - now).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+        // Every replica wakes up at the same time; one of them sends
+        if !lease::should_run(&state, "digest", std::time::Duration::from_secs(3600)).await {
+            continue;
+        }
+
+        match send_digest(&state, &mailer).await {
+            Ok(sent) => println!("Sent the daily digest to {} subscribers", sent),
+            Err(error_message) => eprintln!("Daily digest failed: {}", error_message),
+        }
+    }
+}
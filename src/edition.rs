@@ -0,0 +1,468 @@
+use askama::Template;
+use axum::extract::{Path as UrlPath, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, SecondsFormat, TimeZone, Utc};
+use std::collections::BTreeSet;
+use std::io::{Cursor, Write};
+use std::path::PathBuf;
+use tokio::task::JoinSet;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::article;
+use crate::config::Config;
+use crate::dates::{DateFormat, Locale};
+use crate::fetch_queue::{self, Priority};
+use crate::url_safety::{self, UrlPolicy};
+use crate::{json_file, AppState, NewsItem};
+
+// Images bigger than this are left out of the book
+const MAX_IMAGE_BYTES: usize = 2 * 1024 * 1024;
+// Today's edition is rebuilt once it is this old; past days never change
+const TODAY_MAX_AGE_SECS: i64 = 3600;
+const STYLE: &str = "body { font-family: serif; line-height: 1.5; }\n\
+    h1 { font-size: 1.4em; }\n\
+    .subtitle { font-style: italic; }\n\
+    .author, .source { font-size: 0.85em; }\n\
+    .image img { max-width: 100%; }\n";
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+pub struct ChapterImage {
+    pub file: String,
+    pub media_type: &'static str,
+    pub alt: String,
+    data: Vec<u8>,
+}
+
+// One article of the edition
+pub struct Chapter {
+    pub number: usize,
+    pub title: String,
+    pub subtitle: Option<String>,
+    pub author: Option<String>,
+    pub link: String,
+    pub paragraphs: Vec<String>,
+    pub image: Option<ChapterImage>,
+}
+
+#[derive(Template)]
+#[template(path = "edition/content.opf", escape = "html")]
+struct PackageTemplate<'a> {
+    identifier: &'a str,
+    title: &'a str,
+    date: &'a str,
+    modified: &'a str,
+    chapters: &'a [Chapter],
+}
+
+#[derive(Template)]
+#[template(path = "edition/nav.xhtml", escape = "html")]
+struct NavTemplate<'a> {
+    title: &'a str,
+    chapters: &'a [Chapter],
+}
+
+#[derive(Template)]
+#[template(path = "edition/chapter.xhtml", escape = "html")]
+struct ChapterTemplate<'a> {
+    chapter: &'a Chapter,
+}
+
+pub struct CatalogEntry {
+    pub date: String,
+    pub title: String,
+    pub updated: String,
+}
+
+#[derive(Template)]
+#[template(path = "edition/opds.xml", escape = "html")]
+struct CatalogTemplate<'a> {
+    public_url: &'a str,
+    updated: &'a str,
+    editions: &'a [CatalogEntry],
+}
+
+fn editions_dir(config: &Config) -> PathBuf {
+    config.data_dir.join("editions")
+}
+
+fn title(day: NaiveDate) -> String {
+    let dates = DateFormat {
+        tz: None,
+        locale: Some(Locale::Italian),
+    };
+    let day = dates
+        .display_day(day.and_time(NaiveTime::MIN).and_utc())
+        .unwrap_or_default();
+    format!("Corriere della Sera – {}", day)
+}
+
+// Helper function to find the UTC span of a calendar day in DIGEST_TIME_ZONE
+pub fn day_span(config: &Config, day: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+    let start = |day: NaiveDate| {
+        config
+            .digest_time_zone
+            .from_local_datetime(&day.and_time(NaiveTime::MIN))
+            .earliest()
+            .map(|start| start.with_timezone(&Utc))
+            .unwrap_or_else(|| day.and_time(NaiveTime::MIN).and_utc())
+    };
+    (start(day), start(day + Duration::days(1)))
+}
+
+pub fn today(config: &Config) -> NaiveDate {
+    Utc::now()
+        .with_timezone(&config.digest_time_zone)
+        .date_naive()
+}
+
+// Helper function to pick the day's articles: the live homepage for today,
+// the last archived scrape of the day otherwise. Errors come with the status
+// to answer with: 404 when there is no edition, 502 when it can't be made
+async fn day_news(state: &AppState, day: NaiveDate) -> Result<Vec<NewsItem>, (StatusCode, String)> {
+    let top_n = state.config.edition_top_n;
+    if day == today(&state.config) {
+        let news = crate::get_news(state)
+            .await
+            .map_err(|response| (StatusCode::BAD_GATEWAY, response.error.unwrap_or_default()))?
+            .response
+            .news;
+        return Ok(news.into_iter().take(top_n).collect());
+    }
+
+    let not_found = |error_message: &str| (StatusCode::NOT_FOUND, error_message.to_string());
+    let archive = state
+        .archive
+        .as_ref()
+        .ok_or_else(|| not_found("No edition for that day, past editions need ARCHIVE_URL"))?;
+    let (from, to) = day_span(&state.config, day);
+    let scrapes = archive
+        .scrapes(from, to)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+    let last = scrapes
+        .into_iter()
+        .last()
+        .ok_or_else(|| not_found("No edition for that day"))?;
+    Ok(last.news.into_iter().take(top_n).collect())
+}
+
+// Helper function to download an image for the book through the fetch
+// queue, if it is one, like fetch_queue::fetch_page: with the scrape client,
+// within the host's PER_HOST_CONCURRENCY limit. Reading stops once it grows
+// past MAX_IMAGE_BYTES
+async fn fetch_image(state: &AppState, url: &str) -> Option<(&'static str, Vec<u8>)> {
+    let parsed = url_safety::check(&UrlPolicy::from_config(&state.config), url).ok()?;
+    let permit = state
+        .host_limiter
+        .acquire(parsed.host_str().unwrap_or_default())
+        .await;
+    let client = state.scrape_client.clone();
+    let fetch = async move {
+        let _permit = permit;
+        let mut response = client
+            .get(parsed)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to fetch image: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Image returned HTTP {}", response.status()));
+        }
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let media_type = ["image/jpeg", "image/png", "image/gif", "image/webp"]
+            .into_iter()
+            .find(|media_type| content_type.starts_with(media_type))
+            .ok_or("Not an image")?;
+        let too_large = || format!("Image is larger than {} bytes", MAX_IMAGE_BYTES);
+        if response
+            .content_length()
+            .is_some_and(|len| len > MAX_IMAGE_BYTES as u64)
+        {
+            return Err(too_large());
+        }
+        let mut data = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| format!("Failed to read image: {}", e))?
+        {
+            if data.len() + chunk.len() > MAX_IMAGE_BYTES {
+                return Err(too_large());
+            }
+            data.extend_from_slice(&chunk);
+        }
+        Ok((media_type, data))
+    };
+    state.fetches.run(fetch).await.ok()
+}
+
+// Helper function to turn a homepage item into a chapter, with the full
+// article body when the article can be fetched and the summary otherwise
+async fn chapter(state: AppState, number: usize, item: NewsItem) -> Chapter {
+    let article = article::fetch_article(&state, &item.link).await.ok();
+    let paragraphs = match &article {
+        Some(article) if !article.body.is_empty() => {
+            article.body.split("\n\n").map(str::to_string).collect()
+        }
+        _ => vec![item.description.clone()],
+    };
+    let image_url = article
+        .as_ref()
+        .and_then(|article| article.image_url.clone())
+        .or(item.image_url.clone());
+
+    let mut image = None;
+    if let Some(url) = image_url {
+        if let Some((media_type, data)) = fetch_image(&state, &url).await {
+            let extension = media_type
+                .trim_start_matches("image/")
+                .replace("jpeg", "jpg");
+            image = Some(ChapterImage {
+                file: format!("images/{}.{}", number, extension),
+                media_type,
+                alt: item.title.clone(),
+                data,
+            });
+        }
+    }
+
+    Chapter {
+        number,
+        title: article
+            .as_ref()
+            .map(|article| article.title.clone())
+            .unwrap_or(item.title),
+        subtitle: article
+            .as_ref()
+            .and_then(|article| article.subtitle.clone())
+            .or((!item.description.is_empty()).then_some(item.description)),
+        author: article.and_then(|article| article.author),
+        link: item.link,
+        paragraphs,
+        image,
+    }
+}
+
+// Helper function to package the chapters as an EPUB 3 book
+pub fn epub(day: NaiveDate, chapters: &[Chapter]) -> Result<Vec<u8>, String> {
+    let title = title(day);
+    let date = day.format("%Y-%m-%d").to_string();
+    let modified = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let render_error = |e: askama::Error| format!("Failed to render edition: {}", e);
+    let package = PackageTemplate {
+        identifier: &format!("urn:corriere-scraper:edition:{}", date),
+        title: &title,
+        date: &date,
+        modified: &modified,
+        chapters,
+    }
+    .render()
+    .map_err(render_error)?;
+    let nav = NavTemplate {
+        title: &title,
+        chapters,
+    }
+    .render()
+    .map_err(render_error)?;
+
+    let mut files = vec![
+        (
+            "META-INF/container.xml".to_string(),
+            CONTAINER_XML.as_bytes().to_vec(),
+        ),
+        ("OEBPS/content.opf".to_string(), package.into_bytes()),
+        ("OEBPS/nav.xhtml".to_string(), nav.into_bytes()),
+        ("OEBPS/style.css".to_string(), STYLE.as_bytes().to_vec()),
+    ];
+    for chapter in chapters {
+        let page = ChapterTemplate { chapter }.render().map_err(render_error)?;
+        files.push((
+            format!("OEBPS/chapter-{}.xhtml", chapter.number),
+            page.into_bytes(),
+        ));
+        if let Some(image) = &chapter.image {
+            files.push((format!("OEBPS/{}", image.file), image.data.clone()));
+        }
+    }
+
+    let zip_error = |e: zip::result::ZipError| format!("Failed to write EPUB: {}", e);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    // The mimetype entry must come first and be stored uncompressed
+    zip.start_file(
+        "mimetype",
+        SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+    )
+    .map_err(zip_error)?;
+    zip.write_all(b"application/epub+zip")
+        .map_err(|e| format!("Failed to write EPUB: {}", e))?;
+    for (name, data) in files {
+        zip.start_file(name, SimpleFileOptions::default())
+            .map_err(zip_error)?;
+        zip.write_all(&data)
+            .map_err(|e| format!("Failed to write EPUB: {}", e))?;
+    }
+    Ok(zip.finish().map_err(zip_error)?.into_inner())
+}
+
+// Helper function to get the EPUB of a day, building and storing it when
+// there is none yet (or today's is over an hour old). Errors come with their
+// status, as from day_news
+pub async fn edition(state: &AppState, day: NaiveDate) -> Result<Vec<u8>, (StatusCode, String)> {
+    let path = editions_dir(&state.config).join(format!("{}.epub", day.format("%Y-%m-%d")));
+    let fresh = std::fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| {
+            let age = Utc::now() - DateTime::<Utc>::from(modified);
+            day != today(&state.config) || age < Duration::seconds(TODAY_MAX_AGE_SECS)
+        });
+    if fresh {
+        if let Ok(book) = std::fs::read(&path) {
+            return Ok(book);
+        }
+    }
+
+    let failed = |error_message: String| (StatusCode::INTERNAL_SERVER_ERROR, error_message);
+    let news = day_news(state, day).await?;
+    let mut tasks = JoinSet::new();
+    for (index, item) in news.into_iter().enumerate() {
+        // Nobody is waiting on a single article, so the homepage goes first
+        tasks.spawn(fetch_queue::scope(
+            Priority::Backfill,
+            chapter(state.clone(), index + 1, item),
+        ));
+    }
+    let mut chapters = Vec::new();
+    while let Some(joined) = tasks.join_next().await {
+        chapters.push(joined.map_err(|e| failed(format!("Edition task failed: {}", e)))?);
+    }
+    chapters.sort_by_key(|chapter| chapter.number);
+
+    let book = epub(day, &chapters).map_err(failed)?;
+    std::fs::create_dir_all(editions_dir(&state.config))
+        .map_err(|e| failed(format!("Failed to create editions directory: {}", e)))?;
+    json_file::write_atomic(&path, &book).map_err(failed)?;
+    Ok(book)
+}
+
+// The day's top articles as an EPUB, for "today" or a date such as 2026-10-14
+pub async fn epub_handler(
+    State(state): State<AppState>,
+    UrlPath(file): UrlPath<String>,
+) -> Response {
+    let Some(name) = file.strip_suffix(".epub") else {
+        return (StatusCode::NOT_FOUND, "Editions are served as .epub").into_response();
+    };
+    let day = match name {
+        "today" => today(&state.config),
+        _ => match NaiveDate::parse_from_str(name, "%Y-%m-%d") {
+            Ok(day) => day,
+            Err(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    format!("Invalid edition '{}', expected today or YYYY-MM-DD", name),
+                )
+                    .into_response()
+            }
+        },
+    };
+    if day > today(&state.config) {
+        return (StatusCode::NOT_FOUND, "No edition for that day").into_response();
+    }
+
+    match edition(&state, day).await {
+        Ok(book) => (
+            [
+                (header::CONTENT_TYPE, "application/epub+zip".to_string()),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("attachment; filename=\"corriere-{}.epub\"", day),
+                ),
+            ],
+            book,
+        )
+            .into_response(),
+        Err((status, error_message)) => (status, error_message).into_response(),
+    }
+}
+
+// OPDS acquisition feed of the editions of the last EDITION_DAYS days:
+// today, days with a stored EPUB and days the archive has scrapes for
+pub async fn catalog_handler(State(state): State<AppState>) -> Response {
+    let config = &state.config;
+    let today = today(config);
+    let first = today - Duration::days(config.edition_days.saturating_sub(1) as i64);
+
+    let mut days = BTreeSet::from([today]);
+    if let Ok(entries) = std::fs::read_dir(editions_dir(config)) {
+        days.extend(entries.filter_map(|entry| {
+            let name = entry
+                .ok()?
+                .file_name()
+                .to_str()?
+                .strip_suffix(".epub")?
+                .to_string();
+            NaiveDate::parse_from_str(&name, "%Y-%m-%d").ok()
+        }));
+    }
+    if let Some(archive) = &state.archive {
+        let (from, _) = day_span(config, first);
+        let (_, to) = day_span(config, today);
+        match archive.scrapes(from, to).await {
+            Ok(scrapes) => days.extend(scrapes.iter().map(|scrape| {
+                scrape
+                    .scraped_at
+                    .with_timezone(&config.digest_time_zone)
+                    .date_naive()
+            })),
+            Err(error_message) => eprintln!("Failed to list archived editions: {}", error_message),
+        }
+    }
+
+    let updated = Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true);
+    let editions: Vec<CatalogEntry> = days
+        .into_iter()
+        .rev()
+        .filter(|day| *day >= first)
+        .map(|day| CatalogEntry {
+            date: day.format("%Y-%m-%d").to_string(),
+            title: title(day),
+            updated: day_span(config, day)
+                .0
+                .to_rfc3339_opts(SecondsFormat::Secs, true),
+        })
+        .collect();
+
+    match (CatalogTemplate {
+        public_url: &config.public_url,
+        updated: &updated,
+        editions: &editions,
+    })
+    .render()
+    {
+        Ok(feed) => (
+            [(
+                header::CONTENT_TYPE,
+                "application/atom+xml;profile=opds-catalog;kind=acquisition",
+            )],
+            feed,
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to render catalog: {}", e),
+        )
+            .into_response(),
+    }
+}
@@ -0,0 +1,366 @@
+use axum::extract::{Path as UrlPath, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::archive::SearchQuery;
+use crate::{json_file, AppState};
+
+const DEFAULT_NEWS_LIMIT: usize = 50;
+const MAX_NEWS_LIMIT: usize = 200;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Person,
+    Place,
+    Organization,
+}
+
+// A person, place or organization named in an item
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct Entity {
+    pub name: String,
+    pub kind: Kind,
+}
+
+// A gazetteer entry: the canonical name and the other ways headlines write
+// it, e.g. just the surname
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GazetteerEntry {
+    pub name: String,
+    pub kind: Kind,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+// Built-in names, as (name, kind, aliases). Ambiguous surnames (Conte, Sala)
+// are only matched in full
+const BUILT_IN: &[(&str, Kind, &[&str])] = &[
+    ("Giorgia Meloni", Kind::Person, &["Meloni"]),
+    ("Sergio Mattarella", Kind::Person, &["Mattarella"]),
+    ("Elly Schlein", Kind::Person, &["Schlein"]),
+    ("Matteo Salvini", Kind::Person, &["Salvini"]),
+    ("Antonio Tajani", Kind::Person, &["Tajani"]),
+    ("Giuseppe Conte", Kind::Person, &[]),
+    ("Matteo Renzi", Kind::Person, &["Renzi"]),
+    ("Carlo Calenda", Kind::Person, &["Calenda"]),
+    ("Giancarlo Giorgetti", Kind::Person, &["Giorgetti"]),
+    ("Guido Crosetto", Kind::Person, &["Crosetto"]),
+    ("Carlo Nordio", Kind::Person, &["Nordio"]),
+    ("Matteo Piantedosi", Kind::Person, &["Piantedosi"]),
+    ("Giuseppe Sala", Kind::Person, &["Beppe Sala"]),
+    ("Roberto Gualtieri", Kind::Person, &["Gualtieri"]),
+    ("Papa Leone XIV", Kind::Person, &["Leone XIV", "Papa Leone"]),
+    ("Ursula von der Leyen", Kind::Person, &["von der Leyen"]),
+    ("Donald Trump", Kind::Person, &["Trump"]),
+    ("Vladimir Putin", Kind::Person, &["Putin"]),
+    ("Volodymyr Zelensky", Kind::Person, &["Zelensky"]),
+    ("Emmanuel Macron", Kind::Person, &["Macron"]),
+    ("Benjamin Netanyahu", Kind::Person, &["Netanyahu"]),
+    ("Italia", Kind::Place, &[]),
+    ("Roma", Kind::Place, &[]),
+    ("Milano", Kind::Place, &[]),
+    ("Napoli", Kind::Place, &[]),
+    ("Torino", Kind::Place, &[]),
+    ("Firenze", Kind::Place, &[]),
+    ("Bologna", Kind::Place, &[]),
+    ("Venezia", Kind::Place, &[]),
+    ("Genova", Kind::Place, &[]),
+    ("Palermo", Kind::Place, &[]),
+    ("Bari", Kind::Place, &[]),
+    ("Bruxelles", Kind::Place, &[]),
+    ("Kiev", Kind::Place, &["Kyiv"]),
+    ("Stati Uniti", Kind::Place, &["Usa"]),
+    ("Ucraina", Kind::Place, &[]),
+    ("Russia", Kind::Place, &[]),
+    ("Cina", Kind::Place, &[]),
+    ("Israele", Kind::Place, &[]),
+    ("Gaza", Kind::Place, &[]),
+    ("Francia", Kind::Place, &[]),
+    ("Germania", Kind::Place, &[]),
+    (
+        "Unione europea",
+        Kind::Organization,
+        &["Ue", "Unione Europea"],
+    ),
+    (
+        "Commissione europea",
+        Kind::Organization,
+        &["Commissione Ue"],
+    ),
+    ("Banca centrale europea", Kind::Organization, &["Bce"]),
+    ("Banca d'Italia", Kind::Organization, &["Bankitalia"]),
+    ("Nato", Kind::Organization, &[]),
+    ("Onu", Kind::Organization, &["Nazioni Unite"]),
+    ("Fratelli d'Italia", Kind::Organization, &["FdI"]),
+    (
+        "Partito democratico",
+        Kind::Organization,
+        &["Pd", "Partito Democratico"],
+    ),
+    ("Lega", Kind::Organization, &[]),
+    ("Forza Italia", Kind::Organization, &[]),
+    (
+        "Movimento 5 Stelle",
+        Kind::Organization,
+        &["M5S", "5 Stelle"],
+    ),
+    ("Stellantis", Kind::Organization, &[]),
+    ("Eni", Kind::Organization, &[]),
+    ("Enel", Kind::Organization, &[]),
+    ("UniCredit", Kind::Organization, &["Unicredit"]),
+    ("Intesa Sanpaolo", Kind::Organization, &[]),
+    ("Juventus", Kind::Organization, &["Juve"]),
+    ("Inter", Kind::Organization, &[]),
+    ("Milan", Kind::Organization, &[]),
+];
+
+// Names to look for in titles and texts: the built-in list plus
+// ENTITY_GAZETTEER_PATH
+#[derive(Clone, Debug)]
+pub struct Gazetteer {
+    pub entries: Vec<GazetteerEntry>,
+}
+
+impl Default for Gazetteer {
+    fn default() -> Gazetteer {
+        Gazetteer {
+            entries: BUILT_IN
+                .iter()
+                .map(|(name, kind, aliases)| GazetteerEntry {
+                    name: name.to_string(),
+                    kind: *kind,
+                    aliases: aliases.iter().map(|alias| alias.to_string()).collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Gazetteer {
+    // Helper function to add the entries of a JSON file to the built-in ones.
+    // An entry with a built-in name replaces it
+    pub fn load(path: &Path) -> Result<Gazetteer, String> {
+        let extra: Vec<GazetteerEntry> =
+            json_file::load(path)?.ok_or(format!("{} does not exist", path.display()))?;
+        let mut gazetteer = Gazetteer::default();
+        for entry in extra {
+            if entry.name.trim().is_empty() {
+                return Err("Gazetteer entries need a name".to_string());
+            }
+            gazetteer.entries.retain(|known| known.name != entry.name);
+            gazetteer.entries.push(entry);
+        }
+        Ok(gazetteer)
+    }
+
+    // Helper function to find the entry a name or alias refers to, ignoring
+    // case and accepting dashes for spaces, e.g. "giorgia-meloni"
+    pub fn find(&self, name: &str) -> Option<&GazetteerEntry> {
+        let wanted = normalize(name).to_lowercase().replace('-', " ");
+        self.entries.iter().find(|entry| {
+            std::iter::once(&entry.name)
+                .chain(&entry.aliases)
+                .any(|form| normalize(form).to_lowercase() == wanted)
+        })
+    }
+
+    // Helper function to list the entities named in a text, in order of
+    // appearance. Names are matched as whole words and with their case, so
+    // "Lega" the party isn't "lega" the verb; where names overlap the
+    // longest wins, so "Forza Italia" isn't also "Italia"
+    pub fn extract(&self, text: &str) -> Vec<Entity> {
+        let text = normalize(text);
+        let mut matches: Vec<(usize, usize, &GazetteerEntry)> = Vec::new();
+        for entry in &self.entries {
+            for form in std::iter::once(&entry.name).chain(&entry.aliases) {
+                let form = normalize(form);
+                if form.is_empty() {
+                    continue;
+                }
+                for (start, _) in text.match_indices(form.as_str()) {
+                    let end = start + form.len();
+                    let before = text[..start].chars().next_back();
+                    let after = text[end..].chars().next();
+                    if !before.is_some_and(char::is_alphanumeric)
+                        && !after.is_some_and(char::is_alphanumeric)
+                    {
+                        matches.push((start, end, entry));
+                    }
+                }
+            }
+        }
+
+        matches.sort_by_key(|(start, end, _)| (std::cmp::Reverse(end - start), *start));
+        let mut taken: Vec<(usize, usize, &GazetteerEntry)> = Vec::new();
+        for candidate in matches {
+            if taken
+                .iter()
+                .all(|(start, end, _)| candidate.1 <= *start || candidate.0 >= *end)
+            {
+                taken.push(candidate);
+            }
+        }
+        taken.sort_by_key(|(start, _, _)| *start);
+
+        let mut seen = HashSet::new();
+        taken
+            .into_iter()
+            .filter(|(_, _, entry)| seen.insert(entry.name.as_str()))
+            .map(|(_, _, entry)| Entity {
+                name: entry.name.clone(),
+                kind: entry.kind,
+            })
+            .collect()
+    }
+
+    // Whether a text names the entry, by any of its forms
+    pub fn mentions(&self, text: &str, name: &str) -> bool {
+        self.extract(text).iter().any(|entity| entity.name == name)
+    }
+}
+
+// Typographic apostrophes are common in headlines ("Banca d’Italia")
+fn normalize(text: &str) -> String {
+    text.replace('’', "'")
+}
+
+#[derive(Deserialize)]
+pub struct EntityNewsParams {
+    limit: Option<usize>,
+}
+
+// An item naming the entity, either on the homepage now or in the archive
+#[derive(Serialize, Clone, Debug)]
+pub struct EntityNewsItem {
+    pub link: String,
+    pub title: String,
+    pub description: String,
+    pub image_url: Option<String>,
+    pub last_seen_at: DateTime<Utc>,
+    pub on_homepage: bool,
+}
+
+#[derive(Serialize)]
+pub struct EntityNewsResponse {
+    pub entity: Option<Entity>,
+    pub news: Vec<EntityNewsItem>,
+    pub error: Option<String>,
+}
+
+fn entity_error(
+    status: StatusCode,
+    error_message: String,
+) -> (StatusCode, Json<EntityNewsResponse>) {
+    (
+        status,
+        Json(EntityNewsResponse {
+            entity: None,
+            news: vec![],
+            error: Some(error_message),
+        }),
+    )
+}
+
+// Coverage of an entity, most recent first: the homepage items naming it,
+// then the archived ones when ARCHIVE_URL is set
+pub async fn news_handler(
+    State(state): State<AppState>,
+    UrlPath(name): UrlPath<String>,
+    Query(params): Query<EntityNewsParams>,
+) -> (StatusCode, Json<EntityNewsResponse>) {
+    let gazetteer = &state.config.gazetteer;
+    let Some(entry) = gazetteer.find(&name) else {
+        return entity_error(
+            StatusCode::NOT_FOUND,
+            format!("'{}' is not in the gazetteer", name),
+        );
+    };
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_NEWS_LIMIT)
+        .clamp(1, MAX_NEWS_LIMIT);
+    let mentions = |title: &str, description: &str| {
+        gazetteer.mentions(&format!("{}\n{}", title, description), &entry.name)
+    };
+
+    let mut news = Vec::new();
+    let mut links = HashSet::new();
+    if let Ok(cached) = crate::get_news(&state).await {
+        let scraped_at = cached.response.scraped_at;
+        for item in cached.response.news {
+            if mentions(&item.title, &item.description) && links.insert(item.link.clone()) {
+                news.push(EntityNewsItem {
+                    link: item.link,
+                    title: item.title,
+                    description: item.description,
+                    image_url: item.image_url,
+                    last_seen_at: scraped_at,
+                    on_homepage: true,
+                });
+            }
+        }
+    }
+
+    if let Some(archive) = &state.archive {
+        // The archive matches words without case, so every form is searched
+        // and the results checked again
+        let mut archived = Vec::new();
+        for form in std::iter::once(&entry.name).chain(&entry.aliases) {
+            let query = SearchQuery {
+                text: Some(form.clone()),
+                limit,
+                ..SearchQuery::default()
+            };
+            match archive.search(&query).await {
+                Ok(articles) => archived.extend(articles),
+                Err(error_message) => {
+                    return entity_error(StatusCode::INTERNAL_SERVER_ERROR, error_message)
+                }
+            }
+        }
+        archived.sort_by_key(|article| std::cmp::Reverse(article.last_seen_at));
+        for article in archived {
+            if mentions(&article.title, &article.description) && links.insert(article.link.clone())
+            {
+                news.push(EntityNewsItem {
+                    link: article.link,
+                    title: article.title,
+                    description: article.description,
+                    image_url: article.image_url,
+                    last_seen_at: article.last_seen_at,
+                    on_homepage: false,
+                });
+            }
+        }
+    }
+    news.truncate(limit);
+
+    state.metrics.increment(
+        "corriere_entity_lookups_total",
+        &[("kind", kind_label(entry.kind))],
+    );
+    (
+        StatusCode::OK,
+        Json(EntityNewsResponse {
+            entity: Some(Entity {
+                name: entry.name.clone(),
+                kind: entry.kind,
+            }),
+            news,
+            error: None,
+        }),
+    )
+}
+
+fn kind_label(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Person => "person",
+        Kind::Place => "place",
+        Kind::Organization => "organization",
+    }
+}
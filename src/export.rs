@@ -0,0 +1,302 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Serialize;
+use std::io::Write;
+
+use crate::archive::ArchivedScrape;
+#[cfg(feature = "parquet")]
+use crate::article::ArticleDetail;
+#[cfg(feature = "parquet")]
+use crate::engagement::Engagement;
+use crate::extract;
+
+// File formats an archive day can be exported in
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ExportFormat {
+    // Gzipped newline delimited JSON
+    Ndjson,
+    // Apache Parquet with the schema from parquet_schema(), Snappy compressed
+    Parquet,
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_ascii_lowercase().as_str() {
+            "ndjson" => Ok(ExportFormat::Ndjson),
+            "parquet" if cfg!(feature = "parquet") => Ok(ExportFormat::Parquet),
+            "parquet" => Err("parquet support is not enabled in this build".to_string()),
+            _ => Err("expected ndjson or parquet".to_string()),
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "ndjson.gz",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    // Whether the encoded bytes are gzipped as a whole
+    pub fn is_gzipped(self) -> bool {
+        self == ExportFormat::Ndjson
+    }
+}
+
+// One item of one archived scrape. The field order and names are the export
+// schema; add fields at the end rather than renaming or reordering
+#[derive(Serialize, Clone, Debug)]
+pub struct ExportRow {
+    pub scraped_at: DateTime<Utc>,
+    pub position: u32,
+    pub title: String,
+    pub description: String,
+    pub link: String,
+    pub image_url: Option<String>,
+    pub section: Option<String>,
+}
+
+// Helper function to flatten scrapes into rows, positions starting at 1
+pub fn rows(scrapes: &[ArchivedScrape]) -> Vec<ExportRow> {
+    scrapes
+        .iter()
+        .flat_map(|scrape| {
+            scrape
+                .news
+                .iter()
+                .enumerate()
+                .map(move |(index, item)| ExportRow {
+                    scraped_at: scrape.scraped_at,
+                    position: index as u32 + 1,
+                    title: item.title.clone(),
+                    description: item.description.clone(),
+                    link: item.link.clone(),
+                    image_url: item.image_url.clone(),
+                    section: extract::section(&item.link),
+                })
+        })
+        .collect()
+}
+
+// Helper function to encode rows in the given format
+pub fn encode(format: ExportFormat, rows: &[ExportRow]) -> Result<Vec<u8>, String> {
+    match format {
+        ExportFormat::Ndjson => ndjson_gz(rows),
+        #[cfg(feature = "parquet")]
+        ExportFormat::Parquet => parquet(rows),
+        #[cfg(not(feature = "parquet"))]
+        ExportFormat::Parquet => Err("parquet support is not enabled in this build".to_string()),
+    }
+}
+
+// Helper function to encode off the async runtime, as a day of rows takes a
+// while to compress
+pub async fn run_encode(format: ExportFormat, rows: Vec<ExportRow>) -> Result<Vec<u8>, String> {
+    extract::run_blocking(move || encode(format, &rows)).await?
+}
+
+// Helper function to encode rows as gzipped newline delimited JSON
+pub fn ndjson_gz(rows: &[ExportRow]) -> Result<Vec<u8>, String> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    for row in rows {
+        let line =
+            serde_json::to_string(row).map_err(|e| format!("Failed to serialize row: {}", e))?;
+        encoder
+            .write_all(line.as_bytes())
+            .and_then(|_| encoder.write_all(b"\n"))
+            .map_err(|e| format!("Failed to compress export: {}", e))?;
+    }
+    encoder
+        .finish()
+        .map_err(|e| format!("Failed to compress export: {}", e))
+}
+
+// The UTC day as a half-open range, the unit exports are made in
+pub fn day_range(day: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+    let start = day.and_time(chrono::NaiveTime::MIN).and_utc();
+    (start, start + chrono::Duration::days(1))
+}
+
+// Arrow schema of the Parquet export, matching ExportRow field for field
+#[cfg(feature = "parquet")]
+pub fn parquet_schema() -> std::sync::Arc<arrow_schema::Schema> {
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+
+    std::sync::Arc::new(Schema::new(vec![
+        Field::new(
+            "scraped_at",
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            false,
+        ),
+        Field::new("position", DataType::UInt32, false),
+        Field::new("title", DataType::Utf8, false),
+        Field::new("description", DataType::Utf8, false),
+        Field::new("link", DataType::Utf8, false),
+        Field::new("image_url", DataType::Utf8, true),
+        Field::new("section", DataType::Utf8, true),
+    ]))
+}
+
+// Helper function to encode rows as a single Parquet file
+#[cfg(feature = "parquet")]
+fn parquet(rows: &[ExportRow]) -> Result<Vec<u8>, String> {
+    use arrow_array::{ArrayRef, StringArray, TimestampMicrosecondArray, UInt32Array};
+    use std::sync::Arc;
+
+    let schema = parquet_schema();
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(
+            TimestampMicrosecondArray::from_iter_values(
+                rows.iter().map(|row| row.scraped_at.timestamp_micros()),
+            )
+            .with_timezone("UTC"),
+        ),
+        Arc::new(UInt32Array::from_iter_values(
+            rows.iter().map(|row| row.position),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|row| row.title.as_str()),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|row| row.description.as_str()),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            rows.iter().map(|row| row.link.as_str()),
+        )),
+        Arc::new(StringArray::from_iter(
+            rows.iter().map(|row| row.image_url.as_deref()),
+        )),
+        Arc::new(StringArray::from_iter(
+            rows.iter().map(|row| row.section.as_deref()),
+        )),
+    ];
+    write_parquet(schema, columns)
+}
+
+// Arrow schema of the Parquet export of fetched articles, one row per
+// ArticleDetail. As with ExportRow, add fields at the end rather than
+// renaming or reordering
+#[cfg(feature = "parquet")]
+pub fn article_parquet_schema() -> std::sync::Arc<arrow_schema::Schema> {
+    use arrow_schema::{DataType, Field, Schema, TimeUnit};
+
+    std::sync::Arc::new(Schema::new(vec![
+        Field::new("url", DataType::Utf8, false),
+        Field::new("title", DataType::Utf8, false),
+        Field::new("subtitle", DataType::Utf8, true),
+        Field::new("author", DataType::Utf8, true),
+        Field::new(
+            "published_at",
+            DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            true,
+        ),
+        Field::new("image_url", DataType::Utf8, true),
+        Field::new("body", DataType::Utf8, false),
+        Field::new(
+            "corrections",
+            DataType::List(std::sync::Arc::new(Field::new(
+                "item",
+                DataType::Utf8,
+                true,
+            ))),
+            false,
+        ),
+        Field::new("comments", DataType::UInt64, true),
+        Field::new("shares", DataType::UInt64, true),
+    ]))
+}
+
+// Helper function to encode articles as a single Parquet file
+#[cfg(feature = "parquet")]
+pub fn articles_parquet(articles: &[ArticleDetail]) -> Result<Vec<u8>, String> {
+    use arrow_array::builder::{ListBuilder, StringBuilder};
+    use arrow_array::{ArrayRef, StringArray, TimestampMicrosecondArray, UInt64Array};
+    use std::sync::Arc;
+
+    let mut corrections = ListBuilder::new(StringBuilder::new());
+    for article in articles {
+        for correction in &article.corrections {
+            corrections.values().append_value(correction);
+        }
+        corrections.append(true);
+    }
+    let engagement = |count: fn(&Engagement) -> Option<u64>| {
+        UInt64Array::from_iter(
+            articles
+                .iter()
+                .map(|article| article.engagement.as_ref().and_then(count)),
+        )
+    };
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(
+            articles.iter().map(|article| article.url.as_str()),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            articles.iter().map(|article| article.title.as_str()),
+        )),
+        Arc::new(StringArray::from_iter(
+            articles.iter().map(|article| article.subtitle.as_deref()),
+        )),
+        Arc::new(StringArray::from_iter(
+            articles.iter().map(|article| article.author.as_deref()),
+        )),
+        Arc::new(
+            TimestampMicrosecondArray::from_iter(articles.iter().map(|article| {
+                article
+                    .published_at
+                    .map(|published_at| published_at.timestamp_micros())
+            }))
+            .with_timezone("UTC"),
+        ),
+        Arc::new(StringArray::from_iter(
+            articles.iter().map(|article| article.image_url.as_deref()),
+        )),
+        Arc::new(StringArray::from_iter_values(
+            articles.iter().map(|article| article.body.as_str()),
+        )),
+        Arc::new(corrections.finish()),
+        Arc::new(engagement(|engagement| engagement.comments)),
+        Arc::new(engagement(|engagement| engagement.shares)),
+    ];
+    write_parquet(article_parquet_schema(), columns)
+}
+
+// Helper function to write columns matching `schema` as one Snappy
+// compressed Parquet file
+#[cfg(feature = "parquet")]
+fn write_parquet(
+    schema: std::sync::Arc<arrow_schema::Schema>,
+    columns: Vec<arrow_array::ArrayRef>,
+) -> Result<Vec<u8>, String> {
+    use arrow_array::RecordBatch;
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::Compression as ParquetCompression;
+    use parquet::file::properties::WriterProperties;
+
+    let batch = RecordBatch::try_new(schema.clone(), columns)
+        .map_err(|e| format!("Failed to build record batch: {}", e))?;
+
+    let properties = WriterProperties::builder()
+        .set_compression(ParquetCompression::SNAPPY)
+        .build();
+    let mut buffer = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut buffer, schema, Some(properties))
+        .map_err(|e| format!("Failed to write Parquet: {}", e))?;
+    writer
+        .write(&batch)
+        .and_then(|_| writer.close().map(|_| ()))
+        .map_err(|e| format!("Failed to write Parquet: {}", e))?;
+    Ok(buffer)
+}
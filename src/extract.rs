@@ -0,0 +1,61 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::json_file;
+
+// The selectors and extraction rules live in corriere_core, which builds
+// without tokio or reqwest so the browser can run the same code
+pub use corriere_core::extract::*;
+
+// Ordered selector sets per source ("homepage" or a scheduler job name),
+// loaded from the JSON file in SELECTOR_SETS_PATH, e.g.
+// {"homepage": [{"name": "classic"}, {"name": "experiment", "article": ".card"}]}
+// Sources without an entry use the default selectors alone
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SelectorSets(HashMap<String, Vec<SelectorSet>>);
+
+impl SelectorSets {
+    // Helper function to read the sets and check every selector parses
+    pub fn load(path: &Path) -> Result<SelectorSets, String> {
+        let sets: SelectorSets =
+            json_file::load(path)?.ok_or(format!("{} does not exist", path.display()))?;
+        for (source, chain) in &sets.0 {
+            if chain.is_empty() {
+                return Err(format!("No selector sets for '{}'", source));
+            }
+            for set in chain {
+                Selectors::parse(&set.selectors)
+                    .map_err(|e| format!("Selector set '{}/{}': {}", source, set.name, e))?;
+            }
+        }
+        Ok(sets)
+    }
+
+    pub fn for_source(&self, source: &str) -> Vec<SelectorSet> {
+        self.for_source_or(source, SelectorConfig::default())
+    }
+
+    // Like for_source, for sources whose built-in selectors aren't the
+    // homepage ones
+    pub fn for_source_or(&self, source: &str, default: SelectorConfig) -> Vec<SelectorSet> {
+        self.0.get(source).cloned().unwrap_or_else(|| {
+            vec![SelectorSet {
+                name: "default".to_string(),
+                selectors: default,
+            }]
+        })
+    }
+}
+
+// Helper function to run CPU-bound work such as HTML parsing on the blocking
+// thread pool, so large pages don't stall the async workers
+pub async fn run_blocking<T, F>(work: F) -> Result<T, String>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| format!("Extraction task failed: {}", e))
+}
@@ -0,0 +1,250 @@
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use reqwest::Url;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::sync::{mpsc, oneshot, Semaphore};
+
+use crate::metrics::Metrics;
+use crate::url_safety::{self, FetchedPage};
+use crate::AppState;
+
+// Who is waiting for a fetch. Higher priorities are always dequeued first
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Priority {
+    // A client request is waiting on it
+    Interactive,
+    // Scheduler jobs and the notifiers' polls
+    Scheduled,
+    // Work nobody waits on right now, such as building editions
+    Backfill,
+}
+
+impl Priority {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Priority::Interactive => "interactive",
+            Priority::Scheduled => "scheduled",
+            Priority::Backfill => "backfill",
+        }
+    }
+}
+
+tokio::task_local! {
+    static PRIORITY: Priority;
+}
+
+// The priority fetches get in the current task; Scheduled outside of `scope`
+pub fn current() -> Priority {
+    PRIORITY
+        .try_with(|priority| *priority)
+        .unwrap_or(Priority::Scheduled)
+}
+
+// Runs a future with its fetches queued at `priority`
+pub async fn scope<F: Future>(priority: Priority, future: F) -> F::Output {
+    PRIORITY.scope(priority, future).await
+}
+
+// Middleware queueing the fetches of HTTP requests as interactive
+pub async fn middleware(request: Request, next: Next) -> Response {
+    scope(Priority::Interactive, next.run(request)).await
+}
+
+type Work = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+struct Job {
+    priority: Priority,
+    enqueued_at: Instant,
+    work: Work,
+}
+
+// Jobs waiting at each priority, shared with the dispatcher
+struct Depth {
+    waiting: [AtomicUsize; 3],
+    in_flight: AtomicUsize,
+    metrics: Arc<Metrics>,
+}
+
+impl Depth {
+    fn change(&self, priority: Priority, queued: bool) {
+        let counter = &self.waiting[priority as usize];
+        let depth = if queued {
+            counter.fetch_add(1, Ordering::SeqCst) + 1
+        } else {
+            counter.fetch_sub(1, Ordering::SeqCst) - 1
+        };
+        self.metrics.set_gauge(
+            "corriere_fetch_queue_depth",
+            &[("priority", priority.as_str())],
+            depth as f64,
+        );
+    }
+
+    fn running(&self, started: bool) {
+        let in_flight = if started {
+            self.in_flight.fetch_add(1, Ordering::SeqCst) + 1
+        } else {
+            self.in_flight.fetch_sub(1, Ordering::SeqCst) - 1
+        };
+        self.metrics
+            .set_gauge("corriere_fetch_in_flight", &[], in_flight as f64);
+    }
+}
+
+// Every upstream fetch goes through here: jobs wait in a bounded channel per
+// priority and a dispatcher starts them, highest priority first, while fewer
+// than FETCH_CONCURRENCY are running. When a channel is full, interactive
+// fetches fail right away and the others wait for room, which slows down the
+// background work producing them
+pub struct FetchQueue {
+    senders: [mpsc::Sender<Job>; 3],
+    // Taken by the dispatcher, started with the first fetch so the queue can
+    // be created outside of a runtime
+    receivers: Mutex<Option<[mpsc::Receiver<Job>; 3]>>,
+    concurrency: usize,
+    depth: Arc<Depth>,
+}
+
+impl FetchQueue {
+    pub fn new(concurrency: usize, queue_size: usize, metrics: Arc<Metrics>) -> FetchQueue {
+        let (interactive, interactive_rx) = mpsc::channel(queue_size.max(1));
+        let (scheduled, scheduled_rx) = mpsc::channel(queue_size.max(1));
+        let (backfill, backfill_rx) = mpsc::channel(queue_size.max(1));
+        FetchQueue {
+            senders: [interactive, scheduled, backfill],
+            receivers: Mutex::new(Some([interactive_rx, scheduled_rx, backfill_rx])),
+            concurrency: concurrency.max(1),
+            depth: Arc::new(Depth {
+                waiting: Default::default(),
+                in_flight: AtomicUsize::new(0),
+                metrics,
+            }),
+        }
+    }
+
+    // Jobs waiting at `priority`
+    pub fn depth(&self, priority: Priority) -> usize {
+        self.depth.waiting[priority as usize].load(Ordering::SeqCst)
+    }
+
+    // Helper function to run `work` once the queue gets to it, at the
+    // priority of the current task
+    pub async fn run<T, F>(&self, work: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: Future<Output = Result<T, String>> + Send + 'static,
+    {
+        if let Some(receivers) = self.receivers.lock().unwrap().take() {
+            tokio::spawn(dispatch(receivers, self.concurrency, self.depth.clone()));
+        }
+
+        let priority = current();
+        let (reply, result) = oneshot::channel();
+        let job = Job {
+            priority,
+            enqueued_at: Instant::now(),
+            work: Box::pin(async move {
+                let _ = reply.send(work.await);
+            }),
+        };
+
+        // Counted before sending, as the dispatcher may take it right away
+        self.depth.change(priority, true);
+        let sender = &self.senders[priority as usize];
+        let sent = match priority {
+            Priority::Interactive => sender.try_send(job).map_err(|_| ()),
+            _ => sender.send(job).await.map_err(|_| ()),
+        };
+        if sent.is_err() {
+            self.depth.change(priority, false);
+            self.depth.metrics.increment(
+                "corriere_fetch_queue_rejected_total",
+                &[("priority", priority.as_str())],
+            );
+            return Err("Too many upstream fetches queued, try again later".to_string());
+        }
+
+        result
+            .await
+            .map_err(|_| "The fetch was abandoned".to_string())?
+    }
+}
+
+// Helper function for the dispatcher loop: takes a concurrency slot, then the
+// most urgent job waiting, and runs it in its own task
+async fn dispatch(receivers: [mpsc::Receiver<Job>; 3], concurrency: usize, depth: Arc<Depth>) {
+    let slots = Arc::new(Semaphore::new(concurrency));
+    let [mut interactive, mut scheduled, mut backfill] = receivers;
+    loop {
+        let Ok(slot) = slots.clone().acquire_owned().await else {
+            return;
+        };
+        let job = tokio::select! {
+            biased;
+            Some(job) = interactive.recv() => job,
+            Some(job) = scheduled.recv() => job,
+            Some(job) = backfill.recv() => job,
+            // The queue was dropped
+            else => return,
+        };
+
+        depth.change(job.priority, false);
+        let priority = [("priority", job.priority.as_str())];
+        depth
+            .metrics
+            .increment("corriere_fetch_queue_jobs_total", &priority);
+        depth.metrics.add(
+            "corriere_fetch_queue_wait_milliseconds_total",
+            &priority,
+            job.enqueued_at.elapsed().as_millis() as u64,
+        );
+
+        let depth = depth.clone();
+        depth.running(true);
+        tokio::spawn(async move {
+            job.work.await;
+            depth.running(false);
+            drop(slot);
+        });
+    }
+}
+
+// Helper function to fetch a page through the queue, within the
+// PER_HOST_CONCURRENCY limit of its host and up to SCRAPE_MAX_BYTES
+pub async fn fetch_html(
+    state: &AppState,
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<String, String> {
+    fetch_page(state, client, url).await.map(|page| page.html)
+}
+
+// Like fetch_html, keeping the status, size and timing of the fetch
+pub async fn fetch_page(
+    state: &AppState,
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<FetchedPage, String> {
+    let host = Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default();
+    // The host's permit comes first, so a fetch waiting on a busy host
+    // doesn't hold one of the FETCH_CONCURRENCY slots other hosts could use
+    let permit = state.host_limiter.acquire(&host).await;
+    let client = client.clone();
+    let url = url.to_string();
+    let max_bytes = state.config.scrape_max_bytes;
+    state
+        .fetches
+        .run(async move {
+            let _permit = permit;
+            url_safety::fetch_page(&client, &url, max_bytes).await
+        })
+        .await
+}
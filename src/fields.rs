@@ -0,0 +1,53 @@
+use serde_json::{Map, Value};
+
+use crate::NewsItem;
+
+// Fields of NewsItem that can be requested with ?fields=
+pub const NEWS_ITEM_FIELDS: &[&str] = &["title", "description", "link", "image_url"];
+
+// Helper function to parse a comma separated field list like "title,link"
+pub fn parse_fields(raw: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+
+    for name in raw.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+        if !NEWS_ITEM_FIELDS.contains(&name) {
+            return Err(format!(
+                "Unknown field '{}', expected one of: {}",
+                name,
+                NEWS_ITEM_FIELDS.join(", ")
+            ));
+        }
+        if !fields.iter().any(|field| field == name) {
+            fields.push(name.to_string());
+        }
+    }
+
+    if fields.is_empty() {
+        return Err("The fields parameter must name at least one field".to_string());
+    }
+    Ok(fields)
+}
+
+// Helper function to serialize news items, keeping only the requested fields
+// when a selection was made
+pub fn project(news: &[NewsItem], fields: Option<&[String]>) -> Vec<Value> {
+    news.iter()
+        .map(|item| {
+            let mut full = match serde_json::to_value(item) {
+                Ok(Value::Object(full)) => full,
+                _ => Map::new(),
+            };
+            let Some(fields) = fields else {
+                return Value::Object(full);
+            };
+
+            let mut projected = Map::new();
+            for field in fields {
+                if let Some(value) = full.remove(field) {
+                    projected.insert(field.clone(), value);
+                }
+            }
+            Value::Object(projected)
+        })
+        .collect()
+}
@@ -0,0 +1,458 @@
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::{json, Value};
+
+use crate::budget::Budget;
+use crate::dates::DateFormat;
+use crate::fields;
+use crate::problem::ParameterError;
+use crate::NewsResponse;
+
+// Output formats for /api/news, chosen with ?format= or the Accept header
+pub enum Format {
+    Json,
+    Jsonp(String),
+    Html,
+    Xml,
+    Csv,
+    Rss,
+}
+
+// Media types offered during content negotiation, in order of preference.
+// JSONP and the HTML snippet are only available through ?format=
+pub const NEGOTIABLE_TYPES: &[&str] = &[
+    "application/json",
+    "application/xml",
+    "text/xml",
+    "text/csv",
+    "application/rss+xml",
+];
+
+// Helper function to resolve the format and callback query parameters
+pub fn parse_format(format: &str, callback: Option<&str>) -> Result<Format, ParameterError> {
+    match format {
+        "json" => Ok(Format::Json),
+        "jsonp" => {
+            let callback = callback.ok_or_else(|| {
+                ParameterError::new(
+                    "callback",
+                    "format=jsonp requires a callback parameter".to_string(),
+                )
+            })?;
+            if !is_valid_callback(callback) {
+                return Err(ParameterError::new(
+                    "callback",
+                    format!("Invalid JSONP callback name '{}'", callback),
+                ));
+            }
+            Ok(Format::Jsonp(callback.to_string()))
+        }
+        "html" => Ok(Format::Html),
+        "xml" => Ok(Format::Xml),
+        "csv" => Ok(Format::Csv),
+        "rss" => Ok(Format::Rss),
+        other => Err(ParameterError::new(
+            "format",
+            format!(
+                "Unknown format '{}', expected one of: json, jsonp, html, xml, csv, rss",
+                other
+            ),
+        )),
+    }
+}
+
+// Helper function to pick a format from an Accept header. A missing header
+// means JSON; None means nothing we can produce is acceptable. JSON is kept
+// unless another format outranks it and is also the client's first choice:
+// browsers list application/xml below text/html, which isn't asking for XML
+pub fn negotiate(accept: Option<&str>) -> Option<Format> {
+    let Some(accept) = accept.filter(|accept| !accept.trim().is_empty()) else {
+        return Some(Format::Json);
+    };
+
+    let mut best: Option<(&str, f32)> = None;
+    for media_type in NEGOTIABLE_TYPES {
+        if let Some(quality) = quality(accept, media_type) {
+            if quality > 0.0 && best.is_none_or(|(_, best_quality)| quality > best_quality) {
+                best = Some((media_type, quality));
+            }
+        }
+    }
+
+    // The highest quality given to anything, including types we don't serve
+    let top = accept.split(',').map(range_quality).fold(0.0, f32::max);
+    let json = quality(accept, "application/json").filter(|quality| *quality > 0.0);
+    if let (Some((_, best_quality)), Some(_)) = (best, json) {
+        if best_quality < top {
+            return Some(Format::Json);
+        }
+    }
+
+    best.map(|(media_type, _)| match media_type {
+        "application/xml" | "text/xml" => Format::Xml,
+        "text/csv" => Format::Csv,
+        "application/rss+xml" => Format::Rss,
+        _ => Format::Json,
+    })
+}
+
+// Quality the Accept header gives a media type, taken from the most specific
+// matching range (exact type, then type/*, then */*)
+fn quality(accept: &str, media_type: &str) -> Option<f32> {
+    let (main_type, _) = media_type.split_once('/')?;
+    let mut best: Option<(u8, f32)> = None;
+
+    for range in accept.split(',') {
+        let range_type = range
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        let quality = range_quality(range);
+
+        let specificity = if range_type == media_type {
+            2
+        } else if range_type == format!("{}/*", main_type) {
+            1
+        } else if range_type == "*/*" {
+            0
+        } else {
+            continue;
+        };
+
+        if best.is_none_or(|(best_specificity, _)| specificity > best_specificity) {
+            best = Some((specificity, quality));
+        }
+    }
+
+    best.map(|(_, quality)| quality)
+}
+
+// The q parameter of one media range in an Accept header, 1 when missing
+fn range_quality(range: &str) -> f32 {
+    range
+        .split(';')
+        .skip(1)
+        .filter_map(|param| param.trim().strip_prefix("q="))
+        .find_map(|q| q.trim().parse::<f32>().ok())
+        .unwrap_or(1.0)
+}
+
+pub fn not_acceptable() -> Response {
+    (
+        StatusCode::NOT_ACCEPTABLE,
+        [(header::VARY, "accept")],
+        format!("Supported media types: {}", NEGOTIABLE_TYPES.join(", ")),
+    )
+        .into_response()
+}
+
+// Helper function to serialize a news response in the chosen format, within
+// the response budget. RESPONSE_MAX_BYTES is measured on the JSON body
+pub fn render(
+    format: &Format,
+    response: &NewsResponse,
+    fields: Option<&[String]>,
+    dates: &DateFormat,
+    budget: &Budget,
+) -> Response {
+    let mut items = fields::project(&response.news, fields);
+    let json = |items| {
+        let mut body = envelope(response, items, dates);
+        budget.apply(&mut body);
+        body
+    };
+
+    let mut rendered = match format {
+        Format::Json => Json(json(items)).into_response(),
+        Format::Jsonp(callback) => render_jsonp(callback, &json(items)),
+        Format::Html => render_html(&trimmed(response, budget)),
+        Format::Xml => {
+            let truncated = budget.trim_items(&mut items);
+            render_xml(response, &items, truncated, dates)
+        }
+        Format::Csv => {
+            budget.trim_items(&mut items);
+            render_csv(response, &items, fields)
+        }
+        Format::Rss => render_rss(&trimmed(response, budget), dates),
+    };
+
+    rendered
+        .headers_mut()
+        .insert(header::VARY, HeaderValue::from_static("accept"));
+    rendered
+}
+
+// The response with its items cut to the budget, for the formats that
+// render the scraped items rather than their JSON projection
+fn trimmed(response: &NewsResponse, budget: &Budget) -> NewsResponse {
+    let mut response = response.clone();
+    budget.trim_news(&mut response.news);
+    response
+}
+
+// The JSON body of /api/news
+pub fn envelope(response: &NewsResponse, items: Vec<Value>, dates: &DateFormat) -> Value {
+    let mut envelope = json!({
+        "scraped_at": response.scraped_at,
+        "news": items,
+        "error": response.error,
+        "stale": response.stale,
+    });
+    if let Value::Object(object) = &mut envelope {
+        dates.apply(object, "scraped_at");
+        // Only there for ?at=, like the X-Snapshot-At header
+        if response.snapshot {
+            object.insert("snapshot".to_string(), Value::Bool(true));
+        }
+    }
+    envelope
+}
+
+// Only plain (optionally dotted) JavaScript identifiers are accepted, so the
+// callback can't be used to inject script into the response
+fn is_valid_callback(callback: &str) -> bool {
+    !callback.is_empty()
+        && callback.len() <= 128
+        && callback.split('.').all(|part| {
+            let mut chars = part.chars();
+            matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+        })
+}
+
+fn render_jsonp(callback: &str, body: &Value) -> Response {
+    // The leading comment guards against content-sniffing attacks on JSONP endpoints
+    let script = format!("/**/{}({});", callback, body);
+    (
+        [
+            (
+                header::CONTENT_TYPE,
+                "application/javascript; charset=utf-8",
+            ),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        ],
+        script,
+    )
+        .into_response()
+}
+
+// Ready to embed list of headlines for pages that can't template JSON themselves
+fn render_html(response: &NewsResponse) -> Response {
+    let mut html = String::new();
+
+    if let Some(error) = &response.error {
+        html.push_str(&format!(
+            "<p class=\"corriere-news-error\">{}</p>\n",
+            escape_html(error)
+        ));
+    } else {
+        html.push_str("<ul class=\"corriere-news\">\n");
+        for item in &response.news {
+            html.push_str(&format!(
+                "  <li><a href=\"{}\" target=\"_blank\" rel=\"noopener\">{}</a></li>\n",
+                escape_html(&item.link),
+                escape_html(&item.title)
+            ));
+        }
+        html.push_str("</ul>\n");
+    }
+
+    ([(header::CONTENT_TYPE, "text/html; charset=utf-8")], html).into_response()
+}
+
+fn render_xml(
+    response: &NewsResponse,
+    items: &[Value],
+    truncated: bool,
+    dates: &DateFormat,
+) -> Response {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<news_response>\n");
+    xml.push_str(&format!(
+        "  <scraped_at>{}</scraped_at>\n",
+        dates.localize(response.scraped_at).to_rfc3339()
+    ));
+    if let Some(display) = dates.display(response.scraped_at) {
+        xml.push_str(&format!(
+            "  <scraped_at_display>{}</scraped_at_display>\n",
+            escape_html(&display)
+        ));
+    }
+    if let Some(error) = &response.error {
+        xml.push_str(&format!("  <error>{}</error>\n", escape_html(error)));
+    }
+    if response.stale {
+        xml.push_str("  <stale>true</stale>\n");
+    }
+    if response.snapshot {
+        xml.push_str("  <snapshot>true</snapshot>\n");
+    }
+    if truncated {
+        xml.push_str("  <truncated>true</truncated>\n");
+    }
+
+    xml.push_str("  <news>\n");
+    for item in items {
+        xml.push_str("    <item>\n");
+        if let Value::Object(item) = item {
+            for (name, value) in item {
+                // Missing values (e.g. no image) are left out rather than written empty
+                if let Some(text) = value_text(value) {
+                    xml.push_str(&format!(
+                        "      <{}>{}</{}>\n",
+                        name,
+                        escape_html(&text),
+                        name
+                    ));
+                }
+            }
+        }
+        xml.push_str("    </item>\n");
+    }
+    xml.push_str("  </news>\n</news_response>\n");
+
+    (
+        [(header::CONTENT_TYPE, "application/xml; charset=utf-8")],
+        xml,
+    )
+        .into_response()
+}
+
+fn render_csv(response: &NewsResponse, items: &[Value], fields: Option<&[String]>) -> Response {
+    // A bare table has nowhere to put the error message
+    if let Some(error) = &response.error {
+        return (StatusCode::BAD_GATEWAY, error.clone()).into_response();
+    }
+
+    let columns: Vec<String> = match fields {
+        Some(fields) => fields.to_vec(),
+        None => fields::NEWS_ITEM_FIELDS
+            .iter()
+            .map(|field| field.to_string())
+            .collect(),
+    };
+
+    let mut csv = columns
+        .iter()
+        .map(|column| csv_field(column))
+        .collect::<Vec<_>>()
+        .join(",");
+    csv.push_str("\r\n");
+
+    for item in items {
+        let row = columns
+            .iter()
+            .map(|column| csv_field(&item.get(column).and_then(value_text).unwrap_or_default()))
+            .collect::<Vec<_>>()
+            .join(",");
+        csv.push_str(&row);
+        csv.push_str("\r\n");
+    }
+
+    ([(header::CONTENT_TYPE, "text/csv; charset=utf-8")], csv).into_response()
+}
+
+fn render_rss(response: &NewsResponse, dates: &DateFormat) -> Response {
+    if let Some(error) = &response.error {
+        return (StatusCode::BAD_GATEWAY, error.clone()).into_response();
+    }
+
+    (
+        [(header::CONTENT_TYPE, "application/rss+xml; charset=utf-8")],
+        rss_document(response, dates),
+    )
+        .into_response()
+}
+
+// Helper function to write the headlines as an RSS 2.0 feed
+pub fn rss_document(response: &NewsResponse, dates: &DateFormat) -> String {
+    let mut rss = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<rss version=\"2.0\">\n<channel>\n",
+    );
+    rss.push_str("  <title>Corriere della Sera</title>\n");
+    rss.push_str("  <link>https://www.corriere.it</link>\n");
+    rss.push_str("  <description>Headlines scraped from the corriere.it homepage</description>\n");
+    rss.push_str(&format!(
+        "  <lastBuildDate>{}</lastBuildDate>\n",
+        dates.localize(response.scraped_at).to_rfc2822()
+    ));
+
+    for item in &response.news {
+        rss.push_str("  <item>\n");
+        rss.push_str(&format!(
+            "    <title>{}</title>\n",
+            escape_html(&item.title)
+        ));
+        rss.push_str(&format!("    <link>{}</link>\n", escape_html(&item.link)));
+        rss.push_str(&format!(
+            "    <description>{}</description>\n",
+            escape_html(&item.description)
+        ));
+        rss.push_str(&format!(
+            "    <guid isPermaLink=\"true\">{}</guid>\n",
+            escape_html(&item.link)
+        ));
+        if let Some(image_url) = &item.image_url {
+            rss.push_str(&format!(
+                "    <enclosure url=\"{}\" type=\"{}\" length=\"0\"/>\n",
+                escape_html(image_url),
+                image_mime_type(image_url)
+            ));
+        }
+        rss.push_str("  </item>\n");
+    }
+    rss.push_str("</channel>\n</rss>\n");
+    rss
+}
+
+fn value_text(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(text) => Some(text.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn image_mime_type(url: &str) -> &'static str {
+    let path = url
+        .split(['?', '#'])
+        .next()
+        .unwrap_or(url)
+        .to_ascii_lowercase();
+    if path.ends_with(".png") {
+        "image/png"
+    } else if path.ends_with(".webp") {
+        "image/webp"
+    } else if path.ends_with(".gif") {
+        "image/gif"
+    } else {
+        "image/jpeg"
+    }
+}
+
+// Escapes text for use in HTML and XML, both in content and attribute values
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use crate::AppState;
+
+// Monitors answer quickly; a slow one mustn't hold up the scheduler
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Helper function to ping the configured monitor after a scheduler job run:
+// HEARTBEAT_URL when it succeeded, HEARTBEAT_FAIL_URL with the error as the
+// body when it failed. A ping that doesn't go through is only logged
+pub async fn report(state: &AppState, job: &str, result: &Result<usize, String>) {
+    let config = &state.config;
+    if !config.heartbeat_jobs.is_empty() && !config.heartbeat_jobs.iter().any(|name| name == job) {
+        return;
+    }
+    let (url, body, outcome) = match result {
+        Ok(items) => (&config.heartbeat_url, format!("{} items", items), "ok"),
+        Err(error_message) => (&config.heartbeat_fail_url, error_message.clone(), "fail"),
+    };
+    let Some(url) = url else {
+        return;
+    };
+
+    let sent = state
+        .callback_client
+        .post(url.replace("{job}", job))
+        .timeout(PING_TIMEOUT)
+        .body(body)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    let result = match sent {
+        Ok(_) => "sent",
+        Err(e) => {
+            eprintln!("Heartbeat for job {} failed: {}", job, e);
+            "failed"
+        }
+    };
+    state.metrics.increment(
+        "corriere_heartbeats_total",
+        &[("job", job), ("ping", outcome), ("result", result)],
+    );
+}
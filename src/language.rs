@@ -0,0 +1,45 @@
+use whatlang::{Detector, Lang};
+
+use crate::NewsItem;
+
+// Languages items are classified into. Corriere publishes in Italian, with
+// the occasional piece from Corriere in English
+pub const LANGUAGES: &[&str] = &["it", "en"];
+
+// Helper function to tell the language of an item. Links into the English
+// edition settle it; otherwise the title and summary are run through
+// whatlang, limited to Italian and English since headlines are too short to
+// tell apart dozens of languages
+pub fn detect(item: &NewsItem) -> &'static str {
+    if item.link.contains("/english/") {
+        return "en";
+    }
+
+    let text = format!("{} {}", item.title, item.description);
+    let detector = Detector::with_allowlist(vec![Lang::Ita, Lang::Eng]);
+    match detector.detect_lang(&text) {
+        Some(Lang::Eng) => "en",
+        _ => "it",
+    }
+}
+
+// Helper function to check a ?language= value
+pub fn parse_language(value: &str) -> Result<&'static str, String> {
+    let value = value.trim().to_ascii_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|language| **language == value)
+        .copied()
+        .ok_or(format!(
+            "Unknown language '{}', expected one of: {}",
+            value,
+            LANGUAGES.join(", ")
+        ))
+}
+
+// Helper function to keep only the items in `language`
+pub fn filter(news: Vec<NewsItem>, language: &str) -> Vec<NewsItem> {
+    news.into_iter()
+        .filter(|item| detect(item) == language)
+        .collect()
+}
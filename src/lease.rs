@@ -0,0 +1,265 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::json_file;
+use crate::subscriptions::random_string;
+
+// Where leases are kept. Without LOCK_URL every job runs on this instance,
+// which is right for a single replica
+enum Backend {
+    Local,
+    // Lease files in a directory shared by the replicas, e.g. a volume
+    File(PathBuf),
+    #[cfg(feature = "redis")]
+    Redis(redis::Client),
+}
+
+#[derive(Serialize, Deserialize)]
+struct LeaseFile {
+    owner: String,
+    expires_at: DateTime<Utc>,
+}
+
+// A guard file older than this was left by a replica that died while
+// taking a lease, and is removed
+const STALE_GUARD: Duration = Duration::from_secs(30);
+
+// Whether this replica holds a lease after asking for it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lease {
+    NotHeld,
+    // Held since an earlier call
+    Held,
+    // Taken over just now, e.g. from a replica that went away: state another
+    // replica may have changed meanwhile has to be read again
+    Acquired,
+}
+
+impl Lease {
+    pub fn is_held(self) -> bool {
+        self != Lease::NotHeld
+    }
+}
+
+// Sets the lease when it's free, or extends it when we already hold it
+#[cfg(feature = "redis")]
+const REDIS_ACQUIRE: &str = r"
+if redis.call('get', KEYS[1]) == ARGV[1] then
+    return redis.call('pexpire', KEYS[1], ARGV[2])
+elseif redis.call('set', KEYS[1], ARGV[1], 'NX', 'PX', ARGV[2]) then
+    return 1
+end
+return 0
+";
+
+// Time-limited leases on named jobs, so that when several replicas share a
+// lock backend only one of them scrapes or notifies at a time. The holder
+// keeps a lease by acquiring it again before it expires; if it goes away,
+// another replica takes over once the lease runs out
+pub struct Leases {
+    backend: Backend,
+    owner: String,
+    lock: Mutex<()>,
+    // Names of the leases held after the last call for each
+    held: Mutex<HashSet<String>>,
+}
+
+impl Leases {
+    pub fn local() -> Leases {
+        Leases::new(Backend::Local)
+    }
+
+    fn new(backend: Backend) -> Leases {
+        Leases {
+            backend,
+            owner: random_string(16),
+            lock: Mutex::new(()),
+            held: Mutex::new(HashSet::new()),
+        }
+    }
+
+    // Helper function to pick the backend from LOCK_URL: file:///shared/dir
+    // or, with the redis feature, redis://host/
+    pub fn from_config(config: &Config) -> Result<Leases, String> {
+        let Some(url) = &config.lock_url else {
+            return Ok(Leases::local());
+        };
+
+        if let Some(dir) = url.strip_prefix("file://") {
+            return Ok(Leases::new(Backend::File(PathBuf::from(dir))));
+        }
+        if url.starts_with("redis://") || url.starts_with("rediss://") {
+            #[cfg(feature = "redis")]
+            {
+                let client = redis::Client::open(url.as_str())
+                    .map_err(|e| format!("Invalid LOCK_URL '{}': {}", url, e))?;
+                return Ok(Leases::new(Backend::Redis(client)));
+            }
+            #[cfg(not(feature = "redis"))]
+            return Err(
+                "LOCK_URL points at Redis but the redis feature is not enabled".to_string(),
+            );
+        }
+        Err(format!(
+            "Invalid LOCK_URL '{}': expected file:// or redis://",
+            url
+        ))
+    }
+
+    // Identifies this instance as a lease holder
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    // Helper function to take or extend the lease on `name` for `ttl`,
+    // telling whether it was already held
+    pub async fn acquire(&self, name: &str, ttl: Duration) -> Result<Lease, String> {
+        let acquired = self.try_acquire(name, ttl).await;
+        let mut held = self.held.lock().unwrap();
+        match acquired {
+            Ok(true) if held.insert(name.to_string()) => Ok(Lease::Acquired),
+            Ok(true) => Ok(Lease::Held),
+            Ok(false) => {
+                held.remove(name);
+                Ok(Lease::NotHeld)
+            }
+            Err(error_message) => {
+                held.remove(name);
+                Err(error_message)
+            }
+        }
+    }
+
+    // Makes the next call for `name` report the lease as just acquired
+    pub fn forget(&self, name: &str) {
+        self.held.lock().unwrap().remove(name);
+    }
+
+    // Helper function to take or extend the lease on `name` for `ttl`.
+    // Returns false when another replica holds it
+    pub async fn try_acquire(&self, name: &str, ttl: Duration) -> Result<bool, String> {
+        match &self.backend {
+            Backend::Local => Ok(true),
+            Backend::File(dir) => self.acquire_file(&dir.join(format!("{}.lease", name)), ttl),
+            #[cfg(feature = "redis")]
+            Backend::Redis(client) => {
+                let mut connection = client
+                    .get_multiplexed_async_connection()
+                    .await
+                    .map_err(|e| format!("Failed to reach Redis: {}", e))?;
+                let acquired: i64 = redis::Script::new(REDIS_ACQUIRE)
+                    .key(format!("corriere:lease:{}", name))
+                    .arg(&self.owner)
+                    .arg(ttl.as_millis() as u64)
+                    .invoke_async(&mut connection)
+                    .await
+                    .map_err(|e| format!("Failed to acquire lease {}: {}", name, e))?;
+                Ok(acquired == 1)
+            }
+        }
+    }
+
+    // Helper function to read, check and write a lease file while holding
+    // its guard file. The guard is created exclusively, so only one replica
+    // at a time gets past it; the lease file itself is replaced atomically
+    fn acquire_file(&self, path: &Path, ttl: Duration) -> Result<bool, String> {
+        let _lock = self.lock.lock().unwrap();
+        let guard = path.with_extension("lease.guard");
+        if !create_guard(&guard)? {
+            // Another replica is taking or renewing the lease right now
+            return Ok(false);
+        }
+        let acquired = self.write_lease(path, ttl);
+        let _ = std::fs::remove_file(&guard);
+        acquired
+    }
+
+    fn write_lease(&self, path: &Path, ttl: Duration) -> Result<bool, String> {
+        let now = Utc::now();
+        let current: Option<LeaseFile> = json_file::load(path)?;
+        if let Some(lease) = &current {
+            if lease.owner != self.owner && lease.expires_at > now {
+                return Ok(false);
+            }
+        }
+
+        let lease = LeaseFile {
+            owner: self.owner.clone(),
+            expires_at: now + chrono::Duration::from_std(ttl).unwrap_or_default(),
+        };
+        json_file::save(path, &lease)?;
+        Ok(true)
+    }
+}
+
+// Helper function to create a guard file, failing when it exists. One left
+// behind by a crashed replica is removed once it is STALE_GUARD old
+fn create_guard(path: &Path) -> Result<bool, String> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let create = || {
+        std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+    };
+    match create() {
+        Ok(_) => return Ok(true),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(format!("Failed to create {}: {}", path.display(), e)),
+    }
+    let stale = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age > STALE_GUARD);
+    if !stale {
+        return Ok(false);
+    }
+    let _ = std::fs::remove_file(path);
+    match create() {
+        Ok(_) => Ok(true),
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => Ok(false),
+        Err(e) => Err(format!("Failed to create {}: {}", path.display(), e)),
+    }
+}
+
+// Helper function for background jobs: whether this replica holds the
+// lease on `name` now, and whether it just took it over. Errors count as
+// not holding it, since running anyway could duplicate the work on another
+// replica
+pub async fn check(state: &crate::AppState, name: &str, ttl: Duration) -> Lease {
+    match state.leases.acquire(name, ttl).await {
+        Ok(Lease::NotHeld) => {
+            state
+                .metrics
+                .increment("corriere_lease_skips_total", &[("job", name)]);
+            Lease::NotHeld
+        }
+        Ok(lease) => lease,
+        Err(error_message) => {
+            eprintln!("Lease {} unavailable: {}", name, error_message);
+            Lease::NotHeld
+        }
+    }
+}
+
+// Helper function for background jobs: whether this replica should run
+// `name` now
+pub async fn should_run(state: &crate::AppState, name: &str, ttl: Duration) -> bool {
+    check(state, name, ttl).await.is_held()
+}
+
+// Lease length for a job running every `every`: long enough to survive a
+// late tick, short enough that another replica takes over soon
+pub fn lease_ttl(every: Duration) -> Duration {
+    (every * 2).max(Duration::from_secs(30))
+}
@@ -0,0 +1,892 @@
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
+use axum::middleware;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{delete, get, post, put};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tower_http::services::ServeDir;
+
+pub mod admin;
+pub mod alerts;
+pub mod analytics;
+pub mod archive;
+pub mod article;
+pub mod audit;
+pub mod bloom;
+pub mod breaker;
+#[cfg(feature = "tts")]
+pub mod briefing;
+pub mod budget;
+pub mod cache;
+pub mod cli;
+pub mod clusters;
+pub mod config;
+pub mod dates;
+pub mod delivery;
+pub mod demo;
+pub mod digest;
+pub mod edition;
+pub mod entities;
+pub mod export;
+pub mod extract;
+pub mod fetch_queue;
+pub mod fields;
+pub mod formats;
+pub mod heartbeat;
+pub mod json_file;
+pub mod language;
+pub mod lease;
+pub mod limits;
+pub mod listener;
+pub mod local;
+pub mod metrics;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod newsletter;
+pub mod plugins;
+pub mod politeness;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod preferences;
+pub mod problem;
+pub mod provenance;
+pub mod ranges;
+#[cfg(feature = "read_later")]
+pub mod read_later;
+pub mod reload;
+pub mod repair;
+pub mod request_id;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod scheduler;
+pub mod scoring;
+pub mod scrape;
+#[cfg(feature = "semantic")]
+pub mod semantic;
+pub mod sent_log;
+pub mod server;
+pub mod serverless;
+pub mod snapshot;
+#[cfg(feature = "social")]
+pub mod social;
+pub mod static_site;
+pub mod stats;
+pub mod subscriptions;
+pub mod summary;
+pub mod takedown;
+#[cfg(feature = "telegram")]
+pub mod telegram;
+pub mod url_safety;
+pub mod v2;
+pub mod validate;
+pub mod watch;
+pub mod webhooks;
+
+// Extraction shared with the browser build, see corriere_core
+pub use corriere_core::{
+    engagement, fragment, image, ranking, typography, NewsItem, EXTRACTOR_VERSION,
+};
+
+use alerts::AlertStore;
+use archive::{ArchivedScrape, Storage};
+use audit::{AuditEvent, AuditLog};
+use breaker::CircuitBreaker;
+use cache::NewsCache;
+use clusters::SourceCache;
+use config::Config;
+use dates::DateFormat;
+use delivery::{ChatSpacing, DeliveryQueue};
+use demo::DemoMode;
+use fetch_queue::FetchQueue;
+use lease::Leases;
+use local::LocalCache;
+use metrics::Metrics;
+use newsletter::NewsletterCache;
+use politeness::HostLimiter;
+use problem::{Lang, Problem};
+use provenance::Provenance;
+use reload::{Clients, LiveConfig};
+use repair::RepairLog;
+use scheduler::Scheduler;
+use scoring::SortOrder;
+use snapshot::SnapshotStore;
+use stats::{ScrapeLog, StatsStore};
+use subscriptions::SubscriptionStore;
+use takedown::TakedownStore;
+use watch::WatchStore;
+use webhooks::WebhookStore;
+
+// Shared state handed to every handler
+#[derive(Clone)]
+pub struct AppState {
+    // The configuration this state was taken with; see reload.rs for how a
+    // reload replaces it
+    pub config: Arc<Config>,
+    pub live_config: Arc<LiveConfig>,
+    pub client: reqwest::Client,
+    // For webhooks, notify URLs and heartbeats; see url_safety.rs
+    pub callback_client: reqwest::Client,
+    pub scrape_client: reqwest::Client,
+    pub host_limiter: Arc<HostLimiter>,
+    // Every upstream fetch waits its turn here
+    pub fetches: Arc<FetchQueue>,
+    pub snapshots: Option<Arc<SnapshotStore>>,
+    pub metrics: Arc<Metrics>,
+    pub homepage_breaker: Arc<CircuitBreaker>,
+    pub news_cache: Arc<NewsCache>,
+    pub subscriptions: Arc<SubscriptionStore>,
+    pub webhooks: Arc<WebhookStore>,
+    // Notifications waiting to be sent again
+    pub deliveries: Arc<DeliveryQueue>,
+    // Shared by everything posting to Telegram chats
+    pub telegram_spacing: Arc<ChatSpacing>,
+    pub watches: Arc<WatchStore>,
+    pub alerts: Arc<AlertStore>,
+    // Archived articles redacted through the admin API
+    pub takedowns: Arc<TakedownStore>,
+    // Scrapes and admin actions, for /api/admin/audit
+    pub audit: Arc<AuditLog>,
+    // Homepage scrape outcomes per day, and the nightly statistics
+    pub scrape_log: Arc<ScrapeLog>,
+    pub daily_stats: Arc<StatsStore>,
+    pub scheduler: Arc<Scheduler>,
+    // Suggested selectors for pages whose latest scrape found nothing
+    pub repairs: Arc<RepairLog>,
+    pub local_news: Arc<LocalCache>,
+    // Latest scrape of each NEWS_SOURCES outlet
+    pub sources: Arc<SourceCache>,
+    // Latest scrape of each NEWSLETTER_URLS digest
+    pub newsletters: Arc<NewsletterCache>,
+    // Local until serve() sets up the backend from LOCK_URL
+    pub leases: Arc<Leases>,
+    // Set by serve() when ARCHIVE_URL is configured
+    pub archive: Option<Arc<dyn Storage>>,
+    // Set by serve() when an embedding backend is configured
+    #[cfg(feature = "semantic")]
+    pub semantic: Option<Arc<semantic::SemanticIndex>>,
+}
+
+impl AppState {
+    pub fn new(config: Config) -> AppState {
+        let snapshots = config.snapshot_html.then(|| {
+            Arc::new(SnapshotStore::new(
+                config.snapshot_dir(),
+                config.snapshot_max_files,
+                config.snapshot_max_age_days,
+            ))
+        });
+
+        let metrics = Arc::new(Metrics::default());
+        let homepage_breaker = Arc::new(CircuitBreaker::new(
+            "homepage",
+            config.breaker_failure_threshold,
+            Duration::from_secs(config.breaker_cooldown_secs),
+            metrics.clone(),
+        ));
+
+        let config = Arc::new(config);
+        let clients = Clients::new(&config);
+        AppState {
+            callback_client: clients.callback.clone(),
+            scrape_client: clients.scrape.clone(),
+            host_limiter: Arc::new(HostLimiter::new(config.per_host_concurrency)),
+            fetches: Arc::new(FetchQueue::new(
+                config.fetch_concurrency,
+                config.fetch_queue_size,
+                metrics.clone(),
+            )),
+            deliveries: Arc::new(DeliveryQueue::new(
+                config.deliveries_path(),
+                config.delivery_max_attempts,
+                Duration::from_secs(config.delivery_backoff_secs),
+                Duration::from_secs(config.delivery_max_backoff_secs),
+                metrics.clone(),
+            )),
+            telegram_spacing: Arc::new(ChatSpacing::new(delivery::TELEGRAM_CHAT_INTERVAL)),
+            snapshots,
+            metrics,
+            homepage_breaker,
+            news_cache: Arc::new(NewsCache::default()),
+            subscriptions: Arc::new(SubscriptionStore::new(config.subscriptions_path())),
+            webhooks: Arc::new(WebhookStore::new(config.webhooks_path())),
+            watches: Arc::new(WatchStore::new(config.watches_path())),
+            alerts: Arc::new(AlertStore::new(config.alerts_path())),
+            takedowns: Arc::new(TakedownStore::new(config.takedowns_path())),
+            audit: Arc::new(AuditLog::new(
+                config.audit_log.then(|| config.audit_log_path()),
+            )),
+            scrape_log: Arc::new(ScrapeLog::new(config.scrape_log_path())),
+            daily_stats: Arc::new(StatsStore::new(config.daily_stats_path())),
+            scheduler: Arc::new(Scheduler::new(config.scheduler_jobs.clone())),
+            repairs: Arc::new(RepairLog::default()),
+            local_news: Arc::new(LocalCache::default()),
+            sources: Arc::new(SourceCache::default()),
+            newsletters: Arc::new(NewsletterCache::default()),
+            leases: Arc::new(Leases::local()),
+            archive: None,
+            #[cfg(feature = "semantic")]
+            semantic: None,
+            live_config: Arc::new(LiveConfig::new(config.clone(), clients)),
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct NewsResponse {
+    pub scraped_at: DateTime<Utc>,
+    pub news: Vec<NewsItem>,
+    pub error: Option<String>,
+    // Set when upstream is failing and this is the last good scrape
+    pub stale: bool,
+    // How a homepage scrape was produced; v2 only
+    #[serde(skip)]
+    pub provenance: Option<Provenance>,
+    // Sample data served under DEMO_MODE; v2 only, v1 responses get the
+    // X-Demo header instead
+    #[serde(skip)]
+    pub demo: bool,
+    // An archived snapshot served for ?at=, taken at scraped_at. Marked
+    // "snapshot": true in the v1 JSON and XML bodies
+    #[serde(skip)]
+    pub snapshot: bool,
+}
+
+// Homepage news along with how long ago it was scraped
+pub struct CachedNews {
+    pub response: NewsResponse,
+    pub age: Duration,
+}
+
+#[derive(Deserialize)]
+struct NewsParams {
+    fields: Option<String>,
+    format: Option<String>,
+    callback: Option<String>,
+    tz: Option<String>,
+    locale: Option<String>,
+    language: Option<String>,
+    // RFC 3339 instant: serve the archived snapshot nearest to it
+    at: Option<String>,
+    // page, prominence, published_at, title or score
+    sort: Option<String>,
+}
+
+// Header naming when the archived snapshot served for ?at= was taken
+pub const SNAPSHOT_AT: &str = "x-snapshot-at";
+
+// Helper function to create an error response
+pub fn create_error_response(error_message: String) -> Json<NewsResponse> {
+    Json(NewsResponse {
+        scraped_at: Utc::now(),
+        news: vec![],
+        error: Some(error_message),
+        stale: false,
+        provenance: None,
+        demo: false,
+        snapshot: false,
+    })
+}
+
+// Helper function to build the application router
+pub fn router(state: AppState) -> Router {
+    reload::reloadable(state, routes)
+}
+
+// The routes and middleware, built with one configuration
+fn routes(state: AppState) -> Router {
+    // Enable CORS for CORS_ORIGINS, or any origin when it's empty
+    let origins = if state.config.cors_origins.is_empty() {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            state
+                .config
+                .cors_origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        )
+    };
+    let cors = CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(vec![Method::GET, Method::POST, Method::PUT, Method::DELETE])
+        .allow_headers([
+            HeaderName::from_static("authorization"),
+            HeaderName::from_static("content-type"),
+            HeaderName::from_static(request_id::HEADER),
+        ])
+        .expose_headers([HeaderName::from_static(request_id::HEADER)]);
+
+    let router = Router::new()
+        .nest_service(
+            "/",
+            ServeDir::new("public").append_index_html_on_directories(true),
+        )
+        // Unversioned routes are aliases of v1, see the policy in v2.rs
+        .route("/api/news", get(news_handler))
+        .route("/api/v1/news", get(news_handler))
+        .route("/api/v2/news", get(v2::news_handler))
+        .route("/api/top", get(v2::top_handler))
+        .route("/api/news/for-me", get(preferences::for_me_handler))
+        .route(
+            "/api/preferences",
+            put(preferences::save_handler).delete(preferences::clear_handler),
+        )
+        .route("/api/sections/:name", get(section_handler))
+        .route("/api/local", get(local::list_handler))
+        .route("/api/local/:city", get(local::news_handler))
+        .route("/api/clusters", get(clusters::clusters_handler))
+        .route("/api/digest/today", get(newsletter::today_handler))
+        .route(
+            "/api/edition/:file",
+            get(edition::epub_handler).layer(middleware::from_fn(ranges::middleware)),
+        )
+        .route("/api/opds", get(edition::catalog_handler))
+        .route("/api/archive/search", get(archive::search_handler))
+        .route("/api/archive/history", get(archive::history_handler))
+        .route("/api/article/engagement", get(archive::engagement_handler))
+        .route(
+            "/api/archive/export",
+            get(archive::export_handler).layer(middleware::from_fn(ranges::middleware)),
+        )
+        .route("/api/stats/daily", get(stats::daily_handler))
+        .route(
+            "/api/analytics/placement",
+            get(analytics::placement_handler),
+        )
+        .route("/api/scrape", post(scrape::scrape_handler))
+        .route("/api/articles/batch", post(article::batch_handler))
+        .route("/api/article/summary", get(summary::summary_handler))
+        .route("/api/entities/:name/news", get(entities::news_handler))
+        .route("/api/subscriptions", post(subscriptions::create_handler))
+        .route(
+            "/api/subscriptions/:id",
+            get(subscriptions::get_handler)
+                .put(subscriptions::update_handler)
+                .delete(subscriptions::delete_handler),
+        )
+        .route(
+            "/api/subscriptions/:id/unsubscribe",
+            get(subscriptions::unsubscribe_page_handler).post(subscriptions::unsubscribe_handler),
+        )
+        .route("/api/webhooks", post(webhooks::create_handler))
+        .route(
+            "/api/webhooks/:id",
+            get(webhooks::get_handler)
+                .put(webhooks::update_handler)
+                .delete(webhooks::delete_handler),
+        )
+        .route("/api/watch", post(watch::create_handler))
+        .route(
+            "/api/watch/:id",
+            get(watch::get_handler).delete(watch::delete_handler),
+        )
+        .route("/api/watch/:id/events", get(watch::events_handler))
+        .route(
+            "/api/alerts",
+            get(alerts::list_handler).post(alerts::create_handler),
+        )
+        .route(
+            "/api/alerts/:id",
+            get(alerts::get_handler).delete(alerts::delete_handler),
+        )
+        .route("/api/alerts/:id/events", get(alerts::events_handler))
+        .route(
+            "/api/alerts/:id/unsubscribe",
+            get(alerts::unsubscribe_page_handler).post(alerts::unsubscribe_handler),
+        )
+        .route("/api/admin/scheduler", get(admin::scheduler_handler))
+        .route("/api/admin/selectors", get(admin::selectors_handler))
+        .route("/api/admin/reload", post(reload::reload_handler))
+        .route("/api/admin/audit", get(audit::list_handler))
+        .route("/api/admin/cache/flush", post(admin::flush_cache_handler))
+        .route(
+            "/api/admin/scheduler/:name/run",
+            post(admin::run_job_handler),
+        )
+        .route("/api/admin/deliveries", get(admin::deliveries_handler))
+        .route(
+            "/api/admin/takedowns",
+            get(takedown::list_handler).post(takedown::create_handler),
+        )
+        .route(
+            "/api/admin/deliveries/:id",
+            delete(admin::delete_delivery_handler),
+        )
+        .route(
+            "/api/admin/deliveries/:id/requeue",
+            post(admin::requeue_delivery_handler),
+        )
+        .route("/metrics", get(metrics_handler));
+    #[cfg(feature = "semantic")]
+    let router = router.route("/api/search/semantic", get(semantic::search_handler));
+    #[cfg(feature = "tts")]
+    let router = router
+        .route(
+            "/api/briefing.mp3",
+            get(briefing::latest_handler).layer(middleware::from_fn(ranges::middleware)),
+        )
+        .route("/api/briefing.rss", get(briefing::feed_handler))
+        .route(
+            "/api/briefing/:file",
+            get(briefing::episode_handler).layer(middleware::from_fn(ranges::middleware)),
+        );
+    #[cfg(feature = "read_later")]
+    let router = router
+        .route("/api/save", post(read_later::save_handler))
+        .route(
+            "/api/read-later/pocket/connect",
+            get(read_later::pocket_connect_handler),
+        )
+        .route(
+            "/api/read-later/pocket/callback",
+            get(read_later::pocket_callback_handler),
+        );
+
+    router
+        .layer(middleware::from_fn(problem::middleware))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            limits::middleware,
+        ))
+        .layer(middleware::from_fn(fetch_queue::middleware))
+        .layer(middleware::from_fn(request_id::middleware))
+        .layer(cors)
+        .with_state(state)
+}
+
+async fn news_handler(
+    state: State<AppState>,
+    headers: HeaderMap,
+    params: Query<NewsParams>,
+) -> Response {
+    v2::with_version(news_v1(state, headers, params).await, 1)
+}
+
+// The homepage news in the v1 shape, in any of the negotiable formats
+async fn news_v1(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<NewsParams>,
+) -> Response {
+    let fields = match params.fields.as_deref().map(fields::parse_fields) {
+        Some(Ok(fields)) => Some(fields),
+        Some(Err(error_message)) => {
+            return invalid_parameter(&headers, "fields", error_message);
+        }
+        None => None,
+    };
+
+    let dates = match DateFormat::parse(params.tz.as_deref(), params.locale.as_deref()) {
+        Ok(dates) => dates,
+        Err(error) => return invalid_parameter(&headers, error.parameter, error.message),
+    };
+
+    let language = match params.language.as_deref().map(language::parse_language) {
+        Some(Ok(language)) => Some(language),
+        Some(Err(error_message)) => {
+            return invalid_parameter(&headers, "language", error_message);
+        }
+        None => None,
+    };
+
+    let at = match params.at.as_deref().map(archive::parse_instant) {
+        Some(Ok(at)) => Some(at),
+        Some(Err(error_message)) => {
+            return invalid_parameter(&headers, "at", error_message);
+        }
+        None => None,
+    };
+
+    let sort = match params.sort.as_deref().map(scoring::parse_sort) {
+        Some(Ok(sort)) => sort,
+        Some(Err(error_message)) => {
+            return invalid_parameter(&headers, "sort", error_message);
+        }
+        None => SortOrder::Page,
+    };
+
+    // An explicit ?format= wins over the Accept header
+    let format = match params.format.as_deref() {
+        Some(format) => match formats::parse_format(format, params.callback.as_deref()) {
+            Ok(format) => format,
+            Err(error) => return invalid_parameter(&headers, error.parameter, error.message),
+        },
+        None => {
+            let accept = headers
+                .get(header::ACCEPT)
+                .and_then(|value| value.to_str().ok());
+            match formats::negotiate(accept) {
+                Some(format) => format,
+                None if problem::is_requested(&headers) => {
+                    return Problem::not_acceptable(formats::NEGOTIABLE_TYPES)
+                        .respond(Lang::from_headers(&headers))
+                }
+                None => return formats::not_acceptable(),
+            }
+        }
+    };
+
+    let (mut response, age) = match at {
+        Some(at) => match archive::snapshot_news(&state, at).await {
+            Ok(response) => (response, None),
+            Err(problem) if problem::is_requested(&headers) => {
+                return problem.respond(Lang::from_headers(&headers));
+            }
+            Err(problem) => {
+                let error_message = problem.reason.unwrap_or_default();
+                return (problem.kind.status(), create_error_response(error_message))
+                    .into_response();
+            }
+        },
+        None => match serve_news(&state).await {
+            Ok(cached) => (cached.response, Some(cached.age)),
+            Err(response) if problem::is_requested(&headers) => {
+                let reason = response.error.unwrap_or_default();
+                return Problem::upstream_unavailable(reason).respond(Lang::from_headers(&headers));
+            }
+            Err(response) => (response, None),
+        },
+    };
+    if let Some(language) = language {
+        response.news = language::filter(response.news, language);
+    }
+    scoring::sort(
+        &mut response.news,
+        sort,
+        response.scraped_at,
+        &state.config.scoring,
+    );
+
+    let mut rendered = formats::render(
+        &format,
+        &response,
+        fields.as_deref(),
+        &dates,
+        &state.config.budget,
+    );
+    let cache_headers = match age {
+        // Proxies may keep serving this while we revalidate in the background
+        Some(age) => {
+            let soft_ttl = state.config.cache_soft_ttl_secs;
+            let hard_ttl = state.config.cache_hard_ttl_secs.max(soft_ttl);
+            vec![
+                (header::AGE, age.as_secs().to_string()),
+                (
+                    header::CACHE_CONTROL,
+                    format!(
+                        "public, max-age={}, stale-while-revalidate={}",
+                        soft_ttl.saturating_sub(age.as_secs()),
+                        hard_ttl - soft_ttl
+                    ),
+                ),
+            ]
+        }
+        // Archived snapshots don't change, though a later scrape can come
+        // nearer to a recent instant
+        None if at.is_some() => vec![
+            (
+                HeaderName::from_static(SNAPSHOT_AT),
+                response.scraped_at.to_rfc3339(),
+            ),
+            (header::CACHE_CONTROL, "public, max-age=3600".to_string()),
+        ],
+        None => vec![(header::CACHE_CONTROL, "no-store".to_string())],
+    };
+    for (name, value) in cache_headers {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            rendered.headers_mut().insert(name, value);
+        }
+    }
+    if response.demo {
+        return demo::flagged(rendered);
+    }
+    rendered
+}
+
+// Helper function to reject a bad query parameter, as a problem document
+// for clients that asked for one and in the v1 shape for everyone else
+fn invalid_parameter(headers: &HeaderMap, name: &str, error_message: String) -> Response {
+    if problem::is_requested(headers) {
+        return Problem::invalid_parameter(name, error_message)
+            .respond(Lang::from_headers(headers));
+    }
+    (
+        StatusCode::BAD_REQUEST,
+        create_error_response(error_message),
+    )
+        .into_response()
+}
+
+// Helper function implementing stale-while-revalidate for the homepage:
+// fresh entries are served as is, entries past the soft TTL are served while
+// a background refresh runs, and only entries past the hard TTL (or a cold
+// cache) make the request wait for upstream
+pub async fn get_news(state: &AppState) -> Result<CachedNews, NewsResponse> {
+    let soft_ttl = Duration::from_secs(state.config.cache_soft_ttl_secs);
+    let hard_ttl = Duration::from_secs(state.config.cache_hard_ttl_secs).max(soft_ttl);
+
+    if let Some(entry) = state.news_cache.get() {
+        let age = entry.age();
+        if age < soft_ttl {
+            state
+                .metrics
+                .increment("corriere_cache_requests_total", &[("result", "hit")]);
+            return Ok(CachedNews {
+                response: from_cache(entry.response, "hit"),
+                age,
+            });
+        }
+        if age < hard_ttl {
+            state
+                .metrics
+                .increment("corriere_cache_requests_total", &[("result", "revalidate")]);
+            if let Some(guard) = state.news_cache.try_begin_refresh() {
+                let state = state.clone();
+                request_id::spawn(async move {
+                    let _guard = guard;
+                    if let Err(error_message) = coalesced_refresh(&state).await {
+                        eprintln!(
+                            "{}Background refresh failed: {}",
+                            request_id::prefix(),
+                            error_message
+                        );
+                    }
+                });
+            }
+            return Ok(CachedNews {
+                response: from_cache(entry.response, "revalidate"),
+                age,
+            });
+        }
+    }
+
+    state
+        .metrics
+        .increment("corriere_cache_requests_total", &[("result", "miss")]);
+    match coalesced_refresh(state).await {
+        Ok(response) => Ok(CachedNews {
+            response,
+            age: Duration::ZERO,
+        }),
+        // Upstream is failing: fall back to the last good scrape, however old
+        Err(error_message) => match state.news_cache.get() {
+            Some(entry) => {
+                state
+                    .metrics
+                    .increment("corriere_stale_responses_total", &[]);
+                Ok(CachedNews {
+                    age: entry.age(),
+                    response: from_cache(
+                        NewsResponse {
+                            stale: true,
+                            ..entry.response
+                        },
+                        "stale",
+                    ),
+                })
+            }
+            None => Err(create_error_response(error_message).0),
+        },
+    }
+}
+
+// Helper function for the HTTP handlers: the homepage from get_news, or the
+// sample homepage when DEMO_MODE says so. Background jobs call get_news
+// itself, so sample data is never notified, published or indexed
+pub async fn serve_news(state: &AppState) -> Result<CachedNews, NewsResponse> {
+    let news = match state.config.demo_mode {
+        DemoMode::Always => None,
+        _ => Some(get_news(state).await),
+    };
+    match news {
+        Some(Ok(cached)) => Ok(cached),
+        // Upstream is failing with nothing scraped yet
+        Some(Err(response)) if !state.config.demo_mode.stands_in(false) => Err(response),
+        _ => {
+            state
+                .metrics
+                .increment("corriere_demo_responses_total", &[]);
+            Ok(CachedNews {
+                response: demo::sample_response(),
+                age: Duration::ZERO,
+            })
+        }
+    }
+}
+
+// Helper function to note in the provenance of a cached response how the
+// cache answered
+fn from_cache(mut response: NewsResponse, cache_status: &'static str) -> NewsResponse {
+    if let Some(provenance) = &mut response.provenance {
+        provenance.cache_status = cache_status;
+    }
+    response
+}
+
+// Helper function to refresh the homepage through the cache's single flight,
+// so concurrent misses trigger only one upstream fetch
+pub(crate) async fn coalesced_refresh(state: &AppState) -> Result<NewsResponse, String> {
+    let started = Instant::now();
+    let (result, joined) = state.news_cache.coalesce(|| refresh_news(state)).await;
+    if joined {
+        state
+            .metrics
+            .increment("corriere_coalesced_requests_total", &[]);
+    } else {
+        stats::record_scrape(state, result.is_ok()).await;
+        let items = result
+            .as_ref()
+            .map(|response| response.news.len())
+            .map_err(Clone::clone);
+        let url = &state.config.homepage_url;
+        audit::record(state, AuditEvent::scrape("homepage", url, started, &items)).await;
+    }
+    result
+}
+
+// Helper function to scrape the homepage and store the result in the cache
+async fn refresh_news(state: &AppState) -> Result<NewsResponse, String> {
+    // Fetch the HTML content, unless the breaker says upstream is down
+    let mut page = state
+        .homepage_breaker
+        .call(fetch_queue::fetch_page(
+            state,
+            &state.client,
+            &state.config.homepage_url,
+        ))
+        .await?;
+    let response = std::mem::take(&mut page.html);
+
+    // Keep a copy of the raw page for offline replay, without delaying the response
+    if let Some(snapshots) = &state.snapshots {
+        let snapshots = snapshots.clone();
+        let html = response.clone();
+        let log_prefix = request_id::prefix();
+        tokio::task::spawn_blocking(move || {
+            if let Err(error_message) = snapshots.save(&html, Utc::now()) {
+                eprintln!("{}Failed to store snapshot: {}", log_prefix, error_message);
+            }
+        });
+    }
+
+    // Parse and extract off the async runtime
+    let parse_mode = state.config.parse_mode;
+    let min_items = state.config.selector_min_items;
+    let sets = state
+        .config
+        .selector_sets
+        .for_source(scheduler::HOMEPAGE_JOB);
+    let (news_list, set, suggestions, provenance) = extract::run_blocking(move || {
+        let (news, set) = extract::extract_with_fallback(
+            &response,
+            &sets,
+            extract::CORRIERE_BASE_URL,
+            extract::HOMEPAGE_LIMIT,
+            parse_mode,
+            min_items,
+        )?;
+        // The page layout probably changed: look for selectors that still work
+        let suggestions = news
+            .is_empty()
+            .then(|| repair::suggest(&response, extract::CORRIERE_BASE_URL));
+        let provenance = Provenance::new(&page, &set, &sets);
+        Ok::<_, String>((news, set, suggestions, provenance))
+    })
+    .await??;
+    state.metrics.increment(
+        "corriere_selector_sets_used_total",
+        &[("source", scheduler::HOMEPAGE_JOB), ("set", &set)],
+    );
+    match suggestions {
+        Some(suggestions) => repair::report(
+            state,
+            scheduler::HOMEPAGE_JOB,
+            &state.config.homepage_url,
+            suggestions,
+        ),
+        None => state.repairs.clear(scheduler::HOMEPAGE_JOB),
+    }
+
+    let news_list = validate::apply(
+        state,
+        scheduler::HOMEPAGE_JOB,
+        &state.config.homepage_url,
+        news_list,
+    );
+
+    let news_response = NewsResponse {
+        scraped_at: Utc::now(),
+        news: news_list,
+        error: None,
+        stale: false,
+        provenance: Some(provenance),
+        demo: false,
+        snapshot: false,
+    };
+    state.news_cache.store(news_response.clone());
+
+    if let Some(archive) = &state.archive {
+        let archive = archive.clone();
+        let takedowns = state.takedowns.clone();
+        let mut scrape = ArchivedScrape {
+            scraped_at: news_response.scraped_at,
+            news: news_response.news.clone(),
+        };
+        request_id::spawn(async move {
+            let recorded = match takedowns.redact_items(&mut scrape.news) {
+                Ok(()) => archive.record(&scrape).await,
+                Err(error_message) => Err(error_message),
+            };
+            if let Err(error_message) = recorded {
+                eprintln!(
+                    "{}Failed to archive scrape: {}",
+                    request_id::prefix(),
+                    error_message
+                );
+            }
+        });
+    }
+
+    Ok(news_response)
+}
+
+// The latest scrape of a section job. Under DEMO_MODE=fallback the sample
+// homepage stands in while the section can't be scraped and there's no
+// earlier copy
+async fn section_handler(State(state): State<AppState>, Path(name): Path<String>) -> Response {
+    let name = name.to_ascii_lowercase();
+    if state.config.demo_mode == DemoMode::Always {
+        return demo::flagged(Json(demo::sample_response()));
+    }
+    match scheduler::current_section(&state, &name).await {
+        Some(Ok(response)) => Json(response).into_response(),
+        Some(Err(_)) if state.config.demo_mode.stands_in(false) => {
+            demo::flagged(Json(demo::sample_response()))
+        }
+        Some(Err(error_message)) => (
+            StatusCode::BAD_GATEWAY,
+            create_error_response(error_message),
+        )
+            .into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            create_error_response(format!("No section named '{}'", name)),
+        )
+            .into_response(),
+    }
+}
+
+async fn metrics_handler(State(state): State<AppState>) -> Response {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+        .into_response()
+}
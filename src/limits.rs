@@ -0,0 +1,93 @@
+use axum::body::{to_bytes, Body};
+use axum::extract::{Request, State};
+use axum::http::{header, Method};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::time::Duration;
+
+use crate::problem::{Lang, Problem};
+use crate::AppState;
+
+// Timeouts of routes that need a different one than REQUEST_TIMEOUT_SECS:
+// the homepage should answer fast, exports and job runs take a while
+pub fn default_route_timeouts() -> Vec<(String, Duration)> {
+    [
+        ("/api/news", 10),
+        ("/api/v1/news", 10),
+        ("/api/v2/news", 10),
+        ("/api/archive/export", 120),
+        ("/api/edition", 120),
+        ("/api/admin/scheduler", 300),
+    ]
+    .into_iter()
+    .map(|(path, secs)| (path.to_string(), Duration::from_secs(secs)))
+    .collect()
+}
+
+// Helper function to find the timeout of a request path: the one of the
+// longest configured route it falls under, whole segments only, or the
+// default
+pub fn timeout_for(path: &str, routes: &[(String, Duration)], default: Duration) -> Duration {
+    routes
+        .iter()
+        .filter(|(route, _)| {
+            let route = route.trim_end_matches('/');
+            path.strip_prefix(route)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+        .max_by_key(|(route, _)| route.len())
+        .map(|(_, timeout)| *timeout)
+        .unwrap_or(default)
+}
+
+// Middleware enforcing the route's timeout and, on requests with a body,
+// MAX_REQUEST_BODY_BYTES. Either failure is a problem document (408 or 413)
+// whatever the client accepts. The timeout covers reading the body and the
+// handler up to the response headers, so a slow upload can't hold a request
+// open but event streams aren't cut once they've started
+pub async fn middleware(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let config = &state.config;
+    let lang = Lang::from_headers(request.headers());
+    let timeout = timeout_for(
+        request.uri().path(),
+        &config.route_timeouts,
+        Duration::from_secs(config.request_timeout_secs),
+    );
+
+    let handled = async {
+        let request = if matches!(
+            *request.method(),
+            Method::POST | Method::PUT | Method::PATCH
+        ) {
+            let limit = config.max_request_body_bytes;
+            let declared = request
+                .headers()
+                .get(header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<usize>().ok());
+            if declared.is_some_and(|length| length > limit) {
+                return Problem::payload_too_large(limit).respond(lang);
+            }
+            // Chunked bodies don't declare a length, so the limit is checked
+            // while reading
+            let (parts, body) = request.into_parts();
+            match to_bytes(body, limit).await {
+                Ok(body) => Request::from_parts(parts, Body::from(body)),
+                Err(_) => return Problem::payload_too_large(limit).respond(lang),
+            }
+        } else {
+            request
+        };
+        next.run(request).await
+    };
+
+    match tokio::time::timeout(timeout, handled).await {
+        Ok(response) => response,
+        Err(_) => {
+            state
+                .metrics
+                .increment("corriere_request_timeouts_total", &[]);
+            Problem::request_timeout(timeout).respond(lang)
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
+use tokio::net::{TcpListener, UnixListener};
+
+use crate::config::Config;
+
+// First file descriptor passed by systemd socket activation (SD_LISTEN_FDS_START)
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    pub fn describe(&self) -> String {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => format!("http://{}", addr),
+                Err(_) => "tcp socket".to_string(),
+            },
+            Listener::Unix(listener) => match listener.local_addr() {
+                Ok(addr) => match addr.as_pathname() {
+                    Some(path) => format!("unix:{}", path.display()),
+                    None => "unix socket".to_string(),
+                },
+                Err(_) => "unix socket".to_string(),
+            },
+        }
+    }
+}
+
+// Helper function to pick the listener: an inherited systemd socket wins,
+// then a Unix socket path, then the TCP bind address
+pub async fn bind(config: &Config) -> Result<Listener, String> {
+    if let Some(fd) = systemd_listen_fd() {
+        return listener_from_fd(fd);
+    }
+
+    if let Some(path) = &config.unix_socket_path {
+        // A socket file left over from a previous run would make bind fail.
+        // Anything else at that path is left alone: a typo in the setting
+        // mustn't delete a file
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)
+                .map_err(|e| format!("Failed to remove stale socket {}: {}", path.display(), e))?,
+            Ok(_) => {
+                return Err(format!(
+                    "{} exists and is not a socket, not replacing it",
+                    path.display()
+                ))
+            }
+            Err(_) => {}
+        }
+        let listener = UnixListener::bind(path)
+            .map_err(|e| format!("Failed to bind {}: {}", path.display(), e))?;
+        if let Some(mode) = config.unix_socket_mode {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+                .map_err(|e| format!("Failed to set permissions on {}: {}", path.display(), e))?;
+        }
+        return Ok(Listener::Unix(listener));
+    }
+
+    let listener = TcpListener::bind(config.bind_addr)
+        .await
+        .map_err(|e| format!("Failed to bind {}: {}", config.bind_addr, e))?;
+    Ok(Listener::Tcp(listener))
+}
+
+// Helper function implementing the sd_listen_fds protocol for a single socket
+fn systemd_listen_fd() -> Option<RawFd> {
+    let pid: u32 = std::env::var("LISTEN_PID").ok()?.parse().ok()?;
+    let fds: u32 = std::env::var("LISTEN_FDS").ok()?.parse().ok()?;
+    if pid != std::process::id() || fds == 0 {
+        return None;
+    }
+
+    // Don't pass the sockets on to child processes
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+
+    if fds > 1 {
+        eprintln!(
+            "systemd passed {} sockets, only the first one will be used",
+            fds
+        );
+    }
+    Some(SD_LISTEN_FDS_START)
+}
+
+// Helper function to wrap an inherited descriptor, which may be either a
+// Unix or a TCP socket depending on the .socket unit
+fn listener_from_fd(fd: RawFd) -> Result<Listener, String> {
+    // SAFETY: the descriptor was handed to us by systemd and nothing else owns it
+    let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) };
+
+    // local_addr only succeeds when the socket really is AF_UNIX
+    if unix.local_addr().is_ok() {
+        unix.set_nonblocking(true)
+            .map_err(|e| format!("Failed to configure inherited socket: {}", e))?;
+        let listener = UnixListener::from_std(unix)
+            .map_err(|e| format!("Failed to use inherited socket: {}", e))?;
+        return Ok(Listener::Unix(listener));
+    }
+
+    // SAFETY: ownership moves from the UnixListener we just released
+    let tcp = unsafe { std::net::TcpListener::from_raw_fd(unix.into_raw_fd()) };
+    tcp.set_nonblocking(true)
+        .map_err(|e| format!("Failed to configure inherited socket: {}", e))?;
+    let listener =
+        TcpListener::from_std(tcp).map_err(|e| format!("Failed to use inherited socket: {}", e))?;
+    Ok(Listener::Tcp(listener))
+}
+
+pub async fn serve(listener: Listener, app: Router) -> Result<(), String> {
+    match listener {
+        Listener::Tcp(listener) => axum::serve(listener, app)
+            .await
+            .map_err(|e| format!("Server error: {}", e)),
+        Listener::Unix(listener) => serve_unix(listener, app).await,
+    }
+}
+
+// axum::serve only accepts TCP listeners, so Unix sockets get a small
+// hyper accept loop of their own
+async fn serve_unix(listener: UnixListener, app: Router) -> Result<(), String> {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                // Usually running out of file descriptors; back off instead of spinning
+                eprintln!("Failed to accept connection: {}", e);
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let service = TowerToHyperService::new(app.clone());
+
+        tokio::spawn(async move {
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                eprintln!("Failed to serve connection: {}", e);
+            }
+        });
+    }
+}
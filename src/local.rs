@@ -0,0 +1,271 @@
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use crate::cache::Flights;
+use crate::demo::{self, DemoMode};
+use crate::extract::SelectorConfig;
+use crate::{scheduler, AppState, NewsItem};
+
+// Corriere's local editions: city, display name and front page
+const EDITIONS: &[(&str, &str, &str)] = &[
+    ("milano", "Milano", "https://milano.corriere.it/"),
+    ("roma", "Roma", "https://roma.corriere.it/"),
+    ("torino", "Torino", "https://torino.corriere.it/"),
+    ("bergamo", "Bergamo", "https://bergamo.corriere.it/"),
+    ("brescia", "Brescia", "https://brescia.corriere.it/"),
+    (
+        "bologna",
+        "Bologna",
+        "https://corrieredibologna.corriere.it/",
+    ),
+    (
+        "firenze",
+        "Firenze",
+        "https://corrierefiorentino.corriere.it/",
+    ),
+    (
+        "napoli",
+        "Napoli",
+        "https://corrieredelmezzogiorno.corriere.it/",
+    ),
+    ("veneto", "Veneto", "https://corrieredelveneto.corriere.it/"),
+];
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Edition {
+    pub city: String,
+    pub name: String,
+    pub url: String,
+}
+
+// Helper function to list the editions: the built-in ones, with URLs
+// replaced or editions added through LOCAL_EDITIONS
+pub fn editions(overrides: &[(String, String)]) -> Vec<Edition> {
+    let mut editions: Vec<Edition> = EDITIONS
+        .iter()
+        .map(|(city, name, url)| Edition {
+            city: city.to_string(),
+            name: name.to_string(),
+            url: url.to_string(),
+        })
+        .collect();
+    for (city, url) in overrides {
+        match editions.iter_mut().find(|edition| edition.city == *city) {
+            Some(edition) => edition.url = url.clone(),
+            None => editions.push(Edition {
+                city: city.clone(),
+                name: capitalize(city),
+                url: url.clone(),
+            }),
+        }
+    }
+    editions
+}
+
+fn capitalize(city: &str) -> String {
+    let mut chars = city.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// Helper function to tell which of `editions` a link belongs to, from its
+// host, so editions moved or added through LOCAL_EDITIONS are recognized
+pub fn city_of_link(link: &str, editions: &[Edition]) -> Option<String> {
+    let host = Url::parse(link).ok()?.host_str()?.to_ascii_lowercase();
+    editions.iter().find_map(|edition| {
+        let edition_host = Url::parse(&edition.url)
+            .ok()?
+            .host_str()?
+            .to_ascii_lowercase();
+        (edition_host == host).then(|| edition.city.clone())
+    })
+}
+
+// Helper function for the selectors of an edition front page. They use the
+// homepage cards, but without its .body-hp wrapper. SELECTOR_SETS_PATH can
+// still override them under "local-<city>"
+pub fn edition_selectors() -> SelectorConfig {
+    SelectorConfig {
+        container: "body".to_string(),
+        ..SelectorConfig::default()
+    }
+}
+
+#[derive(Serialize, Clone)]
+pub struct LocalNewsItem {
+    #[serde(flatten)]
+    pub item: NewsItem,
+    pub city: String,
+}
+
+#[derive(Serialize, Clone)]
+pub struct LocalNewsResponse {
+    pub city: String,
+    pub edition: String,
+    pub scraped_at: DateTime<Utc>,
+    pub news: Vec<LocalNewsItem>,
+    pub error: Option<String>,
+    // Set when the edition is failing and this is the last good scrape
+    pub stale: bool,
+    // Sample data served under DEMO_MODE, which omits the field otherwise
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub demo: bool,
+}
+
+// Latest scrape of each edition, and the scrape in flight for each
+#[derive(Default)]
+pub struct LocalCache {
+    entries: RwLock<HashMap<String, LocalNewsResponse>>,
+    inflight: Flights<LocalNewsResponse>,
+}
+
+impl LocalCache {
+    pub fn get(&self, city: &str) -> Option<LocalNewsResponse> {
+        self.entries.read().unwrap().get(city).cloned()
+    }
+
+    fn store(&self, response: LocalNewsResponse) {
+        self.entries
+            .write()
+            .unwrap()
+            .insert(response.city.clone(), response);
+    }
+}
+
+// Helper function to build an error response for /api/local/:city
+fn local_error(city: &str, error_message: String) -> LocalNewsResponse {
+    LocalNewsResponse {
+        city: city.to_string(),
+        edition: String::new(),
+        scraped_at: Utc::now(),
+        news: vec![],
+        error: Some(error_message),
+        stale: false,
+        demo: false,
+    }
+}
+
+// Helper function to serve the sample homepage as an edition's, under
+// DEMO_MODE
+fn sample(edition: &Edition) -> LocalNewsResponse {
+    let demo = demo::sample_response();
+    LocalNewsResponse {
+        city: edition.city.clone(),
+        edition: edition.name.clone(),
+        scraped_at: demo.scraped_at,
+        news: demo
+            .news
+            .into_iter()
+            .map(|item| LocalNewsItem {
+                item,
+                city: edition.city.clone(),
+            })
+            .collect(),
+        error: None,
+        stale: false,
+        demo: true,
+    }
+}
+
+// Helper function to scrape an edition and cache the result
+async fn refresh(state: &AppState, edition: &Edition) -> Result<LocalNewsResponse, String> {
+    let source = format!("local-{}", edition.city);
+    let news = scheduler::scrape_page(state, &source, &edition.url, edition_selectors()).await?;
+    let response = LocalNewsResponse {
+        city: edition.city.clone(),
+        edition: edition.name.clone(),
+        scraped_at: Utc::now(),
+        news: news
+            .into_iter()
+            .map(|item| LocalNewsItem {
+                item,
+                city: edition.city.clone(),
+            })
+            .collect(),
+        error: None,
+        stale: false,
+        demo: false,
+    };
+    state.local_news.store(response.clone());
+    Ok(response)
+}
+
+// The configured editions
+pub async fn list_handler(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "editions": editions(&state.config.local_editions),
+    }))
+}
+
+// Items from a local edition's front page, tagged with its city. Scrapes are
+// cached for CACHE_SOFT_TTL_SECS and coalesced, and the last good one is
+// served when the edition can't be reached
+pub async fn news_handler(State(state): State<AppState>, Path(city): Path<String>) -> Response {
+    let city = city.to_ascii_lowercase();
+    let editions = editions(&state.config.local_editions);
+    let Some(edition) = editions.iter().find(|edition| edition.city == city) else {
+        let cities: Vec<&str> = editions
+            .iter()
+            .map(|edition| edition.city.as_str())
+            .collect();
+        return (
+            StatusCode::NOT_FOUND,
+            Json(local_error(
+                &city,
+                format!(
+                    "Unknown edition '{}', expected one of: {}",
+                    city,
+                    cities.join(", ")
+                ),
+            )),
+        )
+            .into_response();
+    };
+    if state.config.demo_mode == DemoMode::Always {
+        return demo::flagged(Json(sample(edition)));
+    }
+
+    let cached = state.local_news.get(&city);
+    let ttl = Duration::from_secs(state.config.cache_soft_ttl_secs);
+    if let Some(cached) = &cached {
+        let age = (Utc::now() - cached.scraped_at)
+            .to_std()
+            .unwrap_or_default();
+        if age < ttl {
+            return Json(cached.clone()).into_response();
+        }
+    }
+
+    // Requests arriving during a scrape of the same edition share it
+    let refreshed = state
+        .local_news
+        .inflight
+        .coalesce(&city, || refresh(&state, edition))
+        .await;
+    match refreshed {
+        Ok(response) => Json(response).into_response(),
+        Err(error_message) => match cached {
+            Some(cached) => Json(LocalNewsResponse {
+                stale: true,
+                ..cached
+            })
+            .into_response(),
+            None if state.config.demo_mode.stands_in(false) => demo::flagged(Json(sample(edition))),
+            None => (
+                StatusCode::BAD_GATEWAY,
+                Json(local_error(&city, error_message)),
+            )
+                .into_response(),
+        },
+    }
+}
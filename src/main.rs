@@ -1,229 +1,102 @@
-use axum::http::{HeaderName, Method};
-use axum::{routing::get, Json, Router};
-use chrono::{DateTime, Utc};
-use dotenv::dotenv;
-use scraper::{Html, Selector};
-use serde::Serialize;
-use std::net::SocketAddr;
-use tower_http::cors::{AllowOrigin, CorsLayer};
-use tower_http::services::ServeDir;
+#[cfg(feature = "s3")]
+use corriere_scraper::archive;
+use corriere_scraper::config::Config;
+use corriere_scraper::demo::DemoMode;
+use corriere_scraper::digest::{self, Mailer};
+#[cfg(feature = "s3")]
+use corriere_scraper::s3;
+use corriere_scraper::static_site;
+use corriere_scraper::{cli, listener, reload, router, server, AppState};
+use dotenvy::dotenv;
 
-#[derive(Serialize)]
-struct NewsItem {
-    title: String,
-    description: String,
-    link: String,
-    image_url: Option<String>,
-}
-
-#[derive(Serialize)]
-struct NewsResponse {
-    scraped_at: DateTime<Utc>,
-    news: Vec<NewsItem>,
-    error: Option<String>,
-}
-
-// Helper function to fetch and parse HTML
-async fn fetch_html(url: &str) -> Result<String, String> {
-    match reqwest::get(url).await {
-        Ok(resp) => match resp.text().await {
-            Ok(text) => Ok(text),
-            Err(e) => Err(format!("Failed to read response text: {}", e)),
-        },
-        Err(e) => Err(format!("Failed to fetch URL: {}", e)),
-    }
-}
-
-// Helper function to create CSS selectors
-fn create_selectors() -> Result<(Selector, Selector, Selector, Selector, Selector, Selector), String>
-{
-    let article_selector = Selector::parse(".bck-media-news")
-        .map_err(|e| format!("Failed to parse article selector: {}", e))?;
-    let title_selector = Selector::parse("h4.title-art-hp")
-        .map_err(|e| format!("Failed to parse title selector: {}", e))?;
-    let link_selector =
-        Selector::parse("a").map_err(|e| format!("Failed to parse link selector: {}", e))?;
-    let summary_selector = Selector::parse("p[class^='subtitle']")
-        .map_err(|e| format!("Failed to parse summary selector: {}", e))?;
-    let img_selector = Selector::parse("img.is_full_image")
-        .map_err(|e| format!("Failed to parse image selector: {}", e))?;
-    let body_hp_selector =
-        Selector::parse(".body-hp").map_err(|e| format!("Failed to parse body selector: {}", e))?;
-
-    Ok((
-        article_selector,
-        title_selector,
-        link_selector,
-        summary_selector,
-        img_selector,
-        body_hp_selector,
-    ))
-}
-
-// Helper function to extract news item from an element
-fn extract_news_item(
-    element: scraper::ElementRef,
-    title_selector: &Selector,
-    link_selector: &Selector,
-    summary_selector: &Selector,
-    img_selector: &Selector,
-) -> Option<NewsItem> {
-    let base_url = "https://www.corriere.it";
+#[tokio::main]
+async fn main() {
+    // Load environment variables from .env file if it exists; a reload reads
+    // it again, without overriding what was set before
+    reload::capture_environment();
+    dotenv().ok();
 
-    let normalize_url = |url: &str| -> String {
-        if !url.starts_with("http") && !url.is_empty() {
-            format!("{}{}", base_url, url)
-        } else {
-            url.to_string()
+    let mut config = match Config::from_env() {
+        Ok(config) => config,
+        Err(error_message) => {
+            eprintln!("Invalid configuration: {}", error_message);
+            std::process::exit(1);
         }
     };
 
-    // Extract Title and Link
-    let (title, link) = if let Some(title_element) = element.select(title_selector).next() {
-        let text = title_element
-            .text()
-            .collect::<Vec<_>>()
-            .join(" ")
-            .trim()
-            .to_string();
-        let href = title_element
-            .select(link_selector)
-            .next()
-            .and_then(|a| a.value().attr("href"))
-            .unwrap_or("")
-            .to_string();
-        (text, normalize_url(&href))
-    } else {
-        return None;
-    };
-
-    // Extract Description and Image
-    let mut description = String::new();
-    let mut image_url = None;
-
-    if let Some(summary) = element.select(summary_selector).next() {
-        description = summary
-            .text()
-            .collect::<Vec<_>>()
-            .join(" ")
-            .trim()
-            .to_string();
-    }
-
-    if let Some(img) = element.select(img_selector).next() {
-        // Try data-src first (lazy loading), then src
-        if let Some(src) = img
-            .value()
-            .attr("data-src")
-            .or_else(|| img.value().attr("src"))
-        {
-            image_url = Some(normalize_url(src));
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        None | Some("serve") => serve(config).await,
+        Some("--demo") | Some("demo") => {
+            config.demo_mode = DemoMode::Always;
+            serve(config).await
         }
-        // Fallback description from alt if empty
-        if description.is_empty() {
-            if let Some(alt) = img.value().attr("alt") {
-                description = alt.to_string();
-            }
+        Some("replay") => cli::replay(&config, args.get(1)),
+        Some("test-plugin") => cli::test_plugin(config, &args[1..]).await,
+        Some("digest") => send_digest(config).await,
+        Some("build") => build(config, &args[1..]).await,
+        #[cfg(feature = "s3")]
+        Some("export") => export(config, args.get(1)).await,
+        Some("--check-config") | Some("check-config") => cli::check_config(&config).await,
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", cli::USAGE);
+            Ok(())
         }
-    }
+        Some(other) => Err(format!("Unknown command '{}'\n{}", other, cli::USAGE)),
+    };
 
-    Some(NewsItem {
-        title,
-        description,
-        link,
-        image_url,
-    })
+    if let Err(error_message) = result {
+        eprintln!("{}", error_message);
+        std::process::exit(1);
+    }
 }
 
+async fn serve(config: Config) -> Result<(), String> {
+    let (state, listener) = server::start(config).await?;
+    let app = router(state);
+    println!("Server listening on {}", listener.describe());
 
-// Helper function to create an error response
-fn create_error_response(error_message: String) -> Json<NewsResponse> {
-    Json(NewsResponse {
-        scraped_at: Utc::now(),
-        news: vec![],
-        error: Some(error_message),
-    })
+    listener::serve(listener, app).await
 }
 
-#[tokio::main]
-async fn main() {
-    // Load environment variables from .env file if it exists
-    dotenv().ok();
-
-    // Enable CORS with specific allowed origins and methods
-    let cors = CorsLayer::new()
-        .allow_origin(AllowOrigin::any())
-        .allow_methods(vec![Method::GET, Method::POST, Method::PUT, Method::DELETE])
-        .allow_headers([
-            HeaderName::from_static("authorization"),
-            HeaderName::from_static("content-type"),
-        ]);
-
-    let app = Router::new()
-        .nest_service(
-            "/",
-            ServeDir::new("public").append_index_html_on_directories(true),
-        )
-        .route("/api/news", get(|| async { get_news().await.unwrap_or_else(|e| e) }))
-        .layer(cors);
-
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    println!("Server listening on http://{}", addr);
-
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+// Sends the daily digest right away, e.g. to check the SMTP settings
+async fn send_digest(config: Config) -> Result<(), String> {
+    let mailer = Mailer::from_config(&config)?.ok_or("SMTP_URL is not set")?;
+    let state = AppState::new(config);
+    let sent = digest::send_digest(&state, &mailer).await?;
+    println!("Sent the digest to {} subscribers", sent);
+    Ok(())
 }
 
-async fn get_news() -> Result<Json<NewsResponse>, Json<NewsResponse>> {
-    let url = "https://www.corriere.it";
-    let mut news_list = Vec::new();
-
-    // Fetch the HTML content
-    let response = match fetch_html(url).await {
-        Ok(text) => text,
-        Err(error_message) => return Err(create_error_response(error_message)),
-    };
-
-    // Parse the HTML document
-    let document = Html::parse_document(&response);
+// Writes the latest news as static files, e.g. from a cron job that then
+// pushes the directory to GitHub Pages or Netlify
+async fn build(config: Config, args: &[String]) -> Result<(), String> {
+    let out = static_site::out_dir(&config.static_out_dir, args)?;
+    let state = AppState::new(config);
+    let manifest = static_site::build(&state, &out).await?;
+    println!(
+        "Wrote {} items to {} ({} files)",
+        manifest.items,
+        out.display(),
+        manifest.files.len() + 1
+    );
+    Ok(())
+}
 
-    // Create CSS selectors
-    let selectors = match create_selectors() {
-        Ok(s) => s,
-        Err(error_message) => return Err(create_error_response(error_message)),
+// Uploads one day of the archive to S3 now, by default yesterday (UTC), e.g.
+// to backfill days the server was down for
+#[cfg(feature = "s3")]
+async fn export(config: Config, day: Option<&String>) -> Result<(), String> {
+    let exporter = s3::S3Exporter::from_config(&config)?.ok_or("S3_BUCKET is not set")?;
+    let day = match day {
+        Some(day) => chrono::NaiveDate::parse_from_str(day, "%Y-%m-%d")
+            .map_err(|e| format!("Invalid date '{}': {}", day, e))?,
+        None => (chrono::Utc::now() - chrono::Duration::days(1)).date_naive(),
     };
 
-    let (
-        article_selector,
-        title_selector,
-        link_selector,
-        summary_selector,
-        img_selector,
-        body_hp_selector,
-    ) = selectors;
-
-    // Extract news items
-    if let Some(section) = document.select(&body_hp_selector).next() {
-        for element in section.select(&article_selector) {
-            if let Some(news_item) = extract_news_item(
-                element,
-                &title_selector,
-                &link_selector,
-                &summary_selector,
-                &img_selector,
-            ) {
-                news_list.push(news_item);
-
-                if news_list.len() >= 20 {
-                    break;
-                }
-            }
-        }
-    }
-
-    Ok(Json(NewsResponse {
-        scraped_at: Utc::now(),
-        news: news_list,
-        error: None,
-    }))
+    let mut state = AppState::new(config);
+    state.archive = archive::connect(&state.config).await?;
+    let uploaded = exporter.export_day(&state, day).await?;
+    println!("Exported {} to S3 ({} objects)", day, uploaded);
+    Ok(())
 }
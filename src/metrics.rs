@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+// Minimal in-process metrics registry, rendered in the Prometheus text format
+// at /metrics. Series are keyed by name plus labels
+#[derive(Default)]
+pub struct Metrics {
+    counters: Mutex<BTreeMap<String, BTreeMap<String, u64>>>,
+    gauges: Mutex<BTreeMap<String, BTreeMap<String, f64>>>,
+}
+
+impl Metrics {
+    pub fn increment(&self, name: &str, labels: &[(&str, &str)]) {
+        self.add(name, labels, 1);
+    }
+
+    pub fn add(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        let mut counters = self.counters.lock().unwrap();
+        *counters
+            .entry(name.to_string())
+            .or_default()
+            .entry(format_labels(labels))
+            .or_default() += value;
+    }
+
+    pub fn set_gauge(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.gauges
+            .lock()
+            .unwrap()
+            .entry(name.to_string())
+            .or_default()
+            .insert(format_labels(labels), value);
+    }
+
+    pub fn counter(&self, name: &str, labels: &[(&str, &str)]) -> u64 {
+        self.counters
+            .lock()
+            .unwrap()
+            .get(name)
+            .and_then(|series| series.get(&format_labels(labels)))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+
+        for (name, series) in self.counters.lock().unwrap().iter() {
+            let _ = writeln!(output, "# TYPE {} counter", name);
+            for (labels, value) in series {
+                let _ = writeln!(output, "{}{} {}", name, labels, value);
+            }
+        }
+        for (name, series) in self.gauges.lock().unwrap().iter() {
+            let _ = writeln!(output, "# TYPE {} gauge", name);
+            for (labels, value) in series {
+                let _ = writeln!(output, "{}{} {}", name, labels, value);
+            }
+        }
+
+        output
+    }
+}
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let pairs: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "{}=\"{}\"",
+                key,
+                value
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n")
+            )
+        })
+        .collect();
+    format!("{{{}}}", pairs.join(","))
+}
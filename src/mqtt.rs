@@ -0,0 +1,333 @@
+use reqwest::Url;
+use serde_json::json;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::config::Config;
+use crate::sent_log::{self, SentLog};
+use crate::{lease, typography, AppState, NewsItem};
+
+const DEFAULT_PORT: u16 = 1883;
+// Limit for the whole exchange with the broker on each poll
+const BROKER_TIMEOUT: Duration = Duration::from_secs(10);
+// The broker drops the connection after 1.5 times this without traffic
+const KEEP_ALIVE_SECS: u16 = 60;
+// Labels marking breaking news at the start of a title, lowercase
+const BREAKING_LABELS: &[&str] = &["ultim'ora", "ultima ora", "breaking news", "breaking"];
+
+// MQTT 3.1.1 packet types, in the high nibble of the first byte
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const DISCONNECT: u8 = 0xE0;
+
+// Where the broker is and who we are to it
+struct Broker {
+    host: String,
+    port: u16,
+    username: Option<String>,
+    password: Option<String>,
+    client_id: String,
+}
+
+// Publishes new homepage items to topics on an MQTT broker, one JSON
+// message per item. Only what's needed to publish is implemented: a
+// connection is opened for each batch and closed once it's out
+pub struct MqttPublisher {
+    broker: Broker,
+    topic: String,
+    breaking_topic: String,
+    qos: u8,
+    retain: bool,
+    sent: SentLog,
+}
+
+// Helper function to tell whether an item is breaking news: new in the
+// opening block, or labelled as such
+pub fn is_breaking(item: &NewsItem) -> bool {
+    let title = typography::normalize(&item.title).to_lowercase();
+    let labelled = BREAKING_LABELS.iter().any(|label| {
+        title
+            .strip_prefix(label)
+            .is_some_and(|rest| rest.trim_start().starts_with([':', '|', '-', '–', '—']))
+    });
+    labelled || item.placement.opening
+}
+
+// The message published for an item
+pub fn payload(item: &NewsItem) -> Vec<u8> {
+    json!({
+        "title": item.title,
+        "description": item.description,
+        "link": item.link,
+        "image_url": item.image_url,
+        "prominence_score": item.placement.score(),
+        "breaking": is_breaking(item),
+    })
+    .to_string()
+    .into_bytes()
+}
+
+impl MqttPublisher {
+    // Helper function to build the publisher, or None when no broker is
+    // configured
+    pub fn from_config(config: &Config) -> Result<Option<MqttPublisher>, String> {
+        let Some(url) = &config.mqtt_url else {
+            return Ok(None);
+        };
+        let url = Url::parse(url).map_err(|e| format!("Invalid MQTT_URL '{}': {}", url, e))?;
+        if url.scheme() != "mqtt" {
+            return Err(format!(
+                "Invalid MQTT_URL: unsupported scheme '{}', expected mqtt",
+                url.scheme()
+            ));
+        }
+        let host = url.host_str().ok_or("Invalid MQTT_URL: missing host")?;
+        if config.mqtt_qos > 1 {
+            return Err(format!("MQTT_QOS must be 0 or 1, got {}", config.mqtt_qos));
+        }
+        if config.mqtt_topic.is_empty() || config.mqtt_breaking_topic.is_empty() {
+            return Err("MQTT_TOPIC and MQTT_BREAKING_TOPIC can't be empty".to_string());
+        }
+
+        Ok(Some(MqttPublisher {
+            broker: Broker {
+                host: host.to_string(),
+                port: url.port().unwrap_or(DEFAULT_PORT),
+                username: Some(url.username())
+                    .filter(|username| !username.is_empty())
+                    .map(str::to_string),
+                password: url.password().map(str::to_string),
+                client_id: config.mqtt_client_id.clone(),
+            },
+            topic: config.mqtt_topic.clone(),
+            breaking_topic: config.mqtt_breaking_topic.clone(),
+            qos: config.mqtt_qos,
+            retain: config.mqtt_retain,
+            sent: SentLog::load(config, "mqtt")?,
+        }))
+    }
+
+    // Helper function to publish the items that weren't published before,
+    // returning how many. On the first run the current items are only
+    // recorded, so starting up doesn't replay the whole homepage
+    pub async fn publish(&mut self, state: &AppState, news: &[NewsItem]) -> Result<usize, String> {
+        if !self.sent.is_stored() {
+            for item in news {
+                self.sent.push(item.link.clone());
+            }
+            self.sent.save()?;
+            return Ok(0);
+        }
+
+        // The homepage can list the same article twice
+        let mut seen = HashSet::new();
+        let fresh: Vec<&NewsItem> = news
+            .iter()
+            .filter(|item| !self.sent.contains(&item.link) && seen.insert(item.link.as_str()))
+            .collect();
+        if fresh.is_empty() {
+            return Ok(0);
+        }
+
+        let mut published = Vec::new();
+        let result = tokio::time::timeout(BROKER_TIMEOUT, self.send(&fresh, &mut published))
+            .await
+            .unwrap_or(Err("Timed out talking to the MQTT broker".to_string()));
+        // Items that went out aren't published again, even when the batch
+        // broke off; the rest are retried on the next poll
+        for link in &published {
+            self.sent.push(link.clone());
+        }
+        if !published.is_empty() {
+            self.sent.save()?;
+        }
+        state.metrics.add(
+            "corriere_mqtt_messages_total",
+            &[("result", "published")],
+            published.len() as u64,
+        );
+        if result.is_err() {
+            state
+                .metrics
+                .increment("corriere_mqtt_messages_total", &[("result", "failed")]);
+        }
+        result.map(|()| published.len())
+    }
+
+    // Helper function to publish a batch on one connection, adding the link
+    // of each item to `published` once it's out
+    async fn send(&self, items: &[&NewsItem], published: &mut Vec<String>) -> Result<(), String> {
+        let mut stream = TcpStream::connect((self.broker.host.as_str(), self.broker.port))
+            .await
+            .map_err(|e| format!("Failed to connect to the MQTT broker: {}", e))?;
+        write(&mut stream, &connect_packet(&self.broker)).await?;
+        let (kind, body) = read_packet(&mut stream).await?;
+        if kind & 0xF0 != CONNACK || body.len() != 2 {
+            return Err("Expected CONNACK from the MQTT broker".to_string());
+        }
+        if body[1] != 0 {
+            return Err(format!(
+                "MQTT broker refused the connection: {}",
+                connack_reason(body[1])
+            ));
+        }
+
+        // Lower ranked items first, so the top story is the latest message
+        let mut packet_id: u16 = 0;
+        for item in items.iter().rev() {
+            let payload = payload(item);
+            let mut topics = vec![self.topic.as_str()];
+            if is_breaking(item) {
+                topics.push(self.breaking_topic.as_str());
+            }
+            for topic in topics {
+                packet_id = packet_id.wrapping_add(1).max(1);
+                let packet = publish_packet(topic, &payload, self.qos, self.retain, packet_id);
+                write(&mut stream, &packet).await?;
+                if self.qos == 1 {
+                    let (kind, body) = read_packet(&mut stream).await?;
+                    if kind & 0xF0 != PUBACK || body != packet_id.to_be_bytes() {
+                        return Err("Expected PUBACK from the MQTT broker".to_string());
+                    }
+                }
+            }
+            published.push(item.link.clone());
+        }
+
+        // The messages are out either way
+        let _ = write(&mut stream, &[DISCONNECT, 0]).await;
+        Ok(())
+    }
+}
+
+fn connack_reason(code: u8) -> &'static str {
+    match code {
+        1 => "unacceptable protocol version",
+        2 => "client identifier rejected",
+        3 => "server unavailable",
+        4 => "bad user name or password",
+        5 => "not authorized",
+        _ => "unknown reason",
+    }
+}
+
+// Helper function to encode the remaining length of a packet
+fn encode_length(mut length: usize, out: &mut Vec<u8>) {
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+}
+
+fn encode_string(value: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    out.extend_from_slice(value);
+}
+
+fn packet(first_byte: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![first_byte];
+    encode_length(body.len(), &mut packet);
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn connect_packet(broker: &Broker) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_string(b"MQTT", &mut body);
+    // Protocol level 4 is MQTT 3.1.1
+    body.push(4);
+    // Clean session, plus the credentials present
+    let mut flags = 0x02;
+    if broker.username.is_some() {
+        flags |= 0x80;
+    }
+    if broker.password.is_some() {
+        flags |= 0x40;
+    }
+    body.push(flags);
+    body.extend_from_slice(&KEEP_ALIVE_SECS.to_be_bytes());
+    encode_string(broker.client_id.as_bytes(), &mut body);
+    for credential in [&broker.username, &broker.password].into_iter().flatten() {
+        encode_string(credential.as_bytes(), &mut body);
+    }
+    packet(CONNECT, &body)
+}
+
+fn publish_packet(topic: &str, payload: &[u8], qos: u8, retain: bool, packet_id: u16) -> Vec<u8> {
+    let mut body = Vec::new();
+    encode_string(topic.as_bytes(), &mut body);
+    if qos > 0 {
+        body.extend_from_slice(&packet_id.to_be_bytes());
+    }
+    body.extend_from_slice(payload);
+    packet(PUBLISH | (qos << 1) | u8::from(retain), &body)
+}
+
+async fn write(stream: &mut TcpStream, packet: &[u8]) -> Result<(), String> {
+    stream
+        .write_all(packet)
+        .await
+        .map_err(|e| format!("Failed to write to the MQTT broker: {}", e))
+}
+
+// Helper function to read one packet: its first byte and its body
+async fn read_packet(stream: &mut TcpStream) -> Result<(u8, Vec<u8>), String> {
+    let read_error = |e: std::io::Error| format!("Failed to read from the MQTT broker: {}", e);
+    let kind = stream.read_u8().await.map_err(read_error)?;
+    let mut length = 0usize;
+    for shift in 0..4 {
+        let byte = stream.read_u8().await.map_err(read_error)?;
+        length |= ((byte & 0x7F) as usize) << (7 * shift);
+        if byte & 0x80 == 0 {
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).await.map_err(read_error)?;
+            return Ok((kind, body));
+        }
+    }
+    Err("Malformed packet from the MQTT broker".to_string())
+}
+
+// Checks the homepage every MQTT_POLL_SECS and publishes what's new, for as
+// long as the server runs
+pub async fn run(state: AppState, mut publisher: MqttPublisher) {
+    let every = Duration::from_secs(state.config.mqtt_poll_secs.max(1));
+    let mut interval = tokio::time::interval(every);
+
+    loop {
+        interval.tick().await;
+        // Settings changed by a reload apply from this run on
+        let state = state.current();
+        let ttl = lease::lease_ttl(every);
+        if !sent_log::should_post(&state, "mqtt", ttl, &mut [&mut publisher.sent]).await {
+            continue;
+        }
+
+        let news = match crate::get_news(&state).await {
+            // A stale copy has nothing new to announce
+            Ok(cached) if !cached.response.stale => cached.response.news,
+            Ok(_) => continue,
+            Err(response) => {
+                eprintln!(
+                    "MQTT: homepage unavailable: {}",
+                    response.error.unwrap_or_default()
+                );
+                continue;
+            }
+        };
+
+        if let Err(error_message) = publisher.publish(&state, &news).await {
+            eprintln!("MQTT publishing failed: {}", error_message);
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, NaiveDate, Utc};
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::cache::Flights;
+use crate::demo::{self, DemoMode};
+use crate::problem::{Lang, Problem};
+use crate::{dates, edition, extract, fetch_queue, AppState};
+
+// Digest pages scraped when NEWSLETTER_URLS doesn't say otherwise
+pub const DEFAULT_NEWSLETTERS: &[(&str, &str)] = &[
+    ("prima-ora", "https://www.corriere.it/newsletter/prima-ora/"),
+    ("caffe", "https://www.corriere.it/caffe-gramellini/"),
+];
+
+// The element holding the digest, tried in order. There is no fallback to
+// the whole page, which would serve its navigation and footer as a digest
+const BODY_SELECTORS: &[&str] = &[
+    ".newsletter-body",
+    ".body-newsletter",
+    "article .chapter",
+    "article",
+    "main",
+];
+const TITLE_SELECTORS: &[&str] = &["h1", "meta[property='og:title']", "title"];
+const DATE_SELECTORS: &[&str] = &["meta[property='article:published_time']", "time[datetime]"];
+const AUTHOR_SELECTORS: &[&str] = &[".writer", ".author", "meta[name='author']"];
+// A heading starts a segment; paragraphs and list items fill it
+const BLOCKS: &str = "h2, h3, p, li, blockquote";
+
+// One issue of a daily digest such as Prima Ora or Il caffè
+#[derive(Serialize, Clone, Debug)]
+pub struct Digest {
+    pub name: String,
+    pub url: String,
+    pub title: String,
+    pub author: Option<String>,
+    // The day the issue is dated, when the page says
+    pub published_on: Option<NaiveDate>,
+    pub segments: Vec<Segment>,
+    pub scraped_at: DateTime<Utc>,
+    // Set when upstream failed and this is an older copy, or when the issue
+    // is dated before today in DIGEST_TIME_ZONE
+    pub stale: bool,
+    // Sample data served under DEMO_MODE, which omits the field otherwise
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub demo: bool,
+}
+
+// A section of the digest, in page order. Text before the first heading
+// makes a segment without one
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Segment {
+    pub position: usize,
+    pub heading: Option<String>,
+    pub paragraphs: Vec<String>,
+    // Articles the segment links to
+    pub links: Vec<SegmentLink>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SegmentLink {
+    pub text: String,
+    pub url: String,
+}
+
+// Wait before scraping a digest again after a failure, doubled with each
+// failure in a row up to NEWSLETTER_CACHE_SECS
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+// Why the last scrape of a digest failed, and when to try again
+struct Failure {
+    error: String,
+    backoff: Duration,
+    retry_at: Instant,
+}
+
+// Latest scrape of each digest, the scrape in flight for each and the ones
+// failing upstream
+#[derive(Default)]
+pub struct NewsletterCache {
+    entries: RwLock<HashMap<String, Digest>>,
+    inflight: Flights<Digest>,
+    failures: Mutex<HashMap<String, Failure>>,
+}
+
+impl NewsletterCache {
+    fn get(&self, name: &str) -> Option<Digest> {
+        self.entries.read().unwrap().get(name).cloned()
+    }
+
+    fn store(&self, digest: Digest) {
+        self.failures.lock().unwrap().remove(&digest.name);
+        self.entries
+            .write()
+            .unwrap()
+            .insert(digest.name.clone(), digest);
+    }
+
+    // The error of the last scrape, while it's too early to try again
+    fn failing(&self, name: &str) -> Option<String> {
+        let failures = self.failures.lock().unwrap();
+        let failure = failures.get(name)?;
+        (Instant::now() < failure.retry_at).then(|| failure.error.clone())
+    }
+
+    fn failed(&self, name: &str, error: String, max_backoff: Duration) {
+        let mut failures = self.failures.lock().unwrap();
+        let backoff = match failures.get(name) {
+            Some(failure) => (failure.backoff * 2).min(max_backoff.max(RETRY_AFTER)),
+            None => RETRY_AFTER,
+        };
+        failures.insert(
+            name.to_string(),
+            Failure {
+                error,
+                backoff,
+                retry_at: Instant::now() + backoff,
+            },
+        );
+    }
+}
+
+fn selector(selector: &str) -> Selector {
+    Selector::parse(selector).expect("Newsletter selectors are valid")
+}
+
+fn text(element: ElementRef) -> String {
+    element
+        .text()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Text of the first element matched by any of the selectors; meta tags
+// contribute their content and time elements their datetime
+fn first_text(document: &Html, selectors: &[&str]) -> Option<String> {
+    selectors.iter().find_map(|candidate| {
+        document.select(&selector(candidate)).find_map(|element| {
+            let value = match element.value().name() {
+                "meta" => element.value().attr("content").map(str::to_string),
+                "time" => element.value().attr("datetime").map(str::to_string),
+                _ => Some(text(element)),
+            }?;
+            let value = value.trim().to_string();
+            (!value.is_empty()).then_some(value)
+        })
+    })
+}
+
+// Helper function to read a date such as 2024-05-01T06:00:00+02:00 or
+// 2024-05-01
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|date| date.date_naive())
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok())
+}
+
+// Helper function to split a digest page into its segments. The generic
+// homepage selectors see a list of teasers; a digest is running text under
+// headings, with links to the articles it summarizes
+pub fn extract_digest(html: &str, name: &str, url: &str) -> Result<Digest, String> {
+    let document = Html::parse_document(html);
+    let base_url = Url::parse(url).ok();
+    let body = BODY_SELECTORS
+        .iter()
+        .find_map(|candidate| document.select(&selector(candidate)).next())
+        .ok_or("No digest body found on the page")?;
+
+    let blocks = selector(BLOCKS);
+    let links = selector("a[href]");
+    let mut segments: Vec<Segment> = Vec::new();
+    for element in body.select(&blocks) {
+        // Blocks nested in another block are read with it
+        let nested = element
+            .ancestors()
+            .take_while(|ancestor| *ancestor != *body)
+            .filter_map(ElementRef::wrap)
+            .any(|ancestor| blocks.matches(&ancestor));
+        if nested {
+            continue;
+        }
+        let content = text(element);
+        if content.is_empty() {
+            continue;
+        }
+
+        if matches!(element.value().name(), "h2" | "h3") {
+            segments.push(Segment {
+                position: segments.len(),
+                heading: Some(content),
+                paragraphs: vec![],
+                links: vec![],
+            });
+            continue;
+        }
+        if segments.is_empty() {
+            segments.push(Segment {
+                position: 0,
+                heading: None,
+                paragraphs: vec![],
+                links: vec![],
+            });
+        }
+        let segment = segments.last_mut().expect("a segment was just added");
+        segment.paragraphs.push(content);
+        for link in element.select(&links) {
+            let href = link.value().attr("href").unwrap_or_default();
+            let url = extract::resolve_url(base_url.as_ref(), href);
+            let text = text(link);
+            if !url.starts_with("http") || text.is_empty() {
+                continue;
+            }
+            if !segment.links.iter().any(|existing| existing.url == url) {
+                segment.links.push(SegmentLink { text, url });
+            }
+        }
+    }
+    // Headings with nothing under them are page furniture, e.g. "Condividi"
+    segments.retain(|segment| !segment.paragraphs.is_empty());
+    for (position, segment) in segments.iter_mut().enumerate() {
+        segment.position = position;
+    }
+    if segments.is_empty() {
+        return Err("No digest segments found on the page".to_string());
+    }
+
+    Ok(Digest {
+        name: name.to_string(),
+        url: url.to_string(),
+        title: first_text(&document, TITLE_SELECTORS).unwrap_or_else(|| name.to_string()),
+        author: first_text(&document, AUTHOR_SELECTORS),
+        published_on: first_text(&document, DATE_SELECTORS).and_then(|value| parse_date(&value)),
+        segments,
+        scraped_at: Utc::now(),
+        stale: false,
+        demo: false,
+    })
+}
+
+// Helper function to get a digest, scraping it again once the cached copy
+// is older than NEWSLETTER_CACHE_SECS. Callers arriving during a scrape share
+// it. A failed scrape falls back to the cached copy, however old, marked as
+// stale, and upstream isn't tried again until the failure's backoff is over
+pub async fn fetch_digest(state: &AppState, name: &str, url: &str) -> Result<Digest, String> {
+    let cached = state.newsletters.get(name);
+    let max_age = Duration::from_secs(state.config.newsletter_cache_secs);
+    let fresh = cached.as_ref().is_some_and(|digest| {
+        (Utc::now() - digest.scraped_at)
+            .to_std()
+            .unwrap_or_default()
+            < max_age
+    });
+
+    let scraped = match (fresh, state.newsletters.failing(name)) {
+        (true, _) => Ok(None),
+        (false, Some(error_message)) => Err(error_message),
+        (false, None) => state
+            .newsletters
+            .inflight
+            .coalesce(name, || scrape_digest(state, name, url))
+            .await
+            .map(Some),
+    };
+    let digest = match (scraped, cached) {
+        (Ok(Some(digest)), _) => digest,
+        (Ok(None), Some(cached)) => cached,
+        (Err(_), Some(cached)) => Digest {
+            stale: true,
+            ..cached
+        },
+        (Ok(None), None) => return Err("No digest scraped yet".to_string()),
+        (Err(error_message), None) => return Err(error_message),
+    };
+    let today = edition::today(&state.config);
+    let outdated = digest.published_on.is_some_and(|day| day < today);
+    Ok(Digest {
+        stale: digest.stale || outdated,
+        ..digest
+    })
+}
+
+// Helper function to scrape a digest page and keep the result, or the
+// failure for the backoff
+async fn scrape_digest(state: &AppState, name: &str, url: &str) -> Result<Digest, String> {
+    let scraped = match fetch_queue::fetch_html(state, &state.client, url).await {
+        Ok(html) => {
+            let (name, url) = (name.to_string(), url.to_string());
+            extract::run_blocking(move || extract_digest(&html, &name, &url)).await?
+        }
+        Err(error_message) => Err(error_message),
+    };
+    state.metrics.increment(
+        "corriere_newsletter_scrapes_total",
+        &[
+            ("newsletter", name),
+            ("result", if scraped.is_ok() { "ok" } else { "failed" }),
+        ],
+    );
+    let max_backoff = Duration::from_secs(state.config.newsletter_cache_secs);
+    match &scraped {
+        Ok(digest) => state.newsletters.store(digest.clone()),
+        Err(error_message) => state
+            .newsletters
+            .failed(name, error_message.clone(), max_backoff),
+    }
+    scraped
+}
+
+#[derive(Deserialize)]
+pub struct DigestParams {
+    // A name from NEWSLETTER_URLS, by default the first
+    pub name: Option<String>,
+    pub tz: Option<String>,
+    pub locale: Option<String>,
+}
+
+// The latest issue of a daily digest, split into its segments. The page
+// decides what "today" is: published_on tells which day the issue is for,
+// and an issue dated before today in DIGEST_TIME_ZONE is marked stale
+pub async fn today_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<DigestParams>,
+) -> Response {
+    let lang = Lang::from_headers(&headers);
+    let dates = match dates::DateFormat::parse(params.tz.as_deref(), params.locale.as_deref()) {
+        Ok(dates) => dates,
+        Err(error) => return Problem::from(error).respond(lang),
+    };
+    let newsletters = &state.config.newsletter_urls;
+    let requested = params.name.as_deref().map(str::to_ascii_lowercase);
+    let found = match &requested {
+        Some(name) => newsletters.iter().find(|(candidate, _)| candidate == name),
+        None => newsletters.first(),
+    };
+    let Some((name, url)) = found else {
+        let names: Vec<&str> = newsletters.iter().map(|(name, _)| name.as_str()).collect();
+        return Problem::not_found(format!(
+            "No newsletter named '{}' (available: {})",
+            requested.unwrap_or_default(),
+            names.join(", ")
+        ))
+        .respond(lang);
+    };
+
+    let digest = match state.config.demo_mode {
+        DemoMode::Always => demo::sample_digest(name, url),
+        mode => match fetch_digest(&state, name, url).await {
+            Err(_) if mode.stands_in(false) => demo::sample_digest(name, url),
+            fetched => fetched,
+        },
+    };
+    match digest {
+        Ok(digest) => {
+            let mut body = serde_json::to_value(digest).unwrap_or_default();
+            if let Some(object) = body.as_object_mut() {
+                dates.apply(object, "scraped_at");
+            }
+            if body["demo"] == true {
+                return demo::flagged(Json(body));
+            }
+            Json(body).into_response()
+        }
+        Err(error_message) => Problem::upstream_unavailable(error_message).respond(lang),
+    }
+}
@@ -0,0 +1,369 @@
+use reqwest::Url;
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::ranking::Placement;
+use crate::scheduler::{self, Job};
+use crate::{budget, extract, fetch_queue, json_file, typography, validate};
+use crate::{AppState, NewsItem};
+
+// A source defined in SOURCE_PLUGINS_PATH rather than in code: where its
+// page is, how to find the items on it and how to clean them up. Each one
+// is registered as a scheduler job at startup and served under
+// /api/sections/:name, e.g.
+// {"name": "ansa-politica", "url": "https://www.ansa.it/politica/", "every": "15m",
+//  "container": "main", "article": "article.news",
+//  "fields": {"title": {"selector": "h2"}, "link": {"selector": "h2 a"}},
+//  "rules": [{"field": "title", "action": "strip_prefix", "value": "VIDEO |"}]}
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct SourcePlugin {
+    pub name: String,
+    pub url: String,
+    // Interval between scrapes, as in SCHEDULER_JOBS: 300, 90s, 15m, 6h or 1d
+    pub every: String,
+    // The element holding the list, and each item within it
+    pub container: String,
+    pub article: String,
+    pub fields: FieldMap,
+    // Applied in order once the fields are read
+    #[serde(default)]
+    pub rules: Vec<PostRule>,
+    #[serde(default = "default_limit")]
+    pub limit: usize,
+}
+
+fn default_limit() -> usize {
+    extract::HOMEPAGE_LIMIT
+}
+
+// Where each field of an item comes from
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct FieldMap {
+    pub title: FieldRule,
+    pub link: FieldRule,
+    pub description: Option<FieldRule>,
+    pub image_url: Option<FieldRule>,
+}
+
+// An element within the item, by CSS selector, and what to read from it:
+// an attribute, or its text when none is named. The link reads href and the
+// image src unless told otherwise. An empty selector is the item itself
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct FieldRule {
+    pub selector: String,
+    pub attribute: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    Title,
+    Description,
+    Link,
+    ImageUrl,
+}
+
+// A post-processing step on one field of every item
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct PostRule {
+    pub field: Field,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    // typography::normalize, with editorial labels removed from titles
+    Normalize,
+    StripPrefix { value: String },
+    StripSuffix { value: String },
+    Replace { from: String, to: String },
+    // Shortened on a word, with an ellipsis
+    Truncate { max_chars: usize },
+    // Items whose field doesn't contain the value are dropped
+    Require { contains: String },
+    // Items whose field contains the value are dropped
+    Exclude { contains: String },
+}
+
+// A plugin with its selectors parsed, ready to run
+pub struct CompiledPlugin<'a> {
+    plugin: &'a SourcePlugin,
+    container: Selector,
+    article: Selector,
+    title: CompiledField,
+    link: CompiledField,
+    description: Option<CompiledField>,
+    image_url: Option<CompiledField>,
+}
+
+struct CompiledField {
+    selector: Option<Selector>,
+    attribute: Option<String>,
+}
+
+fn compile_field(
+    rule: &FieldRule,
+    name: &str,
+    default_attribute: Option<&str>,
+) -> Result<CompiledField, String> {
+    let selector = match rule.selector.trim() {
+        "" => None,
+        selector => Some(
+            Selector::parse(selector)
+                .map_err(|e| format!("Failed to parse {} selector: {}", name, e))?,
+        ),
+    };
+    Ok(CompiledField {
+        selector,
+        attribute: rule
+            .attribute
+            .clone()
+            .or(default_attribute.map(str::to_string)),
+    })
+}
+
+impl SourcePlugin {
+    // Helper function to parse the selectors, which also checks them
+    pub fn compile(&self) -> Result<CompiledPlugin<'_>, String> {
+        let parse = |selector: &str, name: &str| {
+            Selector::parse(selector)
+                .map_err(|e| format!("Failed to parse {} selector: {}", name, e))
+        };
+        Ok(CompiledPlugin {
+            plugin: self,
+            container: parse(&self.container, "container")?,
+            article: parse(&self.article, "article")?,
+            title: compile_field(&self.fields.title, "title", None)?,
+            link: compile_field(&self.fields.link, "link", Some("href"))?,
+            description: self
+                .fields
+                .description
+                .as_ref()
+                .map(|rule| compile_field(rule, "description", None))
+                .transpose()?,
+            image_url: self
+                .fields
+                .image_url
+                .as_ref()
+                .map(|rule| compile_field(rule, "image_url", Some("src")))
+                .transpose()?,
+        })
+    }
+
+    // Helper function to check a definition before registering it
+    pub fn validate(&self) -> Result<(), String> {
+        let valid_name = !self.name.is_empty()
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if !valid_name {
+            return Err("name must be lowercase letters, digits, - or _".to_string());
+        }
+        if self.name == scheduler::HOMEPAGE_JOB || self.name == scheduler::BRIEFING_JOB {
+            return Err(format!("'{}' is a built-in job", self.name));
+        }
+        let url =
+            Url::parse(&self.url).map_err(|e| format!("invalid URL '{}': {}", self.url, e))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!(
+                "invalid URL '{}': expected http or https",
+                self.url
+            ));
+        }
+        scheduler::parse_interval(&self.every)?;
+        if self.limit == 0 {
+            return Err("limit must be at least 1".to_string());
+        }
+        for rule in &self.rules {
+            if let Action::Truncate { max_chars: 0 } = rule.action {
+                return Err("truncate needs max_chars of at least 1".to_string());
+            }
+        }
+        self.compile().map(|_| ())
+    }
+
+    // The scheduler job that scrapes the plugin
+    pub fn job(&self) -> Result<Job, String> {
+        Ok(Job {
+            name: self.name.clone(),
+            every: scheduler::parse_interval(&self.every)?,
+            url: Some(self.url.clone()),
+        })
+    }
+}
+
+// Helper function to read the plugins in a JSON array and validate them.
+// Errors name the plugin at fault
+pub fn load(path: &Path) -> Result<Vec<SourcePlugin>, String> {
+    let plugins: Vec<SourcePlugin> =
+        json_file::load(path)?.ok_or(format!("{} does not exist", path.display()))?;
+    let mut names = HashSet::new();
+    for (index, plugin) in plugins.iter().enumerate() {
+        let label = if plugin.name.is_empty() {
+            format!("#{}", index + 1)
+        } else {
+            format!("'{}'", plugin.name)
+        };
+        plugin
+            .validate()
+            .map_err(|e| format!("Plugin {}: {}", label, e))?;
+        if !names.insert(plugin.name.as_str()) {
+            return Err(format!("Plugin {} is defined twice", label));
+        }
+    }
+    Ok(plugins)
+}
+
+fn read_field(element: ElementRef, field: &CompiledField, base_url: Option<&Url>) -> String {
+    let target = match &field.selector {
+        Some(selector) => element.select(selector).next(),
+        None => Some(element),
+    };
+    let Some(target) = target else {
+        return String::new();
+    };
+    match &field.attribute {
+        Some(attribute) => {
+            let value = target.value().attr(attribute).unwrap_or("");
+            if attribute == "href" || attribute.ends_with("src") {
+                extract::resolve_url(base_url, value)
+            } else {
+                value.trim().to_string()
+            }
+        }
+        None => typography::collapse_whitespace(&target.text().collect::<Vec<_>>().join(" ")),
+    }
+}
+
+fn field_mut(item: &mut NewsItem, field: Field) -> Option<&mut String> {
+    match field {
+        Field::Title => Some(&mut item.title),
+        Field::Description => Some(&mut item.description),
+        Field::Link => Some(&mut item.link),
+        Field::ImageUrl => item.image_url.as_mut(),
+    }
+}
+
+// Helper function to run the post-processing rules over an item. None when
+// a rule drops it
+pub fn apply_rules(mut item: NewsItem, rules: &[PostRule]) -> Option<NewsItem> {
+    for rule in rules {
+        let Some(value) = field_mut(&mut item, rule.field) else {
+            // Only the image can be missing, and then there's nothing to require
+            if let Action::Require { .. } = rule.action {
+                return None;
+            }
+            continue;
+        };
+        match &rule.action {
+            Action::Normalize if rule.field == Field::Title => {
+                *value = typography::normalize_title(value)
+            }
+            Action::Normalize => *value = typography::normalize(value),
+            Action::StripPrefix { value: prefix } => {
+                if let Some(rest) = value.strip_prefix(prefix.as_str()) {
+                    *value = rest.trim_start().to_string();
+                }
+            }
+            Action::StripSuffix { value: suffix } => {
+                if let Some(rest) = value.strip_suffix(suffix.as_str()) {
+                    *value = rest.trim_end().to_string();
+                }
+            }
+            Action::Replace { from, to } => *value = value.replace(from.as_str(), to),
+            Action::Truncate { max_chars } => {
+                if let Some(shortened) = budget::truncate_text(value, *max_chars) {
+                    *value = shortened;
+                }
+            }
+            Action::Require { contains } if !value.contains(contains.as_str()) => return None,
+            Action::Exclude { contains } if value.contains(contains.as_str()) => return None,
+            Action::Require { .. } | Action::Exclude { .. } => {}
+        }
+    }
+    Some(item)
+}
+
+impl CompiledPlugin<'_> {
+    // Helper function to extract the items of a page fetched from `base_url`
+    pub fn extract(&self, html: &str, base_url: &str) -> Vec<NewsItem> {
+        let base_url = Url::parse(base_url).ok();
+        let document = Html::parse_document(html);
+        let Some(container) = document.select(&self.container).next() else {
+            return vec![];
+        };
+
+        let mut news = Vec::new();
+        for element in container.select(&self.article) {
+            let title = read_field(element, &self.title, base_url.as_ref());
+            let link = read_field(element, &self.link, base_url.as_ref());
+            if title.is_empty() || link.is_empty() {
+                continue;
+            }
+            let image_url = self
+                .image_url
+                .as_ref()
+                .map(|field| read_field(element, field, base_url.as_ref()))
+                .filter(|url| !url.is_empty());
+            let item = NewsItem {
+                title,
+                description: self
+                    .description
+                    .as_ref()
+                    .map(|field| read_field(element, field, base_url.as_ref()))
+                    .unwrap_or_default(),
+                link,
+                placement: Placement {
+                    position: news.len(),
+                    image: image_url.is_some(),
+                    ..Placement::default()
+                },
+                image_url,
+                ..NewsItem::default()
+            };
+            if let Some(item) = apply_rules(item, &self.plugin.rules) {
+                news.push(item);
+                if news.len() >= self.plugin.limit {
+                    break;
+                }
+            }
+        }
+        news
+    }
+}
+
+// Helper function to fetch and extract a plugin's page for the scheduler,
+// through the fetch queue and item validation like the built-in sections
+pub async fn scrape(state: &AppState, plugin: &SourcePlugin) -> Result<Vec<NewsItem>, String> {
+    let html = fetch_queue::fetch_html(state, &state.scrape_client, &plugin.url).await?;
+    extract_page(state, plugin, html).await
+}
+
+// Helper function to extract and validate the items of a plugin's page, for
+// scrape and for a dry run against a saved copy
+pub async fn extract_page(
+    state: &AppState,
+    plugin: &SourcePlugin,
+    html: String,
+) -> Result<Vec<NewsItem>, String> {
+    let owned = plugin.clone();
+    let news = extract::run_blocking(move || {
+        owned
+            .compile()
+            .map(|compiled| compiled.extract(&html, &owned.url))
+    })
+    .await??;
+    state.metrics.increment(
+        "corriere_plugin_scrapes_total",
+        &[
+            ("plugin", &plugin.name),
+            ("result", if news.is_empty() { "empty" } else { "ok" }),
+        ],
+    );
+    Ok(validate::apply(state, &plugin.name, &plugin.url, news))
+}
@@ -0,0 +1,34 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+// Caps the number of concurrent requests made to any single upstream host
+pub struct HostLimiter {
+    permits_per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimiter {
+    pub fn new(permits_per_host: usize) -> HostLimiter {
+        HostLimiter {
+            permits_per_host: permits_per_host.max(1),
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Waits until a request to `host` may start; the request is allowed for
+    // as long as the returned permit is held
+    pub async fn acquire(&self, host: &str) -> OwnedSemaphorePermit {
+        let semaphore = {
+            let mut hosts = self.hosts.lock().unwrap();
+            hosts
+                .entry(host.to_ascii_lowercase())
+                .or_insert_with(|| Arc::new(Semaphore::new(self.permits_per_host)))
+                .clone()
+        };
+        semaphore
+            .acquire_owned()
+            .await
+            .expect("host semaphore is never closed")
+    }
+}
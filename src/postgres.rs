@@ -0,0 +1,377 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use std::collections::HashSet;
+
+use crate::archive::{
+    Appearance, ArchivedArticle, ArchivedScrape, EngagementPoint, SearchQuery, Storage,
+};
+use crate::engagement::Engagement;
+#[cfg(feature = "semantic")]
+use crate::semantic::{Entry, VectorStore};
+use crate::takedown;
+use crate::NewsItem;
+
+const MAX_CONNECTIONS: u32 = 5;
+
+// The archive in PostgreSQL, for deployments with several writers or more
+// history than the file backend searches comfortably. Queries are checked
+// against the schema at compile time; after changing one, regenerate .sqlx/
+// with `cargo sqlx prepare -- --all-features`, as some are only built with
+// the semantic feature
+pub struct PostgresStorage {
+    pool: PgPool,
+}
+
+impl PostgresStorage {
+    pub async fn connect(url: &str) -> Result<PostgresStorage, String> {
+        let pool = PgPoolOptions::new()
+            .max_connections(MAX_CONNECTIONS)
+            .connect(url)
+            .await
+            .map_err(|e| format!("Failed to connect to PostgreSQL: {}", e))?;
+        Ok(PostgresStorage { pool })
+    }
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    async fn prepare(&self) -> Result<(), String> {
+        sqlx::migrate!()
+            .run(&self.pool)
+            .await
+            .map_err(|e| format!("Failed to migrate the archive: {}", e))
+    }
+
+    async fn record(&self, scrape: &ArchivedScrape) -> Result<(), String> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        // The homepage can list the same article twice; keep its top spot
+        let mut seen = HashSet::new();
+        for (index, item) in scrape.news.iter().enumerate() {
+            if !seen.insert(item.link.as_str()) {
+                continue;
+            }
+
+            sqlx::query!(
+                "INSERT INTO articles
+                     (link, title, description, image_url, first_seen_at, last_seen_at)
+                 VALUES ($1, $2, $3, $4, $5, $5)
+                 ON CONFLICT (link) DO UPDATE SET
+                     title = EXCLUDED.title,
+                     description = EXCLUDED.description,
+                     image_url = EXCLUDED.image_url,
+                     last_seen_at = GREATEST(articles.last_seen_at, EXCLUDED.last_seen_at)",
+                item.link,
+                item.title,
+                item.description,
+                item.image_url,
+                scrape.scraped_at,
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| format!("Failed to store {}: {}", item.link, e))?;
+
+            sqlx::query!(
+                "INSERT INTO appearances (scraped_at, link, position)
+                 VALUES ($1, $2, $3)
+                 ON CONFLICT DO NOTHING",
+                scrape.scraped_at,
+                item.link,
+                index as i32 + 1,
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| format!("Failed to store {}: {}", item.link, e))?;
+
+            if let Some(engagement) = item.engagement {
+                sqlx::query!(
+                    "UPDATE appearances SET comments = $3, shares = $4
+                     WHERE link = $1 AND scraped_at = $2",
+                    item.link,
+                    scrape.scraped_at,
+                    engagement.comments.map(|count| count as i64),
+                    engagement.shares.map(|count| count as i64),
+                )
+                .execute(&mut *transaction)
+                .await
+                .map_err(|e| format!("Failed to store {}: {}", item.link, e))?;
+            }
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| format!("Failed to commit scrape: {}", e))
+    }
+
+    // Tombstones are left out before the LIMIT, so a takedown doesn't
+    // shorten the page
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<ArchivedArticle>, String> {
+        sqlx::query_as!(
+            ArchivedArticle,
+            "SELECT link, title, description, image_url, first_seen_at, last_seen_at
+             FROM articles
+             WHERE ($1::text IS NULL
+                    OR to_tsvector('italian', title || ' ' || description)
+                       @@ plainto_tsquery('italian', $1))
+               AND ($2::timestamptz IS NULL OR last_seen_at >= $2)
+               AND ($3::timestamptz IS NULL OR first_seen_at <= $3)
+               AND NOT starts_with(link, $5)
+             ORDER BY last_seen_at DESC
+             LIMIT $4",
+            query.text,
+            query.from,
+            query.to,
+            query.limit as i64,
+            takedown::TOMBSTONE_PREFIX,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| format!("Archive search failed: {}", e))
+    }
+
+    async fn history(
+        &self,
+        link: &str,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> Result<Vec<Appearance>, String> {
+        let rows = sqlx::query!(
+            "SELECT scraped_at, position FROM appearances
+             WHERE link = $1
+               AND ($2::timestamptz IS NULL OR scraped_at >= $2)
+               AND ($3::timestamptz IS NULL OR scraped_at <= $3)
+             ORDER BY scraped_at",
+            link,
+            from,
+            to,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| format!("Archive history failed: {}", e))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| Appearance {
+                scraped_at: row.scraped_at,
+                position: row.position as usize,
+            })
+            .collect())
+    }
+
+    async fn engagement(&self, link: &str) -> Result<Vec<EngagementPoint>, String> {
+        let rows = sqlx::query!(
+            "SELECT scraped_at, comments, shares FROM appearances
+             WHERE link = $1 AND (comments IS NOT NULL OR shares IS NOT NULL)
+             ORDER BY scraped_at",
+            link,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| format!("Archive engagement failed: {}", e))?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| EngagementPoint {
+                scraped_at: row.scraped_at,
+                engagement: Engagement {
+                    comments: row.comments.map(|count| count as u64),
+                    shares: row.shares.map(|count| count as u64),
+                },
+            })
+            .collect())
+    }
+
+    async fn scrapes(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<ArchivedScrape>, String> {
+        // Titles and summaries are stored once per article, so every scrape
+        // shows the latest version of them
+        let rows = sqlx::query!(
+            "SELECT appearances.scraped_at, articles.link, articles.title,
+                    articles.description, articles.image_url,
+                    appearances.comments, appearances.shares
+             FROM appearances
+             JOIN articles ON articles.link = appearances.link
+             WHERE appearances.scraped_at >= $1 AND appearances.scraped_at < $2
+             ORDER BY appearances.scraped_at, appearances.position",
+            from,
+            to,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| format!("Archive export failed: {}", e))?;
+
+        let mut scrapes: Vec<ArchivedScrape> = Vec::new();
+        for row in rows {
+            if takedown::is_tombstone(&row.link) {
+                continue;
+            }
+            let engagement = (row.comments.is_some() || row.shares.is_some()).then(|| Engagement {
+                comments: row.comments.map(|count| count as u64),
+                shares: row.shares.map(|count| count as u64),
+            });
+            let item = NewsItem {
+                title: row.title,
+                description: row.description,
+                link: row.link,
+                image_url: row.image_url,
+                engagement,
+                ..NewsItem::default()
+            };
+            match scrapes.last_mut() {
+                Some(scrape) if scrape.scraped_at == row.scraped_at => scrape.news.push(item),
+                _ => scrapes.push(ArchivedScrape {
+                    scraped_at: row.scraped_at,
+                    news: vec![item],
+                }),
+            }
+        }
+        Ok(scrapes)
+    }
+
+    async fn nearest(
+        &self,
+        at: DateTime<Utc>,
+        within: chrono::Duration,
+    ) -> Result<Option<ArchivedScrape>, String> {
+        // The last scrape up to `at` and the first after it, through the
+        // scraped_at index
+        let candidates = sqlx::query_scalar!(
+            r#"(SELECT scraped_at AS "scraped_at!" FROM appearances
+                WHERE scraped_at <= $1 AND scraped_at >= $2
+                ORDER BY scraped_at DESC LIMIT 1)
+               UNION ALL
+               (SELECT scraped_at FROM appearances
+                WHERE scraped_at > $1 AND scraped_at <= $3
+                ORDER BY scraped_at LIMIT 1)"#,
+            at,
+            at - within,
+            at + within,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| format!("Archive lookup failed: {}", e))?;
+        let Some(scraped_at) = candidates
+            .into_iter()
+            .min_by_key(|scraped_at| (*scraped_at - at).abs())
+        else {
+            return Ok(None);
+        };
+        let scrapes = self
+            .scrapes(scraped_at, scraped_at + chrono::Duration::microseconds(1))
+            .await?;
+        Ok(scrapes.into_iter().next())
+    }
+
+    async fn redact(&self, link: &str) -> Result<usize, String> {
+        let tombstone = takedown::tombstone(link).link;
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+        // The appearances move to a tombstone article before the original
+        // row, which they reference, is deleted
+        sqlx::query!(
+            "INSERT INTO articles
+                 (link, title, description, image_url, first_seen_at, last_seen_at)
+             SELECT $2, '', '', NULL, first_seen_at, last_seen_at
+             FROM articles WHERE link = $1
+             ON CONFLICT (link) DO NOTHING",
+            link,
+            tombstone,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| format!("Failed to redact {}: {}", link, e))?;
+        let moved = sqlx::query!(
+            "UPDATE appearances SET link = $2 WHERE link = $1",
+            link,
+            tombstone,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| format!("Failed to redact {}: {}", link, e))?
+        .rows_affected();
+        sqlx::query!("DELETE FROM articles WHERE link = $1", link)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| format!("Failed to redact {}: {}", link, e))?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|e| format!("Failed to commit redaction: {}", e))?;
+        Ok(moved as usize)
+    }
+}
+
+// The semantic index's vectors, in the embeddings table
+#[cfg(feature = "semantic")]
+#[async_trait]
+impl VectorStore for PostgresStorage {
+    async fn load(&self, model: &str) -> Result<Vec<Entry>, String> {
+        sqlx::query_as!(
+            Entry,
+            "SELECT link, title, description, image_url, model, embedded_at, vector
+             FROM embeddings WHERE model = $1",
+            model,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| format!("Failed to load embeddings: {}", e))
+    }
+
+    async fn store(&self, entries: &[Entry]) -> Result<(), String> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| format!("Failed to start transaction: {}", e))?;
+        for entry in entries {
+            sqlx::query!(
+                "INSERT INTO embeddings
+                     (link, title, description, image_url, model, embedded_at, vector)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)
+                 ON CONFLICT (link) DO UPDATE SET
+                     title = EXCLUDED.title,
+                     description = EXCLUDED.description,
+                     image_url = EXCLUDED.image_url,
+                     model = EXCLUDED.model,
+                     embedded_at = EXCLUDED.embedded_at,
+                     vector = EXCLUDED.vector",
+                entry.link,
+                entry.title,
+                entry.description,
+                entry.image_url,
+                entry.model,
+                entry.embedded_at,
+                &entry.vector,
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| format!("Failed to store the embedding of {}: {}", entry.link, e))?;
+        }
+        transaction
+            .commit()
+            .await
+            .map_err(|e| format!("Failed to commit embeddings: {}", e))
+    }
+
+    async fn remove(&self, link: &str) -> Result<(), String> {
+        sqlx::query!("DELETE FROM embeddings WHERE link = $1", link)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| format!("Failed to remove the embedding of {}: {}", link, e))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha256;
+
+use crate::local::Edition;
+use crate::problem::{Lang, Problem};
+use crate::v2::{self, NewsResponseV2};
+use crate::{local, AppState, NewsItem};
+
+pub const COOKIE: &str = "corriere_prefs";
+pub const HEADER: &str = "x-preferences";
+// A year, renewed whenever the preferences are saved again
+const COOKIE_MAX_AGE_SECS: u64 = 365 * 86400;
+// Per list, so a cookie stays well under the 4 KB browsers keep
+const MAX_ENTRIES: usize = 20;
+const MAX_NAME_LEN: usize = 40;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// What a reader wants first, and what not at all. Names are the categories
+// of v2 items (sections such as "politica", or local editions) and the
+// cities of local::EDITIONS
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct Preferences {
+    // Items in these categories come first, in this order of preference
+    #[serde(default)]
+    pub categories: Vec<String>,
+    // Local editions to keep; items from other editions are left out. Empty
+    // keeps them all
+    #[serde(default)]
+    pub editions: Vec<String>,
+    // Items in these categories are left out
+    #[serde(default)]
+    pub hidden: Vec<String>,
+}
+
+impl Preferences {
+    // Helper function to lowercase and dedupe the names, rejecting any that
+    // aren't plain slugs
+    pub fn normalize(mut self) -> Result<Preferences, String> {
+        for (field, names) in [
+            ("categories", &mut self.categories),
+            ("editions", &mut self.editions),
+            ("hidden", &mut self.hidden),
+        ] {
+            let mut normalized: Vec<String> = Vec::new();
+            for name in names.iter() {
+                let name = name.trim().to_ascii_lowercase();
+                let valid = !name.is_empty()
+                    && name.len() <= MAX_NAME_LEN
+                    && name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+                if !valid {
+                    return Err(format!("Invalid name '{}' in {}", name, field));
+                }
+                if !normalized.contains(&name) {
+                    normalized.push(name);
+                }
+            }
+            if normalized.len() > MAX_ENTRIES {
+                return Err(format!("At most {} {} are allowed", MAX_ENTRIES, field));
+            }
+            *names = normalized;
+        }
+        Ok(self)
+    }
+
+    // Helper function to read the header form, e.g.
+    // categories=politica,esteri; editions=milano; hidden=sport
+    pub fn parse_header(value: &str) -> Result<Preferences, String> {
+        let mut preferences = Preferences::default();
+        for part in value
+            .split(';')
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+        {
+            let (key, names) = part
+                .split_once('=')
+                .ok_or(format!("Expected name=values, got '{}'", part))?;
+            let names = names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_string);
+            match key.trim() {
+                "categories" => preferences.categories.extend(names),
+                "editions" => preferences.editions.extend(names),
+                "hidden" => preferences.hidden.extend(names),
+                other => return Err(format!("Unknown preference '{}'", other)),
+            }
+        }
+        preferences.normalize()
+    }
+
+    // Helper function to filter and reorder a page's items. Items keep their
+    // page order within the same preference; `editions` tells which local
+    // edition a link belongs to
+    pub fn apply(&self, news: Vec<NewsItem>, editions: &[Edition]) -> Vec<NewsItem> {
+        let mut kept: Vec<(usize, NewsItem)> = news
+            .into_iter()
+            .filter_map(|item| {
+                let categories = v2::link_metadata(&item.link).0;
+                if categories
+                    .iter()
+                    .any(|category| self.hidden.contains(category))
+                {
+                    return None;
+                }
+                let edition = local::city_of_link(&item.link, editions);
+                if edition
+                    .is_some_and(|city| !self.editions.is_empty() && !self.editions.contains(&city))
+                {
+                    return None;
+                }
+                let rank = self
+                    .categories
+                    .iter()
+                    .position(|preferred| categories.contains(preferred))
+                    .unwrap_or(self.categories.len());
+                Some((rank, item))
+            })
+            .collect();
+        kept.sort_by_key(|(rank, _)| *rank);
+        kept.into_iter().map(|(_, item)| item).collect()
+    }
+}
+
+fn mac(secret: &str) -> HmacSha256 {
+    HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length")
+}
+
+// Helper function to encode preferences as a cookie value: the JSON in
+// URL-safe base64, a dot and its HMAC-SHA256 under PREFERENCES_SECRET
+pub fn sign(preferences: &Preferences, secret: &str) -> String {
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(preferences).unwrap_or_default());
+    let mut mac = mac(secret);
+    mac.update(payload.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+    format!("{}.{}", payload, signature)
+}
+
+// Helper function to read a cookie value made by `sign`. None when it was
+// tampered with or signed under another secret
+pub fn verify(value: &str, secret: &str) -> Option<Preferences> {
+    let (payload, signature) = value.split_once('.')?;
+    let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+    let mut mac = mac(secret);
+    mac.update(payload.as_bytes());
+    mac.verify_slice(&signature).ok()?;
+    let json = URL_SAFE_NO_PAD.decode(payload).ok()?;
+    serde_json::from_slice::<Preferences>(&json)
+        .ok()?
+        .normalize()
+        .ok()
+}
+
+// Helper function to find a cookie in the Cookie headers of a request
+fn cookie<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|pair| {
+            let (key, value) = pair.trim().split_once('=')?;
+            (key == name).then_some(value)
+        })
+}
+
+// Helper function to build the Set-Cookie header. Secure when the server is
+// reached over HTTPS
+fn set_cookie(state: &AppState, value: &str, max_age: u64) -> Option<HeaderValue> {
+    let secure = if state.config.public_url.starts_with("https://") {
+        "; Secure"
+    } else {
+        ""
+    };
+    HeaderValue::from_str(&format!(
+        "{}={}; Path=/api; Max-Age={}; HttpOnly; SameSite=Lax{}",
+        COOKIE, value, max_age, secure
+    ))
+    .ok()
+}
+
+fn disabled(lang: Lang) -> Response {
+    Problem::not_found("Preference cookies need PREFERENCES_SECRET to be set".to_string())
+        .respond(lang)
+}
+
+// Stores preferences in a signed cookie and echoes them back normalized.
+// Nothing is kept on the server
+pub async fn save_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(preferences): Json<Preferences>,
+) -> Response {
+    let lang = Lang::from_headers(&headers);
+    let Some(secret) = &state.config.preferences_secret else {
+        return disabled(lang);
+    };
+    let preferences = match preferences.normalize() {
+        Ok(preferences) => preferences,
+        Err(error_message) => {
+            return Problem::invalid_parameter("preferences", error_message).respond(lang)
+        }
+    };
+
+    let mut response = Json(json!({ "preferences": preferences })).into_response();
+    if let Some(value) = set_cookie(&state, &sign(&preferences, secret), COOKIE_MAX_AGE_SECS) {
+        response.headers_mut().insert(header::SET_COOKIE, value);
+    }
+    response
+}
+
+// Forgets the preferences by expiring the cookie
+pub async fn clear_handler(State(state): State<AppState>) -> Response {
+    let mut response = StatusCode::NO_CONTENT.into_response();
+    if let Some(value) = set_cookie(&state, "", 0) {
+        response.headers_mut().insert(header::SET_COOKIE, value);
+    }
+    response
+}
+
+// The homepage news filtered and reordered by the reader's preferences,
+// from the X-Preferences header or else the cookie; without either it's the
+// page as is. A newer endpoint, so it only exists in the v2 shape
+pub async fn for_me_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let lang = Lang::from_headers(&headers);
+    let preferences = match headers.get(HEADER).map(|value| value.to_str()) {
+        Some(Ok(value)) => match Preferences::parse_header(value) {
+            Ok(preferences) => preferences,
+            Err(error_message) => {
+                let problem = Problem::invalid_parameter(HEADER, error_message);
+                return v2::with_version(problem.respond(lang), 2);
+            }
+        },
+        Some(Err(_)) => {
+            let problem = Problem::invalid_parameter(HEADER, "Expected a text header".to_string());
+            return v2::with_version(problem.respond(lang), 2);
+        }
+        // A cookie that doesn't verify, e.g. after the secret changed, is as
+        // good as none
+        None => state
+            .config
+            .preferences_secret
+            .as_deref()
+            .and_then(|secret| verify(cookie(&headers, COOKIE)?, secret))
+            .unwrap_or_default(),
+    };
+
+    let mut response = match crate::serve_news(&state).await {
+        Ok(cached) => cached.response,
+        Err(response) => {
+            let problem = Problem::upstream_unavailable(response.error.unwrap_or_default());
+            return v2::with_version(problem.respond(lang), 2);
+        }
+    };
+    response.news = preferences.apply(
+        response.news,
+        &local::editions(&state.config.local_editions),
+    );
+
+    let response = NewsResponseV2::from_v1(&response, &state.config);
+    let mut rendered = Json(response).into_response();
+    let headers = rendered.headers_mut();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("private"));
+    headers.insert(
+        header::VARY,
+        HeaderValue::from_static("cookie, x-preferences"),
+    );
+    v2::with_version(rendered, 2)
+}
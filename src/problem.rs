@@ -0,0 +1,445 @@
+use axum::body::{to_bytes, Body};
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::time::Duration;
+
+use crate::request_id;
+
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+// Largest JSON error body the middleware turns into a problem document
+const MAX_ERROR_BYTES: usize = 64 * 1024;
+
+// A query parameter that doesn't parse, named, for parsers that read more
+// than one and whose errors must say which
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParameterError {
+    pub parameter: &'static str,
+    pub message: String,
+}
+
+impl ParameterError {
+    pub fn new(parameter: &'static str, message: String) -> ParameterError {
+        ParameterError { parameter, message }
+    }
+}
+
+impl fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<ParameterError> for String {
+    fn from(error: ParameterError) -> String {
+        error.message
+    }
+}
+
+impl From<ParameterError> for Problem {
+    fn from(error: ParameterError) -> Problem {
+        Problem::invalid_parameter(error.parameter, error.message)
+    }
+}
+
+// Languages error messages are available in, negotiated with Accept-Language
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Lang {
+    #[default]
+    English,
+    Italian,
+}
+
+impl Lang {
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::English => "en",
+            Lang::Italian => "it",
+        }
+    }
+
+    // Helper function to pick the preferred supported language from an
+    // Accept-Language header, falling back to English
+    pub fn negotiate(accept_language: Option<&str>) -> Lang {
+        let mut best: Option<(Lang, f32)> = None;
+        for range in accept_language.unwrap_or("").split(',') {
+            let mut parts = range.split(';');
+            let tag = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            let primary = tag.split('-').next().unwrap_or("");
+            let lang = match primary {
+                "it" => Lang::Italian,
+                "en" => Lang::English,
+                _ => continue,
+            };
+            if quality > 0.0 && best.is_none_or(|(_, best_quality)| quality > best_quality) {
+                best = Some((lang, quality));
+            }
+        }
+        best.map(|(lang, _)| lang).unwrap_or_default()
+    }
+
+    pub fn from_headers(headers: &HeaderMap) -> Lang {
+        Lang::negotiate(
+            headers
+                .get(header::ACCEPT_LANGUAGE)
+                .and_then(|value| value.to_str().ok()),
+        )
+    }
+}
+
+// Machine-readable error codes. Each becomes the `type` of a problem document
+// as urn:corriere-scraper:problem:<code>, and is also sent as `code`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ProblemKind {
+    InvalidParameter,
+    NotAcceptable,
+    NotFound,
+    UpstreamUnavailable,
+    RequestTimeout,
+    PayloadTooLarge,
+    // The generic kinds other endpoints' errors are served as
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    Conflict,
+    TooManyRequests,
+    Internal,
+    ServiceUnavailable,
+}
+
+impl ProblemKind {
+    pub fn code(self) -> &'static str {
+        match self {
+            ProblemKind::InvalidParameter => "invalid-parameter",
+            ProblemKind::NotAcceptable => "not-acceptable",
+            ProblemKind::NotFound => "not-found",
+            ProblemKind::UpstreamUnavailable => "upstream-unavailable",
+            ProblemKind::RequestTimeout => "request-timeout",
+            ProblemKind::PayloadTooLarge => "payload-too-large",
+            ProblemKind::BadRequest => "bad-request",
+            ProblemKind::Unauthorized => "unauthorized",
+            ProblemKind::Forbidden => "forbidden",
+            ProblemKind::Conflict => "conflict",
+            ProblemKind::TooManyRequests => "too-many-requests",
+            ProblemKind::Internal => "internal-error",
+            ProblemKind::ServiceUnavailable => "service-unavailable",
+        }
+    }
+
+    // The generic kind of an error response with this status, if any
+    pub fn for_status(status: StatusCode) -> Option<ProblemKind> {
+        match status {
+            StatusCode::BAD_REQUEST => Some(ProblemKind::BadRequest),
+            StatusCode::UNAUTHORIZED => Some(ProblemKind::Unauthorized),
+            StatusCode::FORBIDDEN => Some(ProblemKind::Forbidden),
+            StatusCode::NOT_FOUND => Some(ProblemKind::NotFound),
+            StatusCode::CONFLICT => Some(ProblemKind::Conflict),
+            StatusCode::TOO_MANY_REQUESTS => Some(ProblemKind::TooManyRequests),
+            StatusCode::INTERNAL_SERVER_ERROR => Some(ProblemKind::Internal),
+            StatusCode::BAD_GATEWAY => Some(ProblemKind::UpstreamUnavailable),
+            StatusCode::SERVICE_UNAVAILABLE => Some(ProblemKind::ServiceUnavailable),
+            _ => None,
+        }
+    }
+
+    pub fn status(self) -> StatusCode {
+        match self {
+            ProblemKind::InvalidParameter => StatusCode::BAD_REQUEST,
+            ProblemKind::NotAcceptable => StatusCode::NOT_ACCEPTABLE,
+            ProblemKind::NotFound => StatusCode::NOT_FOUND,
+            ProblemKind::UpstreamUnavailable => StatusCode::BAD_GATEWAY,
+            ProblemKind::RequestTimeout => StatusCode::REQUEST_TIMEOUT,
+            ProblemKind::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ProblemKind::BadRequest => StatusCode::BAD_REQUEST,
+            ProblemKind::Unauthorized => StatusCode::UNAUTHORIZED,
+            ProblemKind::Forbidden => StatusCode::FORBIDDEN,
+            ProblemKind::Conflict => StatusCode::CONFLICT,
+            ProblemKind::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            ProblemKind::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+            ProblemKind::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    pub fn title(self, lang: Lang) -> &'static str {
+        match (self, lang) {
+            (ProblemKind::InvalidParameter, Lang::English) => "Invalid parameter",
+            (ProblemKind::InvalidParameter, Lang::Italian) => "Parametro non valido",
+            (ProblemKind::NotAcceptable, Lang::English) => "No acceptable representation",
+            (ProblemKind::NotAcceptable, Lang::Italian) => "Nessun formato accettabile",
+            (ProblemKind::NotFound, Lang::English) => "Not found",
+            (ProblemKind::NotFound, Lang::Italian) => "Risorsa non trovata",
+            (ProblemKind::UpstreamUnavailable, Lang::English) => "Corriere.it is unavailable",
+            (ProblemKind::UpstreamUnavailable, Lang::Italian) => "Corriere.it non è raggiungibile",
+            (ProblemKind::RequestTimeout, Lang::English) => "Request timed out",
+            (ProblemKind::RequestTimeout, Lang::Italian) => "Tempo scaduto",
+            (ProblemKind::PayloadTooLarge, Lang::English) => "Request body too large",
+            (ProblemKind::PayloadTooLarge, Lang::Italian) => "Richiesta troppo grande",
+            (ProblemKind::BadRequest, Lang::English) => "Bad request",
+            (ProblemKind::BadRequest, Lang::Italian) => "Richiesta non valida",
+            (ProblemKind::Unauthorized, Lang::English) => "Unauthorized",
+            (ProblemKind::Unauthorized, Lang::Italian) => "Non autorizzato",
+            (ProblemKind::Forbidden, Lang::English) => "Forbidden",
+            (ProblemKind::Forbidden, Lang::Italian) => "Accesso negato",
+            (ProblemKind::Conflict, Lang::English) => "Conflict",
+            (ProblemKind::Conflict, Lang::Italian) => "Conflitto",
+            (ProblemKind::TooManyRequests, Lang::English) => "Too many requests",
+            (ProblemKind::TooManyRequests, Lang::Italian) => "Troppe richieste",
+            (ProblemKind::Internal, Lang::English) => "Internal error",
+            (ProblemKind::Internal, Lang::Italian) => "Errore interno",
+            (ProblemKind::ServiceUnavailable, Lang::English) => "Service unavailable",
+            (ProblemKind::ServiceUnavailable, Lang::Italian) => "Servizio non disponibile",
+        }
+    }
+}
+
+// An RFC 7807 problem document. `detail` is kept in both languages; the
+// untranslated cause from deeper in the scraper goes in `reason`
+#[derive(Clone, Debug)]
+pub struct Problem {
+    pub kind: ProblemKind,
+    pub detail_en: String,
+    pub detail_it: String,
+    pub reason: Option<String>,
+    // Extension members, e.g. the name of the offending parameter
+    pub extensions: Map<String, Value>,
+}
+
+impl Problem {
+    pub fn invalid_parameter(name: &str, reason: String) -> Problem {
+        let mut extensions = Map::new();
+        extensions.insert("parameter".to_string(), Value::String(name.to_string()));
+        Problem {
+            kind: ProblemKind::InvalidParameter,
+            detail_en: format!("The '{}' parameter is not valid", name),
+            detail_it: format!("Il parametro '{}' non è valido", name),
+            reason: Some(reason),
+            extensions,
+        }
+    }
+
+    pub fn not_acceptable(supported: &[&str]) -> Problem {
+        let mut extensions = Map::new();
+        extensions.insert("supported".to_string(), json!(supported));
+        Problem {
+            kind: ProblemKind::NotAcceptable,
+            detail_en: "None of the accepted media types can be produced".to_string(),
+            detail_it: "Nessuno dei formati richiesti è disponibile".to_string(),
+            reason: None,
+            extensions,
+        }
+    }
+
+    pub fn not_found(reason: String) -> Problem {
+        Problem::generic(ProblemKind::NotFound, Some(reason))
+    }
+
+    pub fn upstream_unavailable(reason: String) -> Problem {
+        Problem {
+            kind: ProblemKind::UpstreamUnavailable,
+            detail_en: "Corriere.it could not be scraped and no earlier scrape is available"
+                .to_string(),
+            detail_it: "Impossibile leggere corriere.it e non c'è uno scrape precedente"
+                .to_string(),
+            reason: Some(reason),
+            extensions: Map::new(),
+        }
+    }
+
+    pub fn request_timeout(timeout: Duration) -> Problem {
+        let mut extensions = Map::new();
+        extensions.insert("timeout_secs".to_string(), json!(timeout.as_secs()));
+        Problem {
+            kind: ProblemKind::RequestTimeout,
+            detail_en: format!(
+                "The request could not be completed within {} seconds",
+                timeout.as_secs()
+            ),
+            detail_it: format!(
+                "Non è stato possibile completare la richiesta entro {} secondi",
+                timeout.as_secs()
+            ),
+            reason: None,
+            extensions,
+        }
+    }
+
+    pub fn payload_too_large(max_bytes: usize) -> Problem {
+        let mut extensions = Map::new();
+        extensions.insert("max_bytes".to_string(), json!(max_bytes));
+        Problem {
+            kind: ProblemKind::PayloadTooLarge,
+            detail_en: format!("Request bodies are limited to {} bytes", max_bytes),
+            detail_it: format!(
+                "Il corpo della richiesta non può superare {} byte",
+                max_bytes
+            ),
+            reason: None,
+            extensions,
+        }
+    }
+
+    // A problem of a generic kind, for an endpoint's own error message
+    pub fn generic(kind: ProblemKind, reason: Option<String>) -> Problem {
+        let (detail_en, detail_it) = match kind {
+            ProblemKind::Unauthorized => ("A valid token is required", "Serve un token valido"),
+            ProblemKind::Forbidden => (
+                "The request is not allowed",
+                "La richiesta non è consentita",
+            ),
+            ProblemKind::NotFound => (
+                "The requested resource does not exist",
+                "La risorsa richiesta non esiste",
+            ),
+            ProblemKind::Conflict => (
+                "The request conflicts with what is already there",
+                "La richiesta è in conflitto con quanto già presente",
+            ),
+            ProblemKind::TooManyRequests => (
+                "Too many requests, try again later",
+                "Troppe richieste, riprova più tardi",
+            ),
+            ProblemKind::Internal => (
+                "The server failed to complete the request",
+                "Il server non è riuscito a completare la richiesta",
+            ),
+            ProblemKind::UpstreamUnavailable => (
+                "A service the request depends on failed",
+                "Un servizio da cui dipende la richiesta non ha risposto",
+            ),
+            ProblemKind::ServiceUnavailable => (
+                "The service is temporarily unavailable",
+                "Il servizio è momentaneamente non disponibile",
+            ),
+            _ => ("The request is not valid", "La richiesta non è valida"),
+        };
+        Problem {
+            kind,
+            detail_en: detail_en.to_string(),
+            detail_it: detail_it.to_string(),
+            reason,
+            extensions: Map::new(),
+        }
+    }
+
+    pub fn detail(&self, lang: Lang) -> &str {
+        match lang {
+            Lang::English => &self.detail_en,
+            Lang::Italian => &self.detail_it,
+        }
+    }
+
+    // Helper function to build the JSON document in the given language
+    pub fn to_json(&self, lang: Lang) -> Value {
+        let mut document = Map::new();
+        document.insert(
+            "type".to_string(),
+            Value::String(format!("urn:corriere-scraper:problem:{}", self.kind.code())),
+        );
+        document.insert("code".to_string(), json!(self.kind.code()));
+        document.insert("title".to_string(), json!(self.kind.title(lang)));
+        document.insert("status".to_string(), json!(self.kind.status().as_u16()));
+        document.insert("detail".to_string(), json!(self.detail(lang)));
+        if let Some(reason) = &self.reason {
+            document.insert("reason".to_string(), json!(reason));
+        }
+        for (name, value) in &self.extensions {
+            document.insert(name.clone(), value.clone());
+        }
+        if let Some(id) = request_id::current() {
+            document.insert("request_id".to_string(), json!(id));
+        }
+        Value::Object(document)
+    }
+
+    pub fn respond(&self, lang: Lang) -> Response {
+        let body = self.to_json(lang).to_string();
+        (
+            self.kind.status(),
+            [
+                (header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON)),
+                (
+                    header::CONTENT_LANGUAGE,
+                    HeaderValue::from_static(lang.code()),
+                ),
+                (
+                    header::VARY,
+                    HeaderValue::from_static("accept, accept-language"),
+                ),
+            ],
+            body,
+        )
+            .into_response()
+    }
+}
+
+// Helper function to tell whether a client asked for problem documents by
+// listing application/problem+json in its Accept header. v1 endpoints keep
+// their `error` field for everyone else
+pub fn is_requested(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    accept.split(',').any(|range| {
+        let mut parts = range.split(';');
+        let media_type = parts.next().unwrap_or("").trim();
+        let quality = parts
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        media_type.eq_ignore_ascii_case(PROBLEM_JSON) && quality > 0.0
+    })
+}
+
+// Middleware serving the JSON errors of every endpoint, `{"error": "..."}`
+// with a 4xx or 5xx status, as problem documents of the generic kinds to
+// clients that ask for them. Other clients get the endpoint's own shape,
+// and endpoints answering with a problem document are left alone
+pub async fn middleware(request: Request, next: Next) -> Response {
+    if !is_requested(request.headers()) {
+        return next.run(request).await;
+    }
+    let lang = Lang::from_headers(request.headers());
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    let Some(kind) = ProblemKind::for_status(response.status()).filter(|_| is_json) else {
+        return response;
+    };
+
+    let (parts, body) = response.into_parts();
+    let reason = match to_bytes(body, MAX_ERROR_BYTES).await {
+        Ok(bytes) => match serde_json::from_slice::<Value>(&bytes) {
+            Ok(Value::Object(object)) if object.get("error").is_some_and(Value::is_string) => {
+                object["error"].as_str().map(str::to_string)
+            }
+            // Not an error body after all
+            _ => return Response::from_parts(parts, Body::from(bytes)),
+        },
+        Err(_) => None,
+    };
+    let mut converted = Problem::generic(kind, reason).respond(lang);
+    // Headers such as Retry-After or WWW-Authenticate still apply
+    for (name, value) in &parts.headers {
+        if !matches!(
+            *name,
+            header::CONTENT_TYPE | header::CONTENT_LENGTH | header::CONTENT_LANGUAGE | header::VARY
+        ) {
+            converted.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+    converted
+}
@@ -0,0 +1,57 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::extract::{SelectorConfig, SelectorSet};
+use crate::url_safety::FetchedPage;
+
+// How a homepage scrape was produced, for clients that record where each
+// row of a dataset comes from. Served by /api/v2/news only
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Provenance {
+    // The page fetched, after redirects
+    pub source_url: String,
+    pub http_status: u16,
+    // Bytes of the page body
+    pub content_length: usize,
+    pub fetch_duration_ms: u64,
+    // The set that produced the items, see SELECTOR_SETS_PATH, and a hash of
+    // its selectors that changes whenever they do
+    pub selector_set: String,
+    pub selector_set_version: String,
+    // EXTRACTOR_VERSION, the version of corriere_core
+    pub extractor_version: &'static str,
+    // hit, revalidate (served while refreshing), miss or stale (upstream
+    // failing, last good scrape)
+    pub cache_status: &'static str,
+}
+
+impl Provenance {
+    // Helper function to describe a fresh scrape of `page` by the set named
+    // `set` among `sets`
+    pub fn new(page: &FetchedPage, set: &str, sets: &[SelectorSet]) -> Provenance {
+        Provenance {
+            source_url: page.url.clone(),
+            http_status: page.status,
+            content_length: page.content_length,
+            fetch_duration_ms: page.duration.as_millis() as u64,
+            selector_set: set.to_string(),
+            selector_set_version: sets
+                .iter()
+                .find(|candidate| candidate.name == set)
+                .map(|candidate| selector_version(&candidate.selectors))
+                .unwrap_or_default(),
+            extractor_version: crate::EXTRACTOR_VERSION,
+            cache_status: "miss",
+        }
+    }
+}
+
+// Helper function to fingerprint a set of selectors: the first 12 hex
+// digits of the SHA-256 of their JSON form
+pub fn selector_version(selectors: &SelectorConfig) -> String {
+    let json = serde_json::to_string(selectors).unwrap_or_default();
+    Sha256::digest(json.as_bytes())[..6]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
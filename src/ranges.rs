@@ -0,0 +1,170 @@
+use axum::body::{to_bytes, Body, HttpBody};
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use sha2::{Digest, Sha256};
+
+// Bytes of the content hash kept in the ETag
+const ETAG_BYTES: usize = 12;
+// Largest body served in ranges; bigger ones, and streamed ones of unknown
+// length, go out whole without being buffered here
+const MAX_RANGED_BODY_BYTES: u64 = 256 * 1024 * 1024;
+
+// A single byte range, both ends inclusive
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ByteRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+// What a Range header asks of a body of a given length
+#[derive(Debug, PartialEq)]
+pub enum RangeRequest {
+    // No range, or one we don't serve (several ranges, another unit, bad
+    // syntax): the whole body goes out
+    Full,
+    Partial(ByteRange),
+    // Starts past the end of the body
+    Unsatisfiable,
+}
+
+// Helper function to read a Range header such as bytes=0-499, bytes=500-
+// or bytes=-500 (the last 500 bytes) against a body of `length` bytes
+pub fn parse_range(value: &str, length: usize) -> RangeRequest {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Full;
+    }
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return RangeRequest::Full;
+    };
+    let (first, last) = (first.trim(), last.trim());
+
+    let range = match (first.is_empty(), last.is_empty()) {
+        // The last N bytes
+        (true, false) => match last.parse::<usize>() {
+            Ok(0) => return RangeRequest::Unsatisfiable,
+            Ok(suffix) => ByteRange {
+                start: length.saturating_sub(suffix),
+                end: length.wrapping_sub(1),
+            },
+            Err(_) => return RangeRequest::Full,
+        },
+        (false, _) => {
+            let Ok(start) = first.parse::<usize>() else {
+                return RangeRequest::Full;
+            };
+            let end = match last {
+                "" => length.wrapping_sub(1),
+                last => match last.parse::<usize>() {
+                    Ok(end) if end >= start => end.min(length.wrapping_sub(1)),
+                    _ => return RangeRequest::Full,
+                },
+            };
+            ByteRange { start, end }
+        }
+        (true, true) => return RangeRequest::Full,
+    };
+    if length == 0 || range.start >= length {
+        return RangeRequest::Unsatisfiable;
+    }
+    RangeRequest::Partial(range)
+}
+
+// Helper function to derive a strong ETag from the body, so resumed
+// downloads can check with If-Range that the content is still the same
+fn etag(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    let hex: String = digest[..ETAG_BYTES]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("\"{}\"", hex)
+}
+
+// Helper function to tell whether an If-Range precondition holds. Only
+// ETags are compared; a date can't match since we send no Last-Modified
+fn if_range_matches(request: &HeaderMap, etag: &str) -> bool {
+    match request.get(header::IF_RANGE) {
+        Some(value) => value.to_str().is_ok_and(|value| value.trim() == etag),
+        None => true,
+    }
+}
+
+// Middleware for download endpoints (archive exports, EPUB editions,
+// briefing audio): advertises byte ranges and serves a single Range as a
+// 206 partial response, so download managers can resume. The handlers build
+// their bodies in memory, so a body of known length up to
+// MAX_RANGED_BODY_BYTES is hashed for an ETag and sliced here. HEAD requests
+// are answered by the GET handlers, with the body dropped by axum and the
+// headers left as they are
+pub async fn middleware(request: Request, next: Next) -> Response {
+    let ranged = matches!(*request.method(), Method::GET | Method::HEAD);
+    let request_headers = request.headers().clone();
+    let response = next.run(request).await;
+    if !ranged || response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let known_length = response.body().size_hint().exact();
+    if known_length.is_none_or(|length| length > MAX_RANGED_BODY_BYTES) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = match to_bytes(body, MAX_RANGED_BODY_BYTES as usize).await {
+        Ok(body) => body,
+        Err(e) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to read the response body: {}", e),
+            )
+                .into_response()
+        }
+    };
+    let etag = match parts.headers.get(header::ETAG) {
+        Some(value) => value.to_str().unwrap_or_default().to_string(),
+        None => {
+            let etag = etag(&body);
+            if let Ok(value) = HeaderValue::from_str(&etag) {
+                parts.headers.insert(header::ETAG, value);
+            }
+            etag
+        }
+    };
+    parts
+        .headers
+        .insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    let range = match request_headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(value) if if_range_matches(&request_headers, &etag) => parse_range(value, body.len()),
+        _ => RangeRequest::Full,
+    };
+    match range {
+        RangeRequest::Full => Response::from_parts(parts, Body::from(body)),
+        RangeRequest::Partial(ByteRange { start, end }) => {
+            parts.status = StatusCode::PARTIAL_CONTENT;
+            let content_range = format!("bytes {}-{}/{}", start, end, body.len());
+            if let Ok(value) = HeaderValue::from_str(&content_range) {
+                parts.headers.insert(header::CONTENT_RANGE, value);
+            }
+            parts
+                .headers
+                .insert(header::CONTENT_LENGTH, HeaderValue::from(end - start + 1));
+            Response::from_parts(parts, Body::from(body.slice(start..=end)))
+        }
+        RangeRequest::Unsatisfiable => {
+            let mut response = StatusCode::RANGE_NOT_SATISFIABLE.into_response();
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes */{}", body.len())) {
+                response.headers_mut().insert(header::CONTENT_RANGE, value);
+            }
+            response
+        }
+    }
+}
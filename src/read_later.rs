@@ -0,0 +1,613 @@
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::admin::{actor, admin_error, unauthorized};
+use crate::audit::{self, AuditEvent};
+use crate::config::Config;
+use crate::sent_log::{self, SentLog};
+use crate::{extract, json_file, lease, AppState, NewsItem};
+
+// Wallabag tokens are renewed this long before they run out
+const TOKEN_MARGIN_SECS: i64 = 60;
+
+enum Service {
+    Wallabag {
+        url: String,
+        client_id: String,
+        client_secret: String,
+        username: String,
+        password: String,
+    },
+    Pocket {
+        api_url: String,
+        consumer_key: String,
+        // From POCKET_ACCESS_TOKEN; otherwise the stored one is used
+        access_token: Option<String>,
+    },
+}
+
+impl Service {
+    fn name(&self) -> &'static str {
+        match self {
+            Service::Wallabag { .. } => "wallabag",
+            Service::Pocket { .. } => "pocket",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct WallabagToken {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_at: DateTime<Utc>,
+}
+
+// OAuth tokens obtained at runtime, kept in DATA_DIR/read_later_tokens.json
+#[derive(Serialize, Deserialize, Default)]
+struct Tokens {
+    wallabag: Option<WallabagToken>,
+    pocket_access_token: Option<String>,
+    // Request token of a Pocket authorization waiting for the user's approval
+    pocket_request_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct WallabagTokenResponse {
+    access_token: String,
+    expires_in: i64,
+    refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PocketCode {
+    code: String,
+}
+
+#[derive(Deserialize)]
+struct PocketAuthorization {
+    access_token: String,
+    username: Option<String>,
+}
+
+// Saves articles to the configured read-later accounts
+pub struct ReadLater {
+    client: reqwest::Client,
+    services: Vec<Service>,
+    tokens_path: PathBuf,
+}
+
+impl ReadLater {
+    // Helper function to build the pusher, or None when neither Wallabag nor
+    // Pocket is configured
+    pub fn from_config(config: &Config) -> Result<Option<ReadLater>, String> {
+        let mut services = Vec::new();
+
+        let wallabag = (
+            &config.wallabag_url,
+            &config.wallabag_client_id,
+            &config.wallabag_client_secret,
+            &config.wallabag_username,
+            &config.wallabag_password,
+        );
+        match wallabag {
+            (Some(url), Some(client_id), Some(client_secret), Some(username), Some(password)) => {
+                services.push(Service::Wallabag {
+                    url: url.clone(),
+                    client_id: client_id.clone(),
+                    client_secret: client_secret.clone(),
+                    username: username.clone(),
+                    password: password.clone(),
+                })
+            }
+            (None, None, None, None, None) => {}
+            _ => {
+                return Err("WALLABAG_URL, WALLABAG_CLIENT_ID, WALLABAG_CLIENT_SECRET, \
+                    WALLABAG_USERNAME and WALLABAG_PASSWORD must be set together"
+                    .to_string())
+            }
+        }
+
+        match (&config.pocket_consumer_key, &config.pocket_access_token) {
+            (Some(consumer_key), access_token) => services.push(Service::Pocket {
+                api_url: config.pocket_api_url.clone(),
+                consumer_key: consumer_key.clone(),
+                access_token: access_token.clone(),
+            }),
+            (None, Some(_)) => {
+                return Err("POCKET_ACCESS_TOKEN is set but POCKET_CONSUMER_KEY is not".to_string())
+            }
+            (None, None) => {}
+        }
+
+        if services.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(ReadLater {
+            client: reqwest::Client::new(),
+            services,
+            tokens_path: config.data_dir.join("read_later_tokens.json"),
+        }))
+    }
+
+    fn load_tokens(&self) -> Result<Tokens, String> {
+        Ok(json_file::load(&self.tokens_path)?.unwrap_or_default())
+    }
+
+    fn update_tokens(&self, update: impl FnOnce(&mut Tokens)) -> Result<(), String> {
+        let mut tokens = self.load_tokens()?;
+        update(&mut tokens);
+        json_file::save(&self.tokens_path, &tokens)
+    }
+
+    // Helper function to save an article to every configured account,
+    // returning the outcome per service
+    pub async fn save(
+        &self,
+        url: &str,
+        title: &str,
+        tags: &[String],
+    ) -> BTreeMap<String, Result<(), String>> {
+        let mut results = BTreeMap::new();
+        for service in &self.services {
+            let result = match service {
+                Service::Wallabag { .. } => self.save_to_wallabag(service, url, title, tags).await,
+                Service::Pocket {
+                    api_url,
+                    consumer_key,
+                    access_token,
+                } => {
+                    self.save_to_pocket(
+                        api_url,
+                        consumer_key,
+                        access_token.as_deref(),
+                        url,
+                        title,
+                        tags,
+                    )
+                    .await
+                }
+            };
+            results.insert(service.name().to_string(), result);
+        }
+        results
+    }
+
+    // Helper function to get a Wallabag access token: the stored one while it
+    // is valid, then a refreshed one, and a password grant as a last resort
+    async fn wallabag_token(&self, service: &Service, renew: bool) -> Result<String, String> {
+        let Service::Wallabag {
+            url,
+            client_id,
+            client_secret,
+            username,
+            password,
+        } = service
+        else {
+            return Err("Not a Wallabag account".to_string());
+        };
+
+        let stored = self.load_tokens()?.wallabag;
+        if let Some(token) = &stored {
+            if !renew && token.expires_at - Duration::seconds(TOKEN_MARGIN_SECS) > Utc::now() {
+                return Ok(token.access_token.clone());
+            }
+        }
+
+        let mut grants = Vec::new();
+        if let Some(refresh_token) = stored.and_then(|token| token.refresh_token) {
+            grants.push(vec![
+                ("grant_type", "refresh_token".to_string()),
+                ("refresh_token", refresh_token),
+            ]);
+        }
+        grants.push(vec![
+            ("grant_type", "password".to_string()),
+            ("username", username.clone()),
+            ("password", password.clone()),
+        ]);
+
+        let mut last_error = String::new();
+        for mut form in grants {
+            form.push(("client_id", client_id.clone()));
+            form.push(("client_secret", client_secret.clone()));
+            let response = self
+                .client
+                .post(format!("{}/oauth/v2/token", url))
+                .form(&form)
+                .send()
+                .await
+                .map_err(|e| format!("Failed to reach Wallabag: {}", e))?;
+            if !response.status().is_success() {
+                last_error = format!("Wallabag sign-in failed with HTTP {}", response.status());
+                continue;
+            }
+            let token: WallabagTokenResponse = response
+                .json()
+                .await
+                .map_err(|e| format!("Invalid Wallabag token response: {}", e))?;
+            let access_token = token.access_token.clone();
+            self.update_tokens(|tokens| {
+                tokens.wallabag = Some(WallabagToken {
+                    access_token: token.access_token,
+                    refresh_token: token.refresh_token,
+                    expires_at: Utc::now() + Duration::seconds(token.expires_in),
+                })
+            })?;
+            return Ok(access_token);
+        }
+        Err(last_error)
+    }
+
+    // Helper function to create a Wallabag entry, signing in again once if
+    // the token was revoked before it expired
+    async fn save_to_wallabag(
+        &self,
+        service: &Service,
+        url: &str,
+        title: &str,
+        tags: &[String],
+    ) -> Result<(), String> {
+        let Service::Wallabag { url: base_url, .. } = service else {
+            return Err("Not a Wallabag account".to_string());
+        };
+        let body = json!({
+            "url": url,
+            "title": title,
+            "tags": tags.join(","),
+        });
+
+        for renew in [false, true] {
+            let token = self.wallabag_token(service, renew).await?;
+            let response = self
+                .client
+                .post(format!("{}/api/entries.json", base_url))
+                .bearer_auth(token)
+                .json(&body)
+                .send()
+                .await
+                .map_err(|e| format!("Failed to reach Wallabag: {}", e))?;
+            if response.status() == reqwest::StatusCode::UNAUTHORIZED && !renew {
+                continue;
+            }
+            if !response.status().is_success() {
+                return Err(format!("Wallabag returned HTTP {}", response.status()));
+            }
+            return Ok(());
+        }
+        Err("Wallabag rejected a fresh token".to_string())
+    }
+
+    async fn save_to_pocket(
+        &self,
+        api_url: &str,
+        consumer_key: &str,
+        access_token: Option<&str>,
+        url: &str,
+        title: &str,
+        tags: &[String],
+    ) -> Result<(), String> {
+        let access_token = match access_token {
+            Some(access_token) => access_token.to_string(),
+            None => self
+                .load_tokens()?
+                .pocket_access_token
+                .ok_or("Pocket isn't connected yet, see /api/read-later/pocket/connect")?,
+        };
+        let response = self
+            .client
+            .post(format!("{}/v3/add", api_url))
+            .header("X-Accept", "application/json")
+            .json(&json!({
+                "url": url,
+                "title": title,
+                "tags": tags.join(","),
+                "consumer_key": consumer_key,
+                "access_token": access_token,
+            }))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach Pocket: {}", e))?;
+        if !response.status().is_success() {
+            let reason = response
+                .headers()
+                .get("X-Error")
+                .and_then(|value| value.to_str().ok())
+                .map(|reason| format!(": {}", reason))
+                .unwrap_or_default();
+            return Err(format!(
+                "Pocket returned HTTP {}{}",
+                response.status(),
+                reason
+            ));
+        }
+        Ok(())
+    }
+
+    fn pocket(&self) -> Option<(&str, &str)> {
+        self.services.iter().find_map(|service| match service {
+            Service::Pocket {
+                api_url,
+                consumer_key,
+                ..
+            } => Some((api_url.as_str(), consumer_key.as_str())),
+            _ => None,
+        })
+    }
+
+    // Helper function to start Pocket's OAuth flow: gets a request token,
+    // remembers it and returns the page where the user approves the app
+    pub async fn pocket_authorize_url(&self, redirect_uri: &str) -> Result<String, String> {
+        let (api_url, consumer_key) = self.pocket().ok_or("POCKET_CONSUMER_KEY is not set")?;
+        let response = self
+            .client
+            .post(format!("{}/v3/oauth/request", api_url))
+            .header("X-Accept", "application/json")
+            .json(&json!({ "consumer_key": consumer_key, "redirect_uri": redirect_uri }))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach Pocket: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Pocket returned HTTP {}", response.status()));
+        }
+        let code: PocketCode = response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid Pocket response: {}", e))?;
+
+        let mut authorize_url = reqwest::Url::parse(&format!("{}/auth/authorize", api_url))
+            .map_err(|e| format!("Invalid POCKET_API_URL: {}", e))?;
+        authorize_url
+            .query_pairs_mut()
+            .append_pair("request_token", &code.code)
+            .append_pair("redirect_uri", redirect_uri);
+        self.update_tokens(|tokens| tokens.pocket_request_token = Some(code.code))?;
+        Ok(authorize_url.to_string())
+    }
+
+    // Helper function to finish Pocket's OAuth flow once the user approved
+    // the app, storing the access token. Returns the Pocket username
+    pub async fn pocket_connect(&self) -> Result<String, String> {
+        let (api_url, consumer_key) = self.pocket().ok_or("POCKET_CONSUMER_KEY is not set")?;
+        let code = self
+            .load_tokens()?
+            .pocket_request_token
+            .ok_or("No Pocket authorization is pending")?;
+        let response = self
+            .client
+            .post(format!("{}/v3/oauth/authorize", api_url))
+            .header("X-Accept", "application/json")
+            .json(&json!({ "consumer_key": consumer_key, "code": code }))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach Pocket: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "Pocket refused the authorization (HTTP {})",
+                response.status()
+            ));
+        }
+        let authorization: PocketAuthorization = response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid Pocket response: {}", e))?;
+        self.update_tokens(|tokens| {
+            tokens.pocket_access_token = Some(authorization.access_token);
+            tokens.pocket_request_token = None;
+        })?;
+        Ok(authorization.username.unwrap_or_default())
+    }
+}
+
+// Tags an article is saved with: the site, plus its section when known
+fn tags(link: &str) -> Vec<String> {
+    let mut tags = vec!["corriere".to_string()];
+    tags.extend(extract::section(link));
+    tags
+}
+
+fn record(state: &AppState, results: &BTreeMap<String, Result<(), String>>) {
+    for (service, result) in results {
+        let outcome = if result.is_ok() { "saved" } else { "failed" };
+        state.metrics.increment(
+            "corriere_read_later_saves_total",
+            &[("service", service), ("result", outcome)],
+        );
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SaveRequest {
+    pub url: String,
+    pub title: Option<String>,
+}
+
+// Saves one article to the read-later accounts (admin token required)
+pub async fn save_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SaveRequest>,
+) -> Response {
+    if let Some(response) = unauthorized(&state, &headers) {
+        return response;
+    }
+    let read_later = match ReadLater::from_config(&state.config) {
+        Ok(Some(read_later)) => read_later,
+        Ok(None) => {
+            return admin_error(StatusCode::NOT_FOUND, "No read-later service is configured")
+        }
+        Err(error_message) => {
+            return admin_error(StatusCode::INTERNAL_SERVER_ERROR, &error_message)
+        }
+    };
+    let url = match reqwest::Url::parse(request.url.trim()) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => url.to_string(),
+        _ => return admin_error(StatusCode::BAD_REQUEST, "url must be an http(s) URL"),
+    };
+
+    let title = request.title.unwrap_or_default();
+    let results = read_later.save(&url, &title, &tags(&url)).await;
+    record(&state, &results);
+    // Saved when any service took it, as the response status says
+    let outcome = if results.values().any(Result::is_ok) {
+        Ok(())
+    } else {
+        let errors: Vec<String> = results
+            .iter()
+            .filter_map(|(service, result)| {
+                result.as_ref().err().map(|e| format!("{}: {}", service, e))
+            })
+            .collect();
+        Err(errors.join("; "))
+    };
+    let event = AuditEvent::admin(
+        "read_later_save",
+        actor(&state, &headers),
+        Some(&url),
+        &outcome,
+    );
+    audit::record(&state, event).await;
+
+    let services: BTreeMap<&String, Value> = results
+        .iter()
+        .map(|(service, result)| {
+            let outcome = match result {
+                Ok(()) => json!({ "saved": true }),
+                Err(error_message) => json!({ "saved": false, "error": error_message }),
+            };
+            (service, outcome)
+        })
+        .collect();
+    let status = if results.values().any(Result::is_ok) {
+        StatusCode::OK
+    } else {
+        StatusCode::BAD_GATEWAY
+    };
+    (status, Json(json!({ "url": url, "services": services }))).into_response()
+}
+
+// Starts connecting the Pocket account (admin token required). Open the
+// returned authorize_url in a browser; Pocket then sends it back to the
+// callback below
+pub async fn pocket_connect_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(response) = unauthorized(&state, &headers) {
+        return response;
+    }
+    let Ok(Some(read_later)) = ReadLater::from_config(&state.config) else {
+        return admin_error(StatusCode::NOT_FOUND, "POCKET_CONSUMER_KEY is not set");
+    };
+    let redirect_uri = format!("{}/api/read-later/pocket/callback", state.config.public_url);
+    let result = read_later.pocket_authorize_url(&redirect_uri).await;
+    let event = AuditEvent::admin("pocket_connect", actor(&state, &headers), None, &result);
+    audit::record(&state, event).await;
+    match result {
+        Ok(authorize_url) => Json(json!({ "authorize_url": authorize_url })).into_response(),
+        Err(error_message) => admin_error(StatusCode::BAD_GATEWAY, &error_message),
+    }
+}
+
+// Where Pocket sends the browser back once the app is approved
+pub async fn pocket_callback_handler(State(state): State<AppState>) -> Response {
+    let Ok(Some(read_later)) = ReadLater::from_config(&state.config) else {
+        return admin_error(StatusCode::NOT_FOUND, "POCKET_CONSUMER_KEY is not set");
+    };
+    match read_later.pocket_connect().await {
+        Ok(username) => format!("Pocket connected as {}", username).into_response(),
+        Err(error_message) => admin_error(StatusCode::BAD_GATEWAY, &error_message),
+    }
+}
+
+// Helper function to save the new homepage articles from READ_LATER_SECTIONS.
+// As with Telegram, the first run only records the current items
+pub async fn push_sections(
+    state: &AppState,
+    read_later: &ReadLater,
+    sent: &mut SentLog,
+    news: &[NewsItem],
+) -> Result<usize, String> {
+    let sections = &state.config.read_later_sections;
+    let selected: Vec<&NewsItem> = news
+        .iter()
+        .filter(|item| {
+            extract::section(&item.link).is_some_and(|section| sections.contains(&section))
+        })
+        .collect();
+
+    if !sent.is_stored() {
+        for item in &selected {
+            sent.push(item.link.clone());
+        }
+        sent.save()?;
+        return Ok(0);
+    }
+
+    let mut saved = 0;
+    for item in selected {
+        if sent.contains(&item.link) {
+            continue;
+        }
+        let results = read_later
+            .save(&item.link, &item.title, &tags(&item.link))
+            .await;
+        record(state, &results);
+        for (service, result) in &results {
+            if let Err(error_message) = result {
+                eprintln!("Saving to {} failed: {}", service, error_message);
+            }
+        }
+        if results.values().any(Result::is_ok) {
+            saved += 1;
+        }
+        // Recorded even after a failure, as a retry could duplicate the
+        // article in the accounts where it went through
+        sent.push(item.link.clone());
+    }
+    sent.save()?;
+    Ok(saved)
+}
+
+// Checks the homepage every READ_LATER_POLL_SECS and saves the new articles
+// from READ_LATER_SECTIONS, for as long as the server runs
+pub async fn run(state: AppState, read_later: ReadLater) {
+    let every = std::time::Duration::from_secs(state.config.read_later_poll_secs.max(1));
+    let mut interval = tokio::time::interval(every);
+    let mut sent = match SentLog::load(&state.config, "read_later") {
+        Ok(sent) => sent,
+        Err(error_message) => {
+            eprintln!("Read-later: {}", error_message);
+            return;
+        }
+    };
+
+    loop {
+        interval.tick().await;
+        // Settings changed by a reload apply from this run on
+        let state = state.current();
+        let ttl = lease::lease_ttl(every);
+        if !sent_log::should_post(&state, "read_later", ttl, &mut [&mut sent]).await {
+            continue;
+        }
+
+        let news = match crate::get_news(&state).await {
+            Ok(cached) if !cached.response.stale => cached.response.news,
+            Ok(_) => continue,
+            Err(response) => {
+                eprintln!(
+                    "Read-later: homepage unavailable: {}",
+                    response.error.unwrap_or_default()
+                );
+                continue;
+            }
+        };
+
+        if let Err(error_message) = push_sections(&state, &read_later, &mut sent, &news).await {
+            eprintln!("Read-later push failed: {}", error_message);
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Json, Router};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use tower::{service_fn, ServiceExt};
+
+use crate::admin;
+use crate::audit::{self, AuditEvent};
+use crate::config::Config;
+use crate::url_safety::{self, UrlPolicy};
+use crate::{scheduler, AppState};
+
+// Variables set before .env was read. They win over .env at startup, so a
+// reload mustn't let .env override them either
+static ORIGINAL_ENV: OnceLock<HashSet<String>> = OnceLock::new();
+
+// The clients built from a configuration's URL policy, so scrapes and
+// callbacks refuse the same hosts check and check_callback do
+#[derive(Clone)]
+pub struct Clients {
+    pub scrape: reqwest::Client,
+    pub callback: reqwest::Client,
+}
+
+impl Clients {
+    pub fn new(config: &Config) -> Clients {
+        let policy = UrlPolicy::from_config(config);
+        Clients {
+            scrape: url_safety::build_client(&policy),
+            callback: url_safety::build_callback_client(&policy, url_safety::CALLBACK_TIMEOUT),
+        }
+    }
+}
+
+// The configuration the server runs with, replaced as a whole by a reload,
+// with the clients built from its URL policy
+pub struct LiveConfig {
+    current: RwLock<(Arc<Config>, Clients)>,
+    // Held for the whole of a reload, so two can't interleave
+    reloading: Mutex<()>,
+}
+
+impl LiveConfig {
+    pub fn new(config: Arc<Config>, clients: Clients) -> LiveConfig {
+        LiveConfig {
+            current: RwLock::new((config, clients)),
+            reloading: Mutex::new(()),
+        }
+    }
+
+    pub fn get(&self) -> (Arc<Config>, Clients) {
+        self.current.read().unwrap().clone()
+    }
+}
+
+impl AppState {
+    // The state with the latest configuration. Handlers get it through the
+    // router; background tasks call this at the start of each run
+    pub fn current(&self) -> AppState {
+        let (config, clients) = self.live_config.get();
+        AppState {
+            config,
+            scrape_client: clients.scrape,
+            callback_client: clients.callback,
+            ..self.clone()
+        }
+    }
+}
+
+// What a reload changed
+#[derive(Serialize)]
+pub struct ReloadReport {
+    pub reloaded_at: DateTime<Utc>,
+    // Settings that changed but are only read at startup; they keep their
+    // running values until the next restart
+    pub requires_restart: Vec<&'static str>,
+    // Scheduled jobs started or restarted for a new interval or URL
+    pub restarted_jobs: Vec<String>,
+}
+
+// Helper function to note the variables set before .env is read. Called by
+// main before dotenv
+pub fn capture_environment() {
+    let _ = ORIGINAL_ENV.set(std::env::vars().map(|(name, _)| name).collect());
+}
+
+// Helper function to gather the variables a reload reads: .env read again,
+// under the variables set before .env was first read, which win over it as
+// they did at startup. A variable removed from .env is unset from then on,
+// even though the process environment still holds the old value
+fn variables() -> Result<HashMap<String, String>, String> {
+    let mut variables = HashMap::new();
+    // No .env: the environment alone
+    if let Ok(entries) = dotenvy::from_filename_iter(".env") {
+        for entry in entries {
+            let (name, value) = entry.map_err(|e| format!("Failed to read .env: {}", e))?;
+            variables.insert(name, value);
+        }
+    }
+    let original = ORIGINAL_ENV.get();
+    for (name, value) in std::env::vars() {
+        if original.is_none_or(|original| original.contains(&name)) {
+            variables.insert(name, value);
+        }
+    }
+    Ok(variables)
+}
+
+// Helper function to keep a startup-only setting at its running value,
+// noting it when the new configuration wanted it changed
+fn keep<T: PartialEq + Clone>(
+    name: &'static str,
+    running: &T,
+    new: &mut T,
+    requires_restart: &mut Vec<&'static str>,
+) {
+    if running != new {
+        requires_restart.push(name);
+        *new = running.clone();
+    }
+}
+
+// Helper function to carry over what only takes effect at startup: the
+// listener, DATA_DIR, the backends, the queues and breaker built from these
+// limits, and the intervals, accounts and clients the notifiers are set up
+// with. The notifiers read the rest of their settings again on each run
+fn keep_startup_settings(running: &Config, new: &mut Config) -> Vec<&'static str> {
+    let mut kept = Vec::new();
+    macro_rules! keep {
+        ($($name:literal => $field:ident),* $(,)?) => {
+            $(keep($name, &running.$field, &mut new.$field, &mut kept);)*
+        };
+    }
+    keep!(
+        "BIND_ADDR" => bind_addr,
+        "UNIX_SOCKET_PATH" => unix_socket_path,
+        "UNIX_SOCKET_MODE" => unix_socket_mode,
+        "DATA_DIR" => data_dir,
+        "ARCHIVE_URL" => archive_url,
+        "LOCK_URL" => lock_url,
+        "SMTP_URL" => smtp_url,
+        "DIGEST_FROM" => digest_from,
+        "AUDIT_LOG" => audit_log,
+        "FETCH_CONCURRENCY" => fetch_concurrency,
+        "FETCH_QUEUE_SIZE" => fetch_queue_size,
+        "PER_HOST_CONCURRENCY" => per_host_concurrency,
+        "BREAKER_FAILURE_THRESHOLD" => breaker_failure_threshold,
+        "BREAKER_COOLDOWN_SECS" => breaker_cooldown_secs,
+        "SNAPSHOT_HTML" => snapshot_html,
+        "SNAPSHOT_MAX_FILES" => snapshot_max_files,
+        "SNAPSHOT_MAX_AGE_DAYS" => snapshot_max_age_days,
+        "DELIVERY_MAX_ATTEMPTS" => delivery_max_attempts,
+        "DELIVERY_BACKOFF_SECS" => delivery_backoff_secs,
+        "DELIVERY_MAX_BACKOFF_SECS" => delivery_max_backoff_secs,
+        "DELIVERY_RETRY_SECS" => delivery_retry_secs,
+        "WEBHOOKS_ENABLED" => webhooks_enabled,
+        "WEBHOOK_POLL_SECS" => webhook_poll_secs,
+        "WATCH_ENABLED" => watch_enabled,
+        "WATCH_POLL_SECS" => watch_poll_secs,
+        "ALERT_POLL_SECS" => alert_poll_secs,
+        "TELEGRAM_BOT_TOKEN" => telegram_bot_token,
+        "TELEGRAM_CHAT_IDS" => telegram_chat_ids,
+        "TELEGRAM_TOP_N" => telegram_top_n,
+        "TELEGRAM_POLL_SECS" => telegram_poll_secs,
+        "TELEGRAM_API_URL" => telegram_api_url,
+        "MQTT_URL" => mqtt_url,
+        "MQTT_CLIENT_ID" => mqtt_client_id,
+        "MQTT_TOPIC" => mqtt_topic,
+        "MQTT_BREAKING_TOPIC" => mqtt_breaking_topic,
+        "MQTT_QOS" => mqtt_qos,
+        "MQTT_RETAIN" => mqtt_retain,
+        "MQTT_POLL_SECS" => mqtt_poll_secs,
+        "SOCIAL_TEMPLATE" => social_template,
+        "SOCIAL_SECTIONS" => social_sections,
+        "SOCIAL_TOP_N" => social_top_n,
+        "SOCIAL_POLL_SECS" => social_poll_secs,
+        "MASTODON_URL" => mastodon_url,
+        "MASTODON_TOKEN" => mastodon_token,
+        "MASTODON_MIN_INTERVAL_SECS" => mastodon_min_interval_secs,
+        "BLUESKY_HANDLE" => bluesky_handle,
+        "BLUESKY_APP_PASSWORD" => bluesky_app_password,
+        "BLUESKY_PDS_URL" => bluesky_pds_url,
+        "BLUESKY_MIN_INTERVAL_SECS" => bluesky_min_interval_secs,
+        "WALLABAG_URL" => wallabag_url,
+        "WALLABAG_CLIENT_ID" => wallabag_client_id,
+        "WALLABAG_CLIENT_SECRET" => wallabag_client_secret,
+        "WALLABAG_USERNAME" => wallabag_username,
+        "WALLABAG_PASSWORD" => wallabag_password,
+        "POCKET_CONSUMER_KEY" => pocket_consumer_key,
+        "POCKET_ACCESS_TOKEN" => pocket_access_token,
+        "POCKET_API_URL" => pocket_api_url,
+        "READ_LATER_SECTIONS" => read_later_sections,
+        "READ_LATER_POLL_SECS" => read_later_poll_secs,
+        "EMBEDDING_COMMAND" => embedding_command,
+        "EMBEDDING_API_URL" => embedding_api_url,
+        "EMBEDDING_API_KEY" => embedding_api_key,
+        "EMBEDDING_MODEL" => embedding_model,
+        "EMBEDDING_BATCH_SIZE" => embedding_batch_size,
+        "EMBEDDING_POLL_SECS" => embedding_poll_secs,
+        "SEMANTIC_QUERIES_PER_MINUTE" => semantic_queries_per_minute,
+        "S3_BUCKET" => s3_bucket,
+        "S3_ENDPOINT" => s3_endpoint,
+        "S3_REGION" => s3_region,
+        "S3_ACCESS_KEY_ID" => s3_access_key_id,
+        "S3_SECRET_ACCESS_KEY" => s3_secret_access_key,
+        "S3_PATH_STYLE" => s3_path_style,
+        "S3_KEY_TEMPLATE" => s3_key_template,
+        "S3_EXPORT_SNAPSHOTS" => s3_export_snapshots,
+        "S3_EXPORT_FORMAT" => s3_export_format,
+    );
+    kept
+}
+
+// Helper function to reload the configuration from .env and the
+// environment. The new configuration is validated in full first; when it's
+// invalid nothing changes and the error is returned. Otherwise it replaces
+// the running one in one step: requests from then on are served by a router
+// built with it (CORS, timeouts, limits) and the scheduler's jobs follow
+// SCHEDULER_JOBS
+pub fn reload(state: &AppState) -> Result<ReloadReport, String> {
+    let result =
+        variables().and_then(|variables| Config::from_lookup(&|name| variables.get(name).cloned()));
+    apply(state, result)
+}
+
+// Like reload, with the configuration read from `variables` alone
+pub fn reload_from(
+    state: &AppState,
+    variables: &HashMap<String, String>,
+) -> Result<ReloadReport, String> {
+    apply(
+        state,
+        Config::from_lookup(&|name| variables.get(name).cloned()),
+    )
+}
+
+// Helper function to put a newly read configuration in place of the running
+// one, or count it as invalid
+fn apply(state: &AppState, result: Result<Config, String>) -> Result<ReloadReport, String> {
+    let live = &state.live_config;
+    let _reloading = live.reloading.lock().unwrap();
+    let mut config = match result {
+        Ok(config) => config,
+        Err(error_message) => {
+            state
+                .metrics
+                .increment("corriere_config_reloads_total", &[("result", "invalid")]);
+            return Err(error_message);
+        }
+    };
+
+    let (running, _) = live.get();
+    let requires_restart = keep_startup_settings(&running, &mut config);
+    let jobs = config.scheduler_jobs.clone();
+    let clients = Clients::new(&config);
+    *live.current.write().unwrap() = (Arc::new(config), clients);
+
+    let state = state.current();
+    let mut restarted_jobs = Vec::new();
+    for (job, generation) in state.scheduler.replace_jobs(jobs) {
+        restarted_jobs.push(job.name.clone());
+        scheduler::spawn_job(state.clone(), job, generation);
+    }
+    state
+        .metrics
+        .increment("corriere_config_reloads_total", &[("result", "ok")]);
+    Ok(ReloadReport {
+        reloaded_at: Utc::now(),
+        requires_restart,
+        restarted_jobs,
+    })
+}
+
+// Helper function to wrap the routes in a router that rebuilds them whenever
+// the configuration was reloaded, so each request sees one configuration
+// from its first middleware to its handler
+pub fn reloadable(state: AppState, routes: fn(AppState) -> Router) -> Router {
+    let built = Arc::new(Mutex::new((state.config.clone(), routes(state.clone()))));
+    Router::new().fallback_service(service_fn(move |request: Request| {
+        let router = {
+            let mut built = built.lock().unwrap();
+            let (config, _) = state.live_config.get();
+            if !Arc::ptr_eq(&built.0, &config) {
+                *built = (config, routes(state.current()));
+            }
+            built.1.clone()
+        };
+        async move { Ok::<_, Infallible>(router.oneshot(request).await.into_response()) }
+    }))
+}
+
+// Reloads the configuration on SIGHUP, for as long as the server runs
+pub async fn run(state: AppState) {
+    let mut hangups = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            eprintln!("Failed to listen for SIGHUP: {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        let result = reload(&state);
+        let event = AuditEvent::admin("reload", Some("signal".to_string()), None, &result);
+        audit::record(&state, event).await;
+        match result {
+            Ok(report) if report.requires_restart.is_empty() => {
+                println!("Reloaded the configuration")
+            }
+            Ok(report) => println!(
+                "Reloaded the configuration; {} only change on restart",
+                report.requires_restart.join(", ")
+            ),
+            Err(error_message) => eprintln!(
+                "Configuration not reloaded, keeping the running one: {}",
+                error_message
+            ),
+        }
+    }
+}
+
+// Reloads the configuration, like SIGHUP. An invalid configuration is a 422
+// and leaves the running one in place
+pub async fn reload_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(response) = admin::unauthorized(&state, &headers) {
+        return response;
+    }
+    let result = reload(&state);
+    let event = AuditEvent::admin("reload", admin::actor(&state, &headers), None, &result);
+    audit::record(&state, event).await;
+    match result {
+        Ok(report) => Json(report).into_response(),
+        Err(error_message) => admin::admin_error(StatusCode::UNPROCESSABLE_ENTITY, &error_message),
+    }
+}
@@ -0,0 +1,215 @@
+use chrono::{DateTime, Utc};
+use scraper::{ElementRef, Html, Selector};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::extract::{self, ParseMode, SelectorConfig, Selectors};
+use crate::AppState;
+
+// A repeated card needs at least this many headlines to be worth suggesting
+const MIN_ITEMS: usize = 3;
+// How far above a headline the card container is looked for
+const MAX_DEPTH: usize = 4;
+const MAX_SUGGESTIONS: usize = 5;
+
+// A set of selectors that would extract items from a page the configured
+// ones found nothing on, with what it extracts as evidence
+#[derive(Serialize, Clone, Debug)]
+pub struct Suggestion {
+    pub selectors: SelectorConfig,
+    pub items: usize,
+    pub with_images: usize,
+    pub sample_titles: Vec<String>,
+}
+
+// The suggestions made for the latest empty scrape of a page
+#[derive(Serialize, Clone, Debug)]
+pub struct RepairReport {
+    pub source: String,
+    pub url: String,
+    pub detected_at: DateTime<Utc>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+// Latest report per scraped page, for the admin API
+#[derive(Default)]
+pub struct RepairLog {
+    reports: Mutex<HashMap<String, RepairReport>>,
+}
+
+impl RepairLog {
+    pub fn reports(&self) -> Vec<RepairReport> {
+        let mut reports: Vec<RepairReport> =
+            self.reports.lock().unwrap().values().cloned().collect();
+        reports.sort_by(|a, b| a.source.cmp(&b.source));
+        reports
+    }
+
+    fn insert(&self, report: RepairReport) {
+        self.reports
+            .lock()
+            .unwrap()
+            .insert(report.source.clone(), report);
+    }
+
+    // Forgets a page's report once its selectors work again
+    pub fn clear(&self, source: &str) {
+        self.reports.lock().unwrap().remove(source);
+    }
+}
+
+// Headlines sharing a card selector, tallied while walking the page
+#[derive(Default)]
+struct Group<'a> {
+    members: Vec<ElementRef<'a>>,
+    titles: HashMap<String, usize>,
+    summaries: HashMap<String, usize>,
+}
+
+// Helper function to describe an element as `tag.class` using its first
+// class, in source order, that is a plain CSS identifier
+fn element_selector(element: ElementRef) -> Option<String> {
+    let classes = element.value().attr("class")?;
+    let class = classes.split_whitespace().find(|class| {
+        class
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            && !class.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+    })?;
+    Some(format!("{}.{}", element.value().name(), class))
+}
+
+fn most_common(counts: &HashMap<String, usize>) -> Option<String> {
+    counts
+        .iter()
+        .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+        .map(|(selector, _)| selector.clone())
+}
+
+// Helper function to look for repeated article cards (a classed element
+// holding a linked h2/h3/h4 headline) and turn each kind into a selector
+// config. Every candidate is checked by running the real extraction with it,
+// so only configs that actually produce items are suggested
+pub fn suggest(html: &str, base_url: &str) -> Vec<Suggestion> {
+    let document = Html::parse_document(html);
+    let headings = Selector::parse("h2, h3, h4").unwrap();
+    let anchor = Selector::parse("a[href]").unwrap();
+    let paragraph = Selector::parse("p").unwrap();
+
+    let mut groups: HashMap<String, Group> = HashMap::new();
+    for heading in document.select(&headings) {
+        if heading.select(&anchor).next().is_none() {
+            continue;
+        }
+        let card = heading
+            .ancestors()
+            .filter_map(ElementRef::wrap)
+            .take(MAX_DEPTH)
+            .find_map(|ancestor| element_selector(ancestor).map(|selector| (ancestor, selector)));
+        let Some((card, card_selector)) = card else {
+            continue;
+        };
+
+        let group = groups.entry(card_selector).or_default();
+        group.members.push(card);
+        let title = element_selector(heading).unwrap_or_else(|| heading.value().name().to_string());
+        *group.titles.entry(title).or_default() += 1;
+        if let Some(summary) = card
+            .select(&paragraph)
+            .find(|p| !p.ancestors().any(|node| node == *heading))
+        {
+            let summary = element_selector(summary).unwrap_or_else(|| "p".to_string());
+            *group.summaries.entry(summary).or_default() += 1;
+        }
+    }
+
+    let mut suggestions: Vec<Suggestion> = groups
+        .into_iter()
+        .filter(|(_, group)| group.members.len() >= MIN_ITEMS)
+        .filter_map(|(article, group)| {
+            let config = SelectorConfig {
+                container: container_selector(&group.members),
+                article,
+                title: most_common(&group.titles)?,
+                link: "a".to_string(),
+                summary: most_common(&group.summaries).unwrap_or_else(|| "p".to_string()),
+                image: "img".to_string(),
+                ..SelectorConfig::default()
+            };
+            let selectors = Selectors::parse(&config).ok()?;
+            let news = extract::extract_news(
+                html,
+                &selectors,
+                base_url,
+                extract::HOMEPAGE_LIMIT,
+                ParseMode::Document,
+            );
+            let news: Vec<_> = news
+                .into_iter()
+                .filter(|item| !item.title.is_empty() && !item.link.is_empty())
+                .collect();
+            (news.len() >= MIN_ITEMS).then(|| Suggestion {
+                selectors: config,
+                items: news.len(),
+                with_images: news.iter().filter(|item| item.image_url.is_some()).count(),
+                sample_titles: news.iter().take(3).map(|item| item.title.clone()).collect(),
+            })
+        })
+        .collect();
+
+    suggestions.sort_by(|a, b| {
+        (b.items, b.with_images)
+            .cmp(&(a.items, a.with_images))
+            .then_with(|| a.selectors.article.cmp(&b.selectors.article))
+    });
+    suggestions.truncate(MAX_SUGGESTIONS);
+    suggestions
+}
+
+// Helper function to pick the innermost classed element holding every card,
+// falling back to the whole body
+fn container_selector(members: &[ElementRef]) -> String {
+    let Some((first, rest)) = members.split_first() else {
+        return "body".to_string();
+    };
+    first
+        .ancestors()
+        .filter(|ancestor| {
+            rest.iter()
+                .all(|member| member.ancestors().any(|node| node == *ancestor))
+        })
+        .filter_map(ElementRef::wrap)
+        .find_map(element_selector)
+        .unwrap_or_else(|| "body".to_string())
+}
+
+// Helper function to record and log the suggestions for an empty scrape of
+// `source`, so an operator can update the selectors
+pub fn report(state: &AppState, source: &str, url: &str, suggestions: Vec<Suggestion>) {
+    state
+        .metrics
+        .increment("corriere_empty_scrapes_total", &[("source", source)]);
+    match suggestions.first() {
+        Some(best) => eprintln!(
+            "Scrape of {} ({}) found no items; suggested selectors: container {:?}, article {:?}, title {:?}, summary {:?} ({} items)",
+            source,
+            url,
+            best.selectors.container,
+            best.selectors.article,
+            best.selectors.title,
+            best.selectors.summary,
+            best.items
+        ),
+        None => eprintln!(
+            "Scrape of {} ({}) found no items and no replacement selectors",
+            source, url
+        ),
+    }
+    state.repairs.insert(RepairReport {
+        source: source.to_string(),
+        url: url.to_string(),
+        detected_at: Utc::now(),
+        suggestions,
+    });
+}
@@ -0,0 +1,84 @@
+use axum::extract::Request;
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+use std::future::Future;
+
+use crate::subscriptions::random_string;
+
+pub const HEADER: &str = "x-request-id";
+
+// Longest client supplied id we pass on; anything else gets a fresh one
+const MAX_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+// The id of the request (or background run) being handled, added to request
+// extensions for handlers that want to extract it
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+pub fn generate() -> String {
+    random_string(20)
+}
+
+// Helper function to keep a client supplied id only when it's short and
+// printable, so it is safe to log and to send on in headers
+fn accept(value: &HeaderValue) -> Option<String> {
+    let value = value.to_str().ok()?.trim();
+    let valid =
+        !value.is_empty() && value.len() <= MAX_LEN && value.chars().all(|c| c.is_ascii_graphic());
+    valid.then(|| value.to_string())
+}
+
+// The current request id, when running inside `scope`
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+// Helper function to prefix log lines with the current request id
+pub fn prefix() -> String {
+    current().map(|id| format!("[{}] ", id)).unwrap_or_default()
+}
+
+// Runs a future with `id` as its request id
+pub async fn scope<F: Future>(id: String, future: F) -> F::Output {
+    REQUEST_ID.scope(id, future).await
+}
+
+// Helper function to spawn a task that keeps the current request id, so
+// work started by a request logs under the same id
+pub fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    match current() {
+        Some(id) => tokio::spawn(REQUEST_ID.scope(id, future)),
+        None => tokio::spawn(future),
+    };
+}
+
+// Middleware that takes the X-Request-Id of each request, or generates one,
+// makes it the request id while the request is handled and echoes it back.
+// Server errors are logged with it so client reports can be matched up
+pub async fn middleware(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(HEADER)
+        .and_then(accept)
+        .unwrap_or_else(generate);
+    request.extensions_mut().insert(RequestId(id.clone()));
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+
+    let mut response = scope(id.clone(), next.run(request)).await;
+    if response.status().is_server_error() {
+        eprintln!("[{}] {} {} -> {}", id, method, path, response.status());
+    }
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(HEADER, value);
+    }
+    response
+}
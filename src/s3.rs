@@ -0,0 +1,180 @@
+use chrono::{Duration as ChronoDuration, NaiveDate, Utc};
+use reqwest::Url;
+use rusty_s3::{Bucket, Credentials, S3Action, UrlStyle};
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::digest::next_run;
+use crate::export::{self, day_range, ExportFormat};
+use crate::{lease, snapshot, AppState};
+
+// How long a presigned upload URL stays valid
+const SIGNATURE_TTL: Duration = Duration::from_secs(300);
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Uploads each day's archive, and optionally its raw homepage snapshots, to
+// an S3-compatible bucket
+pub struct S3Exporter {
+    bucket: Bucket,
+    credentials: Credentials,
+    client: reqwest::Client,
+    key_template: String,
+    snapshots: bool,
+    format: ExportFormat,
+}
+
+impl S3Exporter {
+    // Helper function to build the exporter, or None when no bucket is configured
+    pub fn from_config(config: &Config) -> Result<Option<S3Exporter>, String> {
+        let Some(bucket) = &config.s3_bucket else {
+            return Ok(None);
+        };
+        let (Some(key), Some(secret)) = (&config.s3_access_key_id, &config.s3_secret_access_key)
+        else {
+            return Err(
+                "S3_BUCKET is set but S3_ACCESS_KEY_ID or S3_SECRET_ACCESS_KEY is missing"
+                    .to_string(),
+            );
+        };
+
+        let endpoint = Url::parse(&config.s3_endpoint)
+            .map_err(|e| format!("Invalid S3_ENDPOINT '{}': {}", config.s3_endpoint, e))?;
+        let style = if config.s3_path_style {
+            UrlStyle::Path
+        } else {
+            UrlStyle::VirtualHost
+        };
+        let bucket = Bucket::new(endpoint, style, bucket.clone(), config.s3_region.clone())
+            .map_err(|e| format!("Invalid S3 bucket settings: {:?}", e))?;
+
+        Ok(Some(S3Exporter {
+            bucket,
+            credentials: Credentials::new(key.clone(), secret.clone()),
+            client: reqwest::Client::builder()
+                .timeout(UPLOAD_TIMEOUT)
+                .build()
+                .map_err(|e| format!("Failed to create S3 client: {}", e))?,
+            key_template: config.s3_key_template.clone(),
+            snapshots: config.s3_export_snapshots,
+            format: config.s3_export_format,
+        }))
+    }
+
+    // Helper function to fill in the key template. {kind} is "items" or
+    // "snapshots", {name} the file name and {date}, {year}, {month}, {day}
+    // the day being exported
+    pub fn object_key(&self, kind: &str, day: NaiveDate, name: &str) -> String {
+        self.key_template
+            .replace("{kind}", kind)
+            .replace("{date}", &day.format("%Y-%m-%d").to_string())
+            .replace("{year}", &day.format("%Y").to_string())
+            .replace("{month}", &day.format("%m").to_string())
+            .replace("{day}", &day.format("%d").to_string())
+            .replace("{name}", name)
+    }
+
+    // Helper function to upload one day. Returns the number of objects written
+    pub async fn export_day(&self, state: &AppState, day: NaiveDate) -> Result<usize, String> {
+        self.export_items(state, day).await?;
+        let (from, to) = day_range(day);
+        let mut uploaded = 1;
+
+        if self.snapshots {
+            let dir = state.config.snapshot_dir();
+            let files = match dir.exists() {
+                true => snapshot::list(&dir)?,
+                false => vec![],
+            };
+            for (path, taken_at) in files {
+                if taken_at < from || taken_at >= to {
+                    continue;
+                }
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let body = std::fs::read(&path)
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                self.put(&self.object_key("snapshots", day, &name), body, "text/html")
+                    .await?;
+                uploaded += 1;
+            }
+        }
+        Ok(uploaded)
+    }
+
+    // Helper function to upload the items of one day, replacing what was
+    // exported before, e.g. after a takedown redacted one of them
+    pub async fn export_items(&self, state: &AppState, day: NaiveDate) -> Result<(), String> {
+        let archive = state
+            .archive
+            .as_ref()
+            .ok_or("The archive is not enabled, set ARCHIVE_URL")?;
+        let (from, to) = day_range(day);
+
+        let rows = export::rows(&archive.scrapes(from, to).await?);
+        let body = export::run_encode(self.format, rows).await?;
+        let name = format!("items.{}", self.format.extension());
+        self.put(
+            &self.object_key("items", day, &name),
+            body,
+            self.format.content_type(),
+        )
+        .await
+    }
+
+    async fn put(&self, key: &str, body: Vec<u8>, content_type: &str) -> Result<(), String> {
+        let url = self
+            .bucket
+            .put_object(Some(&self.credentials), key)
+            .sign(SIGNATURE_TTL);
+        let mut request = self
+            .client
+            .put(url.as_str())
+            .header(reqwest::header::CONTENT_TYPE, content_type);
+        // Gzipped files are stored as such; clients fetching them get them decoded
+        if key.ends_with(".gz") {
+            request = request.header(reqwest::header::CONTENT_ENCODING, "gzip");
+        }
+
+        request
+            .body(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| format!("Failed to upload {}: {}", key, e))?;
+        Ok(())
+    }
+}
+
+// Exports the previous UTC day every night at S3_EXPORT_HOUR (UTC), for as
+// long as the server runs
+pub async fn run(state: AppState, exporter: S3Exporter) {
+    loop {
+        // Settings changed by a reload apply from this run on
+        let state = state.current();
+        let now = Utc::now();
+        let run_at = next_run(now, state.config.s3_export_hour, chrono_tz::UTC);
+        let wait = (run_at - now).to_std().unwrap_or_default();
+        tokio::time::sleep(wait).await;
+        if !lease::should_run(&state, "s3-export", Duration::from_secs(3600)).await {
+            continue;
+        }
+
+        let day = (Utc::now() - ChronoDuration::days(1)).date_naive();
+        match exporter.export_day(&state, day).await {
+            Ok(uploaded) => {
+                state
+                    .metrics
+                    .increment("corriere_s3_exports_total", &[("result", "ok")]);
+                println!("Exported {} to S3 ({} objects)", day, uploaded);
+            }
+            Err(error_message) => {
+                state
+                    .metrics
+                    .increment("corriere_s3_exports_total", &[("result", "failed")]);
+                eprintln!("S3 export of {} failed: {}", day, error_message);
+            }
+        }
+    }
+}
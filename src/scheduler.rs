@@ -0,0 +1,507 @@
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::audit::{self, AuditEvent};
+use crate::extract::{self, SelectorConfig};
+use crate::fetch_queue;
+use crate::heartbeat;
+use crate::lease;
+use crate::plugins;
+use crate::repair;
+use crate::validate;
+use crate::{AppState, NewsItem, NewsResponse};
+
+// The job that refreshes the homepage cache rather than scraping a section
+pub const HOMEPAGE_JOB: &str = "homepage";
+// The job that records a new audio briefing (needs the tts feature)
+pub const BRIEFING_JOB: &str = "briefing";
+// The job that fills in what is built from the archive, e.g. nightly
+pub const BACKFILL_JOB: &str = "backfill";
+
+// A scrape run in the background at a fixed interval. Jobs are configured in
+// SCHEDULER_JOBS as `name:interval[:url]`, e.g. `sport:15m:https://www.corriere.it/sport/`.
+// The homepage, briefing and backfill jobs take no URL
+#[derive(Clone, Debug, PartialEq)]
+pub struct Job {
+    pub name: String,
+    pub every: Duration,
+    // Section page to scrape; the homepage job uses HOMEPAGE_URL, the
+    // briefing job reads the homepage and the backfill job the archive
+    pub url: Option<String>,
+}
+
+impl std::str::FromStr for Job {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut parts = value.trim().splitn(3, ':');
+        let name = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let every = parts
+            .next()
+            .ok_or(format!("missing interval in job '{}'", value))?;
+        let url = parts.next().map(|url| url.trim().to_string());
+
+        if name.is_empty() {
+            return Err(format!("missing name in job '{}'", value));
+        }
+        let every = parse_interval(every)?;
+        if name == BRIEFING_JOB && !cfg!(feature = "tts") {
+            return Err("the briefing job needs the tts feature".to_string());
+        }
+        let builtin = [HOMEPAGE_JOB, BRIEFING_JOB, BACKFILL_JOB].contains(&name.as_str());
+        match (&url, builtin) {
+            (Some(_), true) => return Err(format!("the {} job takes no URL", name)),
+            (None, false) => return Err(format!("job '{}' needs a URL", name)),
+            (Some(url), false) => {
+                Url::parse(url).map_err(|e| format!("invalid URL '{}': {}", url, e))?;
+            }
+            (None, true) => {}
+        }
+
+        Ok(Job { name, every, url })
+    }
+}
+
+// Helper function to parse an interval such as 300, 90s, 15m, 6h or 1d
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => value.split_at(index),
+        None => (value, "s"),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid interval '{}'", value))?;
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(format!("invalid interval '{}'", value)),
+    };
+    let seconds = number
+        .checked_mul(multiplier)
+        .ok_or(format!("interval '{}' is too long", value))?;
+    if seconds == 0 {
+        return Err(format!("invalid interval '{}'", value));
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+// Helper function to spread runs by up to `jitter_pct` percent of the
+// interval either way, so jobs and replicas don't all hit upstream together
+pub fn jittered(every: Duration, jitter_pct: u32) -> Duration {
+    let spread = every.as_secs_f64() * f64::from(jitter_pct.min(100)) / 100.0;
+    if spread == 0.0 {
+        return every;
+    }
+    let offset = rand::thread_rng().gen_range(-spread..=spread);
+    Duration::from_secs_f64((every.as_secs_f64() + offset).max(1.0))
+}
+
+// Helper function to pick a job's first run within its jitter window, so a
+// restart doesn't fire every job at once
+fn startup_delay(every: Duration, jitter_pct: u32) -> Duration {
+    let spread = every.as_secs_f64() * f64::from(jitter_pct.min(100)) / 100.0;
+    Duration::from_secs_f64(rand::thread_rng().gen_range(0.0..=spread))
+}
+
+// What the admin API reports about a job
+#[derive(Serialize, Clone, Default)]
+pub struct JobStatus {
+    pub name: String,
+    pub url: Option<String>,
+    pub interval_secs: u64,
+    pub running: bool,
+    pub runs: u64,
+    pub failures: u64,
+    pub last_started_at: Option<DateTime<Utc>>,
+    pub last_finished_at: Option<DateTime<Utc>>,
+    pub last_items: Option<usize>,
+    pub last_error: Option<String>,
+    pub next_run_at: Option<DateTime<Utc>>,
+}
+
+// Configured jobs with their status, and the latest scrape of each section
+pub struct Scheduler {
+    // Each job with the generation of the task running it. A reload that
+    // changes a job starts a new task, and the old one stops at its next wake
+    jobs: RwLock<Vec<(Job, u64)>>,
+    generations: AtomicU64,
+    status: Mutex<HashMap<String, JobStatus>>,
+    sections: RwLock<HashMap<String, NewsResponse>>,
+}
+
+fn job_status(job: &Job) -> JobStatus {
+    JobStatus {
+        name: job.name.clone(),
+        url: job.url.clone(),
+        interval_secs: job.every.as_secs(),
+        ..JobStatus::default()
+    }
+}
+
+impl Scheduler {
+    pub fn new(jobs: Vec<Job>) -> Scheduler {
+        let status = jobs
+            .iter()
+            .map(|job| (job.name.clone(), job_status(job)))
+            .collect();
+
+        Scheduler {
+            jobs: RwLock::new(jobs.into_iter().map(|job| (job, 0)).collect()),
+            generations: AtomicU64::new(1),
+            status: Mutex::new(status),
+            sections: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn jobs(&self) -> Vec<Job> {
+        let jobs = self.jobs.read().unwrap();
+        jobs.iter().map(|(job, _)| job.clone()).collect()
+    }
+
+    fn job(&self, name: &str) -> Option<Job> {
+        let jobs = self.jobs.read().unwrap();
+        jobs.iter()
+            .find(|(job, _)| job.name == name)
+            .map(|(job, _)| job.clone())
+    }
+
+    // Whether the task of generation `generation` still runs the job
+    fn is_current(&self, name: &str, generation: u64) -> bool {
+        let jobs = self.jobs.read().unwrap();
+        jobs.iter()
+            .any(|(job, current)| job.name == name && *current == generation)
+    }
+
+    // Helper function to switch to the jobs of a reloaded configuration.
+    // Unchanged jobs keep their task and status; returns the new and changed
+    // ones, with the generation their new task runs under
+    pub fn replace_jobs(&self, jobs: Vec<Job>) -> Vec<(Job, u64)> {
+        let mut running = self.jobs.write().unwrap();
+        let mut status = self.status.lock().unwrap();
+        let mut started = Vec::new();
+        let replaced: Vec<(Job, u64)> = jobs
+            .into_iter()
+            .map(
+                |job| match running.iter().find(|(current, _)| *current == job) {
+                    Some((_, generation)) => (job, *generation),
+                    None => {
+                        let generation = self.generations.fetch_add(1, Ordering::Relaxed);
+                        let entry = status
+                            .entry(job.name.clone())
+                            .or_insert_with(|| job_status(&job));
+                        entry.url = job.url.clone();
+                        entry.interval_secs = job.every.as_secs();
+                        started.push((job.clone(), generation));
+                        (job, generation)
+                    }
+                },
+            )
+            .collect();
+        status.retain(|name, _| replaced.iter().any(|(job, _)| job.name == *name));
+        *running = replaced;
+        started
+    }
+
+    // Status of every job, in configuration order
+    pub fn status(&self) -> Vec<JobStatus> {
+        let status = self.status.lock().unwrap();
+        self.jobs()
+            .iter()
+            .filter_map(|job| status.get(&job.name).cloned())
+            .collect()
+    }
+
+    pub fn section(&self, name: &str) -> Option<NewsResponse> {
+        self.sections.read().unwrap().get(name).cloned()
+    }
+
+    // Marks a job as running; false when its previous run hasn't finished
+    fn begin(&self, name: &str) -> bool {
+        let mut status = self.status.lock().unwrap();
+        let Some(job) = status.get_mut(name) else {
+            return false;
+        };
+        if job.running {
+            return false;
+        }
+        job.running = true;
+        job.last_started_at = Some(Utc::now());
+        true
+    }
+
+    fn finish(&self, name: &str, result: &Result<usize, String>) {
+        let mut status = self.status.lock().unwrap();
+        let Some(job) = status.get_mut(name) else {
+            return;
+        };
+        job.running = false;
+        job.runs += 1;
+        job.last_finished_at = Some(Utc::now());
+        match result {
+            Ok(items) => {
+                job.last_items = Some(*items);
+                job.last_error = None;
+            }
+            Err(error_message) => {
+                job.failures += 1;
+                job.last_error = Some(error_message.clone());
+            }
+        }
+    }
+
+    // Helper function to mark a run as finished even when its task panicked or
+    // was cancelled, so the job isn't skipped as still running forever after
+    fn guard<'a>(&'a self, name: &'a str) -> RunGuard<'a> {
+        RunGuard {
+            scheduler: self,
+            name,
+            finished: false,
+        }
+    }
+
+    fn schedule(&self, name: &str, delay: Duration) {
+        if let Some(job) = self.status.lock().unwrap().get_mut(name) {
+            job.next_run_at = chrono::Duration::from_std(delay)
+                .ok()
+                .map(|delay| Utc::now() + delay);
+        }
+    }
+}
+
+// A run in progress, see Scheduler::guard
+struct RunGuard<'a> {
+    scheduler: &'a Scheduler,
+    name: &'a str,
+    finished: bool,
+}
+
+impl RunGuard<'_> {
+    fn finish(mut self, result: &Result<usize, String>) {
+        self.finished = true;
+        self.scheduler.finish(self.name, result);
+    }
+}
+
+impl Drop for RunGuard<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let interrupted = Err("The run was interrupted".to_string());
+            self.scheduler.finish(self.name, &interrupted);
+        }
+    }
+}
+
+// Helper function to run one job now. Returns how many items it scraped, or
+// an error when it failed or its previous run is still going
+pub async fn run_job(state: &AppState, name: &str) -> Result<usize, String> {
+    let scheduler = &state.scheduler;
+    let job = scheduler
+        .job(name)
+        .ok_or(format!("No job named '{}'", name))?;
+    if !scheduler.begin(name) {
+        state.metrics.increment(
+            "corriere_scheduler_runs_total",
+            &[("job", name), ("result", "skipped")],
+        );
+        return Err(format!("Job '{}' is still running", name));
+    }
+    let run = scheduler.guard(name);
+
+    let result = match &job.url {
+        #[cfg(feature = "tts")]
+        None if job.name == BRIEFING_JOB => crate::briefing::generate(state).await,
+        None if job.name == BACKFILL_JOB => backfill(state).await,
+        None => crate::coalesced_refresh(state)
+            .await
+            .map(|response| response.news.len()),
+        Some(url) => scrape_section(state, &job.name, url).await,
+    };
+
+    run.finish(&result);
+    heartbeat::report(state, name, &result).await;
+    let outcome = if result.is_ok() { "ok" } else { "failed" };
+    state.metrics.increment(
+        "corriere_scheduler_runs_total",
+        &[("job", name), ("result", outcome)],
+    );
+    result
+}
+
+// Helper function for the backfill job: aggregates the daily statistics of
+// archived days that have none and, with semantic search, embeds archived
+// articles the index is missing. Returns how many days and articles it added
+async fn backfill(state: &AppState) -> Result<usize, String> {
+    let days = crate::stats::aggregate_missing(state).await?;
+    #[cfg(feature = "semantic")]
+    if let Some(index) = &state.semantic {
+        return Ok(days + crate::semantic::backfill(state, index).await?);
+    }
+    Ok(days)
+}
+
+// Helper function to scrape a section page, with the homepage selectors or
+// as its plugin says, and keep the result for /api/sections/:name
+async fn scrape_section(state: &AppState, name: &str, url: &str) -> Result<usize, String> {
+    let news = match state.config.plugin(name) {
+        Some(plugin) => {
+            let started = Instant::now();
+            let scraped = plugins::scrape(state, plugin).await;
+            record_scrape(state, name, url, started, &scraped).await;
+            scraped?
+        }
+        None => scrape_page(state, name, url, SelectorConfig::default()).await?,
+    };
+    let count = news.len();
+    state.scheduler.sections.write().unwrap().insert(
+        name.to_string(),
+        NewsResponse {
+            scraped_at: Utc::now(),
+            news,
+            error: None,
+            stale: false,
+            provenance: None,
+            demo: false,
+            snapshot: false,
+        },
+    );
+    Ok(count)
+}
+
+// Helper function for /api/sections/:name: the latest scrape of a section
+// job, scraped on the spot when this replica has none from the last two
+// intervals, e.g. because another replica holds the job's lease. A failed
+// scrape falls back to the older copy. None when there's no such section job
+pub async fn current_section(state: &AppState, name: &str) -> Option<Result<NewsResponse, String>> {
+    let job = state.scheduler.job(name)?;
+    let url = job.url.as_ref()?;
+    let cached = state.scheduler.section(name);
+    if let Some(section) = &cached {
+        let age = (Utc::now() - section.scraped_at)
+            .to_std()
+            .unwrap_or_default();
+        if age < lease::lease_ttl(job.every) {
+            return cached.map(Ok);
+        }
+    }
+    let scraped = scrape_section(state, name, url).await;
+    Some(match (scraped, cached) {
+        (Ok(_), _) => state
+            .scheduler
+            .section(name)
+            .ok_or(format!("No scrape of section '{}' yet", name)),
+        (Err(_), Some(cached)) => Ok(NewsResponse {
+            stale: true,
+            ..cached
+        }),
+        (Err(error_message), None) => Err(error_message),
+    })
+}
+
+// Helper function to add a scrape of `source` to the audit log
+async fn record_scrape(
+    state: &AppState,
+    source: &str,
+    url: &str,
+    started: Instant,
+    scraped: &Result<Vec<NewsItem>, String>,
+) {
+    let items = scraped.as_ref().map(Vec::len).map_err(Clone::clone);
+    audit::record(state, AuditEvent::scrape(source, url, started, &items)).await;
+}
+
+// Helper function to fetch a page and extract its items with the selector
+// sets configured for `source`, or `default` when there are none. Empty
+// results get replacement selectors suggested on the admin API. Every
+// scrape goes to the audit log, whichever feature asked for it
+pub(crate) async fn scrape_page(
+    state: &AppState,
+    source: &str,
+    url: &str,
+    default: SelectorConfig,
+) -> Result<Vec<NewsItem>, String> {
+    let started = Instant::now();
+    let scraped = extract_page(state, source, url, default).await;
+    record_scrape(state, source, url, started, &scraped).await;
+    scraped
+}
+
+async fn extract_page(
+    state: &AppState,
+    source: &str,
+    url: &str,
+    default: SelectorConfig,
+) -> Result<Vec<NewsItem>, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("Invalid URL '{}': {}", url, e))?;
+    let html = fetch_queue::fetch_html(state, &state.scrape_client, parsed.as_str()).await?;
+
+    let base_url = parsed.to_string();
+    let mode = state.config.parse_mode;
+    let min_items = state.config.selector_min_items;
+    let sets = state.config.selector_sets.for_source_or(source, default);
+    let (news, set, suggestions) = extract::run_blocking(move || {
+        let (news, set) = extract::extract_with_fallback(
+            &html,
+            &sets,
+            &base_url,
+            extract::HOMEPAGE_LIMIT,
+            mode,
+            min_items,
+        )?;
+        let suggestions = news.is_empty().then(|| repair::suggest(&html, &base_url));
+        Ok::<_, String>((news, set, suggestions))
+    })
+    .await??;
+    state.metrics.increment(
+        "corriere_selector_sets_used_total",
+        &[("source", source), ("set", &set)],
+    );
+    match suggestions {
+        Some(suggestions) => repair::report(state, source, url, suggestions),
+        None => state.repairs.clear(source),
+    }
+    Ok(validate::apply(state, source, url, news))
+}
+
+// Starts a task per configured job that runs it on its interval, for as long
+// as the server runs. A job is only rescheduled once its run has finished
+pub fn spawn(state: AppState) {
+    let jobs = state.scheduler.jobs.read().unwrap().clone();
+    for (job, generation) in jobs {
+        spawn_job(state.clone(), job, generation);
+    }
+}
+
+// Starts the task running one job, until a reload changes or removes it.
+// Each run takes the configuration current at the time
+pub fn spawn_job(state: AppState, job: Job, generation: u64) {
+    tokio::spawn(async move {
+        let mut delay = startup_delay(job.every, state.current().config.scheduler_jitter_pct);
+        loop {
+            state.scheduler.schedule(&job.name, delay);
+            tokio::time::sleep(delay).await;
+            if !state.scheduler.is_current(&job.name, generation) {
+                return;
+            }
+            let state = state.current();
+            delay = jittered(job.every, state.config.scheduler_jitter_pct);
+
+            let lease_name = format!("job-{}", job.name);
+            if !lease::should_run(&state, &lease_name, lease::lease_ttl(job.every)).await {
+                continue;
+            }
+            let result = audit::triggered("scheduler", run_job(&state, &job.name)).await;
+            if let Err(error_message) = result {
+                eprintln!("Scheduled job {} failed: {}", job.name, error_message);
+            }
+        }
+    });
+}
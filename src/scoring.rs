@@ -0,0 +1,113 @@
+use chrono::{DateTime, Utc};
+use std::cmp::Reverse;
+
+use crate::{typography, v2, NewsItem};
+
+// Orders the news endpoints can return items in, through ?sort=
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortOrder {
+    // As on the page, the default
+    Page,
+    // Most prominent first, see ranking::Placement::score
+    Prominence,
+    // Newest first by the day in the link; undated items go last
+    PublishedAt,
+    // Alphabetical, ignoring case and editorial labels
+    Title,
+    // Highest first by the SCORE_* weights
+    Score,
+}
+
+// Weights of the ?sort=score ranking, which adds up the prominence score,
+// a recency score and a bonus or malus per category
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScoreWeights {
+    pub prominence: f64,
+    pub recency: f64,
+    // Added once for each of the item's categories, e.g. ("politica", 10.0)
+    pub categories: Vec<(String, f64)>,
+}
+
+impl Default for ScoreWeights {
+    fn default() -> ScoreWeights {
+        ScoreWeights {
+            prominence: 1.0,
+            recency: 0.0,
+            categories: vec![],
+        }
+    }
+}
+
+pub fn parse_sort(value: &str) -> Result<SortOrder, String> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "" | "page" => Ok(SortOrder::Page),
+        "prominence" => Ok(SortOrder::Prominence),
+        "published_at" => Ok(SortOrder::PublishedAt),
+        "title" => Ok(SortOrder::Title),
+        "score" => Ok(SortOrder::Score),
+        other => Err(format!(
+            "Unsupported sort '{}', expected page, prominence, published_at, title or score",
+            other
+        )),
+    }
+}
+
+// Helper function to score how recent an item is, from 100 when published
+// on the day of the scrape, halving with each day before it. Undated items
+// score 0
+pub fn recency(item: &NewsItem, scraped_at: DateTime<Utc>) -> f64 {
+    let Some(published_on) = v2::link_metadata(&item.link).1 else {
+        return 0.0;
+    };
+    let days = (scraped_at.date_naive() - published_on).num_days().max(0);
+    100.0 * 0.5f64.powi(days.min(i32::MAX as i64) as i32)
+}
+
+// Helper function to compute an item's ?sort=score value
+pub fn score(item: &NewsItem, scraped_at: DateTime<Utc>, weights: &ScoreWeights) -> f64 {
+    let mut score = weights.prominence * item.placement.score();
+    if weights.recency != 0.0 {
+        score += weights.recency * recency(item, scraped_at);
+    }
+    if !weights.categories.is_empty() {
+        let categories = v2::link_metadata(&item.link).0;
+        for (category, weight) in &weights.categories {
+            if categories.contains(category) {
+                score += weight;
+            }
+        }
+    }
+    score
+}
+
+// Helper function to put the items of a scrape taken at `scraped_at` in the
+// given order. Sorts are stable, so ties keep their page order
+pub fn sort(
+    news: &mut [NewsItem],
+    order: SortOrder,
+    scraped_at: DateTime<Utc>,
+    weights: &ScoreWeights,
+) {
+    match order {
+        SortOrder::Page => {}
+        SortOrder::Prominence => {
+            news.sort_by(|a, b| b.placement.score().total_cmp(&a.placement.score()))
+        }
+        SortOrder::PublishedAt => {
+            news.sort_by_cached_key(|item| Reverse(v2::link_metadata(&item.link).1))
+        }
+        SortOrder::Title => {
+            news.sort_by_cached_key(|item| typography::normalize_title(&item.title).to_lowercase())
+        }
+        SortOrder::Score => {
+            let mut scored: Vec<(f64, NewsItem)> = news
+                .iter()
+                .map(|item| (score(item, scraped_at, weights), item.clone()))
+                .collect();
+            scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+            for (slot, (_, item)) in news.iter_mut().zip(scored) {
+                *slot = item;
+            }
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::extract::{self, SelectorConfig, Selectors};
+use crate::fetch_queue;
+use crate::url_safety::{self, UrlPolicy};
+use crate::validate;
+use crate::{create_error_response, AppState, NewsResponse};
+
+// Upper bound on items returned by a single ad-hoc scrape
+const MAX_SCRAPE_LIMIT: usize = 100;
+
+#[derive(Deserialize)]
+pub struct ScrapeRequest {
+    url: String,
+    #[serde(default)]
+    selectors: SelectorConfig,
+    limit: Option<usize>,
+}
+
+pub async fn scrape_handler(
+    State(state): State<AppState>,
+    Json(request): Json<ScrapeRequest>,
+) -> (StatusCode, Json<NewsResponse>) {
+    let url = match url_safety::check(&UrlPolicy::from_config(&state.config), &request.url) {
+        Ok(url) => url,
+        Err(error_message) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                create_error_response(error_message),
+            )
+        }
+    };
+
+    let selectors = match Selectors::parse(&request.selectors) {
+        Ok(selectors) => selectors,
+        Err(error_message) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                create_error_response(error_message),
+            )
+        }
+    };
+
+    let html = match fetch_queue::fetch_html(&state, &state.scrape_client, url.as_str()).await {
+        Ok(html) => html,
+        Err(error_message) => {
+            return (
+                StatusCode::BAD_GATEWAY,
+                create_error_response(error_message),
+            )
+        }
+    };
+
+    // Relative links resolve against the page that was scraped
+    let base_url = url.to_string();
+    let limit = request
+        .limit
+        .unwrap_or(extract::HOMEPAGE_LIMIT)
+        .min(MAX_SCRAPE_LIMIT);
+
+    let mode = state.config.parse_mode;
+    let extracted = extract::run_blocking(move || {
+        extract::extract_news(&html, &selectors, &base_url, limit, mode)
+    })
+    .await;
+    let news = match extracted {
+        Ok(news) => validate::apply(&state, "scrape", url.as_str(), news),
+        Err(error_message) => {
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                create_error_response(error_message),
+            )
+        }
+    };
+
+    (
+        StatusCode::OK,
+        Json(NewsResponse {
+            scraped_at: Utc::now(),
+            news,
+            error: None,
+            stale: false,
+            provenance: None,
+            demo: false,
+            snapshot: false,
+        }),
+    )
+}
@@ -0,0 +1,739 @@
+use async_trait::async_trait;
+use axum::extract::{Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+
+use crate::archive::{ArchivedArticle, SearchQuery};
+use crate::config::Config;
+use crate::takedown;
+use crate::{AppState, NewsItem};
+
+const DEFAULT_LIMIT: usize = 20;
+const MAX_LIMIT: usize = 100;
+// Query vectors kept, so repeated searches don't call the embedding backend
+const QUERY_CACHE_SIZE: usize = 1000;
+const QUERY_WINDOW: Duration = Duration::from_secs(60);
+
+// Turns texts into vectors, one per text and in the same order
+#[async_trait]
+pub trait Embedder: Send + Sync {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String>;
+}
+
+// Runs EMBEDDING_COMMAND through the shell with the texts on stdin as a JSON
+// array of strings, reading a JSON array of vectors from stdout. Meant for a
+// local model, such as a sentence-transformer exported to ONNX
+pub struct CommandEmbedder {
+    pub command: String,
+}
+
+#[async_trait]
+impl Embedder for CommandEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let mut child = tokio::process::Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("Failed to run EMBEDDING_COMMAND: {}", e))?;
+
+        let mut stdin = child.stdin.take().ok_or("EMBEDDING_COMMAND has no stdin")?;
+        let input =
+            serde_json::to_vec(texts).map_err(|e| format!("Failed to serialize texts: {}", e))?;
+        let writer = tokio::spawn(async move {
+            // The command may exit without reading everything; its status says why
+            let _ = stdin.write_all(&input).await;
+        });
+        let output = child
+            .wait_with_output()
+            .await
+            .map_err(|e| format!("Failed to run EMBEDDING_COMMAND: {}", e))?;
+        let _ = writer.await;
+
+        if !output.status.success() {
+            return Err(format!(
+                "EMBEDDING_COMMAND failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+        serde_json::from_slice(&output.stdout)
+            .map_err(|e| format!("Invalid EMBEDDING_COMMAND output: {}", e))
+    }
+}
+
+// Posts the texts to an OpenAI-style embeddings endpoint
+pub struct HttpEmbedder {
+    pub client: reqwest::Client,
+    pub url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[async_trait]
+impl Embedder for HttpEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        let mut request = self.client.post(&self.url).json(&json!({
+            "model": self.model,
+            "input": texts,
+        }));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach EMBEDDING_API_URL: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Embedding API returned HTTP {}", response.status()));
+        }
+        let mut response: EmbeddingsResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid embedding response: {}", e))?;
+        response.data.sort_by_key(|data| data.index);
+        Ok(response
+            .data
+            .into_iter()
+            .map(|data| data.embedding)
+            .collect())
+    }
+}
+
+// Helper function to pick the embedding backend, or None when neither
+// EMBEDDING_COMMAND nor EMBEDDING_API_URL is set
+pub fn embedder(config: &Config) -> Result<Option<Box<dyn Embedder>>, String> {
+    match (&config.embedding_command, &config.embedding_api_url) {
+        (Some(_), Some(_)) => {
+            Err("Set only one of EMBEDDING_COMMAND and EMBEDDING_API_URL".to_string())
+        }
+        (Some(command), None) => Ok(Some(Box::new(CommandEmbedder {
+            command: command.clone(),
+        }))),
+        (None, Some(url)) => Ok(Some(Box::new(HttpEmbedder {
+            client: reqwest::Client::new(),
+            url: url.clone(),
+            api_key: config.embedding_api_key.clone(),
+            model: config.embedding_model.clone(),
+        }))),
+        (None, None) => Ok(None),
+    }
+}
+
+// An article as indexed: what was embedded and the unit-length vector
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Entry {
+    pub link: String,
+    pub title: String,
+    pub description: String,
+    pub image_url: Option<String>,
+    // Vectors of different models can't be compared
+    pub model: String,
+    pub embedded_at: DateTime<Utc>,
+    pub vector: Vec<f32>,
+}
+
+impl Entry {
+    fn text(&self) -> String {
+        embedding_text(&self.title, &self.description)
+    }
+}
+
+// An article to index, from the homepage or the archive
+pub struct Document {
+    pub link: String,
+    pub title: String,
+    pub description: String,
+    pub image_url: Option<String>,
+}
+
+impl From<&NewsItem> for Document {
+    fn from(item: &NewsItem) -> Self {
+        Document {
+            link: item.link.clone(),
+            title: item.title.clone(),
+            description: item.description.clone(),
+            image_url: item.image_url.clone(),
+        }
+    }
+}
+
+impl From<ArchivedArticle> for Document {
+    fn from(article: ArchivedArticle) -> Self {
+        Document {
+            link: article.link,
+            title: article.title,
+            description: article.description,
+            image_url: article.image_url,
+        }
+    }
+}
+
+fn embedding_text(title: &str, description: &str) -> String {
+    if description.is_empty() {
+        return title.to_string();
+    }
+    format!("{}. {}", title.trim_end_matches('.'), description)
+}
+
+// Helper function to scale a vector to unit length, so cosine similarity is
+// a dot product. None for the zero vector
+fn normalize(mut vector: Vec<f32>) -> Option<Vec<f32>> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 || !norm.is_finite() {
+        return None;
+    }
+    for x in &mut vector {
+        *x /= norm;
+    }
+    Some(vector)
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SemanticMatch {
+    pub link: String,
+    pub title: String,
+    pub description: String,
+    pub image_url: Option<String>,
+    // Cosine similarity with the query, 1 being the same meaning
+    pub score: f32,
+}
+
+// Where the vectors are persisted between restarts
+#[async_trait]
+pub trait VectorStore: Send + Sync {
+    // The entries of `model`, the latest for each link
+    async fn load(&self, model: &str) -> Result<Vec<Entry>, String>;
+
+    // Adds the entries, replacing those of the same links
+    async fn store(&self, entries: &[Entry]) -> Result<(), String>;
+
+    async fn remove(&self, link: &str) -> Result<(), String>;
+}
+
+// Vectors appended to a JSON lines file under DATA_DIR, the latest line for
+// a link winning
+pub struct FileVectors {
+    path: PathBuf,
+}
+
+impl FileVectors {
+    pub fn new(path: PathBuf) -> FileVectors {
+        FileVectors { path }
+    }
+
+    // The entries in the file, and how many lines it has
+    fn read(&self) -> Result<(Vec<Entry>, usize), String> {
+        match std::fs::read_to_string(&self.path) {
+            // A line cut short by a crash is skipped
+            Ok(content) => Ok((
+                content
+                    .lines()
+                    .filter_map(|line| serde_json::from_str::<Entry>(line).ok())
+                    .collect(),
+                content.lines().count(),
+            )),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok((Vec::new(), 0)),
+            Err(e) => Err(format!("Failed to read {}: {}", self.path.display(), e)),
+        }
+    }
+
+    // Helper function to rewrite the file with just `entries`
+    fn rewrite(&self, entries: &[Entry]) -> Result<(), String> {
+        let partial = self.path.with_extension("partial");
+        write_entries(&partial, entries, false)?;
+        std::fs::rename(&partial, &self.path)
+            .map_err(|e| format!("Failed to write {}: {}", self.path.display(), e))
+    }
+}
+
+#[async_trait]
+impl VectorStore for FileVectors {
+    // Lines from another model are dropped, and the file is compacted when
+    // it has lines that no longer count
+    async fn load(&self, model: &str) -> Result<Vec<Entry>, String> {
+        let (stored, lines) = self.read()?;
+        let mut entries: HashMap<String, Entry> = HashMap::new();
+        for entry in stored.into_iter().filter(|entry| entry.model == model) {
+            entries.insert(entry.link.clone(), entry);
+        }
+        let entries: Vec<Entry> = entries.into_values().collect();
+        if lines > entries.len() {
+            self.rewrite(&entries)?;
+        }
+        Ok(entries)
+    }
+
+    async fn store(&self, entries: &[Entry]) -> Result<(), String> {
+        write_entries(&self.path, entries, true)
+    }
+
+    async fn remove(&self, link: &str) -> Result<(), String> {
+        let (mut entries, _) = self.read()?;
+        entries.retain(|entry| entry.link != link);
+        self.rewrite(&entries)
+    }
+}
+
+// Query vectors by query, the oldest dropped first
+#[derive(Default)]
+struct QueryCache {
+    vectors: HashMap<String, Vec<f32>>,
+    order: VecDeque<String>,
+}
+
+// Vectors of every indexed article, kept in memory for ranking and persisted
+// in a VectorStore: PostgreSQL when the archive is there, otherwise
+// DATA_DIR/embeddings.jsonl
+pub struct SemanticIndex {
+    embedder: Box<dyn Embedder>,
+    store: Box<dyn VectorStore>,
+    model: String,
+    batch_size: usize,
+    entries: RwLock<HashMap<String, Entry>>,
+    queries: Mutex<QueryCache>,
+    // Queries embedded in the current minute, at most
+    // SEMANTIC_QUERIES_PER_MINUTE; 0 for no limit
+    queries_per_minute: u32,
+    window: Mutex<(Instant, u32)>,
+}
+
+impl SemanticIndex {
+    // An empty index, until `load` reads what the store holds
+    pub fn new(
+        store: Box<dyn VectorStore>,
+        embedder: Box<dyn Embedder>,
+        model: &str,
+        batch_size: usize,
+        queries_per_minute: u32,
+    ) -> SemanticIndex {
+        SemanticIndex {
+            embedder,
+            store,
+            model: model.to_string(),
+            batch_size: batch_size.max(1),
+            entries: RwLock::new(HashMap::new()),
+            queries: Mutex::new(QueryCache::default()),
+            queries_per_minute,
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    // Helper function to read the vectors of the model from the store.
+    // Vectors of other models are left out
+    pub async fn load(&self) -> Result<(), String> {
+        let entries = self.store.load(&self.model).await?;
+        *self.entries.write().unwrap() = entries
+            .into_iter()
+            .map(|entry| (entry.link.clone(), entry))
+            .collect();
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Helper function to embed the documents not indexed yet, or whose title
+    // or summary changed since. Returns how many were embedded
+    pub async fn index(&self, documents: Vec<Document>) -> Result<usize, String> {
+        let pending: Vec<Document> = {
+            let entries = self.entries.read().unwrap();
+            let mut seen = HashSet::new();
+            documents
+                .into_iter()
+                .filter(|document| {
+                    let text = embedding_text(&document.title, &document.description);
+                    entries
+                        .get(&document.link)
+                        .is_none_or(|entry| entry.text() != text)
+                        && seen.insert(document.link.clone())
+                })
+                .collect()
+        };
+
+        let mut indexed = 0;
+        for batch in pending.chunks(self.batch_size) {
+            let texts: Vec<String> = batch
+                .iter()
+                .map(|document| embedding_text(&document.title, &document.description))
+                .collect();
+            let vectors = self.embedder.embed(&texts).await?;
+            if vectors.len() != batch.len() {
+                return Err(format!(
+                    "Asked for {} embeddings, got {}",
+                    batch.len(),
+                    vectors.len()
+                ));
+            }
+
+            let now = Utc::now();
+            let entries: Vec<Entry> = batch
+                .iter()
+                .zip(vectors)
+                .filter_map(|(document, vector)| {
+                    Some(Entry {
+                        link: document.link.clone(),
+                        title: document.title.clone(),
+                        description: document.description.clone(),
+                        image_url: document.image_url.clone(),
+                        model: self.model.clone(),
+                        embedded_at: now,
+                        vector: normalize(vector)?,
+                    })
+                })
+                .collect();
+            self.store.store(&entries).await?;
+            indexed += entries.len();
+            let mut stored = self.entries.write().unwrap();
+            for entry in entries {
+                stored.insert(entry.link.clone(), entry);
+            }
+        }
+        Ok(indexed)
+    }
+
+    // Helper function to rank the indexed articles by cosine similarity with
+    // `query`, best first. Queries not seen lately count towards
+    // SEMANTIC_QUERIES_PER_MINUTE, past which they fail with 429
+    pub async fn search(
+        &self,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SemanticMatch>, (StatusCode, String)> {
+        let vector = match self.cached_query(query) {
+            Some(vector) => vector,
+            None => {
+                if !self.take_query_slot() {
+                    return Err((
+                        StatusCode::TOO_MANY_REQUESTS,
+                        "Too many new semantic searches, try again in a minute".to_string(),
+                    ));
+                }
+                let vector = self
+                    .embedder
+                    .embed(&[query.to_string()])
+                    .await
+                    .map_err(|e| (StatusCode::BAD_GATEWAY, e))?
+                    .pop()
+                    .and_then(normalize)
+                    .ok_or((
+                        StatusCode::BAD_GATEWAY,
+                        "The embedding backend returned no vector for the query".to_string(),
+                    ))?;
+                self.cache_query(query, &vector);
+                vector
+            }
+        };
+
+        let entries = self.entries.read().unwrap();
+        let mut matches: Vec<SemanticMatch> = entries
+            .values()
+            .filter(|entry| entry.vector.len() == vector.len())
+            .map(|entry| SemanticMatch {
+                link: entry.link.clone(),
+                title: entry.title.clone(),
+                description: entry.description.clone(),
+                image_url: entry.image_url.clone(),
+                score: entry.vector.iter().zip(&vector).map(|(a, b)| a * b).sum(),
+            })
+            .collect();
+        matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+        matches.truncate(limit);
+        Ok(matches)
+    }
+
+    fn cached_query(&self, query: &str) -> Option<Vec<f32>> {
+        self.queries.lock().unwrap().vectors.get(query).cloned()
+    }
+
+    fn cache_query(&self, query: &str, vector: &[f32]) {
+        let mut cache = self.queries.lock().unwrap();
+        if cache
+            .vectors
+            .insert(query.to_string(), vector.to_vec())
+            .is_none()
+        {
+            cache.order.push_back(query.to_string());
+        }
+        while cache.order.len() > QUERY_CACHE_SIZE {
+            if let Some(oldest) = cache.order.pop_front() {
+                cache.vectors.remove(&oldest);
+            }
+        }
+    }
+
+    // Helper function to count a query embedding towards the current
+    // minute's allowance. Returns false when it is used up
+    fn take_query_slot(&self) -> bool {
+        if self.queries_per_minute == 0 {
+            return true;
+        }
+        let mut window = self.window.lock().unwrap();
+        if window.0.elapsed() >= QUERY_WINDOW {
+            *window = (Instant::now(), 0);
+        }
+        if window.1 >= self.queries_per_minute {
+            return false;
+        }
+        window.1 += 1;
+        true
+    }
+
+    // Helper function to drop an article from the index and the store, after
+    // a takedown. Returns whether it was indexed
+    pub async fn remove(&self, link: &str) -> Result<bool, String> {
+        if self.entries.write().unwrap().remove(link).is_none() {
+            return Ok(false);
+        }
+        self.store.remove(link).await?;
+        Ok(true)
+    }
+}
+
+// Helper function to write entries as JSON lines, after what the file holds
+// with `append` and replacing it otherwise
+fn write_entries(path: &Path, entries: &[Entry], append: bool) -> Result<(), String> {
+    let mut lines = String::new();
+    for entry in entries {
+        lines.push_str(
+            &serde_json::to_string(entry)
+                .map_err(|e| format!("Failed to serialize embedding: {}", e))?,
+        );
+        lines.push('\n');
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    file.write_all(lines.as_bytes())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[derive(Deserialize)]
+pub struct SemanticParams {
+    q: Option<String>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct SemanticResponse {
+    pub query: String,
+    pub results: Vec<SemanticMatch>,
+    pub error: Option<String>,
+}
+
+fn semantic_error(
+    status: StatusCode,
+    query: String,
+    error_message: String,
+) -> (StatusCode, Json<SemanticResponse>) {
+    (
+        status,
+        Json(SemanticResponse {
+            query,
+            results: vec![],
+            error: Some(error_message),
+        }),
+    )
+}
+
+// Indexed articles closest in meaning to ?q=, so paraphrased headlines are
+// found too
+pub async fn search_handler(
+    State(state): State<AppState>,
+    Query(params): Query<SemanticParams>,
+) -> Response {
+    let query = params.q.unwrap_or_default().trim().to_string();
+    let Some(index) = &state.semantic else {
+        return semantic_error(
+            StatusCode::NOT_FOUND,
+            query,
+            "Semantic search is not enabled".to_string(),
+        )
+        .into_response();
+    };
+    if query.is_empty() {
+        return semantic_error(
+            StatusCode::BAD_REQUEST,
+            query,
+            "Missing query parameter q".to_string(),
+        )
+        .into_response();
+    }
+
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    match index.search(&query, limit).await {
+        Ok(results) => {
+            state
+                .metrics
+                .increment("corriere_semantic_searches_total", &[("result", "ok")]);
+            (
+                StatusCode::OK,
+                Json(SemanticResponse {
+                    query,
+                    results,
+                    error: None,
+                }),
+            )
+                .into_response()
+        }
+        Err((StatusCode::TOO_MANY_REQUESTS, error_message)) => {
+            state
+                .metrics
+                .increment("corriere_semantic_searches_total", &[("result", "limited")]);
+            (
+                [(header::RETRY_AFTER, QUERY_WINDOW.as_secs().to_string())],
+                semantic_error(StatusCode::TOO_MANY_REQUESTS, query, error_message),
+            )
+                .into_response()
+        }
+        Err((status, error_message)) => {
+            state
+                .metrics
+                .increment("corriere_semantic_searches_total", &[("result", "failed")]);
+            semantic_error(status, query, error_message).into_response()
+        }
+    }
+}
+
+// Helper function to set up the index when an embedding backend is
+// configured, without reading the store yet: the PostgreSQL table only
+// exists once the archive is migrated. Vectors go where the archive is
+pub async fn open(config: &Config) -> Result<Option<Arc<SemanticIndex>>, String> {
+    let Some(embedder) = embedder(config)? else {
+        return Ok(None);
+    };
+    let store: Box<dyn VectorStore> = match &config.archive_url {
+        #[cfg(feature = "postgres")]
+        Some(url) if url.starts_with("postgres://") || url.starts_with("postgresql://") => {
+            Box::new(crate::postgres::PostgresStorage::connect(url).await?)
+        }
+        _ => Box::new(FileVectors::new(config.data_dir.join("embeddings.jsonl"))),
+    };
+    Ok(Some(Arc::new(SemanticIndex::new(
+        store,
+        embedder,
+        &config.embedding_model,
+        config.embedding_batch_size,
+        config.semantic_queries_per_minute,
+    ))))
+}
+
+// Like open, loading the stored vectors
+pub async fn connect(config: &Config) -> Result<Option<Arc<SemanticIndex>>, String> {
+    let index = open(config).await?;
+    if let Some(index) = &index {
+        index.load().await?;
+    }
+    Ok(index)
+}
+
+// Helper function to index up to EMBEDDING_BACKFILL_LIMIT of the most recent
+// archived articles, so searches cover more than what was seen since start
+pub async fn backfill(state: &AppState, index: &SemanticIndex) -> Result<usize, String> {
+    let Some(archive) = &state.archive else {
+        return Ok(0);
+    };
+    let articles = archive
+        .search(&SearchQuery {
+            limit: state.config.embedding_backfill_limit,
+            ..SearchQuery::default()
+        })
+        .await?;
+    index
+        .index(articles.into_iter().map(Document::from).collect())
+        .await
+}
+
+// Indexes the homepage every EMBEDDING_POLL_SECS, for as long as the server
+// runs. Every replica keeps its own index, so there is no lease
+pub async fn run(state: AppState, index: Arc<SemanticIndex>) {
+    match backfill(&state, &index).await {
+        Ok(0) => {}
+        Ok(indexed) => println!("Embedded {} archived articles", indexed),
+        Err(error_message) => eprintln!("Embedding backfill failed: {}", error_message),
+    }
+
+    let every = Duration::from_secs(state.config.embedding_poll_secs.max(1));
+    let mut interval = tokio::time::interval(every);
+    loop {
+        interval.tick().await;
+        // Settings changed by a reload apply from this run on
+        let state = state.current();
+        let news = match crate::get_news(&state).await {
+            Ok(cached) => cached.response.news,
+            Err(response) => {
+                eprintln!(
+                    "Embeddings: homepage unavailable: {}",
+                    response.error.unwrap_or_default()
+                );
+                continue;
+            }
+        };
+
+        // An article taken down stays out even while it's on the homepage
+        let taken_down = match state.takedowns.hashes() {
+            Ok(hashes) => hashes,
+            Err(error_message) => {
+                eprintln!("Embeddings: {}", error_message);
+                continue;
+            }
+        };
+        let documents = news
+            .iter()
+            .filter(|item| !taken_down.contains(&takedown::link_hash(&item.link)))
+            .map(Document::from)
+            .collect();
+        let result = index.index(documents).await;
+        let outcome = if result.is_ok() { "ok" } else { "failed" };
+        state
+            .metrics
+            .increment("corriere_embedding_runs_total", &[("result", outcome)]);
+        match result {
+            Ok(_) => {
+                state
+                    .metrics
+                    .set_gauge("corriere_embeddings_indexed", &[], index.len() as f64)
+            }
+            Err(error_message) => eprintln!("Embedding failed: {}", error_message),
+        }
+    }
+}
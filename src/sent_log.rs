@@ -0,0 +1,217 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::bloom::{self, SeenFilter};
+use crate::config::Config;
+use crate::json_file;
+use crate::lease::{self, Lease};
+use crate::AppState;
+
+// Links remembered in full; older ones only by their fingerprint
+const SENT_LOG_SIZE: usize = 1000;
+
+// Links already posted somewhere, persisted under DATA_DIR so a restart
+// doesn't post the same headlines again. The most recent entries are kept as
+// a list, written on every save; all of them go into a bloom filter
+// (DATA_DIR/<name>_seen.bloom) checkpointed every SEEN_FILTER_CHECKPOINT_SECS,
+// so links that left the list are still recognized. The filter's answers are
+// confirmed against the fingerprints of the posted links
+// (DATA_DIR/<name>_seen.ids), so a false positive doesn't hold back a new
+// article
+pub struct SentLog {
+    name: String,
+    path: PathBuf,
+    links: VecDeque<String>,
+    seen: SeenFilter,
+    ids_path: PathBuf,
+    // Sorted, for lookups by binary search
+    ids: Vec<u64>,
+    // Fingerprints pushed since the last save, in order
+    new_ids: Vec<u64>,
+    // Fingerprints in the file, up to twice SEEN_FILTER_CAPACITY: the links
+    // the filter can still recognize
+    stored_ids: usize,
+    max_ids: usize,
+    // Links pushed since the filter was last written
+    unchecked: usize,
+    // False until the log has been written once, i.e. on the very first run
+    stored: bool,
+}
+
+impl SentLog {
+    // Opens the log of one notifier, stored as DATA_DIR/<name>_sent.json
+    pub fn load(config: &Config, name: &str) -> Result<SentLog, String> {
+        let path = config.data_dir.join(format!("{}_sent.json", name));
+        let links: Option<VecDeque<String>> = json_file::load(&path)?;
+        let seen = SeenFilter::load(
+            config.data_dir.join(format!("{}_seen.bloom", name)),
+            config.seen_filter_capacity,
+            config.seen_filter_fp_rate,
+            Duration::from_secs(config.seen_filter_checkpoint_secs),
+        )?;
+        let ids_path = config.data_dir.join(format!("{}_seen.ids", name));
+        let mut ids = read_ids(&ids_path)?;
+        let stored_ids = ids.len();
+        ids.sort_unstable();
+        ids.dedup();
+
+        let mut log = SentLog {
+            name: name.to_string(),
+            path,
+            stored: links.is_some(),
+            links: VecDeque::new(),
+            seen,
+            ids_path,
+            ids,
+            new_ids: Vec::new(),
+            stored_ids,
+            max_ids: config.seen_filter_capacity.max(1).saturating_mul(2),
+            unchecked: 0,
+        };
+        // Logs written before the filter or the fingerprints existed seed them
+        for link in links.iter().flatten() {
+            log.seen.insert(link);
+            log.remember(link);
+        }
+        log.links = links.unwrap_or_default();
+        Ok(log)
+    }
+
+    // Reads the log again, since another replica may have added to it
+    pub fn reload(&mut self, config: &Config) -> Result<(), String> {
+        *self = SentLog::load(config, &self.name)?;
+        Ok(())
+    }
+
+    pub fn is_stored(&self) -> bool {
+        self.stored
+    }
+
+    // The filter rules most links out without a search; what it recognizes
+    // is looked up among the fingerprints
+    pub fn contains(&self, link: &str) -> bool {
+        self.seen.contains(link) && self.ids.binary_search(&bloom::fingerprint(link)).is_ok()
+    }
+
+    fn remember(&mut self, link: &str) {
+        let id = bloom::fingerprint(link);
+        if let Err(index) = self.ids.binary_search(&id) {
+            self.ids.insert(index, id);
+            self.new_ids.push(id);
+        }
+    }
+
+    pub fn push(&mut self, link: String) {
+        self.seen.insert(&link);
+        self.remember(&link);
+        self.unchecked += 1;
+        self.links.push_back(link);
+        while self.links.len() > SENT_LOG_SIZE {
+            self.links.pop_front();
+        }
+    }
+
+    pub fn save(&mut self) -> Result<(), String> {
+        json_file::save(&self.path, &self.links)?;
+        self.save_ids()?;
+        // The list covers what a crash would lose since the last checkpoint,
+        // as long as it holds everything pushed since
+        if self
+            .seen
+            .checkpoint(!self.stored || self.unchecked >= SENT_LOG_SIZE)?
+        {
+            self.unchecked = 0;
+        }
+        self.stored = true;
+        Ok(())
+    }
+
+    // Helper function to append the new fingerprints to the file. Past
+    // `max_ids` it is rewritten with the most recent ones, and the older
+    // links are forgotten as the filter forgets them
+    fn save_ids(&mut self) -> Result<(), String> {
+        if self.new_ids.is_empty() {
+            return Ok(());
+        }
+
+        if self.stored_ids + self.new_ids.len() > self.max_ids {
+            let mut kept = read_ids(&self.ids_path)?;
+            kept.append(&mut self.new_ids);
+            let excess = kept.len().saturating_sub(self.max_ids);
+            kept.drain(..excess);
+            let bytes: Vec<u8> = kept.iter().flat_map(|id| id.to_le_bytes()).collect();
+            json_file::write_atomic(&self.ids_path, &bytes)?;
+            self.stored_ids = kept.len();
+            kept.sort_unstable();
+            kept.dedup();
+            self.ids = kept;
+            return Ok(());
+        }
+
+        if let Some(dir) = self.ids_path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+        }
+        let bytes: Vec<u8> = self
+            .new_ids
+            .iter()
+            .flat_map(|id| id.to_le_bytes())
+            .collect();
+        // Cutting the file back to what was read drops what a crash may have
+        // left of a fingerprint
+        let stored_len = self.stored_ids as u64 * 8;
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.ids_path)
+            .and_then(|mut file| {
+                file.set_len(stored_len)?;
+                file.write_all(&bytes)
+            })
+            .map_err(|e| format!("Failed to write {}: {}", self.ids_path.display(), e))?;
+        self.stored_ids += self.new_ids.len();
+        self.new_ids.clear();
+        Ok(())
+    }
+}
+
+// Helper function to read the fingerprints stored at `path`, oldest first,
+// leaving out a partial one at the end
+fn read_ids(path: &Path) -> Result<Vec<u64>, String> {
+    match std::fs::read(path) {
+        Ok(bytes) => Ok(bytes
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap_or_default()))
+            .collect()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+// Helper function for notifier loops: whether this replica posts now. One
+// taking the lease over from another replica reads its logs again first, so
+// what the other one posted isn't posted twice
+pub async fn should_post(
+    state: &AppState,
+    name: &str,
+    ttl: Duration,
+    logs: &mut [&mut SentLog],
+) -> bool {
+    match lease::check(state, name, ttl).await {
+        Lease::NotHeld => false,
+        Lease::Held => true,
+        Lease::Acquired => {
+            for log in logs.iter_mut() {
+                if let Err(error_message) = log.reload(&state.config) {
+                    eprintln!("Failed to reload the {} log: {}", name, error_message);
+                    // Read again on the next tick, before posting anything
+                    state.leases.forget(name);
+                    return false;
+                }
+            }
+            true
+        }
+    }
+}
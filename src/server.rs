@@ -0,0 +1,153 @@
+use crate::alerts::{self, AlertNotifier};
+use crate::archive::{self, Storage};
+use crate::config::Config;
+use crate::digest::{self, Mailer};
+use crate::lease::Leases;
+use crate::listener::{self, Listener};
+#[cfg(feature = "mqtt")]
+use crate::mqtt;
+#[cfg(feature = "read_later")]
+use crate::read_later;
+#[cfg(feature = "s3")]
+use crate::s3;
+#[cfg(feature = "semantic")]
+use crate::semantic;
+#[cfg(feature = "social")]
+use crate::social;
+#[cfg(feature = "telegram")]
+use crate::telegram;
+use crate::webhooks::{self, WebhookNotifier};
+use crate::{delivery, reload, scheduler, stats, watch, AppState};
+use std::sync::Arc;
+
+// What `start` builds from the configuration before it binds the listener
+// and spawns the jobs
+pub struct Services {
+    mailer: Option<Mailer>,
+    leases: Leases,
+    archive: Option<Arc<dyn Storage>>,
+    #[cfg(feature = "semantic")]
+    semantic: Option<Arc<semantic::SemanticIndex>>,
+    sender: delivery::Sender,
+    webhooks: Option<WebhookNotifier>,
+    alerts: Option<AlertNotifier>,
+    #[cfg(feature = "telegram")]
+    telegram: Option<telegram::TelegramNotifier>,
+    #[cfg(feature = "mqtt")]
+    mqtt: Option<mqtt::MqttPublisher>,
+    #[cfg(feature = "s3")]
+    s3: Option<s3::S3Exporter>,
+    #[cfg(feature = "social")]
+    social: Option<social::SocialPublisher>,
+    #[cfg(feature = "read_later")]
+    read_later: Option<read_later::ReadLater>,
+}
+
+// Helper function to check everything `serve` would refuse to start with,
+// building the services on the way. Nothing is bound, created or migrated,
+// so check-config runs it on its own; the archive is connected to, though
+pub async fn validate(config: &Config) -> Result<Services, String> {
+    config.check_data_dir()?;
+    let mailer = Mailer::from_config(config)?;
+    let archive = archive::open(config).await?;
+    #[cfg(feature = "s3")]
+    let s3 = s3::S3Exporter::from_config(config)?;
+    #[cfg(feature = "s3")]
+    if s3.is_some() && archive.is_none() {
+        return Err("S3_BUCKET is set but ARCHIVE_URL is not".to_string());
+    }
+    Ok(Services {
+        leases: Leases::from_config(config)?,
+        archive,
+        #[cfg(feature = "semantic")]
+        semantic: semantic::open(config).await?,
+        sender: delivery::Sender::new(config, mailer.clone())?,
+        webhooks: config
+            .webhooks_enabled
+            .then(|| WebhookNotifier::new(config))
+            .transpose()?,
+        alerts: (!config.alert_api_keys.is_empty())
+            .then(|| AlertNotifier::new(config, mailer.clone()))
+            .transpose()?,
+        #[cfg(feature = "telegram")]
+        telegram: telegram::TelegramNotifier::from_config(config)?,
+        #[cfg(feature = "mqtt")]
+        mqtt: mqtt::MqttPublisher::from_config(config)?,
+        #[cfg(feature = "s3")]
+        s3,
+        #[cfg(feature = "social")]
+        social: social::SocialPublisher::from_config(config)?,
+        #[cfg(feature = "read_later")]
+        read_later: read_later::ReadLater::from_config(config)?,
+        mailer,
+    })
+}
+
+// Helper function to set up the state, bind the listener and start the
+// background jobs of `serve`, leaving the caller to serve the router on the
+// listener. Front ends other than the REST API, such as the gRPC server,
+// start from here to share the cache and jobs
+pub async fn start(config: Config) -> Result<(AppState, Listener), String> {
+    let services = validate(&config).await?;
+    config.create_data_dir()?;
+    if let Some(archive) = &services.archive {
+        archive.prepare().await?;
+    }
+    #[cfg(feature = "semantic")]
+    if let Some(index) = &services.semantic {
+        index.load().await?;
+    }
+    let mut state = AppState::new(config);
+    state.leases = Arc::new(services.leases);
+    state.archive = services.archive;
+    #[cfg(feature = "semantic")]
+    {
+        state.semantic = services.semantic;
+    }
+    let listener = listener::bind(&state.config).await?;
+    tokio::spawn(delivery::run(state.clone(), services.sender));
+    if let Some(mailer) = services.mailer {
+        tokio::spawn(digest::run_scheduler(state.clone(), mailer));
+    }
+    if let Some(notifier) = services.webhooks {
+        tokio::spawn(webhooks::run(state.clone(), notifier));
+    }
+    scheduler::spawn(state.clone());
+    tokio::spawn(reload::run(state.clone()));
+    if state.archive.is_some() {
+        tokio::spawn(stats::run(state.clone()));
+    }
+    if state.config.watch_enabled {
+        tokio::spawn(watch::run(state.clone()));
+    }
+    if let Some(notifier) = services.alerts {
+        tokio::spawn(alerts::run(state.clone(), notifier));
+    }
+    #[cfg(feature = "telegram")]
+    if let Some(notifier) = services.telegram {
+        tokio::spawn(telegram::run(state.clone(), notifier));
+    }
+    #[cfg(feature = "mqtt")]
+    if let Some(publisher) = services.mqtt {
+        tokio::spawn(mqtt::run(state.clone(), publisher));
+    }
+    #[cfg(feature = "s3")]
+    if let Some(exporter) = services.s3 {
+        tokio::spawn(s3::run(state.clone(), exporter));
+    }
+    #[cfg(feature = "social")]
+    if let Some(publisher) = services.social {
+        tokio::spawn(social::run(state.clone(), publisher));
+    }
+    #[cfg(feature = "read_later")]
+    if let Some(read_later) = services.read_later {
+        if !state.config.read_later_sections.is_empty() {
+            tokio::spawn(read_later::run(state.clone(), read_later));
+        }
+    }
+    #[cfg(feature = "semantic")]
+    if let Some(index) = state.semantic.clone() {
+        tokio::spawn(semantic::run(state.clone(), index));
+    }
+    Ok((state, listener))
+}
@@ -0,0 +1,80 @@
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+use tower::ServiceExt;
+
+use crate::config::Config;
+use crate::lease::Leases;
+use crate::{archive, AppState};
+
+// The API without a listener or background jobs, for platforms that invoke a
+// function per request (AWS Lambda, Cloudflare Workers and the like). The
+// homepage cache lives as long as the instance does, so warm invocations
+// are served from it and cold ones scrape
+pub struct Handler {
+    state: AppState,
+    router: Router,
+}
+
+impl Handler {
+    // Sets up the state the way serve() does, minus the background jobs. With
+    // nothing left running between invocations to revalidate in, entries
+    // past CACHE_SOFT_TTL_SECS are refreshed before answering
+    pub async fn new(mut config: Config) -> Result<Handler, String> {
+        config.cache_hard_ttl_secs = config.cache_soft_ttl_secs;
+        let leases = Leases::from_config(&config)?;
+        let mut state = AppState::new(config);
+        state.leases = Arc::new(leases);
+        state.archive = archive::connect(&state.config).await?;
+        #[cfg(feature = "semantic")]
+        {
+            state.semantic = crate::semantic::connect(&state.config).await?;
+        }
+        Ok(Handler::from_state(state))
+    }
+
+    pub fn from_state(state: AppState) -> Handler {
+        Handler {
+            router: crate::router(state.clone()),
+            state,
+        }
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    // Runs one request through the same routes the server has
+    pub async fn handle(&self, request: Request<Body>) -> Response {
+        match self.router.clone().oneshot(request).await {
+            Ok(response) => response,
+            Err(never) => match never {},
+        }
+    }
+}
+
+static HANDLER: OnceCell<Handler> = OnceCell::const_new();
+
+// Entry point for function runtimes: the first invocation reads the
+// configuration from the environment, later ones in the same instance reuse
+// it along with the cache. Adapters such as lambda_http only need to convert
+// their request and response types
+pub async fn handle_request(request: Request<Body>) -> Response {
+    let handler = HANDLER
+        .get_or_try_init(|| async {
+            dotenvy::dotenv().ok();
+            Handler::new(Config::from_env()?).await
+        })
+        .await;
+    match handler {
+        Ok(handler) => handler.handle(request).await,
+        Err(error_message) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Invalid configuration: {}", error_message),
+        )
+            .into_response(),
+    }
+}
@@ -0,0 +1,120 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+const SNAPSHOT_PREFIX: &str = "homepage-";
+const SNAPSHOT_SUFFIX: &str = ".html.gz";
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+// Gzipped copies of fetched homepages, kept for replaying extraction offline
+pub struct SnapshotStore {
+    dir: PathBuf,
+    max_files: usize,
+    max_age: Option<chrono::Duration>,
+}
+
+impl SnapshotStore {
+    pub fn new(dir: PathBuf, max_files: usize, max_age_days: u32) -> SnapshotStore {
+        SnapshotStore {
+            dir,
+            max_files,
+            max_age: (max_age_days > 0).then(|| chrono::Duration::days(max_age_days as i64)),
+        }
+    }
+
+    // Helper function to write a snapshot and apply the retention limits
+    pub fn save(&self, html: &str, fetched_at: DateTime<Utc>) -> Result<PathBuf, String> {
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("Failed to create {}: {}", self.dir.display(), e))?;
+
+        let name = format!(
+            "{}{}{}",
+            SNAPSHOT_PREFIX,
+            fetched_at.format(TIMESTAMP_FORMAT),
+            SNAPSHOT_SUFFIX
+        );
+        let path = self.dir.join(name);
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(html.as_bytes())
+            .map_err(|e| format!("Failed to compress snapshot: {}", e))?;
+        let compressed = encoder
+            .finish()
+            .map_err(|e| format!("Failed to compress snapshot: {}", e))?;
+
+        // Write under a temporary name so a crash never leaves a truncated snapshot
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, compressed)
+            .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+        std::fs::rename(&partial, &path)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+        self.prune(fetched_at)?;
+        Ok(path)
+    }
+
+    fn prune(&self, now: DateTime<Utc>) -> Result<(), String> {
+        let snapshots = list(&self.dir)?;
+        let excess = snapshots.len().saturating_sub(self.max_files);
+
+        for (index, (path, taken_at)) in snapshots.iter().enumerate() {
+            let too_old = self
+                .max_age
+                .is_some_and(|max_age| now.signed_duration_since(*taken_at) > max_age);
+            if index < excess || too_old {
+                std::fs::remove_file(path)
+                    .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Helper function to list the snapshots in a directory, oldest first
+pub fn list(dir: &Path) -> Result<Vec<(PathBuf, DateTime<Utc>)>, String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+
+    let mut snapshots: Vec<(PathBuf, DateTime<Utc>)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let taken_at = snapshot_time(&path)?;
+            Some((path, taken_at))
+        })
+        .collect();
+    snapshots.sort_by_key(|(_, taken_at)| *taken_at);
+    Ok(snapshots)
+}
+
+// The fetch time is encoded in the file name
+pub fn snapshot_time(path: &Path) -> Option<DateTime<Utc>> {
+    let name = path.file_name()?.to_str()?;
+    let timestamp = name
+        .strip_prefix(SNAPSHOT_PREFIX)?
+        .strip_suffix(SNAPSHOT_SUFFIX)?;
+    NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT)
+        .ok()
+        .map(|naive| naive.and_utc())
+}
+
+// Helper function to read a snapshot back; plain .html files are accepted as
+// well so hand-saved pages can be replayed too
+pub fn load(path: &Path) -> Result<String, String> {
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    if path.extension().is_some_and(|extension| extension == "gz") {
+        let mut html = String::new();
+        GzDecoder::new(bytes.as_slice())
+            .read_to_string(&mut html)
+            .map_err(|e| format!("Failed to decompress {}: {}", path.display(), e))?;
+        Ok(html)
+    } else {
+        String::from_utf8(bytes).map_err(|e| format!("{} is not UTF-8: {}", path.display(), e))
+    }
+}
@@ -0,0 +1,539 @@
+use chrono::{SecondsFormat, Utc};
+use reqwest::multipart::{Form, Part};
+use reqwest::{header, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+use crate::extract;
+use crate::lease;
+use crate::sent_log::{self, SentLog};
+use crate::{AppState, NewsItem};
+
+// Post length limits of each platform
+const MASTODON_MAX_CHARS: usize = 500;
+const BLUESKY_MAX_CHARS: usize = 300;
+// Bluesky rejects blobs above 1 MB; larger images are posted without a thumbnail
+const BLUESKY_MAX_IMAGE_BYTES: usize = 1_000_000;
+
+enum Platform {
+    Mastodon {
+        url: String,
+        token: String,
+    },
+    Bluesky {
+        pds_url: String,
+        handle: String,
+        app_password: String,
+    },
+}
+
+impl Platform {
+    fn name(&self) -> &'static str {
+        match self {
+            Platform::Mastodon { .. } => "mastodon",
+            Platform::Bluesky { .. } => "bluesky",
+        }
+    }
+}
+
+// A configured account, with its own posting pace and record of what it
+// already posted
+struct Account {
+    platform: Platform,
+    min_interval: Duration,
+    last_post: Option<Instant>,
+    sent: SentLog,
+}
+
+enum PostError {
+    // Rate limited, unreachable or failing on its side: the item is retried
+    // on the next poll
+    Deferred(String),
+    // Rejected by the platform; retrying wouldn't help
+    Failed(String),
+}
+
+#[derive(Deserialize)]
+struct MastodonMedia {
+    id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BlueskySession {
+    access_jwt: String,
+    did: String,
+}
+
+#[derive(Deserialize)]
+struct BlueskyBlob {
+    blob: Value,
+}
+
+struct Image {
+    bytes: Vec<u8>,
+    mime_type: String,
+}
+
+// Posts new top headlines to Mastodon and/or Bluesky
+pub struct SocialPublisher {
+    client: reqwest::Client,
+    accounts: Vec<Account>,
+    template: String,
+    sections: Vec<String>,
+    top_n: usize,
+}
+
+impl SocialPublisher {
+    // Helper function to build the publisher, or None when no account is configured
+    pub fn from_config(config: &Config) -> Result<Option<SocialPublisher>, String> {
+        let mut accounts = Vec::new();
+
+        match (&config.mastodon_url, &config.mastodon_token) {
+            (Some(url), Some(token)) => accounts.push(Account {
+                platform: Platform::Mastodon {
+                    url: url.clone(),
+                    token: token.clone(),
+                },
+                min_interval: Duration::from_secs(config.mastodon_min_interval_secs),
+                last_post: None,
+                sent: SentLog::load(config, "mastodon")?,
+            }),
+            (None, None) => {}
+            _ => return Err("MASTODON_URL and MASTODON_TOKEN must be set together".to_string()),
+        }
+
+        match (&config.bluesky_handle, &config.bluesky_app_password) {
+            (Some(handle), Some(app_password)) => accounts.push(Account {
+                platform: Platform::Bluesky {
+                    pds_url: config.bluesky_pds_url.clone(),
+                    handle: handle.clone(),
+                    app_password: app_password.clone(),
+                },
+                min_interval: Duration::from_secs(config.bluesky_min_interval_secs),
+                last_post: None,
+                sent: SentLog::load(config, "bluesky")?,
+            }),
+            (None, None) => {}
+            _ => {
+                return Err(
+                    "BLUESKY_HANDLE and BLUESKY_APP_PASSWORD must be set together".to_string(),
+                )
+            }
+        }
+
+        if accounts.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(SocialPublisher {
+            client: reqwest::Client::new(),
+            accounts,
+            template: config.social_template.clone(),
+            sections: config.social_sections.clone(),
+            top_n: config.social_top_n,
+        }))
+    }
+
+    // Whether an item passes the configured filters: among the top slots of
+    // the homepage and, when sections are configured, in one of them
+    pub fn selects(&self, position: usize, item: &NewsItem) -> bool {
+        position < self.top_n
+            && (self.sections.is_empty()
+                || extract::section(&item.link)
+                    .is_some_and(|section| self.sections.contains(&section)))
+    }
+
+    // Helper function to post the selected items each account hasn't posted
+    // yet. As with Telegram, the first run only records the current items
+    pub async fn publish(&mut self, state: &AppState, news: &[NewsItem]) -> Result<usize, String> {
+        let selected: Vec<&NewsItem> = news
+            .iter()
+            .enumerate()
+            .filter(|(position, item)| self.selects(*position, item))
+            .map(|(_, item)| item)
+            .collect();
+
+        let mut posted = 0;
+        for account in &mut self.accounts {
+            if !account.sent.is_stored() {
+                for item in &selected {
+                    account.sent.push(item.link.clone());
+                }
+                account.sent.save()?;
+                continue;
+            }
+
+            let fresh: Vec<&NewsItem> = selected
+                .iter()
+                .filter(|item| !account.sent.contains(&item.link))
+                .copied()
+                .collect();
+            if fresh.is_empty() {
+                continue;
+            }
+
+            // Lower ranked items first, so the top story is the most recent post.
+            // Bluesky needs a session, created once per round
+            let mut session = None;
+            for item in fresh.iter().rev() {
+                if let Some(last_post) = account.last_post {
+                    tokio::time::sleep(account.min_interval.saturating_sub(last_post.elapsed()))
+                        .await;
+                }
+                account.last_post = Some(Instant::now());
+
+                let text = render_template(&self.template, item);
+                let result = post(&self.client, &account.platform, &mut session, &text, item).await;
+                let platform = account.platform.name();
+                match result {
+                    Ok(()) => {
+                        posted += 1;
+                        account.sent.push(item.link.clone());
+                        state.metrics.increment(
+                            "corriere_social_posts_total",
+                            &[("platform", platform), ("result", "sent")],
+                        );
+                    }
+                    Err(PostError::Deferred(error_message)) => {
+                        eprintln!("Posting to {} deferred: {}", platform, error_message);
+                        state.metrics.increment(
+                            "corriere_social_posts_total",
+                            &[("platform", platform), ("result", "deferred")],
+                        );
+                        break;
+                    }
+                    // Not retried, so a post the platform rejects isn't attempted forever
+                    Err(PostError::Failed(error_message)) => {
+                        eprintln!("Posting to {} failed: {}", platform, error_message);
+                        account.sent.push(item.link.clone());
+                        state.metrics.increment(
+                            "corriere_social_posts_total",
+                            &[("platform", platform), ("result", "failed")],
+                        );
+                    }
+                }
+            }
+            account.sent.save()?;
+        }
+        Ok(posted)
+    }
+}
+
+// Helper function to fill in a post template. Placeholders are {title},
+// {description}, {link} and {section}
+pub fn render_template(template: &str, item: &NewsItem) -> String {
+    template
+        .replace("{title}", &item.title)
+        .replace("{description}", &item.description)
+        .replace("{link}", &item.link)
+        .replace(
+            "{section}",
+            &extract::section(&item.link).unwrap_or_default(),
+        )
+        .trim()
+        .to_string()
+}
+
+// Shortens a post to `limit` characters. The link is what readers need, so
+// the text before it is cut rather than the link itself; only a link too long
+// for the post on its own is cut too
+pub fn fit(text: &str, link: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text.to_string();
+    }
+
+    let (before, tail) = match text.find(link) {
+        Some(start) => text.split_at(start),
+        None => (text, ""),
+    };
+    // Whatever separated the text from the link stays as it was
+    let head = before.trim_end();
+    let separator = &before[head.len()..];
+
+    let room = limit.saturating_sub(tail.chars().count() + separator.chars().count() + 1);
+    let mut shortened: String = head.chars().take(room).collect();
+    shortened.truncate(shortened.trim_end().len());
+    shortened.push('…');
+    shortened.push_str(separator);
+    shortened.push_str(tail);
+    if shortened.chars().count() <= limit {
+        return shortened;
+    }
+
+    let mut cut: String = text.chars().take(limit.saturating_sub(1)).collect();
+    if limit > 0 {
+        cut.push('…');
+    }
+    cut
+}
+
+async fn post(
+    client: &reqwest::Client,
+    platform: &Platform,
+    session: &mut Option<BlueskySession>,
+    text: &str,
+    item: &NewsItem,
+) -> Result<(), PostError> {
+    // A missing image is no reason to skip the post
+    let image = match &item.image_url {
+        Some(image_url) => download_image(client, image_url).await.ok(),
+        None => None,
+    };
+
+    match platform {
+        Platform::Mastodon { url, token } => {
+            let text = fit(text, &item.link, MASTODON_MAX_CHARS);
+            post_mastodon(client, url, token, &text, item, image).await
+        }
+        Platform::Bluesky {
+            pds_url,
+            handle,
+            app_password,
+        } => {
+            // Without a session nothing can be posted, so wrong credentials
+            // defer the items instead of dropping them
+            if session.is_none() {
+                let created = bluesky_session(client, pds_url, handle, app_password)
+                    .await
+                    .map_err(|error| match error {
+                        PostError::Deferred(error_message) | PostError::Failed(error_message) => {
+                            PostError::Deferred(error_message)
+                        }
+                    })?;
+                *session = Some(created);
+            }
+            let Some(session) = session.as_ref() else {
+                return Err(PostError::Failed("No Bluesky session".to_string()));
+            };
+            let text = fit(text, &item.link, BLUESKY_MAX_CHARS);
+            post_bluesky(client, pds_url, session, &text, item, image).await
+        }
+    }
+}
+
+async fn download_image(client: &reqwest::Client, url: &str) -> Result<Image, String> {
+    let response = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Failed to fetch image {}: {}", url, e))?;
+    let mime_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| value.starts_with("image/"))
+        .unwrap_or("image/jpeg")
+        .to_string();
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to fetch image {}: {}", url, e))?;
+    Ok(Image {
+        bytes: bytes.to_vec(),
+        mime_type,
+    })
+}
+
+// Helper function to turn an unsuccessful response into a PostError
+async fn check_response(
+    response: reqwest::Response,
+    what: &str,
+) -> Result<reqwest::Response, PostError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let error_message = format!("{} failed with {}: {}", what, status, body);
+    if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        Err(PostError::Deferred(error_message))
+    } else {
+        Err(PostError::Failed(error_message))
+    }
+}
+
+// Requests that never got an answer are worth another try
+fn request_failed(what: &str, e: reqwest::Error) -> PostError {
+    let error_message = format!("{} failed: {}", what, e);
+    if e.is_decode() || e.is_builder() {
+        PostError::Failed(error_message)
+    } else {
+        PostError::Deferred(error_message)
+    }
+}
+
+async fn post_mastodon(
+    client: &reqwest::Client,
+    url: &str,
+    token: &str,
+    text: &str,
+    item: &NewsItem,
+    image: Option<Image>,
+) -> Result<(), PostError> {
+    let mut media_ids = Vec::new();
+    if let Some(image) = image {
+        let part = Part::bytes(image.bytes)
+            .file_name("image")
+            .mime_str(&image.mime_type)
+            .map_err(|e| request_failed("Mastodon media upload", e))?;
+        let form = Form::new()
+            .part("file", part)
+            .text("description", item.title.clone());
+        let response = client
+            .post(format!("{}/api/v1/media", url))
+            .bearer_auth(token)
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|e| request_failed("Mastodon media upload", e))?;
+        let media: MastodonMedia = check_response(response, "Mastodon media upload")
+            .await?
+            .json()
+            .await
+            .map_err(|e| request_failed("Mastodon media upload", e))?;
+        media_ids.push(media.id);
+    }
+
+    // The idempotency key makes Mastodon drop a repeated post of the same
+    // article, e.g. after a crash before the sent log was saved
+    let response = client
+        .post(format!("{}/api/v1/statuses", url))
+        .bearer_auth(token)
+        .header("Idempotency-Key", &item.link)
+        .json(&json!({
+            "status": text,
+            "media_ids": media_ids,
+            "language": "it",
+        }))
+        .send()
+        .await
+        .map_err(|e| request_failed("Mastodon post", e))?;
+    check_response(response, "Mastodon post").await?;
+    Ok(())
+}
+
+async fn bluesky_session(
+    client: &reqwest::Client,
+    pds_url: &str,
+    handle: &str,
+    app_password: &str,
+) -> Result<BlueskySession, PostError> {
+    let response = client
+        .post(format!("{}/xrpc/com.atproto.server.createSession", pds_url))
+        .json(&json!({ "identifier": handle, "password": app_password }))
+        .send()
+        .await
+        .map_err(|e| request_failed("Bluesky login", e))?;
+    check_response(response, "Bluesky login")
+        .await?
+        .json()
+        .await
+        .map_err(|e| request_failed("Bluesky login", e))
+}
+
+async fn post_bluesky(
+    client: &reqwest::Client,
+    pds_url: &str,
+    session: &BlueskySession,
+    text: &str,
+    item: &NewsItem,
+    image: Option<Image>,
+) -> Result<(), PostError> {
+    // The article is attached as a link card, with the image as its thumbnail
+    let mut external = json!({
+        "uri": item.link,
+        "title": item.title,
+        "description": item.description,
+    });
+    if let Some(image) = image.filter(|image| image.bytes.len() <= BLUESKY_MAX_IMAGE_BYTES) {
+        let response = client
+            .post(format!("{}/xrpc/com.atproto.repo.uploadBlob", pds_url))
+            .bearer_auth(&session.access_jwt)
+            .header(header::CONTENT_TYPE, image.mime_type)
+            .body(image.bytes)
+            .send()
+            .await
+            .map_err(|e| request_failed("Bluesky image upload", e))?;
+        let blob: BlueskyBlob = check_response(response, "Bluesky image upload")
+            .await?
+            .json()
+            .await
+            .map_err(|e| request_failed("Bluesky image upload", e))?;
+        external["thumb"] = blob.blob;
+    }
+
+    let mut record = json!({
+        "$type": "app.bsky.feed.post",
+        "text": text,
+        "createdAt": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        "langs": ["it"],
+        "embed": {
+            "$type": "app.bsky.embed.external",
+            "external": external,
+        },
+    });
+    // Links in the text are only clickable when marked with a facet, which
+    // counts UTF-8 bytes
+    if let Some(start) = text.find(&item.link) {
+        record["facets"] = json!([{
+            "index": { "byteStart": start, "byteEnd": start + item.link.len() },
+            "features": [{ "$type": "app.bsky.richtext.facet#link", "uri": item.link }],
+        }]);
+    }
+
+    let response = client
+        .post(format!("{}/xrpc/com.atproto.repo.createRecord", pds_url))
+        .bearer_auth(&session.access_jwt)
+        .json(&json!({
+            "repo": session.did,
+            "collection": "app.bsky.feed.post",
+            "record": record,
+        }))
+        .send()
+        .await
+        .map_err(|e| request_failed("Bluesky post", e))?;
+    check_response(response, "Bluesky post").await?;
+    Ok(())
+}
+
+// Checks the homepage every SOCIAL_POLL_SECS and posts what's new, for as
+// long as the server runs
+pub async fn run(state: AppState, mut publisher: SocialPublisher) {
+    let every = Duration::from_secs(state.config.social_poll_secs.max(1));
+    let mut interval = tokio::time::interval(every);
+
+    loop {
+        interval.tick().await;
+        // Settings changed by a reload apply from this run on
+        let state = state.current();
+        let ttl = lease::lease_ttl(every);
+        let mut logs: Vec<&mut SentLog> = publisher
+            .accounts
+            .iter_mut()
+            .map(|account| &mut account.sent)
+            .collect();
+        if !sent_log::should_post(&state, "social", ttl, &mut logs).await {
+            continue;
+        }
+
+        let news = match crate::get_news(&state).await {
+            // A stale copy has nothing new to announce
+            Ok(cached) if !cached.response.stale => cached.response.news,
+            Ok(_) => continue,
+            Err(response) => {
+                eprintln!(
+                    "Social: homepage unavailable: {}",
+                    response.error.unwrap_or_default()
+                );
+                continue;
+            }
+        };
+
+        if let Err(error_message) = publisher.publish(&state, &news).await {
+            eprintln!("Social publishing failed: {}", error_message);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use askama::Template;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::dates::{DateFormat, Locale};
+use crate::{fields, formats, AppState, NewsItem, NewsResponse};
+
+pub const MANIFEST: &str = "manifest.json";
+
+#[derive(Template)]
+#[template(path = "static/index.html")]
+struct IndexTemplate<'a> {
+    updated: &'a str,
+    news: &'a [NewsItem],
+}
+
+// One file of the build, as listed in the manifest
+#[derive(Serialize, Debug)]
+pub struct BuiltFile {
+    pub path: String,
+    pub content_type: &'static str,
+    pub bytes: usize,
+}
+
+// Written last, so a deploy that finds it knows every other file is complete
+#[derive(Serialize, Debug)]
+pub struct Manifest {
+    pub generated_at: DateTime<Utc>,
+    pub scraped_at: DateTime<Utc>,
+    pub items: usize,
+    pub files: Vec<BuiltFile>,
+}
+
+// A rendered file and its media type
+pub struct StaticFile {
+    pub name: &'static str,
+    pub content_type: &'static str,
+    pub content: Vec<u8>,
+}
+
+// Helper function to render the static files of a scrape: news.json in the
+// /api/news shape, feed.rss and index.html, with dates in DIGEST_TIME_ZONE
+pub fn render(state: &AppState, response: &NewsResponse) -> Result<Vec<StaticFile>, String> {
+    let dates = DateFormat {
+        tz: Some(state.config.digest_time_zone),
+        locale: Some(Locale::Italian),
+    };
+
+    let json = formats::envelope(response, fields::project(&response.news, None), &dates);
+    let json = serde_json::to_vec_pretty(&json)
+        .map_err(|e| format!("Failed to serialize news.json: {}", e))?;
+    let rss = formats::rss_document(response, &dates);
+    let updated = dates.display(response.scraped_at).unwrap_or_default();
+    let html = IndexTemplate {
+        updated: &updated,
+        news: &response.news,
+    }
+    .render()
+    .map_err(|e| format!("Failed to render index.html: {}", e))?;
+
+    Ok(vec![
+        StaticFile {
+            name: "news.json",
+            content_type: "application/json",
+            content: json,
+        },
+        StaticFile {
+            name: "feed.rss",
+            content_type: "application/rss+xml",
+            content: rss.into_bytes(),
+        },
+        StaticFile {
+            name: "index.html",
+            content_type: "text/html",
+            content: html.into_bytes(),
+        },
+    ])
+}
+
+// Helper function to replace a file in one step: the content goes to a
+// hidden file in the same directory, then renamed over the old one, so a
+// web server never serves half a file
+pub fn write_atomic(path: &Path, content: &[u8]) -> Result<(), String> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or(format!("Invalid file name {}", path.display()))?;
+    let partial = path.with_file_name(format!(".{}.partial", name));
+    std::fs::write(&partial, content)
+        .map_err(|e| format!("Failed to write {}: {}", partial.display(), e))?;
+    std::fs::rename(&partial, path)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+// Helper function to write the latest news into a directory for static
+// hosting. Nothing is written when the homepage can't be scraped, so a
+// failing run leaves the previous build in place
+pub async fn build(state: &AppState, out: &Path) -> Result<Manifest, String> {
+    let response = crate::get_news(state)
+        .await
+        .map_err(|response| response.error.unwrap_or_default())?
+        .response;
+    let files = render(state, &response)?;
+
+    std::fs::create_dir_all(out)
+        .map_err(|e| format!("Failed to create {}: {}", out.display(), e))?;
+    let mut built = Vec::new();
+    for file in files {
+        write_atomic(&out.join(file.name), &file.content)?;
+        built.push(BuiltFile {
+            path: file.name.to_string(),
+            content_type: file.content_type,
+            bytes: file.content.len(),
+        });
+    }
+
+    let manifest = Manifest {
+        generated_at: Utc::now(),
+        scraped_at: response.scraped_at,
+        items: response.news.len(),
+        files: built,
+    };
+    let json = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| format!("Failed to serialize {}: {}", MANIFEST, e))?;
+    write_atomic(&out.join(MANIFEST), &json)?;
+    Ok(manifest)
+}
+
+// Helper function to read `build`'s arguments: `--out DIR`, by default
+// STATIC_OUT_DIR
+pub fn out_dir(default: &Path, args: &[String]) -> Result<PathBuf, String> {
+    match args {
+        [] => Ok(default.to_path_buf()),
+        [flag, dir] if flag == "--out" => Ok(PathBuf::from(dir)),
+        [flag] if flag == "--out" => Err("--out needs a directory".to_string()),
+        _ => Err(format!("Unexpected arguments: {}", args.join(" "))),
+    }
+}
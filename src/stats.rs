@@ -0,0 +1,340 @@
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{Duration as ChronoDuration, NaiveDate, Utc};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::archive::ArchivedScrape;
+use crate::config::Config;
+use crate::edition::{day_span, today};
+use crate::{digest, json_file, lease, v2, AppState, NewsItem};
+
+const MAX_RANGE_DAYS: i64 = 366;
+// Scrape outcomes older than this are dropped from the log
+const LOG_RETENTION_DAYS: i64 = 400;
+
+// Homepage scrapes that worked and failed on a day
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct ScrapeCounts {
+    pub ok: usize,
+    pub failed: usize,
+}
+
+// Outcome of every homepage scrape, per day in DIGEST_TIME_ZONE. The archive
+// only holds the scrapes that worked, so failures are counted here
+pub struct ScrapeLog {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl ScrapeLog {
+    pub fn new(path: PathBuf) -> ScrapeLog {
+        ScrapeLog {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn load(&self) -> Result<BTreeMap<NaiveDate, ScrapeCounts>, String> {
+        Ok(json_file::load(&self.path)?.unwrap_or_default())
+    }
+
+    pub fn record(&self, day: NaiveDate, ok: bool) -> Result<(), String> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut days = self.load()?;
+        let counts = days.entry(day).or_default();
+        if ok {
+            counts.ok += 1;
+        } else {
+            counts.failed += 1;
+        }
+        let oldest = day - ChronoDuration::days(LOG_RETENTION_DAYS);
+        days.retain(|day, _| *day >= oldest);
+        json_file::save(&self.path, &days)
+    }
+
+    pub fn get(&self, day: NaiveDate) -> Result<Option<ScrapeCounts>, String> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(self.load()?.get(&day).copied())
+    }
+}
+
+// Helper function to count a homepage scrape towards today's success rate.
+// The log is rewritten off the async runtime
+pub async fn record_scrape(state: &AppState, ok: bool) {
+    let scrape_log = state.scrape_log.clone();
+    let day = today(&state.config);
+    let written = tokio::task::spawn_blocking(move || scrape_log.record(day, ok))
+        .await
+        .unwrap_or_else(|e| Err(format!("Scrape log writer failed: {}", e)));
+    if let Err(error_message) = written {
+        eprintln!("Failed to record scrape outcome: {}", error_message);
+    }
+}
+
+// What the homepage carried on one day
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct DailyStats {
+    pub date: NaiveDate,
+    // Distinct articles listed at some point during the day
+    pub articles: usize,
+    // Articles per category of their link (edition and sections), e.g. "politica"
+    pub categories: BTreeMap<String, usize>,
+    // Articles per site, e.g. "corriere.it" or "milano.corriere.it"
+    pub sources: BTreeMap<String, usize>,
+    pub paywalled: usize,
+    pub free: usize,
+    // In characters, over the day's distinct articles
+    pub average_headline_length: f64,
+    pub scrapes: usize,
+    pub failed_scrapes: usize,
+    // Share of scrapes that worked, from 0 to 1; null without scrapes
+    pub scrape_success_rate: Option<f64>,
+}
+
+// Helper function to tell a subscriber-only article from its link, by the
+// PAYWALL_LINK_PATTERNS it contains
+pub fn is_paywalled(config: &Config, link: &str) -> bool {
+    config
+        .paywall_link_patterns
+        .iter()
+        .any(|pattern| link.contains(pattern.as_str()))
+}
+
+fn source_of(link: &str) -> String {
+    Url::parse(link)
+        .ok()
+        .and_then(|url| url.host_str().map(|host| host.to_ascii_lowercase()))
+        .map(|host| host.strip_prefix("www.").unwrap_or(&host).to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+// Helper function to compute the statistics of a day from its archived
+// scrapes and the logged outcomes. Without logged outcomes, every archived
+// scrape counts as one that worked
+pub fn aggregate(
+    config: &Config,
+    date: NaiveDate,
+    scrapes: &[ArchivedScrape],
+    outcomes: Option<ScrapeCounts>,
+) -> DailyStats {
+    // The latest version of each article: titles change during the day
+    let mut articles: HashMap<&str, &NewsItem> = HashMap::new();
+    for scrape in scrapes {
+        for item in &scrape.news {
+            articles.insert(item.link.as_str(), item);
+        }
+    }
+
+    let mut categories = BTreeMap::new();
+    let mut sources = BTreeMap::new();
+    let mut paywalled = 0;
+    let mut headline_chars = 0;
+    for item in articles.values() {
+        for category in v2::link_metadata(&item.link).0 {
+            *categories.entry(category).or_insert(0) += 1;
+        }
+        *sources.entry(source_of(&item.link)).or_insert(0) += 1;
+        if is_paywalled(config, &item.link) {
+            paywalled += 1;
+        }
+        headline_chars += item.title.chars().count();
+    }
+
+    let outcomes = outcomes.unwrap_or(ScrapeCounts {
+        ok: scrapes.len(),
+        failed: 0,
+    });
+    let total = outcomes.ok + outcomes.failed;
+    DailyStats {
+        date,
+        articles: articles.len(),
+        categories,
+        sources,
+        paywalled,
+        free: articles.len() - paywalled,
+        average_headline_length: if articles.is_empty() {
+            0.0
+        } else {
+            headline_chars as f64 / articles.len() as f64
+        },
+        scrapes: total,
+        failed_scrapes: outcomes.failed,
+        scrape_success_rate: (total > 0).then(|| outcomes.ok as f64 / total as f64),
+    }
+}
+
+// Statistics of past days, computed once by the nightly job
+pub struct StatsStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl StatsStore {
+    pub fn new(path: PathBuf) -> StatsStore {
+        StatsStore {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn load(&self) -> Result<BTreeMap<NaiveDate, DailyStats>, String> {
+        Ok(json_file::load(&self.path)?.unwrap_or_default())
+    }
+
+    // Days from `from` to `to` included, oldest first
+    pub fn range(&self, from: NaiveDate, to: NaiveDate) -> Result<Vec<DailyStats>, String> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(self
+            .load()?
+            .range(from..=to)
+            .map(|(_, day)| day.clone())
+            .collect())
+    }
+
+    pub fn contains(&self, day: NaiveDate) -> Result<bool, String> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(self.load()?.contains_key(&day))
+    }
+
+    pub fn store(&self, stats: DailyStats) -> Result<(), String> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut days = self.load()?;
+        days.insert(stats.date, stats);
+        json_file::save(&self.path, &days)
+    }
+}
+
+// Helper function to aggregate every finished day of the last
+// STATS_BACKFILL_DAYS that isn't cached yet. Returns how many were added
+pub async fn aggregate_missing(state: &AppState) -> Result<usize, String> {
+    let Some(archive) = &state.archive else {
+        return Ok(0);
+    };
+    let yesterday = today(&state.config) - ChronoDuration::days(1);
+    let mut added = 0;
+    for days_ago in (0..state.config.stats_backfill_days).rev() {
+        let day = yesterday - ChronoDuration::days(days_ago as i64);
+        if state.daily_stats.contains(day)? {
+            continue;
+        }
+        let (start, end) = day_span(&state.config, day);
+        let scrapes = archive.scrapes(start, end).await?;
+        let outcomes = state.scrape_log.get(day)?;
+        if scrapes.is_empty() && outcomes.is_none() {
+            continue;
+        }
+        state
+            .daily_stats
+            .store(aggregate(&state.config, day, &scrapes, outcomes))?;
+        added += 1;
+    }
+    Ok(added)
+}
+
+// Aggregates the finished days at start and then every night at STATS_HOUR
+// in DIGEST_TIME_ZONE, for as long as the server runs
+pub async fn run(state: AppState) {
+    loop {
+        // Settings changed by a reload apply from this run on
+        let state = state.current();
+        if lease::should_run(&state, "daily-stats", Duration::from_secs(3600)).await {
+            match aggregate_missing(&state).await {
+                Ok(added) => {
+                    state
+                        .metrics
+                        .increment("corriere_stats_aggregations_total", &[("result", "ok")]);
+                    if added > 0 {
+                        println!("Aggregated statistics for {} days", added);
+                    }
+                }
+                Err(error_message) => {
+                    state
+                        .metrics
+                        .increment("corriere_stats_aggregations_total", &[("result", "failed")]);
+                    eprintln!("Daily statistics failed: {}", error_message);
+                }
+            }
+        }
+
+        let now = Utc::now();
+        let run_at = digest::next_run(now, state.config.stats_hour, state.config.digest_time_zone);
+        tokio::time::sleep((run_at - now).to_std().unwrap_or_default()).await;
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DailyParams {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[derive(Serialize)]
+pub struct DailyResponse {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub days: Vec<DailyStats>,
+    pub error: Option<String>,
+}
+
+fn daily_error(status: StatusCode, error_message: String) -> (StatusCode, Json<DailyResponse>) {
+    (
+        status,
+        Json(DailyResponse {
+            from: None,
+            to: None,
+            days: vec![],
+            error: Some(error_message),
+        }),
+    )
+}
+
+// Statistics per day between ?from= and ?to= (YYYY-MM-DD, both included),
+// by default the last week. Days the nightly job hasn't aggregated, such as
+// today, are left out
+pub async fn daily_handler(
+    State(state): State<AppState>,
+    Query(params): Query<DailyParams>,
+) -> (StatusCode, Json<DailyResponse>) {
+    if state.archive.is_none() {
+        return daily_error(
+            StatusCode::NOT_FOUND,
+            "The archive is not enabled".to_string(),
+        );
+    }
+
+    let to = params
+        .to
+        .unwrap_or_else(|| today(&state.config) - ChronoDuration::days(1));
+    let from = params.from.unwrap_or(to - ChronoDuration::days(6));
+    if from > to {
+        return daily_error(
+            StatusCode::BAD_REQUEST,
+            "from must not be after to".to_string(),
+        );
+    }
+    if (to - from).num_days() >= MAX_RANGE_DAYS {
+        return daily_error(
+            StatusCode::BAD_REQUEST,
+            format!("At most {} days can be requested", MAX_RANGE_DAYS),
+        );
+    }
+
+    match state.daily_stats.range(from, to) {
+        Ok(days) => (
+            StatusCode::OK,
+            Json(DailyResponse {
+                from: Some(from),
+                to: Some(to),
+                days,
+                error: None,
+            }),
+        ),
+        Err(error_message) => daily_error(StatusCode::INTERNAL_SERVER_ERROR, error_message),
+    }
+}
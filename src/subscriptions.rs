@@ -0,0 +1,391 @@
+use axum::extract::{Path, Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use lettre::Address;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use subtle::ConstantTimeEq;
+
+use crate::formats::escape_html;
+use crate::json_file;
+use crate::AppState;
+
+// A digest subscriber. The token is the subscriber's secret: it is returned
+// once on creation, is part of the unsubscribe link and is needed to read,
+// change or delete the subscription
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Subscription {
+    pub id: String,
+    pub email: String,
+    pub token: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// What the API shows of a subscription, without its token
+#[derive(Serialize)]
+pub struct SubscriptionView {
+    pub id: String,
+    pub email: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&Subscription> for SubscriptionView {
+    fn from(subscription: &Subscription) -> Self {
+        SubscriptionView {
+            id: subscription.id.clone(),
+            email: subscription.email.clone(),
+            created_at: subscription.created_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct SubscriptionResponse {
+    pub subscription: Option<SubscriptionView>,
+    // Only set when the subscription was just created
+    pub token: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct SubscriptionRequest {
+    email: String,
+}
+
+#[derive(Deserialize)]
+pub struct TokenParams {
+    pub token: Option<String>,
+}
+
+// Subscribers persisted as a JSON file under DATA_DIR. The file is read and
+// rewritten on every change; the lock keeps concurrent changes from racing
+pub struct SubscriptionStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl SubscriptionStore {
+    pub fn new(path: PathBuf) -> SubscriptionStore {
+        SubscriptionStore {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<Subscription>, String> {
+        let _lock = self.lock.lock().unwrap();
+        self.load()
+    }
+
+    pub fn create(&self, email: &str) -> Result<Subscription, String> {
+        let _lock = self.lock.lock().unwrap();
+        let mut subscriptions = self.load()?;
+
+        if subscriptions
+            .iter()
+            .any(|subscription| subscription.email.eq_ignore_ascii_case(email))
+        {
+            return Err(format!("{} is already subscribed", email));
+        }
+
+        let subscription = Subscription {
+            id: random_string(12),
+            email: email.to_string(),
+            token: random_string(32),
+            created_at: Utc::now(),
+        };
+        subscriptions.push(subscription.clone());
+        self.save(&subscriptions)?;
+        Ok(subscription)
+    }
+
+    // Helper function to find a subscription by id, provided the token matches
+    pub fn get(&self, id: &str, token: &str) -> Result<Option<Subscription>, String> {
+        let _lock = self.lock.lock().unwrap();
+        Ok(self
+            .load()?
+            .into_iter()
+            .find(|subscription| subscription.id == id && tokens_match(token, &subscription.token)))
+    }
+
+    pub fn update_email(
+        &self,
+        id: &str,
+        token: &str,
+        email: &str,
+    ) -> Result<Option<Subscription>, String> {
+        let _lock = self.lock.lock().unwrap();
+        let mut subscriptions = self.load()?;
+
+        if subscriptions.iter().any(|subscription| {
+            subscription.id != id && subscription.email.eq_ignore_ascii_case(email)
+        }) {
+            return Err(format!("{} is already subscribed", email));
+        }
+
+        let Some(subscription) = subscriptions
+            .iter_mut()
+            .find(|subscription| subscription.id == id && tokens_match(token, &subscription.token))
+        else {
+            return Ok(None);
+        };
+        subscription.email = email.to_string();
+        let updated = subscription.clone();
+
+        self.save(&subscriptions)?;
+        Ok(Some(updated))
+    }
+
+    // Returns whether a matching subscription was removed
+    pub fn delete(&self, id: &str, token: &str) -> Result<bool, String> {
+        let _lock = self.lock.lock().unwrap();
+        let mut subscriptions = self.load()?;
+
+        let count = subscriptions.len();
+        subscriptions.retain(|subscription| {
+            !(subscription.id == id && tokens_match(token, &subscription.token))
+        });
+        if subscriptions.len() == count {
+            return Ok(false);
+        }
+
+        self.save(&subscriptions)?;
+        Ok(true)
+    }
+
+    fn load(&self) -> Result<Vec<Subscription>, String> {
+        Ok(json_file::load(&self.path)?.unwrap_or_default())
+    }
+
+    fn save(&self, subscriptions: &[Subscription]) -> Result<(), String> {
+        json_file::save(&self.path, subscriptions)
+    }
+}
+
+pub fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+}
+
+// Helper function to compare a secret given by a client with ours, taking
+// the same time wherever they differ
+pub fn tokens_match(given: &str, expected: &str) -> bool {
+    given.as_bytes().ct_eq(expected.as_bytes()).into()
+}
+
+// Helper function to render the page an unsubscribe link opens. With
+// `confirm`, the page holds a button posting to that URL: mail scanners open
+// links, so following one mustn't unsubscribe anybody
+pub fn unsubscribe_page(status: StatusCode, message: &str, confirm: Option<&str>) -> Response {
+    let button = confirm
+        .map(|action| {
+            format!(
+                "<form method=\"post\" action=\"{}\"><button type=\"submit\">Annulla l'iscrizione</button></form>\n",
+                escape_html(action)
+            )
+        })
+        .unwrap_or_default();
+    let html = format!(
+        "<!DOCTYPE html>\n<html lang=\"it\">\n<head><meta charset=\"utf-8\"><title>Corriere Scraper</title></head>\n<body><p>{}</p>\n{}</body>\n</html>\n",
+        escape_html(message),
+        button
+    );
+    (
+        status,
+        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+        html,
+    )
+        .into_response()
+}
+
+fn subscription_response(
+    status: StatusCode,
+    subscription: Option<&Subscription>,
+    token: Option<String>,
+    error: Option<String>,
+) -> (StatusCode, Json<SubscriptionResponse>) {
+    (
+        status,
+        Json(SubscriptionResponse {
+            subscription: subscription.map(SubscriptionView::from),
+            token,
+            error,
+        }),
+    )
+}
+
+fn error_response(
+    status: StatusCode,
+    error_message: String,
+) -> (StatusCode, Json<SubscriptionResponse>) {
+    subscription_response(status, None, None, Some(error_message))
+}
+
+fn not_found() -> (StatusCode, Json<SubscriptionResponse>) {
+    error_response(
+        StatusCode::NOT_FOUND,
+        "No subscription with this id and token".to_string(),
+    )
+}
+
+// Helper function to check the digest is on and normalize the email address
+fn check_request(
+    state: &AppState,
+    email: Option<&str>,
+) -> Result<Option<String>, (StatusCode, Json<SubscriptionResponse>)> {
+    if !state.config.digest_enabled() {
+        return Err(error_response(
+            StatusCode::NOT_FOUND,
+            "The email digest is not enabled".to_string(),
+        ));
+    }
+
+    email
+        .map(|email| {
+            let email = email.trim();
+            email
+                .parse::<Address>()
+                .map(|_| email.to_string())
+                .map_err(|_| {
+                    error_response(
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid email address '{}'", email),
+                    )
+                })
+        })
+        .transpose()
+}
+
+pub async fn create_handler(
+    State(state): State<AppState>,
+    Json(request): Json<SubscriptionRequest>,
+) -> (StatusCode, Json<SubscriptionResponse>) {
+    let email = match check_request(&state, Some(&request.email)) {
+        Ok(email) => email.unwrap_or_default(),
+        Err(response) => return response,
+    };
+
+    match state.subscriptions.create(&email) {
+        Ok(subscription) => subscription_response(
+            StatusCode::CREATED,
+            Some(&subscription),
+            Some(subscription.token.clone()),
+            None,
+        ),
+        Err(error_message) => error_response(StatusCode::CONFLICT, error_message),
+    }
+}
+
+pub async fn get_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<TokenParams>,
+) -> (StatusCode, Json<SubscriptionResponse>) {
+    if let Err(response) = check_request(&state, None) {
+        return response;
+    }
+
+    match state
+        .subscriptions
+        .get(&id, params.token.as_deref().unwrap_or(""))
+    {
+        Ok(Some(subscription)) => {
+            subscription_response(StatusCode::OK, Some(&subscription), None, None)
+        }
+        Ok(None) => not_found(),
+        Err(error_message) => error_response(StatusCode::INTERNAL_SERVER_ERROR, error_message),
+    }
+}
+
+pub async fn update_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<TokenParams>,
+    Json(request): Json<SubscriptionRequest>,
+) -> (StatusCode, Json<SubscriptionResponse>) {
+    let email = match check_request(&state, Some(&request.email)) {
+        Ok(email) => email.unwrap_or_default(),
+        Err(response) => return response,
+    };
+
+    match state
+        .subscriptions
+        .update_email(&id, params.token.as_deref().unwrap_or(""), &email)
+    {
+        Ok(Some(subscription)) => {
+            subscription_response(StatusCode::OK, Some(&subscription), None, None)
+        }
+        Ok(None) => not_found(),
+        Err(error_message) => error_response(StatusCode::CONFLICT, error_message),
+    }
+}
+
+pub async fn delete_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<TokenParams>,
+) -> (StatusCode, Json<SubscriptionResponse>) {
+    if let Err(response) = check_request(&state, None) {
+        return response;
+    }
+
+    match state
+        .subscriptions
+        .delete(&id, params.token.as_deref().unwrap_or(""))
+    {
+        Ok(true) => subscription_response(StatusCode::OK, None, None, None),
+        Ok(false) => not_found(),
+        Err(error_message) => error_response(StatusCode::INTERNAL_SERVER_ERROR, error_message),
+    }
+}
+
+// Target of the link in every digest email, so it answers with a page
+// rather than JSON. Opening it only asks to confirm; the button there, or a
+// one-click List-Unsubscribe from the mail client, posts back to delete
+pub async fn unsubscribe_page_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<TokenParams>,
+) -> Response {
+    let action = format!(
+        "{}/api/subscriptions/{}/unsubscribe?token={}",
+        state.config.public_url,
+        id,
+        params.token.as_deref().unwrap_or("")
+    );
+    unsubscribe_page(
+        StatusCode::OK,
+        "Vuoi smettere di ricevere il riepilogo quotidiano?",
+        Some(&action),
+    )
+}
+
+pub async fn unsubscribe_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<TokenParams>,
+) -> Response {
+    let (status, message) = match state
+        .subscriptions
+        .delete(&id, params.token.as_deref().unwrap_or(""))
+    {
+        Ok(true) => (
+            StatusCode::OK,
+            "Iscrizione annullata: non riceverai più il riepilogo quotidiano.".to_string(),
+        ),
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            "Link non valido o iscrizione già annullata.".to_string(),
+        ),
+        Err(error_message) => (StatusCode::INTERNAL_SERVER_ERROR, error_message),
+    };
+    unsubscribe_page(status, &message, None)
+}
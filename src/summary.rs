@@ -0,0 +1,359 @@
+use async_trait::async_trait;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+
+use crate::article::{self, ArticleDetail};
+use crate::clusters;
+use crate::config::Config;
+use crate::AppState;
+
+const DEFAULT_SENTENCES: usize = 3;
+const MAX_SENTENCES: usize = 10;
+// TextRank's usual damping factor and a bound on the iterations
+const DAMPING: f64 = 0.85;
+const ITERATIONS: usize = 50;
+// Words are compared on their first letters, so "governo" and "governi"
+// count as the same word
+const STEM_CHARS: usize = 6;
+
+// Lowercased words after which a full stop doesn't end the sentence
+const ABBREVIATIONS: &[&str] = &[
+    "sig", "sigg", "sig.ra", "dott", "dott.ssa", "prof", "prof.ssa", "avv", "ing", "arch", "on",
+    "sen", "gen", "col", "mons", "don", "s", "ss", "st", "ecc", "etc", "art", "artt", "pag",
+    "pagg", "cap", "vol", "n", "nr", "tel", "fig", "cfr", "ca", "sec", "dr", "mr", "mrs", "jr",
+    "spa", "srl", "p.es", "es", "v", "vs",
+];
+
+// Helper function to split Italian text into sentences. A stop ends one when
+// followed by a space and a capital letter, digit or opening quote, unless it
+// closes an abbreviation or an initial. Paragraphs always end one
+pub fn sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    for paragraph in text.split("\n\n") {
+        let chars: Vec<char> = paragraph.chars().collect();
+        let mut start = 0;
+        let mut i = 0;
+        while i < chars.len() {
+            if matches!(chars[i], '.' | '!' | '?' | '…') {
+                // Runs like "?!" or "..." and a closing quote stay attached
+                let mut end = i + 1;
+                while end < chars.len()
+                    && matches!(chars[end], '.' | '!' | '?' | '…' | '»' | '"' | ')')
+                {
+                    end += 1;
+                }
+                let next = chars[end..].iter().find(|c| !c.is_whitespace());
+                let spaced = chars.get(end).is_some_and(|c| c.is_whitespace());
+                let starts_sentence = next.is_some_and(|c| {
+                    c.is_uppercase()
+                        || c.is_ascii_digit()
+                        || matches!(c, '«' | '"' | '(' | '-' | '—')
+                });
+                if spaced
+                    && starts_sentence
+                    && !(chars[i] == '.' && is_abbreviation(&chars[start..i]))
+                {
+                    push_sentence(&mut sentences, &chars[start..end]);
+                    start = end;
+                }
+                i = end;
+            } else {
+                i += 1;
+            }
+        }
+        push_sentence(&mut sentences, &chars[start..]);
+    }
+    sentences
+}
+
+fn push_sentence(sentences: &mut Vec<String>, chars: &[char]) {
+    let sentence = chars.iter().collect::<String>();
+    let sentence = sentence.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !sentence.is_empty() {
+        sentences.push(sentence);
+    }
+}
+
+// Whether the text before a full stop ends with an abbreviation or a single
+// capital letter, as in "G. Rossi"
+fn is_abbreviation(before: &[char]) -> bool {
+    let word: String = before
+        .iter()
+        .rev()
+        .take_while(|c| !c.is_whitespace() && !matches!(c, '(' | '«' | '"'))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    let mut letters = word.chars();
+    if let (Some(first), None) = (letters.next(), letters.next()) {
+        return first.is_uppercase();
+    }
+    ABBREVIATIONS.contains(&word.to_lowercase().as_str())
+}
+
+fn stems(sentence: &str) -> BTreeSet<String> {
+    clusters::tokens(sentence)
+        .into_iter()
+        .map(|word| word.chars().take(STEM_CHARS).collect())
+        .collect()
+}
+
+// Helper function to rank sentences with TextRank: sentences are nodes,
+// linked by how many words they share relative to their lengths, and the
+// score is their PageRank. Returns the indexes of the best `count`, in the
+// order they appear in the text
+pub fn textrank(sentences: &[String], count: usize) -> Vec<usize> {
+    let n = sentences.len();
+    if n <= count {
+        return (0..n).collect();
+    }
+
+    let words: Vec<BTreeSet<String>> = sentences.iter().map(|s| stems(s)).collect();
+    let mut weights = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in i + 1..n {
+            let shared = words[i].intersection(&words[j]).count() as f64;
+            let norm = (words[i].len() as f64).ln_1p() + (words[j].len() as f64).ln_1p();
+            if shared > 0.0 && norm > 0.0 {
+                weights[i][j] = shared / norm;
+                weights[j][i] = shared / norm;
+            }
+        }
+    }
+    let totals: Vec<f64> = weights.iter().map(|row| row.iter().sum()).collect();
+
+    let mut scores = vec![1.0; n];
+    for _ in 0..ITERATIONS {
+        let next: Vec<f64> = (0..n)
+            .map(|i| {
+                let incoming: f64 = (0..n)
+                    .filter(|&j| totals[j] > 0.0)
+                    .map(|j| weights[j][i] / totals[j] * scores[j])
+                    .sum();
+                (1.0 - DAMPING) + DAMPING * incoming
+            })
+            .collect();
+        let change: f64 = next.iter().zip(&scores).map(|(a, b)| (a - b).abs()).sum();
+        scores = next;
+        if change < 1e-6 {
+            break;
+        }
+    }
+
+    // Ties go to the earlier sentence, as articles lead with what matters
+    let mut ranked: Vec<usize> = (0..n).collect();
+    ranked.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]).then(a.cmp(&b)));
+    ranked.truncate(count);
+    ranked.sort();
+    ranked
+}
+
+// Produces the summary of an article in at most the given number of sentences
+#[async_trait]
+pub trait Summarizer: Send + Sync {
+    async fn summarize(&self, article: &ArticleDetail, count: usize)
+        -> Result<Vec<String>, String>;
+}
+
+// Picks the most central sentences of the body with TextRank
+pub struct ExtractiveSummarizer;
+
+#[async_trait]
+impl Summarizer for ExtractiveSummarizer {
+    async fn summarize(
+        &self,
+        article: &ArticleDetail,
+        count: usize,
+    ) -> Result<Vec<String>, String> {
+        let sentences = sentences(&article.body);
+        if sentences.is_empty() {
+            return Err("The article has no text to summarize".to_string());
+        }
+        Ok(textrank(&sentences, count)
+            .into_iter()
+            .map(|index| sentences[index].clone())
+            .collect())
+    }
+}
+
+// Asks an OpenAI-style chat completions endpoint for an abstractive summary
+pub struct LlmSummarizer {
+    pub client: reqwest::Client,
+    pub url: String,
+    pub api_key: Option<String>,
+    pub model: String,
+}
+
+#[async_trait]
+impl Summarizer for LlmSummarizer {
+    async fn summarize(
+        &self,
+        article: &ArticleDetail,
+        count: usize,
+    ) -> Result<Vec<String>, String> {
+        if article.body.is_empty() {
+            return Err("The article has no text to summarize".to_string());
+        }
+        let instructions = format!(
+            "Riassumi l'articolo in italiano in al massimo {} frasi, senza aggiungere informazioni che non contiene.",
+            count
+        );
+        let mut request = self.client.post(&self.url).json(&json!({
+            "model": self.model,
+            "messages": [
+                { "role": "system", "content": instructions },
+                { "role": "user", "content": format!("{}\n\n{}", article.title, article.body) },
+            ],
+        }));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach SUMMARY_API_URL: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("Summary API returned HTTP {}", response.status()));
+        }
+        let response: Value = response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid summary response: {}", e))?;
+        let text = response["choices"][0]["message"]["content"]
+            .as_str()
+            .ok_or("The summary response has no text")?;
+
+        let mut summary = sentences(text);
+        summary.truncate(count);
+        Ok(summary)
+    }
+}
+
+// Helper function to build the LLM backend, or None when no SUMMARY_API_URL
+// is set
+pub fn llm_summarizer(config: &Config) -> Option<LlmSummarizer> {
+    config.summary_api_url.as_ref().map(|url| LlmSummarizer {
+        client: reqwest::Client::new(),
+        url: url.clone(),
+        api_key: config.summary_api_key.clone(),
+        model: config.summary_model.clone(),
+    })
+}
+
+#[derive(Deserialize)]
+pub struct SummaryParams {
+    url: String,
+    sentences: Option<usize>,
+    // "extractive" (default) or "llm"
+    method: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SummaryResponse {
+    pub url: String,
+    pub title: Option<String>,
+    pub method: String,
+    pub sentences: Vec<String>,
+    // The sentences joined, for display
+    pub summary: Option<String>,
+    pub error: Option<String>,
+}
+
+fn summary_error(
+    status: StatusCode,
+    url: String,
+    method: String,
+    error_message: String,
+) -> (StatusCode, Json<SummaryResponse>) {
+    (
+        status,
+        Json(SummaryResponse {
+            url,
+            title: None,
+            method,
+            sentences: vec![],
+            summary: None,
+            error: Some(error_message),
+        }),
+    )
+}
+
+// Summary of the full text of an article, in ?sentences= sentences
+pub async fn summary_handler(
+    State(state): State<AppState>,
+    Query(params): Query<SummaryParams>,
+) -> (StatusCode, Json<SummaryResponse>) {
+    let method = params
+        .method
+        .unwrap_or_else(|| "extractive".to_string())
+        .to_lowercase();
+    let count = params.sentences.unwrap_or(DEFAULT_SENTENCES);
+    if count == 0 || count > MAX_SENTENCES {
+        return summary_error(
+            StatusCode::BAD_REQUEST,
+            params.url,
+            method,
+            format!("sentences must be between 1 and {}", MAX_SENTENCES),
+        );
+    }
+
+    let llm = llm_summarizer(&state.config);
+    let summarizer: &dyn Summarizer = match (method.as_str(), &llm) {
+        ("extractive", _) => &ExtractiveSummarizer,
+        ("llm", Some(llm)) => llm,
+        ("llm", None) => {
+            return summary_error(
+                StatusCode::BAD_REQUEST,
+                params.url,
+                method,
+                "LLM summaries need SUMMARY_API_URL".to_string(),
+            )
+        }
+        _ => {
+            return summary_error(
+                StatusCode::BAD_REQUEST,
+                params.url,
+                method,
+                "method must be extractive or llm".to_string(),
+            )
+        }
+    };
+
+    let article = match article::fetch_article(&state, &params.url).await {
+        Ok(article) => article,
+        Err(error_message) => {
+            return summary_error(StatusCode::BAD_GATEWAY, params.url, method, error_message)
+        }
+    };
+    let result = summarizer.summarize(&article, count).await;
+    state.metrics.increment(
+        "corriere_summaries_total",
+        &[
+            ("method", method.as_str()),
+            ("result", if result.is_ok() { "ok" } else { "failed" }),
+        ],
+    );
+
+    match result {
+        Ok(sentences) => (
+            StatusCode::OK,
+            Json(SummaryResponse {
+                url: params.url,
+                title: Some(article.title),
+                method,
+                summary: Some(sentences.join(" ")),
+                sentences,
+                error: None,
+            }),
+        ),
+        Err(error_message) => {
+            summary_error(StatusCode::BAD_GATEWAY, params.url, method, error_message)
+        }
+    }
+}
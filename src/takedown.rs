@@ -0,0 +1,222 @@
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashSet};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::admin::{actor, admin_error, unauthorized};
+use crate::audit::{self, AuditEvent};
+use crate::{json_file, request_id, AppState, NewsItem};
+
+// Links of redacted items start with this, followed by the hash
+pub const TOMBSTONE_PREFIX: &str = "redacted:sha256:";
+
+// A takedown as recorded in DATA_DIR/takedowns.json, which doubles as the
+// log of who asked for what: entries are only ever added
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Takedown {
+    pub link_hash: String,
+    pub reason: String,
+    pub redacted_at: DateTime<Utc>,
+    pub request_id: Option<String>,
+}
+
+// Helper function to hash a link the way tombstones and the takedown list
+// store it
+pub fn link_hash(link: &str) -> String {
+    let digest = Sha256::digest(link.trim().as_bytes());
+    digest.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// The item left in the archive in place of a redacted one
+pub fn tombstone(link: &str) -> NewsItem {
+    NewsItem {
+        title: String::new(),
+        description: String::new(),
+        link: format!("{}{}", TOMBSTONE_PREFIX, link_hash(link)),
+        ..NewsItem::default()
+    }
+}
+
+pub fn is_tombstone(link: &str) -> bool {
+    link.starts_with(TOMBSTONE_PREFIX)
+}
+
+// Links taken down, persisted as a JSON file under DATA_DIR
+pub struct TakedownStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl TakedownStore {
+    pub fn new(path: PathBuf) -> TakedownStore {
+        TakedownStore {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<Takedown>, String> {
+        let _lock = self.lock.lock().unwrap();
+        self.load()
+    }
+
+    // Records a takedown, unless its link was taken down before: a request
+    // repeated because the redaction failed the first time keeps the first
+    // entry. Returns the entry in force
+    pub fn add(&self, takedown: Takedown) -> Result<Takedown, String> {
+        let _lock = self.lock.lock().unwrap();
+        let mut takedowns = self.load()?;
+        if let Some(existing) = takedowns
+            .iter()
+            .find(|existing| existing.link_hash == takedown.link_hash)
+        {
+            return Ok(existing.clone());
+        }
+        takedowns.push(takedown.clone());
+        json_file::save(&self.path, &takedowns)?;
+        Ok(takedown)
+    }
+
+    // The hashes of the links taken down
+    pub fn hashes(&self) -> Result<HashSet<String>, String> {
+        Ok(self
+            .list()?
+            .into_iter()
+            .map(|takedown| takedown.link_hash)
+            .collect())
+    }
+
+    // Helper function to replace the items taken down with tombstones,
+    // before a scrape is archived
+    pub fn redact_items(&self, news: &mut [NewsItem]) -> Result<(), String> {
+        let hashes = self.hashes()?;
+        if hashes.is_empty() {
+            return Ok(());
+        }
+        for item in news.iter_mut() {
+            if hashes.contains(&link_hash(&item.link)) {
+                *item = tombstone(&item.link);
+            }
+        }
+        Ok(())
+    }
+
+    fn load(&self) -> Result<Vec<Takedown>, String> {
+        Ok(json_file::load(&self.path)?.unwrap_or_default())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct TakedownRequest {
+    pub link: String,
+    pub reason: String,
+}
+
+// Redacts an article from the archive, e.g. after a legal takedown or a
+// GDPR request: every appearance becomes a tombstone holding only the hash
+// of the link, which the read APIs and exports skip, and later scrapes
+// listing it are archived the same way. The article also leaves the
+// semantic index, and the S3 exports of the days it appeared are uploaded
+// again without it. Repeating a request that failed half way is safe
+pub async fn create_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<TakedownRequest>,
+) -> Response {
+    if let Some(response) = unauthorized(&state, &headers) {
+        return response;
+    }
+    let link = request.link.trim();
+    if link.is_empty() || request.reason.trim().is_empty() {
+        return admin_error(StatusCode::BAD_REQUEST, "Both link and reason are required");
+    }
+
+    // Recorded first, so a scrape archived meanwhile is redacted too
+    let takedown = Takedown {
+        link_hash: link_hash(link),
+        reason: request.reason.trim().to_string(),
+        redacted_at: Utc::now(),
+        request_id: request_id::current(),
+    };
+    let result = match state.takedowns.add(takedown.clone()) {
+        Ok(recorded) => purge(&state, link).await.map(|scrapes| (recorded, scrapes)),
+        Err(error_message) => Err(error_message),
+    };
+    let actor = actor(&state, &headers);
+    let event = AuditEvent::admin("takedown", actor, Some(&takedown.link_hash), &result);
+    audit::record(&state, event).await;
+    let (takedown, scrapes) = match result {
+        Ok(result) => result,
+        Err(error_message) => {
+            return admin_error(StatusCode::INTERNAL_SERVER_ERROR, &error_message)
+        }
+    };
+    eprintln!(
+        "{}Takedown of {}: {} scrapes redacted ({})",
+        request_id::prefix(),
+        takedown.link_hash,
+        scrapes,
+        takedown.reason
+    );
+    state.metrics.increment("corriere_takedowns_total", &[]);
+
+    (
+        StatusCode::CREATED,
+        Json(json!({ "takedown": takedown, "scrapes_redacted": scrapes })),
+    )
+        .into_response()
+}
+
+// Helper function to remove a link from everything that keeps a copy of it:
+// the archive, the semantic index and the S3 exports. Returns how many
+// scrapes were redacted
+async fn purge(state: &AppState, link: &str) -> Result<usize, String> {
+    let (scrapes, days) = match &state.archive {
+        Some(archive) => {
+            // The days to export again, looked up before the appearances go.
+            // Every day, as a takedown has to reach all of them
+            let days: BTreeSet<NaiveDate> = archive
+                .history(link, None, None)
+                .await?
+                .into_iter()
+                .map(|appearance| appearance.scraped_at.date_naive())
+                .collect();
+            (archive.redact(link).await?, days)
+        }
+        None => (0, BTreeSet::new()),
+    };
+
+    #[cfg(feature = "semantic")]
+    if let Some(index) = &state.semantic {
+        index.remove(link).await?;
+    }
+    // Only past days have been exported
+    #[cfg(feature = "s3")]
+    if let Some(exporter) = crate::s3::S3Exporter::from_config(&state.config)? {
+        let today = Utc::now().date_naive();
+        for day in days.into_iter().filter(|day| *day < today) {
+            exporter.export_items(state, day).await?;
+        }
+    }
+    #[cfg(not(feature = "s3"))]
+    let _ = days;
+    Ok(scrapes)
+}
+
+// Every takedown so far, oldest first
+pub async fn list_handler(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    if let Some(response) = unauthorized(&state, &headers) {
+        return response;
+    }
+    match state.takedowns.list() {
+        Ok(takedowns) => Json(json!({ "takedowns": takedowns })).into_response(),
+        Err(error_message) => admin_error(StatusCode::INTERNAL_SERVER_ERROR, &error_message),
+    }
+}
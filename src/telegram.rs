@@ -0,0 +1,282 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::delivery::{self, Message};
+use crate::formats::escape_html;
+use crate::lease;
+use crate::sent_log::{self, SentLog};
+use crate::{AppState, NewsItem};
+
+// Attempts per message when Telegram answers 429 Too Many Requests
+const MAX_ATTEMPTS: u32 = 3;
+// Telegram's limit for photo captions
+const CAPTION_LIMIT: usize = 1024;
+
+#[derive(Deserialize)]
+struct ApiResponse {
+    ok: bool,
+    description: Option<String>,
+    parameters: Option<ApiParameters>,
+}
+
+#[derive(Deserialize)]
+struct ApiParameters {
+    retry_after: Option<u64>,
+}
+
+// Posts new top headlines to the configured chats through the Bot API
+pub struct TelegramNotifier {
+    client: reqwest::Client,
+    bot_url: String,
+    chat_ids: Vec<String>,
+    top_n: usize,
+    sent: SentLog,
+}
+
+impl TelegramNotifier {
+    // Helper function to build the notifier, or None when no bot token or
+    // chat is configured
+    pub fn from_config(config: &Config) -> Result<Option<TelegramNotifier>, String> {
+        let Some(token) = &config.telegram_bot_token else {
+            return Ok(None);
+        };
+        if config.telegram_chat_ids.is_empty() {
+            return Err("TELEGRAM_BOT_TOKEN is set but TELEGRAM_CHAT_IDS is empty".to_string());
+        }
+
+        Ok(Some(TelegramNotifier {
+            client: reqwest::Client::new(),
+            bot_url: format!("{}/bot{}", config.telegram_api_url, token),
+            chat_ids: config.telegram_chat_ids.clone(),
+            top_n: config.telegram_top_n,
+            sent: SentLog::load(config, "telegram")?,
+        }))
+    }
+
+    // Helper function to post the top items that weren't posted before.
+    // On the first run the current items are only recorded, so starting the
+    // bot doesn't flood the chats with the whole homepage
+    pub async fn notify(&mut self, state: &AppState, news: &[NewsItem]) -> Result<usize, String> {
+        let top = &news[..news.len().min(self.top_n)];
+
+        if !self.sent.is_stored() {
+            for item in top {
+                self.sent.push(item.link.clone());
+            }
+            self.sent.save()?;
+            return Ok(0);
+        }
+
+        // The homepage can list the same article twice
+        let mut seen = HashSet::new();
+        let fresh: Vec<NewsItem> = top
+            .iter()
+            .filter(|item| !self.sent.contains(&item.link) && seen.insert(item.link.clone()))
+            .cloned()
+            .collect();
+
+        // Post the lower ranked items first, so the top story ends up last in the chat
+        let mut posted = 0;
+        for item in fresh.iter().rev() {
+            for chat_id in self.chat_ids.clone() {
+                match self.post(state, &chat_id, item).await {
+                    Ok(()) => {
+                        posted += 1;
+                        state
+                            .metrics
+                            .increment("corriere_telegram_messages_total", &[("result", "sent")]);
+                    }
+                    Err(error_message) => {
+                        eprintln!("Telegram post to {} failed: {}", chat_id, error_message);
+                        state
+                            .metrics
+                            .increment("corriere_telegram_messages_total", &[("result", "failed")]);
+                        // Retried as text, which doesn't depend on the image
+                        let message = Message::Telegram {
+                            body: text_body(&chat_id, item),
+                            chat_id,
+                            method: "sendMessage".to_string(),
+                        };
+                        delivery::enqueue(state, message, None, &error_message);
+                    }
+                }
+            }
+            // Recorded even after a failure: a retry on the next poll could
+            // duplicate the message in the chats where it did go through
+            self.sent.push(item.link.clone());
+        }
+
+        if !fresh.is_empty() {
+            self.sent.save()?;
+        }
+        Ok(posted)
+    }
+
+    // Helper function to post one item, with its image when it has one. A
+    // photo Telegram can't fetch falls back to a text message
+    async fn post(&self, state: &AppState, chat_id: &str, item: &NewsItem) -> Result<(), String> {
+        if let (Some(image_url), Some(caption)) = (&item.image_url, caption(item, CAPTION_LIMIT)) {
+            let body = json!({
+                "chat_id": chat_id,
+                "photo": image_url,
+                "caption": caption,
+                "parse_mode": "HTML",
+            });
+            match self.call(state, chat_id, "sendPhoto", &body).await {
+                Ok(()) => return Ok(()),
+                Err(error_message) => {
+                    eprintln!("Telegram sendPhoto failed, sending text: {}", error_message)
+                }
+            }
+        }
+
+        self.call(state, chat_id, "sendMessage", &text_body(chat_id, item))
+            .await
+    }
+
+    // Helper function to call a Bot API method, spacing messages to the same
+    // chat and waiting out 429 responses as Telegram asks
+    async fn call(
+        &self,
+        state: &AppState,
+        chat_id: &str,
+        method: &str,
+        body: &Value,
+    ) -> Result<(), String> {
+        for _ in 0..MAX_ATTEMPTS {
+            state.telegram_spacing.wait(chat_id).await;
+
+            let response = self
+                .client
+                .post(format!("{}/{}", self.bot_url, method))
+                .json(body)
+                .send()
+                .await
+                .map_err(|e| format!("Failed to reach Telegram: {}", e))?;
+            let response: ApiResponse = response
+                .json()
+                .await
+                .map_err(|e| format!("Invalid Telegram response: {}", e))?;
+
+            if response.ok {
+                return Ok(());
+            }
+            match response.parameters.and_then(|p| p.retry_after) {
+                Some(retry_after) => tokio::time::sleep(Duration::from_secs(retry_after)).await,
+                None => return Err(response.description.unwrap_or_default()),
+            }
+        }
+        Err(format!(
+            "Still rate limited after {} attempts",
+            MAX_ATTEMPTS
+        ))
+    }
+}
+
+// Message in Telegram's HTML flavour: bold title, summary and a link
+pub fn message_text(item: &NewsItem) -> String {
+    let mut text = format!("<b>{}</b>\n", escape_html(&item.title));
+    if !item.description.is_empty() {
+        text.push_str(&format!("{}\n", escape_html(&item.description)));
+    }
+    text.push_str(&format!(
+        "<a href=\"{}\">Leggi su corriere.it</a>",
+        escape_html(&item.link)
+    ));
+    text
+}
+
+// Body of a sendMessage call posting `item` as text
+fn text_body(chat_id: &str, item: &NewsItem) -> Value {
+    json!({
+        "chat_id": chat_id,
+        "text": message_text(item),
+        "parse_mode": "HTML",
+    })
+}
+
+// Photo caption for `item` within `limit` characters, counted on the final
+// markup. The summary is shortened first, then the title; markup is never cut
+// in half. None when even the link doesn't fit, so a text message is sent
+pub fn caption(item: &NewsItem, limit: usize) -> Option<String> {
+    let text = message_text(item);
+    if text.chars().count() <= limit {
+        return Some(text);
+    }
+
+    let bare = NewsItem {
+        title: String::new(),
+        description: String::new(),
+        ..item.clone()
+    };
+    let room = limit.checked_sub(message_text(&bare).chars().count())?;
+    let title = shorten(&item.title, room);
+    // The summary needs room for its line break too
+    let room = room.saturating_sub(escape_html(&title).chars().count() + 1);
+    let caption = message_text(&NewsItem {
+        title,
+        description: shorten(&item.description, room),
+        ..item.clone()
+    });
+    (caption.chars().count() <= limit).then_some(caption)
+}
+
+// Helper function to cut `text` so it takes at most `room` characters once
+// escaped, ellipsis included
+fn shorten(text: &str, room: usize) -> String {
+    if escape_html(text).chars().count() <= room {
+        return text.to_string();
+    }
+    let mut used = 1;
+    let mut shortened = String::new();
+    for c in text.chars() {
+        used += escape_html(&c.to_string()).chars().count();
+        if used > room {
+            break;
+        }
+        shortened.push(c);
+    }
+    shortened.truncate(shortened.trim_end().len());
+    if shortened.is_empty() {
+        return shortened;
+    }
+    shortened.push('…');
+    shortened
+}
+
+// Checks the homepage every TELEGRAM_POLL_SECS and posts what's new, for as
+// long as the server runs
+pub async fn run(state: AppState, mut notifier: TelegramNotifier) {
+    let every = Duration::from_secs(state.config.telegram_poll_secs.max(1));
+    let mut interval = tokio::time::interval(every);
+
+    loop {
+        interval.tick().await;
+        // Settings changed by a reload apply from this run on
+        let state = state.current();
+        let ttl = lease::lease_ttl(every);
+        if !sent_log::should_post(&state, "telegram", ttl, &mut [&mut notifier.sent]).await {
+            continue;
+        }
+
+        let news = match crate::get_news(&state).await {
+            // A stale copy has nothing new to announce
+            Ok(cached) if !cached.response.stale => cached.response.news,
+            Ok(_) => continue,
+            Err(response) => {
+                eprintln!(
+                    "Telegram: homepage unavailable: {}",
+                    response.error.unwrap_or_default()
+                );
+                continue;
+            }
+        };
+
+        if let Err(error_message) = notifier.notify(&state, &news).await {
+            eprintln!("Telegram notification failed: {}", error_message);
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use hyper::client::connect::dns::Name;
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::redirect::Policy;
+use reqwest::Url;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+
+// Default timeout of the callback client; callers with their own override it
+pub const CALLBACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Where URLs given by clients may lead: the checks shared by every endpoint
+// that fetches one, such as /api/scrape, /api/article and watches
+#[derive(Clone, Debug)]
+pub struct UrlPolicy {
+    pub allowed_hosts: Vec<String>,
+    pub allow_private_addresses: bool,
+    pub max_redirects: usize,
+    pub max_bytes: usize,
+}
+
+impl UrlPolicy {
+    pub fn from_config(config: &Config) -> UrlPolicy {
+        UrlPolicy {
+            allowed_hosts: config.scrape_allowed_hosts.clone(),
+            allow_private_addresses: config.scrape_allow_private_addresses,
+            max_redirects: config.scrape_max_redirects,
+            max_bytes: config.scrape_max_bytes,
+        }
+    }
+}
+
+// A host is allowed when it equals an allowlist entry or is a subdomain of it
+pub fn is_allowed(url: &Url, allowed_hosts: &[String]) -> bool {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return false;
+    }
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let host = host.to_ascii_lowercase();
+
+    allowed_hosts
+        .iter()
+        .any(|allowed| host == *allowed || host.ends_with(&format!(".{}", allowed)))
+}
+
+// Helper function to tell an address on the internet from one of the
+// loopback, private, link-local, shared (CGNAT) or otherwise reserved ranges
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_v4(mapped),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && ip.octets()[2] == 0)
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local fc00::/7 and link-local fe80::/10
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        // Documentation 2001:db8::/32
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8))
+}
+
+// Helper function to check a URL given by a client before fetching it: a web
+// address without credentials, on an allowed host
+pub fn check(policy: &UrlPolicy, url: &str) -> Result<Url, String> {
+    let parsed = Url::parse(url).map_err(|e| format!("Invalid URL '{}': {}", url, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!(
+            "Unsupported URL scheme '{}', expected http or https",
+            parsed.scheme()
+        ));
+    }
+    if !parsed.username().is_empty() || parsed.password().is_some() {
+        return Err("URLs with credentials are not allowed".to_string());
+    }
+    if !is_allowed(&parsed, &policy.allowed_hosts) {
+        return Err(format!(
+            "Host not allowed: {}",
+            parsed.host_str().unwrap_or("")
+        ));
+    }
+    Ok(parsed)
+}
+
+// Helper function to check a URL we will call back, such as a webhook or a
+// watch's notify URL: any host is fine, but not an address of the internal
+// network unless it is on the allowlist. Host names are resolved and refused
+// when any of their addresses is internal; one that doesn't resolve yet is
+// left to the callback client, which checks again when it connects
+pub async fn check_callback(policy: &UrlPolicy, url: &str) -> Result<Url, String> {
+    let parsed = Url::parse(url)
+        .ok()
+        .filter(|parsed| matches!(parsed.scheme(), "http" | "https") && parsed.has_host())
+        .ok_or(format!("Invalid callback URL '{}'", url))?;
+    if !parsed.username().is_empty() || parsed.password().is_some() {
+        return Err("URLs with credentials are not allowed".to_string());
+    }
+    if policy.allow_private_addresses || is_allowed(&parsed, &policy.allowed_hosts) {
+        return Ok(parsed);
+    }
+    let internal = || format!("Callback URL points to an internal address: {}", url);
+    let host = parsed.host_str().unwrap_or_default();
+    if let Ok(ip) = host
+        .trim_matches(|c| c == '[' || c == ']')
+        .parse::<IpAddr>()
+    {
+        return if is_public(ip) {
+            Ok(parsed)
+        } else {
+            Err(internal())
+        };
+    }
+    let domain = host.trim_end_matches('.').to_ascii_lowercase();
+    if domain == "localhost" || domain.ends_with(".localhost") {
+        return Err(internal());
+    }
+    let port = parsed.port_or_known_default().unwrap_or(80);
+    if let Ok(mut addrs) = tokio::net::lookup_host((domain.as_str(), port)).await {
+        if addrs.any(|addr| !is_public(addr.ip())) {
+            return Err(internal());
+        }
+    }
+    Ok(parsed)
+}
+
+// Resolves host names for the scrape and callback clients and drops internal
+// addresses, except for the hosts in `trusted`. Running at connection time,
+// the address checked is the one connected to, so a record that changes
+// between the check and the fetch doesn't get through
+struct PublicResolver {
+    trusted: Vec<String>,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_ascii_lowercase();
+        let trusted = self
+            .trusted
+            .iter()
+            .any(|allowed| host == *allowed || host.ends_with(&format!(".{}", allowed)));
+        Box::pin(async move {
+            let resolved: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            let public: Vec<SocketAddr> = resolved
+                .into_iter()
+                .filter(|addr| trusted || is_public(addr.ip()))
+                .collect();
+            if public.is_empty() {
+                return Err(format!("{} resolves to an internal address", host).into());
+            }
+            let addrs: Addrs = Box::new(public.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+// Helper function to build a client for URLs we call back: webhooks, alerts,
+// watch notifications and heartbeats. Host names resolving to internal
+// addresses are refused as for scrapes, except on allowed hosts, matching
+// check_callback, and redirects aren't followed so one can't lead inside
+pub fn build_callback_client(policy: &UrlPolicy, timeout: Duration) -> reqwest::Client {
+    let mut builder = reqwest::Client::builder()
+        .timeout(timeout)
+        .redirect(Policy::none());
+    if !policy.allow_private_addresses {
+        builder = builder.dns_resolver(Arc::new(PublicResolver {
+            trusted: policy.allowed_hosts.clone(),
+        }));
+    }
+    builder.build().expect("Failed to build HTTP client")
+}
+
+// Helper function to build the client used for URLs given by clients.
+// Redirects are only followed while they stay on allowed hosts, up to
+// SCRAPE_MAX_REDIRECTS, so the allowlist can't be sidestepped with a redirect
+pub fn build_client(policy: &UrlPolicy) -> reqwest::Client {
+    let allowed_hosts = policy.allowed_hosts.clone();
+    let max_redirects = policy.max_redirects;
+    let redirects = Policy::custom(move |attempt| {
+        if attempt.previous().len() > max_redirects {
+            attempt.error("too many redirects")
+        } else if is_allowed(attempt.url(), &allowed_hosts) {
+            attempt.follow()
+        } else {
+            attempt.stop()
+        }
+    });
+
+    let mut builder = reqwest::Client::builder().redirect(redirects);
+    if !policy.allow_private_addresses {
+        builder = builder.dns_resolver(Arc::new(PublicResolver { trusted: vec![] }));
+    }
+    builder.build().expect("Failed to build HTTP client")
+}
+
+// A fetched page and how it was fetched
+pub struct FetchedPage {
+    pub html: String,
+    // After redirects
+    pub url: String,
+    pub status: u16,
+    pub content_length: usize,
+    pub duration: Duration,
+}
+
+// Helper function to fetch a page, giving up once it grows past `max_bytes`
+// instead of holding all of it in memory
+pub async fn fetch_html(
+    client: &reqwest::Client,
+    url: &str,
+    max_bytes: usize,
+) -> Result<String, String> {
+    fetch_page(client, url, max_bytes)
+        .await
+        .map(|page| page.html)
+}
+
+// Like fetch_html, keeping the details of the exchange
+pub async fn fetch_page(
+    client: &reqwest::Client,
+    url: &str,
+    max_bytes: usize,
+) -> Result<FetchedPage, String> {
+    let started = Instant::now();
+    let mut resp = client
+        .get(url)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch URL: {}", e))?;
+    if !resp.status().is_success() {
+        return Err(format!("Upstream returned HTTP {}", resp.status()));
+    }
+    let too_large = || format!("Page is larger than {} bytes", max_bytes);
+    if resp
+        .content_length()
+        .is_some_and(|len| len > max_bytes as u64)
+    {
+        return Err(too_large());
+    }
+    let status = resp.status().as_u16();
+    let final_url = resp.url().to_string();
+
+    let mut body = Vec::new();
+    while let Some(chunk) = resp
+        .chunk()
+        .await
+        .map_err(|e| format!("Failed to read response text: {}", e))?
+    {
+        if body.len() + chunk.len() > max_bytes {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(FetchedPage {
+        content_length: body.len(),
+        html: String::from_utf8_lossy(&body).into_owned(),
+        url: final_url,
+        status,
+        duration: started.elapsed(),
+    })
+}
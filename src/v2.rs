@@ -0,0 +1,355 @@
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, NaiveDate, Utc};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::archive;
+use crate::config::Config;
+use crate::dates::{self, DateFormat};
+use crate::engagement::Engagement;
+use crate::entities::{Entity, Gazetteer};
+use crate::extract;
+use crate::image::ImageInfo;
+use crate::language;
+use crate::local::{self, Edition};
+use crate::problem::{self, Lang, Problem};
+use crate::provenance::Provenance;
+use crate::scoring::{self, SortOrder};
+use crate::typography::{self, NormalizedText};
+use crate::{AppState, NewsItem, NewsResponse};
+
+// Versioning policy: once an API version is published, its fields are never
+// removed, renamed or retyped. New fields are only added to the latest
+// version, so v1 keeps exactly the shape it has; anything else needs a new
+// version. /api/news is, and stays, an alias of /api/v1/news, and every
+// versioned response names its version in the API-Version header
+pub const API_VERSION_HEADER: &str = "api-version";
+
+// Helper function to tag a response with the API version it follows
+pub fn with_version(mut response: Response, version: u32) -> Response {
+    response
+        .headers_mut()
+        .insert(API_VERSION_HEADER, HeaderValue::from(version));
+    response
+}
+
+// A homepage item as served by /api/v2/news
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct NewsItemV2 {
+    // Stable across scrapes: derived from the link without its query string
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    pub link: String,
+    pub image_url: Option<String>,
+    // The image with its sizes, srcset variants, caption and credit
+    pub image: Option<ImageInfo>,
+    // Local edition and sections from the link, e.g. ["milano", "cronaca"]
+    pub categories: Vec<String>,
+    // Publication day from the link, when it has one
+    pub published_on: Option<NaiveDate>,
+    // "it" or "en", see language::detect
+    pub language: &'static str,
+    // Local edition the item comes from, e.g. "milano"
+    pub city: Option<String>,
+    // Editorial prominence from 0 to 100, see ranking::Placement::score
+    pub prominence_score: f64,
+    // People, places and organizations named in the title or summary
+    pub entities: Vec<Entity>,
+    // Validation rules the item breaks, only filled with VALIDATION_MODE=flag
+    pub issues: Vec<String>,
+    // Comment and share counters, when the teaser shows them
+    pub engagement: Option<Engagement>,
+    pub scraped_at: DateTime<Utc>,
+    // Title and description through typography::normalize_item, next to the
+    // raw ones; only filled with ?normalize=true
+    pub normalized: Option<NormalizedText>,
+}
+
+// Query parameters of /api/v2/news
+#[derive(Deserialize)]
+pub struct NewsParams {
+    pub tz: Option<String>,
+    pub locale: Option<String>,
+    pub language: Option<String>,
+    #[serde(default)]
+    pub normalize: bool,
+    // RFC 3339 instant: serve the archived snapshot nearest to it
+    pub at: Option<String>,
+    // page, prominence, published_at, title or score
+    pub sort: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct NewsResponseV2 {
+    pub api_version: u32,
+    pub scraped_at: DateTime<Utc>,
+    pub count: usize,
+    pub news: Vec<NewsItemV2>,
+    pub error: Option<String>,
+    pub stale: bool,
+    // An archived snapshot served for ?at=, taken at scraped_at
+    pub snapshot: bool,
+    // How the scrape was produced; null for snapshots
+    pub provenance: Option<Provenance>,
+    // The bundled sample homepage rather than a scrape, see DEMO_MODE
+    pub demo: bool,
+}
+
+impl NewsResponseV2 {
+    pub fn from_v1(response: &NewsResponse, config: &Config) -> NewsResponseV2 {
+        let editions = local::editions(&config.local_editions);
+        let news: Vec<NewsItemV2> = response
+            .news
+            .iter()
+            .map(|item| {
+                NewsItemV2::from_v1(item, response.scraped_at, &config.gazetteer, &editions)
+            })
+            .collect();
+        NewsResponseV2 {
+            api_version: 2,
+            scraped_at: response.scraped_at,
+            count: news.len(),
+            news,
+            error: response.error.clone(),
+            snapshot: response.snapshot,
+            stale: response.stale,
+            provenance: response.provenance.clone(),
+            demo: response.demo,
+        }
+    }
+
+    // Helper function to run the items through the typography normalization
+    pub fn normalize(&mut self) {
+        for item in &mut self.news {
+            item.normalized = Some(typography::normalize_item(&item.title, &item.description));
+        }
+    }
+}
+
+impl NewsItemV2 {
+    pub fn from_v1(
+        item: &NewsItem,
+        scraped_at: DateTime<Utc>,
+        gazetteer: &Gazetteer,
+        editions: &[Edition],
+    ) -> NewsItemV2 {
+        let (categories, published_on) = link_metadata(&item.link);
+        NewsItemV2 {
+            id: item_id(&item.link),
+            title: item.title.clone(),
+            description: item.description.clone(),
+            link: item.link.clone(),
+            image_url: item.image_url.clone(),
+            image: item.image.clone(),
+            categories,
+            published_on,
+            language: language::detect(item),
+            city: local::city_of_link(&item.link, editions),
+            prominence_score: item.placement.score(),
+            entities: gazetteer.extract(&format!("{}\n{}", item.title, item.description)),
+            issues: item.issues.clone(),
+            engagement: item.engagement,
+            scraped_at,
+            normalized: None,
+        }
+    }
+}
+
+// Helper function to derive an item id from its link. FNV-1a rather than the
+// std hasher, whose output may change between Rust releases
+pub fn item_id(link: &str) -> String {
+    let canonical = link.split(['?', '#']).next().unwrap_or(link);
+    let hash = canonical.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+// Helper function to read the categories and publication day out of a
+// corriere.it link such as https://milano.corriere.it/notizie/cronaca/24_maggio_01/metro.shtml
+pub fn link_metadata(link: &str) -> (Vec<String>, Option<NaiveDate>) {
+    let Ok(url) = Url::parse(link) else {
+        return (vec![], None);
+    };
+
+    let mut categories = Vec::new();
+    // Local editions live on their own subdomain
+    if let Some(edition) = url
+        .host_str()
+        .and_then(|host| host.strip_suffix(".corriere.it"))
+        .filter(|edition| *edition != "www" && !edition.contains('.'))
+    {
+        categories.push(edition.to_string());
+    }
+
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.filter(|segment| !segment.is_empty()).collect())
+        .unwrap_or_default();
+    let mut published_on = None;
+    // The last segment is the article itself
+    for segment in segments.iter().take(segments.len().saturating_sub(1)) {
+        if let Some(day) = dates::parse_path_date(segment) {
+            published_on = Some(day);
+            break;
+        }
+        if *segment != "notizie" {
+            categories.push(segment.to_ascii_lowercase());
+        }
+    }
+    (categories, published_on)
+}
+
+// Helper function to answer a v2 request with an error: a problem document
+// for clients that ask for one, like v1, and otherwise the v2 shape with
+// its `error` field, which the versioning policy keeps
+fn error_response(state: &AppState, headers: &HeaderMap, problem: Problem) -> Response {
+    if problem::is_requested(headers) {
+        return with_version(problem.respond(Lang::from_headers(headers)), 2);
+    }
+    let error_message = match &problem.reason {
+        Some(reason) => reason.clone(),
+        None => problem.detail(Lang::English).to_string(),
+    };
+    let response = NewsResponseV2::from_v1(
+        &crate::create_error_response(error_message).0,
+        &state.config,
+    );
+    with_version((problem.kind.status(), Json(response)).into_response(), 2)
+}
+
+// The homepage news in the v2 shape. JSON only; timestamps honour ?tz= and
+// ?locale= like the v1 endpoint
+pub async fn news_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<NewsParams>,
+) -> Response {
+    let dates = match DateFormat::parse(params.tz.as_deref(), params.locale.as_deref()) {
+        Ok(dates) => dates,
+        Err(error) => return error_response(&state, &headers, error.into()),
+    };
+
+    let language = match params.language.as_deref().map(language::parse_language) {
+        Some(Ok(language)) => Some(language),
+        Some(Err(error_message)) => {
+            let problem = Problem::invalid_parameter("language", error_message);
+            return error_response(&state, &headers, problem);
+        }
+        None => None,
+    };
+
+    let at = match params.at.as_deref().map(archive::parse_instant) {
+        Some(Ok(at)) => Some(at),
+        Some(Err(error_message)) => {
+            let problem = Problem::invalid_parameter("at", error_message);
+            return error_response(&state, &headers, problem);
+        }
+        None => None,
+    };
+
+    let sort = match params.sort.as_deref().map(scoring::parse_sort) {
+        Some(Ok(sort)) => sort,
+        Some(Err(error_message)) => {
+            let problem = Problem::invalid_parameter("sort", error_message);
+            return error_response(&state, &headers, problem);
+        }
+        None => SortOrder::Page,
+    };
+
+    let mut response = match at {
+        Some(at) => match archive::snapshot_news(&state, at).await {
+            Ok(response) => response,
+            Err(problem) => return error_response(&state, &headers, problem),
+        },
+        None => match crate::serve_news(&state).await {
+            Ok(cached) => cached.response,
+            Err(response) => {
+                let problem = Problem::upstream_unavailable(response.error.unwrap_or_default());
+                return error_response(&state, &headers, problem);
+            }
+        },
+    };
+
+    scoring::sort(
+        &mut response.news,
+        sort,
+        response.scraped_at,
+        &state.config.scoring,
+    );
+    let mut response = NewsResponseV2::from_v1(&response, &state.config);
+    let scraped_at = response.scraped_at;
+    if let Some(language) = language {
+        response.news.retain(|item| item.language == language);
+        response.count = response.news.len();
+    }
+    if params.normalize {
+        response.normalize();
+    }
+    let mut body = serde_json::to_value(response).unwrap_or_default();
+    if let Value::Object(object) = &mut body {
+        dates.apply(object, "scraped_at");
+        if let Some(Value::Array(news)) = object.get_mut("news") {
+            for item in news.iter_mut().filter_map(Value::as_object_mut) {
+                dates.apply(item, "scraped_at");
+            }
+        }
+    }
+    state.config.budget.apply(&mut body);
+    let mut rendered = Json(body).into_response();
+    if at.is_some() {
+        if let Ok(value) = HeaderValue::from_str(&scraped_at.to_rfc3339()) {
+            rendered.headers_mut().insert(crate::SNAPSHOT_AT, value);
+        }
+    }
+    with_version(rendered, 2)
+}
+
+#[derive(Deserialize)]
+pub struct TopParams {
+    pub n: Option<usize>,
+    #[serde(default)]
+    pub normalize: bool,
+}
+
+// The n (default 5) most prominent homepage stories, most prominent first.
+// A newer endpoint, so it only exists in the v2 shape
+pub async fn top_handler(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<TopParams>,
+) -> Response {
+    let n = params.n.unwrap_or(5);
+    if n == 0 || n > extract::HOMEPAGE_LIMIT {
+        let problem = Problem::invalid_parameter(
+            "n",
+            format!("n must be between 1 and {}", extract::HOMEPAGE_LIMIT),
+        );
+        return error_response(&state, &headers, problem);
+    }
+
+    let response = match crate::serve_news(&state).await {
+        Ok(cached) => cached.response,
+        Err(response) => {
+            let problem = Problem::upstream_unavailable(response.error.unwrap_or_default());
+            return error_response(&state, &headers, problem);
+        }
+    };
+
+    let mut response = NewsResponseV2::from_v1(&response, &state.config);
+    // Stable, so equal scores keep their page order
+    response
+        .news
+        .sort_by(|a, b| b.prominence_score.total_cmp(&a.prominence_score));
+    response.news.truncate(n);
+    response.count = response.news.len();
+    if params.normalize {
+        response.normalize();
+    }
+    with_version(Json(response).into_response(), 2)
+}
@@ -0,0 +1,17 @@
+use crate::{AppState, NewsItem};
+
+// The rules live in corriere_core, so the Python bindings drop the same items
+pub use corriere_core::validate::*;
+
+// Helper function to run the checks over freshly extracted items from
+// `page_url`, dropping or flagging the bad ones as VALIDATION_MODE says.
+// Every broken rule is counted in corriere_validation_failures_total
+pub fn apply(state: &AppState, source: &str, page_url: &str, news: Vec<NewsItem>) -> Vec<NewsItem> {
+    let rules = Rules::for_page(&state.config.validation_domains, page_url);
+    filter(news, state.config.validation_mode, &rules, |rule| {
+        state.metrics.increment(
+            "corriere_validation_failures_total",
+            &[("source", source), ("rule", rule)],
+        );
+    })
+}
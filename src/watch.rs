@@ -0,0 +1,480 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::StreamExt;
+
+use crate::article::{self, ArticleDetail};
+use crate::json_file;
+use crate::lease;
+use crate::request_id;
+use crate::subscriptions::{random_string, TokenParams};
+use crate::url_safety::{self, UrlPolicy};
+use crate::AppState;
+
+// Change events kept for SSE clients that fall behind
+const EVENT_BUFFER: usize = 64;
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// The parts of an article compared between checks
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct ArticleVersion {
+    pub title: String,
+    pub body: String,
+    pub corrections: Vec<String>,
+}
+
+impl From<&ArticleDetail> for ArticleVersion {
+    fn from(detail: &ArticleDetail) -> Self {
+        ArticleVersion {
+            title: detail.title.clone(),
+            body: detail.body.clone(),
+            corrections: detail.corrections.clone(),
+        }
+    }
+}
+
+// An article URL being watched for edits. The token is returned once on
+// creation and is needed to read, delete or follow the watch
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Watch {
+    pub id: String,
+    pub url: String,
+    // Receives a POST with every change, besides the SSE stream
+    pub notify_url: Option<String>,
+    pub token: String,
+    pub created_at: DateTime<Utc>,
+    pub checked_at: Option<DateTime<Utc>>,
+    // What the article looked like at the last successful check
+    pub version: Option<ArticleVersion>,
+}
+
+#[derive(Serialize)]
+pub struct WatchView {
+    pub id: String,
+    pub url: String,
+    pub notify_url: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub checked_at: Option<DateTime<Utc>>,
+    pub title: Option<String>,
+}
+
+impl From<&Watch> for WatchView {
+    fn from(watch: &Watch) -> Self {
+        WatchView {
+            id: watch.id.clone(),
+            url: watch.url.clone(),
+            notify_url: watch.notify_url.clone(),
+            created_at: watch.created_at,
+            checked_at: watch.checked_at,
+            title: watch.version.as_ref().map(|version| version.title.clone()),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct WatchResponse {
+    pub watch: Option<WatchView>,
+    // Only set when the watch was just created
+    pub token: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct WatchRequest {
+    url: String,
+    notify_url: Option<String>,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: String,
+    pub after: String,
+}
+
+// Sent to SSE clients and the notify URL when a watched article changes
+#[derive(Serialize, Clone, Debug)]
+pub struct WatchEvent {
+    pub watch_id: String,
+    pub url: String,
+    pub detected_at: DateTime<Utc>,
+    pub changes: Vec<FieldChange>,
+}
+
+// Watched articles, persisted as a JSON file under DATA_DIR, along with the
+// channel their change events are published on
+pub struct WatchStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+    events: broadcast::Sender<WatchEvent>,
+}
+
+impl WatchStore {
+    pub fn new(path: PathBuf) -> WatchStore {
+        WatchStore {
+            path,
+            lock: Mutex::new(()),
+            events: broadcast::channel(EVENT_BUFFER).0,
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<Watch>, String> {
+        let _lock = self.lock.lock().unwrap();
+        self.load()
+    }
+
+    // Fails with 409 when the article already notifies the same webhook,
+    // 503 when `max` watches already exist and 500 when they can't be saved
+    pub fn create(
+        &self,
+        url: &str,
+        notify_url: Option<String>,
+        max: usize,
+    ) -> Result<Watch, (StatusCode, String)> {
+        let _lock = self.lock.lock().unwrap();
+        let internal = |error_message| (StatusCode::INTERNAL_SERVER_ERROR, error_message);
+        let mut watches = self.load().map_err(internal)?;
+        if notify_url.is_some()
+            && watches
+                .iter()
+                .any(|watch| watch.url == url && watch.notify_url == notify_url)
+        {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("{} is already watched for this notify_url", url),
+            ));
+        }
+        if watches.len() >= max {
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("At most {} articles can be watched", max),
+            ));
+        }
+
+        let watch = Watch {
+            id: random_string(12),
+            url: url.to_string(),
+            notify_url,
+            token: random_string(32),
+            created_at: Utc::now(),
+            checked_at: None,
+            version: None,
+        };
+        watches.push(watch.clone());
+        json_file::save(&self.path, &watches).map_err(internal)?;
+        Ok(watch)
+    }
+
+    pub fn get(&self, id: &str, token: &str) -> Result<Option<Watch>, String> {
+        let _lock = self.lock.lock().unwrap();
+        Ok(self
+            .load()?
+            .into_iter()
+            .find(|watch| watch.id == id && watch.token == token))
+    }
+
+    // Returns whether a matching watch was removed
+    pub fn delete(&self, id: &str, token: &str) -> Result<bool, String> {
+        let _lock = self.lock.lock().unwrap();
+        let mut watches = self.load()?;
+
+        let count = watches.len();
+        watches.retain(|watch| !(watch.id == id && watch.token == token));
+        if watches.len() == count {
+            return Ok(false);
+        }
+
+        json_file::save(&self.path, &watches)?;
+        Ok(true)
+    }
+
+    // Helper function to store the version seen by a check, returning the
+    // one it replaces. None on the first check or when the watch is gone
+    pub fn record(
+        &self,
+        id: &str,
+        version: ArticleVersion,
+    ) -> Result<Option<ArticleVersion>, String> {
+        let _lock = self.lock.lock().unwrap();
+        let mut watches = self.load()?;
+
+        let Some(watch) = watches.iter_mut().find(|watch| watch.id == id) else {
+            return Ok(None);
+        };
+        watch.checked_at = Some(Utc::now());
+        let previous = watch.version.replace(version);
+
+        json_file::save(&self.path, &watches)?;
+        Ok(previous)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<WatchEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn publish(&self, event: WatchEvent) {
+        // Sending only fails when nobody is listening, which is fine
+        let _ = self.events.send(event);
+    }
+
+    fn load(&self) -> Result<Vec<Watch>, String> {
+        Ok(json_file::load(&self.path)?.unwrap_or_default())
+    }
+}
+
+fn watch_response(
+    status: StatusCode,
+    watch: Option<&Watch>,
+    token: Option<String>,
+    error: Option<String>,
+) -> (StatusCode, Json<WatchResponse>) {
+    (
+        status,
+        Json(WatchResponse {
+            watch: watch.map(WatchView::from),
+            token,
+            error,
+        }),
+    )
+}
+
+fn error_response(status: StatusCode, error_message: String) -> (StatusCode, Json<WatchResponse>) {
+    watch_response(status, None, None, Some(error_message))
+}
+
+fn not_found() -> (StatusCode, Json<WatchResponse>) {
+    error_response(
+        StatusCode::NOT_FOUND,
+        "No watch with this id and token".to_string(),
+    )
+}
+
+// The response to give when article watching is off
+fn disabled(state: &AppState) -> Option<(StatusCode, Json<WatchResponse>)> {
+    (!state.config.watch_enabled).then(|| {
+        error_response(
+            StatusCode::NOT_FOUND,
+            "Article watching is not enabled".to_string(),
+        )
+    })
+}
+
+// Helper function to check the article is on a host we may scrape and the
+// notify URL, if any, is a web address outside the internal network
+async fn check_request(state: &AppState, request: &WatchRequest) -> Result<(), String> {
+    let policy = UrlPolicy::from_config(&state.config);
+    url_safety::check(&policy, &request.url)?;
+    if let Some(notify_url) = &request.notify_url {
+        url_safety::check_callback(&policy, notify_url).await?;
+    }
+    Ok(())
+}
+
+pub async fn create_handler(
+    State(state): State<AppState>,
+    Json(request): Json<WatchRequest>,
+) -> (StatusCode, Json<WatchResponse>) {
+    if let Some(response) = disabled(&state) {
+        return response;
+    }
+    if let Err(error_message) = check_request(&state, &request).await {
+        return error_response(StatusCode::BAD_REQUEST, error_message);
+    }
+
+    match state
+        .watches
+        .create(&request.url, request.notify_url, state.config.watch_max)
+    {
+        Ok(watch) => watch_response(
+            StatusCode::CREATED,
+            Some(&watch),
+            Some(watch.token.clone()),
+            None,
+        ),
+        Err((status, error_message)) => error_response(status, error_message),
+    }
+}
+
+pub async fn get_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<TokenParams>,
+) -> (StatusCode, Json<WatchResponse>) {
+    if let Some(response) = disabled(&state) {
+        return response;
+    }
+
+    match state
+        .watches
+        .get(&id, params.token.as_deref().unwrap_or(""))
+    {
+        Ok(Some(watch)) => watch_response(StatusCode::OK, Some(&watch), None, None),
+        Ok(None) => not_found(),
+        Err(error_message) => error_response(StatusCode::INTERNAL_SERVER_ERROR, error_message),
+    }
+}
+
+pub async fn delete_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<TokenParams>,
+) -> (StatusCode, Json<WatchResponse>) {
+    if let Some(response) = disabled(&state) {
+        return response;
+    }
+
+    match state
+        .watches
+        .delete(&id, params.token.as_deref().unwrap_or(""))
+    {
+        Ok(true) => watch_response(StatusCode::OK, None, None, None),
+        Ok(false) => not_found(),
+        Err(error_message) => error_response(StatusCode::INTERNAL_SERVER_ERROR, error_message),
+    }
+}
+
+// Server-sent events with the changes of one watched article, as they are
+// detected. Events missed while disconnected are not replayed
+pub async fn events_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<TokenParams>,
+) -> Response {
+    if let Some(response) = disabled(&state) {
+        return response.into_response();
+    }
+    match state
+        .watches
+        .get(&id, params.token.as_deref().unwrap_or(""))
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => return not_found().into_response(),
+        Err(error_message) => {
+            return error_response(StatusCode::INTERNAL_SERVER_ERROR, error_message).into_response()
+        }
+    }
+
+    // A client that lags behind skips the events it missed
+    let stream = BroadcastStream::new(state.watches.subscribe()).filter_map(move |event| {
+        let event = event.ok().filter(|event| event.watch_id == id)?;
+        Some(Event::default().event("change").json_data(&event))
+    });
+    Sse::new(stream)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+// Helper function to list what differs between two versions of an article
+pub fn compare(before: &ArticleVersion, after: &ArticleVersion) -> Vec<FieldChange> {
+    let fields = [
+        ("title", before.title.clone(), after.title.clone()),
+        ("body", before.body.clone(), after.body.clone()),
+        (
+            "corrections",
+            before.corrections.join("\n\n"),
+            after.corrections.join("\n\n"),
+        ),
+    ];
+    fields
+        .into_iter()
+        .filter(|(_, before, after)| before != after)
+        .map(|(field, before, after)| FieldChange {
+            field: field.to_string(),
+            before,
+            after,
+        })
+        .collect()
+}
+
+// Helper function to re-fetch every watched article once, publishing an
+// event for each one that changed. Returns the events
+pub async fn check_all(state: &AppState) -> Result<Vec<WatchEvent>, String> {
+    let request_id = request_id::current().unwrap_or_else(request_id::generate);
+
+    let mut events = Vec::new();
+    for watch in state.watches.list()? {
+        let detail = match article::fetch_article(state, &watch.url).await {
+            Ok(detail) => detail,
+            Err(error_message) => {
+                eprintln!(
+                    "[{}] Watch: failed to fetch {}: {}",
+                    request_id, watch.url, error_message
+                );
+                state
+                    .metrics
+                    .increment("corriere_watch_checks_total", &[("result", "failed")]);
+                continue;
+            }
+        };
+        state
+            .metrics
+            .increment("corriere_watch_checks_total", &[("result", "ok")]);
+
+        let version = ArticleVersion::from(&detail);
+        let Some(previous) = state.watches.record(&watch.id, version.clone())? else {
+            continue;
+        };
+        let changes = compare(&previous, &version);
+        if changes.is_empty() {
+            continue;
+        }
+
+        let event = WatchEvent {
+            watch_id: watch.id.clone(),
+            url: watch.url.clone(),
+            detected_at: Utc::now(),
+            changes,
+        };
+        state.metrics.increment("corriere_watch_changes_total", &[]);
+        if let Some(notify_url) = &watch.notify_url {
+            let result = state
+                .callback_client
+                .post(notify_url)
+                .timeout(NOTIFY_TIMEOUT)
+                .header(request_id::HEADER, &request_id)
+                .json(&event)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            if let Err(e) = result {
+                eprintln!(
+                    "[{}] Watch: notification to {} failed: {}",
+                    request_id, notify_url, e
+                );
+            }
+        }
+        state.watches.publish(event.clone());
+        events.push(event);
+    }
+    Ok(events)
+}
+
+// Re-fetches the watched articles every WATCH_POLL_SECS, for as long as the
+// server runs
+pub async fn run(state: AppState) {
+    let every = Duration::from_secs(state.config.watch_poll_secs.max(1));
+    let mut interval = tokio::time::interval(every);
+
+    loop {
+        interval.tick().await;
+        // Settings changed by a reload apply from this run on
+        let state = state.current();
+        if !lease::should_run(&state, "watch", lease::lease_ttl(every)).await {
+            continue;
+        }
+
+        let request_id = request_id::generate();
+        if let Err(error_message) = request_id::scope(request_id.clone(), check_all(&state)).await {
+            eprintln!("[{}] Watch check failed: {}", request_id, error_message);
+        }
+    }
+}
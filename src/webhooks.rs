@@ -0,0 +1,582 @@
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config::Config;
+use crate::delivery::{self, Message, Origin};
+use crate::json_file;
+use crate::lease;
+use crate::request_id;
+use crate::sent_log::{self, SentLog};
+use crate::subscriptions::{random_string, TokenParams};
+use crate::url_safety::{self, UrlPolicy};
+use crate::{AppState, NewsItem};
+
+// Items per delivery. Slack allows 50 blocks per message (three per item
+// here); Discord allows 10 embeds and 5 rows of buttons
+const SLACK_ITEMS_PER_MESSAGE: usize = 10;
+const DISCORD_ITEMS_PER_MESSAGE: usize = 5;
+// Discord's limit for button labels
+const DISCORD_LABEL_LIMIT: usize = 80;
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// How the new items are laid out in the POST body
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    // The items as /api/news returns them
+    #[default]
+    Json,
+    // Block Kit message for Slack incoming webhooks
+    Slack,
+    // Embeds with link buttons for Discord channel webhooks
+    Discord,
+}
+
+// A registered webhook. As with subscriptions, the token is returned once on
+// creation and is needed to read, change or delete the webhook
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub format: WebhookFormat,
+    pub token: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct WebhookView {
+    pub id: String,
+    pub url: String,
+    pub format: WebhookFormat,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<&Webhook> for WebhookView {
+    fn from(webhook: &Webhook) -> Self {
+        WebhookView {
+            id: webhook.id.clone(),
+            url: webhook.url.clone(),
+            format: webhook.format,
+            created_at: webhook.created_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct WebhookResponse {
+    pub webhook: Option<WebhookView>,
+    // Only set when the webhook was just registered
+    pub token: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct WebhookRequest {
+    url: String,
+    #[serde(default)]
+    format: WebhookFormat,
+}
+
+// Registered webhooks, persisted as a JSON file under DATA_DIR
+pub struct WebhookStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl WebhookStore {
+    pub fn new(path: PathBuf) -> WebhookStore {
+        WebhookStore {
+            path,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn list(&self) -> Result<Vec<Webhook>, String> {
+        let _lock = self.lock.lock().unwrap();
+        self.load()
+    }
+
+    pub fn create(&self, url: &str, format: WebhookFormat) -> Result<Webhook, String> {
+        let _lock = self.lock.lock().unwrap();
+        let mut webhooks = self.load()?;
+
+        let webhook = Webhook {
+            id: random_string(12),
+            url: url.to_string(),
+            format,
+            token: random_string(32),
+            created_at: Utc::now(),
+        };
+        webhooks.push(webhook.clone());
+        json_file::save(&self.path, &webhooks)?;
+        Ok(webhook)
+    }
+
+    pub fn get(&self, id: &str, token: &str) -> Result<Option<Webhook>, String> {
+        let _lock = self.lock.lock().unwrap();
+        Ok(self
+            .load()?
+            .into_iter()
+            .find(|webhook| webhook.id == id && webhook.token == token))
+    }
+
+    pub fn update(
+        &self,
+        id: &str,
+        token: &str,
+        url: &str,
+        format: WebhookFormat,
+    ) -> Result<Option<Webhook>, String> {
+        let _lock = self.lock.lock().unwrap();
+        let mut webhooks = self.load()?;
+
+        let Some(webhook) = webhooks
+            .iter_mut()
+            .find(|webhook| webhook.id == id && webhook.token == token)
+        else {
+            return Ok(None);
+        };
+        webhook.url = url.to_string();
+        webhook.format = format;
+        let updated = webhook.clone();
+
+        json_file::save(&self.path, &webhooks)?;
+        Ok(Some(updated))
+    }
+
+    // Returns whether a matching webhook was removed
+    pub fn delete(&self, id: &str, token: &str) -> Result<bool, String> {
+        let _lock = self.lock.lock().unwrap();
+        let mut webhooks = self.load()?;
+
+        let count = webhooks.len();
+        webhooks.retain(|webhook| !(webhook.id == id && webhook.token == token));
+        if webhooks.len() == count {
+            return Ok(false);
+        }
+
+        json_file::save(&self.path, &webhooks)?;
+        Ok(true)
+    }
+
+    fn load(&self) -> Result<Vec<Webhook>, String> {
+        Ok(json_file::load(&self.path)?.unwrap_or_default())
+    }
+}
+
+fn webhook_response(
+    status: StatusCode,
+    webhook: Option<&Webhook>,
+    token: Option<String>,
+    error: Option<String>,
+) -> (StatusCode, Json<WebhookResponse>) {
+    (
+        status,
+        Json(WebhookResponse {
+            webhook: webhook.map(WebhookView::from),
+            token,
+            error,
+        }),
+    )
+}
+
+fn error_response(
+    status: StatusCode,
+    error_message: String,
+) -> (StatusCode, Json<WebhookResponse>) {
+    webhook_response(status, None, None, Some(error_message))
+}
+
+fn not_found() -> (StatusCode, Json<WebhookResponse>) {
+    error_response(
+        StatusCode::NOT_FOUND,
+        "No webhook with this id and token".to_string(),
+    )
+}
+
+// Helper function to check webhooks are on and the target URL is usable
+async fn check_request(
+    state: &AppState,
+    url: Option<&str>,
+) -> Result<(), (StatusCode, Json<WebhookResponse>)> {
+    if !state.config.webhooks_enabled {
+        return Err(error_response(
+            StatusCode::NOT_FOUND,
+            "Webhooks are not enabled".to_string(),
+        ));
+    }
+
+    // Anyone may register a webhook, so it mustn't lead into our network
+    if let Some(url) = url {
+        let policy = UrlPolicy::from_config(&state.config);
+        if let Err(error_message) = url_safety::check_callback(&policy, url).await {
+            return Err(error_response(StatusCode::BAD_REQUEST, error_message));
+        }
+    }
+    Ok(())
+}
+
+pub async fn create_handler(
+    State(state): State<AppState>,
+    Json(request): Json<WebhookRequest>,
+) -> (StatusCode, Json<WebhookResponse>) {
+    if let Err(response) = check_request(&state, Some(&request.url)).await {
+        return response;
+    }
+
+    match state.webhooks.create(&request.url, request.format) {
+        Ok(webhook) => webhook_response(
+            StatusCode::CREATED,
+            Some(&webhook),
+            Some(webhook.token.clone()),
+            None,
+        ),
+        Err(error_message) => error_response(StatusCode::INTERNAL_SERVER_ERROR, error_message),
+    }
+}
+
+pub async fn get_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<TokenParams>,
+) -> (StatusCode, Json<WebhookResponse>) {
+    if let Err(response) = check_request(&state, None).await {
+        return response;
+    }
+
+    match state
+        .webhooks
+        .get(&id, params.token.as_deref().unwrap_or(""))
+    {
+        Ok(Some(webhook)) => webhook_response(StatusCode::OK, Some(&webhook), None, None),
+        Ok(None) => not_found(),
+        Err(error_message) => error_response(StatusCode::INTERNAL_SERVER_ERROR, error_message),
+    }
+}
+
+pub async fn update_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<TokenParams>,
+    Json(request): Json<WebhookRequest>,
+) -> (StatusCode, Json<WebhookResponse>) {
+    if let Err(response) = check_request(&state, Some(&request.url)).await {
+        return response;
+    }
+
+    match state.webhooks.update(
+        &id,
+        params.token.as_deref().unwrap_or(""),
+        &request.url,
+        request.format,
+    ) {
+        Ok(Some(webhook)) => webhook_response(StatusCode::OK, Some(&webhook), None, None),
+        Ok(None) => not_found(),
+        Err(error_message) => error_response(StatusCode::INTERNAL_SERVER_ERROR, error_message),
+    }
+}
+
+pub async fn delete_handler(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<TokenParams>,
+) -> (StatusCode, Json<WebhookResponse>) {
+    if let Err(response) = check_request(&state, None).await {
+        return response;
+    }
+
+    match state
+        .webhooks
+        .delete(&id, params.token.as_deref().unwrap_or(""))
+    {
+        Ok(true) => webhook_response(StatusCode::OK, None, None, None),
+        Ok(false) => not_found(),
+        Err(error_message) => error_response(StatusCode::INTERNAL_SERVER_ERROR, error_message),
+    }
+}
+
+// Helper function to build the request bodies announcing `items` in the
+// given format. Chat formats are split into several messages when the
+// platform limits how much fits in one
+pub fn payloads(format: WebhookFormat, items: &[NewsItem]) -> Vec<Value> {
+    batches(format, items)
+        .into_iter()
+        .map(|(_, payload)| payload)
+        .collect()
+}
+
+// Helper function pairing each payload with the items it carries
+fn batches(format: WebhookFormat, items: &[NewsItem]) -> Vec<(&[NewsItem], Value)> {
+    match format {
+        WebhookFormat::Json => vec![(
+            items,
+            json!({
+                "event": "new_articles",
+                "items": items,
+            }),
+        )],
+        WebhookFormat::Slack => items
+            .chunks(SLACK_ITEMS_PER_MESSAGE)
+            .map(|chunk| (chunk, slack_message(chunk)))
+            .collect(),
+        WebhookFormat::Discord => items
+            .chunks(DISCORD_ITEMS_PER_MESSAGE)
+            .map(|chunk| (chunk, discord_message(chunk)))
+            .collect(),
+    }
+}
+
+fn slack_message(items: &[NewsItem]) -> Value {
+    let mut blocks = Vec::new();
+    for item in items {
+        let mut text = format!("*<{}|{}>*", item.link, escape_slack(&item.title));
+        if !item.description.is_empty() {
+            text.push_str(&format!("\n{}", escape_slack(&item.description)));
+        }
+
+        let mut section = json!({
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": text },
+        });
+        if let Some(image_url) = &item.image_url {
+            section["accessory"] = json!({
+                "type": "image",
+                "image_url": image_url,
+                "alt_text": item.title,
+            });
+        }
+        blocks.push(section);
+        blocks.push(json!({
+            "type": "actions",
+            "elements": [{
+                "type": "button",
+                "text": { "type": "plain_text", "text": "Leggi l'articolo" },
+                "url": item.link,
+            }],
+        }));
+        blocks.push(json!({ "type": "divider" }));
+    }
+
+    // The text is what notifications and clients without Block Kit show
+    let titles: Vec<&str> = items.iter().map(|item| item.title.as_str()).collect();
+    json!({
+        "text": escape_slack(&titles.join(" · ")),
+        "blocks": blocks,
+    })
+}
+
+// Slack's mrkdwn only needs these three escaped
+fn escape_slack(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn discord_message(items: &[NewsItem]) -> Value {
+    let embeds: Vec<Value> = items
+        .iter()
+        .map(|item| {
+            let mut embed = json!({
+                "title": item.title,
+                "description": item.description,
+                "url": item.link,
+                "color": 0x0a3a66,
+            });
+            if let Some(image_url) = &item.image_url {
+                embed["thumbnail"] = json!({ "url": image_url });
+            }
+            embed
+        })
+        .collect();
+
+    // One row with a link button per item
+    let buttons: Vec<Value> = items
+        .iter()
+        .map(|item| {
+            json!({
+                "type": 2,
+                "style": 5,
+                "label": item.title.chars().take(DISCORD_LABEL_LIMIT).collect::<String>(),
+                "url": item.link,
+            })
+        })
+        .collect();
+
+    json!({
+        "embeds": embeds,
+        "components": [{ "type": 1, "components": buttons }],
+    })
+}
+
+// Discord ignores components sent to a plain channel webhook unless asked to
+// respect them
+fn delivery_url(webhook: &Webhook) -> String {
+    match webhook.format {
+        WebhookFormat::Discord => {
+            let separator = if webhook.url.contains('?') { '&' } else { '?' };
+            format!("{}{}with_components=true", webhook.url, separator)
+        }
+        _ => webhook.url.clone(),
+    }
+}
+
+// Announces new homepage items to every registered webhook
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    sent: SentLog,
+}
+
+impl WebhookNotifier {
+    pub fn new(config: &Config) -> Result<WebhookNotifier, String> {
+        Ok(WebhookNotifier {
+            client: url_safety::build_callback_client(
+                &UrlPolicy::from_config(config),
+                DELIVERY_TIMEOUT,
+            ),
+            sent: SentLog::load(config, "webhooks")?,
+        })
+    }
+
+    // Helper function to deliver the items that weren't announced before.
+    // The first run only records the current items. Returns how many
+    // deliveries succeeded
+    pub async fn notify(&mut self, state: &AppState, news: &[NewsItem]) -> Result<usize, String> {
+        if !self.sent.is_stored() {
+            for item in news {
+                self.sent.push(item.link.clone());
+            }
+            self.sent.save()?;
+            return Ok(0);
+        }
+
+        let fresh: Vec<NewsItem> = news
+            .iter()
+            .filter(|item| !self.sent.contains(&item.link))
+            .cloned()
+            .collect();
+        if fresh.is_empty() {
+            return Ok(0);
+        }
+
+        // Receivers see the id of the run that found the items, which is also
+        // on our log lines about it
+        let request_id = request_id::current().unwrap_or_else(request_id::generate);
+        let webhooks = state.webhooks.list()?;
+        let mut delivered = 0;
+        // Links some webhook took, and the deliveries that failed
+        let mut accepted = HashSet::new();
+        let mut failed = Vec::new();
+        for webhook in &webhooks {
+            let format = match webhook.format {
+                WebhookFormat::Json => "json",
+                WebhookFormat::Slack => "slack",
+                WebhookFormat::Discord => "discord",
+            };
+            let url = delivery_url(webhook);
+            for (items, payload) in batches(webhook.format, &fresh) {
+                let result = self
+                    .client
+                    .post(&url)
+                    .header(request_id::HEADER, &request_id)
+                    .json(&payload)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status());
+                match result {
+                    Ok(_) => {
+                        delivered += 1;
+                        accepted.extend(items.iter().map(|item| item.link.clone()));
+                        state.metrics.increment(
+                            "corriere_webhook_deliveries_total",
+                            &[("format", format), ("result", "sent")],
+                        );
+                    }
+                    Err(e) => {
+                        eprintln!(
+                            "[{}] Webhook delivery to {} failed: {}",
+                            request_id, webhook.url, e
+                        );
+                        state.metrics.increment(
+                            "corriere_webhook_deliveries_total",
+                            &[("format", format), ("result", "failed")],
+                        );
+                        let message = Message::Webhook {
+                            url: url.clone(),
+                            request_id: request_id.clone(),
+                            payload,
+                        };
+                        let origin = Origin::Webhook(webhook.id.clone());
+                        failed.push((items, message, origin, e.to_string()));
+                    }
+                }
+            }
+        }
+
+        // Items no webhook took stay unsent and go out again on the next
+        // poll; a failed delivery of items others took is retried on its own
+        for (items, message, origin, error_message) in failed {
+            if items.iter().any(|item| accepted.contains(&item.link)) {
+                delivery::enqueue(state, message, Some(origin), &error_message);
+            }
+        }
+        for item in &fresh {
+            if webhooks.is_empty() || accepted.contains(&item.link) {
+                self.sent.push(item.link.clone());
+            }
+        }
+        self.sent.save()?;
+        Ok(delivered)
+    }
+}
+
+// Checks the homepage every WEBHOOK_POLL_SECS and announces what's new, for
+// as long as the server runs
+pub async fn run(state: AppState, mut notifier: WebhookNotifier) {
+    let every = Duration::from_secs(state.config.webhook_poll_secs.max(1));
+    let mut interval = tokio::time::interval(every);
+
+    loop {
+        interval.tick().await;
+        // Settings changed by a reload apply from this run on
+        let state = state.current();
+        let ttl = lease::lease_ttl(every);
+        if !sent_log::should_post(&state, "webhooks", ttl, &mut [&mut notifier.sent]).await {
+            continue;
+        }
+
+        request_id::scope(request_id::generate(), poll(&state, &mut notifier)).await;
+    }
+}
+
+// Helper function for one check of the homepage, run under its own request id
+async fn poll(state: &AppState, notifier: &mut WebhookNotifier) {
+    let news = match crate::get_news(state).await {
+        // A stale copy has nothing new to announce
+        Ok(cached) if !cached.response.stale => cached.response.news,
+        Ok(_) => return,
+        Err(response) => {
+            eprintln!(
+                "{}Webhooks: homepage unavailable: {}",
+                request_id::prefix(),
+                response.error.unwrap_or_default()
+            );
+            return;
+        }
+    };
+
+    if let Err(error_message) = notifier.notify(state, &news).await {
+        eprintln!(
+            "{}Webhook notification failed: {}",
+            request_id::prefix(),
+            error_message
+        );
+    }
+}
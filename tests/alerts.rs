@@ -0,0 +1,268 @@
+mod common;
+
+use chrono::Utc;
+use common::{news_item, spawn_app, spawn_state, temp_data_dir, test_config};
+use corriere_scraper::alerts::{self, Alert, AlertNotifier, Channel};
+use corriere_scraper::clusters::SourcedItem;
+use corriere_scraper::config::Config;
+use corriere_scraper::AppState;
+use serde_json::{json, Value};
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn sourced(source: &str, title: &str, link: &str) -> SourcedItem {
+    SourcedItem {
+        source: source.to_string(),
+        item: news_item(title, link),
+    }
+}
+
+fn alert_config(name: &str) -> Config {
+    Config {
+        data_dir: temp_data_dir(name),
+        alert_api_keys: vec!["key-a".to_string(), "key-b".to_string()],
+        alert_max_per_key: 2,
+        ..test_config("http://127.0.0.1:9")
+    }
+}
+
+#[test]
+fn alert_matches_every_filter_that_is_set() {
+    let alert = Alert {
+        id: "a".to_string(),
+        api_key: "key-a".to_string(),
+        token: "t".to_string(),
+        keyword: Some("elezioni".to_string()),
+        category: Some("politica".to_string()),
+        source: None,
+        channel: Channel::Sse,
+        created_at: Utc::now(),
+    };
+
+    assert!(alert.matches(&sourced(
+        "corriere",
+        "Le Elezioni regionali",
+        "https://www.corriere.it/politica/24_ottobre_01/voto.shtml"
+    )));
+    assert!(!alert.matches(&sourced(
+        "corriere",
+        "Le elezioni americane",
+        "https://www.corriere.it/esteri/24_ottobre_01/usa.shtml"
+    )));
+    assert!(!alert.matches(&sourced(
+        "corriere",
+        "Il governo e la manovra",
+        "https://www.corriere.it/politica/24_ottobre_01/manovra.shtml"
+    )));
+}
+
+#[tokio::test]
+async fn alerts_belong_to_their_api_key() {
+    let app = spawn_app(alert_config("alerts-api")).await;
+    let client = reqwest::Client::new();
+
+    let anonymous = client
+        .post(format!("{}/api/alerts", app))
+        .json(&json!({ "keyword": "meteo", "channel": { "type": "sse" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(anonymous.status(), 401);
+
+    let empty = client
+        .post(format!("{}/api/alerts", app))
+        .header("x-api-key", "key-a")
+        .json(&json!({ "keyword": " ", "channel": { "type": "sse" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(empty.status(), 400);
+    let email = client
+        .post(format!("{}/api/alerts", app))
+        .header("x-api-key", "key-a")
+        .json(&json!({
+            "keyword": "meteo",
+            "channel": { "type": "email", "address": "lettore@example.com" },
+        }))
+        .send()
+        .await
+        .unwrap();
+    // No SMTP_URL in this config
+    assert_eq!(email.status(), 400);
+
+    let created: Value = client
+        .post(format!("{}/api/alerts", app))
+        .header("x-api-key", "key-a")
+        .json(&json!({ "keyword": "Meteo", "category": "Cronache", "channel": { "type": "sse" } }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = created["alert"]["id"].as_str().unwrap();
+    assert_eq!(created["alert"]["keyword"], "meteo");
+    assert_eq!(created["alert"]["category"], "cronache");
+    assert!(created["alert"].get("api_key").is_none());
+
+    // The key is only taken from the header
+    let in_query = client
+        .get(format!("{}/api/alerts?api_key=key-a", app))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(in_query.status(), 401);
+    let listed: Value = client
+        .get(format!("{}/api/alerts", app))
+        .header("x-api-key", "key-a")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(listed["alerts"].as_array().unwrap().len(), 1);
+    let other: Value = client
+        .get(format!("{}/api/alerts", app))
+        .header("x-api-key", "key-b")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(other["alerts"].as_array().unwrap().is_empty());
+    let foreign = client
+        .delete(format!("{}/api/alerts/{}", app, id))
+        .header("x-api-key", "key-b")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(foreign.status(), 404);
+
+    let deleted = client
+        .delete(format!("{}/api/alerts/{}", app, id))
+        .header("x-api-key", "key-a")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(deleted.status(), 200);
+}
+
+#[tokio::test]
+async fn new_matching_articles_reach_the_alert_webhook_and_stream() {
+    let receiver = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/alert"))
+        .and(body_partial_json(json!({
+            "event": "alert",
+            "items": [{ "source": "corriere", "title": "Allerta meteo a Milano" }],
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&receiver)
+        .await;
+
+    let state = AppState::new(alert_config("alerts-notify"));
+    let request = serde_json::from_value(json!({
+        "keyword": "meteo",
+        "channel": { "type": "webhook", "url": format!("{}/alert", receiver.uri()) },
+    }))
+    .unwrap();
+    let alert = state.alerts.create("key-a", request, 10).unwrap();
+    let mut events = state.alerts.subscribe();
+    let mut notifier = AlertNotifier::new(&state.config, None).unwrap();
+
+    let old = sourced(
+        "corriere",
+        "Meteo, il weekend",
+        "https://www.corriere.it/cronache/1.shtml",
+    );
+    // The first run only records what's already there
+    assert!(notifier
+        .notify(&state, std::slice::from_ref(&old))
+        .await
+        .unwrap()
+        .is_empty());
+
+    let items = vec![
+        sourced(
+            "corriere",
+            "Allerta meteo a Milano",
+            "https://www.corriere.it/cronache/2.shtml",
+        ),
+        sourced(
+            "corriere",
+            "La Borsa chiude in rialzo",
+            "https://www.corriere.it/economia/3.shtml",
+        ),
+        old,
+    ];
+    let sent = notifier.notify(&state, &items).await.unwrap();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].items.len(), 1);
+
+    let event = events.recv().await.unwrap();
+    assert_eq!(event.alert_id, alert.id);
+    assert_eq!(
+        event.items[0].item.link,
+        "https://www.corriere.it/cronache/2.shtml"
+    );
+    assert!(state.deliveries.list().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn alert_emails_link_to_a_confirmed_unsubscribe() {
+    let state = AppState::new(alert_config("alerts-unsubscribe"));
+    let app = spawn_state(state.clone()).await;
+    let config = &state.config;
+    let request = serde_json::from_value(json!({
+        "keyword": "meteo",
+        "channel": { "type": "sse" },
+    }))
+    .unwrap();
+    let alert = state.alerts.create("key-a", request, 10).unwrap();
+    let link = alerts::unsubscribe_url(config, &alert).unwrap();
+    let path = link.strip_prefix(&config.public_url).unwrap();
+    let client = reqwest::Client::new();
+
+    // Opening the link only asks to confirm
+    let page = client.get(format!("{}{}", app, path)).send().await.unwrap();
+    assert_eq!(page.status(), 200);
+    assert!(page.text().await.unwrap().contains("method=\"post\""));
+    assert_eq!(state.alerts.list_for("key-a").unwrap().len(), 1);
+
+    let wrong = client
+        .post(format!(
+            "{}/api/alerts/{}/unsubscribe?token=sbagliato",
+            app, alert.id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(wrong.status(), 404);
+    let confirmed = client
+        .post(format!("{}{}", app, path))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(confirmed.status(), 200);
+    assert!(state.alerts.list_for("key-a").unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn alert_webhooks_must_not_point_inside() {
+    let app = spawn_app(alert_config("alerts-internal")).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/alerts", app))
+        .header("x-api-key", "key-a")
+        .json(&json!({
+            "keyword": "meteo",
+            "channel": { "type": "webhook", "url": "http://169.254.169.254/latest" },
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use chrono::{DateTime, Utc};
+use common::{news_item, spawn_state, temp_data_dir, test_config};
+use corriere_scraper::analytics;
+use corriere_scraper::archive::{self, ArchivedScrape};
+use corriere_scraper::config::Config;
+use corriere_scraper::AppState;
+use serde_json::{json, Value};
+
+fn scrape(timestamp: &str, links: &[&str]) -> ArchivedScrape {
+    ArchivedScrape {
+        scraped_at: timestamp.parse::<DateTime<Utc>>().unwrap(),
+        news: links
+            .iter()
+            .map(|link| {
+                news_item(
+                    &format!("Titolo {}", link),
+                    &format!("https://www.corriere.it/{}", link),
+                )
+            })
+            .collect(),
+    }
+}
+
+// "a" leads, slips to third, climbs back to second and then drops off
+fn scrapes() -> Vec<ArchivedScrape> {
+    vec![
+        scrape("2026-10-15T07:00:00Z", &["x", "y"]),
+        scrape("2026-10-15T08:00:00Z", &["a", "x", "y"]),
+        scrape("2026-10-15T09:00:00Z", &["x", "y", "a"]),
+        scrape("2026-10-15T10:00:00Z", &["x", "a"]),
+        scrape("2026-10-15T11:00:00Z", &["x", "y"]),
+        scrape("2026-10-15T12:00:00Z", &["x", "y"]),
+    ]
+}
+
+#[test]
+fn timeline_follows_the_article_until_it_drops_off() {
+    let timeline = analytics::timeline("https://www.corriere.it/a", &scrapes());
+
+    let positions: Vec<Option<usize>> = timeline.series.iter().map(|p| p.position).collect();
+    assert_eq!(positions, vec![Some(1), Some(3), Some(2), None]);
+    assert_eq!(timeline.appearances, 3);
+    assert_eq!(timeline.best_position, Some(1));
+    assert_eq!(
+        timeline.first_seen_at.unwrap().to_rfc3339(),
+        "2026-10-15T08:00:00+00:00"
+    );
+    assert_eq!(
+        timeline.dropped_off_at.unwrap().to_rfc3339(),
+        "2026-10-15T11:00:00+00:00"
+    );
+    let moves: Vec<(Option<usize>, Option<usize>)> =
+        timeline.moves.iter().map(|m| (m.from, m.to)).collect();
+    assert_eq!(
+        moves,
+        vec![(Some(1), Some(3)), (Some(3), Some(2)), (Some(2), None)]
+    );
+
+    let unseen = analytics::timeline("https://www.corriere.it/z", &scrapes());
+    assert_eq!(unseen.appearances, 0);
+    assert!(unseen.series.is_empty());
+}
+
+#[tokio::test]
+async fn placement_endpoint_reads_the_archive() {
+    let config = Config {
+        archive_url: Some(format!(
+            "file://{}",
+            temp_data_dir("analytics-placement").display()
+        )),
+        ..test_config("http://127.0.0.1:9")
+    };
+    let archive = archive::connect(&config).await.unwrap().unwrap();
+    for scrape in scrapes() {
+        archive.record(&scrape).await.unwrap();
+    }
+    let mut state = AppState::new(config);
+    state.archive = Some(archive);
+    let app = spawn_state(state).await;
+
+    let body: Value = reqwest::Client::new()
+        .get(format!("{}/api/analytics/placement", app))
+        .query(&[
+            ("url", "https://www.corriere.it/a"),
+            ("from", "2026-10-15T00:00:00Z"),
+            ("to", "2026-10-16T00:00:00Z"),
+        ])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["error"], Value::Null);
+    assert_eq!(body["last_seen_at"], "2026-10-15T10:00:00Z");
+    assert_eq!(body["dropped_off_at"], "2026-10-15T11:00:00Z");
+    assert_eq!(
+        body["series"],
+        json!([
+            { "scraped_at": "2026-10-15T08:00:00Z", "position": 1 },
+            { "scraped_at": "2026-10-15T09:00:00Z", "position": 3 },
+            { "scraped_at": "2026-10-15T10:00:00Z", "position": 2 },
+            { "scraped_at": "2026-10-15T11:00:00Z", "position": null },
+        ])
+    );
+
+    // No more than a year is read at once
+    let response = reqwest::Client::new()
+        .get(format!("{}/api/analytics/placement", app))
+        .query(&[
+            ("url", "https://www.corriere.it/a"),
+            ("from", "2024-01-01T00:00:00Z"),
+            ("to", "2026-10-16T00:00:00Z"),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
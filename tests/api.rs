@@ -0,0 +1,658 @@
+mod common;
+
+use common::{spawn_app, temp_data_dir, test_config, TestSource};
+use corriere_scraper::config::Config;
+use corriere_scraper::formats::{self, Format};
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+const ARTICLE: TestSource = TestSource::new("article");
+
+// Mock upstream serving the stored homepage and article fixtures
+async fn mock_corriere() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/article.shtml"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(ARTICLE.html()))
+        .mount(&server)
+        .await;
+    server
+}
+
+#[tokio::test]
+async fn news_endpoint_serves_extracted_items() {
+    let upstream = mock_corriere().await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+
+    let body: Value = reqwest::get(format!("{}/api/news", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(body["error"], Value::Null);
+    HOMEPAGE.assert_golden(&body["news"]);
+}
+
+#[tokio::test]
+async fn news_endpoint_reports_unreachable_upstream() {
+    // Nothing listens on port 9 (discard) locally
+    let app = spawn_app(test_config("http://127.0.0.1:9")).await;
+
+    let body: Value = reqwest::get(format!("{}/api/news", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("Failed to fetch URL"));
+    assert_eq!(body["news"], Value::Array(vec![]));
+}
+
+#[tokio::test]
+async fn news_endpoint_projects_fields() {
+    let upstream = mock_corriere().await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+
+    let body: Value = reqwest::get(format!("{}/api/news?fields=title,link", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let first = body["news"][0].as_object().unwrap();
+    assert_eq!(first.keys().collect::<Vec<_>>(), vec!["title", "link"]);
+}
+
+#[tokio::test]
+async fn news_endpoint_negotiates_csv() {
+    let upstream = mock_corriere().await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/api/news", app))
+        .header("Accept", "text/csv")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(
+        response.headers()["content-type"],
+        "text/csv; charset=utf-8"
+    );
+    let csv = response.text().await.unwrap();
+    assert!(csv.starts_with("title,description,link,image_url\r\n"));
+}
+
+#[test]
+fn browsers_get_json_unless_they_rank_xml_first() {
+    let browser = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+    assert!(matches!(
+        formats::negotiate(Some(browser)),
+        Some(Format::Json)
+    ));
+    assert!(matches!(
+        formats::negotiate(Some("application/xml, application/json;q=0.5")),
+        Some(Format::Xml)
+    ));
+    assert!(matches!(
+        formats::negotiate(Some("application/xml, application/json")),
+        Some(Format::Json)
+    ));
+    assert!(formats::negotiate(Some("text/html")).is_none());
+}
+
+#[tokio::test]
+async fn scrape_endpoint_rejects_hosts_outside_allowlist() {
+    let upstream = mock_corriere().await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/scrape", app))
+        .json(&serde_json::json!({ "url": "https://example.com/" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn batch_endpoint_fetches_articles_in_order() {
+    let upstream = mock_corriere().await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+
+    let urls = vec![
+        format!("{}/article.shtml", upstream.uri()),
+        format!("{}/missing.shtml", upstream.uri()),
+    ];
+    let body: Value = reqwest::Client::new()
+        .post(format!("{}/api/articles/batch", app))
+        .json(&serde_json::json!({ "urls": urls }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let articles = body["articles"].as_array().unwrap();
+    assert_eq!(articles.len(), 2);
+    assert_eq!(articles[0]["article"]["author"], "di Mario Rossi");
+    assert!(articles[1]["error"].is_string());
+}
+
+#[cfg(feature = "parquet")]
+#[tokio::test]
+async fn batch_endpoint_serves_parquet() {
+    use corriere_scraper::export;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let upstream = mock_corriere().await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+
+    let urls = vec![
+        format!("{}/article.shtml", upstream.uri()),
+        format!("{}/missing.shtml", upstream.uri()),
+    ];
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/articles/batch?format=parquet", app))
+        .json(&serde_json::json!({ "urls": urls }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/vnd.apache.parquet"
+    );
+    let bytes = response.bytes().await.unwrap();
+
+    let builder = ParquetRecordBatchReaderBuilder::try_new(bytes).unwrap();
+    assert_eq!(
+        builder.schema().as_ref(),
+        export::article_parquet_schema().as_ref()
+    );
+    let batches: Vec<_> = builder.build().unwrap().map(Result::unwrap).collect();
+    // Only the article that could be fetched
+    assert_eq!(batches[0].num_rows(), 1);
+    let authors = batches[0]
+        .column_by_name("author")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<arrow_array::StringArray>()
+        .unwrap();
+    assert_eq!(authors.value(0), "di Mario Rossi");
+}
+
+#[tokio::test]
+async fn batch_endpoint_localizes_timestamps() {
+    let upstream = mock_corriere().await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+
+    let body: Value = reqwest::Client::new()
+        .post(format!(
+            "{}/api/articles/batch?tz=Europe/Rome&locale=it",
+            app
+        ))
+        .json(&serde_json::json!({ "urls": [format!("{}/article.shtml", upstream.uri())] }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let article = &body["articles"][0]["article"];
+    assert_eq!(article["published_at"], "2024-05-01T08:30:00+02:00");
+    assert_eq!(
+        article["published_at_display"],
+        "mercoledì 1 maggio 2024, 08:30"
+    );
+    assert!(body["fetched_at_display"].is_string());
+}
+
+#[tokio::test]
+async fn news_endpoint_rejects_unknown_time_zone() {
+    let upstream = mock_corriere().await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+
+    let response = reqwest::get(format!("{}/api/news?tz=Mars/Olympus", app))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn subscriptions_need_the_digest_enabled() {
+    let app = spawn_app(test_config("http://127.0.0.1:9")).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/subscriptions", app))
+        .json(&serde_json::json!({ "email": "lettore@example.com" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn subscription_lifecycle() {
+    let config = Config {
+        smtp_url: Some("smtp://127.0.0.1:2525".to_string()),
+        data_dir: temp_data_dir("subscriptions"),
+        ..test_config("http://127.0.0.1:9")
+    };
+    let app = spawn_app(config).await;
+    let client = reqwest::Client::new();
+
+    let created = client
+        .post(format!("{}/api/subscriptions", app))
+        .json(&serde_json::json!({ "email": "lettore@example.com" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(created.status(), 201);
+    let created: Value = created.json().await.unwrap();
+    let id = created["subscription"]["id"].as_str().unwrap();
+    let token = created["token"].as_str().unwrap();
+
+    // The same address can't subscribe twice, and a bad address is rejected
+    let duplicate = client
+        .post(format!("{}/api/subscriptions", app))
+        .json(&serde_json::json!({ "email": "LETTORE@example.com" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(duplicate.status(), 409);
+    let invalid = client
+        .post(format!("{}/api/subscriptions", app))
+        .json(&serde_json::json!({ "email": "not an address" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(invalid.status(), 400);
+
+    // Reading needs the token
+    let url = format!("{}/api/subscriptions/{}", app, id);
+    let forbidden = client.get(&url).send().await.unwrap();
+    assert_eq!(forbidden.status(), 404);
+
+    let updated: Value = client
+        .put(format!("{}?token={}", url, token))
+        .json(&serde_json::json!({ "email": "nuovo@example.com" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(updated["subscription"]["email"], "nuovo@example.com");
+    assert_eq!(updated["token"], Value::Null);
+
+    // Following the link only asks to confirm
+    let page = client
+        .get(format!("{}/unsubscribe?token={}", url, token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(page.status(), 200);
+    assert!(page.text().await.unwrap().contains("method=\"post\""));
+    let kept = client
+        .get(format!("{}?token={}", url, token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(kept.status(), 200);
+
+    let unsubscribed = client
+        .post(format!("{}/unsubscribe?token={}", url, token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(unsubscribed.status(), 200);
+
+    let gone = client
+        .get(format!("{}?token={}", url, token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(gone.status(), 404);
+}
+
+#[tokio::test]
+async fn breaker_serves_stale_snapshot_and_stops_hitting_upstream() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .up_to_n_times(1)
+        .mount(&upstream)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&upstream)
+        .await;
+
+    let config = Config {
+        breaker_failure_threshold: 2,
+        breaker_cooldown_secs: 60,
+        ..test_config(&upstream.uri())
+    };
+    let app = spawn_app(config).await;
+    let get_news = || async {
+        reqwest::get(format!("{}/api/news", app))
+            .await
+            .unwrap()
+            .json::<Value>()
+            .await
+            .unwrap()
+    };
+
+    let fresh = get_news().await;
+    assert_eq!(fresh["stale"], false);
+
+    // Two failures trip the breaker; every response falls back to the last good scrape
+    for _ in 0..3 {
+        let stale = get_news().await;
+        assert_eq!(stale["stale"], true);
+        assert_eq!(stale["news"], fresh["news"]);
+    }
+
+    // The request made while the breaker was open never reached upstream
+    assert_eq!(upstream.received_requests().await.unwrap().len(), 3);
+
+    let metrics = reqwest::get(format!("{}/metrics", app))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(metrics.contains("corriere_breaker_state{breaker=\"homepage\"} 1"));
+    assert!(metrics.contains("corriere_breaker_trips_total{breaker=\"homepage\"} 1"));
+}
+
+#[tokio::test]
+async fn stale_cache_is_served_while_revalidating() {
+    let upstream = mock_corriere().await;
+    let config = Config {
+        cache_soft_ttl_secs: 0,
+        cache_hard_ttl_secs: 60,
+        ..test_config(&upstream.uri())
+    };
+    let app = spawn_app(config).await;
+
+    let first = reqwest::get(format!("{}/api/news", app)).await.unwrap();
+    assert_eq!(first.headers()["age"], "0");
+
+    // Past the soft TTL: answered from cache, refreshed in the background
+    let second = reqwest::get(format!("{}/api/news", app)).await.unwrap();
+    assert_eq!(
+        second.headers()["cache-control"],
+        "public, max-age=0, stale-while-revalidate=60"
+    );
+    let body: Value = second.json().await.unwrap();
+    assert_eq!(body["stale"], false);
+
+    for _ in 0..50 {
+        if upstream.received_requests().await.unwrap().len() == 2 {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("background refresh never reached upstream");
+}
+
+#[tokio::test]
+async fn concurrent_cold_misses_share_one_upstream_fetch() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(HOMEPAGE.html())
+                .set_delay(std::time::Duration::from_millis(300)),
+        )
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+
+    let mut requests = tokio::task::JoinSet::new();
+    for _ in 0..50 {
+        let url = format!("{}/api/news", app);
+        requests.spawn(async move {
+            reqwest::get(url)
+                .await
+                .unwrap()
+                .json::<Value>()
+                .await
+                .unwrap()
+        });
+    }
+    while let Some(body) = requests.join_next().await {
+        assert_eq!(body.unwrap()["news"].as_array().unwrap().len(), 6);
+    }
+
+    assert_eq!(upstream.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn v1_news_keeps_its_shape_and_is_aliased() {
+    let upstream = mock_corriere().await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+
+    let mut bodies = Vec::new();
+    for route in ["/api/news", "/api/v1/news"] {
+        let response = reqwest::get(format!("{}{}", app, route)).await.unwrap();
+        assert_eq!(response.headers()["api-version"], "1");
+        bodies.push(response.json::<Value>().await.unwrap());
+    }
+
+    assert_eq!(bodies[0]["news"], bodies[1]["news"]);
+    let keys: Vec<&String> = bodies[0].as_object().unwrap().keys().collect();
+    assert_eq!(keys, ["scraped_at", "news", "error", "stale"]);
+    HOMEPAGE.assert_golden(&bodies[1]["news"]);
+}
+
+#[tokio::test]
+async fn v2_news_adds_ids_categories_and_timestamps() {
+    let upstream = mock_corriere().await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+
+    let response = reqwest::get(format!("{}/api/v2/news", app)).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["api-version"], "2");
+    let body: Value = response.json().await.unwrap();
+
+    assert_eq!(body["api_version"], 2);
+    assert_eq!(body["count"], 6);
+    let news = body["news"].as_array().unwrap();
+    assert_eq!(news[0]["categories"], serde_json::json!(["politica"]));
+    assert_eq!(news[0]["published_on"], "2024-05-01");
+    assert_eq!(news[0]["scraped_at"], body["scraped_at"]);
+    assert_eq!(
+        news[4]["categories"],
+        serde_json::json!(["milano", "cronaca"])
+    );
+    assert_eq!(
+        news[5]["categories"],
+        serde_json::json!(["sport", "calcio", "serie-a"])
+    );
+
+    // Ids are stable and unique
+    let ids: std::collections::HashSet<&str> = news
+        .iter()
+        .map(|item| item["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids.len(), 6);
+    assert_eq!(
+        news[5]["id"],
+        corriere_scraper::v2::item_id(
+            "https://www.corriere.it/sport/calcio/serie-a/24_maggio_01/inter-scudetto-festa-e5f6a7b8.shtml"
+        )
+    );
+}
+
+#[tokio::test]
+async fn v2_news_reports_upstream_failure_as_bad_gateway() {
+    let app = spawn_app(test_config("http://127.0.0.1:9")).await;
+
+    let response = reqwest::get(format!("{}/api/v2/news", app)).await.unwrap();
+    assert_eq!(response.status(), 502);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["count"], 0);
+    assert!(body["error"].as_str().is_some());
+}
+
+#[tokio::test]
+async fn v2_news_reports_upstream_failure_as_a_problem_when_asked() {
+    let app = spawn_app(test_config("http://127.0.0.1:9")).await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/api/v2/news", app))
+        .header("accept", "application/problem+json")
+        .header("accept-language", "it-IT, it;q=0.9, en;q=0.8")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 502);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/problem+json"
+    );
+    assert_eq!(response.headers()["content-language"], "it");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(
+        body["type"],
+        "urn:corriere-scraper:problem:upstream-unavailable"
+    );
+    assert_eq!(body["code"], "upstream-unavailable");
+    assert_eq!(body["status"], 502);
+    assert_eq!(body["title"], "Corriere.it non è raggiungibile");
+    assert!(body["reason"]
+        .as_str()
+        .unwrap()
+        .starts_with("Failed to fetch URL"));
+}
+
+#[tokio::test]
+async fn v1_news_errors_are_problems_only_when_asked_for() {
+    let app = spawn_app(test_config("http://127.0.0.1:9")).await;
+    let client = reqwest::Client::new();
+    let url = format!("{}/api/news?tz=Mars/Olympus", app);
+
+    let legacy: Value = client.get(&url).send().await.unwrap().json().await.unwrap();
+    assert!(legacy["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("Unknown time zone"));
+
+    let response = client
+        .get(&url)
+        .header("accept", "application/json, application/problem+json")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/problem+json"
+    );
+    assert_eq!(response.headers()["content-language"], "en");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "invalid-parameter");
+    assert_eq!(body["parameter"], "tz");
+    assert_eq!(body["detail"], "The 'tz' parameter is not valid");
+}
+
+#[test]
+fn accept_language_picks_the_preferred_supported_language() {
+    use corriere_scraper::problem::Lang;
+
+    assert_eq!(Lang::negotiate(None), Lang::English);
+    assert_eq!(Lang::negotiate(Some("it-IT")), Lang::Italian);
+    assert_eq!(
+        Lang::negotiate(Some("fr, it;q=0.5, en;q=0.4")),
+        Lang::Italian
+    );
+    assert_eq!(Lang::negotiate(Some("it;q=0.3, en-GB")), Lang::English);
+    assert_eq!(Lang::negotiate(Some("de, fr")), Lang::English);
+}
+
+#[tokio::test]
+async fn request_ids_are_echoed_or_generated() {
+    let app = spawn_app(test_config("http://127.0.0.1:9")).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/api/v2/news", app))
+        .header("accept", "application/problem+json")
+        .header("x-request-id", "client-7f3a")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-request-id"], "client-7f3a");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["request_id"], "client-7f3a");
+
+    // Unusable ids are replaced rather than passed on
+    for sent in [None, Some("has spaces"), Some(&*"x".repeat(200))] {
+        let mut request = client.get(format!("{}/api/news", app));
+        if let Some(sent) = sent {
+            request = request.header("x-request-id", sent);
+        }
+        let response = request.send().await.unwrap();
+        let id = response.headers()["x-request-id"].to_str().unwrap();
+        assert_eq!(id.len(), 20);
+        assert!(id.chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+}
+
+#[tokio::test]
+async fn other_endpoints_errors_are_problems_when_asked() {
+    let app = spawn_app(Config {
+        admin_token: Some("segreto".to_string()),
+        ..test_config("http://127.0.0.1:9")
+    })
+    .await;
+    let client = reqwest::Client::new();
+    let url = format!("{}/api/admin/scheduler", app);
+
+    let legacy = client.get(&url).send().await.unwrap();
+    assert_eq!(legacy.status(), 401);
+    let body: Value = legacy.json().await.unwrap();
+    assert!(body["error"].is_string());
+
+    let response = client
+        .get(&url)
+        .header("accept", "application/problem+json")
+        .header("accept-language", "it")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/problem+json"
+    );
+    let problem: Value = response.json().await.unwrap();
+    assert_eq!(problem["code"], "unauthorized");
+    assert_eq!(problem["status"], 401);
+    assert_eq!(problem["title"], "Non autorizzato");
+    assert_eq!(problem["reason"], body["error"]);
+}
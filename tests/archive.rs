@@ -0,0 +1,375 @@
+mod common;
+
+use chrono::{DateTime, Duration, Utc};
+use common::{news_item, spawn_state, temp_data_dir, test_config, TestSource};
+use corriere_scraper::archive::{self, ArchivedScrape, SearchQuery, Storage};
+use corriere_scraper::config::Config;
+use corriere_scraper::export;
+use corriere_scraper::AppState;
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use std::io::Read;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+fn at(timestamp: &str) -> DateTime<Utc> {
+    timestamp.parse().unwrap()
+}
+
+// Two scrapes a day apart: the budget story drops from first to second place,
+// and the second lists the football story twice
+async fn fill(storage: &dyn Storage) {
+    storage.prepare().await.unwrap();
+    storage
+        .record(&ArchivedScrape {
+            scraped_at: at("2026-10-14T08:00:00Z"),
+            news: vec![
+                news_item(
+                    "Manovra, il governo pone la fiducia",
+                    "https://www.corriere.it/a",
+                ),
+                news_item(
+                    "Meteo, allerta gialla in Liguria",
+                    "https://www.corriere.it/b",
+                ),
+            ],
+        })
+        .await
+        .unwrap();
+    storage
+        .record(&ArchivedScrape {
+            scraped_at: at("2026-10-15T08:00:00Z"),
+            news: vec![
+                news_item(
+                    "Champions, l'Inter vince a Madrid",
+                    "https://www.corriere.it/c",
+                ),
+                news_item("Manovra approvata alla Camera", "https://www.corriere.it/a"),
+                news_item("Champions, le pagelle", "https://www.corriere.it/c"),
+            ],
+        })
+        .await
+        .unwrap();
+}
+
+async fn check_archive(storage: &dyn Storage) {
+    let found = storage
+        .search(&SearchQuery {
+            text: Some("manovra".to_string()),
+            limit: 10,
+            ..SearchQuery::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].title, "Manovra approvata alla Camera");
+    assert_eq!(found[0].first_seen_at, at("2026-10-14T08:00:00Z"));
+    assert_eq!(found[0].last_seen_at, at("2026-10-15T08:00:00Z"));
+
+    let yesterday = storage
+        .search(&SearchQuery {
+            to: Some(at("2026-10-14T23:59:59Z")),
+            limit: 10,
+            ..SearchQuery::default()
+        })
+        .await
+        .unwrap();
+    let links: Vec<&str> = yesterday.iter().map(|a| a.link.as_str()).collect();
+    assert!(!links.contains(&"https://www.corriere.it/c"));
+    assert_eq!(links.len(), 2);
+
+    let history = storage
+        .history("https://www.corriere.it/a", None, None)
+        .await
+        .unwrap();
+    let positions: Vec<usize> = history.iter().map(|a| a.position).collect();
+    assert_eq!(positions, vec![1, 2]);
+    let history = storage
+        .history(
+            "https://www.corriere.it/a",
+            Some(at("2026-10-15T00:00:00Z")),
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].scraped_at, at("2026-10-15T08:00:00Z"));
+
+    let (from, to) = export::day_range("2026-10-15".parse().unwrap());
+    let scrapes = storage.scrapes(from, to).await.unwrap();
+    assert_eq!(scrapes.len(), 1);
+    assert_eq!(scrapes[0].news.len(), 2);
+    assert_eq!(scrapes[0].news[0].link, "https://www.corriere.it/c");
+    assert_eq!(scrapes[0].news[1].link, "https://www.corriere.it/a");
+}
+
+#[tokio::test]
+async fn file_archive_searches_and_tracks_positions() {
+    let storage = archive::FileStorage::new(temp_data_dir("archive-file"));
+    fill(&storage).await;
+    check_archive(&storage).await;
+}
+
+#[tokio::test]
+async fn homepage_scrapes_are_archived() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&upstream)
+        .await;
+    let config = Config {
+        archive_url: Some(format!("file://{}", temp_data_dir("archive-api").display())),
+        ..test_config(&upstream.uri())
+    };
+    let archive = archive::connect(&config).await.unwrap();
+    let mut state = AppState::new(config);
+    state.archive = archive;
+    let app = spawn_state(state).await;
+
+    let news: Value = reqwest::get(format!("{}/api/news", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let first = news["news"][0].clone();
+
+    // Archiving runs in the background
+    let mut history = Value::Null;
+    for _ in 0..50 {
+        history = reqwest::Client::new()
+            .get(format!("{}/api/archive/history", app))
+            .query(&[("link", first["link"].as_str().unwrap())])
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if !history["appearances"].as_array().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(history["appearances"][0]["position"], 1);
+
+    let since = (Utc::now() - Duration::hours(1)).to_rfc3339();
+    let search: Value = reqwest::Client::new()
+        .get(format!("{}/api/archive/search", app))
+        .query(&[("from", since.as_str())])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let articles = search["articles"].as_array().unwrap();
+    assert_eq!(articles.len(), news["news"].as_array().unwrap().len());
+    assert!(articles
+        .iter()
+        .any(|article| article["link"] == first["link"]));
+}
+
+#[tokio::test]
+async fn archive_endpoints_are_off_by_default() {
+    let app = common::spawn_app(test_config("http://127.0.0.1:9")).await;
+
+    let response = reqwest::get(format!("{}/api/archive/search?q=manovra", app))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+}
+
+// Runs against a scratch database when TEST_DATABASE_URL is set
+#[cfg(feature = "postgres")]
+#[tokio::test]
+async fn postgres_archive_matches_the_file_backend() {
+    let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+        return;
+    };
+    let storage = corriere_scraper::postgres::PostgresStorage::connect(&url)
+        .await
+        .unwrap();
+    fill(&storage).await;
+    check_archive(&storage).await;
+}
+
+async fn export_app(name: &str) -> String {
+    let config = Config {
+        archive_url: Some(format!("file://{}", temp_data_dir(name).display())),
+        ..test_config("http://127.0.0.1:9")
+    };
+    let mut state = AppState::new(config);
+    state.archive = archive::connect(&state.config).await.unwrap();
+    fill(state.archive.as_deref().unwrap()).await;
+    spawn_state(state).await
+}
+
+#[tokio::test]
+async fn export_endpoint_serves_a_day_as_ndjson() {
+    let app = export_app("archive-export").await;
+
+    let response = reqwest::get(format!("{}/api/archive/export?date=2026-10-14", app))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.headers()["content-disposition"],
+        "attachment; filename=\"corriere-2026-10-14.ndjson\""
+    );
+
+    assert_eq!(response.headers()["content-encoding"], "gzip");
+    let mut body = String::new();
+    GzDecoder::new(response.bytes().await.unwrap().as_ref())
+        .read_to_string(&mut body)
+        .unwrap();
+    let rows: Vec<Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[1]["position"], 2);
+    assert_eq!(rows[1]["title"], "Meteo, allerta gialla in Liguria");
+
+    let response = reqwest::get(format!(
+        "{}/api/archive/export?date=2026-10-14&format=csv",
+        app
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[cfg(feature = "parquet")]
+#[tokio::test]
+async fn export_endpoint_serves_parquet() {
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let app = export_app("archive-parquet").await;
+
+    let response = reqwest::get(format!(
+        "{}/api/archive/export?date=2026-10-15&format=parquet",
+        app
+    ))
+    .await
+    .unwrap();
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/vnd.apache.parquet"
+    );
+    let bytes = response.bytes().await.unwrap();
+
+    let builder = ParquetRecordBatchReaderBuilder::try_new(bytes).unwrap();
+    assert_eq!(builder.schema().as_ref(), export::parquet_schema().as_ref());
+    let batches: Vec<_> = builder.build().unwrap().map(Result::unwrap).collect();
+    assert_eq!(batches[0].num_rows(), 2);
+    let titles = batches[0]
+        .column_by_name("title")
+        .unwrap()
+        .as_any()
+        .downcast_ref::<arrow_array::StringArray>()
+        .unwrap();
+    assert_eq!(titles.value(0), "Champions, l'Inter vince a Madrid");
+}
+
+#[tokio::test]
+async fn news_at_an_instant_serves_the_nearest_snapshot() {
+    let app = export_app("archive-at").await;
+    let get = |path: &str| reqwest::get(format!("{}{}", app, path));
+
+    let response = get("/api/news?at=2026-10-14T19:00:00Z").await.unwrap();
+    assert_eq!(
+        response.headers()["x-snapshot-at"],
+        "2026-10-14T08:00:00+00:00"
+    );
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["scraped_at"], "2026-10-14T08:00:00Z");
+    assert_eq!(body["news"][0]["link"], "https://www.corriere.it/a");
+    assert_eq!(body["snapshot"], true);
+
+    let body: Value = get("/api/v2/news?at=2026-10-15T07:00:00%2B02:00")
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["snapshot"], true);
+    assert_eq!(body["news"][0]["link"], "https://www.corriere.it/c");
+
+    let far = get("/api/news?at=2020-01-01T00:00:00Z").await.unwrap();
+    assert_eq!(far.status(), 404);
+    let invalid = get("/api/news?at=ieri").await.unwrap();
+    assert_eq!(invalid.status(), 400);
+    let off = reqwest::get(format!(
+        "{}/api/news?at=2026-10-14T08:00:00Z",
+        common::spawn_app(test_config("http://127.0.0.1:9")).await
+    ))
+    .await
+    .unwrap();
+    assert_eq!(off.status(), 404);
+
+    // The live homepage carries no marker
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&upstream)
+        .await;
+    let live: Value = reqwest::get(format!(
+        "{}/api/news",
+        common::spawn_app(test_config(&upstream.uri())).await
+    ))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert!(live.get("snapshot").is_none());
+}
+
+#[tokio::test]
+async fn nearest_looks_across_day_files_outwards() {
+    let storage = archive::FileStorage::new(temp_data_dir("archive-nearest"));
+    storage.prepare().await.unwrap();
+    for scraped_at in [
+        "2026-09-20T12:00:00Z",
+        "2026-10-10T23:30:00Z",
+        "2026-10-13T00:10:00Z",
+    ] {
+        storage
+            .record(&ArchivedScrape {
+                scraped_at: at(scraped_at),
+                news: vec![news_item("Titolo", "https://www.corriere.it/a")],
+            })
+            .await
+            .unwrap();
+    }
+    let nearest = |instant: &str| {
+        let storage = &storage;
+        let instant = at(instant);
+        async move {
+            archive::nearest(storage, instant)
+                .await
+                .unwrap()
+                .map(|scrape| scrape.scraped_at)
+        }
+    };
+
+    assert_eq!(
+        nearest("2026-10-11T00:05:00Z").await,
+        Some(at("2026-10-10T23:30:00Z"))
+    );
+    assert_eq!(
+        nearest("2026-10-12T23:50:00Z").await,
+        Some(at("2026-10-13T00:10:00Z"))
+    );
+    assert_eq!(
+        nearest("2026-09-01T00:00:00Z").await,
+        Some(at("2026-09-20T12:00:00Z"))
+    );
+    // Over a month away from any scrape
+    assert_eq!(nearest("2026-12-01T00:00:00Z").await, None);
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use common::{spawn_app, temp_data_dir, test_config, TestSource};
+use corriere_scraper::audit::{AuditEvent, AuditKind, AuditLog};
+use corriere_scraper::config::Config;
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+async fn audit_app(upstream: &MockServer, name: &str, audit_log: bool) -> String {
+    let job = format!("sport:15m:{}/sport/", upstream.uri())
+        .parse()
+        .unwrap();
+    spawn_app(Config {
+        data_dir: temp_data_dir(name),
+        scheduler_jobs: vec![job],
+        admin_token: Some("segreto".to_string()),
+        admin_tokens: vec![("alice".to_string(), "di-alice".to_string())],
+        audit_log,
+        ..test_config(&upstream.uri())
+    })
+    .await
+}
+
+async fn audit(app: &str, query: &str) -> Vec<Value> {
+    let body: Value = reqwest::Client::new()
+        .get(format!("{}/api/admin/audit?{}", app, query))
+        .bearer_auth("segreto")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    body["events"].as_array().unwrap().clone()
+}
+
+#[tokio::test]
+async fn scrapes_and_admin_actions_are_recorded_with_their_actor() {
+    let upstream = MockServer::start().await;
+    for page in ["/", "/sport/"] {
+        Mock::given(method("GET"))
+            .and(path(page))
+            .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+            .mount(&upstream)
+            .await;
+    }
+    let app = audit_app(&upstream, "audit", true).await;
+    let client = reqwest::Client::new();
+
+    reqwest::get(format!("{}/api/news", app)).await.unwrap();
+    let run = client
+        .post(format!("{}/api/admin/scheduler/sport/run", app))
+        .bearer_auth("di-alice")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(run.status(), 200);
+    let flush = client
+        .post(format!("{}/api/admin/cache/flush", app))
+        .bearer_auth("segreto")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(flush.status(), 204);
+
+    let admin = audit(&app, "kind=admin").await;
+    assert_eq!(admin.len(), 2);
+    assert_eq!(admin[0]["action"], "flush_cache");
+    assert_eq!(admin[0]["actor"], "admin");
+    assert_eq!(admin[1]["action"], "run_job");
+    assert_eq!(admin[1]["actor"], "alice");
+    assert_eq!(admin[1]["target"], "sport");
+    assert_eq!(admin[1]["ok"], true);
+
+    let scrapes = audit(&app, "kind=scrape").await;
+    assert_eq!(scrapes.len(), 2);
+    assert_eq!(scrapes[0]["action"], "sport");
+    assert_eq!(scrapes[0]["trigger"], "admin");
+    assert_eq!(scrapes[1]["action"], "homepage");
+    assert_eq!(scrapes[1]["trigger"], "request");
+    assert!(scrapes[1]["items"].as_u64().unwrap() > 0);
+    assert!(scrapes[1]["duration_ms"].is_u64());
+
+    let by_alice = audit(&app, "actor=alice").await;
+    assert_eq!(by_alice.len(), 1);
+    let limited = audit(&app, "limit=1").await;
+    assert_eq!(limited[0]["action"], "flush_cache");
+    assert!(audit(&app, "until=2000-01-01T00:00:00Z").await.is_empty());
+}
+
+#[tokio::test]
+async fn audit_log_requires_a_token_and_can_be_turned_off() {
+    let upstream = MockServer::start().await;
+    let app = audit_app(&upstream, "audit-off", false).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/api/admin/audit", app))
+        .bearer_auth("sbagliato")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+
+    let flush = client
+        .post(format!("{}/api/admin/cache/flush", app))
+        .bearer_auth("di-alice")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(flush.status(), 204);
+    assert!(audit(&app, "").await.is_empty());
+    assert!(!temp_data_dir("audit-off").join("audit.jsonl").exists());
+}
+
+#[tokio::test]
+async fn scrapes_outside_the_scheduler_are_recorded_too() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/milano/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(Config {
+        data_dir: temp_data_dir("audit-local"),
+        admin_token: Some("segreto".to_string()),
+        local_editions: vec![("milano".to_string(), format!("{}/milano/", upstream.uri()))],
+        audit_log: true,
+        ..test_config(&upstream.uri())
+    })
+    .await;
+
+    reqwest::get(format!("{}/api/local/milano", app))
+        .await
+        .unwrap();
+    let scrapes = audit(&app, "kind=scrape").await;
+    assert_eq!(scrapes.len(), 1);
+    assert_eq!(scrapes[0]["target"], format!("{}/milano/", upstream.uri()));
+    assert_eq!(scrapes[0]["trigger"], "request");
+    assert_eq!(scrapes[0]["ok"], true);
+}
+
+#[test]
+fn the_newest_events_are_read_from_the_end() {
+    let dir = temp_data_dir("audit-newest");
+    let path = dir.join("audit.jsonl");
+    let log = AuditLog::new(Some(path.clone()));
+    // Enough events to span several read blocks
+    for n in 0..3000 {
+        let event = AuditEvent::admin(&format!("action-{}", n), None, None, &Ok(()));
+        log.append(&event).unwrap();
+    }
+    // A line cut short by a crash
+    let mut content = std::fs::read_to_string(&path).unwrap();
+    content.push_str("{\"at\":\"");
+    std::fs::write(&path, content).unwrap();
+
+    let actions = |events: Vec<AuditEvent>| -> Vec<String> {
+        events.into_iter().map(|event| event.action).collect()
+    };
+    assert_eq!(
+        actions(log.newest(3, |_| true).unwrap()),
+        ["action-2999", "action-2998", "action-2997"]
+    );
+    let sevens = log.newest(2, |event| event.action.ends_with("07")).unwrap();
+    assert_eq!(actions(sevens), ["action-2907", "action-2807"]);
+    let all = log
+        .newest(5000, |event| event.kind == AuditKind::Admin)
+        .unwrap();
+    assert_eq!(all.len(), 3000);
+    assert_eq!(all[2999].action, "action-0");
+}
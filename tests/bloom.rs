@@ -0,0 +1,110 @@
+mod common;
+
+use common::{temp_data_dir, test_config};
+use corriere_scraper::bloom::{BloomFilter, SeenFilter};
+use corriere_scraper::config::Config;
+use corriere_scraper::sent_log::SentLog;
+use std::time::Duration;
+
+fn link(n: usize) -> String {
+    format!("https://www.corriere.it/cronache/articolo-{}.shtml", n)
+}
+
+#[test]
+fn bloom_filter_has_no_false_negatives_and_few_false_positives() {
+    let mut filter = BloomFilter::new(10_000, 0.01);
+    for n in 0..10_000 {
+        filter.insert(&link(n));
+    }
+    assert!(!filter.insert(&link(42)));
+    assert!((0..10_000).all(|n| filter.contains(&link(n))));
+
+    let false_positives = (10_000..20_000)
+        .filter(|n| filter.contains(&link(*n)))
+        .count();
+    assert!(false_positives < 200, "{} false positives", false_positives);
+    // About 9.6 bits per entry at 1%
+    assert!(filter.size_bytes() < 13_000);
+}
+
+#[test]
+fn seen_filter_survives_a_restart_and_rotates_when_full() {
+    let path = temp_data_dir("bloom-rotate").join("seen.bloom");
+    let mut seen = SeenFilter::load(path.clone(), 100, 0.01, Duration::ZERO).unwrap();
+    for n in 0..250 {
+        seen.insert(&link(n));
+    }
+    assert!(seen.checkpoint(false).unwrap());
+    // Nothing changed since, so nothing to write
+    assert!(!seen.checkpoint(true).unwrap());
+
+    let reloaded = SeenFilter::load(path, 100, 0.01, Duration::ZERO).unwrap();
+    // About the first hundred went out with the oldest generation. A few of the
+    // rest may have been taken for seen already, which is what a bloom
+    // filter trades for its size
+    assert!((140..=150).contains(&reloaded.len()));
+    assert!((110..250).all(|n| reloaded.contains(&link(n))));
+    let false_positives = (1_000..2_000)
+        .filter(|n| reloaded.contains(&link(*n)))
+        .count();
+    assert!(false_positives < 50, "{} false positives", false_positives);
+}
+
+#[test]
+fn sent_log_remembers_links_beyond_its_list() {
+    let config = Config {
+        data_dir: temp_data_dir("bloom-sent-log"),
+        seen_filter_checkpoint_secs: 3600,
+        ..test_config("http://127.0.0.1:9")
+    };
+    let mut sent = SentLog::load(&config, "webhooks").unwrap();
+    assert!(!sent.is_stored());
+    for n in 0..3_000 {
+        sent.push(link(n));
+    }
+    sent.save().unwrap();
+
+    let sent = SentLog::load(&config, "webhooks").unwrap();
+    assert!(sent.is_stored());
+    assert!(sent.contains(&link(0)));
+    assert!(sent.contains(&link(2_999)));
+    assert!(!sent.contains(&link(3_000)));
+    assert!(config.data_dir.join("webhooks_seen.bloom").exists());
+}
+
+#[test]
+fn sent_log_confirms_what_the_filter_recognizes() {
+    let config = Config {
+        data_dir: temp_data_dir("bloom-confirm"),
+        seen_filter_capacity: 100,
+        seen_filter_fp_rate: 0.3,
+        ..test_config("http://127.0.0.1:9")
+    };
+    let mut sent = SentLog::load(&config, "telegram").unwrap();
+    for n in 0..100 {
+        sent.push(link(n));
+    }
+    sent.save().unwrap();
+    sent.push(link(100));
+    sent.save().unwrap();
+
+    // The filter alone takes plenty of new links for posted ones
+    let seen = SeenFilter::load(
+        config.data_dir.join("telegram_seen.bloom"),
+        100,
+        0.3,
+        Duration::ZERO,
+    )
+    .unwrap();
+    assert!((1_000..2_000).any(|n| seen.contains(&link(n))));
+
+    let sent = SentLog::load(&config, "telegram").unwrap();
+    assert!((0..=100).all(|n| sent.contains(&link(n))));
+    assert!(!(1_000..2_000).any(|n| sent.contains(&link(n))));
+    assert_eq!(
+        std::fs::metadata(config.data_dir.join("telegram_seen.ids"))
+            .unwrap()
+            .len(),
+        101 * 8
+    );
+}
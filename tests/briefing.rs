@@ -0,0 +1,148 @@
+#![cfg(feature = "tts")]
+
+mod common;
+
+use common::{news_item, spawn_state, temp_data_dir, test_config, TestSource};
+use corriere_scraper::config::Config;
+use corriere_scraper::scheduler::{self, Job};
+use corriere_scraper::{briefing, AppState, NewsItem};
+use wiremock::matchers::{body_partial_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+async fn mock_corriere() -> MockServer {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&upstream)
+        .await;
+    upstream
+}
+
+fn briefing_config(upstream: &MockServer, name: &str) -> Config {
+    Config {
+        data_dir: temp_data_dir(name),
+        scheduler_jobs: vec!["briefing:6h".parse().unwrap()],
+        briefing_top_n: 2,
+        public_url: "https://news.example.com".to_string(),
+        ..test_config(&upstream.uri())
+    }
+}
+
+#[test]
+fn script_reads_out_the_headlines_in_order() {
+    let item = |title: &str, description: &str| NewsItem {
+        description: description.to_string(),
+        ..news_item(title, "https://www.corriere.it/a.shtml")
+    };
+    let script = briefing::script(
+        &[
+            item("Manovra, fiducia alla Camera", "Voto entro venerdì"),
+            item("Ucraina, droni su Kiev", ""),
+        ],
+        "giovedì 15 ottobre 2026, 07:00",
+    );
+
+    assert_eq!(
+        script,
+        "Rassegna stampa del Corriere della Sera, giovedì 15 ottobre 2026, 07:00.\n\n\
+         Prima notizia. Manovra, fiducia alla Camera. Voto entro venerdì.\n\n\
+         Seconda notizia. Ucraina, droni su Kiev.\n\n\
+         È tutto. Buona giornata."
+    );
+}
+
+#[test]
+fn briefing_job_takes_no_url() {
+    assert!("briefing:1h:https://www.corriere.it/"
+        .parse::<Job>()
+        .is_err());
+    assert_eq!("briefing:1h".parse::<Job>().unwrap().url, None);
+}
+
+#[tokio::test]
+async fn command_backend_records_episodes_for_the_feed() {
+    let upstream = mock_corriere().await;
+    // cat hands the script back, standing in for the audio
+    let state = AppState::new(Config {
+        tts_command: Some("cat".to_string()),
+        ..briefing_config(&upstream, "briefing-command")
+    });
+    let app = spawn_state(state.clone()).await;
+
+    let missing = reqwest::get(format!("{}/api/briefing.mp3", app))
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), 404);
+
+    assert_eq!(scheduler::run_job(&state, "briefing").await, Ok(2));
+    let audio = reqwest::get(format!("{}/api/briefing.mp3", app))
+        .await
+        .unwrap();
+    assert_eq!(audio.headers()["content-type"], "audio/mpeg");
+    let audio = audio.text().await.unwrap();
+    assert!(audio.starts_with("Rassegna stampa del Corriere della Sera"));
+    assert!(audio.contains("Seconda notizia. Ucraina, attacco di droni su Kiev."));
+    assert!(!audio.contains("Terza notizia"));
+
+    let feed = reqwest::get(format!("{}/api/briefing.rss", app))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(feed.contains("<enclosure url=\"https://news.example.com/api/briefing/briefing-"));
+    assert!(feed.contains(&format!("type=\"audio/mpeg\" length=\"{}\"", audio.len())));
+    assert!(feed.contains("Prima notizia. Manovra"));
+
+    let traversal = reqwest::get(format!("{}/api/briefing/..%2Fsecret.mp3", app))
+        .await
+        .unwrap();
+    assert_eq!(traversal.status(), 404);
+}
+
+#[tokio::test]
+async fn http_backend_posts_the_script_to_the_speech_api() {
+    let upstream = mock_corriere().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/audio/speech"))
+        .and(header("authorization", "Bearer secret"))
+        .and(body_partial_json(serde_json::json!({
+            "model": "tts-1",
+            "voice": "alloy",
+            "response_format": "mp3",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_bytes(b"ID3 fake mp3".to_vec()))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let state = AppState::new(Config {
+        tts_api_url: Some(format!("{}/v1/audio/speech", upstream.uri())),
+        tts_api_key: Some("secret".to_string()),
+        ..briefing_config(&upstream, "briefing-http")
+    });
+    let app = spawn_state(state.clone()).await;
+
+    scheduler::run_job(&state, "briefing").await.unwrap();
+    let audio = reqwest::get(format!("{}/api/briefing.mp3", app))
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    assert_eq!(&audio[..], b"ID3 fake mp3");
+}
+
+#[tokio::test]
+async fn failing_command_fails_the_job() {
+    let upstream = mock_corriere().await;
+    let state = AppState::new(Config {
+        tts_command: Some("echo no voice model >&2; exit 3".to_string()),
+        ..briefing_config(&upstream, "briefing-failing")
+    });
+
+    let error = scheduler::run_job(&state, "briefing").await.unwrap_err();
+    assert!(error.contains("no voice model"), "{}", error);
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use common::{spawn_app, test_config, TestSource};
+use corriere_scraper::budget::{self, Budget};
+use corriere_scraper::config::Config;
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+async fn upstream() -> MockServer {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&upstream)
+        .await;
+    upstream
+}
+
+fn budget_config(upstream: &MockServer, budget: Budget) -> Config {
+    Config {
+        budget,
+        ..test_config(&upstream.uri())
+    }
+}
+
+async fn get_json(url: String) -> (Value, usize) {
+    let body = reqwest::get(url).await.unwrap().bytes().await.unwrap();
+    (serde_json::from_slice(&body).unwrap(), body.len())
+}
+
+#[test]
+fn text_is_cut_on_a_word_with_an_ellipsis() {
+    let text = "Il governo approva la manovra: più fondi per la sanità e le scuole";
+
+    assert_eq!(budget::truncate_text(text, 200), None);
+    let cut = budget::truncate_text(text, 30).unwrap();
+    assert_eq!(cut, "Il governo approva la manovra…");
+    assert!(cut.chars().count() <= 30);
+    // Accented characters count once
+    assert_eq!(budget::truncate_text("più più più", 6).unwrap(), "più…");
+}
+
+#[tokio::test]
+async fn descriptions_and_items_are_cut_to_the_budget() {
+    let upstream = upstream().await;
+    let app = spawn_app(budget_config(
+        &upstream,
+        Budget {
+            max_description_chars: Some(20),
+            max_items: Some(4),
+            max_response_bytes: None,
+        },
+    ))
+    .await;
+
+    let (body, _) = get_json(format!("{}/api/news", app)).await;
+    assert_eq!(body["truncated"], true);
+    let news = body["news"].as_array().unwrap();
+    assert_eq!(news.len(), 4);
+    for item in news {
+        let description = item["description"].as_str().unwrap();
+        assert!(description.chars().count() <= 20);
+        if description.ends_with('…') {
+            assert_eq!(item["truncated"], true);
+        } else {
+            assert!(item.get("truncated").is_none());
+        }
+    }
+    assert!(news.iter().any(|item| item["truncated"] == true));
+
+    let (body, _) = get_json(format!("{}/api/v2/news", app)).await;
+    assert_eq!(body["count"], 4);
+    assert_eq!(body["news"].as_array().unwrap().len(), 4);
+
+    // Without a budget nothing is marked
+    let (body, _) = get_json(format!(
+        "{}/api/news",
+        spawn_app(test_config(&upstream.uri())).await
+    ))
+    .await;
+    assert!(body.get("truncated").is_none());
+    assert_eq!(body["news"].as_array().unwrap().len(), 6);
+}
+
+#[tokio::test]
+async fn items_are_dropped_until_the_body_fits() {
+    let upstream = upstream().await;
+    let app = spawn_app(budget_config(
+        &upstream,
+        Budget {
+            max_response_bytes: Some(1200),
+            ..Budget::default()
+        },
+    ))
+    .await;
+
+    let (body, size) = get_json(format!("{}/api/news", app)).await;
+    assert!(size <= 1200, "{} bytes", size);
+    assert_eq!(body["truncated"], true);
+    let kept = body["news"].as_array().unwrap();
+    assert!(!kept.is_empty() && kept.len() < 6);
+    // The most prominent items are the ones kept
+    let (full, _) = get_json(format!(
+        "{}/api/news",
+        spawn_app(test_config(&upstream.uri())).await
+    ))
+    .await;
+    assert_eq!(kept[..], full["news"].as_array().unwrap()[..kept.len()]);
+}
+
+#[test]
+fn dropping_items_lands_on_the_same_body_as_measuring_each_time() {
+    let items: Vec<Value> = (0..40)
+        .map(|n| {
+            serde_json::json!({
+                "title": format!("Titolo {}", n),
+                "description": "x".repeat(n * 7 % 90),
+            })
+        })
+        .collect();
+    let body = serde_json::json!({ "count": 40, "news": items, "error": null });
+
+    for max_bytes in [0, 60, 500, 1234, 2500, 4000, 100_000] {
+        let mut trimmed = body.clone();
+        Budget {
+            max_response_bytes: Some(max_bytes),
+            ..Budget::default()
+        }
+        .apply(&mut trimmed);
+
+        let kept = trimmed["news"].as_array().unwrap().len();
+        assert_eq!(trimmed["count"], kept);
+        if kept == 40 {
+            assert!(trimmed.get("truncated").is_none());
+            continue;
+        }
+        assert_eq!(trimmed["truncated"], true);
+        let size = |body: &Value| serde_json::to_vec(body).unwrap().len();
+        if kept > 0 {
+            assert!(
+                size(&trimmed) <= max_bytes,
+                "{} over {}",
+                size(&trimmed),
+                max_bytes
+            );
+        }
+        // One more item would not have fit
+        let mut one_more = trimmed.clone();
+        one_more["news"]
+            .as_array_mut()
+            .unwrap()
+            .push(body["news"][kept].clone());
+        one_more["count"] = Value::from(kept + 1);
+        assert!(size(&one_more) > max_bytes);
+    }
+}
+
+#[tokio::test]
+async fn html_and_rss_keep_to_the_budget() {
+    let upstream = upstream().await;
+    let app = spawn_app(budget_config(
+        &upstream,
+        Budget {
+            max_description_chars: Some(20),
+            max_items: Some(2),
+            max_response_bytes: None,
+        },
+    ))
+    .await;
+
+    let html = reqwest::get(format!("{}/api/news?format=html", app))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(html.matches("<li>").count(), 2);
+
+    let rss = reqwest::get(format!("{}/api/news?format=rss", app))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert_eq!(rss.matches("<item>").count(), 2);
+    for description in rss.split("<description>").skip(2) {
+        let description = &description[..description.find("</description>").unwrap()];
+        assert!(description.chars().count() <= 20, "{}", description);
+    }
+}
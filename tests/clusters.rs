@@ -0,0 +1,165 @@
+mod common;
+
+use common::{news_item, spawn_app, test_config, TestSource};
+use corriere_scraper::clusters::{self, SourcedItem};
+use corriere_scraper::config::Config;
+use corriere_scraper::NewsItem;
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+// An outlet front page marked up like the homepage cards
+fn outlet_page(headlines: &[(&str, &str)]) -> String {
+    let cards: String = headlines
+        .iter()
+        .map(|(link, title)| {
+            format!(
+                r#"<div class="bck-media-news"><h4 class="title-art-hp"><a href="{}">{}</a></h4></div>"#,
+                link, title
+            )
+        })
+        .collect();
+    format!(
+        r#"<html><body><main class="body-hp">{}</main></body></html>"#,
+        cards
+    )
+}
+
+fn item(source: &str, title: &str) -> SourcedItem {
+    SourcedItem {
+        source: source.to_string(),
+        item: NewsItem {
+            description: String::new(),
+            ..news_item(
+                title,
+                &format!("https://{}.example/{}", source, title.len()),
+            )
+        },
+    }
+}
+
+#[test]
+fn similar_headlines_score_high_and_unrelated_ones_low() {
+    let manovra = "Manovra, il governo pone la fiducia: voto entro venerdì";
+    assert!(
+        clusters::similarity(
+            manovra,
+            "Manovra, fiducia del governo alla Camera: voto venerdì"
+        ) > 0.7
+    );
+    // Word order doesn't matter
+    assert_eq!(
+        clusters::similarity("Kiev, droni russi", "Droni russi su Kiev"),
+        1.0
+    );
+    assert!(clusters::similarity(manovra, "Inter, festa scudetto a San Siro") < 0.3);
+    assert_eq!(clusters::similarity("", manovra), 0.0);
+}
+
+#[test]
+fn clusters_take_one_item_per_source_with_the_first_as_canonical() {
+    let clusters = clusters::cluster(
+        vec![
+            item("corriere", "Inter, festa scudetto a San Siro"),
+            item("corriere", "Ucraina, attacco di droni su Kiev"),
+            item("ansa", "Ucraina, droni russi su Kiev"),
+            item("ansa", "Attacco di droni su Kiev in Ucraina"),
+            item("repubblica", "Kiev, nuovo attacco di droni"),
+        ],
+        0.5,
+    );
+
+    assert_eq!(clusters.len(), 3);
+    let kiev = &clusters[0];
+    assert_eq!(kiev.sources, vec!["corriere", "ansa", "repubblica"]);
+    assert_eq!(
+        kiev.canonical.item.title,
+        "Ucraina, attacco di droni su Kiev"
+    );
+    // The second ANSA item can't join a cluster that already has ANSA
+    assert_eq!(
+        kiev.alternates[0].item.title,
+        "Ucraina, droni russi su Kiev"
+    );
+    assert_eq!(clusters[1].sources, vec!["corriere"]);
+    assert_eq!(clusters[2].sources, vec!["ansa"]);
+}
+
+#[tokio::test]
+async fn clusters_endpoint_groups_the_homepage_with_other_outlets() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&upstream)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/ansa/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(outlet_page(&[
+            ("/ansa/kiev.html", "Ucraina, droni russi su Kiev"),
+            (
+                "/ansa/manovra.html",
+                "Manovra, fiducia del governo alla Camera: voto venerdì",
+            ),
+            ("/ansa/juve.html", "Juventus, cambia l'allenatore"),
+        ])))
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(Config {
+        news_sources: vec![
+            ("ansa".to_string(), format!("{}/ansa/", upstream.uri())),
+            ("down".to_string(), "http://127.0.0.1:9/".to_string()),
+        ],
+        ..test_config(&upstream.uri())
+    })
+    .await;
+
+    let response = reqwest::get(format!("{}/api/clusters", app)).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["count"], 2);
+    let manovra = &body["clusters"][0];
+    assert_eq!(manovra["sources"], serde_json::json!(["corriere", "ansa"]));
+    assert_eq!(
+        manovra["canonical"]["title"],
+        "Manovra, il governo pone la fiducia: voto entro venerdì"
+    );
+    assert_eq!(
+        manovra["alternates"][0]["link"],
+        format!("{}/ansa/manovra.html", upstream.uri())
+    );
+    assert_eq!(
+        body["clusters"][1]["canonical"]["title"],
+        "Ucraina, attacco di droni su Kiev"
+    );
+    assert!(body["errors"]["down"].is_string());
+
+    let all: Value = reqwest::get(format!("{}/api/clusters?min_sources=1", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    // 6 homepage stories, 2 of them shared, plus the Juventus one
+    assert_eq!(all["count"], 7);
+
+    let response = reqwest::get(format!("{}/api/clusters?min_sources=4", app))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[test]
+fn cluster_similarity_must_be_between_0_and_1() {
+    let with = |value: &'static str| {
+        Config::from_lookup(&move |name| (name == "CLUSTER_SIMILARITY").then(|| value.to_string()))
+    };
+
+    assert_eq!(with("0.7").unwrap().cluster_similarity, 0.7);
+    for value in ["1.5", "-0.1", "NaN"] {
+        let error = with(value).err().unwrap();
+        assert!(error.contains("CLUSTER_SIMILARITY"), "{}", error);
+    }
+}
@@ -0,0 +1,117 @@
+// Shared helpers for the integration tests: fixture pages, golden files and
+// a way to run the app against a mock upstream
+#![allow(dead_code)]
+
+use corriere_scraper::config::Config;
+use corriere_scraper::extract::{self, ParseMode, SelectorConfig, Selectors};
+use corriere_scraper::{router, AppState, NewsItem};
+use serde_json::Value;
+use std::path::PathBuf;
+
+// A stored page under tests/fixtures, with its expected output under tests/golden
+pub struct TestSource {
+    pub name: &'static str,
+}
+
+impl TestSource {
+    pub const fn new(name: &'static str) -> TestSource {
+        TestSource { name }
+    }
+
+    pub fn html(&self) -> String {
+        let path = fixtures_dir().join(format!("{}.html", self.name));
+        std::fs::read_to_string(&path)
+            .unwrap_or_else(|e| panic!("Failed to read fixture {}: {}", path.display(), e))
+    }
+
+    // Runs the homepage extraction with the default selectors
+    pub fn extract_news(&self, mode: ParseMode) -> Vec<NewsItem> {
+        let selectors = Selectors::parse(&SelectorConfig::default()).unwrap();
+        extract::extract_news(
+            &self.html(),
+            &selectors,
+            extract::CORRIERE_BASE_URL,
+            extract::HOMEPAGE_LIMIT,
+            mode,
+        )
+    }
+
+    // Compares `actual` with tests/golden/<name>.json. Run the tests with
+    // UPDATE_GOLDEN=1 to rewrite the golden file after an intended change
+    pub fn assert_golden(&self, actual: &Value) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/golden")
+            .join(format!("{}.json", self.name));
+
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            let json = serde_json::to_string_pretty(actual).unwrap();
+            std::fs::write(&path, json + "\n").unwrap();
+            return;
+        }
+
+        let golden = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+            panic!(
+                "Failed to read {}: {} (run with UPDATE_GOLDEN=1 to create it)",
+                path.display(),
+                e
+            )
+        });
+        let expected: Value = serde_json::from_str(&golden).unwrap();
+        assert_eq!(
+            &expected,
+            actual,
+            "output differs from {}; run with UPDATE_GOLDEN=1 if the change is intended",
+            path.display()
+        );
+    }
+}
+
+pub fn fixtures_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+}
+
+// A homepage item with a title, a link and a summary and nothing else. Tests
+// set the rest with struct update syntax, e.g. NewsItem { image_url, ..news_item(..) }
+pub fn news_item(title: &str, link: &str) -> NewsItem {
+    NewsItem {
+        title: title.to_string(),
+        description: "Il riassunto".to_string(),
+        link: link.to_string(),
+        ..NewsItem::default()
+    }
+}
+
+// Config pointing at a mock upstream instead of corriere.it. Caching is
+// off, so every request scrapes upstream unless a test turns it on
+pub fn test_config(upstream: &str) -> Config {
+    Config {
+        homepage_url: format!("{}/", upstream),
+        scrape_allowed_hosts: vec!["127.0.0.1".to_string()],
+        cache_soft_ttl_secs: 0,
+        cache_hard_ttl_secs: 0,
+        ..Config::default()
+    }
+}
+
+// Empty directory under the system temp dir, for tests that write to DATA_DIR
+pub fn temp_data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("corriere-test-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+// Starts the app on a random local port and returns its base URL
+pub async fn spawn_app(config: Config) -> String {
+    spawn_state(AppState::new(config)).await
+}
+
+// Like spawn_app, for tests that also drive the state directly
+pub async fn spawn_state(state: AppState) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = router(state);
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    format!("http://{}", addr)
+}
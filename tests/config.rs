@@ -0,0 +1,75 @@
+mod common;
+
+use common::{temp_data_dir, test_config};
+use corriere_scraper::config::{self, Config};
+use corriere_scraper::server;
+use std::net::SocketAddr;
+
+#[test]
+fn containers_bind_on_all_interfaces() {
+    assert_eq!(
+        config::default_bind_addr(false, None),
+        "127.0.0.1:3000".parse::<SocketAddr>().unwrap()
+    );
+    assert_eq!(
+        config::default_bind_addr(true, None),
+        "0.0.0.0:3000".parse::<SocketAddr>().unwrap()
+    );
+    assert_eq!(
+        config::default_bind_addr(true, Some(8080)),
+        "0.0.0.0:8080".parse::<SocketAddr>().unwrap()
+    );
+}
+
+#[test]
+fn data_dir_must_be_writable() {
+    let dir = temp_data_dir("config-data-dir");
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = Config {
+        data_dir: dir.join("nested"),
+        ..test_config("http://127.0.0.1:9")
+    };
+    // Checking creates nothing
+    config.check_data_dir().unwrap();
+    assert!(!config.data_dir.exists());
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    config.create_data_dir().unwrap();
+    config.check_data_dir().unwrap();
+    assert!(config.data_dir.is_dir());
+    // The probe file is cleaned up
+    assert_eq!(std::fs::read_dir(&config.data_dir).unwrap().count(), 0);
+
+    // A file where the directory should be
+    let blocked = dir.join("blocked");
+    std::fs::write(&blocked, "").unwrap();
+    let config = Config {
+        data_dir: blocked.join("data"),
+        ..test_config("http://127.0.0.1:9")
+    };
+    let error = config.check_data_dir().unwrap_err();
+    assert!(error.starts_with(&format!("DATA_DIR {}", config.data_dir.display())));
+    assert!(error.contains("set DATA_DIR to a writable directory"));
+}
+
+#[tokio::test]
+async fn validation_covers_what_serve_builds() {
+    let dir = temp_data_dir("config-validate");
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = Config {
+        data_dir: dir.join("data"),
+        archive_url: Some(format!("file://{}", dir.join("archive").display())),
+        webhooks_enabled: true,
+        ..test_config("http://127.0.0.1:9")
+    };
+    server::validate(&config).await.unwrap();
+    // Nor does validating create DATA_DIR or the archive
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+    let config = Config {
+        data_dir: dir.join("data"),
+        archive_url: Some("ftp://example.com/archive".to_string()),
+        ..test_config("http://127.0.0.1:9")
+    };
+    let error = server::validate(&config).await.err().unwrap();
+    assert!(error.starts_with("Invalid ARCHIVE_URL"), "{}", error);
+}
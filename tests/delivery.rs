@@ -0,0 +1,281 @@
+mod common;
+
+use common::{news_item, spawn_state, temp_data_dir, test_config};
+use corriere_scraper::config::Config;
+use corriere_scraper::delivery::{self, DeliveryQueue, Message, Origin, Sender};
+use corriere_scraper::metrics::Metrics;
+use corriere_scraper::request_id;
+use corriere_scraper::webhooks::{WebhookFormat, WebhookNotifier};
+use corriere_scraper::{AppState, NewsItem};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn item(n: u32) -> NewsItem {
+    news_item(
+        &format!("Notizia {}", n),
+        &format!("https://www.corriere.it/cronache/{}.shtml", n),
+    )
+}
+
+fn delivery_config(name: &str) -> Config {
+    Config {
+        webhooks_enabled: true,
+        data_dir: temp_data_dir(name),
+        delivery_max_attempts: 3,
+        delivery_backoff_secs: 0,
+        admin_token: Some("secret".to_string()),
+        ..test_config("http://127.0.0.1:9")
+    }
+}
+
+fn webhook_message(url: &str) -> Message {
+    Message::Webhook {
+        url: url.to_string(),
+        request_id: "run-1".to_string(),
+        payload: json!({ "event": "new_articles" }),
+    }
+}
+
+#[test]
+fn backoff_doubles_up_to_the_cap_and_dead_letters_stop_retries() {
+    let queue = DeliveryQueue::new(
+        temp_data_dir("delivery-backoff").join("deliveries"),
+        3,
+        Duration::from_secs(30),
+        Duration::from_secs(100),
+        Arc::new(Metrics::default()),
+    );
+    assert_eq!(queue.backoff(1), Duration::from_secs(30));
+    assert_eq!(queue.backoff(2), Duration::from_secs(60));
+    assert_eq!(queue.backoff(3), Duration::from_secs(100));
+
+    let queued = queue
+        .enqueue(webhook_message("http://127.0.0.1:9/hook"), None, "refused")
+        .unwrap();
+    assert_eq!(queued.attempts, 1);
+    // Not due before its backoff is over
+    assert!(queue.due(chrono::Utc::now()).unwrap().is_empty());
+
+    queue
+        .record(&queued.id, Err("refused".to_string()))
+        .unwrap();
+    let dead = queue
+        .record(&queued.id, Err("still refused".to_string()))
+        .unwrap()
+        .unwrap();
+    assert!(dead.dead);
+    assert_eq!(dead.last_error, "still refused");
+    let later = chrono::Utc::now() + chrono::Duration::days(1);
+    assert!(queue.due(later).unwrap().is_empty());
+
+    let requeued = queue.requeue(&queued.id).unwrap().unwrap();
+    assert!(!requeued.dead);
+    assert_eq!(requeued.attempts, 0);
+    assert_eq!(queue.due(chrono::Utc::now()).unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn failed_webhook_delivery_is_retried_from_the_queue() {
+    let receiver = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .mount(&receiver)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .and(header("x-request-id", "run-7"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&receiver)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/other"))
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&receiver)
+        .await;
+
+    // Items no webhook took are sent again on the next run instead, so a
+    // second webhook takes them
+    let state = AppState::new(delivery_config("delivery-webhook"));
+    for hook in ["hook", "other"] {
+        state
+            .webhooks
+            .create(&format!("{}/{}", receiver.uri(), hook), WebhookFormat::Json)
+            .unwrap();
+    }
+    let mut notifier = WebhookNotifier::new(&state.config).unwrap();
+    notifier.notify(&state, &[item(1)]).await.unwrap();
+    let delivered = request_id::scope(
+        "run-7".to_string(),
+        notifier.notify(&state, &[item(2), item(1)]),
+    )
+    .await;
+    assert_eq!(delivered.unwrap(), 1);
+
+    let queued = state.deliveries.list().unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].message.kind(), "webhook");
+    assert!(queued[0].last_error.contains("503"));
+
+    let sender = Sender::new(&state.config, None).unwrap();
+    assert_eq!(delivery::retry_due(&state, &sender).await.unwrap(), 1);
+    assert!(state.deliveries.list().unwrap().is_empty());
+    assert_eq!(
+        state.metrics.counter(
+            "corriere_delivery_retries_total",
+            &[("kind", "webhook"), ("result", "sent")]
+        ),
+        1
+    );
+}
+
+#[tokio::test]
+async fn admin_endpoints_list_requeue_and_delete_deliveries() {
+    let state = AppState::new(delivery_config("delivery-admin"));
+    let first = state
+        .deliveries
+        .enqueue(webhook_message("http://127.0.0.1:9/a"), None, "refused")
+        .unwrap();
+    let second = state
+        .deliveries
+        .enqueue(webhook_message("http://127.0.0.1:9/b"), None, "refused")
+        .unwrap();
+    for _ in 0..2 {
+        state
+            .deliveries
+            .record(&first.id, Err("refused".to_string()))
+            .unwrap();
+    }
+    let app = spawn_state(state).await;
+    let client = reqwest::Client::new();
+
+    let anonymous = reqwest::get(format!("{}/api/admin/deliveries", app))
+        .await
+        .unwrap();
+    assert_eq!(anonymous.status(), 401);
+
+    let listed: Value = client
+        .get(format!("{}/api/admin/deliveries", app))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(listed["pending"], 1);
+    assert_eq!(listed["dead"], 1);
+    assert_eq!(listed["deliveries"][0]["message"]["kind"], "webhook");
+    assert_eq!(listed["deliveries"][0]["dead"], true);
+
+    let requeued: Value = client
+        .post(format!("{}/api/admin/deliveries/{}/requeue", app, first.id))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(requeued["delivery"]["dead"], false);
+    assert_eq!(requeued["delivery"]["attempts"], 0);
+
+    let deleted = client
+        .delete(format!("{}/api/admin/deliveries/{}", app, second.id))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(deleted.status(), 204);
+    let missing = client
+        .post(format!(
+            "{}/api/admin/deliveries/{}/requeue",
+            app, second.id
+        ))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), 404);
+}
+
+#[tokio::test]
+async fn retries_stop_once_the_subscription_is_gone() {
+    let receiver = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&receiver)
+        .await;
+
+    let state = AppState::new(delivery_config("delivery-unsubscribed"));
+    let mut queued = vec![];
+    for hook in ["kept", "deleted"] {
+        let url = format!("{}/{}", receiver.uri(), hook);
+        let webhook = state.webhooks.create(&url, WebhookFormat::Json).unwrap();
+        let origin = Some(Origin::Webhook(webhook.id.clone()));
+        state
+            .deliveries
+            .enqueue(webhook_message(&url), origin, "refused")
+            .unwrap();
+        queued.push(webhook);
+    }
+    state
+        .webhooks
+        .delete(&queued[1].id, &queued[1].token)
+        .unwrap();
+
+    let sender = Sender::new(&state.config, None).unwrap();
+    assert_eq!(delivery::retry_due(&state, &sender).await.unwrap(), 1);
+    assert!(state.deliveries.list().unwrap().is_empty());
+    assert_eq!(
+        state.metrics.counter(
+            "corriere_delivery_retries_total",
+            &[("kind", "webhook"), ("result", "dropped")]
+        ),
+        1
+    );
+}
+
+#[test]
+fn each_delivery_is_its_own_file_and_old_queues_are_moved() {
+    let dir = temp_data_dir("delivery-files");
+    let queue = || {
+        DeliveryQueue::new(
+            dir.join("deliveries"),
+            3,
+            Duration::from_secs(30),
+            Duration::from_secs(100),
+            Arc::new(Metrics::default()),
+        )
+    };
+    let first = queue()
+        .enqueue(webhook_message("http://127.0.0.1:9/a"), None, "refused")
+        .unwrap();
+    assert!(dir
+        .join("deliveries")
+        .join(format!("{}.json", first.id))
+        .is_file());
+
+    // A queue from before, kept in a single file
+    let mut legacy = first.clone();
+    legacy.id = "legacy1".to_string();
+    std::fs::write(
+        dir.join("deliveries.json"),
+        serde_json::to_string(&[&legacy]).unwrap(),
+    )
+    .unwrap();
+    let listed = queue().list().unwrap();
+    assert_eq!(listed.len(), 2);
+    assert!(!dir.join("deliveries.json").exists());
+
+    // Ids that aren't ours don't name files
+    assert!(!queue().delete("../deliveries").unwrap());
+    assert!(queue().delete("legacy1").unwrap());
+    assert_eq!(queue().list().unwrap().len(), 1);
+}
@@ -0,0 +1,121 @@
+mod common;
+
+use common::{spawn_app, spawn_state, test_config, TestSource};
+use corriere_scraper::config::Config;
+use corriere_scraper::demo::DemoMode;
+use corriere_scraper::AppState;
+use serde_json::Value;
+use wiremock::matchers::method;
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+#[tokio::test]
+async fn always_mode_serves_the_sample_without_contacting_upstream() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(Config {
+        demo_mode: DemoMode::Always,
+        ..test_config(&upstream.uri())
+    })
+    .await;
+
+    let response = reqwest::get(format!("{}/api/news", app)).await.unwrap();
+    assert_eq!(response.headers()["x-demo"], "true");
+    assert_eq!(response.headers()["cache-control"], "no-store");
+    let body: Value = response.json().await.unwrap();
+    let golden: Value = serde_json::from_str(
+        &std::fs::read_to_string(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/homepage.json"),
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(body["news"], golden);
+    // The v1 shape is unchanged
+    assert!(body.get("demo").is_none());
+
+    let body: Value = reqwest::get(format!("{}/api/v2/news", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["demo"], true);
+    assert_eq!(body["count"], golden.as_array().unwrap().len());
+}
+
+#[tokio::test]
+async fn fallback_mode_only_steps_in_while_upstream_fails() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .mount(&upstream)
+        .await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(Config {
+        demo_mode: DemoMode::Fallback,
+        ..test_config(&upstream.uri())
+    })
+    .await;
+
+    let body: Value = reqwest::get(format!("{}/api/v2/news", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["demo"], true);
+
+    let response = reqwest::get(format!("{}/api/news", app)).await.unwrap();
+    assert!(response.headers().get("x-demo").is_none());
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["stale"], false);
+}
+
+#[tokio::test]
+async fn sample_data_reaches_every_endpoint_but_no_background_job() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&upstream)
+        .await;
+    let job = format!("sport:15m:{}/sport/", upstream.uri())
+        .parse()
+        .unwrap();
+    let state = AppState::new(Config {
+        demo_mode: DemoMode::Always,
+        scheduler_jobs: vec![job],
+        ..test_config(&upstream.uri())
+    });
+    let app = spawn_state(state.clone()).await;
+
+    for path in [
+        "/api/sections/sport",
+        "/api/local/milano",
+        "/api/digest/today",
+    ] {
+        let response = reqwest::get(format!("{}{}", app, path)).await.unwrap();
+        assert_eq!(response.status(), 200, "{}", path);
+        assert_eq!(response.headers()["x-demo"], "true", "{}", path);
+    }
+    let local: Value = reqwest::get(format!("{}/api/local/milano", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(local["demo"], true);
+    assert!(!local["news"].as_array().unwrap().is_empty());
+
+    // What notifiers and exports read is never the sample
+    assert!(corriere_scraper::get_news(&state).await.is_err());
+}
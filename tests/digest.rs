@@ -0,0 +1,69 @@
+mod common;
+
+use chrono::{TimeZone, Utc};
+use common::{news_item, test_config};
+use corriere_scraper::config::Config;
+use corriere_scraper::{digest, NewsItem};
+
+#[test]
+fn digest_escapes_headlines_and_links_unsubscribe() {
+    let news = vec![NewsItem {
+        description: String::new(),
+        ..news_item("Tasse <e> pensioni", "https://www.corriere.it/n/1.shtml")
+    }];
+
+    let (text, html) = digest::render(
+        "giovedì 15 ottobre 2026",
+        &news,
+        "http://localhost/api/subscriptions/abc/unsubscribe?token=xyz",
+    )
+    .unwrap();
+
+    assert!(html.contains("Tasse &lt;e&gt; pensioni"));
+    assert!(html.contains("Le notizie del giovedì 15 ottobre 2026"));
+    assert!(text.contains("- Tasse <e> pensioni\n  https://www.corriere.it/n/1.shtml"));
+    assert!(text.contains("unsubscribe?token=xyz"));
+}
+
+#[tokio::test]
+async fn emails_offer_one_click_unsubscribe() {
+    let mailer = digest::Mailer::from_config(&Config {
+        smtp_url: Some("smtp://localhost:2525".to_string()),
+        ..test_config("http://127.0.0.1:9")
+    })
+    .unwrap()
+    .unwrap();
+    let url = "http://localhost/api/subscriptions/abc/unsubscribe?token=xyz";
+
+    let message = mailer
+        .message(
+            "lettore@example.com",
+            "Le notizie",
+            "testo".to_string(),
+            "<p>testo</p>".to_string(),
+            Some(url),
+        )
+        .unwrap();
+    let headers = String::from_utf8(message.formatted()).unwrap();
+    assert!(headers.contains(&format!("List-Unsubscribe: <{}>", url)));
+    assert!(headers.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+}
+
+#[test]
+fn digest_runs_at_the_local_hour() {
+    let rome = chrono_tz::Europe::Rome;
+
+    // 06:00 in Rome (summer time): today at 07:00 local is still ahead
+    let now = Utc.with_ymd_and_hms(2026, 7, 1, 4, 0, 0).unwrap();
+    assert_eq!(
+        digest::next_run(now, 7, rome),
+        Utc.with_ymd_and_hms(2026, 7, 1, 5, 0, 0).unwrap()
+    );
+
+    // Exactly at the hour the next run is tomorrow
+    let now = Utc.with_ymd_and_hms(2026, 12, 1, 6, 0, 0).unwrap();
+    assert_eq!(
+        digest::next_run(now, 7, rome),
+        Utc.with_ymd_and_hms(2026, 12, 2, 6, 0, 0).unwrap()
+    );
+}
@@ -0,0 +1,224 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use common::{news_item, spawn_app, spawn_state, temp_data_dir, test_config, TestSource};
+use corriere_scraper::archive::{self, ArchivedScrape};
+use corriere_scraper::config::Config;
+use corriere_scraper::{AppState, NewsItem};
+use std::io::{Cursor, Read};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use zip::ZipArchive;
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+const ARTICLE: TestSource = TestSource::new("article");
+
+async fn mock_corriere() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/article.shtml"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(ARTICLE.html()))
+        .mount(&server)
+        .await;
+    server
+}
+
+fn edition_config(upstream: &MockServer, name: &str) -> Config {
+    let data_dir = temp_data_dir(name);
+    Config {
+        archive_url: Some(format!("file://{}", data_dir.join("archive").display())),
+        data_dir,
+        ..test_config(&upstream.uri())
+    }
+}
+
+// Archives a scrape from yesterday whose first item links to the mock article
+async fn archived_state(upstream: &MockServer, config: Config) -> AppState {
+    let archive = archive::connect(&config).await.unwrap().unwrap();
+    archive
+        .record(&ArchivedScrape {
+            scraped_at: Utc::now() - Duration::days(1),
+            news: vec![
+                NewsItem {
+                    description: "Sommario dall'archivio".to_string(),
+                    ..news_item(
+                        "Titolo dall'archivio",
+                        &format!("{}/article.shtml", upstream.uri()),
+                    )
+                },
+                NewsItem {
+                    description: "Solo il sommario".to_string(),
+                    ..news_item(
+                        "Pezzo irraggiungibile",
+                        &format!("{}/missing.shtml", upstream.uri()),
+                    )
+                },
+            ],
+        })
+        .await
+        .unwrap();
+    let mut state = AppState::new(config);
+    state.archive = Some(archive);
+    state
+}
+
+fn yesterday(config: &Config) -> String {
+    (Utc::now().with_timezone(&config.digest_time_zone) - Duration::days(1))
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+fn entry(book: &[u8], name: &str) -> String {
+    let mut zip = ZipArchive::new(Cursor::new(book)).unwrap();
+    let mut text = String::new();
+    zip.by_name(name)
+        .unwrap()
+        .read_to_string(&mut text)
+        .unwrap();
+    text
+}
+
+#[tokio::test]
+async fn past_edition_is_built_from_the_archive() {
+    let upstream = mock_corriere().await;
+    let config = edition_config(&upstream, "edition-past");
+    let day = yesterday(&config);
+    let app = spawn_state(archived_state(&upstream, config).await).await;
+
+    let response = reqwest::get(format!("{}/api/edition/{}.epub", app, day))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/epub+zip");
+    let book = response.bytes().await.unwrap();
+
+    // The mimetype comes first, uncompressed, as e-readers expect
+    let mut zip = ZipArchive::new(Cursor::new(&book[..])).unwrap();
+    let mimetype = zip.by_index(0).unwrap();
+    assert_eq!(mimetype.name(), "mimetype");
+    assert_eq!(mimetype.compression(), zip::CompressionMethod::Stored);
+    drop(mimetype);
+    assert!(zip.by_name("META-INF/container.xml").is_ok());
+
+    let package = entry(&book, "OEBPS/content.opf");
+    assert!(package.contains(&format!("<dc:date>{}</dc:date>", day)));
+    assert!(package.contains("chapter-2.xhtml"));
+
+    // The first article's body comes from the article page, the second falls
+    // back to its summary
+    let first = entry(&book, "OEBPS/chapter-1.xhtml");
+    assert!(first.contains("di Mario Rossi"));
+    let second = entry(&book, "OEBPS/chapter-2.xhtml");
+    assert!(second.contains("<p>Solo il sommario</p>"));
+    assert!(second.contains("Pezzo irraggiungibile"));
+}
+
+#[tokio::test]
+async fn oversized_images_are_left_out() {
+    let upstream = mock_corriere().await;
+    for (name, bytes) in [("/small.jpg", 1024), ("/big.jpg", 3 * 1024 * 1024)] {
+        Mock::given(method("GET"))
+            .and(path(name))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(vec![0u8; bytes], "image/jpeg"))
+            .mount(&upstream)
+            .await;
+    }
+    let config = edition_config(&upstream, "edition-images");
+    let day = yesterday(&config);
+    let archive = archive::connect(&config).await.unwrap().unwrap();
+    let news = ["/small.jpg", "/big.jpg"]
+        .iter()
+        .enumerate()
+        .map(|(index, image)| NewsItem {
+            image_url: Some(format!("{}{}", upstream.uri(), image)),
+            ..news_item(
+                "Senza articolo",
+                &format!("{}/missing-{}.shtml", upstream.uri(), index),
+            )
+        })
+        .collect();
+    archive
+        .record(&ArchivedScrape {
+            scraped_at: Utc::now() - Duration::days(1),
+            news,
+        })
+        .await
+        .unwrap();
+    let mut state = AppState::new(config);
+    state.archive = Some(archive);
+    let app = spawn_state(state).await;
+
+    let book = reqwest::get(format!("{}/api/edition/{}.epub", app, day))
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    let package = entry(&book, "OEBPS/content.opf");
+    assert!(package.contains(r#"id="image-1""#));
+    assert!(!package.contains(r#"id="image-2""#));
+}
+
+#[tokio::test]
+async fn today_edition_uses_the_homepage() {
+    let upstream = mock_corriere().await;
+    let config = Config {
+        edition_top_n: 3,
+        ..edition_config(&upstream, "edition-today")
+    };
+    let data_dir = config.data_dir.clone();
+    let app = spawn_app(config).await;
+
+    let response = reqwest::get(format!("{}/api/edition/today.epub", app))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let book = response.bytes().await.unwrap();
+
+    let nav = entry(&book, "OEBPS/nav.xhtml");
+    assert_eq!(nav.matches("<li>").count(), 3);
+    let stored = std::fs::read_dir(data_dir.join("editions"))
+        .unwrap()
+        .count();
+    assert_eq!(stored, 1);
+}
+
+#[tokio::test]
+async fn unknown_editions_are_not_found() {
+    let upstream = mock_corriere().await;
+    let app = spawn_app(edition_config(&upstream, "edition-unknown")).await;
+
+    let missing = reqwest::get(format!("{}/api/edition/2001-01-01.epub", app))
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), 404);
+    let invalid = reqwest::get(format!("{}/api/edition/yesterday.epub", app))
+        .await
+        .unwrap();
+    assert_eq!(invalid.status(), 400);
+}
+
+#[tokio::test]
+async fn opds_catalog_lists_archived_days() {
+    let upstream = mock_corriere().await;
+    let config = edition_config(&upstream, "edition-opds");
+    let day = yesterday(&config);
+    let public_url = config.public_url.clone();
+    let app = spawn_state(archived_state(&upstream, config).await).await;
+
+    let response = reqwest::get(format!("{}/api/opds", app)).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("application/atom+xml"));
+    let feed = response.text().await.unwrap();
+
+    assert_eq!(feed.matches("<entry>").count(), 2);
+    assert!(feed.contains(&format!("href=\"{}/api/edition/{}.epub\"", public_url, day)));
+}
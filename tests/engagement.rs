@@ -0,0 +1,131 @@
+mod common;
+
+use chrono::{DateTime, Utc};
+use common::{spawn_state, temp_data_dir, test_config};
+use corriere_scraper::archive::{self, ArchivedScrape};
+use corriere_scraper::config::Config;
+use corriere_scraper::engagement::{self, Engagement};
+use corriere_scraper::extract::{self, ParseMode, SelectorConfig, Selectors};
+use corriere_scraper::AppState;
+use serde_json::Value;
+
+const TEASERS: &str = r#"
+<div class="body-hp">
+  <div class="bck-media-news">
+    <h4 class="title-art-hp"><a href="/politica/manovra.shtml">Manovra, il governo pone la fiducia</a></h4>
+    <a class="is-comments" href="/politica/manovra.shtml#commenti">1.234 commenti</a>
+    <span class="share-count" data-shares="56"></span>
+  </div>
+  <div class="bck-media-news">
+    <h4 class="title-art-hp"><a href="/esteri/vertice.shtml">Vertice a Bruxelles</a></h4>
+  </div>
+</div>
+"#;
+
+fn at(timestamp: &str) -> DateTime<Utc> {
+    timestamp.parse().unwrap()
+}
+
+#[test]
+fn counters_are_read_from_teasers() {
+    assert_eq!(engagement::parse_count("1,2k"), Some(1200));
+    assert_eq!(engagement::parse_count("3 mila"), Some(3000));
+    assert_eq!(engagement::parse_count("Commenta"), None);
+
+    let selectors = Selectors::parse(&SelectorConfig::default()).unwrap();
+    let news = extract::extract_news(
+        TEASERS,
+        &selectors,
+        extract::CORRIERE_BASE_URL,
+        extract::HOMEPAGE_LIMIT,
+        ParseMode::Document,
+    );
+    assert_eq!(
+        news[0].engagement,
+        Some(Engagement {
+            comments: Some(1234),
+            shares: Some(56),
+        })
+    );
+    assert_eq!(news[1].engagement, None);
+}
+
+#[tokio::test]
+async fn engagement_is_archived_as_a_time_series() {
+    let config = Config {
+        archive_url: Some(format!(
+            "file://{}",
+            temp_data_dir("engagement-api").display()
+        )),
+        ..test_config("http://127.0.0.1:1")
+    };
+    let archive = archive::connect(&config).await.unwrap().unwrap();
+    let selectors = Selectors::parse(&SelectorConfig::default()).unwrap();
+    // The article left the homepage on the 14th, so the 13th is not read
+    for (scraped_at, comments) in [
+        ("2026-10-13T08:00:00Z", "5"),
+        ("2026-10-14T08:00:00Z", ""),
+        ("2026-10-15T08:00:00Z", "12"),
+        ("2026-10-15T09:00:00Z", "40"),
+    ] {
+        let html = match comments {
+            "" => TEASERS.replace("/politica/manovra.shtml", "/politica/altro.shtml"),
+            comments => TEASERS.replace("1.234", comments),
+        };
+        let news = extract::extract_news(
+            &html,
+            &selectors,
+            extract::CORRIERE_BASE_URL,
+            extract::HOMEPAGE_LIMIT,
+            ParseMode::Document,
+        );
+        archive
+            .record(&ArchivedScrape {
+                scraped_at: at(scraped_at),
+                news,
+            })
+            .await
+            .unwrap();
+    }
+    let mut state = AppState::new(config);
+    state.archive = Some(archive);
+    let app = spawn_state(state).await;
+
+    let body: Value = reqwest::Client::new()
+        .get(format!("{}/api/article/engagement", app))
+        .query(&[("url", "https://www.corriere.it/politica/manovra.shtml")])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let comments: Vec<&Value> = body["points"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|point| &point["comments"])
+        .collect();
+    assert_eq!(comments, [12, 40]);
+    assert_eq!(body["points"][1]["shares"], 56);
+    assert_eq!(body["points"][0]["scraped_at"], "2026-10-15T08:00:00Z");
+
+    // Items without counters have no series
+    let body: Value = reqwest::Client::new()
+        .get(format!("{}/api/article/engagement", app))
+        .query(&[("url", "https://www.corriere.it/esteri/vertice.shtml")])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["points"], serde_json::json!([]));
+
+    let response = reqwest::get(format!("{}/api/article/engagement", app))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["parameter"], "url");
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use chrono::Utc;
+use common::{news_item, spawn_state, temp_data_dir, test_config, TestSource};
+use corriere_scraper::archive::{self, ArchivedScrape};
+use corriere_scraper::config::Config;
+use corriere_scraper::entities::{Entity, Gazetteer, Kind};
+use corriere_scraper::AppState;
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+fn entity(name: &str, kind: Kind) -> Entity {
+    Entity {
+        name: name.to_string(),
+        kind,
+    }
+}
+
+#[test]
+fn gazetteer_finds_whole_names_and_prefers_the_longest() {
+    let gazetteer = Gazetteer::default();
+
+    assert_eq!(
+        gazetteer.extract("Meloni e Tajani: Forza Italia resta nel governo della Banca d’Italia"),
+        vec![
+            entity("Giorgia Meloni", Kind::Person),
+            entity("Antonio Tajani", Kind::Person),
+            entity("Forza Italia", Kind::Organization),
+            entity("Banca d'Italia", Kind::Organization),
+        ]
+    );
+    // Case and word boundaries matter: "lega" the verb, "Milano" isn't "Milan"
+    assert_eq!(
+        gazetteer.extract("Il filo che lega Milano e il Milan, Giorgia Meloni e Meloni"),
+        vec![
+            entity("Milano", Kind::Place),
+            entity("Milan", Kind::Organization),
+            entity("Giorgia Meloni", Kind::Person),
+        ]
+    );
+}
+
+#[test]
+fn gazetteer_file_adds_and_replaces_entries() {
+    let dir = temp_data_dir("entities-gazetteer");
+    let file = dir.join("entities.json");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        &file,
+        r#"[
+            { "name": "Brembo", "kind": "organization" },
+            { "name": "Milan", "kind": "organization", "aliases": ["rossoneri"] }
+        ]"#,
+    )
+    .unwrap();
+
+    let gazetteer = Gazetteer::load(&file).unwrap();
+
+    assert_eq!(
+        gazetteer.extract("Brembo sponsor dei rossoneri"),
+        vec![
+            entity("Brembo", Kind::Organization),
+            entity("Milan", Kind::Organization),
+        ]
+    );
+    assert_eq!(
+        gazetteer.find("giorgia-meloni").unwrap().name,
+        "Giorgia Meloni"
+    );
+    assert_eq!(gazetteer.find("ROSSONERI").unwrap().name, "Milan");
+    assert!(gazetteer.find("Nessuno").is_none());
+}
+
+#[tokio::test]
+async fn entity_news_follows_the_homepage_and_the_archive() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&upstream)
+        .await;
+    let config = Config {
+        archive_url: Some(format!(
+            "file://{}",
+            temp_data_dir("entities-archive").display()
+        )),
+        ..test_config(&upstream.uri())
+    };
+    let mut state = AppState::new(config);
+    state.archive = archive::connect(&state.config).await.unwrap();
+    let storage = state.archive.clone().unwrap();
+    storage.prepare().await.unwrap();
+    storage
+        .record(&ArchivedScrape {
+            scraped_at: Utc::now() - chrono::Duration::days(1),
+            news: vec![
+                news_item("Milano, nuove piste ciclabili", "https://www.corriere.it/a"),
+                news_item("Il Milan vince il derby", "https://www.corriere.it/b"),
+            ],
+        })
+        .await
+        .unwrap();
+    let app = spawn_state(state).await;
+
+    let body: Value = reqwest::get(format!("{}/api/entities/milano/news", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["entity"]["name"], "Milano");
+    assert_eq!(body["entity"]["kind"], "place");
+    let news = body["news"].as_array().unwrap();
+    let titles: Vec<&str> = news
+        .iter()
+        .map(|item| item["title"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        titles,
+        vec![
+            "Borsa, Milano apre in rialzo",
+            "Milano, la M4 arriva a San Cristoforo",
+            "Milano, nuove piste ciclabili",
+        ]
+    );
+    assert_eq!(news[0]["on_homepage"], true);
+    assert_eq!(news[2]["on_homepage"], false);
+
+    let missing = reqwest::get(format!("{}/api/entities/nessuno/news", app))
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), 404);
+
+    let v2: Value = reqwest::get(format!("{}/api/v2/news", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let kiev = v2["news"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|item| item["title"] == "Ucraina, attacco di droni su Kiev")
+        .unwrap();
+    assert_eq!(
+        kiev["entities"],
+        serde_json::json!([
+            { "name": "Ucraina", "kind": "place" },
+            { "name": "Kiev", "kind": "place" },
+        ])
+    );
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use common::{spawn_app, test_config, TestSource};
+use corriere_scraper::config::Config;
+use corriere_scraper::fetch_queue::{self, FetchQueue, Priority};
+use corriere_scraper::metrics::Metrics;
+use corriere_scraper::AppState;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+// Takes the queue's only slot until the returned sender is used or dropped
+async fn occupy(queue: &Arc<FetchQueue>) -> oneshot::Sender<()> {
+    let (release, released) = oneshot::channel::<()>();
+    let (started, running) = oneshot::channel();
+    let blocker = queue.clone();
+    tokio::spawn(async move {
+        blocker
+            .run(async move {
+                let _ = started.send(());
+                let _ = released.await;
+                Ok(())
+            })
+            .await
+    });
+    running.await.unwrap();
+    release
+}
+
+async fn wait_for_depth(queue: &FetchQueue, priority: Priority, depth: usize) {
+    for _ in 0..100 {
+        if queue.depth(priority) == depth {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("{} queue never reached {}", priority.as_str(), depth);
+}
+
+#[tokio::test]
+async fn higher_priorities_are_served_first() {
+    let queue = Arc::new(FetchQueue::new(1, 10, Arc::new(Metrics::default())));
+    let release = occupy(&queue).await;
+
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let mut tasks = Vec::new();
+    for priority in [
+        Priority::Backfill,
+        Priority::Scheduled,
+        Priority::Interactive,
+    ] {
+        let (worker, order) = (queue.clone(), order.clone());
+        tasks.push(tokio::spawn(fetch_queue::scope(priority, async move {
+            worker
+                .run(async move {
+                    order.lock().unwrap().push(priority);
+                    Ok(())
+                })
+                .await
+        })));
+        wait_for_depth(&queue, priority, 1).await;
+    }
+
+    release.send(()).unwrap();
+    for task in tasks {
+        task.await.unwrap().unwrap();
+    }
+    assert_eq!(
+        *order.lock().unwrap(),
+        vec![
+            Priority::Interactive,
+            Priority::Scheduled,
+            Priority::Backfill
+        ]
+    );
+}
+
+#[tokio::test]
+async fn full_queue_turns_interactive_fetches_away() {
+    let metrics = Arc::new(Metrics::default());
+    let queue = Arc::new(FetchQueue::new(1, 1, metrics.clone()));
+    let release = occupy(&queue).await;
+
+    let waiting = queue.clone();
+    let queued = tokio::spawn(fetch_queue::scope(Priority::Interactive, async move {
+        waiting.run(async { Ok(1) }).await
+    }));
+    wait_for_depth(&queue, Priority::Interactive, 1).await;
+
+    let rejected = fetch_queue::scope(Priority::Interactive, queue.run(async { Ok(2) })).await;
+    assert!(rejected.unwrap_err().contains("Too many upstream fetches"));
+    assert_eq!(
+        metrics.counter(
+            "corriere_fetch_queue_rejected_total",
+            &[("priority", "interactive")]
+        ),
+        1
+    );
+
+    release.send(()).unwrap();
+    assert_eq!(queued.await.unwrap(), Ok(1));
+}
+
+#[tokio::test]
+async fn request_fetches_are_queued_as_interactive() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+
+    let news = reqwest::get(format!("{}/api/news", app)).await.unwrap();
+    assert_eq!(news.status(), 200);
+    let metrics = reqwest::get(format!("{}/metrics", app))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(metrics.contains("corriere_fetch_queue_jobs_total{priority=\"interactive\"} 1"));
+    assert!(metrics.contains("corriere_fetch_queue_depth{priority=\"interactive\"} 0"));
+}
+
+#[tokio::test]
+async fn fetches_waiting_on_a_busy_host_leave_the_slots_to_other_hosts() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/slow"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(500)))
+        .mount(&upstream)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/fast"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("ok"))
+        .mount(&upstream)
+        .await;
+    let state = AppState::new(Config {
+        fetch_concurrency: 2,
+        per_host_concurrency: 1,
+        ..test_config(&upstream.uri())
+    });
+
+    // Both slow fetches go to 127.0.0.1, which takes one at a time
+    let mut slow = Vec::new();
+    for _ in 0..2 {
+        let (state, url) = (state.clone(), format!("{}/slow", upstream.uri()));
+        slow.push(tokio::spawn(async move {
+            fetch_queue::fetch_html(&state, &state.client, &url).await
+        }));
+    }
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let started = Instant::now();
+    let other_host = upstream.uri().replace("127.0.0.1", "localhost");
+    let page =
+        fetch_queue::fetch_html(&state, &state.client, &format!("{}/fast", other_host)).await;
+    assert_eq!(page.unwrap(), "ok");
+    assert!(started.elapsed() < Duration::from_millis(350));
+
+    for task in slow {
+        task.await.unwrap().unwrap();
+    }
+}
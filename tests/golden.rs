@@ -0,0 +1,217 @@
+mod common;
+
+use common::TestSource;
+use corriere_scraper::article;
+use corriere_scraper::extract::{self, ParseMode, SelectorConfig, Selectors};
+use corriere_scraper::fragment;
+use corriere_scraper::image::ImageVariant;
+use reqwest::Url;
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+const ARTICLE: TestSource = TestSource::new("article");
+
+#[test]
+fn homepage_matches_golden() {
+    let news = HOMEPAGE.extract_news(ParseMode::Document);
+    HOMEPAGE.assert_golden(&serde_json::to_value(&news).unwrap());
+}
+
+#[test]
+fn homepage_fragment_mode_matches_golden() {
+    let news = HOMEPAGE.extract_news(ParseMode::Fragment);
+    HOMEPAGE.assert_golden(&serde_json::to_value(&news).unwrap());
+}
+
+#[test]
+fn fragment_scanner_cuts_out_nested_container() {
+    let html = concat!(
+        "<html><head><script>var s = '<div class=\"body-hp\">';</script></head>",
+        "<body><!-- <div class=\"body-hp\"> --><div class='x body-hp'>",
+        "<div>inner</div></div><div>after</div></body></html>"
+    );
+
+    assert_eq!(
+        fragment::element_with_class(html, "body-hp"),
+        Some("<div class='x body-hp'><div>inner</div></div>")
+    );
+    assert_eq!(fragment::element_with_class(html, "missing"), None);
+}
+
+#[test]
+fn fragment_scanner_skips_scripts_closed_in_any_case() {
+    let html = concat!(
+        "<body><SCRIPT>var s = 'è <div class=\"body-hp\">';</Script >",
+        "<div class=\"body-hp\">città</div></body>"
+    );
+
+    assert_eq!(
+        fragment::element_with_class(html, "body-hp"),
+        Some("<div class=\"body-hp\">città</div>")
+    );
+}
+
+#[test]
+fn fragment_mode_needs_a_plain_class_selector() {
+    assert_eq!(fragment::simple_class_selector(".body-hp"), Some("body-hp"));
+    assert_eq!(
+        fragment::simple_class_selector("main.body-hp"),
+        Some("body-hp")
+    );
+    assert_eq!(fragment::simple_class_selector("div > .body-hp"), None);
+}
+
+#[test]
+fn homepage_skips_cards_outside_body_and_without_title() {
+    let news = HOMEPAGE.extract_news(ParseMode::Document);
+
+    assert!(news.iter().all(|item| !item.title.is_empty()));
+    assert!(!news.iter().any(|item| item.title == "Non deve comparire"));
+}
+
+#[test]
+fn homepage_extraction_stops_at_limit() {
+    let cards: String = (0..30)
+        .map(|i| {
+            format!(
+                "<div class=\"bck-media-news\"><h4 class=\"title-art-hp\"><a href=\"/n/{}.shtml\">Notizia {}</a></h4></div>",
+                i, i
+            )
+        })
+        .collect();
+    let html = format!(
+        "<html><body><div class=\"body-hp\">{}</div></body></html>",
+        cards
+    );
+    let selectors = Selectors::parse(&SelectorConfig::default()).unwrap();
+
+    let news = extract::extract_news(
+        &html,
+        &selectors,
+        extract::CORRIERE_BASE_URL,
+        20,
+        ParseMode::Document,
+    );
+
+    assert_eq!(news.len(), 20);
+    assert_eq!(news[19].title, "Notizia 19");
+}
+
+#[test]
+fn hrefs_resolve_against_the_page_url() {
+    let page = Url::parse("https://www.corriere.it/sport/calcio/index.shtml?ref=hp").unwrap();
+    let resolve = |href| extract::resolve_url(Some(&page), href);
+
+    assert_eq!(
+        resolve("/politica/24_maggio_01/manovra.shtml"),
+        "https://www.corriere.it/politica/24_maggio_01/manovra.shtml"
+    );
+    assert_eq!(
+        resolve("//images2.corriereobjects.it/methode_image/m4.jpg"),
+        "https://images2.corriereobjects.it/methode_image/m4.jpg"
+    );
+    assert_eq!(
+        resolve("https://milano.corriere.it/notizie/cronaca/m4.shtml"),
+        "https://milano.corriere.it/notizie/cronaca/m4.shtml"
+    );
+    assert_eq!(
+        resolve("serie-a/inter.shtml"),
+        "https://www.corriere.it/sport/calcio/serie-a/inter.shtml"
+    );
+    assert_eq!(
+        resolve("?page=2"),
+        "https://www.corriere.it/sport/calcio/index.shtml?page=2"
+    );
+    assert_eq!(
+        resolve(" /cronache/meteo.shtml#commenti "),
+        "https://www.corriere.it/cronache/meteo.shtml#commenti"
+    );
+    assert_eq!(resolve(""), "");
+    // Without a base only absolute URLs resolve; the rest is left for validation
+    assert_eq!(
+        extract::resolve_url(None, "/politica/a.shtml"),
+        "/politica/a.shtml"
+    );
+}
+
+#[test]
+fn image_metadata_comes_with_srcset_caption_and_credit() {
+    let html = r#"<html><body><div class="body-hp"><div class="bck-media-news">
+        <figure>
+          <img class="is_full_image" data-src="//images2.corriereobjects.it/kiev.jpg" width="640px" height="360"
+               data-srcset="/img/kiev-320.jpg 320w, //images2.corriereobjects.it/kiev-960.jpg 960w">
+          <figcaption>Vigili del fuoco a Kiev <span class="credit">Foto Ansa</span></figcaption>
+        </figure>
+        <h4 class="title-art-hp"><a href="/esteri/kiev.shtml">Ucraina, attacco di droni su Kiev</a></h4>
+      </div><div class="bck-media-news">
+        <img class="is_full_image" src="/img/a.jpg" srcset="/img/a.jpg, /img/a@2x.jpg 2x">
+        <h4 class="title-art-hp"><a href="/cronache/a.shtml">Senza didascalia</a></h4>
+      </div></div></body></html>"#;
+    let selectors = Selectors::parse(&SelectorConfig::default()).unwrap();
+    let news = extract::extract_news(
+        html,
+        &selectors,
+        extract::CORRIERE_BASE_URL,
+        extract::HOMEPAGE_LIMIT,
+        ParseMode::Document,
+    );
+
+    let image = news[0].image.as_ref().unwrap();
+    assert_eq!(image.url, "https://images2.corriereobjects.it/kiev.jpg");
+    assert_eq!(news[0].image_url.as_deref(), Some(image.url.as_str()));
+    assert_eq!((image.width, image.height), (Some(640), Some(360)));
+    assert_eq!(image.caption.as_deref(), Some("Vigili del fuoco a Kiev"));
+    assert_eq!(image.credit.as_deref(), Some("Foto Ansa"));
+    assert_eq!(
+        image.variants,
+        vec![
+            ImageVariant {
+                url: "https://www.corriere.it/img/kiev-320.jpg".to_string(),
+                width: Some(320),
+                density: None,
+            },
+            ImageVariant {
+                url: "https://images2.corriereobjects.it/kiev-960.jpg".to_string(),
+                width: Some(960),
+                density: None,
+            },
+        ]
+    );
+
+    let image = news[1].image.as_ref().unwrap();
+    assert_eq!(image.caption, None);
+    assert_eq!(image.credit, None);
+    let densities: Vec<Option<f64>> = image.variants.iter().map(|v| v.density).collect();
+    assert_eq!(densities, [Some(1.0), Some(2.0)]);
+}
+
+#[test]
+fn section_comes_from_path_or_local_edition() {
+    let section = |link| extract::section(link);
+
+    assert_eq!(
+        section("https://www.corriere.it/esteri/24_maggio_01/kiev.shtml").as_deref(),
+        Some("esteri")
+    );
+    assert_eq!(
+        section("https://milano.corriere.it/notizie/cronaca/m4.shtml").as_deref(),
+        Some("milano")
+    );
+    assert_eq!(section("https://www.corriere.it/index.shtml"), None);
+    assert_eq!(section("https://example.com/esteri/x.shtml"), None);
+}
+
+#[test]
+fn article_matches_golden() {
+    let detail = article::extract_article(
+        &ARTICLE.html(),
+        "https://www.corriere.it/politica/24_maggio_01/manovra-voto-aula-governo-4f7c2a1e.shtml",
+    )
+    .unwrap();
+    ARTICLE.assert_golden(&serde_json::to_value(&detail).unwrap());
+}
+
+#[test]
+fn article_without_title_is_an_error() {
+    let result = article::extract_article("<html><body><p>Nothing</p></body></html>", "x");
+    assert!(result.is_err());
+}
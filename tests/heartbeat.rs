@@ -0,0 +1,99 @@
+mod common;
+
+use common::{test_config, TestSource};
+use corriere_scraper::config::Config;
+use corriere_scraper::scheduler;
+use corriere_scraper::AppState;
+use wiremock::matchers::{body_string, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+fn state(upstream: &MockServer, change: impl FnOnce(&mut Config)) -> AppState {
+    let mut config = Config {
+        scheduler_jobs: vec![
+            format!("sport:15m:{}/sport/", upstream.uri())
+                .parse()
+                .unwrap(),
+            format!("esteri:15m:{}/esteri/", upstream.uri())
+                .parse()
+                .unwrap(),
+        ],
+        heartbeat_url: Some(format!("{}/ping/{{job}}", upstream.uri())),
+        heartbeat_fail_url: Some(format!("{}/ping/{{job}}/fail", upstream.uri())),
+        ..test_config(&upstream.uri())
+    };
+    change(&mut config);
+    AppState::new(config)
+}
+
+#[tokio::test]
+async fn runs_ping_success_and_failure_urls() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/sport/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&upstream)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/esteri/"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/ping/sport"))
+        .and(body_string("6 items"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/ping/esteri/fail"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let state = state(&upstream, |_| {});
+
+    assert_eq!(scheduler::run_job(&state, "sport").await.unwrap(), 6);
+    let error = scheduler::run_job(&state, "esteri").await.unwrap_err();
+
+    let requests = upstream.received_requests().await.unwrap();
+    let fail = requests
+        .iter()
+        .find(|request| request.url.path() == "/ping/esteri/fail")
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&fail.body), error);
+    assert_eq!(
+        state.metrics.counter(
+            "corriere_heartbeats_total",
+            &[("job", "sport"), ("ping", "ok"), ("result", "sent")]
+        ),
+        1
+    );
+}
+
+#[tokio::test]
+async fn only_listed_jobs_ping_and_unreachable_monitors_are_harmless() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&upstream)
+        .await;
+    let state = state(&upstream, |config| {
+        config.heartbeat_jobs = vec!["esteri".to_string()];
+        config.heartbeat_url = Some("http://127.0.0.1:9/ping".to_string());
+    });
+
+    assert!(scheduler::run_job(&state, "sport").await.is_ok());
+    assert!(scheduler::run_job(&state, "esteri").await.is_ok());
+
+    let metric = |job| {
+        state.metrics.counter(
+            "corriere_heartbeats_total",
+            &[("job", job), ("ping", "ok"), ("result", "failed")],
+        )
+    };
+    assert_eq!(metric("sport"), 0);
+    assert_eq!(metric("esteri"), 1);
+}
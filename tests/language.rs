@@ -0,0 +1,92 @@
+mod common;
+
+use common::{news_item, spawn_app, test_config, TestSource};
+use corriere_scraper::language;
+use corriere_scraper::NewsItem;
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+const ENGLISH_CARD: &str = r#"<div class="bck-media-news">
+        <h4 class="title-art-hp"><a href="/english/24_maggio_01/government-confidence-vote.shtml">The government calls a confidence vote on the budget</a></h4>
+        <p class="subtitle-art">Tensions within the majority over pensions</p>
+      </div>
+"#;
+
+fn item(title: &str, description: &str, link: &str) -> NewsItem {
+    NewsItem {
+        description: description.to_string(),
+        ..news_item(title, link)
+    }
+}
+
+#[test]
+fn detects_italian_and_english_items() {
+    let italian = item(
+        "Manovra, il governo pone la fiducia: voto entro venerdì",
+        "Tensioni nella maggioranza sulle pensioni",
+        "https://www.corriere.it/politica/24_maggio_01/manovra.shtml",
+    );
+    let english = item(
+        "Italy's economy grows faster than expected in the first quarter",
+        "The figures were released by the national statistics office",
+        "https://www.corriere.it/economia/24_maggio_01/pil.shtml",
+    );
+    let english_edition = item(
+        "Meloni",
+        "",
+        "https://www.corriere.it/english/24_maggio_01/meloni.shtml",
+    );
+
+    assert_eq!(language::detect(&italian), "it");
+    assert_eq!(language::detect(&english), "en");
+    assert_eq!(language::detect(&english_edition), "en");
+    assert!(language::parse_language("fr").is_err());
+    assert_eq!(language::parse_language("EN").unwrap(), "en");
+}
+
+#[tokio::test]
+async fn news_endpoints_filter_by_language() {
+    let upstream = MockServer::start().await;
+    let html = HOMEPAGE.html().replace(
+        "<!-- Promotional card",
+        &format!("{}<!-- Promotional card", ENGLISH_CARD),
+    );
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(html))
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+
+    let v1: Value = reqwest::get(format!("{}/api/news?language=en", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let news = v1["news"].as_array().unwrap();
+    assert_eq!(news.len(), 1);
+    // v1 items keep their shape: no language field
+    assert_eq!(news[0].as_object().unwrap().len(), 4);
+
+    let v2: Value = reqwest::get(format!("{}/api/v2/news?language=it", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(v2["count"], 6);
+    assert!(v2["news"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|item| item["language"] == "it"));
+
+    let response = reqwest::get(format!("{}/api/v2/news?language=de", app))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
@@ -0,0 +1,78 @@
+mod common;
+
+use common::temp_data_dir;
+use corriere_scraper::config::Config;
+use corriere_scraper::lease::{Lease, Leases};
+use std::time::Duration;
+
+fn file_leases(dir: &std::path::Path) -> Leases {
+    Leases::from_config(&Config {
+        lock_url: Some(format!("file://{}", dir.display())),
+        ..Config::default()
+    })
+    .unwrap()
+}
+
+#[tokio::test]
+async fn only_one_replica_holds_a_file_lease() {
+    let dir = temp_data_dir("leases");
+    let first = file_leases(&dir);
+    let second = file_leases(&dir);
+    let ttl = Duration::from_secs(60);
+
+    assert!(first.try_acquire("homepage", ttl).await.unwrap());
+    assert!(!second.try_acquire("homepage", ttl).await.unwrap());
+    // The holder extends its lease; other jobs are independent
+    assert!(first.try_acquire("homepage", ttl).await.unwrap());
+    assert!(second.try_acquire("webhooks", ttl).await.unwrap());
+}
+
+#[tokio::test]
+async fn expired_lease_is_taken_over() {
+    let dir = temp_data_dir("leases-expired");
+    let first = file_leases(&dir);
+    let second = file_leases(&dir);
+
+    assert!(first
+        .try_acquire("digest", Duration::from_millis(50))
+        .await
+        .unwrap());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    assert!(second
+        .try_acquire("digest", Duration::from_secs(60))
+        .await
+        .unwrap());
+    assert!(!first
+        .try_acquire("digest", Duration::from_secs(60))
+        .await
+        .unwrap());
+}
+
+#[test]
+fn lock_url_must_be_a_known_backend() {
+    let result = Leases::from_config(&Config {
+        lock_url: Some("postgres://localhost/corriere".to_string()),
+        ..Config::default()
+    });
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn taking_over_a_lease_is_reported_once() {
+    let dir = temp_data_dir("leases-takeover");
+    let first = file_leases(&dir);
+    let second = file_leases(&dir);
+    let ttl = Duration::from_secs(60);
+
+    assert_eq!(first.acquire("telegram", ttl).await, Ok(Lease::Acquired));
+    assert_eq!(first.acquire("telegram", ttl).await, Ok(Lease::Held));
+    assert_eq!(second.acquire("telegram", ttl).await, Ok(Lease::NotHeld));
+
+    // A replica in the middle of taking the lease keeps the others out
+    std::fs::write(dir.join("mqtt.lease.guard"), "").unwrap();
+    assert_eq!(second.acquire("mqtt", ttl).await, Ok(Lease::NotHeld));
+    std::fs::remove_file(dir.join("mqtt.lease.guard")).unwrap();
+    assert_eq!(second.acquire("mqtt", ttl).await, Ok(Lease::Acquired));
+    assert!(!dir.join("mqtt.lease.guard").exists());
+}
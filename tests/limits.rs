@@ -0,0 +1,151 @@
+mod common;
+
+use common::{spawn_app, test_config, TestSource};
+use corriere_scraper::config::Config;
+use corriere_scraper::limits;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[test]
+fn routes_take_the_timeout_of_their_longest_prefix() {
+    let routes = limits::default_route_timeouts();
+    let default = Duration::from_secs(30);
+    let timeout = |path: &str| limits::timeout_for(path, &routes, default).as_secs();
+
+    assert_eq!(timeout("/api/news"), 10);
+    assert_eq!(timeout("/api/v2/news"), 10);
+    assert_eq!(timeout("/api/edition/today.epub"), 120);
+    assert_eq!(timeout("/api/admin/scheduler/homepage/run"), 300);
+    // Whole segments only
+    assert_eq!(timeout("/api/newsletter"), 30);
+    assert_eq!(timeout("/api/top"), 30);
+}
+
+#[tokio::test]
+async fn slow_requests_and_large_bodies_get_problem_documents() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(TestSource::new("homepage").html())
+                .set_delay(Duration::from_secs(5)),
+        )
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(Config {
+        route_timeouts: vec![("/api/news".to_string(), Duration::from_secs(1))],
+        max_request_body_bytes: 256,
+        ..test_config(&upstream.uri())
+    })
+    .await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/api/news", app))
+        .header("Accept-Language", "it")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 408);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/problem+json"
+    );
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "request-timeout");
+    assert_eq!(body["timeout_secs"], 1);
+    assert_eq!(body["title"], "Tempo scaduto");
+
+    let large = json!({ "email": "lettore@example.com", "filler": "x".repeat(300) });
+    let response = client
+        .post(format!("{}/api/subscriptions", app))
+        .json(&large)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 413);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "payload-too-large");
+    assert_eq!(body["max_bytes"], 256);
+
+    // Without a Content-Length the limit is checked while reading
+    let mut stream = tokio::net::TcpStream::connect(app.trim_start_matches("http://"))
+        .await
+        .unwrap();
+    let chunk = "x".repeat(300);
+    let request = format!(
+        "POST /api/subscriptions HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\n\
+         Transfer-Encoding: chunked\r\nConnection: close\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+        chunk.len(),
+        chunk
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 413"));
+}
+
+#[tokio::test]
+async fn slow_uploads_time_out() {
+    let app = spawn_app(Config {
+        request_timeout_secs: 1,
+        ..test_config("http://127.0.0.1:9")
+    })
+    .await;
+
+    // The body is announced but never sent
+    let mut stream = tokio::net::TcpStream::connect(app.trim_start_matches("http://"))
+        .await
+        .unwrap();
+    let request = "POST /api/subscriptions HTTP/1.1\r\nHost: localhost\r\n\
+                   Content-Type: application/json\r\nContent-Length: 100\r\n\r\n{\"email\":";
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = vec![0; 64];
+    let read = tokio::time::timeout(Duration::from_secs(5), stream.read(&mut response))
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&response[..read]).starts_with("HTTP/1.1 408"));
+}
+
+#[tokio::test]
+async fn job_runs_finish_when_their_request_times_out() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/sport/"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(TestSource::new("homepage").html())
+                .set_delay(Duration::from_secs(2)),
+        )
+        .mount(&upstream)
+        .await;
+    let job = format!("sport:1h:{}/sport/", upstream.uri())
+        .parse()
+        .unwrap();
+    let state = corriere_scraper::AppState::new(Config {
+        scheduler_jobs: vec![job],
+        admin_token: Some("segreto".to_string()),
+        route_timeouts: vec![("/api/admin/scheduler".to_string(), Duration::from_secs(1))],
+        ..test_config(&upstream.uri())
+    });
+    let app = common::spawn_state(state.clone()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/admin/scheduler/sport/run", app))
+        .bearer_auth("segreto")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 408);
+
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    let status = &state.scheduler.status()[0];
+    assert!(!status.running);
+    assert_eq!(status.runs, 1);
+    assert!(state.scheduler.section("sport").is_some());
+}
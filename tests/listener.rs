@@ -0,0 +1,26 @@
+mod common;
+
+use common::{temp_data_dir, test_config};
+use corriere_scraper::config::Config;
+use corriere_scraper::listener;
+
+#[tokio::test]
+async fn unix_socket_only_replaces_stale_sockets() {
+    let dir = temp_data_dir("listener");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("corriere.sock");
+    let config = Config {
+        unix_socket_path: Some(path.clone()),
+        ..test_config("http://127.0.0.1:9")
+    };
+
+    // A socket left by a previous run is replaced
+    drop(listener::bind(&config).await.unwrap());
+    assert!(listener::bind(&config).await.is_ok());
+
+    std::fs::remove_file(&path).unwrap();
+    std::fs::write(&path, "dati").unwrap();
+    let error = listener::bind(&config).await.err().unwrap();
+    assert!(error.contains("not a socket"), "{}", error);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "dati");
+}
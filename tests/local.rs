@@ -0,0 +1,139 @@
+mod common;
+
+use common::{spawn_app, test_config, TestSource};
+use corriere_scraper::config::Config;
+use corriere_scraper::local;
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+#[test]
+fn editions_can_be_overridden_and_added() {
+    let editions = local::editions(&[
+        (
+            "milano".to_string(),
+            "http://127.0.0.1:1/milano/".to_string(),
+        ),
+        (
+            "genova".to_string(),
+            "http://127.0.0.1:1/genova/".to_string(),
+        ),
+    ]);
+
+    let milano = editions.iter().find(|e| e.city == "milano").unwrap();
+    assert_eq!(milano.url, "http://127.0.0.1:1/milano/");
+    let genova = editions.iter().find(|e| e.city == "genova").unwrap();
+    assert_eq!(genova.name, "Genova");
+    assert!(editions.iter().any(|e| e.city == "roma"));
+}
+
+#[test]
+fn links_are_tagged_with_their_edition() {
+    let editions = local::editions(&[(
+        "genova".to_string(),
+        "https://genova.corriere.it/".to_string(),
+    )]);
+    let city = |link| local::city_of_link(link, &editions);
+
+    assert_eq!(
+        city("https://milano.corriere.it/notizie/cronaca/24_maggio_01/m4.shtml").as_deref(),
+        Some("milano")
+    );
+    assert_eq!(
+        city("https://corrieredibologna.corriere.it/notizie/a.shtml").as_deref(),
+        Some("bologna")
+    );
+    // Editions added through LOCAL_EDITIONS are recognized too
+    assert_eq!(
+        city("https://genova.corriere.it/notizie/a.shtml").as_deref(),
+        Some("genova")
+    );
+    assert_eq!(city("https://www.corriere.it/politica/a.shtml"), None);
+}
+
+#[tokio::test]
+async fn local_endpoint_serves_tagged_items_from_the_edition() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/milano/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(Config {
+        local_editions: vec![("milano".to_string(), format!("{}/milano/", upstream.uri()))],
+        cache_soft_ttl_secs: 60,
+        ..test_config(&upstream.uri())
+    })
+    .await;
+
+    let body: Value = reqwest::get(format!("{}/api/local/Milano", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["city"], "milano");
+    assert_eq!(body["edition"], "Milano");
+    let news = body["news"].as_array().unwrap();
+    // Edition pages are read without the homepage wrapper, so the sidebar card counts
+    assert_eq!(news.len(), 7);
+    assert!(news.iter().all(|item| item["city"] == "milano"));
+    assert!(news[1]["link"]
+        .as_str()
+        .unwrap()
+        .starts_with(&upstream.uri()));
+
+    // Served from the cache the second time
+    let again: Value = reqwest::get(format!("{}/api/local/milano", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(again["scraped_at"], body["scraped_at"]);
+}
+
+#[tokio::test]
+async fn unknown_editions_are_not_found() {
+    let app = spawn_app(test_config("http://127.0.0.1:9")).await;
+
+    let response = reqwest::get(format!("{}/api/local/atlantide", app))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("milano"));
+}
+
+#[tokio::test]
+async fn concurrent_requests_share_one_edition_scrape() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/roma/"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(HOMEPAGE.html())
+                .set_delay(std::time::Duration::from_millis(300)),
+        )
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(Config {
+        local_editions: vec![("roma".to_string(), format!("{}/roma/", upstream.uri()))],
+        cache_soft_ttl_secs: 60,
+        ..test_config(&upstream.uri())
+    })
+    .await;
+
+    let mut requests = tokio::task::JoinSet::new();
+    for _ in 0..3 {
+        let url = format!("{}/api/local/roma", app);
+        requests.spawn(async move { reqwest::get(url).await.unwrap().status() });
+    }
+    while let Some(status) = requests.join_next().await {
+        assert_eq!(status.unwrap(), 200);
+    }
+}
@@ -0,0 +1,172 @@
+#![cfg(feature = "mqtt")]
+
+mod common;
+
+use common::{news_item, temp_data_dir, test_config};
+use corriere_scraper::config::Config;
+use corriere_scraper::mqtt::{self, MqttPublisher};
+use corriere_scraper::{AppState, NewsItem};
+use serde_json::Value;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+fn item(n: u32, title: &str) -> NewsItem {
+    NewsItem {
+        description: String::new(),
+        ..news_item(title, &format!("https://www.corriere.it/n/{}.shtml", n))
+    }
+}
+
+// A message as the broker received it
+#[derive(Debug)]
+struct Received {
+    topic: String,
+    payload: Value,
+    qos: u8,
+    retain: bool,
+}
+
+async fn read_packet(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+    let kind = stream.read_u8().await.ok()?;
+    let (mut length, mut shift) = (0usize, 0);
+    loop {
+        let byte = stream.read_u8().await.ok()?;
+        length |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).await.ok()?;
+    Some((kind, body))
+}
+
+// Helper function to run a broker that accepts any client and records what
+// is published to it, acknowledging QoS 1 messages
+async fn spawn_broker() -> (String, Arc<Mutex<Vec<Received>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("mqtt://user:secret@{}", listener.local_addr().unwrap());
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let log = received.clone();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            while let Some((kind, body)) = read_packet(&mut stream).await {
+                match kind >> 4 {
+                    // CONNECT, with the credentials at the end
+                    1 => {
+                        assert!(body.ends_with(b"\x00\x04user\x00\x06secret"));
+                        stream.write_all(&[0x20, 2, 0, 0]).await.unwrap();
+                    }
+                    3 => {
+                        let qos = (kind >> 1) & 3;
+                        let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                        let mut rest = &body[2 + topic_len..];
+                        if qos > 0 {
+                            stream
+                                .write_all(&[0x40, 2, rest[0], rest[1]])
+                                .await
+                                .unwrap();
+                            rest = &rest[2..];
+                        }
+                        log.lock().unwrap().push(Received {
+                            topic: String::from_utf8(body[2..2 + topic_len].to_vec()).unwrap(),
+                            payload: serde_json::from_slice(rest).unwrap(),
+                            qos,
+                            retain: kind & 1 == 1,
+                        });
+                    }
+                    _ => break,
+                }
+            }
+        }
+    });
+    (url, received)
+}
+
+#[test]
+fn breaking_news_is_told_by_label_or_opening_block() {
+    assert!(mqtt::is_breaking(&item(
+        1,
+        "ULTIM’ORA | Scossa di terremoto"
+    )));
+    assert!(mqtt::is_breaking(&item(
+        1,
+        "Breaking news: il governo cade"
+    )));
+    assert!(!mqtt::is_breaking(&item(
+        1,
+        "Breaking Bad, la serie compie vent'anni"
+    )));
+
+    let mut opening = item(2, "Manovra, l'accordo");
+    assert!(!mqtt::is_breaking(&opening));
+    opening.placement.opening = true;
+    assert!(mqtt::is_breaking(&opening));
+}
+
+#[tokio::test]
+async fn new_items_are_published_once_with_qos_and_retain() {
+    let (url, received) = spawn_broker().await;
+    let config = Config {
+        mqtt_url: Some(url),
+        mqtt_qos: 1,
+        mqtt_retain: true,
+        data_dir: temp_data_dir("mqtt"),
+        ..test_config("http://127.0.0.1:9")
+    };
+    let mut publisher = MqttPublisher::from_config(&config).unwrap().unwrap();
+    let state = AppState::new(config);
+
+    // The first run only records what's already on the homepage
+    let first = [item(1, "Notizia 1")];
+    assert_eq!(publisher.publish(&state, &first).await.unwrap(), 0);
+
+    let news = [
+        item(2, "Ultim'ora: sciopero dei treni"),
+        item(1, "Notizia 1"),
+        item(3, "Notizia 3"),
+    ];
+    assert_eq!(publisher.publish(&state, &news).await.unwrap(), 2);
+    assert_eq!(publisher.publish(&state, &news).await.unwrap(), 0);
+
+    let received = received.lock().unwrap();
+    let topics: Vec<&str> = received.iter().map(|r| r.topic.as_str()).collect();
+    // Lower ranked first; the breaking item goes to both topics
+    assert_eq!(
+        topics,
+        ["corriere/news", "corriere/news", "corriere/breaking"]
+    );
+    assert!(received.iter().all(|r| r.qos == 1 && r.retain));
+    assert_eq!(
+        received[0].payload["link"],
+        "https://www.corriere.it/n/3.shtml"
+    );
+    assert_eq!(received[0].payload["breaking"], false);
+    assert_eq!(
+        received[2].payload["title"],
+        "Ultim'ora: sciopero dei treni"
+    );
+    assert_eq!(received[2].payload["breaking"], true);
+}
+
+#[test]
+fn broker_settings_are_checked() {
+    let config = |url: &str, qos: u8| Config {
+        mqtt_url: Some(url.to_string()),
+        mqtt_qos: qos,
+        data_dir: temp_data_dir("mqtt-settings"),
+        ..test_config("http://127.0.0.1:9")
+    };
+
+    assert!(
+        MqttPublisher::from_config(&test_config("http://127.0.0.1:9"))
+            .unwrap()
+            .is_none()
+    );
+    let error = MqttPublisher::from_config(&config("mqtts://broker:8883", 0)).err();
+    assert!(error.unwrap().contains("expected mqtt"));
+    let error = MqttPublisher::from_config(&config("mqtt://broker", 2)).err();
+    assert!(error.unwrap().contains("MQTT_QOS"));
+}
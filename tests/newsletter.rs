@@ -0,0 +1,117 @@
+mod common;
+
+use common::{spawn_app, test_config, TestSource};
+use corriere_scraper::config::Config;
+use corriere_scraper::newsletter;
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const PRIMA_ORA: TestSource = TestSource::new("prima_ora");
+
+#[test]
+fn digest_pages_are_split_into_ordered_segments() {
+    let digest = newsletter::extract_digest(
+        &PRIMA_ORA.html(),
+        "prima-ora",
+        "https://www.corriere.it/newsletter/prima-ora/",
+    )
+    .unwrap();
+
+    assert_eq!(digest.title, "Prima Ora");
+    assert_eq!(digest.author.as_deref(), Some("Gianluca Mercuri"));
+    assert_eq!(digest.published_on.unwrap().to_string(), "2024-05-02");
+
+    let headings: Vec<Option<&str>> = digest
+        .segments
+        .iter()
+        .map(|segment| segment.heading.as_deref())
+        .collect();
+    assert_eq!(
+        headings,
+        [None, Some("Le notizie da sapere"), Some("Da leggere")]
+    );
+    let news = &digest.segments[1];
+    assert_eq!(news.position, 1);
+    assert_eq!(
+        news.paragraphs,
+        [
+            "Il governo approva il decreto.",
+            "Borse in rialzo, i dati",
+            "Sciopero dei treni venerdì"
+        ]
+    );
+    assert_eq!(
+        news.links[0].url,
+        "https://www.corriere.it/politica/24_maggio_02/governo.shtml"
+    );
+    // The quote's paragraph is read with its blockquote, not twice
+    assert_eq!(digest.segments[2].paragraphs.len(), 1);
+}
+
+#[tokio::test]
+async fn today_endpoint_serves_the_cached_digest_and_rejects_unknown_names() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/caffe/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(PRIMA_ORA.html()))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(Config {
+        newsletter_urls: vec![("caffe".to_string(), format!("{}/caffe/", upstream.uri()))],
+        ..test_config(&upstream.uri())
+    })
+    .await;
+
+    for _ in 0..2 {
+        let body: Value = reqwest::get(format!("{}/api/digest/today", app))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["name"], "caffe");
+        assert_eq!(body["segments"][1]["heading"], "Le notizie da sapere");
+    }
+
+    let response = reqwest::get(format!("{}/api/digest/today?name=prima-ora", app))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn failing_upstream_serves_the_stale_digest_and_backs_off() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/caffe/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(PRIMA_ORA.html()))
+        .up_to_n_times(1)
+        .mount(&upstream)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/caffe/"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(Config {
+        newsletter_urls: vec![("caffe".to_string(), format!("{}/caffe/", upstream.uri()))],
+        newsletter_cache_secs: 0,
+        ..test_config(&upstream.uri())
+    })
+    .await;
+
+    for _ in 0..3 {
+        let body: Value = reqwest::get(format!("{}/api/digest/today", app))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(body["segments"][1]["heading"], "Le notizie da sapere");
+        // The fixture's issue is from 2024, so even the fresh scrape is stale
+        assert_eq!(body["stale"], true);
+    }
+}
@@ -0,0 +1,185 @@
+mod common;
+
+use common::{spawn_app, temp_data_dir, test_config};
+use corriere_scraper::cli;
+use corriere_scraper::config::Config;
+use corriere_scraper::plugins::{self, SourcePlugin};
+use serde_json::{json, Value};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const PAGE: &str = r#"<html><body>
+<main>
+  <article class="news"><h2><a href="/politica/manovra.html">VIDEO | Manovra,  l’accordo</a></h2>
+    <p>Il governo trova l&#8217;intesa sulla legge di bilancio dopo una lunga notte</p>
+    <img data-src="/img/manovra.jpg"></article>
+  <article class="news"><h2><a href="/sponsor/auto.html">Sponsorizzato: la nuova auto</a></h2></article>
+  <article class="news"><h2><a href="https://example.org/esteri/kiev.html">Kiev sotto attacco</a></h2></article>
+  <article class="news"><h2>Senza link</h2></article>
+</main>
+</body></html>"#;
+
+fn definition(url: &str) -> Value {
+    json!({
+        "name": "agenzia",
+        "url": url,
+        "every": "15m",
+        "container": "main",
+        "article": "article.news",
+        "fields": {
+            "title": {"selector": "h2"},
+            "link": {"selector": "h2 a"},
+            "description": {"selector": "p"},
+            "image_url": {"selector": "img", "attribute": "data-src"}
+        },
+        "rules": [
+            {"field": "title", "action": "normalize"},
+            {"field": "description", "action": "normalize"},
+            {"field": "description", "action": "truncate", "max_chars": 40},
+            {"field": "link", "action": "exclude", "contains": "/sponsor/"}
+        ]
+    })
+}
+
+fn write_plugins(name: &str, plugins: Value) -> std::path::PathBuf {
+    let dir = temp_data_dir(name);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("plugins.json");
+    std::fs::write(&path, plugins.to_string()).unwrap();
+    path
+}
+
+#[test]
+fn definitions_are_validated_when_loaded() {
+    let valid = write_plugins(
+        "plugins-valid",
+        json!([definition("https://agenzia.test/")]),
+    );
+    let loaded = plugins::load(&valid).unwrap();
+    assert_eq!(loaded[0].name, "agenzia");
+    assert_eq!(loaded[0].limit, 20);
+    assert_eq!(loaded[0].job().unwrap().every.as_secs(), 900);
+
+    let broken = |name: &str, change: &dyn Fn(&mut Value)| {
+        let mut plugin = definition("https://agenzia.test/");
+        change(&mut plugin);
+        plugins::load(&write_plugins(name, json!([plugin]))).unwrap_err()
+    };
+    let error = broken("plugins-selector", &|plugin| {
+        plugin["fields"]["title"]["selector"] = json!("h2[");
+    });
+    assert!(error.contains("Plugin 'agenzia'") && error.contains("title selector"));
+    let error = broken("plugins-interval", &|plugin| {
+        plugin["every"] = json!("soon");
+    });
+    assert!(error.contains("invalid interval"));
+    let error = broken("plugins-name", &|plugin| {
+        plugin["name"] = json!("Agenzia X");
+    });
+    assert!(error.contains("name must be"));
+    let error = broken("plugins-url", &|plugin| {
+        plugin["url"] = json!("ftp://x/");
+    });
+    assert!(error.contains("http or https"));
+    let error = broken("plugins-rule", &|plugin| {
+        plugin["rules"] = json!([{"field": "title", "action": "shout"}]);
+    });
+    assert!(error.contains("unknown variant"));
+
+    let twice = write_plugins(
+        "plugins-twice",
+        json!([
+            definition("https://agenzia.test/"),
+            definition("https://agenzia.test/")
+        ]),
+    );
+    assert!(plugins::load(&twice).unwrap_err().contains("defined twice"));
+}
+
+#[test]
+fn fields_are_mapped_and_rules_applied() {
+    let plugin: SourcePlugin =
+        serde_json::from_value(definition("https://agenzia.test/oggi/")).unwrap();
+
+    let news = plugin.compile().unwrap().extract(PAGE, &plugin.url);
+
+    assert_eq!(news.len(), 2);
+    assert_eq!(news[0].title, "Manovra, l'accordo");
+    assert_eq!(news[0].link, "https://agenzia.test/politica/manovra.html");
+    assert_eq!(
+        news[0].image_url.as_deref(),
+        Some("https://agenzia.test/img/manovra.jpg")
+    );
+    assert_eq!(
+        news[0].description,
+        "Il governo trova l'intesa sulla legge…"
+    );
+    assert_eq!(news[1].title, "Kiev sotto attacco");
+    assert_eq!(news[1].description, "");
+    assert_eq!(news[1].placement.position, 1);
+}
+
+#[tokio::test]
+async fn registered_plugin_is_scraped_as_a_section() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/oggi/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(PAGE))
+        .mount(&upstream)
+        .await;
+    let plugin: SourcePlugin =
+        serde_json::from_value(definition(&format!("{}/oggi/", upstream.uri()))).unwrap();
+    let app = spawn_app(Config {
+        scheduler_jobs: vec![plugin.job().unwrap()],
+        plugins: vec![plugin],
+        admin_token: Some("segreto".to_string()),
+        ..test_config(&upstream.uri())
+    })
+    .await;
+
+    let run: Value = reqwest::Client::new()
+        .post(format!("{}/api/admin/scheduler/agenzia/run", app))
+        .bearer_auth("segreto")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    // The off-site link fails validation
+    assert_eq!(run["items"], 1);
+
+    let section: Value = reqwest::get(format!("{}/api/sections/agenzia", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(section["news"][0]["title"], "Manovra, l'accordo");
+}
+
+#[tokio::test]
+async fn dry_run_validates_like_the_scheduler() {
+    let plugin: SourcePlugin =
+        serde_json::from_value(definition("https://agenzia.test/oggi/")).unwrap();
+    let config = || Config {
+        plugins: vec![plugin.clone()],
+        ..test_config("http://127.0.0.1:9")
+    };
+    let dir = temp_data_dir("plugins-dry-run");
+    std::fs::create_dir_all(&dir).unwrap();
+    let saved = dir.join("page.html");
+    let args = |file: &std::path::Path| ["agenzia".to_string(), file.display().to_string()];
+
+    std::fs::write(&saved, PAGE).unwrap();
+    cli::test_plugin(config(), &args(&saved)).await.unwrap();
+
+    // Only the off-site link is left, which validation drops
+    let offsite: String = PAGE
+        .lines()
+        .filter(|line| !line.contains("/politica/") && !line.contains("<p>"))
+        .collect();
+    std::fs::write(&saved, offsite).unwrap();
+    let error = cli::test_plugin(config(), &args(&saved)).await.unwrap_err();
+    assert_eq!(error, "No news items extracted");
+}
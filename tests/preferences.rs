@@ -0,0 +1,129 @@
+mod common;
+
+use common::{news_item, spawn_app, test_config, TestSource};
+use corriere_scraper::config::Config;
+use corriere_scraper::local;
+use corriere_scraper::preferences::{self, Preferences};
+use serde_json::{json, Value};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[test]
+fn preferences_filter_and_reorder_the_page() {
+    let preferences =
+        Preferences::parse_header("categories=Esteri, politica; editions=milano; hidden=sport")
+            .unwrap();
+    assert_eq!(preferences.categories, ["esteri", "politica"]);
+
+    let page = vec![
+        news_item("Titolo", "https://www.corriere.it/cronache/a.shtml"),
+        news_item("Titolo", "https://www.corriere.it/politica/b.shtml"),
+        news_item("Titolo", "https://www.corriere.it/sport/calcio/c.shtml"),
+        news_item("Titolo", "https://roma.corriere.it/notizie/cronaca/d.shtml"),
+        news_item(
+            "Titolo",
+            "https://milano.corriere.it/notizie/cronaca/e.shtml",
+        ),
+        news_item("Titolo", "https://www.corriere.it/esteri/f.shtml"),
+    ];
+    let links: Vec<String> = preferences
+        .apply(page, &local::editions(&[]))
+        .into_iter()
+        .map(|item| item.link)
+        .collect();
+    assert_eq!(
+        links,
+        [
+            "https://www.corriere.it/esteri/f.shtml",
+            "https://www.corriere.it/politica/b.shtml",
+            "https://www.corriere.it/cronache/a.shtml",
+            "https://milano.corriere.it/notizie/cronaca/e.shtml",
+        ]
+    );
+
+    assert!(Preferences::parse_header("colors=red").is_err());
+    assert!(Preferences::parse_header("hidden=<script>").is_err());
+
+    // Cookies only verify under the secret that signed them, untouched
+    let cookie = preferences::sign(&preferences, "segreto");
+    assert_eq!(preferences::verify(&cookie, "segreto"), Some(preferences));
+    assert_eq!(preferences::verify(&cookie, "altro"), None);
+    let forged = format!("{}{}", &cookie[..2], &cookie[3..]);
+    assert_eq!(preferences::verify(&forged, "segreto"), None);
+}
+
+#[tokio::test]
+async fn for_me_serves_the_news_by_cookie_or_header() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(TestSource::new("homepage").html()),
+        )
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(Config {
+        preferences_secret: Some("segreto".to_string()),
+        ..test_config(&upstream.uri())
+    })
+    .await;
+    let client = reqwest::Client::new();
+    let first_category = |body: &Value| body["news"][0]["categories"][0].clone();
+
+    let saved = client
+        .put(format!("{}/api/preferences", app))
+        .json(&json!({ "categories": ["Sport"], "hidden": ["politica"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(saved.status(), 200);
+    let set_cookie = saved.headers()["set-cookie"].to_str().unwrap().to_string();
+    assert!(set_cookie.contains("HttpOnly") && set_cookie.contains("Path=/api"));
+    let cookie = set_cookie.split(';').next().unwrap().to_string();
+    let body: Value = saved.json().await.unwrap();
+    assert_eq!(body["preferences"]["categories"], json!(["sport"]));
+
+    let body: Value = client
+        .get(format!("{}/api/news/for-me", app))
+        .header("Cookie", &cookie)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(first_category(&body), "sport");
+    let categories = body["news"].to_string();
+    assert!(!categories.contains("\"politica\""));
+
+    // The header wins over the cookie
+    let body: Value = client
+        .get(format!("{}/api/news/for-me", app))
+        .header("Cookie", &cookie)
+        .header("X-Preferences", "categories=economia")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(first_category(&body), "economia");
+
+    let response = client
+        .get(format!("{}/api/news/for-me", app))
+        .header("X-Preferences", "colors=red")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    let cleared = client
+        .delete(format!("{}/api/preferences", app))
+        .send()
+        .await
+        .unwrap();
+    assert!(cleared.headers()["set-cookie"]
+        .to_str()
+        .unwrap()
+        .contains("Max-Age=0"));
+}
@@ -0,0 +1,79 @@
+mod common;
+
+use common::{spawn_app, test_config, TestSource};
+use corriere_scraper::config::Config;
+use corriere_scraper::extract::SelectorConfig;
+use corriere_scraper::provenance;
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn v2_news_records_how_the_scrape_was_produced() {
+    let html = TestSource::new("homepage").html();
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(html.clone()))
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(Config {
+        cache_soft_ttl_secs: 300,
+        cache_hard_ttl_secs: 600,
+        ..test_config(&upstream.uri())
+    })
+    .await;
+    let get = |path: &str| {
+        let url = format!("{}{}", app, path);
+        async move {
+            reqwest::get(url)
+                .await
+                .unwrap()
+                .json::<Value>()
+                .await
+                .unwrap()
+        }
+    };
+
+    let body = get("/api/v2/news").await;
+    let provenance = &body["provenance"];
+    assert_eq!(provenance["source_url"], format!("{}/", upstream.uri()));
+    assert_eq!(provenance["http_status"], 200);
+    assert_eq!(provenance["content_length"], html.len());
+    assert!(provenance["fetch_duration_ms"].is_u64());
+    assert_eq!(provenance["selector_set"], "default");
+    assert_eq!(
+        provenance["selector_set_version"],
+        provenance::selector_version(&SelectorConfig::default())
+    );
+    assert_eq!(
+        provenance["extractor_version"],
+        corriere_scraper::EXTRACTOR_VERSION
+    );
+    assert_eq!(provenance["cache_status"], "miss");
+
+    let cached = get("/api/v2/news").await;
+    assert_eq!(cached["provenance"]["cache_status"], "hit");
+    assert_eq!(
+        cached["provenance"]["fetch_duration_ms"],
+        provenance["fetch_duration_ms"]
+    );
+
+    // v1 keeps its shape
+    let v1 = get("/api/news").await;
+    assert!(v1.get("provenance").is_none());
+}
+
+#[test]
+fn selector_versions_follow_the_selectors() {
+    let default = SelectorConfig::default();
+    let version = provenance::selector_version(&default);
+    assert_eq!(version.len(), 12);
+    assert_eq!(version, provenance::selector_version(&default.clone()));
+
+    let changed = SelectorConfig {
+        title: "h3 a".to_string(),
+        ..default
+    };
+    assert_ne!(provenance::selector_version(&changed), version);
+}
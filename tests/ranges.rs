@@ -0,0 +1,117 @@
+mod common;
+
+use common::{news_item, spawn_state, temp_data_dir, test_config};
+use corriere_scraper::archive::{self, ArchivedScrape};
+use corriere_scraper::config::Config;
+use corriere_scraper::ranges::{self, ByteRange, RangeRequest};
+use corriere_scraper::AppState;
+
+#[test]
+fn range_headers_are_parsed_against_the_body_length() {
+    let partial = |start, end| RangeRequest::Partial(ByteRange { start, end });
+    assert_eq!(ranges::parse_range("bytes=0-99", 1000), partial(0, 99));
+    assert_eq!(ranges::parse_range("bytes=900-", 1000), partial(900, 999));
+    assert_eq!(ranges::parse_range("bytes=-100", 1000), partial(900, 999));
+    // Ends past the body are clamped, suffixes longer than it take it all
+    assert_eq!(
+        ranges::parse_range("bytes=500-5000", 1000),
+        partial(500, 999)
+    );
+    assert_eq!(ranges::parse_range("bytes=-5000", 1000), partial(0, 999));
+
+    assert_eq!(
+        ranges::parse_range("bytes=1000-", 1000),
+        RangeRequest::Unsatisfiable
+    );
+    assert_eq!(
+        ranges::parse_range("bytes=-0", 1000),
+        RangeRequest::Unsatisfiable
+    );
+    for ignored in [
+        "bytes=0-1,5-6",
+        "items=0-1",
+        "bytes=9-3",
+        "bytes=x-",
+        "bytes=-",
+    ] {
+        assert_eq!(ranges::parse_range(ignored, 1000), RangeRequest::Full);
+    }
+}
+
+#[tokio::test]
+async fn exports_can_be_fetched_in_parts() {
+    let config = Config {
+        archive_url: Some(format!(
+            "file://{}",
+            temp_data_dir("ranges-export").display()
+        )),
+        ..test_config("http://127.0.0.1:9")
+    };
+    let mut state = AppState::new(config);
+    state.archive = archive::connect(&state.config).await.unwrap();
+    let scrape = ArchivedScrape {
+        scraped_at: "2026-10-14T08:00:00Z".parse().unwrap(),
+        news: vec![news_item(
+            "Manovra, il voto",
+            "https://www.corriere.it/politica/manovra.shtml",
+        )],
+    };
+    state
+        .archive
+        .as_ref()
+        .unwrap()
+        .record(&scrape)
+        .await
+        .unwrap();
+    let app = spawn_state(state).await;
+    let url = format!("{}/api/archive/export?date=2026-10-14&format=ndjson", app);
+    let client = reqwest::Client::new();
+
+    let full = client.get(&url).send().await.unwrap();
+    assert_eq!(full.status(), 200);
+    assert_eq!(full.headers()["accept-ranges"], "bytes");
+    let etag = full.headers()["etag"].to_str().unwrap().to_string();
+    let body = full.bytes().await.unwrap();
+
+    let head = client.head(&url).send().await.unwrap();
+    assert_eq!(head.status(), 200);
+    assert_eq!(head.headers()["content-length"], body.len().to_string());
+    assert_eq!(head.headers()["etag"], etag.as_str());
+
+    // Resuming from byte 100
+    let rest = client
+        .get(&url)
+        .header("Range", "bytes=100-")
+        .header("If-Range", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rest.status(), 206);
+    assert_eq!(
+        rest.headers()["content-range"],
+        format!("bytes 100-{}/{}", body.len() - 1, body.len()).as_str()
+    );
+    assert_eq!(rest.bytes().await.unwrap(), body.slice(100..));
+
+    // The content changed since: the whole body again
+    let stale = client
+        .get(&url)
+        .header("Range", "bytes=100-")
+        .header("If-Range", "\"0000\"")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stale.status(), 200);
+
+    let past_end = client
+        .get(&url)
+        .header("Range", format!("bytes={}-", body.len()))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(past_end.status(), 416);
+    assert_eq!(
+        past_end.headers()["content-range"],
+        format!("bytes */{}", body.len()).as_str()
+    );
+}
@@ -0,0 +1,89 @@
+mod common;
+
+use common::{spawn_app, test_config, TestSource};
+use corriere_scraper::extract::ParseMode;
+use corriere_scraper::ranking::Placement;
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+#[test]
+fn placement_is_read_from_the_homepage() {
+    let news = HOMEPAGE.extract_news(ParseMode::Document);
+
+    assert_eq!(
+        news[0].placement,
+        Placement {
+            position: 0,
+            opening: true,
+            large_title: true,
+            image: true,
+            image_width: None,
+        }
+    );
+    assert_eq!(news[3].placement.position, 3);
+    assert!(!news[3].placement.opening);
+    assert!(!news[3].placement.image);
+
+    // Fragment mode only parses the container but sees the same placement
+    let fragment = HOMEPAGE.extract_news(ParseMode::Fragment);
+    assert_eq!(fragment[0].placement, news[0].placement);
+}
+
+#[test]
+fn score_decays_down_the_page_and_rewards_prominence() {
+    let at = |position| Placement {
+        position,
+        ..Placement::default()
+    };
+    assert_eq!(at(0).score(), 60.0);
+    assert!(at(1).score() > at(2).score());
+
+    let lead = Placement {
+        opening: true,
+        large_title: true,
+        image: true,
+        image_width: Some(1200),
+        ..at(0)
+    };
+    assert_eq!(lead.score(), 100.0);
+
+    // A wide image lower down can outrank a bare headline above it
+    let illustrated = Placement {
+        image: true,
+        image_width: Some(800),
+        ..at(2)
+    };
+    assert!(illustrated.score() > at(1).score());
+}
+
+#[tokio::test]
+async fn top_endpoint_returns_the_most_prominent_stories() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+
+    let body: Value = reqwest::get(format!("{}/api/top?n=3", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let news = body["news"].as_array().unwrap();
+    assert_eq!(body["count"], 3);
+    assert_eq!(news[0]["prominence_score"], 95.0);
+    let scores: Vec<f64> = news
+        .iter()
+        .map(|item| item["prominence_score"].as_f64().unwrap())
+        .collect();
+    assert!(scores.windows(2).all(|pair| pair[0] >= pair[1]));
+
+    let response = reqwest::get(format!("{}/api/top?n=0", app)).await.unwrap();
+    assert_eq!(response.status(), 400);
+}
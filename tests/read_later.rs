@@ -0,0 +1,230 @@
+#![cfg(feature = "read_later")]
+
+mod common;
+
+use common::{news_item, spawn_app, temp_data_dir, test_config};
+use corriere_scraper::config::Config;
+use corriere_scraper::read_later::{self, ReadLater};
+use corriere_scraper::sent_log::SentLog;
+use corriere_scraper::{AppState, NewsItem};
+use serde_json::{json, Value};
+use wiremock::matchers::{body_partial_json, body_string_contains, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const ARTICLE_URL: &str = "https://www.corriere.it/esteri/26_ottobre_15/kiev.shtml";
+
+fn read_later_config(service: &MockServer, name: &str) -> Config {
+    Config {
+        data_dir: temp_data_dir(name),
+        admin_token: Some("secret".to_string()),
+        wallabag_url: Some(service.uri()),
+        wallabag_client_id: Some("client".to_string()),
+        wallabag_client_secret: Some("client-secret".to_string()),
+        wallabag_username: Some("mario".to_string()),
+        wallabag_password: Some("password".to_string()),
+        ..test_config("http://127.0.0.1:9")
+    }
+}
+
+async fn mock_wallabag_sign_in(service: &MockServer, access_token: &str, calls: u64) {
+    Mock::given(method("POST"))
+        .and(path("/oauth/v2/token"))
+        .and(body_string_contains("grant_type=password"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": access_token,
+            "expires_in": 3600,
+            "refresh_token": "refresh",
+        })))
+        .expect(calls)
+        .mount(service)
+        .await;
+}
+
+async fn save(app: &str, url: &str) -> (u16, Value) {
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/save", app))
+        .bearer_auth("secret")
+        .json(&json!({ "url": url, "title": "Droni su Kiev" }))
+        .send()
+        .await
+        .unwrap();
+    (response.status().as_u16(), response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn save_endpoint_pushes_to_wallabag_and_reuses_the_token() {
+    let service = MockServer::start().await;
+    mock_wallabag_sign_in(&service, "token-1", 1).await;
+    Mock::given(method("POST"))
+        .and(path("/api/entries.json"))
+        .and(header("authorization", "Bearer token-1"))
+        .and(body_partial_json(json!({
+            "url": ARTICLE_URL,
+            "title": "Droni su Kiev",
+            "tags": "corriere,esteri",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": 1 })))
+        .expect(2)
+        .mount(&service)
+        .await;
+    let app = spawn_app(read_later_config(&service, "read-later-wallabag")).await;
+
+    let (status, body) = save(&app, ARTICLE_URL).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["services"]["wallabag"]["saved"], true);
+    let (status, _) = save(&app, ARTICLE_URL).await;
+    assert_eq!(status, 200);
+}
+
+#[tokio::test]
+async fn revoked_wallabag_token_is_renewed() {
+    let service = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/oauth/v2/token"))
+        .and(body_string_contains("grant_type=refresh_token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "token-2",
+            "expires_in": 3600,
+        })))
+        .expect(1)
+        .mount(&service)
+        .await;
+    mock_wallabag_sign_in(&service, "token-1", 1).await;
+    Mock::given(method("POST"))
+        .and(path("/api/entries.json"))
+        .and(header("authorization", "Bearer token-1"))
+        .respond_with(ResponseTemplate::new(401))
+        .mount(&service)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/entries.json"))
+        .and(header("authorization", "Bearer token-2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": 1 })))
+        .expect(1)
+        .mount(&service)
+        .await;
+    let app = spawn_app(read_later_config(&service, "read-later-renew")).await;
+
+    let (status, body) = save(&app, ARTICLE_URL).await;
+    assert_eq!(status, 200, "{}", body);
+}
+
+#[tokio::test]
+async fn save_endpoint_needs_the_admin_token_and_a_valid_url() {
+    let service = MockServer::start().await;
+    let app = spawn_app(read_later_config(&service, "read-later-auth")).await;
+
+    let anonymous = reqwest::Client::new()
+        .post(format!("{}/api/save", app))
+        .json(&json!({ "url": ARTICLE_URL }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(anonymous.status(), 401);
+    let (status, _) = save(&app, "javascript:alert(1)").await;
+    assert_eq!(status, 400);
+}
+
+#[tokio::test]
+async fn pocket_is_connected_through_oauth() {
+    let service = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v3/oauth/request"))
+        .and(body_partial_json(json!({ "consumer_key": "consumer" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "code": "request-code" })))
+        .mount(&service)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v3/oauth/authorize"))
+        .and(body_partial_json(json!({ "code": "request-code" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "pocket-token",
+            "username": "mario",
+        })))
+        .mount(&service)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v3/add"))
+        .and(body_partial_json(json!({
+            "url": ARTICLE_URL,
+            "access_token": "pocket-token",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "status": 1 })))
+        .expect(1)
+        .mount(&service)
+        .await;
+    let config = Config {
+        data_dir: temp_data_dir("read-later-pocket"),
+        admin_token: Some("secret".to_string()),
+        pocket_consumer_key: Some("consumer".to_string()),
+        pocket_api_url: service.uri(),
+        ..test_config("http://127.0.0.1:9")
+    };
+    let app = spawn_app(config).await;
+
+    // Saving before the account is connected fails
+    let (status, body) = save(&app, ARTICLE_URL).await;
+    assert_eq!(status, 502);
+    assert_eq!(body["services"]["pocket"]["saved"], false);
+
+    let connect: Value = reqwest::Client::new()
+        .get(format!("{}/api/read-later/pocket/connect", app))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let authorize_url = connect["authorize_url"].as_str().unwrap();
+    assert!(authorize_url.contains("request_token=request-code"));
+
+    let callback = reqwest::get(format!("{}/api/read-later/pocket/callback", app))
+        .await
+        .unwrap();
+    assert_eq!(callback.status(), 200);
+    assert_eq!(callback.text().await.unwrap(), "Pocket connected as mario");
+
+    let (status, body) = save(&app, ARTICLE_URL).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["services"]["pocket"]["saved"], true);
+}
+
+#[tokio::test]
+async fn new_articles_from_the_chosen_sections_are_pushed() {
+    let service = MockServer::start().await;
+    mock_wallabag_sign_in(&service, "token-1", 1).await;
+    Mock::given(method("POST"))
+        .and(path("/api/entries.json"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "id": 1 })))
+        .expect(1)
+        .mount(&service)
+        .await;
+    let config = Config {
+        read_later_sections: vec!["esteri".to_string()],
+        ..read_later_config(&service, "read-later-sections")
+    };
+    let read_later = ReadLater::from_config(&config).unwrap().unwrap();
+    let mut sent = SentLog::load(&config, "read_later").unwrap();
+    let state = AppState::new(config);
+
+    let item = |link: &str| NewsItem {
+        description: String::new(),
+        ..news_item("Titolo", link)
+    };
+    let first = vec![item("https://www.corriere.it/esteri/a.shtml")];
+    let pushed = read_later::push_sections(&state, &read_later, &mut sent, &first)
+        .await
+        .unwrap();
+    assert_eq!(pushed, 0);
+
+    let second = vec![
+        item("https://www.corriere.it/esteri/a.shtml"),
+        item("https://www.corriere.it/sport/b.shtml"),
+        item("https://www.corriere.it/esteri/c.shtml"),
+    ];
+    let pushed = read_later::push_sections(&state, &read_later, &mut sent, &second)
+        .await
+        .unwrap();
+    assert_eq!(pushed, 1);
+}
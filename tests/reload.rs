@@ -0,0 +1,125 @@
+mod common;
+
+use common::{spawn_state, test_config};
+use corriere_scraper::config::Config;
+use corriere_scraper::{heartbeat, reload, AppState};
+use serde_json::Value;
+use std::collections::HashMap;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn admin_state() -> AppState {
+    AppState::new(Config {
+        admin_token: Some("segreto".to_string()),
+        ..test_config("http://127.0.0.1:9")
+    })
+}
+
+// Helper function to build the variables a reload reads
+fn variables(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn invalid_reload_keeps_the_running_config() {
+    let state = admin_state();
+
+    let error = reload::reload_from(
+        &state,
+        &variables(&[("ADMIN_TOKEN", "segreto"), ("CORS_ORIGINS", "nonsense")]),
+    )
+    .err()
+    .unwrap();
+    assert!(error.contains("CORS_ORIGINS"));
+
+    // Nothing of the new configuration was applied
+    let current = state.current();
+    assert!(current
+        .config
+        .homepage_url
+        .starts_with("http://127.0.0.1:9"));
+    assert!(current.config.cors_origins.is_empty());
+}
+
+#[tokio::test]
+async fn reload_applies_new_settings_and_reports_restart_only_ones() {
+    let state = admin_state();
+    let app = spawn_state(state.clone()).await;
+    let client = reqwest::Client::new();
+
+    let report = reload::reload_from(
+        &state,
+        &variables(&[
+            ("ADMIN_TOKEN", "segreto"),
+            ("CORS_ORIGINS", "https://lettori.example.org"),
+            ("BIND_ADDR", "127.0.0.1:4321"),
+            ("SCHEDULER_JOBS", "sport:1h:http://127.0.0.1:9/sport/"),
+            ("TELEGRAM_CHAT_IDS", "@corriere"),
+        ]),
+    )
+    .unwrap();
+    assert_eq!(
+        report.requires_restart,
+        vec!["BIND_ADDR", "TELEGRAM_CHAT_IDS"]
+    );
+    assert_eq!(report.restarted_jobs, vec!["sport"]);
+    assert_eq!(state.current().config.bind_addr, state.config.bind_addr);
+
+    // Requests from now on see the new CORS origins and jobs
+    let allowed = client
+        .get(format!("{}/api/admin/scheduler", app))
+        .bearer_auth("segreto")
+        .header("Origin", "https://lettori.example.org")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        allowed.headers()["access-control-allow-origin"],
+        "https://lettori.example.org"
+    );
+    let jobs: Value = allowed.json().await.unwrap();
+    assert_eq!(jobs["jobs"][0]["name"], "sport");
+
+    let other = client
+        .get(format!("{}/api/admin/scheduler", app))
+        .bearer_auth("segreto")
+        .header("Origin", "https://altro.example.org")
+        .send()
+        .await
+        .unwrap();
+    assert!(other.headers().get("access-control-allow-origin").is_none());
+}
+
+#[tokio::test]
+async fn reload_rebuilds_the_callback_client() {
+    let monitor = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/ping"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&monitor)
+        .await;
+    // localhost resolves to an internal address, which the callback client
+    // refuses until SCRAPE_ALLOW_PRIVATE_ADDRESSES is turned on
+    let url = format!("http://localhost:{}/ping", monitor.address().port());
+    let state = AppState::new(Config {
+        admin_token: Some("segreto".to_string()),
+        heartbeat_url: Some(url.clone()),
+        ..test_config("http://127.0.0.1:9")
+    });
+    heartbeat::report(&state.current(), "homepage", &Ok(1)).await;
+
+    reload::reload_from(
+        &state,
+        &variables(&[
+            ("ADMIN_TOKEN", "segreto"),
+            ("HEARTBEAT_URL", &url),
+            ("SCRAPE_ALLOW_PRIVATE_ADDRESSES", "true"),
+        ]),
+    )
+    .unwrap();
+    heartbeat::report(&state.current(), "homepage", &Ok(1)).await;
+}
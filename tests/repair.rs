@@ -0,0 +1,88 @@
+mod common;
+
+use common::{spawn_app, test_config, TestSource};
+use corriere_scraper::config::Config;
+use corriere_scraper::extract::CORRIERE_BASE_URL;
+use corriere_scraper::repair;
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+// The homepage fixture after a redesign that renamed every class we select on
+fn redesigned_homepage() -> String {
+    HOMEPAGE
+        .html()
+        .replace("body-hp", "hp-main")
+        .replace("bck-media-news", "card-news")
+        .replace("title-art-hp", "card-title")
+        .replace("subtitle-", "card-summary ")
+        .replace("is_full_image", "card-image")
+}
+
+#[test]
+fn suggests_selectors_that_extract_the_redesigned_cards() {
+    let suggestions = repair::suggest(&redesigned_homepage(), CORRIERE_BASE_URL);
+
+    let best = &suggestions[0];
+    assert_eq!(best.selectors.article, "div.card-news");
+    assert_eq!(best.selectors.title, "h4.card-title");
+    assert_eq!(best.selectors.summary, "p.card-summary");
+    // The sidebar card shares the class, so only the body holds them all
+    assert_eq!(best.selectors.container, "body.hp");
+    assert_eq!(best.items, 7);
+    assert_eq!(best.with_images, 4);
+    assert_eq!(
+        best.sample_titles[1],
+        "Manovra, il governo pone la fiducia: voto entro venerdì"
+    );
+}
+
+#[test]
+fn pages_without_repeated_cards_get_no_suggestions() {
+    let html = "<html><body><h2><a href=\"/a\">Solo</a></h2><p>Niente</p></body></html>";
+    assert!(repair::suggest(html, CORRIERE_BASE_URL).is_empty());
+}
+
+#[tokio::test]
+async fn empty_scrapes_are_reported_on_the_admin_api() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(redesigned_homepage()))
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(Config {
+        admin_token: Some("secret".to_string()),
+        ..test_config(&upstream.uri())
+    })
+    .await;
+    let client = reqwest::Client::new();
+
+    let news: Value = client
+        .get(format!("{}/api/news", app))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(news["news"].as_array().unwrap().len(), 0);
+
+    let body: Value = client
+        .get(format!("{}/api/admin/selectors", app))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let report = &body["reports"][0];
+    assert_eq!(report["source"], "homepage");
+    assert_eq!(
+        report["suggestions"][0]["selectors"]["article"],
+        "div.card-news"
+    );
+}
@@ -0,0 +1,100 @@
+#![cfg(feature = "s3")]
+
+mod common;
+
+use chrono::NaiveDate;
+use common::{news_item, temp_data_dir, test_config};
+use corriere_scraper::archive::{self, ArchivedScrape};
+use corriere_scraper::config::Config;
+use corriere_scraper::s3::S3Exporter;
+use corriere_scraper::{AppState, NewsItem};
+use flate2::read::GzDecoder;
+use serde_json::Value;
+use std::io::Read;
+use wiremock::matchers::{header, method, path, query_param_is_missing};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn s3_config(endpoint: &str, name: &str) -> Config {
+    Config {
+        archive_url: Some(format!("file://{}", temp_data_dir(name).display())),
+        s3_bucket: Some("ricerca".to_string()),
+        s3_endpoint: endpoint.to_string(),
+        s3_access_key_id: Some("AKIDEXAMPLE".to_string()),
+        s3_secret_access_key: Some("segreto".to_string()),
+        s3_path_style: true,
+        ..test_config("http://127.0.0.1:9")
+    }
+}
+
+#[test]
+fn object_keys_follow_the_template() {
+    let config = Config {
+        s3_key_template: "lake/{kind}/dt={date}/{name}".to_string(),
+        ..s3_config("http://127.0.0.1:9", "s3-keys")
+    };
+    let exporter = S3Exporter::from_config(&config).unwrap().unwrap();
+    let day = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap();
+
+    assert_eq!(
+        exporter.object_key("items", day, "items.ndjson.gz"),
+        "lake/items/dt=2026-10-14/items.ndjson.gz"
+    );
+}
+
+#[test]
+fn bucket_needs_credentials() {
+    let config = Config {
+        s3_secret_access_key: None,
+        ..s3_config("http://127.0.0.1:9", "s3-credentials")
+    };
+    assert!(S3Exporter::from_config(&config).is_err());
+}
+
+#[tokio::test]
+async fn day_is_uploaded_as_gzipped_ndjson() {
+    let bucket = MockServer::start().await;
+    Mock::given(method("PUT"))
+        .and(path("/ricerca/corriere/items/2026/10/14/items.ndjson.gz"))
+        .and(header("content-encoding", "gzip"))
+        .and(query_param_is_missing("X-Amz-Security-Token"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&bucket)
+        .await;
+
+    let config = s3_config(&bucket.uri(), "s3-export");
+    let exporter = S3Exporter::from_config(&config).unwrap().unwrap();
+    let mut state = AppState::new(config);
+    state.archive = archive::connect(&state.config).await.unwrap();
+    state
+        .archive
+        .as_ref()
+        .unwrap()
+        .record(&ArchivedScrape {
+            scraped_at: "2026-10-14T08:00:00Z".parse().unwrap(),
+            news: vec![NewsItem {
+                description: "Il voto, atteso venerdì".to_string(),
+                ..news_item(
+                    "Manovra, \"fiducia\" in Aula",
+                    "https://www.corriere.it/politica/manovra.shtml",
+                )
+            }],
+        })
+        .await
+        .unwrap();
+
+    let day = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap();
+    assert_eq!(exporter.export_day(&state, day).await.unwrap(), 1);
+
+    let requests = bucket.received_requests().await.unwrap();
+    let url = requests[0].url.to_string();
+    assert!(url.contains("X-Amz-Signature="), "{}", url);
+    let mut ndjson = String::new();
+    GzDecoder::new(requests[0].body.as_slice())
+        .read_to_string(&mut ndjson)
+        .unwrap();
+    let row: Value = serde_json::from_str(ndjson.lines().next().unwrap()).unwrap();
+    assert_eq!(row["position"], 1);
+    assert_eq!(row["title"], "Manovra, \"fiducia\" in Aula");
+    assert_eq!(row["section"], "politica");
+}
@@ -0,0 +1,212 @@
+mod common;
+
+use common::{spawn_app, test_config, TestSource};
+use corriere_scraper::config::Config;
+use corriere_scraper::scheduler::{self, Job};
+use corriere_scraper::AppState;
+use serde_json::Value;
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+#[test]
+fn jobs_parse_with_intervals_and_urls() {
+    let job: Job = "Sport:15m:https://www.corriere.it/sport/".parse().unwrap();
+    assert_eq!(job.name, "sport");
+    assert_eq!(job.every, Duration::from_secs(900));
+    assert_eq!(job.url.as_deref(), Some("https://www.corriere.it/sport/"));
+
+    let job: Job = "homepage:300".parse().unwrap();
+    assert_eq!(job.every, Duration::from_secs(300));
+    assert_eq!(job.url, None);
+
+    assert!("sport:15m".parse::<Job>().is_err());
+    assert!("homepage:5m:https://www.corriere.it/"
+        .parse::<Job>()
+        .is_err());
+    assert!("sport:0:https://www.corriere.it/sport/"
+        .parse::<Job>()
+        .is_err());
+    assert!("sport:soon:https://www.corriere.it/sport/"
+        .parse::<Job>()
+        .is_err());
+    assert!("sport:999999999999999999d:https://www.corriere.it/sport/"
+        .parse::<Job>()
+        .is_err());
+
+    let job: Job = "backfill:1d".parse().unwrap();
+    assert_eq!(job.every, Duration::from_secs(86400));
+    assert!("backfill:1d:https://www.corriere.it/"
+        .parse::<Job>()
+        .is_err());
+}
+
+#[test]
+fn jitter_stays_within_the_window() {
+    let every = Duration::from_secs(1000);
+    for _ in 0..100 {
+        let delay = scheduler::jittered(every, 10);
+        assert!(delay >= Duration::from_secs(900) && delay <= Duration::from_secs(1100));
+    }
+    assert_eq!(scheduler::jittered(every, 0), every);
+}
+
+async fn admin_app(upstream: &MockServer) -> String {
+    let job = format!("sport:15m:{}/sport/", upstream.uri())
+        .parse()
+        .unwrap();
+    spawn_app(Config {
+        scheduler_jobs: vec![job],
+        admin_token: Some("segreto".to_string()),
+        ..test_config(&upstream.uri())
+    })
+    .await
+}
+
+#[tokio::test]
+async fn section_job_runs_and_reports_status() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/sport/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&upstream)
+        .await;
+    let app = admin_app(&upstream).await;
+    let client = reqwest::Client::new();
+
+    let missing = reqwest::get(format!("{}/api/sections/esteri", app))
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), 404);
+
+    let run: Value = client
+        .post(format!("{}/api/admin/scheduler/sport/run", app))
+        .bearer_auth("segreto")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let items = run["items"].as_u64().unwrap();
+    assert!(items > 0);
+
+    let section: Value = reqwest::get(format!("{}/api/sections/sport", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(section["news"].as_array().unwrap().len() as u64, items);
+
+    let status: Value = client
+        .get(format!("{}/api/admin/scheduler", app))
+        .bearer_auth("segreto")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let job = &status["jobs"][0];
+    assert_eq!(job["name"], "sport");
+    assert_eq!(job["interval_secs"], 900);
+    assert_eq!(job["runs"], 1);
+    assert_eq!(job["running"], false);
+    assert_eq!(job["last_items"].as_u64(), Some(items));
+}
+
+#[tokio::test]
+async fn a_cancelled_run_does_not_block_the_job() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/sport/"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_string(HOMEPAGE.html())
+                .set_delay(Duration::from_secs(30)),
+        )
+        .mount(&upstream)
+        .await;
+    let job: Job = format!("sport:15m:{}/sport/", upstream.uri())
+        .parse()
+        .unwrap();
+    let state = AppState::new(Config {
+        scheduler_jobs: vec![job],
+        ..test_config(&upstream.uri())
+    });
+
+    let run = tokio::spawn({
+        let state = state.clone();
+        async move { scheduler::run_job(&state, "sport").await }
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(state.scheduler.status()[0].running);
+    run.abort();
+    assert!(run.await.unwrap_err().is_cancelled());
+
+    let status = &state.scheduler.status()[0];
+    assert!(!status.running);
+    assert_eq!(status.failures, 1);
+    assert!(status.last_error.is_some());
+}
+
+#[tokio::test]
+async fn backfill_job_aggregates_archived_days() {
+    let upstream = MockServer::start().await;
+    let state = AppState::new(Config {
+        scheduler_jobs: vec!["backfill:1d".parse().unwrap()],
+        ..test_config(&upstream.uri())
+    });
+
+    // Without an archive there is nothing to fill in
+    assert_eq!(scheduler::run_job(&state, "backfill").await, Ok(0));
+    let status = &state.scheduler.status()[0];
+    assert_eq!(status.runs, 1);
+    assert_eq!(status.last_items, Some(0));
+}
+
+#[tokio::test]
+async fn admin_api_requires_the_token() {
+    let upstream = MockServer::start().await;
+    let app = admin_app(&upstream).await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/api/admin/scheduler", app))
+        .bearer_auth("sbagliato")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+
+    let disabled = spawn_app(test_config(&upstream.uri())).await;
+    let response = reqwest::get(format!("{}/api/admin/scheduler", disabled))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn sections_are_scraped_on_demand_without_a_recent_run() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/sport/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    // As on a replica whose scheduler doesn't hold the job's lease
+    let app = admin_app(&upstream).await;
+
+    for _ in 0..2 {
+        let section: Value = reqwest::get(format!("{}/api/sections/sport", app))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert!(!section["news"].as_array().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+mod common;
+
+use common::{news_item, spawn_app, test_config, TestSource};
+use corriere_scraper::config::Config;
+use corriere_scraper::ranking::Placement;
+use corriere_scraper::scoring::{self, ScoreWeights, SortOrder};
+use corriere_scraper::NewsItem;
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn item(title: &str, link: &str, position: usize) -> NewsItem {
+    NewsItem {
+        placement: Placement {
+            position,
+            ..Placement::default()
+        },
+        ..news_item(title, link)
+    }
+}
+
+fn titles(news: &[NewsItem]) -> Vec<&str> {
+    news.iter().map(|item| item.title.as_str()).collect()
+}
+
+#[test]
+fn items_are_sorted_in_each_order() {
+    let page = vec![
+        item(
+            "zanzare in città",
+            "https://www.corriere.it/cronache/24_aprile_28/z.shtml",
+            0,
+        ),
+        item(
+            "LIVE | Borsa",
+            "https://www.corriere.it/economia/24_maggio_01/b.shtml",
+            1,
+        ),
+        item(
+            "Il commento",
+            "https://www.corriere.it/opinioni/rubrica.shtml",
+            2,
+        ),
+        item(
+            "Manovra",
+            "https://www.corriere.it/politica/24_aprile_30/m.shtml",
+            3,
+        ),
+    ];
+    let scraped_at = "2024-05-01T09:00:00Z".parse().unwrap();
+    let sorted = |order: SortOrder, weights: &ScoreWeights| {
+        let mut news = page.clone();
+        scoring::sort(&mut news, order, scraped_at, weights);
+        news
+    };
+    let defaults = ScoreWeights::default();
+
+    assert_eq!(titles(&sorted(SortOrder::Page, &defaults)), titles(&page));
+    assert_eq!(
+        titles(&sorted(SortOrder::Prominence, &defaults)),
+        titles(&page)
+    );
+    assert_eq!(
+        titles(&sorted(SortOrder::PublishedAt, &defaults)),
+        ["LIVE | Borsa", "Manovra", "zanzare in città", "Il commento"]
+    );
+    assert_eq!(
+        titles(&sorted(SortOrder::Title, &defaults)),
+        ["LIVE | Borsa", "Il commento", "Manovra", "zanzare in città"]
+    );
+
+    // Yesterday's politics overtakes the opening story
+    let weights = ScoreWeights {
+        prominence: 1.0,
+        recency: 0.5,
+        categories: vec![
+            ("politica".to_string(), 20.0),
+            ("opinioni".to_string(), -100.0),
+        ],
+    };
+    assert_eq!(
+        titles(&sorted(SortOrder::Score, &weights)),
+        ["LIVE | Borsa", "Manovra", "zanzare in città", "Il commento"]
+    );
+    assert_eq!(scoring::recency(&page[0], scraped_at), 12.5);
+
+    assert_eq!(
+        scoring::parse_sort("Published_At"),
+        Ok(SortOrder::PublishedAt)
+    );
+    assert!(scoring::parse_sort("random")
+        .unwrap_err()
+        .contains("Unsupported sort"));
+}
+
+#[tokio::test]
+async fn news_endpoints_honour_the_sort_parameter() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(TestSource::new("homepage").html()),
+        )
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(Config {
+        scoring: ScoreWeights {
+            categories: vec![("sport".to_string(), 100.0)],
+            ..ScoreWeights::default()
+        },
+        ..test_config(&upstream.uri())
+    })
+    .await;
+
+    let body: Value = reqwest::get(format!("{}/api/v2/news?sort=score", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["news"][0]["categories"][0], "sport");
+
+    let body: Value = reqwest::get(format!("{}/api/news?sort=title", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let titles: Vec<String> = body["news"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["title"].as_str().unwrap().to_lowercase())
+        .collect();
+    assert!(titles.windows(2).all(|pair| pair[0] <= pair[1]));
+
+    for endpoint in ["news", "v2/news"] {
+        let response = reqwest::get(format!("{}/api/{}?sort=random", app, endpoint))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+    }
+}
@@ -0,0 +1,131 @@
+mod common;
+
+use common::{spawn_app, temp_data_dir, test_config, TestSource};
+use corriere_scraper::config::Config;
+use corriere_scraper::extract::{self, ParseMode, SelectorSets};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+// The homepage fixture as served in an experiment with different card markup
+fn experiment_homepage() -> String {
+    HOMEPAGE
+        .html()
+        .replace("bck-media-news", "card-news")
+        .replace("title-art-hp", "card-title")
+}
+
+fn write_sets(name: &str) -> std::path::PathBuf {
+    let path = temp_data_dir(name).join("selectors.json");
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(
+        &path,
+        r#"{
+            "homepage": [
+                { "name": "classic" },
+                { "name": "experiment", "article": ".card-news", "title": "h4.card-title" }
+            ]
+        }"#,
+    )
+    .unwrap();
+    path
+}
+
+#[test]
+fn falls_back_to_the_next_set_below_min_items() {
+    let sets = SelectorSets::load(&write_sets("selector-sets-fallback"))
+        .unwrap()
+        .for_source("homepage");
+
+    let (news, set) = extract::extract_with_fallback(
+        &experiment_homepage(),
+        &sets,
+        extract::CORRIERE_BASE_URL,
+        extract::HOMEPAGE_LIMIT,
+        ParseMode::Document,
+        1,
+    )
+    .unwrap();
+    assert_eq!(set, "experiment");
+    assert_eq!(news.len(), 6);
+
+    // The classic markup still goes to the first set
+    let (_, set) = extract::extract_with_fallback(
+        &HOMEPAGE.html(),
+        &sets,
+        extract::CORRIERE_BASE_URL,
+        extract::HOMEPAGE_LIMIT,
+        ParseMode::Document,
+        1,
+    )
+    .unwrap();
+    assert_eq!(set, "classic");
+
+    // When no set reaches the minimum the most productive one wins
+    let (news, set) = extract::extract_with_fallback(
+        &HOMEPAGE.html(),
+        &sets,
+        extract::CORRIERE_BASE_URL,
+        extract::HOMEPAGE_LIMIT,
+        ParseMode::Document,
+        50,
+    )
+    .unwrap();
+    assert_eq!((news.len(), set.as_str()), (6, "classic"));
+}
+
+#[test]
+fn sources_without_sets_use_the_default_selectors() {
+    let sets = SelectorSets::default().for_source("sport");
+    assert_eq!(sets.len(), 1);
+    assert_eq!(sets[0].name, "default");
+    assert_eq!(sets[0].selectors.article, ".bck-media-news");
+}
+
+#[test]
+fn invalid_selector_sets_are_rejected() {
+    let path = temp_data_dir("selector-sets-invalid").join("selectors.json");
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(
+        &path,
+        r#"{ "homepage": [{ "name": "broken", "title": "h4[" }] }"#,
+    )
+    .unwrap();
+
+    let error = SelectorSets::load(&path).unwrap_err();
+    assert!(error.contains("homepage/broken"), "{}", error);
+}
+
+#[tokio::test]
+async fn homepage_records_the_winning_set_in_metrics() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(experiment_homepage()))
+        .mount(&upstream)
+        .await;
+    let sets = SelectorSets::load(&write_sets("selector-sets-metrics")).unwrap();
+    let app = spawn_app(Config {
+        selector_sets: sets,
+        ..test_config(&upstream.uri())
+    })
+    .await;
+
+    let news: serde_json::Value = reqwest::get(format!("{}/api/news", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(news["news"].as_array().unwrap().len(), 6);
+
+    let metrics = reqwest::get(format!("{}/metrics", app))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(metrics
+        .contains(r#"corriere_selector_sets_used_total{source="homepage",set="experiment"} 1"#));
+}
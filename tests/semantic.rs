@@ -0,0 +1,237 @@
+#![cfg(feature = "semantic")]
+
+mod common;
+
+use async_trait::async_trait;
+use common::{spawn_state, temp_data_dir, test_config};
+use corriere_scraper::semantic::{Document, Embedder, FileVectors, HttpEmbedder, SemanticIndex};
+use corriere_scraper::AppState;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use wiremock::matchers::{body_partial_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+// Words sharing a meaning land on the same dimension, like a real model
+// would place paraphrases close together
+const CONCEPTS: &[&[&str]] = &[
+    &["governo", "esecutivo", "ministri"],
+    &["crisi", "caduta", "dimissioni"],
+    &["calcio", "campionato", "serie"],
+    &["maltempo", "pioggia", "allerta"],
+];
+
+struct ConceptEmbedder {
+    calls: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl Embedder for ConceptEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+        self.calls.fetch_add(texts.len(), Ordering::SeqCst);
+        Ok(texts
+            .iter()
+            .map(|text| {
+                let text = text.to_lowercase();
+                CONCEPTS
+                    .iter()
+                    .map(|words| words.iter().filter(|word| text.contains(*word)).count() as f32)
+                    .chain([0.1])
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+fn document(n: u32, title: &str) -> Document {
+    Document {
+        link: format!("https://www.corriere.it/politica/{}.shtml", n),
+        title: title.to_string(),
+        description: String::new(),
+        image_url: None,
+    }
+}
+
+async fn open(dir: &std::path::Path, model: &str, calls: &Arc<AtomicUsize>) -> SemanticIndex {
+    open_limited(dir, model, calls, 0).await
+}
+
+async fn open_limited(
+    dir: &std::path::Path,
+    model: &str,
+    calls: &Arc<AtomicUsize>,
+    queries_per_minute: u32,
+) -> SemanticIndex {
+    let index = SemanticIndex::new(
+        Box::new(FileVectors::new(dir.join("embeddings.jsonl"))),
+        Box::new(ConceptEmbedder {
+            calls: calls.clone(),
+        }),
+        model,
+        2,
+        queries_per_minute,
+    );
+    index.load().await.unwrap();
+    index
+}
+
+#[tokio::test]
+async fn paraphrased_query_finds_the_headline_and_the_index_persists() {
+    let dir = temp_data_dir("semantic-index");
+    let calls = Arc::new(AtomicUsize::new(0));
+    let index = open(&dir, "concepts", &calls).await;
+
+    let indexed = index
+        .index(vec![
+            document(1, "Crisi di governo, il premier sale al Colle"),
+            document(2, "Serie A, il campionato riparte"),
+            document(3, "Allerta maltempo in Liguria"),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(indexed, 3);
+
+    let results = index
+        .search("Caduta dell'esecutivo e dimissioni dei ministri", 2)
+        .await
+        .unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].link, "https://www.corriere.it/politica/1.shtml");
+    assert!(results[0].score > 0.9 && results[0].score > results[1].score);
+
+    // Unchanged articles aren't embedded again; an edited title is
+    let before = calls.load(Ordering::SeqCst);
+    let indexed = index
+        .index(vec![
+            document(2, "Serie A, il campionato riparte"),
+            document(3, "Pioggia e allerta maltempo in Liguria"),
+        ])
+        .await
+        .unwrap();
+    assert_eq!(indexed, 1);
+    assert_eq!(calls.load(Ordering::SeqCst), before + 1);
+
+    let reopened = open(&dir, "concepts", &calls).await;
+    assert_eq!(reopened.len(), 3);
+    let lines = std::fs::read_to_string(dir.join("embeddings.jsonl")).unwrap();
+    // Compacted on load, dropping the superseded line
+    assert_eq!(lines.lines().count(), 3);
+
+    // A takedown removes the article from the file too
+    let index = open(&dir, "concepts", &calls).await;
+    assert!(index
+        .remove("https://www.corriere.it/politica/1.shtml")
+        .await
+        .unwrap());
+    assert!(!index
+        .remove("https://www.corriere.it/politica/1.shtml")
+        .await
+        .unwrap());
+    let lines = std::fs::read_to_string(dir.join("embeddings.jsonl")).unwrap();
+    assert!(!lines.contains("Crisi di governo"));
+    assert_eq!(open(&dir, "concepts", &calls).await.len(), 2);
+
+    // Vectors of another model don't count
+    assert!(open(&dir, "other-model", &calls).await.is_empty());
+}
+
+#[tokio::test]
+async fn http_embedder_keeps_the_order_of_the_inputs() {
+    let api = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/embeddings"))
+        .and(header("authorization", "Bearer sk-test"))
+        .and(body_partial_json(
+            json!({ "model": "multilingual", "input": ["uno", "due"] }),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "data": [
+                { "index": 1, "embedding": [0.0, 1.0] },
+                { "index": 0, "embedding": [1.0, 0.0] },
+            ],
+        })))
+        .mount(&api)
+        .await;
+    let embedder = HttpEmbedder {
+        client: reqwest::Client::new(),
+        url: format!("{}/v1/embeddings", api.uri()),
+        api_key: Some("sk-test".to_string()),
+        model: "multilingual".to_string(),
+    };
+
+    let vectors = embedder
+        .embed(&["uno".to_string(), "due".to_string()])
+        .await
+        .unwrap();
+
+    assert_eq!(vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0]]);
+}
+
+#[tokio::test]
+async fn semantic_search_endpoint_ranks_by_similarity() {
+    let disabled = spawn_state(AppState::new(test_config("http://127.0.0.1:9"))).await;
+    let response = reqwest::get(format!("{}/api/search/semantic?q=governo", disabled))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+
+    let calls = Arc::new(AtomicUsize::new(0));
+    let index = open(&temp_data_dir("semantic-api"), "concepts", &calls).await;
+    index
+        .index(vec![
+            document(1, "Allerta maltempo in Liguria"),
+            document(2, "Crisi di governo"),
+        ])
+        .await
+        .unwrap();
+    let mut state = AppState::new(test_config("http://127.0.0.1:9"));
+    state.semantic = Some(Arc::new(index));
+    let app = spawn_state(state).await;
+
+    let missing = reqwest::get(format!("{}/api/search/semantic?q=", app))
+        .await
+        .unwrap();
+    assert_eq!(missing.status(), 400);
+
+    let body: Value = reqwest::get(format!(
+        "{}/api/search/semantic?q=dimissioni+dei+ministri&limit=1",
+        app
+    ))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(body["query"], "dimissioni dei ministri");
+    let results = body["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["title"], "Crisi di governo");
+}
+
+#[tokio::test]
+async fn repeated_queries_are_cached_and_new_ones_limited() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let index = open_limited(&temp_data_dir("semantic-limit"), "concepts", &calls, 2).await;
+    index
+        .index(vec![document(1, "Crisi di governo")])
+        .await
+        .unwrap();
+    let mut state = AppState::new(test_config("http://127.0.0.1:9"));
+    state.semantic = Some(Arc::new(index));
+    let app = spawn_state(state).await;
+    let search = |q: &str| reqwest::get(format!("{}/api/search/semantic?q={}", app, q));
+
+    let before = calls.load(Ordering::SeqCst);
+    for _ in 0..5 {
+        assert_eq!(search("governo").await.unwrap().status(), 200);
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), before + 1);
+
+    assert_eq!(search("ministri").await.unwrap().status(), 200);
+    let limited = search("pioggia").await.unwrap();
+    assert_eq!(limited.status(), 429);
+    assert_eq!(limited.headers()["retry-after"], "60");
+    assert_eq!(calls.load(Ordering::SeqCst), before + 2);
+    // Cached queries are still answered
+    assert_eq!(search("governo").await.unwrap().status(), 200);
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use axum::body::Body;
+use axum::http::Request;
+use common::{test_config, TestSource};
+use corriere_scraper::config::Config;
+use corriere_scraper::serverless::Handler;
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+async fn homepage(expected_fetches: u64) -> MockServer {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .expect(expected_fetches)
+        .mount(&upstream)
+        .await;
+    upstream
+}
+
+async fn get_json(handler: &Handler, uri: &str) -> (u16, Value) {
+    let response = handler
+        .handle(Request::get(uri).body(Body::empty()).unwrap())
+        .await;
+    let status = response.status().as_u16();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn invocations_share_the_cache_of_the_instance() {
+    let upstream = homepage(1).await;
+    let handler = Handler::new(Config {
+        cache_soft_ttl_secs: 60,
+        ..test_config(&upstream.uri())
+    })
+    .await
+    .unwrap();
+
+    let (status, body) = get_json(&handler, "/api/news").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["news"].as_array().unwrap().len(), 6);
+
+    let (status, body) = get_json(&handler, "/api/top?n=2").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["count"], 2);
+}
+
+#[tokio::test]
+async fn entries_past_the_soft_ttl_are_refreshed_inline() {
+    let upstream = homepage(2).await;
+    let handler = Handler::new(Config {
+        cache_soft_ttl_secs: 0,
+        cache_hard_ttl_secs: 600,
+        ..test_config(&upstream.uri())
+    })
+    .await
+    .unwrap();
+    assert_eq!(handler.state().config.cache_hard_ttl_secs, 0);
+
+    for _ in 0..2 {
+        let (_, body) = get_json(&handler, "/api/news").await;
+        assert_eq!(body["stale"], false);
+    }
+}
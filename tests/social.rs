@@ -0,0 +1,81 @@
+#![cfg(feature = "social")]
+
+mod common;
+
+use common::{news_item, temp_data_dir, test_config};
+use corriere_scraper::config::Config;
+use corriere_scraper::social::{self, SocialPublisher};
+use corriere_scraper::{AppState, NewsItem};
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn item(section: &str, n: u32) -> NewsItem {
+    news_item(
+        &format!("Notizia {}", n),
+        &format!("https://www.corriere.it/{}/{}.shtml", section, n),
+    )
+}
+
+#[test]
+fn template_fills_placeholders() {
+    let text = social::render_template("[{section}] {title}\n{link}", &item("esteri", 1));
+    assert_eq!(
+        text,
+        "[esteri] Notizia 1\nhttps://www.corriere.it/esteri/1.shtml"
+    );
+}
+
+#[test]
+fn long_posts_keep_the_link() {
+    let link = "https://www.corriere.it/esteri/1.shtml";
+    let text = format!("{}\n\n{}", "parola ".repeat(100).trim_end(), link);
+
+    let fitted = social::fit(&text, link, 100);
+
+    assert!(fitted.chars().count() <= 100);
+    assert!(fitted.starts_with("parola parola"));
+    assert!(fitted.ends_with(&format!("…\n\n{}", link)));
+
+    // A link longer than the limit can't be kept whole
+    let fitted = social::fit(&text, link, 20);
+    assert_eq!(fitted.chars().count(), 20);
+    assert!(fitted.ends_with('…'));
+}
+
+#[tokio::test]
+async fn posts_new_items_from_selected_sections_to_mastodon() {
+    let mastodon = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/statuses"))
+        .and(header(
+            "Idempotency-Key",
+            "https://www.corriere.it/esteri/3.shtml",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "id": "1" })))
+        .expect(1)
+        .mount(&mastodon)
+        .await;
+
+    let config = Config {
+        mastodon_url: Some(mastodon.uri()),
+        mastodon_token: Some("secret".to_string()),
+        mastodon_min_interval_secs: 0,
+        social_sections: vec!["esteri".to_string()],
+        data_dir: temp_data_dir("social"),
+        ..test_config("http://127.0.0.1:9")
+    };
+    let mut publisher = SocialPublisher::from_config(&config).unwrap().unwrap();
+    let state = AppState::new(config);
+
+    // The first run only records what's already on the homepage
+    let posted = publisher
+        .publish(&state, &[item("esteri", 1)])
+        .await
+        .unwrap();
+    assert_eq!(posted, 0);
+
+    // Only the new item from a selected section is posted, and only once
+    let news = [item("politica", 2), item("esteri", 3), item("esteri", 1)];
+    assert_eq!(publisher.publish(&state, &news).await.unwrap(), 1);
+    assert_eq!(publisher.publish(&state, &news).await.unwrap(), 0);
+}
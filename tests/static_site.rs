@@ -0,0 +1,80 @@
+mod common;
+
+use common::{temp_data_dir, test_config, TestSource};
+use corriere_scraper::static_site;
+use corriere_scraper::AppState;
+use serde_json::Value;
+use std::path::Path;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const HOMEPAGE: TestSource = TestSource::new("homepage");
+
+#[tokio::test]
+async fn build_writes_the_news_feed_page_and_manifest() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(HOMEPAGE.html()))
+        .mount(&upstream)
+        .await;
+    let out = temp_data_dir("static-build").join("dist");
+    let state = AppState::new(test_config(&upstream.uri()));
+
+    let manifest = static_site::build(&state, &out).await.unwrap();
+
+    assert_eq!(manifest.items, 6);
+    let news: Value =
+        serde_json::from_str(&std::fs::read_to_string(out.join("news.json")).unwrap()).unwrap();
+    assert_eq!(news["news"].as_array().unwrap().len(), 6);
+    assert!(news["scraped_at_display"].is_string());
+    let rss = std::fs::read_to_string(out.join("feed.rss")).unwrap();
+    assert!(rss.contains("<title>Borsa, Milano apre in rialzo</title>"));
+    let html = std::fs::read_to_string(out.join("index.html")).unwrap();
+    assert!(html.contains("Ucraina, attacco di droni su Kiev"));
+    assert!(html.contains("href=\"feed.rss\""));
+
+    let listed: Value =
+        serde_json::from_str(&std::fs::read_to_string(out.join("manifest.json")).unwrap()).unwrap();
+    let files = listed["files"].as_array().unwrap();
+    assert_eq!(files.len(), 3);
+    assert_eq!(files[2]["path"], "index.html");
+    assert_eq!(files[2]["bytes"], html.len());
+    // No partial files are left behind
+    let names: Vec<String> = std::fs::read_dir(&out)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(names.len(), 4);
+    assert!(names.iter().all(|name| !name.starts_with('.')));
+}
+
+#[tokio::test]
+async fn failed_build_keeps_the_previous_files() {
+    let out = temp_data_dir("static-failed");
+    std::fs::create_dir_all(&out).unwrap();
+    std::fs::write(out.join("index.html"), "previous").unwrap();
+    let state = AppState::new(test_config("http://127.0.0.1:9"));
+
+    assert!(static_site::build(&state, &out).await.is_err());
+
+    assert_eq!(
+        std::fs::read_to_string(out.join("index.html")).unwrap(),
+        "previous"
+    );
+    assert!(!out.join("manifest.json").exists());
+}
+
+#[test]
+fn out_dir_comes_from_the_flag_or_the_default() {
+    let default = Path::new("dist");
+    let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+    assert_eq!(static_site::out_dir(default, &[]).unwrap(), default);
+    assert_eq!(
+        static_site::out_dir(default, &args(&["--out", "./public"])).unwrap(),
+        Path::new("./public")
+    );
+    assert!(static_site::out_dir(default, &args(&["--out"])).is_err());
+    assert!(static_site::out_dir(default, &args(&["--dir", "x"])).is_err());
+}
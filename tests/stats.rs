@@ -0,0 +1,154 @@
+mod common;
+
+use chrono::{Duration, NaiveDate};
+use common::{news_item, spawn_state, temp_data_dir, test_config};
+use corriere_scraper::archive::{self, ArchivedScrape};
+use corriere_scraper::config::Config;
+use corriere_scraper::edition::{day_span, today};
+use corriere_scraper::stats::{self, ScrapeCounts};
+use corriere_scraper::AppState;
+use serde_json::Value;
+
+fn stats_config(name: &str) -> Config {
+    Config {
+        data_dir: temp_data_dir(name),
+        archive_url: Some(format!(
+            "file://{}",
+            temp_data_dir(&format!("{}-archive", name)).display()
+        )),
+        ..test_config("http://127.0.0.1:9")
+    }
+}
+
+fn scrapes(config: &Config, day: NaiveDate) -> Vec<ArchivedScrape> {
+    let (start, _) = day_span(config, day);
+    vec![
+        ArchivedScrape {
+            scraped_at: start + Duration::hours(8),
+            news: vec![
+                news_item("Manovra", "https://www.corriere.it/politica/a.shtml"),
+                news_item(
+                    "Milano, la M4",
+                    "https://milano.corriere.it/notizie/cronaca/b.shtml",
+                ),
+            ],
+        },
+        ArchivedScrape {
+            scraped_at: start + Duration::hours(20),
+            news: vec![
+                news_item(
+                    "Manovra, c'è l'accordo",
+                    "https://www.corriere.it/politica/a.shtml",
+                ),
+                news_item(
+                    "Il retroscena",
+                    "https://www.corriere.it/premium/politica/c.shtml",
+                ),
+            ],
+        },
+    ]
+}
+
+#[test]
+fn day_is_aggregated_over_distinct_articles() {
+    let config = stats_config("stats-aggregate");
+    let day = NaiveDate::from_ymd_opt(2026, 10, 14).unwrap();
+
+    let stats = stats::aggregate(
+        &config,
+        day,
+        &scrapes(&config, day),
+        Some(ScrapeCounts { ok: 3, failed: 1 }),
+    );
+
+    assert_eq!(stats.articles, 3);
+    assert_eq!(stats.categories["politica"], 2);
+    assert_eq!(stats.categories["milano"], 1);
+    assert_eq!(stats.sources["corriere.it"], 2);
+    assert_eq!(stats.sources["milano.corriere.it"], 1);
+    assert_eq!((stats.paywalled, stats.free), (1, 2));
+    // The latest title of an edited article counts
+    let expected = ("Manovra, c'è l'accordo".chars().count() + 13 + 13) as f64 / 3.0;
+    assert!((stats.average_headline_length - expected).abs() < 1e-9);
+    assert_eq!((stats.scrapes, stats.failed_scrapes), (4, 1));
+    assert_eq!(stats.scrape_success_rate, Some(0.75));
+
+    // Without logged outcomes the archived scrapes are the ones that worked
+    let stats = stats::aggregate(&config, day, &scrapes(&config, day), None);
+    assert_eq!((stats.scrapes, stats.scrape_success_rate), (2, Some(1.0)));
+}
+
+#[tokio::test]
+async fn failed_homepage_scrapes_are_logged() {
+    let state = AppState::new(stats_config("stats-log"));
+    let app = spawn_state(state.clone()).await;
+
+    let body: Value = reqwest::get(format!("{}/api/news", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(body["error"].is_string());
+
+    let counts = state.scrape_log.get(today(&state.config)).unwrap().unwrap();
+    assert_eq!(counts, ScrapeCounts { ok: 0, failed: 1 });
+}
+
+#[tokio::test]
+async fn daily_endpoint_serves_the_aggregated_days() {
+    let disabled = spawn_state(AppState::new(test_config("http://127.0.0.1:9"))).await;
+    let response = reqwest::get(format!("{}/api/stats/daily", disabled))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+
+    let mut state = AppState::new(stats_config("stats-daily"));
+    state.archive = archive::connect(&state.config).await.unwrap();
+    let storage = state.archive.clone().unwrap();
+    storage.prepare().await.unwrap();
+    let day = today(&state.config) - Duration::days(2);
+    for scrape in scrapes(&state.config, day) {
+        storage.record(&scrape).await.unwrap();
+    }
+    state
+        .scrape_log
+        .record(day - Duration::days(1), false)
+        .unwrap();
+
+    assert_eq!(stats::aggregate_missing(&state).await.unwrap(), 2);
+    // Cached days aren't computed again
+    assert_eq!(stats::aggregate_missing(&state).await.unwrap(), 0);
+    let app = spawn_state(state).await;
+
+    let body: Value = reqwest::get(format!("{}/api/stats/daily", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let days = body["days"].as_array().unwrap();
+    assert_eq!(days.len(), 2);
+    assert_eq!(days[0]["articles"], 0);
+    assert_eq!(days[0]["scrape_success_rate"], 0.0);
+    assert_eq!(days[1]["date"], day.to_string());
+    assert_eq!(days[1]["articles"], 3);
+
+    let body: Value = reqwest::get(format!("{}/api/stats/daily?from={}&to={}", app, day, day))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["days"].as_array().unwrap().len(), 1);
+
+    let reversed = reqwest::get(format!(
+        "{}/api/stats/daily?from={}&to={}",
+        app,
+        day,
+        day - Duration::days(1)
+    ))
+    .await
+    .unwrap();
+    assert_eq!(reversed.status(), 400);
+}
@@ -0,0 +1,113 @@
+mod common;
+
+use common::{spawn_app, test_config};
+use corriere_scraper::config::Config;
+use corriere_scraper::summary::{sentences, textrank};
+use serde_json::{json, Value};
+use wiremock::matchers::{body_partial_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const BODY: &[&str] = &[
+    "Il Consiglio dei ministri si è riunito nella notte.",
+    "La manovra economica del governo prevede nuove misure sulle pensioni.",
+    "Ieri a Milano ha piovuto per tutto il pomeriggio.",
+    "Le pensioni restano il nodo della manovra per la maggioranza di governo.",
+    "Il derby è finito in parità.",
+];
+
+fn article_html() -> String {
+    let paragraphs: String = BODY
+        .iter()
+        .map(|sentence| format!("<p class=\"chapter-paragraph\">{}</p>", sentence))
+        .collect();
+    format!(
+        "<html><body><article><h1 class=\"title-art\">Manovra approvata</h1>\
+         <div class=\"chapter\">{}</div></article></body></html>",
+        paragraphs
+    )
+}
+
+#[test]
+fn italian_sentences_survive_abbreviations_and_quotes() {
+    let text = "Il sen. Rossi e il dott. G. Bianchi sono arrivati alle 9.30. \
+                «Non ci sono le condizioni», ha detto il prof. Verdi? Sì! \
+                Seguono altre questioni ecc. di cui si parlerà.\n\nNuovo paragrafo senza punto";
+
+    assert_eq!(
+        sentences(text),
+        vec![
+            "Il sen. Rossi e il dott. G. Bianchi sono arrivati alle 9.30.",
+            "«Non ci sono le condizioni», ha detto il prof. Verdi?",
+            "Sì!",
+            "Seguono altre questioni ecc. di cui si parlerà.",
+            "Nuovo paragrafo senza punto",
+        ]
+    );
+}
+
+#[test]
+fn textrank_keeps_the_central_sentences_in_order() {
+    let sentences: Vec<String> = BODY.iter().map(|s| s.to_string()).collect();
+
+    assert_eq!(textrank(&sentences, 2), vec![1, 3]);
+    assert_eq!(textrank(&sentences, 10), vec![0, 1, 2, 3, 4]);
+}
+
+#[tokio::test]
+async fn summary_endpoint_extracts_or_asks_the_llm() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/manovra.shtml"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(article_html()))
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/chat/completions"))
+        .and(header("authorization", "Bearer sk-test"))
+        .and(body_partial_json(json!({ "model": "riassunti" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "choices": [{ "message": { "content": "Approvata la manovra. Nodo pensioni. Altro." } }],
+        })))
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(Config {
+        summary_api_url: Some(format!("{}/v1/chat/completions", upstream.uri())),
+        summary_api_key: Some("sk-test".to_string()),
+        summary_model: "riassunti".to_string(),
+        ..test_config(&upstream.uri())
+    })
+    .await;
+    let url = format!("{}/manovra.shtml", upstream.uri());
+
+    let body: Value = reqwest::get(format!(
+        "{}/api/article/summary?url={}&sentences=2",
+        app, url
+    ))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(body["title"], "Manovra approvata");
+    assert_eq!(body["method"], "extractive");
+    assert_eq!(body["sentences"], json!([BODY[1], BODY[3]]));
+
+    let body: Value = reqwest::get(format!(
+        "{}/api/article/summary?url={}&sentences=2&method=llm",
+        app, url
+    ))
+    .await
+    .unwrap()
+    .json()
+    .await
+    .unwrap();
+    assert_eq!(body["summary"], "Approvata la manovra. Nodo pensioni.");
+
+    let too_many = reqwest::get(format!(
+        "{}/api/article/summary?url={}&sentences=50",
+        app, url
+    ))
+    .await
+    .unwrap();
+    assert_eq!(too_many.status(), 400);
+}
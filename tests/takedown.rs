@@ -0,0 +1,161 @@
+mod common;
+
+use chrono::{DateTime, Utc};
+use common::{news_item, spawn_state, temp_data_dir, test_config};
+use corriere_scraper::archive::{self, ArchivedScrape};
+use corriere_scraper::config::Config;
+use corriere_scraper::takedown::{self, Takedown, TakedownStore};
+use corriere_scraper::AppState;
+use serde_json::{json, Value};
+
+const LINK: &str = "https://www.corriere.it/cronaca/privato.shtml";
+
+fn at(timestamp: &str) -> DateTime<Utc> {
+    timestamp.parse().unwrap()
+}
+
+#[tokio::test]
+async fn takedown_leaves_only_a_hash_and_hides_the_article() {
+    let dir = temp_data_dir("takedown");
+    let config = Config {
+        archive_url: Some(format!("file://{}", dir.join("archive").display())),
+        data_dir: dir.clone(),
+        admin_token: Some("segreto".to_string()),
+        ..test_config("http://127.0.0.1:9")
+    };
+    let mut state = AppState::new(config);
+    state.archive = archive::connect(&state.config).await.unwrap();
+    for (scraped_at, news) in [
+        (
+            "2026-10-14T08:00:00Z",
+            vec![news_item("Il nome del privato cittadino", LINK)],
+        ),
+        (
+            "2026-10-14T09:00:00Z",
+            vec![
+                news_item("Manovra", "https://www.corriere.it/a"),
+                news_item("Il nome del privato cittadino", LINK),
+            ],
+        ),
+    ] {
+        let scrape = ArchivedScrape {
+            scraped_at: at(scraped_at),
+            news,
+        };
+        state
+            .archive
+            .as_ref()
+            .unwrap()
+            .record(&scrape)
+            .await
+            .unwrap();
+    }
+    let app = spawn_state(state).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/api/admin/takedowns", app))
+        .json(&json!({ "link": LINK, "reason": "GDPR request 2026-41" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+
+    let body: Value = client
+        .post(format!("{}/api/admin/takedowns", app))
+        .bearer_auth("segreto")
+        .json(&json!({ "link": LINK, "reason": "GDPR request 2026-41" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["scrapes_redacted"], 2);
+    assert_eq!(body["takedown"]["link_hash"], takedown::link_hash(LINK));
+
+    // Nothing but the hash is left on disk
+    let day = std::fs::read_to_string(dir.join("archive/2026-10-14.jsonl")).unwrap();
+    assert!(!day.contains("privato"));
+    assert!(day.contains(&takedown::link_hash(LINK)));
+
+    let get = |path: String| {
+        let client = client.clone();
+        async move { client.get(path).send().await.unwrap().text().await.unwrap() }
+    };
+    let search = get(format!(
+        "{}/api/archive/search?from=2026-10-01T00:00:00Z",
+        app
+    ))
+    .await;
+    assert!(search.contains("/a\"") && !search.contains("redacted"));
+    let history = get(format!(
+        "{}/api/archive/history?link={}&from=2026-10-01T00:00:00Z",
+        app, LINK
+    ))
+    .await;
+    assert_eq!(
+        serde_json::from_str::<Value>(&history).unwrap()["appearances"],
+        json!([])
+    );
+    let snapshot = get(format!("{}/api/news?at=2026-10-14T08:30:00Z", app)).await;
+    assert!(!snapshot.contains("redacted"));
+
+    let list: Value = client
+        .get(format!("{}/api/admin/takedowns", app))
+        .bearer_auth("segreto")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(list["takedowns"][0]["reason"], "GDPR request 2026-41");
+
+    // Asking again redacts again but keeps the first entry
+    let again: Value = client
+        .post(format!("{}/api/admin/takedowns", app))
+        .bearer_auth("segreto")
+        .json(&json!({ "link": LINK, "reason": "Richiesta ripetuta" }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(again["scrapes_redacted"], 0);
+    assert_eq!(again["takedown"]["reason"], "GDPR request 2026-41");
+    let list: Value = client
+        .get(format!("{}/api/admin/takedowns", app))
+        .bearer_auth("segreto")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(list["takedowns"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn later_scrapes_archive_taken_down_items_as_tombstones() {
+    let store = TakedownStore::new(temp_data_dir("takedown-store").join("takedowns.json"));
+    store
+        .add(Takedown {
+            link_hash: takedown::link_hash(LINK),
+            reason: "Ordine del tribunale".to_string(),
+            redacted_at: Utc::now(),
+            request_id: None,
+        })
+        .unwrap();
+
+    let mut news = vec![
+        news_item("Manovra", "https://www.corriere.it/a"),
+        news_item("Il nome", LINK),
+    ];
+    store.redact_items(&mut news).unwrap();
+
+    assert_eq!(news[0].title, "Manovra");
+    assert!(takedown::is_tombstone(&news[1].link));
+    assert!(news[1].title.is_empty() && !news[1].link.contains("privato"));
+}
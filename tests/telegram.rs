@@ -0,0 +1,80 @@
+#![cfg(feature = "telegram")]
+
+mod common;
+
+use common::{news_item, temp_data_dir, test_config};
+use corriere_scraper::config::Config;
+use corriere_scraper::telegram::{self, TelegramNotifier};
+use corriere_scraper::{AppState, NewsItem};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn item(n: u32) -> NewsItem {
+    NewsItem {
+        description: String::new(),
+        ..news_item(
+            &format!("Notizia {}", n),
+            &format!("https://www.corriere.it/n/{}.shtml", n),
+        )
+    }
+}
+
+#[test]
+fn captions_fit_the_limit_once_escaped() {
+    let short = item(1);
+    assert_eq!(
+        telegram::caption(&short, 1024),
+        Some(telegram::message_text(&short))
+    );
+
+    // Every & takes five characters once escaped
+    let long = NewsItem {
+        title: "Titolo & ".repeat(100),
+        description: "Riassunto & ".repeat(100),
+        ..item(2)
+    };
+    let caption = telegram::caption(&long, 1024).unwrap();
+    assert!(caption.chars().count() <= 1024);
+    assert!(caption.starts_with("<b>Titolo &amp; "));
+    assert!(caption.contains("…</b>"));
+    assert!(caption.ends_with("Leggi su corriere.it</a>"));
+
+    // Nothing but the link fits, so the item goes out as text
+    assert_eq!(telegram::caption(&long, 20), None);
+}
+
+#[tokio::test]
+async fn posts_only_new_top_items_once() {
+    let telegram = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/bottest-token/sendMessage"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "ok": true })))
+        .mount(&telegram)
+        .await;
+
+    let config = Config {
+        telegram_bot_token: Some("test-token".to_string()),
+        telegram_chat_ids: vec!["@corriere".to_string()],
+        telegram_top_n: 2,
+        telegram_api_url: telegram.uri(),
+        data_dir: temp_data_dir("telegram"),
+        ..test_config("http://127.0.0.1:9")
+    };
+    let mut notifier = TelegramNotifier::from_config(&config).unwrap().unwrap();
+    let state = AppState::new(config);
+
+    // The first run only records what's already on the homepage
+    let posted = notifier.notify(&state, &[item(1), item(2)]).await.unwrap();
+    assert_eq!(posted, 0);
+
+    // A new opening story is posted; items below the top slots are not
+    let posted = notifier
+        .notify(&state, &[item(3), item(1), item(4)])
+        .await
+        .unwrap();
+    assert_eq!(posted, 1);
+
+    let posted = notifier.notify(&state, &[item(3), item(1)]).await.unwrap();
+    assert_eq!(posted, 0);
+    assert_eq!(telegram.received_requests().await.unwrap().len(), 1);
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use common::{spawn_app, test_config, TestSource};
+use corriere_scraper::typography;
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[test]
+fn text_is_repaired_and_normalized() {
+    assert_eq!(
+        typography::fix_mojibake("PerchÃ© la cittÃ\u{a0}"),
+        "Perché la città"
+    );
+    assert_eq!(typography::fix_mojibake("lâ€™Italia"), "l’Italia");
+    // Text that isn't mojibake stays as it is
+    assert_eq!(typography::fix_mojibake("Città â"), "Città â");
+
+    assert_eq!(
+        typography::decode_entities("Caff&egrave; &amp; l&#8217;ora &#x2014; &bogus; & co"),
+        "Caffè & l’ora — &bogus; & co"
+    );
+    assert_eq!(
+        typography::normalize("  “Mai più”  disse,\u{a0}l’ex premier — poi   tacque "),
+        "\"Mai più\" disse, l'ex premier – poi tacque"
+    );
+    assert_eq!(
+        typography::normalize("Il voto - e poi?"),
+        "Il voto – e poi?"
+    );
+    assert_eq!(typography::normalize("«Basta»"), "«Basta»");
+}
+
+#[test]
+fn editorial_prefixes_are_stripped_from_titles() {
+    assert_eq!(
+        typography::normalize_title("LIVE | Ucraina, attacco su Kiev"),
+        "Ucraina, attacco su Kiev"
+    );
+    assert_eq!(
+        typography::normalize_title("VIDEO — Diretta | Il discorso"),
+        "Il discorso"
+    );
+    assert_eq!(
+        typography::normalize_title("Ultim’ora: sciopero dei treni"),
+        "sciopero dei treni"
+    );
+    // Words that merely start like a label are kept, and so is a bare label
+    assert_eq!(
+        typography::normalize_title("Videogiochi: il mercato cresce"),
+        "Videogiochi: il mercato cresce"
+    );
+    assert_eq!(
+        typography::normalize_title("LIVE-streaming, la sfida"),
+        "LIVE-streaming, la sfida"
+    );
+    assert_eq!(typography::normalize_title("FOTO |"), "FOTO |");
+}
+
+#[tokio::test]
+async fn v2_news_carries_normalized_text_on_request() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_string(TestSource::new("homepage").html()),
+        )
+        .mount(&upstream)
+        .await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+    let get = |query: &str| {
+        let url = format!("{}/api/v2/news{}", app, query);
+        async move {
+            reqwest::get(url)
+                .await
+                .unwrap()
+                .json::<Value>()
+                .await
+                .unwrap()
+        }
+    };
+
+    let raw = get("").await;
+    assert!(raw["news"][0]["normalized"].is_null());
+
+    let body = get("?normalize=true").await;
+    let item = &body["news"][0];
+    assert_eq!(item["title"], raw["news"][0]["title"]);
+    assert_eq!(
+        item["normalized"]["title"],
+        typography::normalize_title(item["title"].as_str().unwrap())
+    );
+    assert_eq!(
+        item["normalized"]["description"],
+        typography::normalize(item["description"].as_str().unwrap())
+    );
+}
@@ -0,0 +1,169 @@
+mod common;
+
+use common::{news_item, spawn_app, test_config};
+use corriere_scraper::config::Config;
+use corriere_scraper::validate::{self, Rules, ValidationMode};
+use corriere_scraper::NewsItem;
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+// A homepage with one good card and one of each kind of broken card
+const PAGE: &str = r#"<html><body><main class="body-hp">
+  <div class="bck-media-news">
+    <h4 class="title-art-hp"><a href="/politica/buona.shtml">Notizia valida</a></h4>
+    <img class="is_full_image" src="https://images2.corriereobjects.it/foto.jpg">
+  </div>
+  <div class="bck-media-news">
+    <h4 class="title-art-hp"><a>Senza link</a></h4>
+  </div>
+  <div class="bck-media-news">
+    <h4 class="title-art-hp"><a href="https://altrosito.example/pagina">Fuori dominio</a></h4>
+  </div>
+  <div class="bck-media-news">
+    <h4 class="title-art-hp"><a href="/cronache/titolo-vuoto.shtml"> </a></h4>
+  </div>
+  <div class="bck-media-news">
+    <h4 class="title-art-hp"><a href="/esteri/immagine.shtml">Immagine sospetta</a></h4>
+    <img class="is_full_image" src="/static/tracker.js">
+  </div>
+</main></body></html>"#;
+
+fn item(link: &str, image_url: Option<&str>) -> NewsItem {
+    NewsItem {
+        description: String::new(),
+        image_url: image_url.map(str::to_string),
+        ..news_item("Titolo", link)
+    }
+}
+
+#[test]
+fn links_must_be_https_on_the_site_or_an_allowed_domain() {
+    let rules = Rules::for_page(&["corriere.it".to_string()], "https://www.corriere.it/");
+    let check = |link: &str| validate::check(&item(link, None), &rules);
+
+    assert!(check("https://www.corriere.it/politica/a.shtml").is_empty());
+    assert!(check("https://milano.corriere.it/notizie/a.shtml").is_empty());
+    assert_eq!(
+        check("http://www.corriere.it/a.shtml"),
+        [validate::INSECURE_LINK]
+    );
+    assert_eq!(check("https://notcorriere.it/a"), [validate::OFFSITE_LINK]);
+    assert_eq!(check("/politica/a.shtml"), [validate::INVALID_LINK]);
+    assert_eq!(check(""), [validate::MISSING_LINK]);
+
+    // A page served over http may link over http
+    let rules = Rules::for_page(&[], "http://127.0.0.1:8080/");
+    assert!(validate::check(&item("http://127.0.0.1:8080/a", None), &rules).is_empty());
+}
+
+#[test]
+fn image_urls_must_look_like_images() {
+    let rules = Rules::for_page(&["corriere.it".to_string()], "https://www.corriere.it/");
+    let link = "https://www.corriere.it/a.shtml";
+    let check = |image: &str| validate::check(&item(link, Some(image)), &rules);
+
+    assert!(check("https://images2.corriereobjects.it/foto.JPG?v=1").is_empty());
+    assert!(check("https://images2.corriereobjects.it/resize/1234").is_empty());
+    assert_eq!(
+        check("https://www.corriere.it/app.js"),
+        [validate::INVALID_IMAGE]
+    );
+    assert_eq!(
+        check("data:image/gif;base64,R0lGOD"),
+        [validate::INVALID_IMAGE]
+    );
+}
+
+async fn upstream() -> MockServer {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(PAGE))
+        .mount(&upstream)
+        .await;
+    upstream
+}
+
+#[tokio::test]
+async fn broken_items_are_dropped_and_counted() {
+    let upstream = upstream().await;
+    let app = spawn_app(test_config(&upstream.uri())).await;
+
+    let body: Value = reqwest::get(format!("{}/api/news", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let titles: Vec<&str> = body["news"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["title"].as_str().unwrap())
+        .collect();
+    assert_eq!(titles, ["Notizia valida", "Immagine sospetta"]);
+    assert_eq!(body["news"][1]["image_url"], Value::Null);
+
+    let metrics = reqwest::get(format!("{}/metrics", app))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    for rule in [
+        "missing_link",
+        "offsite_link",
+        "empty_title",
+        "invalid_image",
+    ] {
+        let line = format!(
+            "corriere_validation_failures_total{{source=\"homepage\",rule=\"{}\"}} 1",
+            rule
+        );
+        assert!(metrics.contains(&line), "missing {} in {}", line, metrics);
+    }
+
+    // The v2 image details go with the image
+    let body: Value = reqwest::get(format!("{}/api/v2/news", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["news"][1]["image_url"], Value::Null);
+    assert_eq!(body["news"][1]["image"], Value::Null);
+}
+
+#[tokio::test]
+async fn flag_mode_keeps_items_and_lists_their_issues_in_v2() {
+    let upstream = upstream().await;
+    let app = spawn_app(Config {
+        validation_mode: ValidationMode::Flag,
+        ..test_config(&upstream.uri())
+    })
+    .await;
+
+    let body: Value = reqwest::get(format!("{}/api/v2/news", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let issues: Vec<&Value> = body["news"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| &item["issues"])
+        .collect();
+    assert_eq!(
+        issues,
+        [
+            &serde_json::json!([]),
+            &serde_json::json!(["missing_link"]),
+            &serde_json::json!(["offsite_link"]),
+            &serde_json::json!(["empty_title"]),
+            &serde_json::json!(["invalid_image"]),
+        ]
+    );
+}
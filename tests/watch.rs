@@ -0,0 +1,167 @@
+mod common;
+
+use common::{fixtures_dir, spawn_state, temp_data_dir, test_config};
+use corriere_scraper::config::Config;
+use corriere_scraper::watch;
+use corriere_scraper::AppState;
+use serde_json::Value;
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const ARTICLE_PATH: &str = "/politica/manovra.shtml";
+
+fn watch_config(upstream: &str, name: &str) -> Config {
+    Config {
+        watch_enabled: true,
+        data_dir: temp_data_dir(name),
+        ..test_config(upstream)
+    }
+}
+
+fn article_html() -> String {
+    std::fs::read_to_string(fixtures_dir().join("article.html")).unwrap()
+}
+
+async fn create_watch(app: &str, body: Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/api/watch", app))
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn edited_article_emits_a_change_event() {
+    let upstream = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(ARTICLE_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_string(article_html()))
+        .up_to_n_times(1)
+        .mount(&upstream)
+        .await;
+    let edited = article_html().replace("voto entro venerdì", "voto slitta a lunedì");
+    Mock::given(method("GET"))
+        .and(path(ARTICLE_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_string(edited))
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/notify"))
+        .and(body_partial_json(serde_json::json!({
+            "changes": [{ "field": "title" }]
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+
+    let state = AppState::new(watch_config(&upstream.uri(), "watch-changes"));
+    let app = spawn_state(state.clone()).await;
+    let created: Value = create_watch(
+        &app,
+        serde_json::json!({
+            "url": format!("{}{}", upstream.uri(), ARTICLE_PATH),
+            "notify_url": format!("{}/notify", upstream.uri()),
+        }),
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    let id = created["watch"]["id"].as_str().unwrap();
+    let token = created["token"].as_str().unwrap();
+
+    let mut events = reqwest::Client::new()
+        .get(format!("{}/api/watch/{}/events?token={}", app, id, token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(events.headers()["content-type"], "text/event-stream");
+
+    // The first check only records the article as it is
+    assert!(watch::check_all(&state).await.unwrap().is_empty());
+    let changes = watch::check_all(&state).await.unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].changes[0].field, "title");
+    assert_eq!(
+        changes[0].changes[0].after,
+        "Manovra, il governo pone la fiducia: voto slitta a lunedì"
+    );
+
+    let chunk = events.chunk().await.unwrap().unwrap();
+    let chunk = String::from_utf8_lossy(&chunk);
+    assert!(chunk.starts_with("event: change\n"), "{}", chunk);
+    assert!(chunk.contains(id));
+
+    let watched: Value = reqwest::get(format!("{}/api/watch/{}?token={}", app, id, token))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        watched["watch"]["title"],
+        "Manovra, il governo pone la fiducia: voto slitta a lunedì"
+    );
+}
+
+#[test]
+fn corrections_are_compared() {
+    let before = watch::ArticleVersion {
+        title: "Titolo".to_string(),
+        body: "Testo".to_string(),
+        corrections: vec![],
+    };
+    let after = watch::ArticleVersion {
+        corrections: vec!["Rettifica: il nome era errato.".to_string()],
+        ..before.clone()
+    };
+
+    let changes = watch::compare(&before, &after);
+
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].field, "corrections");
+    assert_eq!(changes[0].after, "Rettifica: il nome era errato.");
+}
+
+#[tokio::test]
+async fn watch_rejects_hosts_outside_the_allow_list() {
+    let state = AppState::new(watch_config("http://127.0.0.1:9", "watch-host"));
+    let app = spawn_state(state).await;
+
+    let response = create_watch(
+        &app,
+        serde_json::json!({ "url": "https://example.com/article.html" }),
+    )
+    .await;
+
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn duplicate_watches_conflict_and_a_full_store_is_unavailable() {
+    let upstream = MockServer::start().await;
+    let state = AppState::new(Config {
+        watch_max: 1,
+        ..watch_config(&upstream.uri(), "watch-conflicts")
+    });
+    let app = spawn_state(state).await;
+    let url = format!("{}{}", upstream.uri(), ARTICLE_PATH);
+    let notify_url = format!("{}/notify", upstream.uri());
+
+    let created = create_watch(
+        &app,
+        serde_json::json!({ "url": url, "notify_url": notify_url }),
+    )
+    .await;
+    assert_eq!(created.status(), 201);
+    let duplicate = create_watch(
+        &app,
+        serde_json::json!({ "url": url, "notify_url": notify_url }),
+    )
+    .await;
+    assert_eq!(duplicate.status(), 409);
+    let full = create_watch(&app, serde_json::json!({ "url": url })).await;
+    assert_eq!(full.status(), 503);
+}
@@ -0,0 +1,184 @@
+mod common;
+
+use common::{news_item, spawn_app, temp_data_dir, test_config};
+use corriere_scraper::config::Config;
+use corriere_scraper::request_id;
+use corriere_scraper::webhooks::{self, WebhookFormat, WebhookNotifier};
+use corriere_scraper::{AppState, NewsItem};
+use serde_json::Value;
+use wiremock::matchers::{body_partial_json, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn item(n: u32) -> NewsItem {
+    NewsItem {
+        image_url: Some(format!("https://images2.corriereobjects.it/{}.jpg", n)),
+        ..news_item(
+            &format!("Notizia <{}>", n),
+            &format!("https://www.corriere.it/esteri/{}.shtml", n),
+        )
+    }
+}
+
+fn webhook_config(name: &str) -> Config {
+    Config {
+        webhooks_enabled: true,
+        data_dir: temp_data_dir(name),
+        ..test_config("http://127.0.0.1:9")
+    }
+}
+
+#[test]
+fn slack_payload_uses_blocks_with_thumbnail_and_button() {
+    let payloads = webhooks::payloads(WebhookFormat::Slack, &[item(1)]);
+
+    assert_eq!(payloads.len(), 1);
+    let blocks = payloads[0]["blocks"].as_array().unwrap();
+    assert_eq!(
+        blocks[0]["text"]["text"],
+        "*<https://www.corriere.it/esteri/1.shtml|Notizia &lt;1&gt;>*\nIl riassunto"
+    );
+    assert_eq!(
+        blocks[0]["accessory"]["image_url"],
+        "https://images2.corriereobjects.it/1.jpg"
+    );
+    assert_eq!(
+        blocks[1]["elements"][0]["url"],
+        "https://www.corriere.it/esteri/1.shtml"
+    );
+}
+
+#[test]
+fn discord_payloads_respect_embed_limits() {
+    let items: Vec<NewsItem> = (0..7).map(item).collect();
+
+    let payloads = webhooks::payloads(WebhookFormat::Discord, &items);
+
+    assert_eq!(payloads.len(), 2);
+    assert_eq!(payloads[0]["embeds"].as_array().unwrap().len(), 5);
+    assert_eq!(payloads[1]["embeds"][0]["title"], "Notizia <5>");
+    assert_eq!(
+        payloads[1]["embeds"][0]["thumbnail"]["url"],
+        "https://images2.corriereobjects.it/5.jpg"
+    );
+    assert_eq!(payloads[1]["components"][0]["components"][1]["style"], 5);
+}
+
+#[tokio::test]
+async fn registered_webhook_receives_new_items_in_its_format() {
+    let receiver = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/discord"))
+        .and(query_param("with_components", "true"))
+        .and(header("x-request-id", "run-42"))
+        .and(body_partial_json(serde_json::json!({
+            "embeds": [{ "url": "https://www.corriere.it/esteri/2.shtml" }]
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&receiver)
+        .await;
+
+    let config = webhook_config("webhooks");
+    let state = AppState::new(config);
+    let app = spawn_app(webhook_config("webhooks")).await;
+
+    let registered: Value = reqwest::Client::new()
+        .post(format!("{}/api/webhooks", app))
+        .json(&serde_json::json!({
+            "url": format!("{}/discord", receiver.uri()),
+            "format": "discord",
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(registered["webhook"]["format"], "discord");
+    assert!(registered["token"].is_string());
+
+    let mut notifier = WebhookNotifier::new(&state.config).unwrap();
+    // The first run only records what's already on the homepage
+    assert_eq!(notifier.notify(&state, &[item(1)]).await.unwrap(), 0);
+    // Deliveries carry the id of the run that found the items
+    let delivered = request_id::scope(
+        "run-42".to_string(),
+        notifier.notify(&state, &[item(2), item(1)]),
+    )
+    .await;
+    assert_eq!(delivered.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn webhook_url_must_be_http() {
+    let app = spawn_app(webhook_config("webhooks-invalid")).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/webhooks", app))
+        .json(&serde_json::json!({ "url": "file:///etc/passwd", "format": "slack" }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+
+    // Nor may it point at the internal network
+    for url in [
+        "http://169.254.169.254/latest/meta-data",
+        "http://localhost:8080/",
+    ] {
+        let response = reqwest::Client::new()
+            .post(format!("{}/api/webhooks", app))
+            .json(&serde_json::json!({ "url": url, "format": "json" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+    }
+}
+
+#[tokio::test]
+async fn items_no_webhook_took_are_sent_again() {
+    let receiver = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .mount(&receiver)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .and(body_partial_json(serde_json::json!({
+            "items": [{ "link": "https://www.corriere.it/esteri/2.shtml" }]
+        })))
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&receiver)
+        .await;
+
+    let state = AppState::new(webhook_config("webhooks-retry"));
+    let app = spawn_app(webhook_config("webhooks-retry")).await;
+    let registered = reqwest::Client::new()
+        .post(format!("{}/api/webhooks", app))
+        .json(&serde_json::json!({ "url": format!("{}/hook", receiver.uri()), "format": "json" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(registered.status(), 201);
+
+    let mut notifier = WebhookNotifier::new(&state.config).unwrap();
+    assert_eq!(notifier.notify(&state, &[item(1)]).await.unwrap(), 0);
+    // Every delivery failed: the item isn't recorded as sent
+    assert_eq!(
+        notifier.notify(&state, &[item(2), item(1)]).await.unwrap(),
+        0
+    );
+    assert_eq!(
+        notifier.notify(&state, &[item(2), item(1)]).await.unwrap(),
+        1
+    );
+    assert_eq!(
+        notifier.notify(&state, &[item(2), item(1)]).await.unwrap(),
+        0
+    );
+}